@@ -1,15 +1,88 @@
 //! Text buffer implementation using ropey.
 
+use crate::encoding::Encoding;
+use crate::invisible;
+use crate::line_ending::LineEnding;
 use ropey::Rope;
+use std::fmt;
 use std::fs;
-use std::io::{self, BufReader, BufWriter};
-use std::path::Path;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+/// Files at least this large open in the background: the first screenful
+/// is read right away and the rest streams in, see [`TextBuffer::open`].
+pub const STREAMING_THRESHOLD: u64 = 32 * 1024 * 1024;
+
+/// Bytes [`TextBuffer::open`] reads before returning for a large file.
+const FIRST_SCREEN_BYTES: u64 = 64 * 1024;
+
+/// Bytes the background loader reads at a time.
+const LOAD_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+
+/// The characters that make up words: letters, digits, `_` and a
+/// language's extra word characters, e.g. `$` in JavaScript.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WordChars {
+    extra: &'static str,
+}
+
+impl WordChars {
+    /// Creates a set counting `extra` as word characters too.
+    pub const fn new(extra: &'static str) -> Self {
+        Self { extra }
+    }
+
+    /// Returns true if `ch` is a word character.
+    pub fn contains(&self, ch: char) -> bool {
+        ch.is_alphanumeric() || ch == '_' || self.extra.contains(ch)
+    }
+
+    /// Classifies a character for sub-word movement.
+    fn sub_word_class(&self, ch: char) -> SubWordClass {
+        if !self.contains(ch) {
+            SubWordClass::Separator
+        } else if ch.is_uppercase() {
+            SubWordClass::Upper
+        } else if ch.is_numeric() {
+            SubWordClass::Digit
+        } else if ch.is_alphabetic() {
+            SubWordClass::Lower
+        } else {
+            // `_` and extra word characters like `-` separate sub-words
+            SubWordClass::Separator
+        }
+    }
+}
+
+/// What a character is to sub-word movement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SubWordClass {
+    Upper,
+    Lower,
+    Digit,
+    /// Not part of any sub-word.
+    Separator,
+}
 
 /// A text buffer backed by a rope data structure.
 /// Provides efficient text operations for large files.
 #[derive(Debug, Clone)]
 pub struct TextBuffer {
     rope: Rope,
+    /// Whether a [`BufferLoader`] is still appending the file's text.
+    loading: bool,
+    /// Whether loading stopped at an error, leaving only the start of the
+    /// file. Saving it over the file would cut the file short.
+    partial: bool,
+    /// The encoding the file was read in and is saved in.
+    encoding: Encoding,
+    /// The line ending the file is saved with.
+    line_ending: LineEnding,
+    /// Whether the file mixes line endings. Its `\r\n` breaks are then
+    /// kept as they are, and saved unchanged.
+    mixed_line_endings: bool,
 }
 
 impl Default for TextBuffer {
@@ -18,33 +91,207 @@ impl Default for TextBuffer {
     }
 }
 
+impl fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.rope.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
 impl TextBuffer {
     /// Creates a new empty text buffer.
     pub fn new() -> Self {
-        Self { rope: Rope::new() }
+        Self {
+            rope: Rope::new(),
+            loading: false,
+            partial: false,
+            encoding: Encoding::default(),
+            line_ending: LineEnding::default(),
+            mixed_line_endings: false,
+        }
     }
 
     /// Creates a text buffer from a string.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(text: &str) -> Self {
         Self {
             rope: Rope::from_str(text),
+            loading: false,
+            partial: false,
+            encoding: Encoding::default(),
+            line_ending: LineEnding::default(),
+            mixed_line_endings: false,
         }
     }
 
-    /// Loads a text buffer from a file.
+    /// Loads a text buffer from a file, [detecting](Encoding::detect) its
+    /// encoding.
     pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let file = fs::File::open(path)?;
-        let reader = BufReader::new(file);
-        let rope = Rope::from_reader(reader)?;
-        Ok(Self { rope })
+        let bytes = fs::read(path)?;
+        Self::from_bytes(&bytes, Encoding::detect(&bytes))
+    }
+
+    /// Loads a text buffer from a file in the given encoding.
+    pub fn from_file_as<P: AsRef<Path>>(path: P, encoding: Encoding) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?, encoding)
+    }
+
+    fn from_bytes(bytes: &[u8], encoding: Encoding) -> io::Result<Self> {
+        Ok(Self::from_file_text(encoding.decode(bytes)?, encoding))
+    }
+
+    /// Creates a buffer from a file's decoded text, [detecting](LineEnding::detect)
+    /// its line ending and stripping the `\r` of a CRLF file.
+    fn from_file_text(text: String, encoding: Encoding) -> Self {
+        let (line_ending, mixed_line_endings) = LineEnding::detect(&text).unwrap_or_default();
+        let text = if line_ending == LineEnding::Crlf && !mixed_line_endings {
+            text.replace("\r\n", "\n")
+        } else {
+            text
+        };
+        Self {
+            rope: Rope::from_str(&text),
+            loading: false,
+            partial: false,
+            encoding,
+            line_ending,
+            mixed_line_endings,
+        }
+    }
+
+    /// Opens a file, reading a large one in the background.
+    ///
+    /// Files under [`STREAMING_THRESHOLD`] are read whole. Of a larger one
+    /// only the first screenful is read before returning, with a
+    /// [`BufferLoader`] reading the rest on a thread; the buffer isn't
+    /// [fully loaded](Self::is_fully_loaded) until the loader appended it.
+    /// Only UTF-8 streams; a large file in another encoding is read whole.
+    /// A file that turns out not to be UTF-8 past the first screenful is
+    /// read again whole in the background, in the encoding the whole file
+    /// is detected as. The first screenful decides the line ending.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<(Self, Option<BufferLoader>)> {
+        Self::open_streaming_from(path.as_ref(), STREAMING_THRESHOLD)
+    }
+
+    /// Opens a file like [`TextBuffer::open`], streaming it from `threshold`
+    /// bytes on (for testing/benchmarking).
+    pub fn open_streaming_from(path: &Path, threshold: u64) -> io::Result<(Self, Option<BufferLoader>)> {
+        let total = fs::metadata(path)?.len();
+        if total < threshold {
+            return Ok((Self::from_file(path)?, None));
+        }
+        let mut file = fs::File::open(path)?;
+        let mut first = Vec::new();
+        (&mut file).take(FIRST_SCREEN_BYTES).read_to_end(&mut first)?;
+        let encoding = Encoding::detect(&first);
+        if !matches!(encoding, Encoding::Utf8 | Encoding::Utf8Bom) {
+            return Ok((Self::from_file_as(path, encoding)?, None));
+        }
+        let mut pending = Vec::new();
+        let text = decode_chunk(&mut pending, first.strip_prefix(encoding.bom()).unwrap_or(&first))?;
+        let buffer = Self {
+            loading: true,
+            ..Self::from_file_text(text, encoding)
+        };
+        let loader = BufferLoader::start(file, path.to_path_buf(), pending, first.len() as u64, total);
+        Ok((buffer, Some(loader)))
+    }
+
+    /// Returns true unless a [`BufferLoader`] is still appending the
+    /// file's text or loading it failed partway.
+    pub fn is_fully_loaded(&self) -> bool {
+        !self.loading && !self.partial
+    }
+
+    /// Returns true while a [`BufferLoader`] is appending the file's text.
+    pub fn is_loading(&self) -> bool {
+        self.loading
+    }
+
+    /// Returns true if loading failed partway, so the buffer holds only
+    /// the start of the file.
+    pub fn is_partial(&self) -> bool {
+        self.partial
+    }
+
+    /// Marks a partly loaded buffer as whole, once it was saved to a file
+    /// of its own.
+    pub fn clear_partial(&mut self) {
+        self.partial = false;
+    }
+
+    /// Returns the encoding the buffer is saved in.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Sets the encoding the buffer is saved in from now on.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Returns the line ending the buffer is saved with. A file mixing
+    /// line endings returns the one most of its lines use.
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
     }
 
-    /// Saves the buffer to a file.
+    /// Returns true if the file mixes line endings, which are then saved
+    /// as they are until [converted](Self::set_line_ending).
+    pub fn has_mixed_line_endings(&self) -> bool {
+        self.mixed_line_endings
+    }
+
+    /// Saves the buffer with `line_ending` from now on. Clears the mixed
+    /// line endings flag: the caller has removed the `\r` of the `\r\n`
+    /// breaks the buffer kept.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+        self.mixed_line_endings = false;
+    }
+
+    /// Saves the buffer in the encoding and with the line endings of
+    /// `other`, e.g. a fresh read of the file whose text replaced this
+    /// buffer's.
+    pub fn set_format_from(&mut self, other: &TextBuffer) {
+        self.encoding = other.encoding;
+        self.line_ending = other.line_ending;
+        self.mixed_line_endings = other.mixed_line_endings;
+    }
+
+    /// Returns true if `\r` is stripped from the file's line breaks.
+    fn strips_cr(&self) -> bool {
+        self.line_ending == LineEnding::Crlf && !self.mixed_line_endings
+    }
+
+    /// Saves the buffer to a file in its [encoding](Self::encoding) and
+    /// with its [line ending](Self::line_ending). Characters the encoding
+    /// can't represent are written as `?`.
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
-        let file = fs::File::create(path)?;
-        let mut writer = BufWriter::new(file);
-        self.rope.write_to(&mut writer)?;
-        Ok(())
+        if self.encoding == Encoding::Utf8 && !self.strips_cr() {
+            let file = fs::File::create(path)?;
+            let mut writer = BufWriter::new(file);
+            self.rope.write_to(&mut writer)?;
+            return writer.flush();
+        }
+        let mut text = self.to_string();
+        if self.strips_cr() {
+            text = text.replace('\n', "\r\n");
+        }
+        fs::write(path, self.encoding.encode(&text))
+    }
+
+    /// Returns a 64-bit FNV-1a hash of the text. Equal texts hash the same
+    /// however they were edited into shape.
+    pub fn content_hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in self.rope.chunks().flat_map(str::bytes) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        hash
     }
 
     /// Returns the total number of characters in the buffer.
@@ -52,6 +299,16 @@ impl TextBuffer {
         self.rope.len_chars()
     }
 
+    /// Converts a character index to a byte index in the UTF-8 text.
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        self.rope.char_to_byte(char_idx.min(self.len_chars()))
+    }
+
+    /// Converts a byte index in the UTF-8 text to a character index.
+    pub fn byte_to_char(&self, byte_idx: usize) -> usize {
+        self.rope.byte_to_char(byte_idx.min(self.rope.len_bytes()))
+    }
+
     /// Returns the total number of lines in the buffer.
     pub fn len_lines(&self) -> usize {
         self.rope.len_lines()
@@ -92,6 +349,16 @@ impl TextBuffer {
         }
     }
 
+    /// Returns an iterator over the characters from `char_idx` on.
+    pub fn chars_from(&self, char_idx: usize) -> impl Iterator<Item = char> + '_ {
+        self.rope.chars_at(char_idx.min(self.len_chars()))
+    }
+
+    /// Returns an iterator over all characters.
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.rope.chars()
+    }
+
     /// Converts a character index to a (line, column) position.
     /// Both line and column are 0-indexed.
     pub fn char_to_line_col(&self, char_idx: usize) -> (usize, usize) {
@@ -130,6 +397,18 @@ impl TextBuffer {
         len
     }
 
+    /// Returns the display column of column `col` on `line`, where
+    /// invisible characters before it are drawn as boxes wider than one
+    /// column. Cheaper than a [`LineLayout`](crate::LineLayout) for a
+    /// single column.
+    pub fn display_col(&self, line: usize, col: usize) -> usize {
+        if line >= self.len_lines() {
+            return col;
+        }
+        let end = col.min(self.line_len_chars(line));
+        self.rope.line(line).slice(..end).chars().map(invisible::display_width).sum::<usize>() + (col - end)
+    }
+
     /// Returns the character index of the start of a line.
     pub fn line_start(&self, line: usize) -> usize {
         if line >= self.len_lines() {
@@ -163,6 +442,38 @@ impl TextBuffer {
         }
     }
 
+    /// Returns the characters `start..end`, clamped to the buffer.
+    pub fn slice(&self, start: usize, end: usize) -> String {
+        let end = end.min(self.len_chars());
+        let start = start.min(end);
+        self.rope.slice(start..end).to_string()
+    }
+
+    /// Returns columns `start..end` of a line, clamped to the line. Costs
+    /// only the columns taken, however long the line is.
+    pub fn line_slice(&self, line: usize, start: usize, end: usize) -> String {
+        let len = self.line_len_chars(line);
+        let (start, end) = (start.min(len), end.min(len));
+        if start >= end {
+            return String::new();
+        }
+        self.rope.line(line).slice(start..end).to_string()
+    }
+
+    /// Returns the characters [`line_slice`](Self::line_slice) returns,
+    /// without copying them.
+    pub fn line_slice_chars(&self, line: usize, start: usize, end: usize) -> LineChars<'_> {
+        let len = self.line_len_chars(line);
+        let (start, end) = (start.min(len), end.min(len));
+        let next = if start < end { self.line_start(line) + start } else { 0 };
+        LineChars {
+            rope: &self.rope,
+            chunk: "".chars(),
+            next,
+            stop: next + end.saturating_sub(start),
+        }
+    }
+
     /// Returns an iterator over lines in the given range.
     pub fn lines_range(&self, start: usize, end: usize) -> impl Iterator<Item = String> + '_ {
         let start = start.min(self.len_lines());
@@ -170,21 +481,11 @@ impl TextBuffer {
         (start..end).filter_map(|i| self.line(i))
     }
 
-    /// Returns the entire buffer as a string.
-    pub fn to_string(&self) -> String {
-        self.rope.to_string()
-    }
-
     // ==================== Word Navigation ====================
 
-    /// Returns true if a character is a word character (alphanumeric or underscore).
-    fn is_word_char(ch: char) -> bool {
-        ch.is_alphanumeric() || ch == '_'
-    }
-
     /// Finds the start of the word at or before the given position.
     /// Returns the character index of the word boundary.
-    pub fn find_word_boundary_left(&self, char_idx: usize) -> usize {
+    pub fn find_word_boundary_left(&self, char_idx: usize, word_chars: WordChars) -> usize {
         if char_idx == 0 {
             return 0;
         }
@@ -194,7 +495,7 @@ impl TextBuffer {
         // Skip any whitespace/non-word chars first
         while pos > 0 {
             if let Some(ch) = self.char_at(pos - 1) {
-                if Self::is_word_char(ch) {
+                if word_chars.contains(ch) {
                     break;
                 }
                 pos -= 1;
@@ -206,7 +507,7 @@ impl TextBuffer {
         // Then skip word chars to find word start
         while pos > 0 {
             if let Some(ch) = self.char_at(pos - 1) {
-                if !Self::is_word_char(ch) {
+                if !word_chars.contains(ch) {
                     break;
                 }
                 pos -= 1;
@@ -220,7 +521,7 @@ impl TextBuffer {
 
     /// Finds the end of the word at or after the given position.
     /// Returns the character index after the word boundary.
-    pub fn find_word_boundary_right(&self, char_idx: usize) -> usize {
+    pub fn find_word_boundary_right(&self, char_idx: usize, word_chars: WordChars) -> usize {
         let len = self.len_chars();
         if char_idx >= len {
             return len;
@@ -231,7 +532,7 @@ impl TextBuffer {
         // Skip word chars first to find word end
         while pos < len {
             if let Some(ch) = self.char_at(pos) {
-                if !Self::is_word_char(ch) {
+                if !word_chars.contains(ch) {
                     break;
                 }
                 pos += 1;
@@ -243,7 +544,7 @@ impl TextBuffer {
         // Then skip any whitespace/non-word chars
         while pos < len {
             if let Some(ch) = self.char_at(pos) {
-                if Self::is_word_char(ch) {
+                if word_chars.contains(ch) {
                     break;
                 }
                 pos += 1;
@@ -255,6 +556,76 @@ impl TextBuffer {
         pos
     }
 
+    /// Finds the start of the sub-word at or before the given position.
+    ///
+    /// Sub-words are the parts of a word: camelCase humps (`camel`, `Case`),
+    /// runs of capitals (`XML` in `XMLHttp`), runs of digits, and the parts
+    /// between underscores or other non-alphanumeric word characters.
+    pub fn find_subword_boundary_left(&self, char_idx: usize, word_chars: WordChars) -> usize {
+        let class_before = |pos: usize| self.char_at(pos - 1).map(|ch| word_chars.sub_word_class(ch));
+        let mut pos = char_idx.min(self.len_chars());
+
+        // Skip any separators first
+        while pos > 0 && class_before(pos) == Some(SubWordClass::Separator) {
+            pos -= 1;
+        }
+        let Some(class) = (pos > 0).then(|| class_before(pos)).flatten() else {
+            return pos;
+        };
+
+        // Then skip the sub-word to its start
+        while pos > 0 && class_before(pos) == Some(class) {
+            pos -= 1;
+        }
+        // A hump starts at the capital before its lowercase letters
+        if class == SubWordClass::Lower && pos > 0 && class_before(pos) == Some(SubWordClass::Upper) {
+            pos -= 1;
+        }
+
+        pos
+    }
+
+    /// Finds the start of the next sub-word after the given position.
+    /// See [`Self::find_subword_boundary_left`] for what sub-words are.
+    pub fn find_subword_boundary_right(&self, char_idx: usize, word_chars: WordChars) -> usize {
+        let len = self.len_chars();
+        let class_at = |pos: usize| self.char_at(pos).map(|ch| word_chars.sub_word_class(ch));
+        let mut pos = char_idx.min(len);
+
+        // Skip the sub-word first
+        match class_at(pos) {
+            Some(SubWordClass::Upper) => {
+                let start = pos;
+                while pos < len && class_at(pos) == Some(SubWordClass::Upper) {
+                    pos += 1;
+                }
+                if class_at(pos) == Some(SubWordClass::Lower) {
+                    if pos - start > 1 {
+                        // The last capital starts the next hump
+                        pos -= 1;
+                    } else {
+                        while pos < len && class_at(pos) == Some(SubWordClass::Lower) {
+                            pos += 1;
+                        }
+                    }
+                }
+            }
+            Some(class @ (SubWordClass::Lower | SubWordClass::Digit)) => {
+                while pos < len && class_at(pos) == Some(class) {
+                    pos += 1;
+                }
+            }
+            Some(SubWordClass::Separator) | None => {}
+        }
+
+        // Then skip any separators
+        while pos < len && class_at(pos) == Some(SubWordClass::Separator) {
+            pos += 1;
+        }
+
+        pos
+    }
+
     /// Returns the first non-whitespace column on the given line.
     /// Returns 0 if the line is all whitespace or empty.
     pub fn first_non_whitespace_col(&self, line: usize) -> usize {
@@ -287,18 +658,18 @@ impl TextBuffer {
 
     /// Finds the start of the word at the given position.
     /// Unlike find_word_boundary_left, this doesn't skip whitespace first.
-    pub fn find_word_start(&self, char_idx: usize) -> usize {
+    pub fn find_word_start(&self, char_idx: usize, word_chars: WordChars) -> usize {
         if char_idx == 0 {
             return 0;
         }
 
         // If we're not on a word char, return current position
         if let Some(ch) = self.char_at(char_idx) {
-            if !Self::is_word_char(ch) {
+            if !word_chars.contains(ch) {
                 // Check if previous char is word char
                 if char_idx > 0 {
                     if let Some(prev_ch) = self.char_at(char_idx - 1) {
-                        if !Self::is_word_char(prev_ch) {
+                        if !word_chars.contains(prev_ch) {
                             return char_idx;
                         }
                     }
@@ -310,7 +681,7 @@ impl TextBuffer {
         // Go backwards to find word start
         while pos > 0 {
             if let Some(ch) = self.char_at(pos - 1) {
-                if !Self::is_word_char(ch) {
+                if !word_chars.contains(ch) {
                     break;
                 }
                 pos -= 1;
@@ -324,7 +695,7 @@ impl TextBuffer {
 
     /// Finds the end of the word at the given position.
     /// Unlike find_word_boundary_right, this doesn't skip whitespace after.
-    pub fn find_word_end(&self, char_idx: usize) -> usize {
+    pub fn find_word_end(&self, char_idx: usize, word_chars: WordChars) -> usize {
         let len = self.len_chars();
         if char_idx >= len {
             return len;
@@ -332,7 +703,7 @@ impl TextBuffer {
 
         // If we're not on a word char, check if we're just after a word
         if let Some(ch) = self.char_at(char_idx) {
-            if !Self::is_word_char(ch) {
+            if !word_chars.contains(ch) {
                 return char_idx;
             }
         }
@@ -341,7 +712,7 @@ impl TextBuffer {
         // Go forwards to find word end
         while pos < len {
             if let Some(ch) = self.char_at(pos) {
-                if !Self::is_word_char(ch) {
+                if !word_chars.contains(ch) {
                     break;
                 }
                 pos += 1;
@@ -354,10 +725,325 @@ impl TextBuffer {
     }
 }
 
+/// Characters of part of a line, read chunk by chunk from the rope.
+///
+/// Ropey's own iterators allocate when they span more than one chunk; this
+/// looks each chunk up instead, so drawing a line doesn't allocate.
+pub struct LineChars<'a> {
+    rope: &'a Rope,
+    /// The rest of the current chunk.
+    chunk: std::str::Chars<'a>,
+    /// Index of the next character in the rope.
+    next: usize,
+    /// Index of the character after the last.
+    stop: usize,
+}
+
+impl Iterator for LineChars<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.next >= self.stop {
+            return None;
+        }
+        let ch = match self.chunk.next() {
+            Some(ch) => ch,
+            None => {
+                let (chunk, chunk_byte, _, _) = self.rope.chunk_at_char(self.next);
+                let offset = self.rope.char_to_byte(self.next) - chunk_byte;
+                self.chunk = chunk[offset..].chars();
+                self.chunk.next()?
+            }
+        };
+        self.next += 1;
+        Some(ch)
+    }
+}
+
+/// What the reading thread of a [`BufferLoader`] sends.
+#[derive(Debug)]
+enum LoadMessage {
+    /// Decoded text following the text sent before.
+    Text(String),
+    /// The whole file, read again in the encoding it was detected as once
+    /// a later part turned out not to be UTF-8.
+    Whole(String, Encoding),
+}
+
+/// What a [`BufferLoader::poll`] did to the buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStep {
+    /// Nothing was read since the last poll.
+    Idle,
+    /// Text was appended.
+    Appended,
+    /// The text was replaced with the whole file, read in another
+    /// encoding.
+    Replaced,
+}
+
+/// Reads the rest of a large file on a background thread, for
+/// [`TextBuffer::open`].
+#[derive(Debug)]
+pub struct BufferLoader {
+    /// Decoded text from the reading thread, in file order.
+    chunks: Receiver<io::Result<LoadMessage>>,
+    /// Bytes of the file appended so far.
+    loaded: u64,
+    /// Size of the file in bytes.
+    total: u64,
+    /// Whether all of the file was appended or reading it failed.
+    done: bool,
+}
+
+impl BufferLoader {
+    /// Starts reading `file`, the file at `path`, after the `loaded` bytes
+    /// already in the buffer, the last of which are the incomplete
+    /// character `pending`.
+    pub(crate) fn start(mut file: impl Read + Send + 'static, path: PathBuf, mut pending: Vec<u8>, loaded: u64, total: u64) -> Self {
+        let (sender, chunks) = channel();
+        thread::spawn(move || {
+            let mut bytes = vec![0; LOAD_CHUNK_BYTES];
+            loop {
+                let text = match file.read(&mut bytes) {
+                    Ok(0) if pending.is_empty() => break,
+                    Ok(0) if pending == b"\r" => {
+                        pending.clear();
+                        Some("\r".to_string())
+                    }
+                    // The file ends inside a character
+                    Ok(0) => None,
+                    Ok(read) => decode_chunk(&mut pending, &bytes[..read]).ok(),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        let _ = sender.send(Err(e));
+                        break;
+                    }
+                };
+                let Some(text) = text else {
+                    // Not UTF-8 after all: read it whole, as a smaller file is
+                    let _ = sender.send(read_whole(&path));
+                    break;
+                };
+                if sender.send(Ok(LoadMessage::Text(text))).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            chunks,
+            loaded,
+            total,
+            done: false,
+        }
+    }
+
+    /// Returns how much of the file was appended, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        (self.loaded as f64 / self.total as f64).min(1.0) as f32
+    }
+
+    /// Returns true once all of the file was appended, or reading it
+    /// failed.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Appends the text read since the last call to `buffer` without
+    /// blocking, marking it fully loaded at the end of the file. Returns
+    /// what changed, or the error reading stopped at; the buffer is then
+    /// left [partial](TextBuffer::is_partial).
+    pub fn poll(&mut self, buffer: &mut TextBuffer) -> io::Result<LoadStep> {
+        let mut step = LoadStep::Idle;
+        loop {
+            match self.chunks.try_recv() {
+                Ok(Ok(LoadMessage::Text(text))) => {
+                    self.loaded += text.len() as u64;
+                    let text = if buffer.strips_cr() { text.replace("\r\n", "\n") } else { text };
+                    buffer.rope.insert(buffer.rope.len_chars(), &text);
+                    if step == LoadStep::Idle {
+                        step = LoadStep::Appended;
+                    }
+                }
+                Ok(Ok(LoadMessage::Whole(text, encoding))) => {
+                    self.loaded = self.total;
+                    let whole = TextBuffer::from_file_text(text, encoding);
+                    buffer.set_format_from(&whole);
+                    buffer.rope = whole.rope;
+                    step = LoadStep::Replaced;
+                }
+                Ok(Err(e)) => {
+                    self.done = true;
+                    buffer.loading = false;
+                    buffer.partial = true;
+                    return Err(e);
+                }
+                Err(TryRecvError::Empty) => return Ok(step),
+                Err(TryRecvError::Disconnected) => {
+                    self.done = true;
+                    buffer.loading = false;
+                    return Ok(step);
+                }
+            }
+        }
+    }
+}
+
+/// Reads the file at `path` whole, in the encoding it is detected as.
+fn read_whole(path: &Path) -> io::Result<LoadMessage> {
+    let bytes = fs::read(path)?;
+    let encoding = Encoding::detect(&bytes);
+    Ok(LoadMessage::Whole(encoding.decode(&bytes)?, encoding))
+}
+
+/// Decodes `bytes` read after the incomplete character `pending` of the
+/// previous chunk, leaving this chunk's incomplete last character there.
+/// A last `\r` is left there too, so no chunk ends inside a `\r\n`.
+fn decode_chunk(pending: &mut Vec<u8>, bytes: &[u8]) -> io::Result<String> {
+    pending.extend_from_slice(bytes);
+    let mut valid = match std::str::from_utf8(pending) {
+        Ok(_) => pending.len(),
+        // Cut off at the end of the chunk; the rest comes with the next
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+    };
+    if valid > 0 && pending[valid - 1] == b'\r' {
+        valid -= 1;
+    }
+    let rest = pending.split_off(valid);
+    let text = std::mem::replace(pending, rest);
+    Ok(String::from_utf8(text).expect("checked to be valid UTF-8"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_streaming_open() {
+        let path = std::env::temp_dir().join(format!("cp_editor_streaming_{}.txt", std::process::id()));
+        // Two-byte characters after an odd start, so chunks end inside one
+        let text = format!("a{}\nend\n", "é".repeat(100_000));
+        fs::write(&path, &text).unwrap();
+
+        let (mut buffer, loader) = TextBuffer::open_streaming_from(&path, 0).unwrap();
+        let mut loader = loader.unwrap();
+        assert!(!buffer.is_fully_loaded());
+        assert_eq!(buffer.len_chars(), 1 + (FIRST_SCREEN_BYTES as usize - 1) / 2);
+        while !loader.is_done() {
+            loader.poll(&mut buffer).unwrap();
+            thread::yield_now();
+        }
+        assert!(buffer.is_fully_loaded());
+        assert_eq!(loader.progress(), 1.0);
+        assert_eq!(buffer.to_string(), text);
+
+        // Small files are read whole
+        let (buffer, loader) = TextBuffer::open(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert!(loader.is_none() && buffer.is_fully_loaded());
+        assert_eq!(buffer.to_string(), text);
+    }
+
+    #[test]
+    fn test_streaming_open_of_latin1_past_first_screen() {
+        let path = std::env::temp_dir().join(format!("cp_editor_streaming_latin1_{}.txt", std::process::id()));
+        // Plain ASCII for the first screenful, then a Latin-1 byte
+        let mut bytes = "abc\n".repeat(50_000).into_bytes();
+        bytes.extend_from_slice(b"caf\xe9\n");
+        fs::write(&path, &bytes).unwrap();
+
+        let (mut buffer, loader) = TextBuffer::open_streaming_from(&path, 0).unwrap();
+        let mut loader = loader.unwrap();
+        assert_eq!(buffer.encoding(), Encoding::Utf8);
+        let mut replaced = false;
+        while !loader.is_done() {
+            replaced |= loader.poll(&mut buffer).unwrap() == LoadStep::Replaced;
+            thread::yield_now();
+        }
+        let _ = fs::remove_file(&path);
+        // It opens as it would below the streaming threshold
+        assert!(replaced);
+        assert!(buffer.is_fully_loaded());
+        assert_eq!(buffer.encoding(), Encoding::Latin1);
+        assert_eq!(buffer.to_string(), TextBuffer::from_bytes(&bytes, Encoding::Latin1).unwrap().to_string());
+        assert!(buffer.to_string().ends_with("caf\u{e9}\n"));
+    }
+
+    #[test]
+    fn test_decode_chunk() {
+        let mut pending = Vec::new();
+        let bytes = "aé".as_bytes();
+        assert_eq!(decode_chunk(&mut pending, &bytes[..2]).unwrap(), "a");
+        assert_eq!(pending, &bytes[1..2]);
+        assert_eq!(decode_chunk(&mut pending, &bytes[2..]).unwrap(), "é");
+        assert!(pending.is_empty());
+        assert_eq!(decode_chunk(&mut pending, b"\xff").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_line_endings_round_trip() {
+        let path = std::env::temp_dir().join(format!("cp_editor_line_endings_{}.txt", std::process::id()));
+        fs::write(&path, "a\r\nb\r\n").unwrap();
+        let buffer = TextBuffer::from_file(&path).unwrap();
+        assert_eq!(buffer.to_string(), "a\nb\n");
+        assert_eq!(buffer.line_ending(), LineEnding::Crlf);
+        assert!(!buffer.has_mixed_line_endings());
+        buffer.save_to_file(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\r\nb\r\n");
+
+        // Mixed line endings are kept as they are
+        fs::write(&path, "a\r\nb\r\nc\n").unwrap();
+        let buffer = TextBuffer::from_file(&path).unwrap();
+        assert_eq!(buffer.to_string(), "a\r\nb\r\nc\n");
+        assert!(buffer.has_mixed_line_endings());
+        buffer.save_to_file(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\r\nb\r\nc\n");
+
+        // Streaming strips the \r of a \r\n split across chunks too
+        let text = format!("a\r\n{}\r\n{}", "x".repeat(FIRST_SCREEN_BYTES as usize - 4), "y\r\n".repeat(1000));
+        fs::write(&path, &text).unwrap();
+        let (mut buffer, loader) = TextBuffer::open_streaming_from(&path, 0).unwrap();
+        let mut loader = loader.unwrap();
+        while !loader.is_done() {
+            loader.poll(&mut buffer).unwrap();
+            thread::yield_now();
+        }
+        assert_eq!(buffer.to_string(), text.replace("\r\n", "\n"));
+        buffer.save_to_file(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_non_utf8_files_round_trip() {
+        let path = std::env::temp_dir().join(format!("cp_editor_encoding_{}.txt", std::process::id()));
+        for bytes in [&b"caf\xE9\n"[..], b"\xFF\xFEh\0i\0\n\0", b"\xEF\xBB\xBFhi\n"] {
+            fs::write(&path, bytes).unwrap();
+            let (buffer, loader) = TextBuffer::open_streaming_from(&path, 0).unwrap();
+            assert!(loader.is_none() || buffer.encoding() == Encoding::Utf8Bom);
+            assert!(!buffer.to_string().starts_with('\u{feff}'));
+            let buffer = TextBuffer::from_file(&path).unwrap();
+            buffer.save_to_file(&path).unwrap();
+            assert_eq!(fs::read(&path).unwrap(), bytes, "{}", buffer.encoding().label());
+        }
+        let buffer = TextBuffer::from_file(&path).unwrap();
+        assert_eq!(buffer.to_string(), "hi\n");
+
+        // Reading as Latin-1 shows any bytes; saving keeps them
+        fs::write(&path, b"caf\xC3\xA9").unwrap();
+        let mut buffer = TextBuffer::from_file_as(&path, Encoding::Latin1).unwrap();
+        assert_eq!(buffer.to_string(), "caf\u{c3}\u{a9}");
+        // and a character Latin-1 lacks is written as '?'
+        buffer.insert(0, "\u{20ac}");
+        buffer.save_to_file(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"?caf\xC3\xA9");
+        let _ = fs::remove_file(&path);
+    }
+
     #[test]
     fn test_new_buffer() {
         let buf = TextBuffer::new();
@@ -382,6 +1068,24 @@ mod tests {
         assert_eq!(buf.to_string(), "abc");
     }
 
+    #[test]
+    fn test_content_hash() {
+        let mut buffer = TextBuffer::from_str("hello world");
+        let hash = buffer.content_hash();
+        assert_eq!(TextBuffer::new().content_hash(), 0xcbf2_9ce4_8422_2325);
+
+        buffer.insert(5, ",");
+        assert_ne!(buffer.content_hash(), hash);
+        buffer.remove(5, 6);
+        assert_eq!(buffer.content_hash(), hash);
+
+        // Independent of how the rope splits the text into chunks
+        let long = "line of text\n".repeat(2000);
+        let mut edited = TextBuffer::from_str(&long[..long.len() / 2]);
+        edited.insert(edited.len_chars(), &long[long.len() / 2..]);
+        assert_eq!(edited.content_hash(), TextBuffer::from_str(&long).content_hash());
+    }
+
     #[test]
     fn test_insert_string() {
         let mut buf = TextBuffer::new();
@@ -415,6 +1119,24 @@ mod tests {
         assert_eq!(buf.line_len_chars(2), 0);
     }
 
+    #[test]
+    fn test_line_slice() {
+        let buf = TextBuffer::from_str("abc
+défgh
+");
+        assert_eq!(buf.line_slice(1, 1, 3), "éf");
+        // Clamped to the line, without its line break
+        assert_eq!(buf.line_slice(0, 1, 10), "bc");
+        assert_eq!(buf.line_slice(0, 5, 10), "");
+        assert_eq!(buf.line_slice(7, 0, 10), "");
+        for (line, start, end) in [(1, 1, 3), (0, 1, 10), (0, 5, 10), (7, 0, 10)] {
+            assert_eq!(buf.line_slice_chars(line, start, end).collect::<String>(), buf.line_slice(line, start, end));
+        }
+        // Across the rope's chunks
+        let long = TextBuffer::from_str(&format!("x\n{}\n", "aé".repeat(5000)));
+        assert_eq!(long.line_slice_chars(1, 999, 9001).collect::<String>(), long.line_slice(1, 999, 9001));
+    }
+
     #[test]
     fn test_char_to_line_col() {
         let buf = TextBuffer::from_str("abc\ndefgh");
@@ -437,31 +1159,92 @@ mod tests {
     #[test]
     fn test_word_boundary_left() {
         let buf = TextBuffer::from_str("hello world test");
+        let words = WordChars::default();
         // From end of "test"
-        assert_eq!(buf.find_word_boundary_left(16), 12);
+        assert_eq!(buf.find_word_boundary_left(16, words), 12);
         // From middle of "world"
-        assert_eq!(buf.find_word_boundary_left(8), 6);
+        assert_eq!(buf.find_word_boundary_left(8, words), 6);
         // From start of "world"
-        assert_eq!(buf.find_word_boundary_left(6), 0);
+        assert_eq!(buf.find_word_boundary_left(6, words), 0);
         // From space after "hello"
-        assert_eq!(buf.find_word_boundary_left(5), 0);
+        assert_eq!(buf.find_word_boundary_left(5, words), 0);
         // From start
-        assert_eq!(buf.find_word_boundary_left(0), 0);
+        assert_eq!(buf.find_word_boundary_left(0, words), 0);
     }
 
     #[test]
     fn test_word_boundary_right() {
         let buf = TextBuffer::from_str("hello world test");
+        let words = WordChars::default();
         // From start
-        assert_eq!(buf.find_word_boundary_right(0), 6);
+        assert_eq!(buf.find_word_boundary_right(0, words), 6);
         // From middle of "hello"
-        assert_eq!(buf.find_word_boundary_right(2), 6);
+        assert_eq!(buf.find_word_boundary_right(2, words), 6);
         // From space after "hello"
-        assert_eq!(buf.find_word_boundary_right(5), 6);
+        assert_eq!(buf.find_word_boundary_right(5, words), 6);
         // From start of "world"
-        assert_eq!(buf.find_word_boundary_right(6), 12);
+        assert_eq!(buf.find_word_boundary_right(6, words), 12);
         // From end
-        assert_eq!(buf.find_word_boundary_right(16), 16);
+        assert_eq!(buf.find_word_boundary_right(16, words), 16);
+    }
+
+    #[test]
+    fn test_word_chars() {
+        let text = "$el.foo-bar";
+        let buf = TextBuffer::from_str(text);
+        // `$` and `-` only belong to words where the language says so
+        let words = WordChars::default();
+        assert_eq!(buf.find_word_start(2, words), 1);
+        assert_eq!(buf.find_word_end(5, words), 7);
+        let words = WordChars::new("$-");
+        assert_eq!(buf.find_word_start(2, words), 0);
+        assert_eq!(buf.find_word_end(5, words), text.len());
+        assert_eq!(buf.find_word_boundary_right(0, words), 4);
+        assert_eq!(buf.find_word_boundary_left(text.len(), words), 4);
+    }
+
+    #[test]
+    fn test_subword_boundaries() {
+        // Each case lists the stops moving right from the start, and the
+        // stops moving left from the end
+        let cases: &[(&str, &str, &[usize], &[usize])] = &[
+            ("camelCase", "", &[5, 9], &[5, 0]),
+            ("parseHTTPResponse", "", &[5, 9, 17], &[9, 5, 0]),
+            ("XMLHttpRequest", "", &[3, 7, 14], &[7, 3, 0]),
+            ("SCREAMING_SNAKE", "", &[10, 15], &[10, 0]),
+            ("_private__field", "", &[1, 10, 15], &[10, 1, 0]),
+            ("kebab-case", "-", &[6, 10], &[6, 0]),
+            ("kebab-case", "", &[6, 10], &[6, 0]),
+            ("utf8Decode", "", &[3, 4, 10], &[4, 3, 0]),
+            ("v2Api", "", &[1, 2, 5], &[2, 1, 0]),
+            ("sha256sum", "", &[3, 6, 9], &[6, 3, 0]),
+            ("fooBar baz", "", &[3, 7, 10], &[7, 3, 0]),
+            ("$scope.value", "$", &[1, 7, 12], &[7, 1, 0]),
+        ];
+        for &(text, extra, right, left) in cases {
+            let buf = TextBuffer::from_str(text);
+            let words = WordChars::new(extra);
+
+            let mut stops = Vec::new();
+            let mut pos = 0;
+            while pos < buf.len_chars() {
+                pos = buf.find_subword_boundary_right(pos, words);
+                stops.push(pos);
+            }
+            assert_eq!(stops, right, "moving right through {:?}", text);
+
+            let mut stops = Vec::new();
+            let mut pos = buf.len_chars();
+            loop {
+                let next = buf.find_subword_boundary_left(pos, words);
+                if next == pos {
+                    break;
+                }
+                stops.push(next);
+                pos = next;
+            }
+            assert_eq!(stops, left, "moving left through {:?}", text);
+        }
     }
 
     #[test]
@@ -1,6 +1,6 @@
 //! Cursor and selection handling.
 
-use crate::buffer::TextBuffer;
+use crate::buffer::{TextBuffer, WordChars};
 
 /// Represents a position in the buffer as (line, column).
 /// Both are 0-indexed.
@@ -83,7 +83,7 @@ impl BlockSelection {
 
 /// A text selection with an anchor and a cursor position.
 /// When anchor == cursor, there is no active selection.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct Selection {
     /// The anchor point (where selection started).
     pub anchor: usize,
@@ -91,14 +91,6 @@ pub struct Selection {
     pub cursor: usize,
 }
 
-impl Default for Selection {
-    fn default() -> Self {
-        Self {
-            anchor: 0,
-            cursor: 0,
-        }
-    }
-}
 
 impl Selection {
     /// Creates a new selection at the given position (no active selection).
@@ -161,6 +153,20 @@ pub enum SelectionMode {
     Block,
 }
 
+/// What a mouse selection is made of: characters for a single click, words
+/// after a double click and lines after a triple click. Dragging extends
+/// the selection a whole unit at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionGranularity {
+    /// Single characters.
+    #[default]
+    Char,
+    /// Whole words.
+    Word,
+    /// Whole lines, with their line breaks.
+    Line,
+}
+
 /// Cursor manager that handles cursor movement relative to a buffer.
 #[derive(Debug, Clone)]
 pub struct Cursor {
@@ -231,6 +237,19 @@ impl Cursor {
         self.selection.cursor
     }
 
+    /// Returns the column vertical movement aims for: the one kept from
+    /// the last vertical move, or else the cursor's own column.
+    pub fn goal_col(&self, buffer: &TextBuffer) -> usize {
+        self.preferred_col
+            .unwrap_or_else(|| buffer.char_to_line_col(self.selection.cursor).1)
+    }
+
+    /// Makes vertical movement aim for `col`, as if the cursor had come
+    /// from that column.
+    pub fn set_preferred_col(&mut self, col: usize) {
+        self.preferred_col = Some(col);
+    }
+
     /// Sets the cursor position.
     pub fn set_position(&mut self, pos: usize, extend: bool) {
         self.selection.set_cursor(pos, extend);
@@ -275,15 +294,29 @@ impl Cursor {
     }
 
     /// Moves cursor left by one word.
-    pub fn move_word_left(&mut self, buffer: &TextBuffer, extend: bool) {
-        let new_pos = buffer.find_word_boundary_left(self.selection.cursor);
+    pub fn move_word_left(&mut self, buffer: &TextBuffer, word_chars: WordChars, extend: bool) {
+        let new_pos = buffer.find_word_boundary_left(self.selection.cursor, word_chars);
         self.selection.set_cursor(new_pos, extend);
         self.preferred_col = None;
     }
 
     /// Moves cursor right by one word.
-    pub fn move_word_right(&mut self, buffer: &TextBuffer, extend: bool) {
-        let new_pos = buffer.find_word_boundary_right(self.selection.cursor);
+    pub fn move_word_right(&mut self, buffer: &TextBuffer, word_chars: WordChars, extend: bool) {
+        let new_pos = buffer.find_word_boundary_right(self.selection.cursor, word_chars);
+        self.selection.set_cursor(new_pos, extend);
+        self.preferred_col = None;
+    }
+
+    /// Moves cursor left by one sub-word (camelCase hump or snake_case part).
+    pub fn move_subword_left(&mut self, buffer: &TextBuffer, word_chars: WordChars, extend: bool) {
+        let new_pos = buffer.find_subword_boundary_left(self.selection.cursor, word_chars);
+        self.selection.set_cursor(new_pos, extend);
+        self.preferred_col = None;
+    }
+
+    /// Moves cursor right by one sub-word (camelCase hump or snake_case part).
+    pub fn move_subword_right(&mut self, buffer: &TextBuffer, word_chars: WordChars, extend: bool) {
+        let new_pos = buffer.find_subword_boundary_right(self.selection.cursor, word_chars);
         self.selection.set_cursor(new_pos, extend);
         self.preferred_col = None;
     }
@@ -475,6 +508,11 @@ impl MultiCursor {
         self.cursors.len()
     }
 
+    /// Returns true if there are no cursors.
+    pub fn is_empty(&self) -> bool {
+        self.cursors.is_empty()
+    }
+
     /// Returns true if there's only one cursor.
     pub fn is_single(&self) -> bool {
         self.cursors.len() == 1
@@ -517,12 +555,63 @@ impl MultiCursor {
         true
     }
 
+    /// Adds a new cursor with the given selection and makes it the primary.
+    /// Returns false if a cursor already has that selection.
+    pub fn add_selection(&mut self, selection: Selection) -> bool {
+        if self.cursors.iter().any(|c| c.selection.range() == selection.range()) {
+            return false;
+        }
+
+        let mut new_cursor = Cursor::new();
+        new_cursor.selection = selection;
+        self.cursors.push(new_cursor);
+        self.primary_index = self.cursors.len() - 1;
+        self.normalize();
+        true
+    }
+
+    /// Replaces all cursors with one per selection, making the one at
+    /// `primary` the primary. Does nothing if `selections` is empty.
+    ///
+    /// Cheaper than adding the cursors one by one, which normalizes after
+    /// each of them.
+    pub fn set_selections(&mut self, selections: Vec<Selection>, primary: usize) {
+        if selections.is_empty() {
+            return;
+        }
+        self.primary_index = primary.min(selections.len() - 1);
+        self.cursors = selections
+            .into_iter()
+            .map(|selection| {
+                let mut cursor = Cursor::new();
+                cursor.selection = selection;
+                cursor
+            })
+            .collect();
+        self.normalize();
+    }
+
     /// Adds a new cursor at the given line and column.
     pub fn add_cursor_at(&mut self, buffer: &TextBuffer, line: usize, col: usize) -> bool {
         let pos = buffer.line_col_to_char(line, col);
         self.add_cursor(pos)
     }
 
+    /// Adds a cursor on `line` at `goal_col`, or at the line's end if the
+    /// line is shorter. The cursor keeps aiming for `goal_col` when it
+    /// moves vertically. Returns true if the cursor was added.
+    pub fn add_cursor_at_goal(&mut self, buffer: &TextBuffer, line: usize, goal_col: usize) -> bool {
+        let col = goal_col.min(buffer.line_len_chars(line));
+        let pos = buffer.line_col_to_char(line, col);
+        if !self.add_cursor(pos) {
+            return false;
+        }
+        if let Some(cursor) = self.cursors.iter_mut().find(|c| c.position() == pos) {
+            cursor.set_preferred_col(goal_col);
+        }
+        true
+    }
+
     /// Removes all secondary cursors, keeping only the primary.
     pub fn collapse_to_primary(&mut self) {
         let primary = self.cursors[self.primary_index].clone();
@@ -546,6 +635,18 @@ impl MultiCursor {
         }
     }
 
+    /// Removes the cursor at the given character position.
+    /// Returns false if no cursor is there or it is the only one.
+    pub fn remove_cursor_at(&mut self, pos: usize) -> bool {
+        match self.cursors.iter().position(|c| c.position() == pos) {
+            Some(index) if self.cursors.len() > 1 => {
+                self.remove_cursor(index);
+                true
+            }
+            _ => false,
+        }
+    }
+
     /// Normalizes cursors: sorts by position and merges overlapping selections.
     pub fn normalize(&mut self) {
         if self.cursors.len() <= 1 {
@@ -700,6 +801,22 @@ mod tests {
         assert_eq!(col, 10);
     }
 
+    #[test]
+    fn test_add_cursor_at_goal() {
+        let buffer = TextBuffer::from_str("long line here\nab\nanother long line");
+        let mut cursors = MultiCursor::new();
+        assert!(cursors.add_cursor_at_goal(&buffer, 1, 10));
+        let cursor = cursors.iter().last().unwrap().clone();
+        assert_eq!(buffer.char_to_line_col(cursor.position()), (1, 2));
+        assert_eq!(cursor.goal_col(&buffer), 10);
+
+        // Moving on from the short line goes back to the goal column
+        let mut cursor = cursor;
+        cursor.move_down(&buffer, false);
+        assert_eq!(buffer.char_to_line_col(cursor.position()), (2, 10));
+        assert!(!cursors.add_cursor_at_goal(&buffer, 1, 12));
+    }
+
     #[test]
     fn test_line_navigation() {
         let buffer = TextBuffer::from_str("hello world");
@@ -768,6 +885,23 @@ mod tests {
         assert_eq!(mc.len(), 1);
     }
 
+    #[test]
+    fn test_multi_cursor_remove_at() {
+        let mut mc = MultiCursor::new();
+        mc.add_cursor(5);
+        mc.add_cursor(10);
+
+        assert!(!mc.remove_cursor_at(7));
+        assert!(mc.remove_cursor_at(5));
+        assert_eq!(mc.positions(), vec![0, 10]);
+
+        // The last cursor stays
+        assert!(mc.remove_cursor_at(0));
+        assert!(!mc.remove_cursor_at(10));
+        assert_eq!(mc.positions(), vec![10]);
+        assert_eq!(mc.primary().position(), 10);
+    }
+
     #[test]
     fn test_multi_cursor_adjust_positions() {
         let mut mc = MultiCursor::new();
@@ -0,0 +1,371 @@
+//! Line-based diff.
+//!
+//! Compares two texts line by line (Myers' algorithm) and reports the changed
+//! runs as hunks. Optionally ignores whitespace: lines are normalized before
+//! comparing, but hunks always refer to the real line numbers.
+
+use std::collections::HashMap;
+
+/// How a hunk changes the old text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// Lines were inserted.
+    Added,
+    /// Lines were replaced.
+    Modified,
+    /// Lines were removed.
+    Deleted,
+}
+
+/// Options for comparing lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DiffOptions {
+    /// Treat lines that differ only in whitespace as equal.
+    pub ignore_whitespace: bool,
+}
+
+/// A run of changed lines.
+///
+/// `old_start..old_start + old_len` in the old text was replaced by
+/// `new_start..new_start + new_len` in the new text. For a pure insertion
+/// `old_len` is 0; for a pure deletion `new_len` is 0 and `new_start` is the
+/// line the removed lines were in front of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hunk {
+    /// First changed line in the old text.
+    pub old_start: usize,
+    /// Number of changed lines in the old text.
+    pub old_len: usize,
+    /// First changed line in the new text.
+    pub new_start: usize,
+    /// Number of changed lines in the new text.
+    pub new_len: usize,
+    /// Whether the old and new lines differ only in whitespace.
+    /// Always false when whitespace is ignored, since such lines are equal.
+    pub whitespace_only: bool,
+}
+
+impl Hunk {
+    /// Returns how this hunk changes the old text.
+    pub fn kind(&self) -> ChangeKind {
+        match (self.old_len, self.new_len) {
+            (0, _) => ChangeKind::Added,
+            (_, 0) => ChangeKind::Deleted,
+            _ => ChangeKind::Modified,
+        }
+    }
+
+    /// Returns the range of lines this hunk covers in the new text.
+    pub fn new_lines(&self) -> std::ops::Range<usize> {
+        self.new_start..self.new_start + self.new_len
+    }
+}
+
+/// Normalizes a line for whitespace-insensitive comparison: leading and
+/// trailing whitespace is dropped and inner runs collapse to one space.
+pub fn normalize_whitespace(line: &str) -> String {
+    line.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Diffs two texts line by line.
+pub fn diff_text(old: &str, new: &str, options: DiffOptions) -> Vec<Hunk> {
+    let old: Vec<&str> = old.split('\n').collect();
+    let new: Vec<&str> = new.split('\n').collect();
+    diff_lines(&old, &new, options)
+}
+
+/// Diffs two sequences of lines.
+pub fn diff_lines(old: &[&str], new: &[&str], options: DiffOptions) -> Vec<Hunk> {
+    // Compare small integer ids instead of strings
+    let mut ids: HashMap<String, u32> = HashMap::new();
+    let mut intern = |line: &str| {
+        let key = if options.ignore_whitespace {
+            normalize_whitespace(line)
+        } else {
+            line.trim_end_matches('\r').to_string()
+        };
+        let next = ids.len() as u32;
+        *ids.entry(key).or_insert(next)
+    };
+    let a: Vec<u32> = old.iter().map(|line| intern(line)).collect();
+    let b: Vec<u32> = new.iter().map(|line| intern(line)).collect();
+
+    // The common prefix and suffix are usually most of the file
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut matches: Vec<(usize, usize)> = myers_matches(a_mid, b_mid)
+        .into_iter()
+        .map(|(x, y)| (x + prefix, y + prefix))
+        .collect();
+    matches.push((a.len() - suffix, b.len() - suffix));
+
+    let mut hunks = Vec::new();
+    let (mut x, mut y) = (prefix, prefix);
+    for (mx, my) in matches {
+        if mx > x || my > y {
+            let whitespace_only = !options.ignore_whitespace
+                && mx - x == my - y
+                && old[x..mx]
+                    .iter()
+                    .zip(&new[y..my])
+                    .all(|(o, n)| normalize_whitespace(o) == normalize_whitespace(n));
+            hunks.push(Hunk {
+                old_start: x,
+                old_len: mx - x,
+                new_start: y,
+                new_len: my - y,
+                whitespace_only,
+            });
+        }
+        x = mx + 1;
+        y = my + 1;
+    }
+    hunks
+}
+
+/// Returns the matched index pairs of a shortest edit script from `a` to `b`,
+/// in increasing order.
+/// Formats the changes from `old` to `new` as a unified diff, with
+/// `context` unchanged lines around each change. Empty if the texts have
+/// the same lines.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str, context: usize) -> String {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let hunks = diff_lines(&old_lines, &new_lines, DiffOptions::default());
+    if hunks.is_empty() {
+        return String::new();
+    }
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    let mut first = 0;
+    while first < hunks.len() {
+        // Changes whose context would touch share a section
+        let mut last = first;
+        while last + 1 < hunks.len()
+            && hunks[last + 1].old_start - (hunks[last].old_start + hunks[last].old_len) <= 2 * context
+        {
+            last += 1;
+        }
+        let (start, end) = (hunks[first], hunks[last]);
+        let old_from = start.old_start.saturating_sub(context);
+        let new_from = start.new_start - (start.old_start - old_from);
+        let old_to = (end.old_start + end.old_len + context).min(old_lines.len());
+        let new_to = end.new_start + end.new_len + (old_to - end.old_start - end.old_len);
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            section_range(old_from, old_to - old_from),
+            section_range(new_from, new_to - new_from)
+        ));
+        let mut old_pos = old_from;
+        for hunk in &hunks[first..=last] {
+            for line in &old_lines[old_pos..hunk.old_start] {
+                out.push_str(&format!(" {}\n", line));
+            }
+            for line in &old_lines[hunk.old_start..hunk.old_start + hunk.old_len] {
+                out.push_str(&format!("-{}\n", line));
+            }
+            for line in &new_lines[hunk.new_lines()] {
+                out.push_str(&format!("+{}\n", line));
+            }
+            old_pos = hunk.old_start + hunk.old_len;
+        }
+        for line in &old_lines[old_pos..old_to] {
+            out.push_str(&format!(" {}\n", line));
+        }
+        first = last + 1;
+    }
+    out
+}
+
+/// Splits text into lines, without the empty one after a final line break.
+fn split_lines(text: &str) -> Vec<&str> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    text.strip_suffix('\n').unwrap_or(text).split('\n').collect()
+}
+
+/// Formats a section's line range for its `@@` header: the 1-based first
+/// line and the count, or for an empty range the line before it.
+fn section_range(from: usize, len: usize) -> String {
+    if len == 0 {
+        format!("{},0", from)
+    } else {
+        format!("{},{}", from + 1, len)
+    }
+}
+
+fn myers_matches(a: &[u32], b: &[u32]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    // trace[d] holds the furthest x on diagonals -(d + 1)..=d + 1 before step d
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        let lo = (offset - d - 1) as usize;
+        trace.push(v[lo..=lo + 2 * d as usize + 2].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk back from the end, collecting the diagonal (matching) moves
+    let mut matches = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = if d == 0 { 0 } else { at(prev_k) };
+        let prev_y = if d == 0 { 0 } else { prev_x - prev_k };
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    matches.reverse();
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hunk(old_start: usize, old_len: usize, new_start: usize, new_len: usize) -> Hunk {
+        Hunk {
+            old_start,
+            old_len,
+            new_start,
+            new_len,
+            whitespace_only: false,
+        }
+    }
+
+    #[test]
+    fn test_identical_texts() {
+        assert!(diff_text("a\nb\nc", "a\nb\nc", DiffOptions::default()).is_empty());
+        assert!(diff_text("", "", DiffOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_added_modified_deleted() {
+        let old = "a\nb\nc\nd\ne";
+        let new = "a\nx\nb\nc\nE\ne";
+        // Deleting "d" and adding "E" in its place is a modification
+        let hunks = diff_text(old, new, DiffOptions::default());
+        assert_eq!(hunks, vec![hunk(1, 0, 1, 1), hunk(3, 1, 4, 1)]);
+        assert_eq!(hunks[0].kind(), ChangeKind::Added);
+        assert_eq!(hunks[1].kind(), ChangeKind::Modified);
+
+        let hunks = diff_text(old, "a\ne", DiffOptions::default());
+        assert_eq!(hunks, vec![hunk(1, 3, 1, 0)]);
+        assert_eq!(hunks[0].kind(), ChangeKind::Deleted);
+    }
+
+    #[test]
+    fn test_changes_far_apart() {
+        let old: Vec<String> = (0..50).map(|i| format!("line {}", i)).collect();
+        let mut new = old.clone();
+        new[3] = "changed".to_string();
+        new.insert(30, "inserted".to_string());
+        new.remove(45);
+        let old: Vec<&str> = old.iter().map(String::as_str).collect();
+        let new: Vec<&str> = new.iter().map(String::as_str).collect();
+        assert_eq!(
+            diff_lines(&old, &new, DiffOptions::default()),
+            vec![hunk(3, 1, 3, 1), hunk(30, 0, 30, 1), hunk(44, 1, 45, 0)]
+        );
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\nI\nj\n";
+        assert_eq!(unified_diff(old, old, "old", "new", 3), "");
+        assert_eq!(
+            unified_diff(old, new, "old", "new", 1),
+            "--- old\n+++ new\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -8,3 +8,3 @@\n h\n-i\n+I\n j\n"
+        );
+        // With more context the two changes share a section
+        assert_eq!(unified_diff(old, new, "old", "new", 3).matches("@@ -").count(), 1);
+        assert_eq!(unified_diff("", "x\n", "old", "new", 3), "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+x\n");
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        assert_eq!(normalize_whitespace("\t  foo   bar\t"), "foo bar");
+        assert_eq!(normalize_whitespace("foo\t\tbar"), "foo bar");
+        assert_eq!(normalize_whitespace(" \t "), "");
+        assert_ne!(normalize_whitespace("foo bar"), normalize_whitespace("foobar"));
+    }
+
+    #[test]
+    fn test_whitespace_only_hunks_are_flagged() {
+        let old = "fn f() {\n\tlet x = 1;\n\tx\n}";
+        let new = "fn f() {\n    let x  = 1;\n    x\n}";
+        let hunks = diff_text(old, new, DiffOptions::default());
+        assert_eq!(hunks.len(), 1);
+        assert_eq!((hunks[0].new_start, hunks[0].new_len), (1, 2));
+        assert!(hunks[0].whitespace_only);
+
+        // One real change in the run makes the whole hunk a real change
+        let new = "fn f() {\n    let x = 2;\n    x\n}";
+        let hunks = diff_text(old, new, DiffOptions::default());
+        assert_eq!(hunks.len(), 1);
+        assert!(!hunks[0].whitespace_only);
+    }
+
+    #[test]
+    fn test_ignore_whitespace_keeps_real_line_numbers() {
+        let ignore = DiffOptions { ignore_whitespace: true };
+        // Re-indented from tabs to spaces, one line inserted above and one
+        // real change inside the re-indented block
+        let old = "if a {\n\tb();\n\tc();\n\td();\n}";
+        let new = "// note\nif a {\n    b();\n  \t c(1);\n    d();\n}";
+        assert_eq!(diff_text(old, new, ignore), vec![hunk(0, 0, 0, 1), hunk(2, 1, 3, 1)]);
+
+        // Without the option every re-indented line is part of the change
+        assert_eq!(
+            diff_text(old, new, DiffOptions::default()),
+            vec![hunk(0, 0, 0, 1), hunk(1, 3, 2, 3)]
+        );
+    }
+
+    #[test]
+    fn test_ignore_whitespace_trailing_and_blank_lines() {
+        let ignore = DiffOptions { ignore_whitespace: true };
+        assert!(diff_text("a  \nb\r\n\t\nc", "a\n  b\n\nc", ignore).is_empty());
+        // Removing a blank line is still a change
+        assert_eq!(diff_text("a\n\t\nb", "a\nb", ignore), vec![hunk(1, 1, 1, 0)]);
+    }
+}
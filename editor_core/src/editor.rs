@@ -1,14 +1,98 @@
 //! Main editor logic.
 
-use crate::buffer::TextBuffer;
-use crate::cursor::{Cursor, MultiCursor, Position, Selection};
+use crate::buffer::{BufferLoader, LoadStep, TextBuffer, WordChars, STREAMING_THRESHOLD};
+use crate::cursor::{BlockSelection, Cursor, MultiCursor, Position, Selection, SelectionGranularity};
+use crate::diff::{self, ChangeKind, DiffOptions, Hunk};
+use crate::encoding::Encoding;
 use crate::fold::FoldManager;
-use crate::history::{EditOperation, History};
-use crate::lsp_types::{CompletionItem, Diagnostic, HoverInfo};
+use crate::fuzzy;
+use crate::history::{merge_adjacent, EditOperation, History};
+use crate::indent::{IndentConfig, IndentStyle};
+use crate::invisible::{self, InvisibleCounts, LineLayout};
+use crate::line_ending::LineEnding;
+use crate::lsp_types::{CompletionItem, Diagnostic, DiagnosticSeverity, HoverInfo, SignatureHelp};
+use crate::freshness::{BufferTimes, DiskStamp};
 use crate::search::{Search, SearchMatch};
-use crate::syntax::{Language, SyntaxHighlighter};
+use crate::settings::EditorSettings;
+use crate::snippet::{self, SnippetSession};
+use crate::view_state::ViewState;
+use crate::viewport;
+use crate::syntax::{Language, SyntaxHighlighter, TokenStyle};
 use std::io;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Most cursors [`Editor::select_all_occurrences`] places.
+pub const MAX_OCCURRENCE_CURSORS: usize = 10_000;
+
+/// One screen row of a buffer line. With word wrap on a line is split into
+/// several rows; otherwise each line is a single row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VisualLine {
+    /// Buffer line the row belongs to.
+    pub line: usize,
+    /// First column of the line on this row.
+    pub start_col: usize,
+    /// Column after the last one on this row.
+    pub end_col: usize,
+    /// Whether the line continues on the next row.
+    pub continues: bool,
+}
+
+impl VisualLine {
+    /// Returns whether this is the first row of its line.
+    pub fn is_first(&self) -> bool {
+        self.start_col == 0
+    }
+
+    /// Returns whether a cursor at `col` of the line is drawn on this row.
+    /// A column at a wrap point belongs to the row after it.
+    pub fn contains(&self, col: usize) -> bool {
+        col >= self.start_col && (col < self.end_col || !self.continues)
+    }
+
+    /// Clips the column range `start..end` of the line to this row, returning
+    /// it relative to the row's first column. The range may extend past the
+    /// end of the line, e.g. for a selected line break; that part is on the
+    /// last row. Returns `None` if the range isn't on this row.
+    pub fn clip(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        let row_end = if self.continues { self.end_col } else { usize::MAX };
+        if end <= self.start_col || start >= row_end {
+            return None;
+        }
+        Some((start.max(self.start_col) - self.start_col, end.min(row_end) - self.start_col))
+    }
+}
+
+/// What [`Editor::clean_up_for_save`] changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SaveCleanup {
+    /// Lines trailing whitespace was removed from.
+    pub trimmed_lines: usize,
+    /// Whether a final line break was added.
+    pub added_final_newline: bool,
+}
+
+impl SaveCleanup {
+    /// Returns true if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.trimmed_lines == 0 && !self.added_final_newline
+    }
+}
+
+/// Another pane's view of a buffer, as character positions so that it
+/// follows edits made in the editor.
+#[derive(Debug, Clone)]
+struct TrackedView {
+    cursor: usize,
+    /// Selection anchor, if there is a selection.
+    anchor: Option<usize>,
+    /// Start of the first visible line.
+    scroll: usize,
+    horizontal_scroll: usize,
+    /// Starts of the folded regions' first lines.
+    folds: Vec<usize>,
+}
 
 /// The main editor state.
 ///
@@ -27,6 +111,15 @@ pub struct Editor {
     file_path: Option<PathBuf>,
     /// Whether the buffer has unsaved changes.
     modified: bool,
+    /// Incremented on every change to the text; tells whether a save
+    /// snapshot still matches the buffer.
+    content_version: u64,
+    /// Content version and hash of the text, once computed for it.
+    content_hash: Option<(u64, u64)>,
+    /// Hash of the text last sent to the language server.
+    sent_hash: Option<u64>,
+    /// Hash and language of the text the syntax tree was parsed from.
+    parsed: Option<(u64, Language)>,
     /// Number of visible lines (for page up/down).
     visible_lines: usize,
     /// Number of visible columns.
@@ -43,18 +136,62 @@ pub struct Editor {
     search: Search,
     /// LSP diagnostics for this buffer.
     diagnostics: Vec<Diagnostic>,
+    /// Least severe diagnostic level that is displayed.
+    diagnostic_display_min: DiagnosticSeverity,
+    /// When the text was last edited, saved, sent and given diagnostics.
+    times: BufferTimes,
+    /// Whether the diagnostics are older than edits the server hasn't
+    /// answered; they are drawn dimmed.
+    diagnostics_stale: bool,
     /// Current hover information (if any).
     hover_info: Option<HoverInfo>,
-    /// Current completion items (if any).
+    /// Current completion items (if any), narrowed to the typed prefix.
     completions: Vec<CompletionItem>,
+    /// Completion items as the server sent them.
+    completion_items: Vec<CompletionItem>,
+    /// Signature help for the call being typed (if any).
+    signature_help: Option<SignatureHelp>,
     /// Document version for LSP (increments on each change).
     document_version: i32,
-    /// Whether word wrap is enabled.
-    word_wrap: bool,
-    /// Wrap width in characters (used when word_wrap is true).
-    wrap_width: usize,
+    /// Word wrap, indentation and language override of this buffer.
+    settings: EditorSettings,
     /// Code folding manager.
     fold_manager: FoldManager,
+    /// Positions of closing brackets the editor auto-inserted and that can
+    /// still be typed over.
+    auto_closers: Vec<usize>,
+    /// Tab stops of the snippet being filled in, if any.
+    snippet: Option<SnippetSession>,
+    /// Bookmarked lines, stored as character positions so they follow edits.
+    bookmarks: Vec<usize>,
+    /// Whether the opened file indents some lines with tabs and others
+    /// with spaces.
+    mixed_indentation: bool,
+    /// Reads the rest of a large file being opened.
+    loader: Option<BufferLoader>,
+    /// Text as of the last open or save; changed lines are diffed against
+    /// it. `None` for a buffer that was never saved.
+    saved_text: Option<TextBuffer>,
+    /// Changed runs of lines against `saved_text`, sorted by line.
+    line_changes: Vec<Hunk>,
+    /// Content version `line_changes` was computed for; `None` if stale.
+    line_changes_version: Option<u64>,
+    /// How lines are compared for `line_changes`.
+    diff_options: DiffOptions,
+    /// Invisible characters in the text, kept up to date across edits.
+    invisible_counts: InvisibleCounts,
+    /// Selections `expand_selection` grew from, innermost last.
+    expansion_stack: Vec<Selection>,
+    /// The selection the last expand or shrink left; the stack only applies
+    /// while the selection is still this one.
+    expanded_selection: Option<Selection>,
+    /// The file on disk as last read or written here.
+    disk_stamp: Option<DiskStamp>,
+    /// Whether the file changed on disk under unsaved edits, until the
+    /// user reloads or keeps the edits.
+    disk_conflict: bool,
+    /// The view of another pane showing this buffer, while one does.
+    tracked_view: Option<TrackedView>,
 }
 
 impl Default for Editor {
@@ -73,6 +210,10 @@ impl Editor {
             history: History::default(),
             file_path: None,
             modified: false,
+            content_version: 0,
+            content_hash: None,
+            sent_hash: None,
+            parsed: None,
             visible_lines: 40,
             visible_cols: 80,
             scroll_offset: 0,
@@ -81,63 +222,402 @@ impl Editor {
             highlighter: SyntaxHighlighter::new(),
             search: Search::new(),
             diagnostics: Vec::new(),
+            diagnostic_display_min: DiagnosticSeverity::Hint,
+            times: BufferTimes::default(),
+            diagnostics_stale: false,
             hover_info: None,
             completions: Vec::new(),
+            completion_items: Vec::new(),
+            signature_help: None,
             document_version: 0,
-            word_wrap: false,
-            wrap_width: 80,
+            settings: EditorSettings::default(),
             fold_manager: FoldManager::new(),
+            auto_closers: Vec::new(),
+            snippet: None,
+            bookmarks: Vec::new(),
+            mixed_indentation: false,
+            loader: None,
+            saved_text: None,
+            line_changes: Vec::new(),
+            line_changes_version: Some(0),
+            diff_options: DiffOptions::default(),
+            invisible_counts: InvisibleCounts::default(),
+            expansion_stack: Vec::new(),
+            expanded_selection: None,
+            disk_stamp: None,
+            disk_conflict: false,
+            tracked_view: None,
+        }
+    }
+
+    // ==================== Embedding ====================
+    //
+    // Enough to drive an editor without a window: build one from text,
+    // edit it by position, read it back and search it. None of these
+    // touch the scroll position, the search bar's state or the language
+    // server, so the view-related sections below can be ignored.
+
+    /// Creates an editor holding `text`, unmodified and with nothing to
+    /// undo, its cursor at the start.
+    pub fn from_text(text: &str) -> Self {
+        let mut editor = Self::new();
+        editor.set_buffer(TextBuffer::from_str(text));
+        editor
+    }
+
+    /// Returns the whole text.
+    pub fn text(&self) -> String {
+        self.buffer.to_string()
+    }
+
+    /// Returns the characters `start..end` of the text, clamped to it.
+    pub fn text_range(&self, start: usize, end: usize) -> String {
+        self.buffer.slice(start, end)
+    }
+
+    /// Inserts `text` at character `pos` as one undo step.
+    pub fn insert_at(&mut self, pos: usize, text: &str) {
+        self.replace_chars(pos, pos, text);
+    }
+
+    /// Deletes the characters `start..end` as one undo step.
+    pub fn delete_chars(&mut self, start: usize, end: usize) {
+        self.replace_chars(start, end, "");
+    }
+
+    /// Returns every match of `query`, without moving the cursor or
+    /// changing the search bar's query. Fails with the regex error if
+    /// `regex` is set and `query` doesn't compile.
+    pub fn find_matches(&self, query: &str, case_sensitive: bool, regex: bool) -> Result<Vec<SearchMatch>, String> {
+        let mut search = Search::new();
+        search.set_case_sensitive(case_sensitive, &self.buffer);
+        search.set_use_regex(regex, &self.buffer);
+        search.set_query(query, &self.buffer);
+        match search.error() {
+            Some(error) => Err(error.to_string()),
+            None => Ok(search.matches().to_vec()),
         }
     }
 
-    /// Opens a file in the editor.
+    /// Opens a file in the editor. A large file opens with its first
+    /// screenful while the rest loads, see [`Editor::poll_loading`].
     pub fn open_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
-        let path = path.as_ref();
-        self.buffer = TextBuffer::from_file(path)?;
+        self.open_file_streaming_from(path.as_ref(), STREAMING_THRESHOLD)
+    }
+
+    /// Opens a file like [`Editor::open_file`], streaming it from
+    /// `threshold` bytes on (for testing/benchmarking).
+    pub fn open_file_streaming_from(&mut self, path: &Path, threshold: u64) -> io::Result<()> {
+        let (buffer, loader) = TextBuffer::open_streaming_from(path, threshold)?;
+        self.load(path, buffer, loader);
+        Ok(())
+    }
+
+    /// Reads the current file again in `encoding`, e.g. when detection
+    /// guessed wrong, keeping the cursor's line. Saving writes `encoding`
+    /// from then on. Unsaved changes are lost.
+    pub fn reopen_with_encoding(&mut self, encoding: Encoding) -> io::Result<()> {
+        let path = self.file_path.clone().ok_or_else(|| io::Error::other("No file path set"))?;
+        let buffer = TextBuffer::from_file_as(&path, encoding)?;
+        let (line, scroll_offset) = (self.cursor_position().line, self.scroll_offset);
+        // Still the same document to a language server, just a new version
+        let document_version = self.document_version;
+        self.load(&path, buffer, None);
+        self.document_version = document_version;
+        let line = line.min(self.buffer.len_lines() - 1);
+        self.cursor.set_position(self.buffer.line_start(line), false);
+        self.scroll_offset = scroll_offset.min(line);
+        Ok(())
+    }
+
+    /// Returns the encoding the file was read in and is saved in.
+    pub fn encoding(&self) -> Encoding {
+        self.buffer.encoding()
+    }
+
+    /// Fails with [`io::ErrorKind::InvalidData`], naming them, if the
+    /// encoding can't represent characters of the text, so a save would
+    /// write them as `?`.
+    pub fn check_encodable(&self) -> io::Result<()> {
+        if self.encoding().is_unicode() {
+            return Ok(());
+        }
+        self.encoding().check_mappable(&self.buffer.to_string())
+    }
+
+    /// Replaces the buffer with a file's freshly read text, resetting all
+    /// per-file state.
+    fn load(&mut self, path: &Path, buffer: TextBuffer, loader: Option<BufferLoader>) {
+        self.buffer = buffer;
+        self.loader = loader;
         self.cursor = Cursor::new();
         self.multi_cursors = MultiCursor::new();
         self.history.clear();
         self.file_path = Some(path.to_path_buf());
         self.modified = false;
+        self.content_version += 1;
+        self.invisible_counts = InvisibleCounts::count(self.buffer.chars());
         self.scroll_offset = 0;
         self.smooth_scroll = 0.0;
         self.horizontal_scroll = 0;
         self.diagnostics.clear();
+        self.times = BufferTimes::default();
+        self.diagnostics_stale = false;
         self.hover_info = None;
         self.completions.clear();
+        self.completion_items.clear();
+        self.signature_help = None;
         self.document_version = 0;
+        self.sent_hash = None;
+        self.auto_closers.clear();
+        self.snippet = None;
+        self.bookmarks.clear();
+        self.set_saved_text();
+        self.disk_stamp = DiskStamp::of(path).ok();
+        self.disk_conflict = false;
+
+        // Follow the file's own indentation so edits don't mix styles
+        let detected = IndentConfig::detect_mixed(&self.buffer);
+        self.mixed_indentation = detected.is_some_and(|(_, mixed)| mixed);
+        if let Some((detected, _)) = detected {
+            self.settings.indent = match detected.style {
+                // Tabs don't imply a width; keep the configured display width
+                IndentStyle::Tabs => IndentConfig::tabs(self.settings.indent.width),
+                IndentStyle::Spaces => detected,
+            };
+        }
 
-        // Set up syntax highlighting based on file extension
-        let language = Language::from_path(path);
+        // Set up syntax highlighting based on file extension, unless the
+        // buffer's language is overridden
+        let language = self.settings.language.unwrap_or_else(|| Language::from_path(path));
         self.highlighter.set_language(language);
         self.reparse_syntax();
-
-        Ok(())
     }
 
     /// Saves the buffer to the current file path.
     pub fn save(&mut self) -> io::Result<()> {
+        self.check_fully_loaded()?;
+        if self.file_path.is_some() {
+            self.clean_up_for_save();
+        }
         if let Some(path) = &self.file_path {
             self.buffer.save_to_file(path)?;
             self.modified = false;
+            self.auto_closers.clear();
+            self.set_saved_text();
+            self.times.last_saved = Some(Instant::now());
+            self.note_written();
             Ok(())
         } else {
+            Err(io::Error::other("No file path set"))
+        }
+    }
+
+    /// Returns true unless the rest of a large file is still loading or
+    /// failed to load.
+    pub fn is_fully_loaded(&self) -> bool {
+        self.buffer.is_fully_loaded()
+    }
+
+    /// Returns true while the rest of a large file is loading.
+    pub fn is_loading(&self) -> bool {
+        self.buffer.is_loading()
+    }
+
+    /// Returns how much of a large file being opened has loaded, from 0 to
+    /// 1, or `None` if none is loading.
+    pub fn loading_progress(&self) -> Option<f32> {
+        self.loader.as_ref().map(BufferLoader::progress)
+    }
+
+    /// Appends the text of a large file read since the last call. Returns
+    /// whether any was appended, or the error loading stopped at; the
+    /// buffer then keeps the start of the file but can't be saved over it.
+    /// The text counts as the saved text, not as an edit.
+    ///
+    /// If the rest of the file turns out not to be UTF-8, the whole file
+    /// replaces the text, decoded in its detected encoding. Edits made
+    /// while it loaded are dropped with the misread text.
+    pub fn poll_loading(&mut self) -> io::Result<bool> {
+        let Some(loader) = &mut self.loader else {
+            return Ok(false);
+        };
+        let start = self.buffer.len_chars();
+        let result = loader.poll(&mut self.buffer);
+        let done = loader.is_done();
+        if done {
+            self.loader = None;
+        }
+        let appended = match result {
+            Ok(LoadStep::Replaced) => {
+                let line = self.cursor_position().line.min(self.buffer.len_lines() - 1);
+                self.cursor = Cursor::new();
+                self.cursor.set_position(self.buffer.line_start(line), false);
+                self.multi_cursors = MultiCursor::new();
+                self.history.clear();
+                self.modified = false;
+                self.invisible_counts = InvisibleCounts::count(self.buffer.chars());
+                self.content_version += 1;
+                self.set_saved_text();
+                true
+            }
+            _ if self.buffer.len_chars() > start => {
+                self.invisible_counts.add(self.buffer.chars_from(start));
+                self.content_version += 1;
+                self.set_saved_text();
+                true
+            }
+            _ => false,
+        };
+        if done {
+            // Highlighting covered only the first screenful
+            self.reparse_syntax();
+        }
+        result.map(|_| appended)
+    }
+
+    /// Returns a `WouldBlock` error while a large file is still loading,
+    /// for saves, so its first part isn't written over the whole. Once
+    /// loading failed partway it is an `InvalidData` one for good.
+    pub fn check_fully_loaded(&self) -> io::Result<()> {
+        if self.buffer.is_partial() {
             Err(io::Error::new(
-                io::ErrorKind::Other,
-                "No file path set",
+                io::ErrorKind::InvalidData,
+                "Only part of the file loaded; save it elsewhere or reopen it",
             ))
+        } else if self.is_loading() {
+            Err(io::Error::new(io::ErrorKind::WouldBlock, "File is still loading"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Applies the save clean-up the settings ask for: strips trailing
+    /// spaces and tabs from every line and adds a missing final line break.
+    /// The changes are one undo step; a cursor in removed whitespace moves
+    /// to the new end of its line. Called by [`Editor::save`] and
+    /// [`Editor::save_as`]; background saves call it before taking the
+    /// [snapshot](Editor::save_snapshot).
+    pub fn clean_up_for_save(&mut self) -> SaveCleanup {
+        let EditorSettings { trim_trailing_whitespace, ensure_final_newline, .. } = self.settings;
+        let mut trimmed = Vec::new();
+        if trim_trailing_whitespace {
+            for line in 0..self.buffer.len_lines() {
+                let Some(text) = self.buffer.line(line) else {
+                    continue;
+                };
+                // The \r of a line break kept in a mixed file isn't trimmed
+                let body = text.strip_suffix('\r').unwrap_or(&text);
+                let kept = body.trim_end_matches([' ', '\t']);
+                if kept.len() < body.len() {
+                    let start = self.buffer.line_start(line) + kept.chars().count();
+                    trimmed.push((start, start + body[kept.len()..].chars().count()));
+                }
+            }
+        }
+        let len = self.buffer.len_chars();
+        let add_newline = ensure_final_newline && len > 0 && self.buffer.char_at(len - 1) != Some('\n');
+        let cleanup = SaveCleanup {
+            trimmed_lines: trimmed.len(),
+            added_final_newline: add_newline,
+        };
+        if cleanup.is_empty() {
+            return cleanup;
+        }
+
+        self.begin_edit();
+        if add_newline {
+            self.buffer.insert(len, "\n");
+            self.record_edit(EditOperation::Insert {
+                position: len,
+                text: "\n".to_string(),
+            });
+        }
+        // Remove from the end so the positions before stay valid
+        for &(start, end) in trimmed.iter().rev() {
+            let text: String = (start..end).filter_map(|i| self.buffer.char_at(i)).collect();
+            self.buffer.remove(start, end);
+            self.record_edit(EditOperation::Delete { position: start, text });
+        }
+        let map = |pos: usize| {
+            let removed: usize = trimmed
+                .iter()
+                .take_while(|&&(start, _)| start < pos)
+                .map(|&(start, end)| end.min(pos) - start)
+                .sum();
+            pos - removed
+        };
+        let selection = &mut self.cursor.selection;
+        (selection.anchor, selection.cursor) = (map(selection.anchor), map(selection.cursor));
+        for cursor in self.multi_cursors.iter_mut() {
+            let selection = &mut cursor.selection;
+            (selection.anchor, selection.cursor) = (map(selection.anchor), map(selection.cursor));
+        }
+        self.finish_edit();
+        cleanup
+    }
+
+    /// Returns a copy of the text and its version for saving in the
+    /// background. Cloning the rope is cheap; the copy shares its chunks.
+    pub fn save_snapshot(&self) -> (TextBuffer, u64) {
+        (self.buffer.clone(), self.content_version)
+    }
+
+    /// Records that the snapshot taken at `version` was written.
+    ///
+    /// Clears the modified flag only if the text hasn't changed since the
+    /// snapshot; returns false if newer edits remain unsaved.
+    pub fn mark_saved(&mut self, version: u64) -> bool {
+        // The file is ours either way
+        self.note_written();
+        if version != self.content_version {
+            return false;
+        }
+        self.modified = false;
+        self.auto_closers.clear();
+        self.set_saved_text();
+        self.times.last_saved = Some(Instant::now());
+        true
+    }
+
+    /// Returns the content version, which changes whenever the text does.
+    pub fn content_version(&self) -> u64 {
+        self.content_version
+    }
+
+    /// Returns a hash of the text. It is computed at most once per content
+    /// version, and unlike the version it comes back to its old value when
+    /// an edit is reverted.
+    pub fn content_hash(&mut self) -> u64 {
+        match self.content_hash {
+            Some((version, hash)) if version == self.content_version => hash,
+            _ => {
+                let hash = self.buffer.content_hash();
+                self.content_hash = Some((self.content_version, hash));
+                hash
+            }
         }
     }
 
     /// Saves the buffer to a new file path.
     pub fn save_as<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
         let path = path.as_ref();
+        // What loaded of a partly loaded file may go to another file
+        if self.is_loading() || (self.buffer.is_partial() && self.file_path.as_deref() == Some(path)) {
+            self.check_fully_loaded()?;
+        }
+        self.clean_up_for_save();
         self.buffer.save_to_file(path)?;
+        self.buffer.clear_partial();
         self.file_path = Some(path.to_path_buf());
         self.modified = false;
+        self.auto_closers.clear();
+        self.set_saved_text();
+        self.times.last_saved = Some(Instant::now());
+        self.note_written();
 
         // Update syntax highlighting based on new file extension
-        let language = Language::from_path(path);
+        let language = self.settings.language.unwrap_or_else(|| Language::from_path(path));
         self.highlighter.set_language(language);
         self.reparse_syntax();
 
@@ -154,6 +634,78 @@ impl Editor {
         self.modified
     }
 
+    // ==================== External Changes ====================
+
+    /// Returns true if the file changed on disk since it was last read or
+    /// written here. A file that can't be read, e.g. one that was deleted,
+    /// doesn't count.
+    pub fn disk_changed(&self) -> bool {
+        let Some(path) = &self.file_path else {
+            return false;
+        };
+        DiskStamp::of(path).is_ok_and(|stamp| self.disk_stamp != Some(stamp))
+    }
+
+    /// Returns true while the file has changed on disk under unsaved edits
+    /// and the user hasn't chosen between the two.
+    pub fn has_disk_conflict(&self) -> bool {
+        self.disk_conflict
+    }
+
+    /// Flags that the file changed on disk under unsaved edits.
+    pub fn raise_disk_conflict(&mut self) {
+        self.disk_conflict = true;
+    }
+
+    /// Keeps the unsaved edits over the file's new text. The conflict is
+    /// dismissed until the file changes again.
+    pub fn keep_local_changes(&mut self) {
+        self.note_written();
+    }
+
+    /// Replaces the text with the file's current text, keeping the cursor's
+    /// line. The replacement is one undo step, so undoing it brings back
+    /// the discarded edits; the buffer counts as saved afterwards.
+    pub fn reload_from_disk(&mut self) -> io::Result<()> {
+        let path = self.file_path.clone().ok_or_else(|| io::Error::other("No file path set"))?;
+        self.check_fully_loaded()?;
+        let stamp = DiskStamp::of(&path)?;
+        let fresh = TextBuffer::from_file(&path)?;
+        let text = fresh.to_string();
+        let old_text = self.buffer.to_string();
+        if text != old_text {
+            let line = self.cursor_position().line;
+            self.begin_edit();
+            if !old_text.is_empty() {
+                self.buffer.remove(0, self.buffer.len_chars());
+                self.record_edit(EditOperation::Delete { position: 0, text: old_text });
+            }
+            if !text.is_empty() {
+                self.buffer.insert(0, &text);
+                self.record_edit(EditOperation::Insert { position: 0, text });
+            }
+            self.multi_cursors.collapse_to_primary();
+            let line = line.min(self.buffer.len_lines() - 1);
+            self.cursor.set_position(self.buffer.line_start(line), false);
+            self.finish_edit();
+            self.document_version += 1;
+        }
+        self.buffer.set_format_from(&fresh);
+        self.modified = false;
+        self.auto_closers.clear();
+        self.set_saved_text();
+        self.disk_stamp = Some(stamp);
+        self.disk_conflict = false;
+        Ok(())
+    }
+
+    /// Takes the file on disk as this buffer's own, after writing it or
+    /// choosing to keep the buffer's text over it.
+    fn note_written(&mut self) {
+        self.disk_stamp = self.file_path.as_deref().and_then(|path| DiskStamp::of(path).ok());
+        self.disk_conflict = false;
+    }
+
     /// Returns a reference to the buffer.
     pub fn buffer(&self) -> &TextBuffer {
         &self.buffer
@@ -165,9 +717,15 @@ impl Editor {
         self.cursor.clamp_to_buffer(&self.buffer);
         self.multi_cursors.clamp_to_buffer(&self.buffer);
         self.history.clear();
-        self.search.clear();
+        self.clear_search();
         self.highlighter.invalidate_cache();
         self.modified = false;
+        self.content_version += 1;
+        self.invisible_counts = InvisibleCounts::count(self.buffer.chars());
+        self.auto_closers.clear();
+        self.snippet = None;
+        self.bookmarks.clear();
+        self.set_saved_text();
     }
 
     /// Returns the cursor position as (line, column).
@@ -229,7 +787,29 @@ impl Editor {
     /// Scrolls to ensure the cursor is visible.
     pub fn scroll_to_cursor(&mut self) {
         let (line, col) = self.buffer.char_to_line_col(self.cursor.position());
-        
+
+        // With word wrap, lines can take several rows. The scroll offset is
+        // still a buffer line, so move it down to the first line from which
+        // the rows up to the cursor's fit on screen.
+        if self.settings.word_wrap {
+            if line < self.scroll_offset {
+                self.scroll_offset = line;
+                return;
+            }
+            let mut rows = self.wrap_row_of(line, col) + 1;
+            let mut top = line;
+            while top > self.scroll_offset {
+                let above = self.get_wrapped_line_segments(top - 1).len();
+                if rows + above > self.visible_lines {
+                    self.scroll_offset = top;
+                    break;
+                }
+                rows += above;
+                top -= 1;
+            }
+            return;
+        }
+
         // Vertical scrolling
         if line < self.scroll_offset {
             self.scroll_offset = line;
@@ -238,6 +818,11 @@ impl Editor {
         }
 
         // Horizontal scrolling with some margin (keep 4 chars visible on each side)
+        let col = if self.invisible_counts.total == 0 {
+            col
+        } else {
+            self.buffer.display_col(line, col)
+        };
         let margin = 4;
         if col < self.horizontal_scroll + margin {
             self.horizontal_scroll = col.saturating_sub(margin);
@@ -283,6 +868,36 @@ impl Editor {
     pub fn set_cursor_position(&mut self, line: usize, col: usize, extend_selection: bool) {
         let char_pos = self.buffer.line_col_to_char(line, col);
         self.cursor.set_position(char_pos, extend_selection);
+        if !extend_selection {
+            self.clear_search_in_selection();
+        }
+        self.scroll_to_cursor();
+    }
+
+    // ==================== Settings ====================
+
+    /// Returns the buffer's own settings. Their language is `None` unless
+    /// overridden; [`Editor::language`] is the one in effect.
+    pub fn settings(&self) -> EditorSettings {
+        self.settings
+    }
+
+    /// Replaces the buffer's settings, e.g. with a workspace's defaults.
+    /// Without a language override the language follows the file name.
+    pub fn set_settings(&mut self, settings: EditorSettings) {
+        self.settings = EditorSettings {
+            wrap_width: settings.wrap_width.max(10),
+            ..settings
+        };
+        if self.settings.word_wrap {
+            self.horizontal_scroll = 0;
+        }
+        let language = settings
+            .language
+            .or_else(|| self.file_path.as_deref().map(Language::from_path));
+        if let Some(language) = language.filter(|&language| language != self.language()) {
+            self.set_language(language);
+        }
         self.scroll_to_cursor();
     }
 
@@ -290,45 +905,50 @@ impl Editor {
 
     /// Returns whether word wrap is enabled.
     pub fn word_wrap(&self) -> bool {
-        self.word_wrap
+        self.settings.word_wrap
     }
 
-    /// Enables or disables word wrap.
+    /// Enables or disables word wrap. Wrapped text needs no horizontal
+    /// scrolling, so the view scrolls back to the first column.
     pub fn set_word_wrap(&mut self, enabled: bool) {
-        self.word_wrap = enabled;
+        self.settings.word_wrap = enabled;
+        if enabled {
+            self.horizontal_scroll = 0;
+        }
+        self.scroll_to_cursor();
     }
 
     /// Toggles word wrap.
     pub fn toggle_word_wrap(&mut self) {
-        self.word_wrap = !self.word_wrap;
+        self.set_word_wrap(!self.settings.word_wrap);
     }
 
     /// Returns the wrap width in characters.
     pub fn wrap_width(&self) -> usize {
-        self.wrap_width
+        self.settings.wrap_width
     }
 
     /// Sets the wrap width in characters.
     pub fn set_wrap_width(&mut self, width: usize) {
-        self.wrap_width = width.max(10);
+        self.settings.wrap_width = width.max(10);
     }
 
     /// Returns wrapped line segments for rendering.
     /// Each segment is (start_col, end_col) within the line.
     /// If word wrap is disabled, returns a single segment covering the whole line.
     pub fn get_wrapped_line_segments(&self, line: usize) -> Vec<(usize, usize)> {
-        if !self.word_wrap {
+        if !self.settings.word_wrap {
             // No wrapping - return entire line as one segment
             let line_len = self.buffer.line_len_chars(line);
             return vec![(0, line_len)];
         }
 
-        let line_text = match self.buffer.line(line) {
-            Some(text) => text,
+        let line_text: Vec<char> = match self.buffer.line(line) {
+            Some(text) => text.chars().collect(),
             None => return vec![],
         };
 
-        let line_len = line_text.chars().count();
+        let line_len = line_text.len();
         if line_len == 0 {
             return vec![(0, 0)];
         }
@@ -337,17 +957,14 @@ impl Editor {
         let mut start = 0;
 
         while start < line_len {
-            let end = (start + self.wrap_width).min(line_len);
+            let end = (start + self.settings.wrap_width).min(line_len);
 
             // Try to find a word boundary if we're not at the end
             let actual_end = if end < line_len {
                 // Look for last space or punctuation within the wrap width
-                let search_start = start;
-                let text_slice: String = line_text.chars().skip(search_start).take(end - search_start).collect();
-
                 // Find last word boundary (space, tab)
-                if let Some(last_space) = text_slice.rfind(|c: char| c == ' ' || c == '\t') {
-                    let boundary = search_start + last_space + 1;
+                if let Some(last_space) = line_text[start..end].iter().rposition(|&ch| ch == ' ' || ch == '\t') {
+                    let boundary = start + last_space + 1;
                     if boundary > start {
                         boundary
                     } else {
@@ -371,9 +988,54 @@ impl Editor {
         segments
     }
 
+    /// Returns up to `max_rows` screen rows, starting with the first row of
+    /// `first_line`.
+    pub fn visual_lines(&self, first_line: usize, max_rows: usize) -> Vec<VisualLine> {
+        let mut rows = Vec::with_capacity(max_rows);
+        self.visual_lines_into(first_line, max_rows, &mut rows);
+        rows
+    }
+
+    /// Fills `rows` with the rows [`visual_lines`](Self::visual_lines)
+    /// returns, reusing its memory from frame to frame.
+    pub fn visual_lines_into(&self, first_line: usize, max_rows: usize, rows: &mut Vec<VisualLine>) {
+        rows.clear();
+        let mut line = first_line;
+        while rows.len() < max_rows && line < self.buffer.len_lines() {
+            if !self.settings.word_wrap {
+                // Without wrapping every line is one row
+                let end_col = self.buffer.line_len_chars(line);
+                rows.push(VisualLine { line, start_col: 0, end_col, continues: false });
+                line += 1;
+                continue;
+            }
+            let segments = self.get_wrapped_line_segments(line);
+            let count = segments.len();
+            for (i, (start_col, end_col)) in segments.into_iter().enumerate().take(max_rows - rows.len()) {
+                rows.push(VisualLine {
+                    line,
+                    start_col,
+                    end_col,
+                    continues: i + 1 < count,
+                });
+            }
+            line += 1;
+        }
+    }
+
+    /// Returns the index of the row of `line` that column `col` is drawn on.
+    fn wrap_row_of(&self, line: usize, col: usize) -> usize {
+        let segments = self.get_wrapped_line_segments(line);
+        let last = segments.len().saturating_sub(1);
+        segments
+            .iter()
+            .position(|&(start, end)| col >= start && col < end)
+            .unwrap_or(last)
+    }
+
     /// Returns the total number of visual lines (accounting for word wrap).
     pub fn visual_line_count(&self) -> usize {
-        if !self.word_wrap {
+        if !self.settings.word_wrap {
             return self.buffer.len_lines();
         }
 
@@ -446,110 +1108,445 @@ impl Editor {
         self.fold_manager.is_line_folded(line)
     }
 
-    // ==================== Text Editing ====================
+    // ==================== Bookmarks ====================
 
-    /// Inserts a character at the cursor position.
-    pub fn insert_char(&mut self, ch: char) {
-        self.begin_edit();
-        
-        // Delete selection first if any
-        self.delete_selection_internal();
-        
-        let pos = self.cursor.position();
-        self.buffer.insert_char(pos, ch);
-        self.history.record(EditOperation::Insert {
-            position: pos,
-            text: ch.to_string(),
-        });
-        
-        self.cursor.set_position(pos + 1, false);
-        self.finish_edit();
-        self.scroll_to_cursor();
+    /// Toggles a bookmark on the given line. Returns true if the line is now bookmarked.
+    pub fn toggle_bookmark(&mut self, line: usize) -> bool {
+        if line >= self.buffer.len_lines() {
+            return false;
+        }
+        let before = self.bookmarks.len();
+        self.bookmarks
+            .retain(|&pos| self.buffer.char_to_line_col(pos).0 != line);
+        if self.bookmarks.len() != before {
+            return false;
+        }
+        self.bookmarks.push(self.buffer.line_start(line));
+        true
     }
 
-    /// Inserts a string at the cursor position.
-    pub fn insert_text(&mut self, text: &str) {
-        if text.is_empty() {
-            return;
+    /// Returns whether the given line has a bookmark.
+    pub fn has_bookmark(&self, line: usize) -> bool {
+        self.bookmarks
+            .iter()
+            .any(|&pos| self.buffer.char_to_line_col(pos).0 == line)
+    }
+
+    /// Returns the bookmarked lines in ascending order.
+    pub fn bookmark_lines(&self) -> Vec<usize> {
+        let mut lines: Vec<usize> = self
+            .bookmarks
+            .iter()
+            .map(|&pos| self.buffer.char_to_line_col(pos).0)
+            .collect();
+        lines.sort_unstable();
+        lines.dedup();
+        lines
+    }
+
+    // ==================== View State ====================
+
+    /// Returns the cursor, selection, scroll position, folds and bookmarks.
+    pub fn view_state(&self) -> ViewState {
+        let position = |pos: usize| self.buffer.char_to_line_col(pos);
+        ViewState {
+            cursor: position(self.cursor.position()),
+            selection: self.cursor.has_selection().then(|| position(self.cursor.selection.anchor)),
+            scroll_offset: self.scroll_offset,
+            horizontal_scroll: self.horizontal_scroll,
+            folds: self.fold_manager.regions().iter().filter(|r| r.is_folded).map(|r| r.start_line).collect(),
+            bookmarks: self.bookmark_lines(),
         }
-        
-        self.begin_edit();
-        
-        // Delete selection first if any
-        self.delete_selection_internal();
-        
-        let pos = self.cursor.position();
-        self.buffer.insert(pos, text);
-        self.history.record(EditOperation::Insert {
-            position: pos,
-            text: text.to_string(),
+    }
+
+    /// Restores a view state exported by [`Editor::view_state`]. Positions
+    /// past the end of the text are clamped to it, and folds that no longer
+    /// start a foldable region are dropped. Leaves a single cursor.
+    pub fn apply_view_state(&mut self, state: &ViewState) {
+        let last_line = self.buffer.len_lines().saturating_sub(1);
+        let to_char = |(line, col): (usize, usize)| self.buffer.line_col_to_char(line.min(last_line), col);
+        let cursor = to_char(state.cursor);
+        let anchor = state.selection.map(to_char);
+
+        self.collapse_cursors();
+        self.cursor.set_position(anchor.unwrap_or(cursor), false);
+        self.cursor.set_position(cursor, anchor.is_some());
+        self.clear_search_in_selection();
+
+        if self.fold_manager.regions().is_empty() {
+            self.detect_folds();
+        }
+        self.fold_manager.unfold_all();
+        for &line in &state.folds {
+            if let Some(region) = self.fold_manager.region_at_line_mut(line) {
+                region.is_folded = true;
+            }
+        }
+
+        self.bookmarks = state
+            .bookmarks
+            .iter()
+            .filter(|&&line| line <= last_line)
+            .map(|&line| self.buffer.line_start(line))
+            .collect();
+        self.bookmarks.dedup();
+
+        self.set_scroll_offset(state.scroll_offset);
+        self.snap_scroll();
+        self.horizontal_scroll = state.horizontal_scroll;
+    }
+
+    /// Starts keeping `state`, the view of another pane showing this
+    /// buffer, in step with the edits made here, so that its cursor,
+    /// selection, scroll position and folds stay on the same text.
+    pub fn track_view(&mut self, state: &ViewState) {
+        let last_line = self.buffer.len_lines().saturating_sub(1);
+        let to_char = |(line, col): (usize, usize)| self.buffer.line_col_to_char(line.min(last_line), col);
+        let line_start = |line: usize| self.buffer.line_start(line.min(last_line));
+        self.tracked_view = Some(TrackedView {
+            cursor: to_char(state.cursor),
+            anchor: state.selection.map(to_char),
+            scroll: line_start(state.scroll_offset),
+            horizontal_scroll: state.horizontal_scroll,
+            folds: state.folds.iter().map(|&line| line_start(line)).collect(),
         });
-        
-        self.cursor.set_position(pos + text.chars().count(), false);
-        self.finish_edit();
-        self.scroll_to_cursor();
     }
 
-    /// Inserts a newline at the cursor position with auto-indentation.
-    pub fn insert_newline(&mut self) {
-        self.begin_edit();
+    /// Returns the view passed to [`Editor::track_view`], as the edits
+    /// since have moved it, with this buffer's bookmarks.
+    pub fn tracked_view(&self) -> Option<ViewState> {
+        let view = self.tracked_view.as_ref()?;
+        let position = |pos: usize| self.buffer.char_to_line_col(pos);
+        Some(ViewState {
+            cursor: position(view.cursor),
+            selection: view.anchor.map(position),
+            scroll_offset: position(view.scroll).0,
+            horizontal_scroll: view.horizontal_scroll,
+            folds: view.folds.iter().map(|&pos| position(pos).0).collect(),
+            bookmarks: self.bookmark_lines(),
+        })
+    }
 
-        // Delete selection first if any
-        self.delete_selection_internal();
+    /// Stops tracking the other pane's view, returning it as
+    /// [`Editor::tracked_view`] does.
+    pub fn untrack_view(&mut self) -> Option<ViewState> {
+        let view = self.tracked_view();
+        self.tracked_view = None;
+        view
+    }
 
-        let pos = self.cursor.position();
-        let (line, _col) = self.buffer.char_to_line_col(pos);
+    // ==================== Invisible Characters ====================
 
-        // Get the indentation of the current line
-        let indent = self.get_line_indentation(line);
+    /// Returns the display columns of a line, where invisible characters
+    /// are drawn as boxes wider than one column.
+    pub fn line_layout(&self, line: usize) -> LineLayout {
+        // Without boxed characters anywhere, skip reading the line, which
+        // may be very long
+        if self.invisible_counts.total == 0 {
+            return LineLayout::default();
+        }
+        self.buffer.line(line).map(|text| LineLayout::new(&text)).unwrap_or_default()
+    }
+
+    /// Returns the invisible characters in the text.
+    pub fn invisible_counts(&self) -> InvisibleCounts {
+        self.invisible_counts
+    }
 
-        // Check if we should add extra indentation (after { or :)
-        let extra_indent = self.should_increase_indent(line, pos);
+    /// Removes every bidi control character, as one undo step. Returns the
+    /// number removed.
+    pub fn strip_bidi_controls(&mut self) -> usize {
+        self.replace_bidi_controls(|_| String::new())
+    }
 
-        // Insert newline
-        self.buffer.insert_char(pos, '\n');
-        self.history.record(EditOperation::Insert {
-            position: pos,
-            text: "\n".to_string(),
-        });
+    /// Replaces every bidi control character with its `\u{XXXX}` escape,
+    /// as one undo step. Returns the number replaced.
+    pub fn escape_bidi_controls(&mut self) -> usize {
+        self.replace_bidi_controls(invisible::escape)
+    }
 
-        // Build indentation string
-        let mut indent_str = indent.clone();
-        if extra_indent {
-            // Add one level of indentation (use same style as current line or default to 4 spaces)
-            if indent.contains('\t') {
-                indent_str.push('\t');
-            } else {
-                indent_str.push_str("    ");
-            }
+    fn replace_bidi_controls(&mut self, replacement: fn(char) -> String) -> usize {
+        let found: Vec<(usize, char)> =
+            self.buffer.chars().enumerate().filter(|&(_, ch)| invisible::is_bidi_control(ch)).collect();
+        if found.is_empty() {
+            return 0;
         }
 
-        // Insert indentation
-        if !indent_str.is_empty() {
-            self.buffer.insert(pos + 1, &indent_str);
-            self.history.record(EditOperation::Insert {
-                position: pos + 1,
-                text: indent_str.clone(),
+        self.begin_edit();
+        let cursor = self.cursor.position();
+        let mut new_cursor = cursor;
+        // Replace from the end so the positions before stay valid
+        for &(pos, ch) in found.iter().rev() {
+            let text = replacement(ch);
+            self.buffer.remove(pos, pos + 1);
+            self.record_edit(EditOperation::Delete {
+                position: pos,
+                text: ch.to_string(),
             });
+            if !text.is_empty() {
+                self.buffer.insert(pos, &text);
+                self.record_edit(EditOperation::Insert {
+                    position: pos,
+                    text: text.clone(),
+                });
+            }
+            if pos < cursor {
+                new_cursor = new_cursor + text.chars().count() - 1;
+            }
         }
-
-        self.cursor.set_position(pos + 1 + indent_str.len(), false);
+        self.multi_cursors.collapse_to_primary();
+        self.cursor.set_position(new_cursor, false);
         self.finish_edit();
         self.scroll_to_cursor();
+        found.len()
     }
 
-    /// Gets the indentation (leading whitespace) of a line.
-    fn get_line_indentation(&self, line: usize) -> String {
-        if let Some(line_text) = self.buffer.line(line) {
-            let mut indent = String::new();
-            for ch in line_text.chars() {
-                if ch == ' ' || ch == '\t' {
-                    indent.push(ch);
-                } else {
-                    break;
+    /// Returns the line ending the buffer is saved with.
+    pub fn line_ending(&self) -> LineEnding {
+        self.buffer.line_ending()
+    }
+
+    /// Returns true if the file mixes line endings, kept until converted.
+    pub fn has_mixed_line_endings(&self) -> bool {
+        self.buffer.has_mixed_line_endings()
+    }
+
+    /// Saves the file with `line_ending` from now on. A file mixing line
+    /// endings has the `\r` of its `\r\n` breaks removed, as one undo step;
+    /// undoing it doesn't bring the mixed endings back on save.
+    pub fn convert_line_endings(&mut self, line_ending: LineEnding) {
+        if self.buffer.has_mixed_line_endings() {
+            let mut found = Vec::new();
+            let mut previous = None;
+            for (pos, ch) in self.buffer.chars().enumerate() {
+                if ch == '\n' && previous == Some('\r') {
+                    found.push(pos - 1);
                 }
+                previous = Some(ch);
             }
-            indent
+            if !found.is_empty() {
+                self.begin_edit();
+                let cursor = self.cursor.position();
+                // Remove from the end so the positions before stay valid
+                for &pos in found.iter().rev() {
+                    self.buffer.remove(pos, pos + 1);
+                    self.record_edit(EditOperation::Delete {
+                        position: pos,
+                        text: "\r".to_string(),
+                    });
+                }
+                let before_cursor = found.iter().filter(|&&pos| pos < cursor).count();
+                self.multi_cursors.collapse_to_primary();
+                self.cursor.set_position(cursor - before_cursor, false);
+                self.finish_edit();
+            }
+        } else if line_ending == self.buffer.line_ending() {
+            return;
+        }
+        self.buffer.set_line_ending(line_ending);
+        // The file's bytes change even when the text doesn't
+        self.modified = true;
+    }
+
+    // ==================== Change Tracking ====================
+
+    /// Takes the current text as the saved version and clears the change
+    /// markers.
+    fn set_saved_text(&mut self) {
+        self.saved_text = Some(self.buffer.clone());
+        self.line_changes.clear();
+        self.line_changes_version = Some(self.content_version);
+    }
+
+    /// Returns whether the change markers are out of date with the text.
+    pub fn line_changes_stale(&self) -> bool {
+        self.line_changes_version != Some(self.content_version)
+    }
+
+    /// Recomputes the changed lines against the saved text if the text
+    /// changed since the last call. Diffs the whole buffer, so callers
+    /// should debounce it rather than call it on every keystroke.
+    /// Returns true if the markers were recomputed.
+    pub fn update_line_changes(&mut self) -> bool {
+        if !self.line_changes_stale() {
+            return false;
+        }
+        self.line_changes = match &self.saved_text {
+            Some(saved) => diff::diff_text(&saved.to_string(), &self.buffer.to_string(), self.diff_options),
+            None => Vec::new(),
+        };
+        self.line_changes_version = Some(self.content_version);
+        true
+    }
+
+    /// Returns the changed runs of lines, as of the last
+    /// [`update_line_changes`](Self::update_line_changes).
+    pub fn line_changes(&self) -> &[Hunk] {
+        &self.line_changes
+    }
+
+    /// Returns the change hunk marked on `line`. Lines removed are marked
+    /// on the line above them (the first line if they were at the top).
+    fn line_change_hunk(&self, line: usize) -> Option<&Hunk> {
+        // Hunks are sorted and don't overlap; only the last one starting at
+        // or before the line below can cover it
+        let after = self.line_changes.partition_point(|h| h.new_start <= line + 1);
+        self.line_changes[..after].iter().rev().take(2).find(|h| match h.kind() {
+            ChangeKind::Deleted => h.new_start.saturating_sub(1) == line,
+            _ => h.new_lines().contains(&line),
+        })
+    }
+
+    /// Returns how `line` differs from the saved text, if it does.
+    pub fn line_change_status(&self, line: usize) -> Option<ChangeKind> {
+        self.line_change_hunk(line).map(Hunk::kind)
+    }
+
+    /// Returns whether the change on `line` is only in whitespace.
+    pub fn is_whitespace_only_change(&self, line: usize) -> bool {
+        self.line_change_hunk(line).is_some_and(|h| h.whitespace_only)
+    }
+
+    /// Returns whether changes that are only in whitespace are ignored.
+    pub fn diff_ignores_whitespace(&self) -> bool {
+        self.diff_options.ignore_whitespace
+    }
+
+    /// Sets whether changes that are only in whitespace are ignored.
+    pub fn set_diff_ignore_whitespace(&mut self, ignore: bool) {
+        if self.diff_options.ignore_whitespace != ignore {
+            self.diff_options.ignore_whitespace = ignore;
+            self.line_changes_version = None;
+        }
+    }
+
+    // ==================== Text Editing ====================
+
+    /// Inserts a character at the cursor position.
+    /// A tab is expanded to spaces up to the next indent stop when indenting
+    /// with spaces.
+    pub fn insert_char(&mut self, ch: char) {
+        if self.has_multiple_cursors() {
+            self.edit_each_cursor(|editor, selection| {
+                let (start, end) = selection.range();
+                Some((start, end, editor.typed_text(ch, start)))
+            });
+            return;
+        }
+
+        self.begin_edit();
+        
+        // Delete selection first if any
+        self.delete_selection_internal();
+        
+        let pos = self.cursor.position();
+        let text = self.typed_text(ch, pos);
+        self.buffer.insert(pos, &text);
+        let len = text.chars().count();
+        self.record_edit(EditOperation::Insert {
+            position: pos,
+            text,
+        });
+        
+        self.cursor.set_position(pos + len, false);
+        self.finish_edit();
+        self.scroll_to_cursor();
+    }
+
+    /// Returns the text typing `ch` at `pos` inserts, expanding a tab to
+    /// spaces when indenting with spaces.
+    fn typed_text(&self, ch: char, pos: usize) -> String {
+        if ch == '\t' && self.settings.indent.style == IndentStyle::Spaces {
+            let line_start = self.buffer.line_start(self.buffer.char_to_line_col(pos).0);
+            let before: String = (line_start..pos).filter_map(|i| self.buffer.char_at(i)).collect();
+            " ".repeat(self.settings.indent.spaces_to_next_stop(self.settings.indent.visual_width(&before)))
+        } else {
+            ch.to_string()
+        }
+    }
+
+    /// Inserts a string at the cursor position.
+    pub fn insert_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if self.has_multiple_cursors() {
+            self.edit_each_cursor(|_, selection| {
+                let (start, end) = selection.range();
+                Some((start, end, text.to_string()))
+            });
+            return;
+        }
+        
+        self.begin_edit();
+        
+        // Delete selection first if any
+        self.delete_selection_internal();
+        
+        let pos = self.cursor.position();
+        self.buffer.insert(pos, text);
+        self.record_edit(EditOperation::Insert {
+            position: pos,
+            text: text.to_string(),
+        });
+        
+        self.cursor.set_position(pos + text.chars().count(), false);
+        self.finish_edit();
+        self.scroll_to_cursor();
+    }
+
+    /// Inserts a newline at the cursor position with auto-indentation.
+    pub fn insert_newline(&mut self) {
+        if self.has_multiple_cursors() {
+            self.edit_each_cursor(|editor, selection| {
+                let (start, end) = selection.range();
+                Some((start, end, editor.newline_with_indent(start)))
+            });
+            return;
+        }
+
+        self.begin_edit();
+
+        // Delete selection first if any
+        self.delete_selection_internal();
+
+        let pos = self.cursor.position();
+        let text = self.newline_with_indent(pos);
+        self.buffer.insert(pos, &text);
+        self.record_edit(EditOperation::Insert {
+            position: pos,
+            text: text.clone(),
+        });
+
+        self.cursor.set_position(pos + text.chars().count(), false);
+        self.finish_edit();
+        self.scroll_to_cursor();
+    }
+
+    /// Returns the text a newline typed at `pos` inserts: the line break and
+    /// the indentation of the current line, one level deeper after an
+    /// opening bracket or colon.
+    fn newline_with_indent(&self, pos: usize) -> String {
+        let (line, _col) = self.buffer.char_to_line_col(pos);
+        let mut text = format!("\n{}", self.get_line_indentation(line));
+        if self.should_increase_indent(line, pos) {
+            text.push_str(&self.settings.indent.unit());
+        }
+        text
+    }
+
+    /// Gets the indentation (leading whitespace) of a line.
+    fn get_line_indentation(&self, line: usize) -> String {
+        if let Some(line_text) = self.buffer.line(line) {
+            let mut indent = String::new();
+            for ch in line_text.chars() {
+                if ch == ' ' || ch == '\t' {
+                    indent.push(ch);
+                } else {
+                    break;
+                }
+            }
+            indent
         } else {
             String::new()
         }
@@ -606,6 +1603,62 @@ impl Editor {
         text
     }
 
+    /// Returns the selected text of each cursor in buffer order, with an
+    /// empty string for cursors without a selection.
+    /// Returns None if no cursor has a selection.
+    pub fn cursor_selected_texts(&self) -> Option<Vec<String>> {
+        if !self.has_multiple_cursors() {
+            return self.get_selected_text().map(|text| vec![text]);
+        }
+        let mut cursors = self.multi_cursors.clone();
+        *cursors.primary_mut() = self.cursor.clone();
+        cursors.normalize();
+        let ranges: Vec<_> = cursors.iter().map(|c| c.selected_range()).collect();
+        if ranges.iter().all(Option::is_none) {
+            return None;
+        }
+        Some(
+            ranges
+                .into_iter()
+                .map(|range| {
+                    range.map_or_else(String::new, |(start, end)| {
+                        (start..end).filter_map(|i| self.buffer.char_at(i)).collect()
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// Cuts the selection of every cursor as one undo step and returns the
+    /// pieces as [`cursor_selected_texts`](Self::cursor_selected_texts) does.
+    pub fn cut_selections(&mut self) -> Option<Vec<String>> {
+        if !self.has_multiple_cursors() {
+            return self.cut_selection().map(|text| vec![text]);
+        }
+        let pieces = self.cursor_selected_texts()?;
+        self.edit_each_cursor(|_, selection| {
+            selection.selected_range().map(|(start, end)| (start, end, String::new()))
+        });
+        Some(pieces)
+    }
+
+    /// Pastes one piece at each cursor, in buffer order.
+    ///
+    /// If the number of pieces doesn't match the number of cursors, the
+    /// pieces are joined with newlines and the whole text is pasted at every
+    /// cursor instead.
+    pub fn paste_each(&mut self, pieces: &[String]) {
+        if pieces.len() != self.cursor_count() || pieces.len() < 2 {
+            self.paste(&pieces.join("\n"));
+            return;
+        }
+        let mut pieces = pieces.iter();
+        self.edit_each_cursor(|_, selection| {
+            let (start, end) = selection.range();
+            Some((start, end, pieces.next()?.clone()))
+        });
+    }
+
     /// Pastes text at the cursor position.
     pub fn paste(&mut self, text: &str) {
         if text.is_empty() {
@@ -616,6 +1669,18 @@ impl Editor {
 
     /// Deletes the character before the cursor (backspace).
     pub fn delete_backward(&mut self) {
+        if self.has_multiple_cursors() {
+            self.edit_each_cursor(|editor, selection| match selection.selected_range() {
+                Some((start, end)) => Some((start, end, String::new())),
+                None => {
+                    let pos = selection.cursor;
+                    let start = editor.indent_stop_before(pos).unwrap_or(pos.saturating_sub(1));
+                    (pos > 0).then(|| (start, pos, String::new()))
+                }
+            });
+            return;
+        }
+
         self.begin_edit();
         
         if self.delete_selection_internal() {
@@ -626,13 +1691,20 @@ impl Editor {
         
         let pos = self.cursor.position();
         if pos > 0 {
-            let ch = self.buffer.char_at(pos - 1).unwrap();
-            self.buffer.remove(pos - 1, pos);
-            self.history.record(EditOperation::Delete {
-                position: pos - 1,
-                text: ch.to_string(),
+            // Deleting the opening half of an empty auto-closed pair
+            // deletes the closing half too, and in indentation a whole
+            // level goes
+            let (start, end) = self
+                .empty_auto_pair_around(pos)
+                .or_else(|| Some((self.indent_stop_before(pos)?, pos)))
+                .unwrap_or((pos - 1, pos));
+            let text: String = (start..end).filter_map(|i| self.buffer.char_at(i)).collect();
+            self.buffer.remove(start, end);
+            self.record_edit(EditOperation::Delete {
+                position: start,
+                text,
             });
-            self.cursor.set_position(pos - 1, false);
+            self.cursor.set_position(start, false);
         }
         
         self.finish_edit();
@@ -641,6 +1713,18 @@ impl Editor {
 
     /// Deletes the character after the cursor (delete key).
     pub fn delete_forward(&mut self) {
+        if self.has_multiple_cursors() {
+            let len = self.buffer.len_chars();
+            self.edit_each_cursor(|_, selection| match selection.selected_range() {
+                Some((start, end)) => Some((start, end, String::new())),
+                None => {
+                    let pos = selection.cursor;
+                    (pos < len).then(|| (pos, pos + 1, String::new()))
+                }
+            });
+            return;
+        }
+
         self.begin_edit();
         
         if self.delete_selection_internal() {
@@ -653,7 +1737,7 @@ impl Editor {
         if pos < self.buffer.len_chars() {
             let ch = self.buffer.char_at(pos).unwrap();
             self.buffer.remove(pos, pos + 1);
-            self.history.record(EditOperation::Delete {
+            self.record_edit(EditOperation::Delete {
                 position: pos,
                 text: ch.to_string(),
             });
@@ -676,7 +1760,7 @@ impl Editor {
             }
             
             self.buffer.remove(start, end);
-            self.history.record(EditOperation::Delete {
+            self.record_edit(EditOperation::Delete {
                 position: start,
                 text: deleted,
             });
@@ -727,16 +1811,33 @@ impl Editor {
 
     /// Moves cursor left by one word.
     pub fn move_word_left(&mut self, extend_selection: bool) {
-        self.cursor.move_word_left(&self.buffer, extend_selection);
+        self.cursor.move_word_left(&self.buffer, self.word_chars(), extend_selection);
         self.scroll_to_cursor();
     }
 
     /// Moves cursor right by one word.
     pub fn move_word_right(&mut self, extend_selection: bool) {
-        self.cursor.move_word_right(&self.buffer, extend_selection);
+        self.cursor.move_word_right(&self.buffer, self.word_chars(), extend_selection);
+        self.scroll_to_cursor();
+    }
+
+    /// Moves cursor left by one sub-word (camelCase hump or snake_case part).
+    pub fn move_subword_left(&mut self, extend_selection: bool) {
+        self.cursor.move_subword_left(&self.buffer, self.word_chars(), extend_selection);
+        self.scroll_to_cursor();
+    }
+
+    /// Moves cursor right by one sub-word (camelCase hump or snake_case part).
+    pub fn move_subword_right(&mut self, extend_selection: bool) {
+        self.cursor.move_subword_right(&self.buffer, self.word_chars(), extend_selection);
         self.scroll_to_cursor();
     }
 
+    /// Returns the characters that make up words in this buffer's language.
+    pub fn word_chars(&self) -> WordChars {
+        self.language().word_chars()
+    }
+
     /// Moves cursor to the start of the line.
     pub fn move_to_line_start(&mut self, extend_selection: bool) {
         self.cursor.move_to_line_start(&self.buffer, extend_selection);
@@ -791,6 +1892,8 @@ impl Editor {
         self.history.set_selection_after(self.cursor.selection);
         self.history.commit_edit();
         self.modified = true;
+        self.content_version += 1;
+        self.times.last_edit = Some(Instant::now());
         // Invalidate syntax cache - will be rebuilt on next render
         self.highlighter.invalidate_cache();
     }
@@ -798,11 +1901,15 @@ impl Editor {
     /// Undoes the last edit.
     pub fn undo(&mut self) {
         if let Some((ops, selection)) = self.history.undo() {
-            for op in ops {
+            for op in merge_adjacent(ops) {
                 self.apply_operation(&op);
             }
             self.cursor.selection = selection;
             self.cursor.clamp_to_buffer(&self.buffer);
+            self.multi_cursors.collapse_to_primary();
+            self.modified = true;
+            self.content_version += 1;
+            self.times.last_edit = Some(Instant::now());
             self.scroll_to_cursor();
             self.highlighter.invalidate_cache();
         }
@@ -811,11 +1918,15 @@ impl Editor {
     /// Redoes the last undone edit.
     pub fn redo(&mut self) {
         if let Some((ops, selection)) = self.history.redo() {
-            for op in ops {
+            for op in merge_adjacent(ops) {
                 self.apply_operation(&op);
             }
             self.cursor.selection = selection;
             self.cursor.clamp_to_buffer(&self.buffer);
+            self.multi_cursors.collapse_to_primary();
+            self.modified = true;
+            self.content_version += 1;
+            self.times.last_edit = Some(Instant::now());
             self.scroll_to_cursor();
             self.highlighter.invalidate_cache();
         }
@@ -831,6 +1942,77 @@ impl Editor {
                 self.buffer.remove(*position, *position + text.chars().count());
             }
         }
+        self.map_tracked_positions(op);
+    }
+
+    /// Records an applied edit in the history and keeps tracked positions in sync.
+    fn record_edit(&mut self, op: EditOperation) {
+        self.map_tracked_positions(&op);
+        self.history.record(op);
+    }
+
+    /// Maps positions the editor tracks across edits through an applied operation.
+    fn map_tracked_positions(&mut self, op: &EditOperation) {
+        match op {
+            EditOperation::Insert { text, .. } => self.invisible_counts.add(text.chars()),
+            EditOperation::Delete { text, .. } => self.invisible_counts.remove(text.chars()),
+        }
+        self.auto_closers.retain_mut(|pos| match op.map_position(*pos) {
+            Some(mapped) => {
+                *pos = mapped;
+                true
+            }
+            None => false,
+        });
+        if self.buffer.is_empty() {
+            self.auto_closers.clear();
+        }
+        let anchor = match op {
+            EditOperation::Insert { position, .. } | EditOperation::Delete { position, .. } => *position,
+        };
+        // A bookmark whose text was deleted stays on the line of the deletion
+        for pos in &mut self.bookmarks {
+            *pos = op.map_position(*pos).unwrap_or(anchor);
+        }
+        // So does the other pane's view
+        if let Some(view) = &mut self.tracked_view {
+            let positions = [&mut view.cursor, &mut view.scroll].into_iter().chain(view.anchor.as_mut());
+            for pos in positions.chain(view.folds.iter_mut()) {
+                *pos = op.map_position(*pos).unwrap_or(anchor);
+            }
+        }
+        if let Some(snippet) = &mut self.snippet {
+            snippet.map_ranges(|pos, keep_start| match op {
+                EditOperation::Insert { position, .. } if keep_start && *position == pos => pos,
+                _ => op.map_position(pos).unwrap_or(anchor),
+            });
+        }
+        if !self.search.ranges().is_empty() {
+            // Keep the restrictions covering the same text; deleted endpoints
+            // collapse onto the deletion point.
+            let ranges = self.search.ranges();
+            let adjusted = ranges
+                .iter()
+                .map(|&(start, end)| {
+                    let start = match op {
+                        // Text inserted right at the start belongs to the range
+                        EditOperation::Insert { position, .. } if *position == start => start,
+                        _ => op.map_position(start).unwrap_or(anchor),
+                    };
+                    let end = match op {
+                        // ...even if that start is the end of the previous range
+                        EditOperation::Insert { position, .. }
+                            if *position == end && ranges.iter().any(|&(next, _)| next == end) =>
+                        {
+                            end
+                        }
+                        _ => op.map_position(end).unwrap_or(anchor),
+                    };
+                    (start, end.max(start))
+                })
+                .collect();
+            self.search.adjust_ranges(adjusted);
+        }
     }
 
     /// Returns true if undo is available.
@@ -865,7 +2047,7 @@ impl Editor {
         };
 
         self.buffer.insert(actual_insert_pos, &actual_text);
-        self.history.record(EditOperation::Insert {
+        self.record_edit(EditOperation::Insert {
             position: actual_insert_pos,
             text: actual_text.clone(),
         });
@@ -909,7 +2091,7 @@ impl Editor {
 
         // Delete the current line
         self.buffer.remove(line_start, line_end);
-        self.history.record(EditOperation::Delete {
+        self.record_edit(EditOperation::Delete {
             position: line_start,
             text: line_text.clone(),
         });
@@ -925,7 +2107,7 @@ impl Editor {
         };
 
         self.buffer.insert(prev_line_start, &insert_text);
-        self.history.record(EditOperation::Insert {
+        self.record_edit(EditOperation::Insert {
             position: prev_line_start,
             text: insert_text.clone(),
         });
@@ -964,7 +2146,7 @@ impl Editor {
 
         // Delete the current line
         self.buffer.remove(line_start, line_end);
-        self.history.record(EditOperation::Delete {
+        self.record_edit(EditOperation::Delete {
             position: line_start,
             text: line_text.clone(),
         });
@@ -985,7 +2167,7 @@ impl Editor {
         };
 
         self.buffer.insert(new_next_line_end, &insert_text);
-        self.history.record(EditOperation::Insert {
+        self.record_edit(EditOperation::Insert {
             position: new_next_line_end,
             text: insert_text.clone(),
         });
@@ -1001,98 +2183,155 @@ impl Editor {
         self.scroll_to_cursor();
     }
 
+    /// Deletes the current line, or every line the selection touches, with
+    /// its line break, as one undo step. The cursor keeps its column on the
+    /// line that takes their place, as far as that line is long.
+    pub fn delete_line(&mut self) {
+        let (first, last) = self.selected_line_range();
+        let goal_col = self.cursor.goal_col(&self.buffer);
+        let len_chars = self.buffer.len_chars();
+        let (start, end) = if last + 1 < self.buffer.len_lines() {
+            (self.buffer.line_start(first), self.buffer.line_start(last + 1))
+        } else if first > 0 {
+            // The last line has no break of its own; take the one before it
+            (self.buffer.line_start(first) - 1, len_chars)
+        } else {
+            (0, len_chars)
+        };
+        if start == end {
+            return;
+        }
+
+        self.begin_edit();
+        let text = self.buffer.slice(start, end);
+        self.buffer.remove(start, end);
+        self.record_edit(EditOperation::Delete { position: start, text });
+
+        let line = first.min(self.buffer.len_lines() - 1);
+        let col = goal_col.min(self.buffer.line_len_chars(line));
+        self.cursor.set_position(self.buffer.line_col_to_char(line, col), false);
+        self.cursor.set_preferred_col(goal_col);
+
+        self.finish_edit();
+        self.scroll_to_cursor();
+    }
+
+    /// Opens an indented line below the cursor line without splitting it,
+    /// wherever the cursor is on it.
+    pub fn insert_line_below(&mut self) {
+        let (line, _) = self.buffer.char_to_line_col(self.cursor.position());
+        let pos = self.buffer.line_start(line) + self.buffer.line_len_chars(line);
+        // Indented as a newline typed at the end of the line would be
+        let text = self.newline_with_indent(pos);
+        self.insert_line_break(pos, text.clone(), pos + text.chars().count());
+    }
+
+    /// Opens a line above the cursor line, indented like it, without
+    /// splitting it.
+    pub fn insert_line_above(&mut self) {
+        let (line, _) = self.buffer.char_to_line_col(self.cursor.position());
+        let pos = self.buffer.line_start(line);
+        let indent = self.get_line_indentation(line);
+        let cursor = pos + indent.chars().count();
+        self.insert_line_break(pos, format!("{}\n", indent), cursor);
+    }
+
+    /// Inserts `text` at `pos` as one edit and leaves the cursor at `cursor`.
+    fn insert_line_break(&mut self, pos: usize, text: String, cursor: usize) {
+        self.begin_edit();
+        self.buffer.insert(pos, &text);
+        self.record_edit(EditOperation::Insert { position: pos, text });
+        self.cursor.set_position(cursor, false);
+        self.finish_edit();
+        self.scroll_to_cursor();
+    }
+
     /// Toggles line comment on the current line or selected lines.
+    /// Comment markers are aligned at the smallest indentation among the
+    /// affected non-blank lines; blank lines are left alone.
     pub fn toggle_comment(&mut self) {
         let comment_prefix = match self.highlighter.language().line_comment() {
             Some(prefix) => prefix,
             None => return, // Language doesn't support line comments
         };
 
-        self.begin_edit();
-
         let cursor_pos = self.cursor.position();
-        let (start_line, end_line) = if let Some((sel_start, sel_end)) = self.cursor.selected_range() {
-            let (start_line, _) = self.buffer.char_to_line_col(sel_start);
-            let (end_line, end_col) = self.buffer.char_to_line_col(sel_end);
-            // If selection ends at beginning of line, don't include that line
-            let end_line = if end_col == 0 && end_line > start_line {
-                end_line - 1
-            } else {
-                end_line
-            };
-            (start_line, end_line)
-        } else {
-            let (line, _) = self.buffer.char_to_line_col(cursor_pos);
-            (line, line)
-        };
+        let (start_line, end_line) = self.selected_line_range();
+        let lines: Vec<(usize, String)> = (start_line..=end_line)
+            .filter_map(|line| self.buffer.line(line).map(|text| (line, text)))
+            .filter(|(_, text)| !text.trim().is_empty())
+            .collect();
+        if lines.is_empty() {
+            return;
+        }
+
+        self.begin_edit();
 
         // Check if all lines are commented (to decide whether to uncomment or comment)
-        let all_commented = (start_line..=end_line).all(|line| {
-            if let Some(line_text) = self.buffer.line(line) {
-                let trimmed = line_text.trim_start();
-                trimmed.starts_with(comment_prefix)
-            } else {
-                true
-            }
-        });
+        let all_commented = lines
+            .iter()
+            .all(|(_, text)| text.trim_start().starts_with(comment_prefix));
+
+        // Column (in display width) where comments are inserted
+        let min_indent = lines
+            .iter()
+            .map(|(_, text)| {
+                let leading: String = text.chars().take_while(|c| c.is_whitespace()).collect();
+                self.settings.indent.visual_width(&leading)
+            })
+            .min()
+            .unwrap_or(0);
 
         // Calculate position adjustments
-        let comment_len = comment_prefix.len() + 1; // prefix + space
+        let comment_len = comment_prefix.chars().count() + 1; // prefix + space
         let mut total_offset: isize = 0;
 
-        for line in start_line..=end_line {
-            let line_start = self.buffer.line_start(line);
+        // Work bottom-up so earlier line starts stay valid
+        for (line, line_text) in lines.iter().rev() {
+            let line_start = self.buffer.line_start(*line);
+            let first_non_ws = line_text.chars().take_while(|c| c.is_whitespace()).count();
 
             if all_commented {
                 // Uncomment: remove the comment prefix
-                if let Some(line_text) = self.buffer.line(line) {
-                    let first_non_ws = line_text
-                        .chars()
-                        .take_while(|c| c.is_whitespace())
-                        .count();
-
-                    let content_start = line_start + first_non_ws;
-                    let rest = &line_text[first_non_ws..];
-
-                    if rest.starts_with(comment_prefix) {
-                        // Check if there's a space after the prefix
-                        let has_space = rest.len() > comment_prefix.len()
-                            && rest.chars().nth(comment_prefix.len()) == Some(' ');
-                        let remove_len = if has_space { comment_len } else { comment_prefix.len() };
-
-                        let remove_end = (content_start + remove_len).min(self.buffer.len_chars());
-                        let removed_text: String = (content_start..remove_end)
-                            .filter_map(|i| self.buffer.char_at(i))
-                            .collect();
-
-                        self.buffer.remove(content_start, remove_end);
-                        self.history.record(EditOperation::Delete {
-                            position: content_start,
-                            text: removed_text,
-                        });
-
-                        total_offset -= remove_len as isize;
+                let content_start = line_start + first_non_ws;
+                let rest: String = line_text.chars().skip(first_non_ws).collect();
+                let prefix_len = comment_prefix.chars().count();
+                let has_space = rest.chars().nth(prefix_len) == Some(' ');
+                let remove_len = if has_space { comment_len } else { prefix_len };
+
+                let remove_end = (content_start + remove_len).min(self.buffer.len_chars());
+                let removed_text: String = (content_start..remove_end)
+                    .filter_map(|i| self.buffer.char_at(i))
+                    .collect();
+
+                self.buffer.remove(content_start, remove_end);
+                self.record_edit(EditOperation::Delete {
+                    position: content_start,
+                    text: removed_text,
+                });
+
+                total_offset -= remove_len as isize;
+            } else {
+                // Comment: insert at the shared indentation column
+                let mut offset = 0;
+                while offset < first_non_ws {
+                    let leading: String = line_text.chars().take(offset).collect();
+                    if self.settings.indent.visual_width(&leading) >= min_indent {
+                        break;
                     }
+                    offset += 1;
                 }
-            } else {
-                // Comment: add the comment prefix at the start of non-whitespace
-                if let Some(line_text) = self.buffer.line(line) {
-                    let first_non_ws = line_text
-                        .chars()
-                        .take_while(|c| c.is_whitespace())
-                        .count();
-
-                    let insert_pos = line_start + first_non_ws;
-                    let insert_text = format!("{} ", comment_prefix);
-
-                    self.buffer.insert(insert_pos, &insert_text);
-                    self.history.record(EditOperation::Insert {
-                        position: insert_pos,
-                        text: insert_text,
-                    });
 
-                    total_offset += comment_len as isize;
-                }
+                let insert_pos = line_start + offset;
+                let insert_text = format!("{} ", comment_prefix);
+
+                self.buffer.insert(insert_pos, &insert_text);
+                self.record_edit(EditOperation::Insert {
+                    position: insert_pos,
+                    text: insert_text,
+                });
+
+                total_offset += comment_len as isize;
             }
         }
 
@@ -1104,32 +2343,166 @@ impl Editor {
         self.scroll_to_cursor();
     }
 
-    // ==================== Bracket Matching ====================
+    // ==================== Indentation ====================
 
-    /// Finds the matching bracket for the bracket at the given position.
-    /// Returns the position of the matching bracket, or None if not found.
-    pub fn find_matching_bracket(&self, pos: usize) -> Option<usize> {
-        let ch = self.buffer.char_at(pos)?;
-        let bracket_pairs = self.highlighter.language().bracket_pairs();
+    /// Returns the indentation settings.
+    pub fn indent_config(&self) -> IndentConfig {
+        self.settings.indent
+    }
 
-        // Check if character is an opening bracket
-        for &(open, close) in bracket_pairs {
-            if ch == open {
-                return self.find_closing_bracket(pos, open, close);
-            } else if ch == close {
-                return self.find_opening_bracket(pos, open, close);
+    /// Returns true if the opened file indents some lines with tabs and
+    /// others with spaces; its indentation is then the majority's.
+    pub fn has_mixed_indentation(&self) -> bool {
+        self.mixed_indentation
+    }
+
+    /// Sets the indentation settings used by Tab, auto-indent, comment
+    /// alignment, and indent/dedent.
+    pub fn set_indent_config(&mut self, config: IndentConfig) {
+        self.settings.indent = config;
+    }
+
+    /// Returns the first and last line touched by the selection, or the
+    /// cursor line. A selection ending at column 0 doesn't include that line.
+    fn selected_line_range(&self) -> (usize, usize) {
+        if let Some((sel_start, sel_end)) = self.cursor.selected_range() {
+            let (start_line, _) = self.buffer.char_to_line_col(sel_start);
+            let (end_line, end_col) = self.buffer.char_to_line_col(sel_end);
+            if end_col == 0 && end_line > start_line {
+                (start_line, end_line - 1)
+            } else {
+                (start_line, end_line)
             }
+        } else {
+            let (line, _) = self.buffer.char_to_line_col(self.cursor.position());
+            (line, line)
         }
-        None
     }
 
-    /// Finds the closing bracket starting from pos.
-    fn find_closing_bracket(&self, start: usize, open: char, close: char) -> Option<usize> {
-        let mut depth = 1;
-        let mut pos = start + 1;
+    /// Returns true if the selection touches more than one line.
+    pub fn has_multiline_selection(&self) -> bool {
+        let (start_line, end_line) = self.selected_line_range();
+        self.cursor.has_selection() && end_line > start_line
+    }
 
-        while pos < self.buffer.len_chars() && depth > 0 {
-            if let Some(ch) = self.buffer.char_at(pos) {
+    /// Indents the selected lines (or the cursor line) by one level, as a
+    /// single undo step. Blank lines in a multi-line selection are skipped.
+    pub fn indent_selection(&mut self) {
+        let (start_line, end_line) = self.selected_line_range();
+        self.indent_lines(start_line, end_line);
+    }
+
+    /// Indents lines `start_line..=end_line` by one level as a single undo
+    /// step, keeping the selection on the same text. Blank lines are skipped
+    /// unless only one line is indented.
+    pub fn indent_lines(&mut self, start_line: usize, end_line: usize) {
+        let end_line = end_line.min(self.buffer.len_lines().saturating_sub(1));
+        if start_line > end_line {
+            return;
+        }
+        let unit = self.settings.indent.unit();
+
+        self.begin_edit();
+        let mut selection = self.cursor.selection;
+        for line in (start_line..=end_line).rev() {
+            let blank = self.buffer.line(line).is_some_and(|text| text.trim().is_empty());
+            if blank && start_line != end_line {
+                continue;
+            }
+            let position = self.buffer.line_start(line);
+            self.buffer.insert(position, &unit);
+            // Endpoints at the line start stay put so the selection keeps
+            // covering whole lines, indentation included
+            let shift = |p: usize| if p > position { p + unit.chars().count() } else { p };
+            selection = Selection::with_range(shift(selection.anchor), shift(selection.cursor));
+            self.record_edit(EditOperation::Insert {
+                position,
+                text: unit.clone(),
+            });
+        }
+        self.cursor.selection = selection;
+        self.finish_edit();
+        self.scroll_to_cursor();
+    }
+
+    /// Removes one indent level (up to `width` leading spaces, or a single
+    /// tab) from the selected lines (or the cursor line), as a single undo
+    /// step. Lines without leading whitespace are unchanged.
+    pub fn dedent_selection(&mut self) {
+        let (start_line, end_line) = self.selected_line_range();
+        self.dedent_lines(start_line, end_line);
+    }
+
+    /// Removes one indent level from lines `start_line..=end_line` as a
+    /// single undo step, keeping the selection on the same text. Lines without
+    /// leading whitespace are unchanged.
+    pub fn dedent_lines(&mut self, start_line: usize, end_line: usize) {
+        let removals: Vec<(usize, String)> = (start_line..=end_line)
+            .filter_map(|line| {
+                let text = self.buffer.line(line)?;
+                let removed: String = if text.starts_with('\t') {
+                    "\t".to_string()
+                } else {
+                    text.chars().take(self.settings.indent.width).take_while(|&c| c == ' ').collect()
+                };
+                (!removed.is_empty()).then(|| (self.buffer.line_start(line), removed))
+            })
+            .collect();
+        if removals.is_empty() {
+            return;
+        }
+
+        self.begin_edit();
+        let mut selection = self.cursor.selection;
+        for (position, text) in removals.into_iter().rev() {
+            self.buffer.remove(position, position + text.chars().count());
+            let op = EditOperation::Delete { position, text };
+            selection = Self::map_selection(selection, &op);
+            self.record_edit(op);
+        }
+        self.cursor.selection = selection;
+        self.finish_edit();
+        self.scroll_to_cursor();
+    }
+
+    /// Maps a selection's endpoints through a deletion; endpoints inside the
+    /// deleted text land on its start.
+    fn map_selection(selection: Selection, op: &EditOperation) -> Selection {
+        let anchor_pos = match op {
+            EditOperation::Insert { position, .. } | EditOperation::Delete { position, .. } => *position,
+        };
+        Selection::with_range(
+            op.map_position(selection.anchor).unwrap_or(anchor_pos),
+            op.map_position(selection.cursor).unwrap_or(anchor_pos),
+        )
+    }
+
+    // ==================== Bracket Matching ====================
+
+    /// Finds the matching bracket for the bracket at the given position.
+    /// Returns the position of the matching bracket, or None if not found.
+    pub fn find_matching_bracket(&self, pos: usize) -> Option<usize> {
+        let ch = self.buffer.char_at(pos)?;
+        let bracket_pairs = self.highlighter.language().bracket_pairs();
+
+        // Check if character is an opening bracket
+        for &(open, close) in bracket_pairs {
+            if ch == open {
+                return self.find_closing_bracket(pos, open, close);
+            } else if ch == close {
+                return self.find_opening_bracket(pos, open, close);
+            }
+        }
+        None
+    }
+
+    /// Finds the closing bracket starting from pos.
+    fn find_closing_bracket(&self, start: usize, open: char, close: char) -> Option<usize> {
+        let mut depth = 1;
+        let mut pos = start + 1;
+
+        while pos < self.buffer.len_chars() && depth > 0 {
+            if let Some(ch) = self.buffer.char_at(pos) {
                 if ch == open {
                     depth += 1;
                 } else if ch == close {
@@ -1185,42 +2558,78 @@ impl Editor {
         None
     }
 
-    /// Inserts a character with auto-close bracket support.
-    pub fn insert_char_with_auto_bracket(&mut self, ch: char) {
-        let bracket_pairs = self.highlighter.language().bracket_pairs();
+    /// Returns the positions of the opening and closing bracket of the
+    /// innermost pair strictly around `pos`, whichever kind it is.
+    fn enclosing_brackets(&self, pos: usize) -> Option<(usize, usize)> {
+        self.highlighter
+            .language()
+            .bracket_pairs()
+            .iter()
+            .filter_map(|&(open, close)| {
+                let start = self.find_opening_bracket(pos, open, close)?;
+                Some((start, self.find_closing_bracket(start, open, close)?))
+            })
+            .max_by_key(|&(start, _)| start)
+    }
+
+    /// Moves the cursor to the bracket matching the one at (or just before)
+    /// the cursor. Off a bracket, it moves to the closing bracket of the
+    /// innermost pair around the cursor instead.
+    ///
+    /// With `select`, selects the pair and what it encloses instead of
+    /// moving. Returns false if there is no such bracket.
+    pub fn goto_matching_bracket(&mut self, select: bool) -> bool {
+        let pos = self.cursor.position();
+        let (bracket, target) = match self.matching_bracket_at_cursor() {
+            Some(pair) => pair,
+            None => match self.enclosing_brackets(pos) {
+                Some((open, close)) => (open, close),
+                None => return false,
+            },
+        };
+        if select {
+            let (start, end) = (bracket.min(target), bracket.max(target));
+            self.cursor.set_position(start, false);
+            self.cursor.set_position(end + 1, true);
+        } else {
+            self.cursor.set_position(target, false);
+        }
+        self.scroll_to_cursor();
+        true
+    }
 
-        // Check if this is an opening bracket
-        for &(open, close) in bracket_pairs {
-            if ch == open {
-                // Insert both opening and closing bracket
-                self.begin_edit();
-                self.delete_selection_internal();
+    /// Inserts a character with auto-close bracket and quote support.
+    ///
+    /// Typing a closing bracket or quote only types over the next character
+    /// when that character is a closer the editor auto-inserted; otherwise
+    /// it is inserted literally. See [`Editor::should_auto_close_quote`] for
+    /// when a quote gets a closing quote. Nothing is auto-closed inside a
+    /// string or comment.
+    pub fn insert_char_with_auto_bracket(&mut self, ch: char) {
+        if self.has_multiple_cursors() {
+            self.insert_char(ch);
+            return;
+        }
+        let language = self.highlighter.language();
 
-                let pos = self.cursor.position();
-                let pair = format!("{}{}", open, close);
-                self.buffer.insert(pos, &pair);
-                self.history.record(EditOperation::Insert {
-                    position: pos,
-                    text: pair,
-                });
+        if language.quote_chars().contains(&ch) {
+            if !self.type_over_auto_closer(ch) {
+                if self.should_auto_close_quote(ch) && !self.in_string_or_comment() {
+                    self.insert_auto_closed_pair(ch, ch);
+                } else {
+                    self.insert_char(ch);
+                }
+            }
+            return;
+        }
 
-                // Position cursor between brackets
-                self.cursor.set_position(pos + 1, false);
-                self.finish_edit();
-                self.scroll_to_cursor();
+        for &(open, close) in language.bracket_pairs() {
+            if ch == open && !self.in_string_or_comment() {
+                self.insert_auto_closed_pair(open, close);
                 return;
             }
-
-            // If typing a closing bracket and the next char is the same closing bracket, just skip
-            if ch == close {
-                let pos = self.cursor.position();
-                if let Some(next_ch) = self.buffer.char_at(pos) {
-                    if next_ch == close {
-                        self.cursor.set_position(pos + 1, false);
-                        self.scroll_to_cursor();
-                        return;
-                    }
-                }
+            if ch == close && (self.type_over_auto_closer(close) || self.insert_dedented_closer(open, close)) {
+                return;
             }
         }
 
@@ -1228,6 +2637,194 @@ impl Editor {
         self.insert_char(ch);
     }
 
+    /// Inserts `close` on a line with nothing but indentation before the
+    /// cursor, first re-indenting the line like the line of the matching
+    /// `open`, so a block closes at the column it was opened at. Returns
+    /// false, changing nothing, if there is text before the cursor, no
+    /// matching bracket, or the indentation already matches.
+    fn insert_dedented_closer(&mut self, open: char, close: char) -> bool {
+        if self.cursor.has_selection() {
+            return false;
+        }
+        let pos = self.cursor.position();
+        let (line, _) = self.buffer.char_to_line_col(pos);
+        let line_start = self.buffer.line_start(line);
+        let prefix: String = (line_start..pos).filter_map(|i| self.buffer.char_at(i)).collect();
+        if prefix.is_empty() || !prefix.chars().all(|c| c == ' ' || c == '\t') || self.in_string_or_comment() {
+            return false;
+        }
+        let Some(open_pos) = self.find_opening_bracket(pos, open, close) else {
+            return false;
+        };
+        let indent = self.get_line_indentation(self.buffer.char_to_line_col(open_pos).0);
+        if indent == prefix {
+            return false;
+        }
+
+        self.begin_edit();
+        self.buffer.remove(line_start, pos);
+        self.record_edit(EditOperation::Delete {
+            position: line_start,
+            text: prefix,
+        });
+        let text = format!("{}{}", indent, close);
+        self.buffer.insert(line_start, &text);
+        self.cursor.set_position(line_start + text.chars().count(), false);
+        self.record_edit(EditOperation::Insert {
+            position: line_start,
+            text,
+        });
+        self.finish_edit();
+        self.scroll_to_cursor();
+        true
+    }
+
+    /// Inserts `open` and `close` around the cursor, replacing the
+    /// selection, and remembers `close` as auto-inserted.
+    fn insert_auto_closed_pair(&mut self, open: char, close: char) {
+        self.begin_edit();
+        self.delete_selection_internal();
+
+        let pos = self.cursor.position();
+        let pair = format!("{}{}", open, close);
+        self.buffer.insert(pos, &pair);
+        self.record_edit(EditOperation::Insert {
+            position: pos,
+            text: pair,
+        });
+
+        self.auto_closers.push(pos + 1);
+
+        // Position cursor between the pair
+        self.cursor.set_position(pos + 1, false);
+        self.finish_edit();
+        self.scroll_to_cursor();
+    }
+
+    /// Returns true if the cursor, or the start of the selection, is inside
+    /// a string, character literal or comment, per the syntax highlighting.
+    /// A line comment reaches to the end of its line.
+    fn in_string_or_comment(&mut self) -> bool {
+        if !self.highlighter.is_cache_valid() {
+            self.reparse_syntax();
+        }
+        let pos = self.cursor.selected_range().map_or(self.cursor.position(), |(start, _)| start);
+        let (line, col) = self.buffer.char_to_line_col(pos);
+        let Some(highlights) = self.highlighter.line_highlights(line) else {
+            return false;
+        };
+        // Inside a span means between two of its characters, so not before
+        // an opening quote or after a closing one
+        let Some(&(start, end, style)) = col
+            .checked_sub(1)
+            .and_then(|prev| highlights.spans().iter().find(|&&(start, end, _)| start <= prev && prev < end))
+        else {
+            return false;
+        };
+        match style {
+            TokenStyle::String | TokenStyle::Char => col < end,
+            TokenStyle::Comment => {
+                let line_comment = self.highlighter.language().line_comment().is_some_and(|prefix| {
+                    let text = self.buffer.line(line).unwrap_or_default();
+                    text.chars().skip(start).collect::<String>().starts_with(prefix)
+                });
+                col < end || line_comment
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the range of the auto-closed pair around `pos` if nothing
+    /// but the line breaks and indentation of pressing Enter is inside it,
+    /// from the opening character through the closing one.
+    fn empty_auto_pair_around(&self, pos: usize) -> Option<(usize, usize)> {
+        let is_blank = |i: usize| self.buffer.char_at(i).is_some_and(|c| c == ' ' || c == '\t' || c == '\n');
+        let mut open = pos;
+        while open > 0 && is_blank(open - 1) {
+            open -= 1;
+        }
+        let mut close = pos;
+        while is_blank(close) {
+            close += 1;
+        }
+        // Spaces typed between quotes aren't an empty pair
+        let inside: String = (open..close).filter_map(|i| self.buffer.char_at(i)).collect();
+        if !inside.is_empty() && !inside.contains('\n') {
+            return None;
+        }
+        let open = open.checked_sub(1)?;
+        let pair = (self.buffer.char_at(open)?, self.buffer.char_at(close)?);
+        (self.auto_closers.contains(&close) && self.is_auto_pair(pair.0, pair.1)).then_some((open, close + 1))
+    }
+
+    /// Returns where Backspace at `pos` deletes back to when only spaces
+    /// are left of it on its line: the indent stop before it, e.g. column
+    /// 4 from column 7 with a width of 4. `None` when indenting with tabs,
+    /// which go one at a time anyway.
+    fn indent_stop_before(&self, pos: usize) -> Option<usize> {
+        let indent = self.settings.indent;
+        if indent.style != IndentStyle::Spaces {
+            return None;
+        }
+        let (line, col) = self.buffer.char_to_line_col(pos);
+        if col == 0 || self.buffer.line_slice_chars(line, 0, col).any(|c| c != ' ') {
+            return None;
+        }
+        Some(pos - col + (col - 1) / indent.width * indent.width)
+    }
+
+    /// Returns true if `close` is auto-inserted after `open`: a bracket
+    /// pair or a quote of the language.
+    fn is_auto_pair(&self, open: char, close: char) -> bool {
+        let language = self.highlighter.language();
+        language.bracket_pairs().contains(&(open, close)) || (open == close && language.quote_chars().contains(&open))
+    }
+
+    /// Moves the cursor over the next character if it is `close` and the
+    /// editor auto-inserted it. Returns whether it did.
+    fn type_over_auto_closer(&mut self, close: char) -> bool {
+        if self.cursor.has_selection() {
+            return false;
+        }
+        let pos = self.cursor.position();
+        if self.buffer.char_at(pos) != Some(close) {
+            return false;
+        }
+        let Some(idx) = self.auto_closers.iter().position(|&p| p == pos) else {
+            return false;
+        };
+        self.auto_closers.swap_remove(idx);
+        self.cursor.set_position(pos + 1, false);
+        self.scroll_to_cursor();
+        true
+    }
+
+    /// Returns whether typing `quote` at the cursor also inserts a closing
+    /// quote: only when the next character is whitespace, a closing bracket
+    /// or the end of the line. An apostrophe after a word character, as in
+    /// `don't`, isn't closed; nor is one starting a Rust lifetime, as in
+    /// `&'a` or `<'a>`.
+    fn should_auto_close_quote(&self, quote: char) -> bool {
+        let language = self.highlighter.language();
+        let pos = self.cursor.selected_range().map_or(self.cursor.position(), |(start, _)| start);
+        let end = self.cursor.selected_range().map_or(pos, |(_, end)| end);
+        let next_ok = match self.buffer.char_at(end) {
+            None => true,
+            Some(next) => next.is_whitespace() || language.bracket_pairs().iter().any(|&(_, close)| close == next),
+        };
+        if !next_ok {
+            return false;
+        }
+        if quote != '\'' || pos == 0 {
+            return true;
+        }
+        let prev = self.buffer.char_at(pos - 1).unwrap_or(' ');
+        if language.word_chars().contains(prev) {
+            return false;
+        }
+        !(language == Language::Rust && matches!(prev, '<' | '&'))
+    }
+
     // ==================== Selection ====================
 
     /// Selects all text.
@@ -1236,9 +2833,138 @@ impl Editor {
         self.cursor.set_position(self.buffer.len_chars(), true);
     }
 
+    /// Returns the char range of the unit of `granularity` at `line` and
+    /// `col`: the word there (or the character, between words), or the
+    /// line with its line break.
+    pub fn unit_range_at(&self, line: usize, col: usize, granularity: SelectionGranularity) -> (usize, usize) {
+        let pos = self.buffer.line_col_to_char(line, col);
+        match granularity {
+            SelectionGranularity::Char => (pos, pos),
+            SelectionGranularity::Word => {
+                let word_chars = self.word_chars();
+                let start = self.buffer.find_word_start(pos, word_chars);
+                let end = self.buffer.find_word_end(pos, word_chars);
+                if start < end {
+                    (start, end)
+                } else if pos < self.buffer.line_end(line) {
+                    (pos, pos + 1)
+                } else {
+                    (pos, pos)
+                }
+            }
+            SelectionGranularity::Line => (self.buffer.line_start(line), self.buffer.line_start(line + 1)),
+        }
+    }
+
+    /// Selects from the unit of `granularity` at `anchor` to the one at
+    /// `line` and `col`, both included, with the cursor on the side of the
+    /// latter. A double or triple click selects with the anchor at the
+    /// click; dragging after it keeps the anchor.
+    pub fn select_units(&mut self, anchor: (usize, usize), line: usize, col: usize, granularity: SelectionGranularity) {
+        let (anchor_start, anchor_end) = self.unit_range_at(anchor.0, anchor.1, granularity);
+        let (start, end) = self.unit_range_at(line, col, granularity);
+        self.exit_block_selection();
+        self.multi_cursors.collapse_to_primary();
+        if start < anchor_start {
+            self.cursor.set_position(anchor_end, false);
+            self.cursor.set_position(start, true);
+        } else {
+            self.cursor.set_position(anchor_start, false);
+            self.cursor.set_position(end.max(anchor_end), true);
+        }
+        self.clear_search_in_selection();
+        self.scroll_to_cursor();
+    }
+
+    /// Grows the selection to the smallest syntax node strictly containing
+    /// it, e.g. identifier, expression, statement, block, function. Without
+    /// a syntax tree it grows word, line, paragraph, then everything.
+    /// Returns false if it already covers everything.
+    pub fn expand_selection(&mut self) -> bool {
+        let current = self.cursor.selection;
+        let (start, end) = current.range();
+        let Some((new_start, new_end)) = self
+            .enclosing_syntax_range(start, end)
+            .or_else(|| self.enclosing_text_range(start, end))
+        else {
+            return false;
+        };
+
+        if self.expanded_selection != Some(current) {
+            self.expansion_stack.clear();
+        }
+        self.expansion_stack.push(current);
+        self.exit_block_selection();
+        self.multi_cursors.collapse_to_primary();
+        self.cursor.set_position(new_start, false);
+        self.cursor.set_position(new_end, true);
+        self.expanded_selection = Some(self.cursor.selection);
+        self.scroll_to_cursor();
+        true
+    }
+
+    /// Shrinks the selection back to what `expand_selection` grew it from.
+    /// Returns false if the selection wasn't expanded, or was changed since.
+    pub fn shrink_selection(&mut self) -> bool {
+        if self.expanded_selection != Some(self.cursor.selection) {
+            self.expansion_stack.clear();
+            self.expanded_selection = None;
+            return false;
+        }
+        let Some(previous) = self.expansion_stack.pop() else {
+            return false;
+        };
+        self.cursor.selection = previous;
+        self.expanded_selection = (!self.expansion_stack.is_empty()).then_some(previous);
+        self.scroll_to_cursor();
+        true
+    }
+
+    /// Returns the char range of the smallest syntax node strictly
+    /// containing `start..end`, if the language has a syntax tree.
+    fn enclosing_syntax_range(&mut self, start: usize, end: usize) -> Option<(usize, usize)> {
+        if !self.highlighter.is_cache_valid() {
+            self.reparse_syntax();
+        }
+        let range = self
+            .highlighter
+            .enclosing_node(self.buffer.char_to_byte(start), self.buffer.char_to_byte(end))?;
+        Some((self.buffer.byte_to_char(range.start), self.buffer.byte_to_char(range.end)))
+    }
+
+    /// Returns the smallest of the word, lines, paragraph and whole text
+    /// around `start..end` that strictly contains it.
+    fn enclosing_text_range(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        let word_chars = self.word_chars();
+        let word = (
+            self.buffer.find_word_start(start, word_chars),
+            self.buffer.find_word_end(end, word_chars),
+        );
+
+        let first_line = self.buffer.char_to_line_col(start).0;
+        let last_line = self.buffer.char_to_line_col(end).0;
+        let lines = (self.buffer.line_start(first_line), self.buffer.line_end(last_line));
+
+        let is_blank = |line: usize| self.buffer.line(line).is_none_or(|text| text.trim().is_empty());
+        let mut top = first_line;
+        while top > 0 && !is_blank(top - 1) {
+            top -= 1;
+        }
+        let mut bottom = last_line;
+        while bottom + 1 < self.buffer.len_lines() && !is_blank(bottom + 1) {
+            bottom += 1;
+        }
+        let paragraph = (self.buffer.line_start(top), self.buffer.line_end(bottom));
+
+        [word, lines, paragraph, (0, self.buffer.len_chars())]
+            .into_iter()
+            .find(|&(s, e)| s <= start && e >= end && e - s > end - start)
+    }
+
     /// Clears the selection.
     pub fn clear_selection(&mut self) {
         self.cursor.collapse_selection();
+        self.clear_search_in_selection();
     }
 
     /// Returns the selected text, if any.
@@ -1259,8 +2985,8 @@ impl Editor {
         let pos = self.cursor.position();
 
         // Find word boundaries
-        let start = self.buffer.find_word_start(pos);
-        let end = self.buffer.find_word_end(pos);
+        let start = self.buffer.find_word_start(pos, self.word_chars());
+        let end = self.buffer.find_word_end(pos, self.word_chars());
 
         if start >= end {
             return None;
@@ -1285,7 +3011,15 @@ impl Editor {
     pub fn replace_range(&mut self, start_line: usize, start_col: usize, end_line: usize, end_col: usize, new_text: &str) {
         let start_char = self.buffer.line_col_to_char(start_line, start_col);
         let end_char = self.buffer.line_col_to_char(end_line, end_col);
+        self.replace_chars(start_char, end_char, new_text);
+    }
 
+    /// Replaces the characters `start_char..end_char` with `new_text` as
+    /// one undo step. Cursors after the range shift with the text; the view
+    /// doesn't scroll.
+    pub fn replace_chars(&mut self, start_char: usize, end_char: usize, new_text: &str) {
+        let end_char = end_char.min(self.buffer.len_chars());
+        let start_char = start_char.min(end_char);
         let removed_len = end_char.saturating_sub(start_char);
         let new_len = new_text.chars().count();
         let delta = new_len as isize - removed_len as isize;
@@ -1305,7 +3039,7 @@ impl Editor {
         // Delete the range
         if end_char > start_char {
             self.buffer.remove(start_char, end_char);
-            self.history.record(EditOperation::Delete {
+            self.record_edit(EditOperation::Delete {
                 position: start_char,
                 text: removed_text.clone(),
             });
@@ -1314,14 +3048,14 @@ impl Editor {
         // Insert the new text
         self.buffer.insert(start_char, new_text);
         if !new_text.is_empty() {
-            self.history.record(EditOperation::Insert {
+            self.record_edit(EditOperation::Insert {
                 position: start_char,
                 text: new_text.to_string(),
             });
         }
 
         // Adjust cursor/multi-cursor positions
-        let mut adjust_selection = |selection: &mut Selection| {
+        let adjust_selection = |selection: &mut Selection| {
             if selection.cursor >= start_char {
                 selection.cursor = (selection.cursor as isize + delta).max(0) as usize;
             }
@@ -1340,6 +3074,8 @@ impl Editor {
 
         // Mark as modified
         self.modified = true;
+        self.content_version += 1;
+        self.times.last_edit = Some(Instant::now());
         self.document_version += 1;
 
         // Update syntax highlighting
@@ -1379,11 +3115,39 @@ impl Editor {
             // Also update the regular cursor position
             let new_pos = self.buffer.line_col_to_char(line, col);
             self.cursor.set_position(new_pos, false);
+            // Past a short line's end the caret keeps the block's column
+            self.cursor.set_preferred_col(col);
+        }
+    }
+
+    /// Moves the cursor corner of the block selection `lines` down and
+    /// `cols` right (negative for up and left), first starting a block at
+    /// the cursor if there is none. The corner stops at the buffer's first
+    /// and last lines, column 0 and the end of the longest line the block
+    /// covers.
+    pub fn move_block_corner(&mut self, lines: isize, cols: isize) {
+        if !self.cursor.is_block_mode() {
+            self.collapse_cursors();
+            self.cursor.start_block_selection(&self.buffer);
         }
+        let Some(block) = self.cursor.get_block_selection().copied() else {
+            return;
+        };
+        let last_line = self.buffer.len_lines() - 1;
+        let line = block.cursor.line.saturating_add_signed(lines).min(last_line);
+        let moved = BlockSelection { cursor: Position::new(line, block.cursor.col), ..block };
+        let widest = moved.line_range().map(|l| self.buffer.line_len_chars(l)).max().unwrap_or(0);
+        let col = if cols > 0 {
+            block.cursor.col.saturating_add_signed(cols).min(widest.max(block.cursor.col))
+        } else {
+            block.cursor.col.saturating_add_signed(cols)
+        };
+        self.extend_block_selection(line, col);
+        self.scroll_to_cursor();
     }
 
     /// Returns the block selection if active.
-    pub fn get_block_selection(&self) -> Option<&crate::cursor::BlockSelection> {
+    pub fn get_block_selection(&self) -> Option<&BlockSelection> {
         self.cursor.get_block_selection()
     }
 
@@ -1436,7 +3200,7 @@ impl Editor {
                     }
 
                     self.buffer.remove(start_pos, end_pos);
-                    self.history.record(EditOperation::Delete {
+                    self.record_edit(EditOperation::Delete {
                         position: start_pos,
                         text: deleted,
                     });
@@ -1453,43 +3217,120 @@ impl Editor {
         self.scroll_to_cursor();
     }
 
-    /// Inserts text at each line of the block selection.
+    /// Inserts text at each line of the block selection, replacing the
+    /// selected text. The block becomes a column of cursors after the
+    /// inserted text, so typing goes on at every line.
     pub fn insert_text_at_block(&mut self, text: &str) {
+        if !self.cursor.is_block_mode() {
+            // Not in block mode, just insert normally
+            self.insert_text(text);
+            return;
+        }
+
+        self.block_to_cursors();
+        self.edit_each_cursor(|_, selection| {
+            let (start, end) = selection.range();
+            Some((start, end, text.to_string()))
+        });
+    }
+
+    /// Turns the block selection into a column of cursors, one per line,
+    /// each selecting the block's columns on its line, so edits then apply
+    /// at every line. The cursor on the block's cursor line is the primary.
+    pub fn block_to_cursors(&mut self) {
+        let block = match self.cursor.get_block_selection() {
+            Some(b) => *b,
+            None => return,
+        };
+
+        let (top, _) = block.bounds();
+        let forward = block.cursor.col >= block.anchor.col;
+        let selections = block
+            .line_range()
+            .filter_map(|line_num| {
+                let (start_col, end_col) = block.col_range(&self.buffer, line_num)?;
+                let line_start = self.buffer.line_start(line_num);
+                let (start, end) = (line_start + start_col, line_start + end_col);
+                Some(if forward { Selection::with_range(start, end) } else { Selection::with_range(end, start) })
+            })
+            .collect();
+
+        self.cursor.exit_block_mode();
+        self.multi_cursors.set_selections(selections, block.cursor.line - top.line);
+        for cursor in self.multi_cursors.iter_mut() {
+            cursor.set_preferred_col(block.cursor.col);
+        }
+        self.cursor = self.multi_cursors.primary().clone();
+    }
+
+    /// Pastes into the block selection, one line of `text` per selected
+    /// line: extra clipboard lines are dropped and selected lines without
+    /// one are just cleared. Single-line text is pasted on every line.
+    /// Exits block mode with the cursor after the text pasted on the last
+    /// line.
+    pub fn paste_block(&mut self, text: &str) {
         let block = match self.cursor.get_block_selection() {
             Some(b) => *b,
             None => {
-                // Not in block mode, just insert normally
-                self.insert_text(text);
+                self.paste(text);
                 return;
             }
         };
 
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        let lines: Vec<&str> = text.split('\n').map(|line| line.trim_end_matches('\r')).collect();
+        let row_count = block.line_range().count();
+        let rows = if lines.len() == 1 { vec![lines[0]; row_count] } else { lines };
+
         self.begin_edit();
 
+        let top = self.replace_block_rows(block, &rows);
+
+        let (_, bottom) = block.bounds();
+        let last = rows.get(bottom.line - top.line).map_or(0, |row| row.chars().count());
+        self.cursor.exit_block_mode();
+        let new_pos = self.buffer.line_col_to_char(bottom.line, top.col + last);
+        self.cursor.set_position(new_pos, false);
+
+        self.finish_edit();
+        self.scroll_to_cursor();
+    }
+
+    /// Replaces the text the block selects on each line with the matching
+    /// entry of `rows`, top line first; lines past the end of `rows` are
+    /// just cleared. Returns the top-left corner of the block.
+    fn replace_block_rows(&mut self, block: BlockSelection, rows: &[&str]) -> Position {
         let (top, bottom) = block.bounds();
-        let insert_col = top.col;
 
-        // Insert from bottom to top to preserve positions
+        // Edit from bottom to top to preserve positions
         for line_num in (top.line..=bottom.line).rev() {
-            let line_len = self.buffer.line_len_chars(line_num);
-            let actual_col = insert_col.min(line_len);
+            let Some((start_col, end_col)) = block.col_range(&self.buffer, line_num) else {
+                continue;
+            };
             let line_start = self.buffer.line_start(line_num);
-            let insert_pos = line_start + actual_col;
+            let start_pos = line_start + start_col;
+            let end_pos = line_start + end_col;
+
+            if start_pos < end_pos {
+                let deleted: String = (start_pos..end_pos).filter_map(|i| self.buffer.char_at(i)).collect();
+                self.buffer.remove(start_pos, end_pos);
+                self.record_edit(EditOperation::Delete {
+                    position: start_pos,
+                    text: deleted,
+                });
+            }
 
-            self.buffer.insert(insert_pos, text);
-            self.history.record(EditOperation::Insert {
-                position: insert_pos,
-                text: text.to_string(),
-            });
+            let text = rows.get(line_num - top.line).copied().unwrap_or("");
+            if !text.is_empty() {
+                self.buffer.insert(start_pos, text);
+                self.record_edit(EditOperation::Insert {
+                    position: start_pos,
+                    text: text.to_string(),
+                });
+            }
         }
 
-        // Exit block mode and move cursor
-        self.cursor.exit_block_mode();
-        let new_pos = self.buffer.line_col_to_char(top.line, insert_col + text.chars().count());
-        self.cursor.set_position(new_pos, false);
-
-        self.finish_edit();
-        self.scroll_to_cursor();
+        top
     }
 
     // ==================== Multi-Cursor ====================
@@ -1507,40 +3348,112 @@ impl Editor {
     /// Syncs the primary cursor position to multi_cursors.
     /// Call this before transitioning to multi-cursor mode.
     fn sync_cursor_to_multi(&mut self) {
-        // Set the multi_cursors primary position to match the main cursor
-        self.multi_cursors.set_position(self.cursor.position(), false);
-    }
+        // Set the multi_cursors primary to match the main cursor, selection
+        // included
+        self.multi_cursors.collapse_to_primary();
+        *self.multi_cursors.primary_mut() = self.cursor.clone();
+    }
+
+    /// Applies an edit at every cursor as one undo step.
+    ///
+    /// `edit` returns the range to replace and its replacement for a
+    /// cursor's selection, or `None` to leave that cursor alone. Each cursor
+    /// ends up after its replacement text.
+    fn edit_each_cursor<F>(&mut self, mut edit: F)
+    where
+        F: FnMut(&Self, Selection) -> Option<(usize, usize, String)>,
+    {
+        // Movement only updates the main cursor
+        *self.multi_cursors.primary_mut() = self.cursor.clone();
+        self.multi_cursors.normalize();
+        let edits: Vec<_> = self.multi_cursors.iter().map(|c| edit(self, c.selection)).collect();
 
-    /// Adds a cursor above the current cursor position.
-    pub fn add_cursor_above(&mut self) {
-        // Sync primary cursor before adding new cursor
-        if self.multi_cursors.is_single() {
-            self.sync_cursor_to_multi();
+        self.begin_edit();
+        // Edit from the end so the ranges before stay valid
+        for (start, end, text) in edits.iter().rev().flatten() {
+            if end > start {
+                let deleted: String = (*start..*end).filter_map(|i| self.buffer.char_at(i)).collect();
+                self.buffer.remove(*start, *end);
+                self.record_edit(EditOperation::Delete {
+                    position: *start,
+                    text: deleted,
+                });
+            }
+            if !text.is_empty() {
+                self.buffer.insert(*start, text);
+                self.record_edit(EditOperation::Insert {
+                    position: *start,
+                    text: text.clone(),
+                });
+            }
         }
 
-        let (line, col) = self.buffer.char_to_line_col(self.cursor.position());
-        if line > 0 {
-            let new_line = line - 1;
-            let new_col = col.min(self.buffer.line_len_chars(new_line));
-            self.multi_cursors.add_cursor_at(&self.buffer, new_line, new_col);
-            self.scroll_to_cursor();
+        // Shift each cursor by the length change of the edits before it
+        let mut shift = 0isize;
+        for (cursor, edit) in self.multi_cursors.iter_mut().zip(&edits) {
+            let pos = match edit {
+                Some((start, end, text)) => {
+                    let len = text.chars().count();
+                    let pos = (*start as isize + shift) as usize + len;
+                    shift += len as isize - (end - start) as isize;
+                    pos
+                }
+                None => (cursor.position() as isize + shift) as usize,
+            };
+            cursor.set_position(pos, false);
         }
+        // Cursors that ended up in the same place become one
+        self.multi_cursors.normalize();
+        self.cursor = self.multi_cursors.primary().clone();
+
+        self.finish_edit();
+        self.scroll_to_cursor();
+    }
+
+    /// Adds a cursor on the line above the topmost cursor, so each press
+    /// extends the column of cursors by one line.
+    pub fn add_cursor_above(&mut self) {
+        self.add_cursor_vertically(false);
     }
 
-    /// Adds a cursor below the current cursor position.
+    /// Adds a cursor on the line below the bottommost cursor, so each press
+    /// extends the column of cursors by one line.
     pub fn add_cursor_below(&mut self) {
-        // Sync primary cursor before adding new cursor
+        self.add_cursor_vertically(true);
+    }
+
+    /// Adds a cursor past the outermost cursor in the given direction.
+    ///
+    /// The new cursor aims for the outermost cursor's goal column, so a
+    /// column started on a long line stays at its column past short ones.
+    fn add_cursor_vertically(&mut self, down: bool) {
+        // Movement only updates the main cursor
         if self.multi_cursors.is_single() {
             self.sync_cursor_to_multi();
+        } else {
+            *self.multi_cursors.primary_mut() = self.cursor.clone();
         }
-
-        let (line, col) = self.buffer.char_to_line_col(self.cursor.position());
-        if line + 1 < self.buffer.len_lines() {
-            let new_line = line + 1;
-            let new_col = col.min(self.buffer.line_len_chars(new_line));
-            self.multi_cursors.add_cursor_at(&self.buffer, new_line, new_col);
-            self.scroll_to_cursor();
+        let edge = if down { self.multi_cursors.iter().last() } else { self.multi_cursors.iter().next() };
+        let Some((edge, goal)) = edge.map(|c| (c.position(), c.goal_col(&self.buffer))) else {
+            return;
+        };
+        let (line, _) = self.buffer.char_to_line_col(edge);
+        let new_line = if down {
+            Some(line + 1).filter(|&l| l < self.buffer.len_lines())
+        } else {
+            line.checked_sub(1)
+        };
+        let Some(new_line) = new_line else {
+            return;
+        };
+        if let Some(cursor) = self.multi_cursors.iter_mut().find(|c| c.position() == edge) {
+            cursor.set_preferred_col(goal);
+        }
+        if self.cursor.position() == edge {
+            self.cursor.set_preferred_col(goal);
         }
+        self.multi_cursors.add_cursor_at_goal(&self.buffer, new_line, goal);
+        self.scroll_to_cursor();
     }
 
     /// Adds a cursor at the specified line and column.
@@ -1552,24 +3465,160 @@ impl Editor {
         self.multi_cursors.add_cursor_at(&self.buffer, line, col);
     }
 
-    /// Collapses all cursors to the primary cursor.
+    /// Adds a cursor at the given line and column, or removes the cursor
+    /// that is already there. The only cursor is never removed.
+    /// Returns true if a cursor was added.
+    pub fn toggle_cursor_at(&mut self, line: usize, col: usize) -> bool {
+        // Movement only updates the main cursor
+        if self.multi_cursors.is_single() {
+            self.sync_cursor_to_multi();
+        } else {
+            *self.multi_cursors.primary_mut() = self.cursor.clone();
+        }
+        let pos = self.buffer.line_col_to_char(line, col);
+        if self.multi_cursors.remove_cursor_at(pos) {
+            self.cursor = self.multi_cursors.primary().clone();
+            return false;
+        }
+        self.multi_cursors.add_cursor(pos)
+    }
+
+    /// Selects the next occurrence of the selection with a new cursor.
+    ///
+    /// Without a selection, selects the word under the cursor instead. The
+    /// search starts after the last selection in the buffer and wraps around
+    /// at the end, skipping occurrences that are already selected. Returns
+    /// false if nothing was selected.
+    pub fn select_next_occurrence(&mut self) -> bool {
+        let Some((start, end)) = self.cursor.selected_range() else {
+            let pos = self.cursor.position();
+            let start = self.buffer.find_word_start(pos, self.word_chars());
+            let end = self.buffer.find_word_end(pos, self.word_chars());
+            if start >= end {
+                return false;
+            }
+            self.cursor.set_position(start, false);
+            self.cursor.set_position(end, true);
+            if self.has_multiple_cursors() {
+                *self.multi_cursors.primary_mut() = self.cursor.clone();
+                self.multi_cursors.normalize();
+            }
+            return true;
+        };
+
+        if self.multi_cursors.is_single() {
+            self.sync_cursor_to_multi();
+        } else {
+            *self.multi_cursors.primary_mut() = self.cursor.clone();
+            self.multi_cursors.normalize();
+        }
+
+        let needle: String = (start..end).filter_map(|i| self.buffer.char_at(i)).collect();
+        let text = self.buffer.to_string();
+        let selected: Vec<(usize, usize)> = self.multi_cursors.iter().map(|c| c.selection.range()).collect();
+        let search_from = selected.iter().map(|&(_, end)| end).max().unwrap_or(end);
+
+        // Character offsets of every occurrence, in buffer order
+        let mut occurrences = Vec::new();
+        let mut chars_before = 0;
+        let mut last_byte = 0;
+        for (byte, _) in text.match_indices(needle.as_str()) {
+            chars_before += text[last_byte..byte].chars().count();
+            last_byte = byte;
+            occurrences.push(chars_before);
+        }
+
+        let len = needle.chars().count();
+        let split = occurrences.partition_point(|&occ| occ < search_from);
+        let next = occurrences[split..]
+            .iter()
+            .chain(&occurrences[..split])
+            .map(|&occ| (occ, occ + len))
+            .find(|range| !selected.contains(range));
+        let Some((occ_start, occ_end)) = next else {
+            return false;
+        };
+
+        // Keep the direction of the original selection
+        let selection = if self.cursor.selection.cursor >= self.cursor.selection.anchor {
+            Selection::with_range(occ_start, occ_end)
+        } else {
+            Selection::with_range(occ_end, occ_start)
+        };
+        self.multi_cursors.add_selection(selection);
+        self.cursor = self.multi_cursors.primary().clone();
+        self.scroll_to_cursor();
+        true
+    }
+
+    /// Puts a cursor with a selection on every occurrence of the selected
+    /// text, or of the word under the cursor if nothing is selected.
+    ///
+    /// At most [`MAX_OCCURRENCE_CURSORS`] cursors are placed. Returns the
+    /// number of occurrences found, which may be more than that.
+    pub fn select_all_occurrences(&mut self) -> usize {
+        if self.cursor.selected_range().is_none() && !self.select_next_occurrence() {
+            return 0;
+        }
+        let Some((start, end)) = self.cursor.selected_range() else {
+            return 0;
+        };
+
+        let needle: String = (start..end).filter_map(|i| self.buffer.char_at(i)).collect();
+        let mut search = Search::new();
+        search.set_case_sensitive(true, &self.buffer);
+        search.set_query(&needle, &self.buffer);
+
+        // Search reports overlapping matches; keep the ones that don't overlap
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for m in search.matches() {
+            if ranges.last().is_none_or(|&(_, last_end)| m.start >= last_end) {
+                ranges.push((m.start, m.end));
+            }
+        }
+        let total = ranges.len();
+        ranges.truncate(MAX_OCCURRENCE_CURSORS);
+
+        // Keep the direction of the original selection and its cursor as
+        // the primary, so the view doesn't jump
+        let forward = self.cursor.selection.cursor >= self.cursor.selection.anchor;
+        let primary = ranges.iter().position(|&range| range == (start, end)).unwrap_or(0);
+        let selections = ranges
+            .into_iter()
+            .map(|(s, e)| if forward { Selection::with_range(s, e) } else { Selection::with_range(e, s) })
+            .collect();
+        self.multi_cursors.set_selections(selections, primary);
+        self.cursor = self.multi_cursors.primary().clone();
+        self.scroll_to_cursor();
+        total
+    }
+
+    /// Collapses all cursors to the primary cursor. This ends filling in a
+    /// snippet, whose mirrored tab stops have a cursor each.
     pub fn collapse_cursors(&mut self) {
         self.multi_cursors.collapse_to_primary();
+        self.snippet = None;
     }
 
     /// Returns all cursor positions for rendering.
     pub fn all_cursor_positions(&self) -> Vec<(usize, usize)> {
+        let mut positions = Vec::new();
+        self.cursor_positions_into(&mut positions);
+        positions
+    }
+
+    /// Fills `positions` with the positions
+    /// [`all_cursor_positions`](Self::all_cursor_positions) returns, reusing
+    /// its memory from frame to frame.
+    pub fn cursor_positions_into(&self, positions: &mut Vec<(usize, usize)>) {
+        positions.clear();
         // When there's only one cursor, use the primary cursor (self.cursor)
         // which is kept in sync with editing operations
         if self.multi_cursors.is_single() {
-            vec![self.buffer.char_to_line_col(self.cursor.position())]
+            positions.push(self.buffer.char_to_line_col(self.cursor.position()));
         } else {
             // Multi-cursor mode: use positions from multi_cursors
-            self.multi_cursors
-                .positions()
-                .iter()
-                .map(|&pos| self.buffer.char_to_line_col(pos))
-                .collect()
+            positions.extend(self.multi_cursors.iter().map(|c| self.buffer.char_to_line_col(c.position())));
         }
     }
 
@@ -1583,6 +3632,23 @@ impl Editor {
         }
     }
 
+    /// Fills `ranges` with the selected ranges overlapping lines
+    /// `start_line` to `end_line`, in order, for drawing only the visible
+    /// ones. `ranges` is reused from frame to frame.
+    pub fn selection_ranges_in_range(&self, start_line: usize, end_line: usize, ranges: &mut Vec<(usize, usize)>) {
+        ranges.clear();
+        if self.multi_cursors.is_single() {
+            ranges.extend(self.cursor.selected_range());
+        } else {
+            ranges.extend(self.multi_cursors.iter().filter_map(Cursor::selected_range));
+        }
+        ranges.sort_unstable();
+        let window = self.buffer.line_start(start_line)..self.buffer.line_start(end_line + 1);
+        let visible = viewport::overlapping_indices(ranges, window, |&(start, end)| start..end);
+        ranges.truncate(visible.end);
+        ranges.drain(..visible.start);
+    }
+
     // ==================== Syntax Highlighting ====================
 
     /// Returns a reference to the syntax highlighter.
@@ -1607,11 +3673,18 @@ impl Editor {
     }
 
     /// Re-parses the entire buffer for syntax highlighting.
-    /// Call this when the buffer content changes significantly.
+    /// Call this when the buffer content changes significantly. Text and
+    /// language the tree was already parsed from only rebuild the cache.
     pub fn reparse_syntax(&mut self) {
+        let parsed = (self.content_hash(), self.language());
+        if self.parsed == Some(parsed) {
+            self.update_syntax_cache();
+            return;
+        }
         let source = self.buffer.to_string();
         self.highlighter.parse(&source);
         self.highlighter.build_line_cache(&source, self.buffer.len_lines());
+        self.parsed = Some(parsed);
     }
 
     /// Updates the syntax highlighting cache if needed.
@@ -1635,6 +3708,12 @@ impl Editor {
         self.highlighter.color_at(line, col)
     }
 
+    /// Returns the bracket-pair color at a position, if a colorized bracket
+    /// is there.
+    pub fn bracket_color_at(&self, line: usize, col: usize) -> Option<[f32; 4]> {
+        self.highlighter.bracket_color_at(line, col)
+    }
+
     /// Returns true if syntax highlighting is available.
     pub fn has_syntax_highlighting(&self) -> bool {
         self.highlighter.has_highlighting()
@@ -1661,6 +3740,60 @@ impl Editor {
     /// Clears the current search.
     pub fn clear_search(&mut self) {
         self.search.clear();
+        self.search.adjust_ranges(Vec::new());
+    }
+
+    /// Restricts search and replace to the current selection.
+    ///
+    /// With multiple cursors every selected range becomes an independent
+    /// region and the cursors collapse to the primary one, so moving between
+    /// matches doesn't leave the other cursors behind.
+    /// Returns false (and leaves the search unrestricted) if nothing is selected.
+    pub fn set_search_in_selection(&mut self) -> bool {
+        if self.has_multiple_cursors() {
+            *self.multi_cursors.primary_mut() = self.cursor.clone();
+            self.multi_cursors.normalize();
+            let regions: Vec<_> = self
+                .multi_cursors
+                .iter()
+                .filter_map(|cursor| cursor.selected_range())
+                .collect();
+            if regions.is_empty() {
+                return false;
+            }
+            self.collapse_cursors();
+            self.set_search_regions(regions);
+            return true;
+        }
+        let Some(range) = self.cursor.selected_range() else {
+            return false;
+        };
+        self.search.set_range(Some(range), &self.buffer);
+        true
+    }
+
+    /// Restricts search and replace to the given character ranges, each
+    /// searched on its own. Matches spanning two ranges aren't found.
+    pub fn set_search_regions(&mut self, regions: Vec<(usize, usize)>) {
+        self.search.set_ranges(regions, &self.buffer);
+    }
+
+    /// Lifts the search-in-selection restriction, if any.
+    pub fn clear_search_in_selection(&mut self) {
+        if !self.search.ranges().is_empty() {
+            self.search.set_ranges(Vec::new(), &self.buffer);
+        }
+    }
+
+    /// Returns true if search is restricted to a selection.
+    pub fn is_search_in_selection(&self) -> bool {
+        !self.search.ranges().is_empty()
+    }
+
+    /// Returns the number of regions search is restricted to; 0 if it covers
+    /// the whole buffer.
+    pub fn search_region_count(&self) -> usize {
+        self.search.ranges().len()
     }
 
     /// Returns true if there is an active search.
@@ -1700,7 +3833,7 @@ impl Editor {
     }
 
     /// Returns the current search matches visible in the given line range.
-    pub fn search_matches_in_range(&self, start_line: usize, end_line: usize) -> Vec<SearchMatch> {
+    pub fn search_matches_in_range(&self, start_line: usize, end_line: usize) -> &[SearchMatch] {
         self.search.matches_in_range(&self.buffer, start_line, end_line)
     }
 
@@ -1714,15 +3847,24 @@ impl Editor {
         self.search.current_match()
     }
 
-    /// Returns search status string like "1 of 5".
+    /// Returns search status string like "1 of 5", or "5 matches in 3
+    /// selections" when search is restricted to several regions.
     pub fn search_status(&self) -> Option<String> {
         if !self.search.is_active() {
             return None;
         }
+        if self.search.error().is_some() {
+            return Some("Invalid regex".to_string());
+        }
         let count = self.search.match_count();
         if count == 0 {
             return Some("No results".to_string());
         }
+        let regions = self.search_region_count();
+        if regions > 1 {
+            let noun = if count == 1 { "match" } else { "matches" };
+            return Some(format!("{} {} in {} selections", count, noun, regions));
+        }
         if let Some(current) = self.search.current_match_index() {
             Some(format!("{} of {}", current, count))
         } else {
@@ -1739,65 +3881,136 @@ impl Editor {
         }
     }
 
+    /// Toggles regex mode for search.
+    pub fn toggle_search_regex(&mut self) {
+        self.search.toggle_regex(&self.buffer);
+        if let Some(match_) = self.search.find_nearest(self.cursor.position()) {
+            self.jump_to_match(match_);
+        }
+    }
+
+    /// Toggles case-preserving replacement for search/replace.
+    pub fn toggle_search_preserve_case(&mut self) {
+        self.search.toggle_preserve_case();
+    }
+
     /// Replaces the current search match with the given replacement text.
+    /// In regex mode, `$1`/`${name}` in the replacement refer to capture groups.
     /// Returns true if a replacement was made.
     pub fn replace_current(&mut self, replacement: &str) -> bool {
-        let Some(match_) = self.search.current_match() else {
-            return false;
-        };
+        self.replace_matches_from_current(replacement, 1) > 0
+    }
+
+    /// Replaces the current match and the `count - 1` matches after it, as a
+    /// single undo step. Each step continues from the end of the inserted
+    /// text (wrapping to the start of the buffer), so a replacement that
+    /// contains the query is not matched again. Afterwards the next match, if
+    /// any, is selected.
+    /// Returns the number of replacements made.
+    pub fn replace_matches_from_current(&mut self, replacement: &str, count: usize) -> usize {
+        if count == 0 || self.search.current_match().is_none() {
+            return 0;
+        }
 
         self.begin_edit();
 
-        // Delete the match text
-        let mut deleted = String::new();
-        for i in match_.start..match_.end {
-            if let Some(ch) = self.buffer.char_at(i) {
-                deleted.push(ch);
+        let mut replaced = 0;
+        while replaced < count {
+            let Some(match_) = self.search.current_match() else {
+                break;
+            };
+            // The match and a character either side, for anchors to look at
+            let window_start = match_.start.saturating_sub(1);
+            let window = self.buffer.slice(window_start, match_.end + 1);
+            let byte = |chars: usize| window.char_indices().nth(chars).map_or(window.len(), |(i, _)| i);
+            let matched = byte(match_.start - window_start)..byte(match_.end - window_start);
+            let text = self.search.expand_replacement(&window, matched, replacement);
+
+            // Delete the match text
+            let mut deleted = String::new();
+            for i in match_.start..match_.end {
+                if let Some(ch) = self.buffer.char_at(i) {
+                    deleted.push(ch);
+                }
             }
-        }
-        self.buffer.remove(match_.start, match_.end);
-        self.history.record(EditOperation::Delete {
-            position: match_.start,
-            text: deleted,
-        });
+            self.buffer.remove(match_.start, match_.end);
+            self.record_edit(EditOperation::Delete {
+                position: match_.start,
+                text: deleted,
+            });
 
-        // Insert the replacement
-        self.buffer.insert(match_.start, replacement);
-        self.history.record(EditOperation::Insert {
-            position: match_.start,
-            text: replacement.to_string(),
-        });
+            // Insert the replacement
+            self.buffer.insert(match_.start, &text);
+            let end = match_.start + text.chars().count();
+            self.record_edit(EditOperation::Insert {
+                position: match_.start,
+                text,
+            });
+            replaced += 1;
 
-        // Move cursor after the replacement
-        self.cursor.set_position(match_.start + replacement.chars().count(), false);
+            // Move cursor after the replacement
+            self.cursor.set_position(end, false);
 
-        self.finish_edit();
+            // Refresh matches and continue after the inserted text
+            self.search.refresh(&self.buffer);
+            self.search.find_nearest(end);
+        }
 
-        // Refresh search to update matches
-        self.search.refresh(&self.buffer);
+        self.finish_edit();
 
-        // Jump to next match if available
+        // Select the next match if available
         if let Some(next) = self.search.current_match() {
             self.jump_to_match(next);
         }
 
-        true
+        replaced
     }
 
     /// Replaces all search matches with the given replacement text.
+    /// In regex mode each match is expanded with its own capture groups.
+    ///
+    /// When search is restricted to several regions, the regions are kept
+    /// covering their replaced text and a cursor is placed at the start of
+    /// each one.
     /// Returns the number of replacements made.
     pub fn replace_all(&mut self, replacement: &str) -> usize {
-        let matches: Vec<_> = self.search.matches().to_vec();
-        if matches.is_empty() {
+        let replacements = self.search.replacements(&self.buffer, replacement);
+        if replacements.is_empty() {
             return 0;
         }
 
+        // New bounds of each region: shifted by the replacements before it
+        // and grown by the ones inside it. Worked out up front, since regions
+        // can be adjacent and an edit at their border belongs to just one.
+        let regions: Vec<(usize, usize)> = self
+            .search
+            .ranges()
+            .iter()
+            .map(|&(lo, hi)| {
+                let delta = |keep: &dyn Fn(&SearchMatch) -> bool| -> isize {
+                    replacements
+                        .iter()
+                        .filter(|(m, _)| keep(m))
+                        .map(|(m, text)| text.chars().count() as isize - m.len() as isize)
+                        .sum()
+                };
+                let before = delta(&|m| m.end <= lo && m.start < lo);
+                let inside = delta(&|m| m.start >= lo && m.end <= hi);
+                ((lo as isize + before) as usize, (hi as isize + before + inside) as usize)
+            })
+            .collect();
+        let primary = self
+            .search
+            .ranges()
+            .iter()
+            .position(|&(lo, hi)| (lo..=hi).contains(&self.cursor.position()))
+            .unwrap_or(0);
+
         self.begin_edit();
 
-        let replacement_char_count = replacement.chars().count();
         let mut offset: isize = 0;
 
-        for m in &matches {
+        for (m, replacement) in &replacements {
             // Adjust position based on previous replacements
             let adjusted_start = (m.start as isize + offset) as usize;
             let adjusted_end = (m.end as isize + offset) as usize;
@@ -1810,23 +4023,30 @@ impl Editor {
                 }
             }
             self.buffer.remove(adjusted_start, adjusted_end);
-            self.history.record(EditOperation::Delete {
+            self.record_edit(EditOperation::Delete {
                 position: adjusted_start,
                 text: deleted,
             });
 
             // Insert the replacement
             self.buffer.insert(adjusted_start, replacement);
-            self.history.record(EditOperation::Insert {
+            self.record_edit(EditOperation::Insert {
                 position: adjusted_start,
-                text: replacement.to_string(),
+                text: replacement.clone(),
             });
 
             // Update offset for subsequent replacements
-            offset += replacement_char_count as isize - m.len() as isize;
+            offset += replacement.chars().count() as isize - m.len() as isize;
         }
 
-        let count = matches.len();
+        let count = replacements.len();
+
+        if regions.len() > 1 {
+            self.search.adjust_ranges(regions.clone());
+            let cursors = regions.iter().map(|&(start, _)| Selection::new(start)).collect();
+            self.multi_cursors.set_selections(cursors, primary);
+            self.cursor = self.multi_cursors.primary().clone();
+        }
 
         self.finish_edit();
 
@@ -1836,6 +4056,53 @@ impl Editor {
         count
     }
 
+    /// Replaces every literal occurrence of `query` on the given lines as a
+    /// single undo step, e.g. for project-wide replace.
+    /// Returns the number of replacements made.
+    pub fn replace_on_lines(&mut self, lines: &[usize], query: &str, replacement: &str) -> usize {
+        if query.is_empty() {
+            return 0;
+        }
+        let mut lines = lines.to_vec();
+        lines.sort_unstable();
+        lines.dedup();
+
+        let query_len = query.chars().count();
+        let mut ranges = Vec::new();
+        for line in lines {
+            let Some(text) = self.buffer.line(line) else {
+                continue;
+            };
+            let line_start = self.buffer.line_start(line);
+            for (byte, _) in text.match_indices(query) {
+                let start = line_start + text[..byte].chars().count();
+                ranges.push((start, start + query_len));
+            }
+        }
+        if ranges.is_empty() {
+            return 0;
+        }
+
+        self.begin_edit();
+        // Back to front, so earlier ranges stay valid
+        for &(start, end) in ranges.iter().rev() {
+            let deleted: String = (start..end).filter_map(|i| self.buffer.char_at(i)).collect();
+            self.buffer.remove(start, end);
+            self.record_edit(EditOperation::Delete {
+                position: start,
+                text: deleted,
+            });
+            self.buffer.insert(start, replacement);
+            self.record_edit(EditOperation::Insert {
+                position: start,
+                text: replacement.to_string(),
+            });
+        }
+        self.cursor.clamp_to_buffer(&self.buffer);
+        self.finish_edit();
+        ranges.len()
+    }
+
     // ==================== Go to Line ====================
 
     /// Moves the cursor to the specified line number (1-based).
@@ -1886,24 +4153,103 @@ impl Editor {
         self.document_version += 1;
     }
 
+    /// Returns when the text was last edited, saved, sent to the language
+    /// server and given diagnostics.
+    pub fn times(&self) -> &BufferTimes {
+        &self.times
+    }
+
+    /// Records that the text was just sent to the language server.
+    pub fn mark_change_sent(&mut self) {
+        self.times.last_change_sent = Some(Instant::now());
+        self.sent_hash = Some(self.content_hash());
+    }
+
+    /// Returns whether the text differs from what was last sent to the
+    /// language server, e.g. false after typing a character and deleting it.
+    pub fn has_unsent_changes(&mut self) -> bool {
+        Some(self.content_hash()) != self.sent_hash
+    }
+
+    /// Records that the language server just published diagnostics.
+    pub fn mark_diagnostics_published(&mut self) {
+        self.times.last_diagnostics = Some(Instant::now());
+    }
+
+    /// Recomputes whether the diagnostics are stale: older than the last
+    /// edit by more than `threshold` without answering it. Returns true if
+    /// that changed.
+    pub fn update_diagnostics_staleness(&mut self, threshold: Duration) -> bool {
+        let stale = self.times.diagnostics_stale(threshold);
+        let changed = stale != self.diagnostics_stale;
+        self.diagnostics_stale = stale;
+        changed
+    }
+
+    /// Returns whether the diagnostics are stale, as of the last
+    /// [`Editor::update_diagnostics_staleness`].
+    pub fn diagnostics_stale(&self) -> bool {
+        self.diagnostics_stale
+    }
+
     /// Sets the diagnostics for this buffer.
     pub fn set_diagnostics(&mut self, diagnostics: Vec<Diagnostic>) {
         self.diagnostics = diagnostics;
     }
 
-    /// Returns the diagnostics for this buffer.
+    /// Replaces the diagnostics tagged with `source`, keeping the others.
+    /// Used for linters that run alongside (or instead of) a language server.
+    pub fn merge_diagnostics(&mut self, source: &str, diagnostics: Vec<Diagnostic>) {
+        self.diagnostics.retain(|d| d.source.as_deref() != Some(source));
+        self.diagnostics.extend(diagnostics);
+    }
+
+    /// Returns all diagnostics for this buffer, ignoring the display filter.
     pub fn diagnostics(&self) -> &[Diagnostic] {
         &self.diagnostics
     }
 
-    /// Returns diagnostics for a specific line.
+    /// Sets the least severe diagnostic level to display.
+    /// Hidden diagnostics are kept and remain available via `diagnostics()`.
+    pub fn set_diagnostic_display_min(&mut self, severity: DiagnosticSeverity) {
+        self.diagnostic_display_min = severity;
+    }
+
+    /// Returns the least severe diagnostic level that is displayed.
+    pub fn diagnostic_display_min(&self) -> DiagnosticSeverity {
+        self.diagnostic_display_min
+    }
+
+    /// Returns the diagnostics that pass the display filter.
+    pub fn visible_diagnostics(&self) -> impl Iterator<Item = &Diagnostic> {
+        let min = self.diagnostic_display_min;
+        self.diagnostics.iter().filter(move |d| d.severity.is_at_least(min))
+    }
+
+    /// Returns the number of displayed diagnostics with the given severity.
+    pub fn visible_diagnostic_count(&self, severity: DiagnosticSeverity) -> usize {
+        self.visible_diagnostics().filter(|d| d.severity == severity).count()
+    }
+
+    /// Fills `found` with the indices in [`diagnostics`](Self::diagnostics)
+    /// of the displayed diagnostics touching lines `start_line` to
+    /// `end_line`. `found` is reused from frame to frame.
+    pub fn diagnostics_in_range(&self, start_line: usize, end_line: usize, found: &mut Vec<usize>) {
+        let min = self.diagnostic_display_min;
+        found.clear();
+        found.extend(self.diagnostics.iter().enumerate().filter_map(|(i, d)| {
+            (d.severity.is_at_least(min) && d.start_line <= end_line && d.end_line >= start_line).then_some(i)
+        }));
+    }
+
+    /// Returns displayed diagnostics for a specific line.
     pub fn diagnostics_on_line(&self, line: usize) -> Vec<&Diagnostic> {
-        self.diagnostics.iter().filter(|d| d.on_line(line)).collect()
+        self.visible_diagnostics().filter(|d| d.on_line(line)).collect()
     }
 
-    /// Returns the diagnostic at the given position, if any.
+    /// Returns the displayed diagnostic at the given position, if any.
     pub fn diagnostic_at(&self, line: usize, col: usize) -> Option<&Diagnostic> {
-        self.diagnostics.iter().find(|d| d.contains(line, col))
+        self.visible_diagnostics().find(|d| d.contains(line, col))
     }
 
     /// Clears all diagnostics.
@@ -1926,9 +4272,39 @@ impl Editor {
         self.hover_info = None;
     }
 
+    /// Sets the signature help.
+    pub fn set_signature_help(&mut self, help: Option<SignatureHelp>) {
+        self.signature_help = help;
+    }
+
+    /// Returns the current signature help.
+    pub fn signature_help(&self) -> Option<&SignatureHelp> {
+        self.signature_help.as_ref()
+    }
+
+    /// Clears the signature help.
+    pub fn clear_signature_help(&mut self) {
+        self.signature_help = None;
+    }
+
     /// Sets the completion items.
     pub fn set_completions(&mut self, items: Vec<CompletionItem>) {
-        self.completions = items;
+        self.completions = items.clone();
+        self.completion_items = items;
+    }
+
+    /// Narrows the completion items to those whose label fuzzy-matches
+    /// `prefix`, best match first. Returns how many match.
+    pub fn filter_completions(&mut self, prefix: &str) -> usize {
+        let mut scored: Vec<(i32, &CompletionItem)> = self
+            .completion_items
+            .iter()
+            .filter_map(|item| fuzzy::score(prefix, &item.label).map(|score| (score, item)))
+            .collect();
+        // Stable, so equal matches keep the server's order
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        self.completions = scored.into_iter().map(|(_, item)| item.clone()).collect();
+        self.completions.len()
     }
 
     /// Returns the current completion items.
@@ -1939,12 +4315,91 @@ impl Editor {
     /// Clears the completion items.
     pub fn clear_completions(&mut self) {
         self.completions.clear();
+        self.completion_items.clear();
     }
 
     /// Returns true if there are active completions.
     pub fn has_completions(&self) -> bool {
         !self.completions.is_empty()
     }
+
+    // ==================== Snippets ====================
+
+    /// Inserts an LSP snippet at the cursor, replacing the selection, and
+    /// selects its first tab stop. Lines after the first get the current
+    /// line's indentation.
+    ///
+    /// Returns the buffer ranges of each tab stop in the order Tab visits
+    /// them, ending with the final cursor position. Tab stops with several
+    /// ranges are mirrored, with a cursor in each.
+    pub fn insert_snippet(&mut self, text: &str) -> Vec<Vec<(usize, usize)>> {
+        self.collapse_cursors();
+        let start = self.cursor.selected_range().map_or(self.cursor.position(), |(start, _)| start);
+        let (line, _) = self.buffer.char_to_line_col(start);
+        let snippet = snippet::parse(text).reindent(&self.get_line_indentation(line), &self.settings.indent.unit());
+
+        self.insert_text(&snippet.text);
+        let stops: Vec<Vec<(usize, usize)>> = snippet
+            .tab_stops
+            .iter()
+            .map(|stop| stop.ranges.iter().map(|&(s, e)| (start + s, start + e)).collect())
+            .collect();
+
+        // Only the final cursor position needs no filling in
+        if stops.len() > 1 {
+            self.snippet = Some(SnippetSession::new(stops.clone()));
+        }
+        self.select_tab_stop(&stops[0]);
+        stops
+    }
+
+    /// Returns true while a snippet's tab stops are being filled in.
+    pub fn has_snippet(&self) -> bool {
+        self.snippet.is_some()
+    }
+
+    /// Stops filling in the snippet; Tab types a tab again.
+    pub fn cancel_snippet(&mut self) {
+        self.snippet = None;
+    }
+
+    /// Selects the snippet's next tab stop. Reaching the final cursor
+    /// position ends the snippet. Returns false, and ends the snippet, if
+    /// there is none or the cursor left the current tab stop.
+    pub fn next_tab_stop(&mut self) -> bool {
+        let Some(session) = self.snippet.as_mut() else {
+            return false;
+        };
+        if !session.contains(self.cursor.position()) {
+            self.snippet = None;
+            return false;
+        }
+        let ranges = session.next_stop().to_vec();
+        if session.is_at_end() {
+            self.snippet = None;
+        }
+        self.select_tab_stop(&ranges);
+        true
+    }
+
+    /// Selects the snippet's previous tab stop. Returns false if there is
+    /// no snippet.
+    pub fn prev_tab_stop(&mut self) -> bool {
+        let Some(session) = self.snippet.as_mut() else {
+            return false;
+        };
+        let ranges = session.prev_stop().to_vec();
+        self.select_tab_stop(&ranges);
+        true
+    }
+
+    /// Selects every range of a tab stop, with the cursor at their ends.
+    fn select_tab_stop(&mut self, ranges: &[(usize, usize)]) {
+        let selections = ranges.iter().map(|&(start, end)| Selection::with_range(start, end)).collect();
+        self.multi_cursors.set_selections(selections, 0);
+        self.cursor = self.multi_cursors.primary().clone();
+        self.scroll_to_cursor();
+    }
 }
 
 #[cfg(test)]
@@ -1952,54 +4407,271 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_insert_and_delete() {
+    fn test_streamed_file_saves_once_loaded() {
+        let path = std::env::temp_dir().join(format!("cp_editor_editor_streaming_{}.txt", std::process::id()));
+        let text = "line\n".repeat(50_000);
+        std::fs::write(&path, &text).unwrap();
+
         let mut editor = Editor::new();
-        
-        editor.insert_text("hello");
-        assert_eq!(editor.buffer().to_string(), "hello");
-        assert_eq!(editor.cursor_char_index(), 5);
-        
-        editor.delete_backward();
-        assert_eq!(editor.buffer().to_string(), "hell");
-        
-        editor.move_left(false);
-        editor.delete_forward();
-        assert_eq!(editor.buffer().to_string(), "hel");
+        editor.open_file_streaming_from(&path, 0).unwrap();
+        assert!(!editor.is_fully_loaded());
+        assert!(editor.loading_progress().unwrap() < 1.0);
+        assert_eq!(editor.save().unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+        while !editor.is_fully_loaded() {
+            editor.poll_loading().unwrap();
+            std::thread::yield_now();
+        }
+        assert_eq!(editor.loading_progress(), None);
+        assert_eq!(editor.buffer().to_string(), text);
+        assert_eq!(editor.buffer().len_lines(), 50_001);
+        assert!(!editor.is_modified());
+        editor.save().unwrap();
+        let _ = std::fs::remove_file(&path);
     }
 
     #[test]
-    fn test_newline() {
+    fn test_load_error_leaves_partial_buffer() {
+        /// Reads a few bytes, then fails like a disk going away.
+        struct FailingReader(bool);
+        impl io::Read for FailingReader {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if std::mem::replace(&mut self.0, true) {
+                    return Err(io::Error::other("device gone"));
+                }
+                buf[..5].copy_from_slice(b"more\n");
+                Ok(5)
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("cp_editor_load_error_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("big.txt");
+        // The first screenful ends at a line break
+        std::fs::write(&path, "abcdefg\n".repeat(20_000)).unwrap();
         let mut editor = Editor::new();
-        editor.insert_text("hello");
-        editor.insert_newline();
-        editor.insert_text("world");
-        
-        assert_eq!(editor.buffer().to_string(), "hello\nworld");
-        assert_eq!(editor.buffer().len_lines(), 2);
+        editor.open_file_streaming_from(&path, 0).unwrap();
+        // ASCII, so characters are bytes
+        let loaded = editor.buffer().len_chars() as u64;
+        editor.loader = Some(BufferLoader::start(FailingReader(false), path.clone(), Vec::new(), loaded, 160_000));
+
+        let error = loop {
+            match editor.poll_loading() {
+                Ok(_) => std::thread::yield_now(),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(error.to_string(), "device gone");
+        // Loading stops, keeping what was read
+        assert!(!editor.is_loading() && !editor.is_fully_loaded());
+        assert_eq!(editor.loading_progress(), None);
+        assert!(editor.buffer().to_string().ends_with("abcdefg\nmore\n"));
+        assert!(!editor.poll_loading().unwrap());
+        // Saving over the file would cut it short; another file is fine
+        assert_eq!(editor.save().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(editor.save_as(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().len(), 160_000);
+        let copy = dir.join("copy.txt");
+        editor.save_as(&copy).unwrap();
+        assert!(std::fs::read_to_string(&copy).unwrap().ends_with("more\n"));
+        // The copy is the whole of its file
+        assert!(editor.is_fully_loaded());
+        editor.save().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn test_cursor_movement() {
+    fn test_convert_line_endings() {
+        let path = std::env::temp_dir().join(format!("cp_editor_editor_line_endings_{}.txt", std::process::id()));
+        std::fs::write(&path, "a\r\nb\r\nc\n").unwrap();
         let mut editor = Editor::new();
-        editor.insert_text("hello\nworld");
-        
-        // Cursor is at end
-        assert_eq!(editor.cursor_char_index(), 11);
-        
-        editor.move_to_line_start(false);
-        assert_eq!(editor.cursor_char_index(), 6);
-        
-        editor.move_up(false);
-        assert_eq!(editor.cursor_position().line, 0);
-        
-        editor.move_to_line_end(false);
-        assert_eq!(editor.cursor_char_index(), 5);
+        editor.open_file(&path).unwrap();
+        assert_eq!(editor.line_ending(), LineEnding::Crlf);
+        assert!(editor.has_mixed_line_endings());
+
+        // Converting a mixed file normalizes it, as one undo step
+        editor.set_cursor_position(2, 1, false);
+        editor.convert_line_endings(LineEnding::Crlf);
+        assert_eq!(editor.buffer().to_string(), "a\nb\nc\n");
+        assert_eq!(editor.cursor_position(), Position::new(2, 1));
+        assert!(editor.is_modified() && !editor.has_mixed_line_endings());
+        editor.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\r\nb\r\nc\r\n");
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "a\r\nb\r\nc\n");
+        editor.redo();
+
+        // Otherwise only the file changes
+        editor.convert_line_endings(LineEnding::Lf);
+        assert_eq!(editor.buffer().to_string(), "a\nb\nc\n");
+        assert!(editor.is_modified());
+        editor.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nb\nc\n");
+        let _ = std::fs::remove_file(&path);
     }
 
     #[test]
-    fn test_selection() {
+    fn test_reopen_with_encoding() {
+        let path = std::env::temp_dir().join(format!("cp_editor_editor_encoding_{}.txt", std::process::id()));
+        std::fs::write(&path, b"one\ntwo\ncaf\xC3\xA9\n").unwrap();
+
         let mut editor = Editor::new();
-        editor.insert_text("hello world");
+        editor.open_file(&path).unwrap();
+        assert_eq!(editor.encoding(), Encoding::Utf8);
+        editor.go_to_line(3);
+        editor.reopen_with_encoding(Encoding::Latin1).unwrap();
+        assert_eq!(editor.encoding(), Encoding::Latin1);
+        assert_eq!(editor.buffer().line(2).unwrap(), "caf\u{c3}\u{a9}");
+        assert_eq!(editor.cursor_position().line, 2);
+        assert!(!editor.is_modified());
+
+        // Saving writes the text back in the chosen encoding
+        editor.save().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"one\ntwo\ncaf\xC3\xA9\n");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_save_keeps_shift_jis() {
+        let path = std::env::temp_dir().join(format!("cp_editor_editor_shift_jis_{}.txt", std::process::id()));
+        // "日本\n" in Shift-JIS
+        std::fs::write(&path, b"\x93\xfa\x96\x7b\n").unwrap();
+
+        let mut editor = Editor::new();
+        editor.open_file(&path).unwrap();
+        assert_eq!(editor.encoding(), Encoding::ShiftJis);
+        assert_eq!(editor.buffer().to_string(), "\u{65e5}\u{672c}\n");
+        editor.insert_text("!");
+        editor.save().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"!\x93\xfa\x96\x7b\n");
+
+        // A character Shift-JIS lacks is written as '?'
+        editor.insert_text("\u{20ac}");
+        let err = editor.check_encodable().unwrap_err();
+        assert_eq!(err.to_string(), "Shift-JIS can't represent '\u{20ac}' (U+20AC)");
+        editor.save().unwrap();
+        assert!(!editor.is_modified());
+        assert_eq!(std::fs::read(&path).unwrap(), b"!?\x93\xfa\x96\x7b\n");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_clean_up_for_save() {
+        let mut editor = Editor::new();
+        editor.set_buffer(TextBuffer::from_str("fn main() {  \n\tx; \t\n}\n\nend"));
+        // Off by default
+        assert!(editor.clean_up_for_save().is_empty());
+
+        editor.set_settings(EditorSettings {
+            trim_trailing_whitespace: true,
+            ensure_final_newline: true,
+            ..editor.settings()
+        });
+        // Cursor in the whitespace removed from line 1
+        editor.go_to_line(2);
+        editor.move_to_line_end(false);
+        let cleanup = editor.clean_up_for_save();
+        assert_eq!(cleanup, SaveCleanup { trimmed_lines: 2, added_final_newline: true });
+        assert_eq!(editor.buffer().to_string(), "fn main() {\n\tx;\n}\n\nend\n");
+        assert_eq!(editor.cursor_position(), Position::new(1, 3));
+        assert!(editor.is_modified());
+        assert!(editor.clean_up_for_save().is_empty());
+
+        // One undo brings the whitespace back
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "fn main() {  \n\tx; \t\n}\n\nend");
+    }
+
+    #[test]
+    fn test_reload_from_disk() {
+        let path = std::env::temp_dir().join(format!("cp_editor_editor_reload_{}.txt", std::process::id()));
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+
+        let mut editor = Editor::new();
+        editor.open_file(&path).unwrap();
+        assert!(!editor.disk_changed());
+        editor.go_to_line(2);
+        editor.insert_text("mine ");
+
+        // Sizes differ, so the change shows even with coarse timestamps
+        std::fs::write(&path, "one\r\ntwo\r\nthree\r\n").unwrap();
+        assert!(editor.disk_changed());
+        editor.raise_disk_conflict();
+        assert!(editor.has_disk_conflict());
+
+        // Keeping the edits dismisses the conflict until the next change
+        editor.keep_local_changes();
+        assert!(!editor.has_disk_conflict());
+        assert!(!editor.disk_changed());
+        assert!(editor.is_modified());
+
+        std::fs::write(&path, "one\r\nTWO\r\n").unwrap();
+        assert!(editor.disk_changed());
+        editor.raise_disk_conflict();
+        editor.reload_from_disk().unwrap();
+        assert_eq!(editor.buffer().to_string(), "one\nTWO\n");
+        assert_eq!(editor.line_ending(), LineEnding::Crlf);
+        assert_eq!(editor.cursor_position().line, 1);
+        assert!(!editor.is_modified());
+        assert!(!editor.has_disk_conflict());
+        assert!(!editor.disk_changed());
+
+        // The discarded edits come back with undo
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "one\nmine two\n");
+        assert!(editor.is_modified());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        let mut editor = Editor::new();
+        
+        editor.insert_text("hello");
+        assert_eq!(editor.buffer().to_string(), "hello");
+        assert_eq!(editor.cursor_char_index(), 5);
+        
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "hell");
+        
+        editor.move_left(false);
+        editor.delete_forward();
+        assert_eq!(editor.buffer().to_string(), "hel");
+    }
+
+    #[test]
+    fn test_newline() {
+        let mut editor = Editor::new();
+        editor.insert_text("hello");
+        editor.insert_newline();
+        editor.insert_text("world");
+        
+        assert_eq!(editor.buffer().to_string(), "hello\nworld");
+        assert_eq!(editor.buffer().len_lines(), 2);
+    }
+
+    #[test]
+    fn test_cursor_movement() {
+        let mut editor = Editor::new();
+        editor.insert_text("hello\nworld");
+        
+        // Cursor is at end
+        assert_eq!(editor.cursor_char_index(), 11);
+        
+        editor.move_to_line_start(false);
+        assert_eq!(editor.cursor_char_index(), 6);
+        
+        editor.move_up(false);
+        assert_eq!(editor.cursor_position().line, 0);
+        
+        editor.move_to_line_end(false);
+        assert_eq!(editor.cursor_char_index(), 5);
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut editor = Editor::new();
+        editor.insert_text("hello world");
         
         editor.move_to_buffer_start(false);
         editor.move_right(true);
@@ -2079,6 +4751,30 @@ mod tests {
         assert!(!editor.is_block_selection_mode());
     }
 
+    #[test]
+    fn test_move_block_corner() {
+        let mut editor = Editor::new();
+        editor.insert_text("abcd\nef\nijkl");
+        editor.set_cursor_position(0, 1, false);
+
+        // Down, then right, starting the block at the cursor
+        editor.move_block_corner(1, 0);
+        editor.move_block_corner(1, 0);
+        editor.move_block_corner(0, 1);
+        editor.move_block_corner(0, 1);
+        let block = *editor.get_block_selection().unwrap();
+        assert_eq!(block.bounds(), (Position::new(0, 1), Position::new(2, 3)));
+        assert_eq!(editor.block_selected_text().unwrap(), ["bc", "f", "jk"]);
+        // The regular cursor follows the corner
+        assert_eq!(editor.cursor_position(), Position::new(2, 3));
+
+        // The corner stops at the last line and the longest line's end
+        editor.move_block_corner(5, 5);
+        assert_eq!(editor.get_block_selection().unwrap().cursor, Position::new(2, 4));
+        editor.move_block_corner(-5, -5);
+        assert_eq!(editor.get_block_selection().unwrap().cursor, Position::new(0, 0));
+    }
+
     #[test]
     fn test_block_selection_delete() {
         let mut editor = Editor::new();
@@ -2096,4 +4792,1513 @@ mod tests {
         assert_eq!(editor.buffer().to_string(), "ad\neh\nil");
         assert!(!editor.is_block_selection_mode());
     }
+
+    #[test]
+    fn test_block_selection_typing() {
+        let mut editor = Editor::new();
+        editor.insert_text("abcd\nef\nijkl");
+
+        // Select "bc" on each line; the middle line only has "f"
+        editor.move_to_buffer_start(false);
+        editor.move_right(false);
+        editor.start_block_selection();
+        editor.extend_block_selection(2, 3);
+
+        // Typing replaces the block and leaves a column of cursors
+        editor.insert_text_at_block("x");
+        assert!(!editor.is_block_selection_mode());
+        assert_eq!(editor.cursor_count(), 3);
+        assert_eq!(editor.cursor_position(), Position::new(2, 2));
+        editor.insert_char('y');
+        assert_eq!(editor.buffer().to_string(), "axyd\nexy\nixyl");
+
+        // The typing after the replacement is one undo step
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "axd\nex\nixl");
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "abcd\nef\nijkl");
+    }
+
+    #[test]
+    fn test_block_selection_edits_every_line() {
+        let text = "abcd\nefgh\nijkl";
+        let select_column = |editor: &mut Editor| {
+            editor.set_cursor_position(2, 2, false);
+            editor.start_block_selection();
+            editor.extend_block_selection(0, 2);
+            editor.block_to_cursors();
+        };
+
+        let mut editor = Editor::new();
+        editor.insert_text(text);
+        select_column(&mut editor);
+        assert_eq!(editor.all_cursor_positions(), vec![(0, 2), (1, 2), (2, 2)]);
+        // The block's cursor line keeps the primary cursor
+        assert_eq!(editor.cursor_position(), Position::new(0, 2));
+
+        editor.insert_char('x');
+        editor.insert_char('y');
+        assert_eq!(editor.buffer().to_string(), "abxycd\nefxygh\nijxykl");
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), text);
+
+        select_column(&mut editor);
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "acd\negh\nikl");
+        assert_eq!(editor.cursor_count(), 3);
+        editor.undo();
+
+        select_column(&mut editor);
+        editor.insert_newline();
+        assert_eq!(editor.buffer().to_string(), "ab\ncd\nef\ngh\nij\nkl");
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), text);
+    }
+
+    #[test]
+    fn test_block_selection_paste() {
+        let text = "abc\ndef\nghi";
+        let select_column = |editor: &mut Editor, lines: usize| {
+            editor.set_cursor_position(0, 1, false);
+            editor.start_block_selection();
+            editor.extend_block_selection(lines - 1, 1);
+        };
+
+        // One clipboard line per selected line
+        let mut editor = Editor::new();
+        editor.insert_text(text);
+        select_column(&mut editor, 3);
+        editor.paste_block("1\n22\n333\n");
+        assert_eq!(editor.buffer().to_string(), "a1bc\nd22ef\ng333hi");
+        assert!(!editor.is_block_selection_mode());
+        assert_eq!(editor.cursor_position(), Position::new(2, 4));
+
+        // Extra clipboard lines are dropped
+        let mut editor = Editor::new();
+        editor.insert_text(text);
+        select_column(&mut editor, 2);
+        editor.paste_block("1\r\n2\r\n3");
+        assert_eq!(editor.buffer().to_string(), "a1bc\nd2ef\nghi");
+
+        // Lines without a clipboard line get nothing
+        let mut editor = Editor::new();
+        editor.insert_text(text);
+        select_column(&mut editor, 3);
+        editor.paste_block("1\n2");
+        assert_eq!(editor.buffer().to_string(), "a1bc\nd2ef\nghi");
+        assert_eq!(editor.cursor_position(), Position::new(2, 1));
+
+        // A single line goes to every line
+        let mut editor = Editor::new();
+        editor.insert_text(text);
+        select_column(&mut editor, 3);
+        editor.paste_block("-");
+        assert_eq!(editor.buffer().to_string(), "a-bc\nd-ef\ng-hi");
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), text);
+    }
+
+    #[test]
+    fn test_regex_replace_all_with_captures() {
+        let mut editor = Editor::new();
+        editor.insert_text("foo_test();\nbar_test();\ncost $5");
+        editor.toggle_search_regex();
+
+        editor.find(r"(\w+)_test");
+        assert_eq!(editor.replace_all("test_$1"), 2);
+        assert_eq!(editor.buffer().to_string(), "test_foo();\ntest_bar();\ncost $5");
+
+        // The whole replace-all is a single undo step
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "foo_test();\nbar_test();\ncost $5");
+    }
+
+    #[test]
+    fn test_replace_all_preserve_case() {
+        let mut editor = Editor::new();
+        editor.insert_text("color, Color, COLOR");
+        editor.toggle_search_preserve_case();
+
+        // Matching stays case-insensitive; only the replacement is recased
+        editor.find("color");
+        assert_eq!(editor.replace_all("colour"), 3);
+        assert_eq!(editor.buffer().to_string(), "colour, Colour, COLOUR");
+    }
+
+    #[test]
+    fn test_replace_on_lines() {
+        let mut editor = Editor::new();
+        editor.insert_text("foo foo\nfoo\nbar foo\n");
+        let count = editor.replace_on_lines(&[2, 0, 0], "foo", "baz");
+        assert_eq!(count, 3);
+        assert_eq!(editor.buffer().to_string(), "baz baz\nfoo\nbar baz\n");
+        assert!(editor.is_modified());
+
+        // One undo step restores every line
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "foo foo\nfoo\nbar foo\n");
+
+        assert_eq!(editor.replace_on_lines(&[1, 9], "nope", "x"), 0);
+        assert_eq!(editor.replace_on_lines(&[1], "", "x"), 0);
+    }
+
+    #[test]
+    fn test_diagnostic_display_min() {
+        let mut editor = Editor::new();
+        editor.insert_text("let x = 1;");
+        editor.set_diagnostics(vec![
+            Diagnostic::new(0, 4, 0, 5, DiagnosticSeverity::Warning, "unused".to_string()),
+            Diagnostic::new(0, 0, 0, 3, DiagnosticSeverity::Information, "info".to_string()),
+            Diagnostic::new(0, 8, 0, 9, DiagnosticSeverity::Hint, "hint".to_string()),
+        ]);
+        assert_eq!(editor.diagnostics_on_line(0).len(), 3);
+
+        editor.set_diagnostic_display_min(DiagnosticSeverity::Warning);
+        let shown = editor.diagnostics_on_line(0);
+        assert_eq!(shown.len(), 1);
+        assert_eq!(shown[0].severity, DiagnosticSeverity::Warning);
+        assert!(editor.diagnostic_at(0, 1).is_none());
+        assert_eq!(editor.visible_diagnostic_count(DiagnosticSeverity::Hint), 0);
+        assert_eq!(editor.visible_diagnostic_count(DiagnosticSeverity::Warning), 1);
+
+        // Hidden diagnostics are still retrievable
+        assert_eq!(editor.diagnostics().len(), 3);
+
+        editor.set_diagnostic_display_min(DiagnosticSeverity::Hint);
+        assert_eq!(editor.diagnostics_on_line(0).len(), 3);
+    }
+
+    #[test]
+    fn test_bookmarks_follow_edits() {
+        let mut editor = Editor::new();
+        editor.insert_text("one\ntwo\nthree");
+
+        assert!(editor.toggle_bookmark(2));
+        assert!(editor.has_bookmark(2));
+        assert!(!editor.toggle_bookmark(5)); // Past the end
+
+        // Inserting a line above moves the bookmark down
+        editor.set_cursor_position(0, 0, false);
+        editor.insert_text("zero\n");
+        assert_eq!(editor.bookmark_lines(), vec![3]);
+
+        // Deleting the bookmarked line's text keeps it on the line of the deletion
+        editor.set_cursor_position(2, 3, false);
+        editor.set_cursor_position(3, 5, true);
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "zero\none\ntwo");
+        assert_eq!(editor.bookmark_lines(), vec![2]);
+
+        // Undo re-inserts the text after the bookmark, which moves it back
+        // to the restored line
+        editor.undo();
+        assert_eq!(editor.bookmark_lines(), vec![3]);
+
+        // Toggling again removes it
+        assert!(!editor.toggle_bookmark(3));
+        assert!(editor.bookmark_lines().is_empty());
+    }
+
+    #[test]
+    fn test_replace_current_skips_inserted_text() {
+        let mut editor = Editor::new();
+        editor.insert_text("a b a");
+        editor.move_to_buffer_start(false);
+        editor.find("a");
+
+        // The replacement contains the query; the next match is the second "a"
+        assert!(editor.replace_current("aa"));
+        assert_eq!(editor.buffer().to_string(), "aa b a");
+        assert_eq!(editor.current_search_match(), Some(SearchMatch::new(5, 6)));
+    }
+
+    #[test]
+    fn test_replace_matches_from_current() {
+        let mut editor = Editor::new();
+        editor.insert_text("x x x x");
+        editor.move_to_buffer_start(false);
+        editor.find("x");
+
+        // Skip the first, then replace the remaining three at once
+        editor.find_next();
+        assert_eq!(editor.replace_matches_from_current("y", 3), 3);
+        assert_eq!(editor.buffer().to_string(), "x y y y");
+
+        // The batch is a single undo step
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "x x x x");
+    }
+
+    #[test]
+    fn test_tab_inserts_configured_spaces() {
+        let mut editor = Editor::new();
+        editor.set_indent_config(IndentConfig::spaces(4));
+        editor.insert_char('\t');
+        assert_eq!(editor.buffer().to_string(), "    ");
+
+        // Mid-column tabs align to the next stop
+        editor.insert_text("ab");
+        editor.insert_char('\t');
+        assert_eq!(editor.buffer().to_string(), "    ab  ");
+        assert_eq!(editor.cursor_position(), Position::new(0, 8));
+
+        editor.set_indent_config(IndentConfig::tabs(4));
+        editor.insert_char('\t');
+        assert_eq!(editor.buffer().to_string(), "    ab  \t");
+    }
+
+    #[test]
+    fn test_delete_line() {
+        let mut editor = Editor::from_text("one\ntwo\nthree\nfour");
+        editor.set_cursor_position(1, 2, false);
+        editor.delete_line();
+        assert_eq!(editor.text(), "one\nthree\nfour");
+        assert_eq!(editor.cursor_position(), Position::new(1, 2));
+        editor.undo();
+        assert_eq!(editor.text(), "one\ntwo\nthree\nfour");
+
+        // Every line the selection touches, but not one it ends at the start of
+        editor.set_cursor_position(0, 1, false);
+        editor.set_cursor_position(2, 0, true);
+        editor.delete_line();
+        assert_eq!(editor.text(), "three\nfour");
+        editor.undo();
+        editor.set_cursor_position(1, 1, false);
+        editor.set_cursor_position(2, 3, true);
+        editor.delete_line();
+        assert_eq!(editor.text(), "one\nfour");
+        assert_eq!(editor.cursor_position(), Position::new(1, 3));
+
+        // The last line takes the line break before it
+        editor.set_cursor_position(1, 4, false);
+        editor.delete_line();
+        assert_eq!(editor.text(), "one");
+        assert_eq!(editor.cursor_position(), Position::new(0, 3));
+        editor.delete_line();
+        assert_eq!(editor.text(), "");
+        editor.delete_line();
+        assert_eq!(editor.text(), "");
+
+        // The column is kept for the next line down
+        let mut editor = Editor::from_text("abcdef\nx\nabcdef");
+        editor.set_cursor_position(0, 5, false);
+        editor.delete_line();
+        assert_eq!(editor.cursor_position(), Position::new(0, 1));
+        editor.move_down(false);
+        assert_eq!(editor.cursor_position(), Position::new(1, 5));
+    }
+
+    #[test]
+    fn test_insert_line_above_and_below() {
+        let mut editor = Editor::from_text("fn main() {\n    let x = 1;\n}");
+        editor.set_cursor_position(0, 3, false);
+        editor.insert_line_below();
+        assert_eq!(editor.text(), "fn main() {\n    \n    let x = 1;\n}");
+        assert_eq!(editor.cursor_position(), Position::new(1, 4));
+        editor.undo();
+        assert_eq!(editor.text(), "fn main() {\n    let x = 1;\n}");
+
+        editor.set_cursor_position(1, 7, false);
+        editor.insert_line_below();
+        assert_eq!(editor.text(), "fn main() {\n    let x = 1;\n    \n}");
+        assert_eq!(editor.cursor_position(), Position::new(2, 4));
+        editor.undo();
+
+        editor.set_cursor_position(1, 9, false);
+        editor.insert_line_above();
+        assert_eq!(editor.text(), "fn main() {\n    \n    let x = 1;\n}");
+        assert_eq!(editor.cursor_position(), Position::new(1, 4));
+        editor.undo();
+
+        // Below the last line and above the first
+        editor.set_cursor_position(2, 0, false);
+        editor.insert_line_below();
+        assert_eq!(editor.text(), "fn main() {\n    let x = 1;\n}\n");
+        assert_eq!(editor.cursor_position(), Position::new(3, 0));
+        editor.set_cursor_position(0, 5, false);
+        editor.insert_line_above();
+        assert_eq!(editor.text(), "\nfn main() {\n    let x = 1;\n}\n");
+        assert_eq!(editor.cursor_position(), Position::new(0, 0));
+    }
+
+    #[test]
+    fn test_insert_newline_uses_indent_width() {
+        let mut editor = Editor::new();
+        editor.set_language(Language::Rust);
+        editor.set_indent_config(IndentConfig::spaces(2));
+        editor.insert_text("fn a() {");
+        editor.insert_newline();
+        assert_eq!(editor.buffer().line(1).unwrap().trim_end_matches('\n'), "  ");
+        assert_eq!(editor.cursor_position(), Position::new(1, 2));
+    }
+
+    #[test]
+    fn test_indent_dedent_selection() {
+        let mut editor = Editor::new();
+        editor.insert_text("a\n\nb\nc");
+        editor.set_cursor_position(0, 0, false);
+        editor.set_cursor_position(2, 1, true);
+
+        editor.indent_selection();
+        assert_eq!(editor.buffer().to_string(), "    a\n\n    b\nc");
+        // The selection grows with the inserted indentation
+        assert_eq!(editor.selected_text(), Some("    a\n\n    b".to_string()));
+
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "a\n\nb\nc");
+
+        let mut editor = Editor::new();
+        editor.insert_text("      x\n\ty\nz");
+        editor.set_cursor_position(0, 0, false);
+        editor.set_cursor_position(2, 1, true);
+        editor.dedent_selection();
+        assert_eq!(editor.buffer().to_string(), "  x\ny\nz");
+        editor.dedent_selection();
+        assert_eq!(editor.buffer().to_string(), "x\ny\nz");
+
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "  x\ny\nz");
+    }
+
+    #[test]
+    fn test_indent_dedent_lines() {
+        let mut editor = Editor::new();
+        editor.insert_text("a\nb\nc");
+        editor.set_cursor_position(1, 0, false);
+        editor.set_cursor_position(1, 1, true);
+
+        editor.indent_lines(1, 5);
+        assert_eq!(editor.buffer().to_string(), "a\n    b\n    c");
+        // A selection starting at the line start takes in the indentation
+        assert_eq!(editor.selected_text(), Some("    b".to_string()));
+        assert!(!editor.has_multiline_selection());
+
+        // Lines without leading whitespace are left alone
+        editor.dedent_lines(0, 2);
+        assert_eq!(editor.buffer().to_string(), "a\nb\nc");
+        editor.dedent_lines(0, 0);
+        assert_eq!(editor.buffer().to_string(), "a\nb\nc");
+
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "a\n    b\n    c");
+
+        editor.set_cursor_position(0, 0, false);
+        editor.set_cursor_position(1, 2, true);
+        assert!(editor.has_multiline_selection());
+    }
+
+    #[test]
+    fn test_toggle_comment_aligns_to_shared_indent() {
+        let mut editor = Editor::new();
+        editor.set_language(Language::Rust);
+        editor.insert_text("    a;\n\n  b;");
+        editor.set_cursor_position(0, 0, false);
+        editor.set_cursor_position(2, 4, true);
+
+        editor.toggle_comment();
+        assert_eq!(editor.buffer().to_string(), "  //   a;\n\n  // b;");
+
+        editor.set_cursor_position(0, 0, false);
+        editor.set_cursor_position(2, 7, true);
+        editor.toggle_comment();
+        assert_eq!(editor.buffer().to_string(), "    a;\n\n  b;");
+    }
+
+    #[test]
+    fn test_open_file_detects_indentation() {
+        let path = std::env::temp_dir().join(format!("cp_editor_indent_{}.py", std::process::id()));
+        std::fs::write(&path, "def f():\n  if x:\n    y()\n").unwrap();
+
+        let mut editor = Editor::new();
+        editor.open_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(editor.indent_config(), IndentConfig::spaces(2));
+        assert!(!editor.has_mixed_indentation());
+    }
+
+    #[test]
+    fn test_regex_replace_current_escaped_dollar() {
+        let mut editor = Editor::new();
+        editor.insert_text("price: 5, price: 7");
+        editor.toggle_search_regex();
+
+        editor.move_to_buffer_start(false);
+        editor.find(r"price: (\d)");
+        assert!(editor.replace_current("$$${1}.00"));
+        assert_eq!(editor.buffer().to_string(), "$5.00, price: 7");
+    }
+
+    #[test]
+    fn test_closing_bracket_not_auto_inserted_is_typed() {
+        let mut editor = Editor::new();
+        editor.insert_text("f(x)");
+        editor.move_left(false); // Before the existing ")"
+
+        editor.insert_char_with_auto_bracket(')');
+        assert_eq!(editor.buffer().to_string(), "f(x))");
+        assert_eq!(editor.cursor_position(), Position::new(0, 4));
+    }
+
+    #[test]
+    fn test_nested_auto_pairs_type_through() {
+        let mut editor = Editor::new();
+        editor.insert_char_with_auto_bracket('(');
+        editor.insert_char_with_auto_bracket('[');
+        assert_eq!(editor.buffer().to_string(), "([])");
+
+        editor.insert_char_with_auto_bracket(']');
+        editor.insert_char_with_auto_bracket(')');
+        assert_eq!(editor.buffer().to_string(), "([])");
+        assert_eq!(editor.cursor_position(), Position::new(0, 4));
+
+        // Both closers were consumed, so another ")" is inserted literally
+        editor.move_left(false);
+        editor.insert_char_with_auto_bracket(')');
+        assert_eq!(editor.buffer().to_string(), "([]))");
+    }
+
+    #[test]
+    fn test_auto_close_quotes() {
+        let mut editor = Editor::new();
+        editor.insert_text("say(");
+        editor.insert_char_with_auto_bracket('"');
+        editor.insert_text("hi");
+        // The auto-inserted quote is typed over
+        editor.insert_char_with_auto_bracket('"');
+        assert_eq!(editor.buffer().to_string(), "say(\"hi\"");
+        assert_eq!(editor.cursor_position(), Position::new(0, 8));
+
+        // Before a word a quote is inserted alone
+        editor.insert_text(" x");
+        editor.move_left(false);
+        editor.insert_char_with_auto_bracket('`');
+        assert_eq!(editor.buffer().to_string(), "say(\"hi\" `x");
+
+        // So is an apostrophe inside a word
+        editor.move_to_line_end(false);
+        editor.insert_text(" don");
+        editor.insert_char_with_auto_bracket('\'');
+        editor.insert_char_with_auto_bracket('t');
+        assert_eq!(editor.buffer().to_string(), "say(\"hi\" `x don't");
+
+        // Before a closing bracket it is closed
+        editor.move_to_line_end(false);
+        editor.insert_text(" [");
+        editor.insert_text("]");
+        editor.move_left(false);
+        editor.insert_char_with_auto_bracket('\'');
+        assert_eq!(editor.buffer().to_string(), "say(\"hi\" `x don't ['']");
+    }
+
+    #[test]
+    fn test_rust_lifetimes_are_not_auto_closed() {
+        let mut editor = Editor::new();
+        editor.set_language(Language::Rust);
+        for ch in "fn f<'a>(x: &'a str) -> char { '".chars() {
+            editor.insert_char_with_auto_bracket(ch);
+        }
+        assert_eq!(editor.buffer().to_string(), "fn f<'a>(x: &'a str) -> char { ''}");
+
+        // A quote that wasn't auto-inserted isn't typed over
+        editor.set_cursor_position(0, 5, false);
+        editor.insert_char_with_auto_bracket('\'');
+        assert_eq!(editor.buffer().to_string(), "fn f<''a>(x: &'a str) -> char { ''}");
+    }
+
+    #[test]
+    fn test_backspace_removes_indent_level() {
+        let mut editor = Editor::new();
+        editor.insert_text("       x");
+        editor.set_cursor_position(0, 7, false);
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "    x");
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "x");
+        // One undo step per level
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "    x");
+
+        // After text on the line it's one space at a time
+        editor.insert_text("y    ");
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "    y   x");
+
+        // Tabs go one at a time as before
+        let mut editor = Editor::new();
+        editor.set_indent_config(IndentConfig::tabs(4));
+        editor.insert_text("\t\t  ");
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "\t\t ");
+
+        // Every cursor steps back a level
+        let mut editor = Editor::new();
+        editor.insert_text("      a\n      b");
+        editor.set_cursor_position(0, 6, false);
+        editor.add_cursor_below();
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "    a\n    b");
+    }
+
+    #[test]
+    fn test_backspace_deletes_empty_auto_pair() {
+        let mut editor = Editor::new();
+        editor.insert_char_with_auto_bracket('"');
+        assert_eq!(editor.buffer().to_string(), "\"\"");
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "");
+
+        // Typed over, the closing quote is the user's and stays
+        editor.insert_char_with_auto_bracket('"');
+        editor.insert_char_with_auto_bracket('"');
+        assert_eq!(editor.buffer().to_string(), "\"\"");
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "\"");
+        editor.delete_backward();
+
+        // Nested pairs come apart one at a time
+        editor.insert_char_with_auto_bracket('(');
+        editor.insert_char_with_auto_bracket('[');
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "()");
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "");
+
+        // A pair the editor didn't close is deleted a character at a time
+        editor.insert_text("{}");
+        editor.move_left(false);
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "}");
+        editor.delete_forward();
+
+        // An auto-closed pair opened onto its own line with Enter goes
+        // with its indentation
+        editor.insert_text("fn f() ");
+        editor.insert_char_with_auto_bracket('{');
+        editor.insert_newline();
+        assert_eq!(editor.buffer().to_string(), "fn f() {\n    }");
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "fn f() ");
+        assert_eq!(editor.cursor_position(), Position::new(0, 7));
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "fn f() {\n    }");
+
+        // But not a pair with a space typed into it
+        editor.set_buffer(TextBuffer::new());
+        editor.insert_char_with_auto_bracket('(');
+        editor.insert_char(' ');
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "()");
+    }
+
+    #[test]
+    fn test_no_auto_close_in_strings_and_comments() {
+        let mut editor = Editor::new();
+        editor.set_language(Language::Rust);
+        editor.insert_text("let s = \"a b\"; // note\n");
+
+        // Inside the string
+        editor.set_cursor_position(0, 10, false);
+        editor.insert_char_with_auto_bracket('(');
+        editor.insert_char_with_auto_bracket('\'');
+        assert_eq!(editor.buffer().line(0).unwrap(), "let s = \"a(' b\"; // note");
+
+        // At the end of a line comment
+        editor.move_to_line_end(false);
+        editor.insert_char_with_auto_bracket(' ');
+        editor.insert_char_with_auto_bracket('[');
+        assert_eq!(editor.buffer().line(0).unwrap(), "let s = \"a(' b\"; // note [");
+
+        // Right after a string is code again
+        editor.set_cursor_position(0, 15, false);
+        editor.insert_char_with_auto_bracket('[');
+        assert_eq!(editor.buffer().line(0).unwrap(), "let s = \"a(' b\"[]; // note [");
+    }
+
+    #[test]
+    fn test_closing_bracket_dedents_blank_line() {
+        let mut editor = Editor::new();
+        editor.set_language(Language::Rust);
+        editor.insert_text("fn main() {\n    if ok {\n        run();\n        ");
+        editor.insert_char_with_auto_bracket('}');
+        assert_eq!(editor.buffer().to_string(), "fn main() {\n    if ok {\n        run();\n    }");
+        assert_eq!(editor.cursor_position(), Position::new(3, 5));
+
+        // Aligned with the line of its opening bracket from shallower
+        // indentation too
+        editor.insert_text("\n  ");
+        editor.insert_char_with_auto_bracket('}');
+        assert_eq!(editor.buffer().to_string(), "fn main() {\n    if ok {\n        run();\n    }\n}");
+
+        // One undo step
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "fn main() {\n    if ok {\n        run();\n    }\n  ");
+
+        // Text before the cursor leaves the line alone
+        let mut editor = Editor::new();
+        editor.set_language(Language::Rust);
+        editor.insert_text("let v = vec![\n    1, 2");
+        editor.insert_char_with_auto_bracket(']');
+        assert_eq!(editor.buffer().to_string(), "let v = vec![\n    1, 2]");
+    }
+
+    #[test]
+    fn test_goto_matching_bracket() {
+        let mut editor = Editor::new();
+        editor.insert_text("f(a[1], { b })");
+
+        // On a bracket, to its match and back
+        editor.set_cursor_position(0, 1, false);
+        assert!(editor.goto_matching_bracket(false));
+        assert_eq!(editor.cursor_position(), Position::new(0, 13));
+        assert!(editor.goto_matching_bracket(false));
+        assert_eq!(editor.cursor_position(), Position::new(0, 1));
+
+        // Just after a bracket counts too
+        editor.set_cursor_position(0, 6, false);
+        assert!(editor.goto_matching_bracket(false));
+        assert_eq!(editor.cursor_position(), Position::new(0, 3));
+
+        // Off a bracket, to the close of the innermost enclosing pair
+        editor.set_cursor_position(0, 10, false);
+        assert!(editor.goto_matching_bracket(false));
+        assert_eq!(editor.cursor_position(), Position::new(0, 12));
+
+        // Selecting takes the pair and its contents
+        editor.set_cursor_position(0, 10, false);
+        assert!(editor.goto_matching_bracket(true));
+        assert_eq!(editor.selected_text().as_deref(), Some("{ b }"));
+        editor.set_cursor_position(0, 13, false);
+        assert!(editor.goto_matching_bracket(true));
+        assert_eq!(editor.selected_text().as_deref(), Some("(a[1], { b })"));
+
+        // Outside every pair nothing happens
+        editor.set_cursor_position(0, 0, false);
+        assert!(!editor.goto_matching_bracket(false));
+        assert_eq!(editor.cursor_position(), Position::new(0, 0));
+    }
+
+    #[test]
+    fn test_auto_closer_survives_intermediate_typing() {
+        let mut editor = Editor::new();
+        editor.insert_text("let v = ");
+        editor.insert_char_with_auto_bracket('(');
+        editor.insert_text("a, b");
+        editor.insert_newline();
+        editor.delete_backward();
+
+        editor.insert_char_with_auto_bracket(')');
+        assert_eq!(editor.buffer().to_string(), "let v = (a, b)");
+        assert_eq!(editor.cursor_position(), Position::new(0, 14));
+    }
+
+    #[test]
+    fn test_replace_all_in_selection() {
+        let mut editor = Editor::new();
+        editor.insert_text("x = 1\nx = 2\nx = 3\nx = 4");
+
+        // Select the middle two lines
+        editor.set_cursor_position(1, 0, false);
+        editor.set_cursor_position(3, 0, true);
+        assert!(editor.set_search_in_selection());
+
+        editor.find("x");
+        assert_eq!(editor.search_matches().len(), 2);
+        assert_eq!(editor.replace_all("value"), 2);
+        assert_eq!(editor.buffer().to_string(), "x = 1\nvalue = 2\nvalue = 3\nx = 4");
+
+        // The range grew with the replacements, so a new search still covers both lines
+        editor.find("value");
+        assert_eq!(editor.search_matches().len(), 2);
+        editor.find("x");
+        assert_eq!(editor.search_matches().len(), 0);
+    }
+
+    #[test]
+    fn test_collapsing_selection_clears_search_range() {
+        let mut editor = Editor::new();
+        editor.insert_text("a\na\na");
+        editor.set_cursor_position(0, 0, false);
+        editor.set_cursor_position(1, 1, true);
+        assert!(editor.set_search_in_selection());
+        assert!(editor.is_search_in_selection());
+
+        editor.clear_selection();
+        assert!(!editor.is_search_in_selection());
+        assert_eq!(editor.find("a"), 3);
+    }
+
+    #[test]
+    fn test_select_next_occurrence() {
+        let mut editor = Editor::new();
+        editor.insert_text("foo bar\nfoo baz\nfoo");
+        editor.set_cursor_position(1, 1, false);
+
+        // First press selects the word under the cursor
+        assert!(editor.select_next_occurrence());
+        assert_eq!(editor.selected_text().as_deref(), Some("foo"));
+        assert_eq!(editor.cursor_count(), 1);
+
+        // Then each press adds the next occurrence, wrapping at the end
+        assert!(editor.select_next_occurrence());
+        assert_eq!(editor.cursor_position(), Position::new(2, 3));
+        assert!(editor.select_next_occurrence());
+        assert_eq!(editor.cursor_position(), Position::new(0, 3));
+        assert_eq!(editor.cursor_count(), 3);
+        assert!(!editor.select_next_occurrence());
+
+        // Typing replaces every selection as one undo step
+        editor.insert_char('x');
+        editor.insert_text("yz");
+        assert_eq!(editor.buffer().to_string(), "xyz bar\nxyz baz\nxyz");
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "xy bar\nxy baz\nxy");
+        assert_eq!(editor.cursor_count(), 3);
+
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "xyz bar\nxyz baz\nxyz");
+        assert_eq!(editor.cursor_count(), 1);
+        editor.undo();
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "foo bar\nfoo baz\nfoo");
+    }
+
+    #[test]
+    fn test_bidi_controls_strip_and_escape() {
+        // A "trojan source" line: reads as a comment, runs as code
+        let text = "let s = \"user\u{202E} \u{2066}// admin\u{2069}\u{2066}\";\nlet z\u{200B}w = 1;";
+        let mut editor = Editor::new();
+        editor.insert_text(text);
+        assert_eq!(editor.invisible_counts(), InvisibleCounts { total: 5, bidi: 4 });
+
+        editor.set_cursor_position(1, 0, false);
+        assert_eq!(editor.escape_bidi_controls(), 4);
+        assert_eq!(
+            editor.buffer().to_string(),
+            "let s = \"user\\u{202E} \\u{2066}// admin\\u{2069}\\u{2066}\";\nlet z\u{200B}w = 1;"
+        );
+        // The cursor stays at the text it was at
+        assert_eq!(editor.cursor_position(), Position::new(1, 0));
+        assert_eq!(editor.invisible_counts(), InvisibleCounts { total: 1, bidi: 0 });
+
+        // Each fix is one undo step
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), text);
+        assert_eq!(editor.strip_bidi_controls(), 4);
+        assert_eq!(editor.buffer().to_string(), "let s = \"user // admin\";\nlet z\u{200B}w = 1;");
+        assert_eq!(editor.strip_bidi_controls(), 0);
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), text);
+        assert_eq!(editor.invisible_counts(), InvisibleCounts { total: 5, bidi: 4 });
+
+        editor.set_buffer(TextBuffer::from_str("a\u{FEFF}b"));
+        assert_eq!(editor.invisible_counts(), InvisibleCounts { total: 1, bidi: 0 });
+    }
+
+    #[test]
+    fn test_horizontal_scroll_counts_boxes() {
+        let mut editor = Editor::new();
+        editor.set_visible_cols(20);
+        // Ten zero-width spaces are drawn sixty columns wide
+        editor.insert_text(&"\u{200B}".repeat(10));
+        assert_eq!(editor.line_layout(0).display_col(10), 60);
+        assert_eq!(editor.horizontal_scroll(), 60 - 15);
+    }
+
+    #[test]
+    fn test_expand_selection_by_syntax() {
+        let mut editor = Editor::new();
+        editor.set_language(Language::Rust);
+        editor.insert_text("fn main() {\n    let x = foo(1, 2);\n}\n");
+        editor.set_cursor_position(1, 13, false);
+
+        let mut expansions = Vec::new();
+        while editor.expand_selection() {
+            expansions.push(editor.selected_text().unwrap());
+        }
+        assert_eq!(
+            expansions,
+            [
+                "foo",
+                "foo(1, 2)",
+                "let x = foo(1, 2);",
+                "{\n    let x = foo(1, 2);\n}",
+                "fn main() {\n    let x = foo(1, 2);\n}",
+                "fn main() {\n    let x = foo(1, 2);\n}\n",
+            ]
+        );
+
+        // Shrinking walks back down to the cursor
+        assert!(editor.shrink_selection());
+        assert!(editor.shrink_selection());
+        assert_eq!(editor.selected_text().unwrap(), "{\n    let x = foo(1, 2);\n}");
+        while editor.shrink_selection() {}
+        assert!(!editor.cursor.selection.has_selection());
+        assert_eq!(editor.cursor_position(), Position::new(1, 13));
+
+        // Changing the selection in between forgets the stack
+        editor.expand_selection();
+        editor.move_right(true);
+        assert!(!editor.shrink_selection());
+    }
+
+    #[test]
+    fn test_expand_selection_plain_text() {
+        let mut editor = Editor::new();
+        editor.insert_text("one two\nthree\n\nfour");
+        editor.set_cursor_position(0, 5, false);
+
+        let mut expansions = Vec::new();
+        while editor.expand_selection() {
+            expansions.push(editor.selected_text().unwrap());
+        }
+        assert_eq!(expansions, ["two", "one two", "one two\nthree", "one two\nthree\n\nfour"]);
+    }
+
+    #[test]
+    fn test_select_units() {
+        let mut editor = Editor::new();
+        editor.insert_text("let total_sum = a + b;\nsecond line\nthird");
+
+        // A double click selects the word, or the character between words
+        editor.select_units((0, 7), 0, 7, SelectionGranularity::Word);
+        assert_eq!(editor.selected_text().as_deref(), Some("total_sum"));
+        editor.select_units((0, 14), 0, 14, SelectionGranularity::Word);
+        assert_eq!(editor.selected_text().as_deref(), Some("="));
+
+        // Dragging extends word by word, either way from the clicked word
+        editor.select_units((0, 7), 0, 17, SelectionGranularity::Word);
+        assert_eq!(editor.selected_text().as_deref(), Some("total_sum = a"));
+        editor.select_units((0, 7), 0, 1, SelectionGranularity::Word);
+        assert_eq!(editor.selected_text().as_deref(), Some("let total_sum"));
+        assert_eq!(editor.cursor_position(), Position::new(0, 0));
+
+        // A triple click selects the line with its line break
+        editor.select_units((1, 3), 1, 3, SelectionGranularity::Line);
+        assert_eq!(editor.selected_text().as_deref(), Some("second line\n"));
+        editor.select_units((1, 3), 2, 0, SelectionGranularity::Line);
+        assert_eq!(editor.selected_text().as_deref(), Some("second line\nthird"));
+        editor.select_units((1, 3), 0, 4, SelectionGranularity::Line);
+        assert_eq!(editor.selected_text().as_deref(), Some("let total_sum = a + b;\nsecond line\n"));
+        assert_eq!(editor.cursor_position(), Position::new(0, 0));
+    }
+
+    #[test]
+    fn test_content_hash_tracks_sent_text() {
+        let mut editor = Editor::new();
+        editor.insert_text("let a = 1;");
+        assert!(editor.has_unsent_changes());
+        editor.mark_change_sent();
+        assert!(!editor.has_unsent_changes());
+
+        // The version moves on, the hash comes back
+        let (version, hash) = (editor.content_version(), editor.content_hash());
+        editor.insert_text("2");
+        assert!(editor.has_unsent_changes());
+        editor.undo();
+        assert_ne!(editor.content_version(), version);
+        assert_eq!(editor.content_hash(), hash);
+        assert!(!editor.has_unsent_changes());
+    }
+
+    #[test]
+    fn test_buffer_times() {
+        let mut editor = Editor::new();
+        assert_eq!(*editor.times(), BufferTimes::default());
+        editor.insert_text("x");
+        let edited = editor.times().last_edit.unwrap();
+        editor.undo();
+        assert!(editor.times().last_edit.unwrap() >= edited);
+
+        // Only writing the file counts as saving
+        let (_, version) = editor.save_snapshot();
+        assert!(editor.times().last_saved.is_none());
+        assert!(editor.mark_saved(version));
+        assert!(editor.times().last_saved.is_some());
+
+        // Diagnostics published long before an unanswered edit are stale
+        editor.mark_diagnostics_published();
+        assert!(!editor.update_diagnostics_staleness(Duration::ZERO));
+        std::thread::sleep(Duration::from_millis(2));
+        editor.insert_text("y");
+        assert!(editor.update_diagnostics_staleness(Duration::ZERO));
+        assert!(editor.diagnostics_stale());
+        // Within a generous threshold they aren't
+        assert!(editor.update_diagnostics_staleness(Duration::from_secs(60)));
+        assert!(!editor.diagnostics_stale());
+    }
+
+    #[test]
+    fn test_view_state_round_trip() {
+        let text = "fn a() {\n    1\n}\nfn b() {\n    2\n}\n".repeat(20);
+        let mut editor = Editor::new();
+        editor.set_buffer(TextBuffer::from_str(&text));
+        editor.detect_folds();
+        editor.toggle_fold_at_line(3);
+        editor.toggle_fold_at_line(9);
+        editor.toggle_bookmark(4);
+        editor.toggle_bookmark(50);
+        editor.set_cursor_position(40, 1, false);
+        editor.set_cursor_position(42, 0, true);
+        editor.set_scroll_offset(30);
+        editor.set_horizontal_scroll(2);
+        let state = editor.view_state();
+        assert_eq!(
+            state,
+            ViewState {
+                cursor: (42, 0),
+                selection: Some((40, 1)),
+                scroll_offset: 30,
+                horizontal_scroll: 2,
+                folds: vec![3, 9],
+                bookmarks: vec![4, 50],
+            }
+        );
+
+        let mut restored = Editor::new();
+        restored.set_buffer(TextBuffer::from_str(&text));
+        restored.apply_view_state(&state);
+        assert_eq!(restored.view_state(), state);
+        assert_eq!(restored.selected_text().as_deref(), Some("   2\n}\n"));
+        assert!(restored.is_line_hidden(10));
+    }
+
+    #[test]
+    fn test_view_state_clamps_to_shorter_text() {
+        let mut editor = Editor::new();
+        editor.set_buffer(TextBuffer::from_str("fn a() {\n    1\n}"));
+        editor.apply_view_state(&ViewState {
+            cursor: (40, 3),
+            selection: Some((1, 80)),
+            scroll_offset: 30,
+            horizontal_scroll: 0,
+            folds: vec![0, 9],
+            bookmarks: vec![1, 50],
+        });
+        let state = editor.view_state();
+        assert_eq!(state.cursor, (2, 1));
+        assert_eq!(state.selection, Some((1, 5)));
+        assert_eq!(state.scroll_offset, 2);
+        assert_eq!(state.folds, [0]);
+        assert_eq!(state.bookmarks, [1]);
+    }
+
+    #[test]
+    fn test_filter_completions() {
+        let item = |label: &str| CompletionItem {
+            label: label.to_string(),
+            kind: None,
+            detail: None,
+            insert_text: None,
+            is_snippet: false,
+        };
+        let labels = |editor: &Editor| editor.completions().iter().map(|c| c.label.clone()).collect::<Vec<_>>();
+        let mut editor = Editor::new();
+        editor.set_completions(vec![item("flat_map"), item("filter"), item("map"), item("map_err")]);
+
+        assert_eq!(editor.filter_completions("map"), 3);
+        assert_eq!(labels(&editor), ["map", "map_err", "flat_map"]);
+        assert_eq!(editor.filter_completions("fm"), 1);
+        assert_eq!(labels(&editor), ["flat_map"]);
+        assert_eq!(editor.filter_completions("xyz"), 0);
+        assert!(!editor.has_completions());
+
+        // Widening the prefix again starts from the server's list
+        assert_eq!(editor.filter_completions(""), 4);
+        assert_eq!(labels(&editor), ["flat_map", "filter", "map", "map_err"]);
+        editor.clear_completions();
+        assert_eq!(editor.filter_completions(""), 0);
+    }
+
+    #[test]
+    fn test_insert_snippet() {
+        let mut editor = Editor::new();
+        editor.insert_text("fn f() {\n    ");
+        let stops = editor.insert_snippet("for ${1:x} in ${2:xs} {\n\t$1\n}$0");
+        assert_eq!(editor.buffer().to_string(), "fn f() {\n    for x in xs {\n        x\n    }");
+        assert_eq!(stops, [vec![(17, 18), (35, 36)], vec![(22, 24)], vec![(42, 42)]]);
+
+        // The mirrored first tab stop gets a cursor at each place
+        assert_eq!(editor.cursor_count(), 2);
+        assert_eq!(editor.selected_text().as_deref(), Some("x"));
+        editor.insert_text("item");
+        assert_eq!(editor.buffer().to_string(), "fn f() {\n    for item in xs {\n        item\n    }");
+
+        // Tab stops follow the edits
+        assert!(editor.next_tab_stop());
+        assert_eq!(editor.cursor_count(), 1);
+        assert_eq!(editor.selected_text().as_deref(), Some("xs"));
+        assert!(editor.prev_tab_stop());
+        assert_eq!(editor.cursor_count(), 2);
+        assert!(editor.next_tab_stop());
+        editor.insert_text("items");
+
+        // The final tab stop ends the snippet
+        assert!(editor.next_tab_stop());
+        assert!(!editor.has_snippet());
+        assert_eq!(editor.cursor_position(), Position::new(3, 5));
+        assert!(!editor.next_tab_stop());
+
+        // Moving out of the current tab stop ends it too
+        editor.insert_snippet("(${1:a}, ${2:b})");
+        assert!(editor.has_snippet());
+        editor.move_to_line_start(false);
+        assert!(!editor.next_tab_stop());
+        assert!(!editor.has_snippet());
+
+        // Without tab stops the cursor ends up after the text
+        editor.move_to_line_end(false);
+        editor.insert_snippet("done");
+        assert!(!editor.has_snippet());
+        assert_eq!(editor.cursor_position(), Position::new(3, 15));
+    }
+
+    #[test]
+    fn test_word_chars_follow_language() {
+        let mut editor = Editor::new();
+        editor.insert_text("$el = getElementById($id)");
+        editor.set_cursor_position(0, 2, false);
+        assert_eq!(editor.word_under_cursor().as_deref(), Some("el"));
+
+        // In JavaScript `$` is part of the word, for every word operation
+        editor.set_language(Language::JavaScript);
+        assert_eq!(editor.word_under_cursor().as_deref(), Some("$el"));
+        assert!(editor.select_next_occurrence());
+        assert_eq!(editor.selected_text().as_deref(), Some("$el"));
+        editor.set_cursor_position(0, 25, false);
+        editor.move_word_left(false);
+        assert_eq!(editor.cursor_position(), Position::new(0, 21));
+
+        // Sub-word movement stops at the humps
+        editor.move_subword_left(false);
+        assert_eq!(editor.cursor_position(), Position::new(0, 18));
+        editor.move_subword_left(true);
+        editor.move_subword_left(true);
+        assert_eq!(editor.selected_text().as_deref(), Some("ElementBy"));
+    }
+
+    #[test]
+    fn test_select_next_occurrence_scrolls_to_match() {
+        let mut editor = Editor::new();
+        let mut text = vec!["let total = 0;".to_string()];
+        text.extend((0..40).map(|i| format!("// line {}", i)));
+        text.push("total += 1;".to_string());
+        editor.insert_text(&text.join("\n"));
+        editor.set_visible_lines(10);
+        editor.set_cursor_position(0, 5, false);
+        assert_eq!(editor.scroll_offset(), 0);
+
+        // The new selection becomes the primary one and is brought on screen
+        editor.select_next_occurrence();
+        assert!(editor.select_next_occurrence());
+        assert_eq!(editor.cursor_position(), Position::new(41, 5));
+        assert!(editor.scroll_offset() > 31);
+
+        // Escape's collapse keeps only the newest selection
+        editor.collapse_cursors();
+        assert_eq!(editor.cursor_count(), 1);
+        assert_eq!(editor.selected_text().as_deref(), Some("total"));
+    }
+
+    #[test]
+    fn test_editors_hold_their_own_settings() {
+        let mut wrapped = Editor::new();
+        let mut plain = Editor::new();
+        wrapped.set_settings(EditorSettings {
+            word_wrap: true,
+            wrap_width: 40,
+            indent: IndentConfig::tabs(8),
+            language: Some(Language::Rust),
+            ..EditorSettings::default()
+        });
+        assert!(wrapped.word_wrap() && !plain.word_wrap());
+        assert_eq!(wrapped.wrap_width(), 40);
+        assert_eq!(wrapped.indent_config(), IndentConfig::tabs(8));
+        assert_eq!(wrapped.language(), Language::Rust);
+        assert_eq!(plain.settings(), EditorSettings::default());
+
+        // Toggles change only their own editor
+        plain.toggle_word_wrap();
+        wrapped.toggle_word_wrap();
+        assert!(plain.word_wrap() && !wrapped.word_wrap());
+        assert!(plain.settings().language.is_none());
+    }
+
+    #[test]
+    fn test_visual_lines_with_word_wrap() {
+        let mut editor = Editor::new();
+        editor.insert_text("short\naaaa bbbb cccc dddd eeee\nend");
+        editor.set_wrap_width(10);
+
+        // Without wrap every line is one row
+        let rows = editor.visual_lines(0, 10);
+        assert_eq!(rows.len(), 3);
+        assert!(rows.iter().all(|row| row.is_first() && !row.continues));
+
+        editor.set_word_wrap(true);
+        let rows = editor.visual_lines(0, 10);
+        let spans: Vec<_> = rows.iter().map(|r| (r.line, r.start_col, r.end_col, r.continues)).collect();
+        assert_eq!(
+            spans,
+            vec![(0, 0, 5, false), (1, 0, 10, true), (1, 10, 20, true), (1, 20, 24, false), (2, 0, 3, false)]
+        );
+        assert_eq!(editor.visual_lines(1, 2).len(), 2);
+
+        // The wrap point belongs to the next row; selections are clipped per row
+        assert!(!rows[1].contains(10) && rows[2].contains(10));
+        assert!(rows[3].contains(24));
+        assert_eq!(rows[1].clip(8, 12), Some((8, 10)));
+        assert_eq!(rows[2].clip(8, 12), Some((0, 2)));
+        assert_eq!(rows[3].clip(22, 25), Some((2, 5)));
+        assert_eq!(rows[3].clip(0, 20), None);
+
+        // Scrolling counts rows, not lines
+        editor.set_visible_lines(4);
+        editor.set_cursor_position(2, 0, false);
+        assert_eq!(editor.scroll_offset(), 1);
+        editor.set_visible_lines(2);
+        editor.set_cursor_position(1, 22, false);
+        editor.set_cursor_position(2, 1, false);
+        assert_eq!(editor.scroll_offset(), 2);
+    }
+
+    #[test]
+    fn test_edit_with_three_cursors() {
+        let mut editor = Editor::new();
+        editor.insert_text("one\n  two\nthree");
+        editor.set_cursor_position(0, 3, false);
+        editor.add_cursor_at(1, 5);
+        editor.add_cursor_at(2, 5);
+        assert_eq!(editor.cursor_count(), 3);
+
+        // Insert at every cursor, as one undo step
+        editor.insert_char('!');
+        assert_eq!(editor.buffer().to_string(), "one!\n  two!\nthree!");
+        assert_eq!(editor.cursor_count(), 3);
+
+        // Backspace at every cursor
+        editor.delete_backward();
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "on\n  tw\nthre");
+        let positions = editor.all_cursor_positions();
+        assert_eq!(positions, vec![(0, 2), (1, 4), (2, 4)]);
+
+        // Newline at every cursor keeps each line's indentation
+        editor.insert_newline();
+        assert_eq!(editor.buffer().to_string(), "on\n\n  tw\n  \nthre\n");
+        assert_eq!(editor.all_cursor_positions(), vec![(1, 0), (3, 2), (5, 0)]);
+
+        // Each multi-cursor edit undoes in one step, and consecutive
+        // backspaces coalesce like they do with one cursor
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "on\n  tw\nthre");
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "one!\n  two!\nthree!");
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "one\n  two\nthree");
+    }
+
+    #[test]
+    fn test_typing_at_several_cursors_coalesces() {
+        let mut editor = Editor::new();
+        editor.insert_text("a\nb\nc");
+        editor.set_cursor_position(0, 1, false);
+        editor.add_cursor_at(1, 1);
+        editor.add_cursor_at(2, 1);
+        editor.history.set_coalesce_window(std::time::Duration::from_secs(60));
+
+        for ch in "xyz".chars() {
+            editor.insert_char(ch);
+        }
+        assert_eq!(editor.buffer().to_string(), "axyz\nbxyz\ncxyz");
+        // Moving the cursor starts a new undo step
+        editor.move_left(false);
+        editor.insert_char('-');
+        assert_eq!(editor.buffer().to_string(), "axy-z\nbxyz-\ncxyz-");
+
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "axyz\nbxyz\ncxyz");
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "a\nb\nc");
+        editor.redo();
+        assert_eq!(editor.buffer().to_string(), "axyz\nbxyz\ncxyz");
+    }
+
+    #[test]
+    fn test_cursors_merge_when_edits_meet() {
+        let mut editor = Editor::new();
+        editor.insert_text("abcd");
+        editor.set_cursor_position(0, 1, false);
+        editor.add_cursor_at(0, 3);
+        assert_eq!(editor.cursor_count(), 2);
+
+        // The first cursor reaches the start, the second catches up with it
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "bd");
+        assert_eq!(editor.cursor_count(), 2);
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "d");
+        assert_eq!(editor.cursor_count(), 1);
+        assert_eq!(editor.cursor_position(), Position::new(0, 0));
+
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "abcd");
+    }
+
+    #[test]
+    fn test_select_all_occurrences() {
+        let mut editor = Editor::new();
+        editor.insert_text("let ab = ab + Ab;\nab(ab)");
+        editor.set_cursor_position(1, 3, false);
+        editor.set_cursor_position(1, 5, true);
+
+        // Case-sensitive, on every line
+        assert_eq!(editor.select_all_occurrences(), 4);
+        assert_eq!(editor.cursor_count(), 4);
+        // The original selection stays primary
+        assert_eq!(editor.cursor_position(), Position::new(1, 5));
+
+        editor.insert_text("xy");
+        assert_eq!(editor.buffer().to_string(), "let xy = xy + Ab;\nxy(xy)");
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "let ab = ab + Ab;\nab(ab)");
+
+        // Without a selection the word under the cursor is used
+        let mut editor = Editor::new();
+        editor.insert_text("aaaa a");
+        editor.set_cursor_position(0, 5, false);
+        assert_eq!(editor.select_all_occurrences(), 5);
+        assert_eq!(editor.selected_text().as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn test_replace_in_several_selections() {
+        // Two regions on the same line; the lone "x" outside them is left alone
+        let mut editor = Editor::new();
+        editor.insert_text("xx = xx + x");
+        editor.set_cursor_position(0, 0, false);
+        editor.set_cursor_position(0, 2, true);
+        assert_eq!(editor.select_all_occurrences(), 2);
+        assert!(editor.set_search_in_selection());
+        assert_eq!(editor.search_region_count(), 2);
+        assert_eq!(editor.cursor_count(), 1);
+
+        assert_eq!(editor.find("x"), 4);
+        assert_eq!(editor.search_status().as_deref(), Some("4 matches in 2 selections"));
+
+        // The replacement contains the query but isn't matched again
+        assert_eq!(editor.replace_all("yxy"), 4);
+        assert_eq!(editor.buffer().to_string(), "yxyyxy = yxyyxy + x");
+        assert_eq!(editor.search().ranges(), &[(0, 6), (9, 15)]);
+        assert_eq!(editor.all_cursor_positions(), vec![(0, 0), (0, 9)]);
+
+        // One undo step
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "xx = xx + x");
+    }
+
+    #[test]
+    fn test_replace_in_adjacent_regions() {
+        let mut editor = Editor::new();
+        editor.insert_text("abab");
+        editor.set_search_regions(vec![(0, 2), (2, 4)]);
+
+        // Matches don't span the border between regions
+        assert_eq!(editor.find("ba"), 0);
+        assert_eq!(editor.find("ab"), 2);
+        assert_eq!(editor.replace_all("c"), 2);
+        assert_eq!(editor.buffer().to_string(), "cc");
+        assert_eq!(editor.search().ranges(), &[(0, 1), (1, 2)]);
+        assert_eq!(editor.all_cursor_positions(), vec![(0, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn test_line_change_status() {
+        let mut editor = Editor::new();
+        editor.set_buffer(TextBuffer::from_str("one\ntwo\nthree\nfour"));
+        assert!(!editor.update_line_changes());
+
+        editor.set_cursor_position(0, 3, false);
+        editor.insert_text("!");
+        editor.set_cursor_position(2, 0, false);
+        editor.insert_text("new\n");
+        // Removing "four" and the line break before it
+        editor.set_cursor_position(3, 5, false);
+        editor.set_cursor_position(4, 4, true);
+        editor.delete_backward();
+        assert_eq!(editor.buffer().to_string(), "one!\ntwo\nnew\nthree");
+
+        // Nothing is recomputed until asked
+        assert!(editor.line_changes_stale());
+        assert_eq!(editor.line_change_status(0), None);
+        assert!(editor.update_line_changes());
+        assert!(!editor.line_changes_stale());
+        assert_eq!(editor.line_change_status(0), Some(ChangeKind::Modified));
+        assert_eq!(editor.line_change_status(1), None);
+        assert_eq!(editor.line_change_status(2), Some(ChangeKind::Added));
+        // Removed lines are marked on the line above them
+        assert_eq!(editor.line_change_status(3), Some(ChangeKind::Deleted));
+
+        // Undoing everything leaves no markers
+        editor.undo();
+        editor.undo();
+        editor.undo();
+        editor.update_line_changes();
+        assert!(editor.line_changes().is_empty());
+    }
+
+    #[test]
+    fn test_line_changes_cleared_on_save() {
+        let mut editor = Editor::new();
+        editor.insert_text("a\nb");
+        // A buffer that was never saved has nothing to compare against
+        editor.update_line_changes();
+        assert_eq!(editor.line_change_status(0), None);
+
+        editor.set_buffer(TextBuffer::from_str("a\nb"));
+        editor.insert_text("x");
+        editor.update_line_changes();
+        assert_eq!(editor.line_change_status(1), Some(ChangeKind::Modified));
+
+        let (_, version) = editor.save_snapshot();
+        assert!(editor.mark_saved(version));
+        assert!(!editor.line_changes_stale());
+        assert_eq!(editor.line_change_status(1), None);
+    }
+
+    #[test]
+    fn test_whitespace_only_line_changes() {
+        let mut editor = Editor::new();
+        editor.set_buffer(TextBuffer::from_str("if x {\n\ty();\n}"));
+        editor.set_cursor_position(1, 0, false);
+        editor.set_cursor_position(1, 1, true);
+        editor.insert_text("    ");
+        editor.update_line_changes();
+        assert_eq!(editor.line_change_status(1), Some(ChangeKind::Modified));
+        assert!(editor.is_whitespace_only_change(1));
+
+        editor.set_diff_ignore_whitespace(true);
+        assert!(editor.line_changes_stale());
+        editor.update_line_changes();
+        assert_eq!(editor.line_change_status(1), None);
+    }
+
+    #[test]
+    fn test_copy_and_paste_per_cursor() {
+        let mut editor = Editor::new();
+        editor.insert_text("one two three");
+        editor.multi_cursors.set_selections(
+            vec![
+                Selection { anchor: 8, cursor: 13 },
+                Selection { anchor: 0, cursor: 3 },
+                Selection::new(7),
+            ],
+            0,
+        );
+        editor.cursor = editor.multi_cursors.primary().clone();
+
+        // Pieces come in buffer order; a cursor without a selection adds ""
+        let pieces = editor.cursor_selected_texts().unwrap();
+        assert_eq!(pieces, vec!["one", "", "three"]);
+        assert_eq!(editor.cut_selections().unwrap(), pieces);
+        assert_eq!(editor.buffer().to_string(), " two ");
+
+        // Each cursor gets its piece back, as one undo step
+        editor.paste_each(&["1".to_string(), "2".to_string(), "3".to_string()]);
+        assert_eq!(editor.buffer().to_string(), "1 two2 3");
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), " two ");
+
+        // Without a selection anywhere there is nothing to copy
+        editor.collapse_cursors();
+        editor.clear_selection();
+        assert_eq!(editor.cursor_selected_texts(), None);
+    }
+
+    #[test]
+    fn test_toggle_cursor_at() {
+        let mut editor = Editor::new();
+        editor.insert_text("one\ntwo\nthree");
+        editor.set_cursor_position(0, 1, false);
+
+        assert!(editor.toggle_cursor_at(1, 2));
+        assert!(editor.toggle_cursor_at(2, 0));
+        assert_eq!(editor.all_cursor_positions(), vec![(0, 1), (1, 2), (2, 0)]);
+
+        // Toggling an existing cursor removes it, the primary one included
+        assert!(!editor.toggle_cursor_at(1, 2));
+        assert!(!editor.toggle_cursor_at(0, 1));
+        assert_eq!(editor.all_cursor_positions(), vec![(2, 0)]);
+        assert_eq!(editor.cursor_position(), Position::new(2, 0));
+
+        // ...but never the last one
+        assert!(!editor.toggle_cursor_at(2, 0));
+        assert_eq!(editor.cursor_count(), 1);
+    }
+
+    #[test]
+    fn test_embedding() {
+        let mut editor = Editor::from_text("alpha beta\ngamma");
+        assert!(!editor.is_modified());
+        assert!(!editor.can_undo());
+
+        editor.insert_at(5, ",");
+        editor.delete_chars(12, 18);
+        assert_eq!(editor.text(), "alpha, beta\n");
+        assert_eq!(editor.text_range(7, 11), "beta");
+        // Ranges past the end are clamped
+        assert_eq!(editor.text_range(7, 100), "beta\n");
+        editor.replace_chars(0, 5, "omega");
+        assert_eq!(editor.text(), "omega, beta\n");
+        assert_eq!(editor.scroll_offset(), 0);
+
+        // Each call is its own undo step
+        editor.undo();
+        assert_eq!(editor.text(), "alpha, beta\n");
+        editor.undo();
+        editor.undo();
+        assert_eq!(editor.text(), "alpha beta\ngamma");
+
+        let matches = editor.find_matches("A", false, false).unwrap();
+        assert_eq!(matches.len(), 5);
+        assert!(editor.find_matches("A", true, false).unwrap().is_empty());
+        let words = editor.find_matches(r"\b\w+a\b", false, true).unwrap();
+        assert_eq!(words.iter().map(|m| editor.text_range(m.start, m.end)).collect::<Vec<_>>(), ["alpha", "beta", "gamma"]);
+        assert!(editor.find_matches("(", false, true).is_err());
+        // The search bar's state is untouched
+        assert!(!editor.search().is_active());
+    }
+
+    #[test]
+    fn test_add_cursor_keeps_column() {
+        let mut editor = Editor::new();
+        editor.insert_text("long line here\nab\nanother long line\nx");
+        editor.set_cursor_position(0, 10, false);
+
+        // The short line clamps the new cursor, the line after restores it
+        editor.add_cursor_below();
+        assert_eq!(editor.all_cursor_positions(), vec![(0, 10), (1, 2)]);
+        editor.add_cursor_below();
+        assert_eq!(editor.all_cursor_positions(), vec![(0, 10), (1, 2), (2, 10)]);
+        editor.add_cursor_below();
+        assert_eq!(editor.all_cursor_positions(), vec![(0, 10), (1, 2), (2, 10), (3, 1)]);
+        // Nothing below the last line
+        editor.add_cursor_below();
+        assert_eq!(editor.cursor_count(), 4);
+
+        // Going up from the bottom of a column works the same way
+        editor.collapse_cursors();
+        editor.set_cursor_position(2, 10, false);
+        editor.add_cursor_above();
+        editor.add_cursor_above();
+        assert_eq!(editor.all_cursor_positions(), vec![(0, 10), (1, 2), (2, 10)]);
+
+        // A block's caret keeps the block's column past a short line
+        editor.collapse_cursors();
+        editor.set_cursor_position(0, 10, false);
+        editor.start_block_selection();
+        editor.extend_block_selection(1, 10);
+        editor.exit_block_selection();
+        editor.move_down(false);
+        assert_eq!(editor.cursor_position(), Position::new(2, 10));
+    }
 }
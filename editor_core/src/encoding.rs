@@ -0,0 +1,327 @@
+//! Text encodings files are read and written in.
+//!
+//! Besides the Unicode ones, Shift-JIS (through `encoding_rs`) and Latin-1
+//! are supported. Latin-1 decodes any bytes, so a file in another legacy
+//! encoding opens garbled, but every byte survives a save unchanged.
+
+use std::borrow::Cow;
+use std::io;
+
+/// Bytes [`Encoding::detect`] looks at when guessing UTF-16 without a BOM.
+const DETECT_SAMPLE_BYTES: usize = 4096;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16_LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16_BE_BOM: &[u8] = b"\xFE\xFF";
+
+/// A file's text encoding. The buffer always holds UTF-8; files in another
+/// encoding are decoded on load and encoded back on save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// UTF-8 without a byte order mark.
+    #[default]
+    Utf8,
+    /// UTF-8 starting with a byte order mark.
+    Utf8Bom,
+    /// Little-endian UTF-16, written with a byte order mark.
+    Utf16Le,
+    /// Big-endian UTF-16, written with a byte order mark.
+    Utf16Be,
+    /// Shift-JIS, the Japanese double-byte encoding.
+    ShiftJis,
+    /// ISO-8859-1: every byte is the character of the same code point.
+    Latin1,
+}
+
+impl Encoding {
+    /// Every encoding, in the order [`Encoding::next`] steps through them.
+    pub const ALL: [Encoding; 6] = [
+        Encoding::Utf8,
+        Encoding::Utf8Bom,
+        Encoding::Utf16Le,
+        Encoding::Utf16Be,
+        Encoding::ShiftJis,
+        Encoding::Latin1,
+    ];
+
+    /// Returns the name shown in the status bar, e.g. `UTF-16 LE`.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8Bom => "UTF-8 BOM",
+            Encoding::Utf16Le => "UTF-16 LE",
+            Encoding::Utf16Be => "UTF-16 BE",
+            Encoding::ShiftJis => "Shift-JIS",
+            Encoding::Latin1 => "Latin-1",
+        }
+    }
+
+    /// Returns the encoding after this one in [`Encoding::ALL`], wrapping
+    /// around at the end.
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|e| e == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// Returns whether this is a Unicode encoding, which represents any
+    /// text.
+    pub fn is_unicode(&self) -> bool {
+        matches!(self, Encoding::Utf8 | Encoding::Utf8Bom | Encoding::Utf16Le | Encoding::Utf16Be)
+    }
+
+    /// Returns the byte order mark written before the text, if any.
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8Bom => UTF8_BOM,
+            Encoding::Utf16Le => UTF16_LE_BOM,
+            Encoding::Utf16Be => UTF16_BE_BOM,
+            Encoding::Utf8 | Encoding::ShiftJis | Encoding::Latin1 => &[],
+        }
+    }
+
+    /// Guesses the encoding of a file starting with `bytes`.
+    ///
+    /// A byte order mark decides. Without one, text whose NUL bytes sit
+    /// almost all at odd (or even) offsets is taken for ASCII-heavy UTF-16,
+    /// valid UTF-8 for UTF-8, valid Shift-JIS with a lead byte Latin-1 text
+    /// wouldn't have for Shift-JIS, and anything else for Latin-1, which
+    /// decodes every byte. `bytes` may end inside a character.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(UTF8_BOM) {
+            return Encoding::Utf8Bom;
+        }
+        if bytes.starts_with(UTF16_LE_BOM) {
+            return Encoding::Utf16Le;
+        }
+        if bytes.starts_with(UTF16_BE_BOM) {
+            return Encoding::Utf16Be;
+        }
+        let sample = &bytes[..bytes.len().min(DETECT_SAMPLE_BYTES)];
+        let (mut even_nuls, mut odd_nuls) = (0, 0);
+        for (i, _) in sample.iter().enumerate().filter(|&(_, &b)| b == 0) {
+            if i.is_multiple_of(2) {
+                even_nuls += 1;
+            } else {
+                odd_nuls += 1;
+            }
+        }
+        // At least a quarter of the code units have a NUL half, on one side
+        let units = sample.len() / 2;
+        if units > 0 && odd_nuls * 4 >= units && even_nuls * 8 < odd_nuls {
+            return Encoding::Utf16Le;
+        }
+        if units > 0 && even_nuls * 4 >= units && odd_nuls * 8 < even_nuls {
+            return Encoding::Utf16Be;
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(_) => Encoding::Utf8,
+            // Cut off inside a character, which the rest of the file finishes
+            Err(e) if e.error_len().is_none() => Encoding::Utf8,
+            Err(_) if is_shift_jis(bytes) => Encoding::ShiftJis,
+            Err(_) => Encoding::Latin1,
+        }
+    }
+
+    /// Decodes a whole file's `bytes`, skipping this encoding's byte order
+    /// mark if they start with it.
+    pub fn decode(&self, bytes: &[u8]) -> io::Result<String> {
+        let bytes = bytes.strip_prefix(self.bom()).unwrap_or(bytes);
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => {
+                String::from_utf8(bytes.to_vec()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                if !bytes.len().is_multiple_of(2) {
+                    return Err(invalid_data("UTF-16 text has an odd number of bytes"));
+                }
+                let units = bytes.chunks_exact(2).map(|pair| {
+                    let pair = [pair[0], pair[1]];
+                    if *self == Encoding::Utf16Le {
+                        u16::from_le_bytes(pair)
+                    } else {
+                        u16::from_be_bytes(pair)
+                    }
+                });
+                char::decode_utf16(units)
+                    .collect::<Result<String, _>>()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+            Encoding::ShiftJis => encoding_rs::SHIFT_JIS
+                .decode_without_bom_handling_and_without_replacement(bytes)
+                .map(|text| text.into_owned())
+                .ok_or_else(|| invalid_data("Not valid Shift-JIS text")),
+            Encoding::Latin1 => Ok(bytes.iter().map(|&b| char::from(b)).collect()),
+        }
+    }
+
+    /// Encodes `text`, starting with this encoding's byte order mark.
+    /// Characters the encoding can't represent are written as `?`.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        let unmappable = self.unmappable(text);
+        let text = if unmappable.is_empty() {
+            Cow::Borrowed(text)
+        } else {
+            Cow::Owned(text.chars().map(|ch| if unmappable.contains(&ch) { '?' } else { ch }).collect())
+        };
+        let mut bytes = self.bom().to_vec();
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => bytes.extend_from_slice(text.as_bytes()),
+            Encoding::Utf16Le => bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes)),
+            Encoding::Utf16Be => bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes)),
+            Encoding::ShiftJis => bytes.extend_from_slice(&encoding_rs::SHIFT_JIS.encode(&text).0),
+            Encoding::Latin1 => bytes.extend(text.chars().filter_map(|ch| u8::try_from(ch).ok())),
+        }
+        bytes
+    }
+
+    /// Returns the characters of `text` this encoding can't represent, each
+    /// once, in the order they first appear.
+    pub fn unmappable(&self, text: &str) -> Vec<char> {
+        let mut chars: Vec<char> = match self {
+            _ if self.is_unicode() => return Vec::new(),
+            Encoding::ShiftJis => {
+                if !encoding_rs::SHIFT_JIS.encode(text).2 {
+                    return Vec::new();
+                }
+                let mut buf = [0; 4];
+                text.chars()
+                    .filter(|ch| !ch.is_ascii() && encoding_rs::SHIFT_JIS.encode(ch.encode_utf8(&mut buf)).2)
+                    .collect()
+            }
+            _ => text.chars().filter(|&ch| u8::try_from(ch).is_err()).collect(),
+        };
+        let mut seen = std::collections::HashSet::new();
+        chars.retain(|&ch| seen.insert(ch));
+        chars
+    }
+
+    /// Fails with [`io::ErrorKind::InvalidData`] if this encoding can't
+    /// represent characters of `text`, naming the first of them.
+    pub fn check_mappable(&self, text: &str) -> io::Result<()> {
+        let chars = self.unmappable(text);
+        let Some(&first) = chars.first() else {
+            return Ok(());
+        };
+        let mut message = format!("{} can't represent '{}' (U+{:04X})", self.label(), first, u32::from(first));
+        if chars.len() > 1 {
+            let plural = if chars.len() == 2 { "" } else { "s" };
+            message.push_str(&format!(" and {} other character{}", chars.len() - 1, plural));
+        }
+        Err(invalid_data(&message))
+    }
+}
+
+/// Returns whether `bytes` read as Shift-JIS, with at least one lead byte
+/// from 0x81 to 0x9F. Those are control characters in Latin-1, so text
+/// that happens to be valid Shift-JIS without them is more likely Latin-1.
+/// `bytes` may end inside a character.
+fn is_shift_jis(bytes: &[u8]) -> bool {
+    let mut decoder = encoding_rs::SHIFT_JIS.new_decoder_without_bom_handling();
+    let Some(capacity) = decoder.max_utf8_buffer_length_without_replacement(bytes.len()) else {
+        return false;
+    };
+    let mut text = String::with_capacity(capacity);
+    let (result, _) = decoder.decode_to_string_without_replacement(bytes, &mut text, false);
+    if result != encoding_rs::DecoderResult::InputEmpty {
+        return false;
+    }
+    // Being valid, a byte in a lead range starts a pair unless it ends one
+    let mut iter = bytes.iter();
+    while let Some(&byte) = iter.next() {
+        if (0x81..=0x9F).contains(&byte) {
+            return true;
+        }
+        if (0xE0..=0xFC).contains(&byte) {
+            iter.next();
+        }
+    }
+    false
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Encoding::detect(b"plain ascii\n"), Encoding::Utf8);
+        assert_eq!(Encoding::detect("caf\u{e9}".as_bytes()), Encoding::Utf8);
+        assert_eq!(Encoding::detect(b""), Encoding::Utf8);
+        // Cut off inside the é
+        assert_eq!(Encoding::detect(&"caf\u{e9}".as_bytes()[..4]), Encoding::Utf8);
+        assert_eq!(Encoding::detect(b"\xEF\xBB\xBFhi"), Encoding::Utf8Bom);
+        assert_eq!(Encoding::detect(b"\xFF\xFEh\0i\0"), Encoding::Utf16Le);
+        assert_eq!(Encoding::detect(b"\xFE\xFF\0h\0i"), Encoding::Utf16Be);
+        assert_eq!(Encoding::detect(b"h\0e\0l\0l\0o\0"), Encoding::Utf16Le);
+        assert_eq!(Encoding::detect(b"\0h\0e\0l\0l\0o"), Encoding::Utf16Be);
+        assert_eq!(Encoding::detect(b"caf\xE9\n"), Encoding::Latin1);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "caf\u{e9} na\u{ef}ve\r\n";
+        for encoding in Encoding::ALL {
+            // Shift-JIS has no accented letters
+            let text = if encoding == Encoding::ShiftJis { "\u{65e5}\u{672c} text\r\n" } else { text };
+            let bytes = encoding.encode(text);
+            assert!(bytes.starts_with(encoding.bom()));
+            assert_eq!(Encoding::detect(&bytes), encoding, "{}", encoding.label());
+            assert_eq!(encoding.decode(&bytes).unwrap(), text);
+        }
+        assert_eq!(Encoding::Latin1.encode("\u{e9}"), b"\xE9");
+        assert_eq!(Encoding::Utf16Le.encode("\u{1F600}"), b"\xFF\xFE\x3D\xD8\x00\xDE");
+    }
+
+    #[test]
+    fn test_shift_jis() {
+        // "日本" in Shift-JIS
+        let bytes = b"\x93\xfa\x96\x7b\n";
+        assert_eq!(Encoding::detect(bytes), Encoding::ShiftJis);
+        assert_eq!(Encoding::ShiftJis.decode(bytes).unwrap(), "\u{65e5}\u{672c}\n");
+        // Cut off inside the first character
+        assert_eq!(Encoding::detect(&bytes[..1]), Encoding::ShiftJis);
+        assert!(Encoding::ShiftJis.decode(&bytes[..1]).is_err());
+
+        // "naïve" in Latin-1 happens to be valid Shift-JIS too
+        assert_eq!(Encoding::detect(b"na\xefve\n"), Encoding::Latin1);
+    }
+
+    #[test]
+    fn test_unsupported_encoding_round_trips() {
+        // "한국" in EUC-KR
+        let bytes = b"\xc7\xd1\xb1\xb9\n";
+        assert_eq!(Encoding::detect(bytes), Encoding::Latin1);
+        let text = Encoding::Latin1.decode(bytes).unwrap();
+        assert_eq!(Encoding::Latin1.encode(&text), bytes);
+    }
+
+    #[test]
+    fn test_unrepresentable() {
+        let err = Encoding::Latin1.check_mappable("\u{20ac}").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("U+20AC"));
+        assert_eq!(Encoding::Latin1.encode("\u{20ac}5"), b"?5");
+
+        assert_eq!(Encoding::ShiftJis.unmappable("a\u{20ac}\u{65e5}\u{e9}\u{20ac}"), ['\u{20ac}', '\u{e9}']);
+        assert_eq!(Encoding::Utf16Be.unmappable("\u{20ac}"), []);
+        let err = Encoding::ShiftJis.check_mappable("\u{e9}\u{20ac}\u{65e5}").unwrap_err();
+        assert_eq!(err.to_string(), "Shift-JIS can't represent '\u{e9}' (U+00E9) and 1 other character");
+        assert_eq!(Encoding::ShiftJis.encode("\u{e9}\u{20ac}\u{65e5}"), b"??\x93\xfa");
+        assert!(Encoding::Utf8.decode(b"\xff").is_err());
+        assert!(Encoding::Utf16Le.decode(b"a").is_err());
+    }
+
+    #[test]
+    fn test_next_cycles() {
+        let mut encoding = Encoding::Utf8;
+        for _ in 0..Encoding::ALL.len() {
+            encoding = encoding.next();
+        }
+        assert_eq!(encoding, Encoding::Utf8);
+        assert_eq!(Encoding::Latin1.next(), Encoding::Utf8);
+    }
+}
@@ -0,0 +1,116 @@
+//! Formatting buffer text for pasting elsewhere, e.g. into an issue.
+//!
+//! Both formats work on whole lines: a selection that starts or ends in
+//! the middle of a line is widened to all of its lines, see
+//! [`selected_lines`].
+
+use crate::buffer::TextBuffer;
+use std::ops::Range;
+
+/// Returns the lines a selection of the char range `selection` covers, or
+/// every line without a selection, except the empty one after a final line
+/// break. A selection ending at the start of a line, like one made by
+/// selecting whole lines, doesn't cover that line.
+pub fn selected_lines(buffer: &TextBuffer, selection: Option<(usize, usize)>) -> Range<usize> {
+    let Some((start, end)) = selection.filter(|(start, end)| start != end) else {
+        let lines = buffer.len_lines();
+        let trailing_empty = lines > 1 && buffer.line_len_chars(lines - 1) == 0;
+        return 0..lines - usize::from(trailing_empty);
+    };
+    let (first, _) = buffer.char_to_line_col(start);
+    let (last, col) = buffer.char_to_line_col(end);
+    let last = if col == 0 && last > first { last - 1 } else { last };
+    first..last + 1
+}
+
+/// Returns `lines` of the buffer, each prefixed by its right-aligned
+/// 1-based number and a separator, e.g. `  42 | text`. The numbers are as
+/// wide as the largest one.
+pub fn with_line_numbers(buffer: &TextBuffer, lines: Range<usize>) -> String {
+    let width = lines.end.to_string().len();
+    let mut text = String::new();
+    for line in lines {
+        let content = buffer.line(line).unwrap_or_default();
+        if content.is_empty() {
+            text.push_str(&format!("{:>width$} |\n", line + 1));
+        } else {
+            text.push_str(&format!("{:>width$} | {}\n", line + 1, content));
+        }
+    }
+    text
+}
+
+/// Returns `lines` of the buffer in a fenced Markdown code block tagged
+/// with `language`, e.g. `rust`; untagged if it is empty. The fence is
+/// longer than any run of backticks in the text.
+pub fn markdown_code_block(buffer: &TextBuffer, lines: Range<usize>, language: &str) -> String {
+    let body: Vec<String> = lines.map(|line| buffer.line(line).unwrap_or_default()).collect();
+    let longest_run = body
+        .iter()
+        .flat_map(|line| line.split(|c| c != '`'))
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    let mut text = format!("{}{}\n", fence, language);
+    for line in body {
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text.push_str(&fence);
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selected_lines() {
+        let buffer = TextBuffer::from_str("one\ntwo\nthree\nfour");
+        assert_eq!(selected_lines(&buffer, None), 0..4);
+        // Mid-line ends widen to whole lines
+        assert_eq!(selected_lines(&buffer, Some((5, 10))), 1..3);
+        // Ending at a line start leaves that line out
+        assert_eq!(selected_lines(&buffer, Some((4, 14))), 1..3);
+        // An empty selection is none
+        assert_eq!(selected_lines(&buffer, Some((5, 5))), 0..4);
+        let buffer = TextBuffer::from_str("one\ntwo\n");
+        assert_eq!(selected_lines(&buffer, None), 0..2);
+    }
+
+    #[test]
+    fn test_with_line_numbers() {
+        let buffer = TextBuffer::from_str("fn main() {\n\n    run();\n}");
+        assert_eq!(
+            with_line_numbers(&buffer, 0..4),
+            "1 | fn main() {\n2 |\n3 |     run();\n4 | }\n"
+        );
+
+        // Numbers line up when they get wider
+        let text: Vec<String> = (1..=1001).map(|i| format!("line {}", i)).collect();
+        let buffer = TextBuffer::from_str(&text.join("\n"));
+        assert_eq!(
+            with_line_numbers(&buffer, 997..1001),
+            " 998 | line 998\n 999 | line 999\n1000 | line 1000\n1001 | line 1001\n"
+        );
+    }
+
+    #[test]
+    fn test_markdown_code_block() {
+        let buffer = TextBuffer::from_str("let a = 1;\nlet b = 2;\n");
+        assert_eq!(
+            markdown_code_block(&buffer, 0..2, "rust"),
+            "```rust\nlet a = 1;\nlet b = 2;\n```\n"
+        );
+        assert_eq!(markdown_code_block(&buffer, 1..2, ""), "```\nlet b = 2;\n```\n");
+
+        // Backtick fences in the text need a longer one around them
+        let buffer = TextBuffer::from_str("```sh\nls\n```");
+        assert_eq!(
+            markdown_code_block(&buffer, 0..3, "markdown"),
+            "````markdown\n```sh\nls\n```\n````\n"
+        );
+    }
+}
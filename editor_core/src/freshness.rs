@@ -0,0 +1,135 @@
+//! How up to date a buffer's diagnostics and file are.
+//!
+//! A buffer remembers when it was last edited, when its text was last sent
+//! to the language server, when the server last published diagnostics for
+//! it and when it was last saved. Diagnostics are stale when they predate
+//! edits the server hasn't answered yet by more than a threshold, e.g.
+//! while a slow server rebuilds; they are drawn dimmed until fresh ones
+//! arrive.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+/// When things last happened to a buffer. `None` means not yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferTimes {
+    /// Last change to the text.
+    pub last_edit: Option<Instant>,
+    /// Last didChange sent to the language server.
+    pub last_change_sent: Option<Instant>,
+    /// Last diagnostics published by the language server.
+    pub last_diagnostics: Option<Instant>,
+    /// Last write to the file.
+    pub last_saved: Option<Instant>,
+}
+
+impl BufferTimes {
+    /// Returns whether the diagnostics are stale; see [`diagnostics_stale`].
+    pub fn diagnostics_stale(&self, threshold: Duration) -> bool {
+        diagnostics_stale(self.last_edit, self.last_change_sent, self.last_diagnostics, threshold)
+    }
+}
+
+/// What a file on disk looked like when last read or written, to tell
+/// whether something else changed it since.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskStamp {
+    /// Modification time, if the platform reports one.
+    pub modified: Option<SystemTime>,
+    /// Size in bytes.
+    pub len: u64,
+}
+
+impl DiskStamp {
+    /// Reads the stamp of the file at `path`.
+    pub fn of(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        Ok(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Returns whether diagnostics published at `last_diagnostics` are out of
+/// date: they are older than the last edit by more than `threshold`, and
+/// they don't answer a didChange sent after that edit.
+///
+/// Without an edit or without diagnostics nothing is stale.
+pub fn diagnostics_stale(
+    last_edit: Option<Instant>,
+    last_change_sent: Option<Instant>,
+    last_diagnostics: Option<Instant>,
+    threshold: Duration,
+) -> bool {
+    let (Some(edit), Some(published)) = (last_edit, last_diagnostics) else {
+        return false;
+    };
+    // Published after the server was sent the latest text
+    let answered = last_change_sent.is_some_and(|sent| sent >= edit && published >= sent);
+    !answered && edit.saturating_duration_since(published) > threshold
+}
+
+/// Formats how long ago something happened, e.g. "just now", "42s ago",
+/// "5m ago" or "3h ago".
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    if secs < 5 {
+        "just now".to_string()
+    } else if secs < 60 {
+        format!("{}s ago", secs)
+    } else if secs < 60 * 60 {
+        format!("{}m ago", secs / 60)
+    } else if secs < 24 * 60 * 60 {
+        format!("{}h ago", secs / (60 * 60))
+    } else {
+        format!("{}d ago", secs / (24 * 60 * 60))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostics_stale() {
+        let t0 = Instant::now();
+        let at = |secs: u64| Some(t0 + Duration::from_secs(secs));
+        let threshold = Duration::from_secs(2);
+
+        // Nothing to be stale
+        assert!(!diagnostics_stale(None, None, at(0), threshold));
+        assert!(!diagnostics_stale(at(0), at(0), None, threshold));
+
+        // Diagnostics newer than the edit they were sent
+        assert!(!diagnostics_stale(at(10), at(11), at(12), threshold));
+
+        // An edit within the threshold of the diagnostics
+        assert!(!diagnostics_stale(at(11), at(11), at(10), threshold));
+        // One well after them, sent or not, waiting for an answer
+        assert!(diagnostics_stale(at(20), at(20), at(10), threshold));
+        assert!(diagnostics_stale(at(20), at(15), at(10), threshold));
+        // Diagnostics published since, but for text older than the edit
+        assert!(diagnostics_stale(at(20), at(5), at(17), threshold));
+        assert!(!diagnostics_stale(at(20), at(5), at(19), threshold));
+
+        let times = BufferTimes {
+            last_edit: at(20),
+            last_change_sent: at(20),
+            last_diagnostics: at(21),
+            last_saved: None,
+        };
+        assert!(!times.diagnostics_stale(threshold));
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(Duration::from_secs(0)), "just now");
+        assert_eq!(format_age(Duration::from_secs(42)), "42s ago");
+        assert_eq!(format_age(Duration::from_secs(5 * 60 + 59)), "5m ago");
+        assert_eq!(format_age(Duration::from_secs(3 * 60 * 60)), "3h ago");
+        assert_eq!(format_age(Duration::from_secs(50 * 60 * 60)), "2d ago");
+    }
+}
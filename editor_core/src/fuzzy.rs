@@ -0,0 +1,79 @@
+//! Fuzzy matching.
+//!
+//! Matches a pattern as a case-insensitive subsequence of a candidate, so
+//! `fmt` matches `format` and `sbc` matches `set_buffer_content`, and scores
+//! the match for ordering a list narrowed as the user types.
+
+/// Returns how well `pattern` matches `text`, or `None` if the pattern's
+/// characters don't all appear in `text` in order. Higher is better:
+/// consecutive characters, characters starting a word and characters of
+/// the same case score extra, and skipping characters before the first
+/// match costs a little. The empty pattern matches everything equally.
+pub fn score(pattern: &str, text: &str) -> Option<i32> {
+    let text: Vec<char> = text.chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut last_match: Option<usize> = None;
+    for p in pattern.chars() {
+        let i = next + text[next..].iter().position(|&ch| eq_ignore_case(ch, p))?;
+        score += 1;
+        if text[i] == p {
+            score += 1;
+        }
+        if last_match.is_some_and(|last| last + 1 == i) {
+            score += 4;
+        } else if is_word_start(&text, i) {
+            score += 3;
+        }
+        if last_match.is_none() {
+            score -= i.min(5) as i32;
+        }
+        last_match = Some(i);
+        next = i + 1;
+    }
+    Some(score)
+}
+
+fn eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Returns true if `text[i]` starts a word: it follows a non-alphanumeric
+/// character or is an upper case letter after a lower case one.
+fn is_word_start(text: &[char], i: usize) -> bool {
+    let Some(&prev) = i.checked_sub(1).and_then(|p| text.get(p)) else {
+        return true;
+    };
+    !prev.is_alphanumeric() || (prev.is_lowercase() && text[i].is_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subsequence_matching() {
+        assert!(score("fmt", "format").is_some());
+        assert!(score("FMT", "format").is_some());
+        assert!(score("sbc", "set_buffer_content").is_some());
+        assert!(score("", "anything").is_some());
+        assert!(score("tmf", "format").is_none());
+        assert!(score("formats", "format").is_none());
+    }
+
+    #[test]
+    fn test_better_matches_score_higher() {
+        let better = |pattern: &str, a: &str, b: &str| {
+            assert!(score(pattern, a) > score(pattern, b), "{:?} should beat {:?} for {:?}", a, b, pattern);
+        };
+        // A prefix beats a scattered match
+        better("len", "len", "line_end");
+        // Word starts beat the middle of words
+        better("gd", "goto_definition", "grid");
+        better("sb", "setBuffer", "subscribe");
+        // Same case beats a case-insensitive match
+        better("Vec", "Vec", "vector");
+        // A late start costs a little
+        better("map", "map_err", "flat_map");
+    }
+}
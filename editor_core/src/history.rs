@@ -35,6 +35,33 @@ impl EditOperation {
             },
         }
     }
+
+    /// Maps a character position in the text before this operation to the
+    /// text after it.
+    ///
+    /// Insertions at or before the position shift it right. Returns `None`
+    /// if the character at the position was deleted.
+    pub fn map_position(&self, pos: usize) -> Option<usize> {
+        match self {
+            EditOperation::Insert { position, text } => {
+                if *position <= pos {
+                    Some(pos + text.chars().count())
+                } else {
+                    Some(pos)
+                }
+            }
+            EditOperation::Delete { position, text } => {
+                let end = position + text.chars().count();
+                if pos < *position {
+                    Some(pos)
+                } else if pos >= end {
+                    Some(pos - (end - position))
+                } else {
+                    None
+                }
+            }
+        }
+    }
 }
 
 /// A group of edit operations that should be undone/redone together.
@@ -117,6 +144,29 @@ impl EditGroup {
         }
     }
 
+    /// Returns true if a whole multi-cursor edit can be coalesced with this
+    /// group: typing or backspacing one character at each of several
+    /// cursors, right after this group's edits left the cursor where it is.
+    pub fn can_coalesce_group(&self, other: &EditGroup, coalesce_window: Duration) -> bool {
+        if other.operations.len() < 2 || other.selection_before != self.selection_after {
+            return false;
+        }
+        match self.last_edit_time {
+            Some(last_time) if last_time.elapsed() <= coalesce_window => {}
+            _ => return false,
+        }
+        let is_typing = |op: &EditOperation| {
+            matches!(op, EditOperation::Insert { text, .. } if text.chars().count() == 1 && text != "\n")
+        };
+        let is_deleting = |op: &EditOperation| {
+            matches!(op, EditOperation::Delete { text, .. } if text.chars().count() == 1)
+        };
+        let all = |pred: &dyn Fn(&EditOperation) -> bool| {
+            self.operations.iter().chain(&other.operations).all(pred)
+        };
+        all(&is_typing) || all(&is_deleting)
+    }
+
     /// Merges operations from another group into this one.
     pub fn merge(&mut self, other: EditGroup) {
         self.operations.extend(other.operations);
@@ -216,6 +266,11 @@ impl History {
                                 return;
                             }
                         }
+                        // Multi-cursor typing coalesces edit by edit
+                        if last_group.can_coalesce_group(&group, self.coalesce_window) {
+                            last_group.merge(group);
+                            return;
+                        }
                     }
                 }
                 self.push_undo(group);
@@ -297,6 +352,50 @@ impl History {
     }
 }
 
+/// Joins operations that continue one another into one, so a coalesced
+/// group of typed characters is applied as a single insertion or deletion
+/// rather than one buffer edit per character.
+pub fn merge_adjacent(ops: impl IntoIterator<Item = EditOperation>) -> Vec<EditOperation> {
+    let mut merged: Vec<EditOperation> = Vec::new();
+    // Length in characters of the last merged operation's text
+    let mut last_len = 0;
+    for op in ops {
+        let len = match &op {
+            EditOperation::Insert { text, .. } | EditOperation::Delete { text, .. } => text.chars().count(),
+        };
+        match (merged.last_mut(), op) {
+            // Typed after the last insertion, or right before it
+            (Some(EditOperation::Insert { position, text }), EditOperation::Insert { position: next, text: next_text })
+                if next == *position + last_len || next == *position =>
+            {
+                if next == *position {
+                    text.insert_str(0, &next_text);
+                } else {
+                    text.push_str(&next_text);
+                }
+                last_len += len;
+            }
+            // Deleted forward from the same spot, or backspaced before it
+            (Some(EditOperation::Delete { position, text }), EditOperation::Delete { position: next, text: next_text })
+                if next == *position || next + len == *position =>
+            {
+                if next == *position {
+                    text.push_str(&next_text);
+                } else {
+                    text.insert_str(0, &next_text);
+                    *position = next;
+                }
+                last_len += len;
+            }
+            (_, op) => {
+                merged.push(op);
+                last_len = len;
+            }
+        }
+    }
+    merged
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -474,4 +573,48 @@ mod tests {
         // Should have two separate groups when coalescing is disabled
         assert_eq!(history.undo_stack.len(), 2);
     }
+
+    #[test]
+    fn test_map_position() {
+        let insert = EditOperation::Insert {
+            position: 2,
+            text: "ab".to_string(),
+        };
+        assert_eq!(insert.map_position(1), Some(1));
+        assert_eq!(insert.map_position(2), Some(4));
+
+        let delete = EditOperation::Delete {
+            position: 2,
+            text: "ab".to_string(),
+        };
+        assert_eq!(delete.map_position(1), Some(1));
+        assert_eq!(delete.map_position(3), None);
+        assert_eq!(delete.map_position(5), Some(3));
+    }
+
+    #[test]
+    fn test_merge_adjacent() {
+        let op = |insert: bool, position: usize, text: &str| {
+            let text = text.to_string();
+            if insert {
+                EditOperation::Insert { position, text }
+            } else {
+                EditOperation::Delete { position, text }
+            }
+        };
+        let text_of = |op: &EditOperation| match op {
+            EditOperation::Insert { position, text } => (true, *position, text.clone()),
+            EditOperation::Delete { position, text } => (false, *position, text.clone()),
+        };
+
+        // Typing and inserting before, backspacing and deleting forward
+        let merged = merge_adjacent([op(true, 0, "a"), op(true, 1, "é"), op(true, 2, "c"), op(true, 0, "x")]);
+        assert_eq!(merged.iter().map(text_of).collect::<Vec<_>>(), [(true, 0, "xaéc".to_string())]);
+        let merged = merge_adjacent([op(false, 2, "c"), op(false, 1, "b"), op(false, 1, "d")]);
+        assert_eq!(merged.iter().map(text_of).collect::<Vec<_>>(), [(false, 1, "bcd".to_string())]);
+
+        // Operations elsewhere, or of the other kind, stay apart
+        let merged = merge_adjacent([op(true, 0, "a"), op(true, 5, "b"), op(false, 5, "b")]);
+        assert_eq!(merged.len(), 3);
+    }
 }
@@ -0,0 +1,218 @@
+//! Indentation settings and detection.
+
+use crate::buffer::TextBuffer;
+
+/// Default indent width in columns.
+pub const DEFAULT_INDENT_WIDTH: usize = 4;
+
+/// Number of lines scanned when detecting a file's indentation.
+const DETECT_MAX_LINES: usize = 500;
+
+/// Indentations [`IndentConfig::next`] steps through, in order.
+const CYCLE: [(IndentStyle, usize); 4] = [
+    (IndentStyle::Spaces, 2),
+    (IndentStyle::Spaces, 4),
+    (IndentStyle::Spaces, 8),
+    (IndentStyle::Tabs, DEFAULT_INDENT_WIDTH),
+];
+
+/// Whether indentation is made of spaces or tabs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndentStyle {
+    /// Indent with spaces.
+    #[default]
+    Spaces,
+    /// Indent with tab characters.
+    Tabs,
+}
+
+/// Indentation settings for a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndentConfig {
+    /// Spaces or tabs.
+    pub style: IndentStyle,
+    /// Columns per indent level (also the tab display width).
+    pub width: usize,
+}
+
+impl Default for IndentConfig {
+    fn default() -> Self {
+        Self::spaces(DEFAULT_INDENT_WIDTH)
+    }
+}
+
+impl IndentConfig {
+    /// Indent with `width` spaces per level.
+    pub fn spaces(width: usize) -> Self {
+        Self {
+            style: IndentStyle::Spaces,
+            width: width.max(1),
+        }
+    }
+
+    /// Indent with tabs displayed `width` columns wide.
+    pub fn tabs(width: usize) -> Self {
+        Self {
+            style: IndentStyle::Tabs,
+            width: width.max(1),
+        }
+    }
+
+    /// Returns the text for one indent level.
+    pub fn unit(&self) -> String {
+        match self.style {
+            IndentStyle::Spaces => " ".repeat(self.width),
+            IndentStyle::Tabs => "\t".to_string(),
+        }
+    }
+
+    /// Returns a short description for the status bar, e.g. `Spaces: 4`.
+    pub fn label(&self) -> String {
+        match self.style {
+            IndentStyle::Spaces => format!("Spaces: {}", self.width),
+            IndentStyle::Tabs => format!("Tab Size: {}", self.width),
+        }
+    }
+
+    /// Returns the indentation after this one when cycling through 2, 4
+    /// and 8 spaces and tabs. Others start the cycle over.
+    pub fn next(&self) -> Self {
+        let index = CYCLE.iter().position(|&(style, width)| (style, width) == (self.style, self.width));
+        let (style, width) = CYCLE[index.map_or(0, |i| (i + 1) % CYCLE.len())];
+        Self { style, width }
+    }
+
+    /// Returns the display width of `text`, with tabs advancing to the next
+    /// multiple of the indent width.
+    pub fn visual_width(&self, text: &str) -> usize {
+        text.chars().fold(0, |col, ch| {
+            if ch == '\t' {
+                col + self.width - col % self.width
+            } else {
+                col + 1
+            }
+        })
+    }
+
+    /// Returns the number of spaces that advance column `col` to the next
+    /// indent stop.
+    pub fn spaces_to_next_stop(&self, col: usize) -> usize {
+        self.width - col % self.width
+    }
+
+    /// Infers the indentation used by `buffer` from its leading whitespace.
+    ///
+    /// Lines indented with tabs are counted against lines indented with
+    /// spaces and the majority wins. For spaces, the width is the most common
+    /// change in indentation between consecutive indented lines. Returns
+    /// `None` if there is too little indentation to tell; the returned width
+    /// for tabs is the default, since tabs don't imply one.
+    pub fn detect(buffer: &TextBuffer) -> Option<Self> {
+        Self::detect_mixed(buffer).map(|(config, _)| config)
+    }
+
+    /// Like [`IndentConfig::detect`], also returning whether some lines are
+    /// indented with tabs and others with spaces.
+    pub fn detect_mixed(buffer: &TextBuffer) -> Option<(Self, bool)> {
+        let mut tab_lines = 0usize;
+        let mut space_lines = 0usize;
+        // Counts of indentation deltas 1..=8 between consecutive space-indented lines
+        let mut deltas = [0usize; 9];
+        let mut prev_spaces = 0usize;
+
+        for line in buffer.lines_range(0, buffer.len_lines().min(DETECT_MAX_LINES)) {
+            let content = line.trim_end_matches(['\n', '\r']);
+            if content.trim().is_empty() {
+                continue;
+            }
+            if content.starts_with('\t') {
+                tab_lines += 1;
+                continue;
+            }
+
+            let spaces = content.chars().take_while(|&c| c == ' ').count();
+            // A single space is usually alignment (e.g. " * " in block comments)
+            if spaces > 1 {
+                space_lines += 1;
+            }
+            if spaces != 1 {
+                let delta = spaces.abs_diff(prev_spaces);
+                if (2..=8).contains(&delta) {
+                    deltas[delta] += 1;
+                }
+                prev_spaces = spaces;
+            }
+        }
+
+        if tab_lines == 0 && space_lines == 0 {
+            return None;
+        }
+        let mixed = tab_lines > 0 && space_lines > 0;
+        if tab_lines > space_lines {
+            return Some((Self::tabs(DEFAULT_INDENT_WIDTH), mixed));
+        }
+
+        // Prefer the smaller width on ties so 2-space files with some 4-space
+        // jumps are still detected as 2
+        let width = (2..=8)
+            .max_by_key(|&w| (deltas[w], std::cmp::Reverse(w)))
+            .filter(|&w| deltas[w] > 0)
+            .unwrap_or(DEFAULT_INDENT_WIDTH);
+        Some((Self::spaces(width), mixed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_and_visual_width() {
+        assert_eq!(IndentConfig::spaces(2).unit(), "  ");
+        assert_eq!(IndentConfig::tabs(8).unit(), "\t");
+
+        let config = IndentConfig::spaces(4);
+        assert_eq!(config.visual_width("\t"), 4);
+        assert_eq!(config.visual_width("  \t"), 4);
+        assert_eq!(config.visual_width("\t  x"), 7);
+        assert_eq!(config.spaces_to_next_stop(0), 4);
+        assert_eq!(config.spaces_to_next_stop(6), 2);
+    }
+
+    #[test]
+    fn test_next_and_label() {
+        let mut config = IndentConfig::spaces(2);
+        let mut labels = Vec::new();
+        for _ in 0..5 {
+            labels.push(config.label());
+            config = config.next();
+        }
+        assert_eq!(labels, ["Spaces: 2", "Spaces: 4", "Spaces: 8", "Tab Size: 4", "Spaces: 2"]);
+        assert_eq!(IndentConfig::spaces(3).next(), IndentConfig::spaces(2));
+    }
+
+    #[test]
+    fn test_detect_spaces_width() {
+        let two = TextBuffer::from_str("fn a() {\n  if x {\n    y();\n  }\n}\n");
+        assert_eq!(IndentConfig::detect(&two), Some(IndentConfig::spaces(2)));
+
+        let four = TextBuffer::from_str("def f():\n    if x:\n        y()\n    return 1\n");
+        assert_eq!(IndentConfig::detect(&four), Some(IndentConfig::spaces(4)));
+    }
+
+    #[test]
+    fn test_detect_tabs_majority() {
+        let mixed = TextBuffer::from_str("a {\n\tb;\n\tc;\n  d;\n}\n");
+        assert_eq!(IndentConfig::detect(&mixed), Some(IndentConfig::tabs(DEFAULT_INDENT_WIDTH)));
+        assert_eq!(IndentConfig::detect_mixed(&mixed), Some((IndentConfig::tabs(DEFAULT_INDENT_WIDTH), true)));
+        let tabs = TextBuffer::from_str("a {\n\tb;\n}\n");
+        assert_eq!(IndentConfig::detect_mixed(&tabs), Some((IndentConfig::tabs(DEFAULT_INDENT_WIDTH), false)));
+    }
+
+    #[test]
+    fn test_detect_ignores_comment_alignment() {
+        let buffer = TextBuffer::from_str("/*\n * doc\n */\nint x;\n");
+        assert_eq!(IndentConfig::detect(&buffer), None);
+        assert_eq!(IndentConfig::detect(&TextBuffer::from_str("")), None);
+    }
+}
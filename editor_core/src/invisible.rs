@@ -0,0 +1,236 @@
+//! Invisible characters.
+//!
+//! Control and format characters (zero-width spaces, a byte order mark in
+//! the middle of a file, bidi overrides) take no space when drawn, so text
+//! containing them reads differently from what it is; "trojan source"
+//! attacks hide code behind bidi controls this way. Such characters are
+//! drawn as a box holding their code point instead, e.g. `[200B]`, which
+//! is wider than one column. [`LineLayout`] maps between buffer columns
+//! and the display columns they are drawn at, for rendering, hit-testing
+//! and scrolling alike.
+
+/// Returns true if `ch` is drawn as a code point box: a control character
+/// other than tab and line breaks, or an invisible format character.
+pub fn is_invisible(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{0}'..='\u{8}'
+            | '\u{B}'
+            | '\u{C}'
+            | '\u{E}'..='\u{1F}'
+            | '\u{7F}'..='\u{9F}'
+            // Soft hyphen, Mongolian vowel separator
+            | '\u{AD}'
+            | '\u{180E}'
+            // Zero-width space, non-joiner and joiner
+            | '\u{200B}'..='\u{200D}'
+            // Line and paragraph separators
+            | '\u{2028}'
+            | '\u{2029}'
+            // Word joiner and invisible operators
+            | '\u{2060}'..='\u{2064}'
+            // Deprecated format characters
+            | '\u{206A}'..='\u{206F}'
+            | '\u{FEFF}'
+            // Interlinear annotation
+            | '\u{FFF9}'..='\u{FFFB}'
+            // Tags
+            | '\u{E0001}'
+            | '\u{E0020}'..='\u{E007F}'
+    ) || is_bidi_control(ch)
+}
+
+/// Returns true if `ch` is a bidi control character, which can reorder how
+/// the text around it is displayed.
+pub fn is_bidi_control(ch: char) -> bool {
+    matches!(ch, '\u{61C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Returns the label drawn in the box of an invisible character: its code
+/// point in hex.
+pub fn label(ch: char) -> String {
+    format!("{:04X}", ch as u32)
+}
+
+/// Returns the number of columns `ch` is drawn in: the label plus a column
+/// of padding on each side for a boxed character, one otherwise.
+pub fn display_width(ch: char) -> usize {
+    if is_invisible(ch) {
+        label(ch).len() + 2
+    } else {
+        1
+    }
+}
+
+/// Returns how `ch` is written escaped: `\u{XXXX}`, which Rust and
+/// JavaScript strings both accept.
+pub fn escape(ch: char) -> String {
+    format!("\\u{{{}}}", label(ch))
+}
+
+/// How many invisible characters a text has.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InvisibleCounts {
+    /// Characters drawn as a box, bidi controls included.
+    pub total: usize,
+    /// Bidi control characters.
+    pub bidi: usize,
+}
+
+impl InvisibleCounts {
+    /// Counts the invisible characters in `chars`.
+    pub fn count(chars: impl Iterator<Item = char>) -> Self {
+        let mut counts = Self::default();
+        for ch in chars.filter(|&ch| is_invisible(ch)) {
+            counts.total += 1;
+            if is_bidi_control(ch) {
+                counts.bidi += 1;
+            }
+        }
+        counts
+    }
+
+    /// Adds the invisible characters in inserted text.
+    pub fn add(&mut self, chars: impl Iterator<Item = char>) {
+        let added = Self::count(chars);
+        self.total += added.total;
+        self.bidi += added.bidi;
+    }
+
+    /// Takes away the invisible characters in removed text.
+    pub fn remove(&mut self, chars: impl Iterator<Item = char>) {
+        let removed = Self::count(chars);
+        self.total = self.total.saturating_sub(removed.total);
+        self.bidi = self.bidi.saturating_sub(removed.bidi);
+    }
+}
+
+/// Display columns of a line's characters.
+///
+/// Columns past the end of the line are one display column each, so
+/// positions beyond it (a selected line break, a block selection's virtual
+/// columns) map as before.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineLayout {
+    /// Display column each character starts at, followed by the line's
+    /// width. Empty if the line has no boxed characters, which is the
+    /// identity mapping.
+    starts: Vec<usize>,
+}
+
+impl LineLayout {
+    /// Lays out a line of text.
+    pub fn new(text: &str) -> Self {
+        if !text.chars().any(is_invisible) {
+            return Self::default();
+        }
+        let mut starts = Vec::with_capacity(text.len() + 1);
+        let mut display = 0;
+        for ch in text.chars() {
+            starts.push(display);
+            display += display_width(ch);
+        }
+        starts.push(display);
+        Self { starts }
+    }
+
+    /// Returns true if the line has no boxed characters.
+    pub fn is_identity(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// Returns the display column buffer column `col` is drawn at.
+    pub fn display_col(&self, col: usize) -> usize {
+        match self.starts.last() {
+            None => col,
+            Some(&width) => match self.starts.get(col) {
+                Some(&start) => start,
+                None => width + (col + 1 - self.starts.len()),
+            },
+        }
+    }
+
+    /// Returns the buffer column nearest to display column `display_col`.
+    /// A position inside a box goes to the nearer side of it.
+    pub fn col_at(&self, display_col: usize) -> usize {
+        let Some(&width) = self.starts.last() else {
+            return display_col;
+        };
+        if display_col >= width {
+            return self.starts.len() - 1 + (display_col - width);
+        }
+        // The last character starting at or before the position
+        let col = self.starts.partition_point(|&start| start <= display_col) - 1;
+        let (start, end) = (self.starts[col], self.starts[col + 1]);
+        if display_col - start > (end - start) / 2 {
+            col + 1
+        } else {
+            col
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classification() {
+        for ch in ['\u{0}', '\u{7}', '\u{1B}', '\u{7F}', '\u{85}', '\u{200B}', '\u{FEFF}', '\u{AD}', '\u{2028}'] {
+            assert!(is_invisible(ch), "{:04X}", ch as u32);
+            assert!(!is_bidi_control(ch), "{:04X}", ch as u32);
+        }
+        // The trojan source characters
+        for ch in ['\u{202A}', '\u{202B}', '\u{202C}', '\u{202D}', '\u{202E}', '\u{2066}', '\u{2067}', '\u{2068}', '\u{2069}', '\u{200E}', '\u{200F}', '\u{61C}'] {
+            assert!(is_invisible(ch), "{:04X}", ch as u32);
+            assert!(is_bidi_control(ch), "{:04X}", ch as u32);
+        }
+        // Whitespace and line breaks are drawn as they are
+        for ch in ['\t', '\n', '\r', ' ', 'a', 'é', '\u{A0}', '中', '\u{301}'] {
+            assert!(!is_invisible(ch), "{:04X}", ch as u32);
+        }
+    }
+
+    #[test]
+    fn test_label_and_escape() {
+        assert_eq!(label('\u{200B}'), "200B");
+        assert_eq!(label('\u{1}'), "0001");
+        assert_eq!(label('\u{E0041}'), "E0041");
+        assert_eq!(display_width('\u{200B}'), 6);
+        assert_eq!(display_width('\u{E0041}'), 7);
+        assert_eq!(display_width('x'), 1);
+        assert_eq!(escape('\u{202E}'), "\\u{202E}");
+    }
+
+    #[test]
+    fn test_layout_without_boxes_is_identity() {
+        let layout = LineLayout::new("let x = 1;");
+        assert!(layout.is_identity());
+        assert_eq!(layout.display_col(4), 4);
+        assert_eq!(layout.display_col(40), 40);
+        assert_eq!(layout.col_at(7), 7);
+    }
+
+    #[test]
+    fn test_layout_with_boxes() {
+        // "a[200B]b" then past the end
+        let layout = LineLayout::new("a\u{200B}b");
+        let display: Vec<usize> = (0..=5).map(|col| layout.display_col(col)).collect();
+        assert_eq!(display, [0, 1, 7, 8, 9, 10]);
+
+        assert_eq!(layout.col_at(0), 0);
+        assert_eq!(layout.col_at(1), 1);
+        // Inside the box: the nearer side
+        assert_eq!(layout.col_at(3), 1);
+        assert_eq!(layout.col_at(4), 1);
+        assert_eq!(layout.col_at(5), 2);
+        assert_eq!(layout.col_at(7), 2);
+        assert_eq!(layout.col_at(8), 3);
+        assert_eq!(layout.col_at(10), 5);
+
+        // Every column maps back to itself
+        for col in 0..=6 {
+            assert_eq!(layout.col_at(layout.display_col(col)), col);
+        }
+    }
+}
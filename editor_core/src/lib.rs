@@ -2,27 +2,79 @@
 //!
 //! This crate contains all editor state and behavior without any
 //! dependencies on windowing or rendering systems.
+//!
+//! # Embedding
+//!
+//! To use the editor without its UI, build an [`Editor`] from text and
+//! stick to its embedding methods, which work on character positions and
+//! need no scrolling, search bar or language server:
+//!
+//! ```
+//! use cp_editor_core::Editor;
+//!
+//! let mut editor = Editor::from_text("fn main() {}\n");
+//! editor.insert_at(3, "real_");
+//! assert_eq!(editor.text_range(0, 12), "fn real_main");
+//! let matches = editor.find_matches("MAIN", false, false).unwrap();
+//! assert_eq!((matches[0].start, matches[0].end), (8, 12));
+//! editor.undo();
+//! assert_eq!(editor.text(), "fn main() {}\n");
+//! ```
+//!
+//! [`TextBuffer`] alone holds text with the same position arithmetic, and
+//! [`Search`] searches one without an editor.
 
 pub mod buffer;
 pub mod cursor;
+pub mod diff;
 pub mod editor;
+pub mod encoding;
+pub mod export;
 pub mod fold;
+pub mod freshness;
+pub mod fuzzy;
 pub mod history;
+pub mod indent;
+pub mod invisible;
+pub mod line_ending;
+pub mod lint;
 pub mod lsp_types;
 pub mod perf;
+pub mod runner;
+pub mod save;
 pub mod search;
+pub mod session;
+pub mod settings;
+pub mod snippet;
 pub mod syntax;
+pub mod template;
+pub mod view_state;
+pub mod viewport;
 pub mod workspace;
 
-pub use buffer::TextBuffer;
-pub use cursor::{BlockSelection, Cursor, MultiCursor, Position, Selection, SelectionMode};
-pub use editor::Editor;
+pub use buffer::{BufferLoader, LineChars, LoadStep, TextBuffer, WordChars};
+pub use cursor::{BlockSelection, Cursor, MultiCursor, Position, Selection, SelectionGranularity, SelectionMode};
+pub use diff::{ChangeKind, DiffOptions, Hunk};
+pub use editor::{Editor, SaveCleanup, VisualLine};
+pub use encoding::Encoding;
 pub use fold::{FoldManager, FoldRegion};
+pub use freshness::{BufferTimes, DiskStamp};
 pub use history::{EditOperation, History};
-pub use lsp_types::{CompletionItem, CompletionKind, Diagnostic, DiagnosticSeverity, HoverInfo};
+pub use indent::{IndentConfig, IndentStyle};
+pub use invisible::{InvisibleCounts, LineLayout};
+pub use line_ending::LineEnding;
+pub use lint::{Linter, Problem, ProblemMatcher};
+pub use lsp_types::{CompletionItem, CompletionKind, Diagnostic, DiagnosticSeverity, HoverInfo, SignatureHelp};
 pub use perf::{
     FrameStats, MemoryStats, PerfMetrics, RollingStats, ScrollPerf, StartupTiming, TypingLatency,
 };
+pub use runner::{CommandEvent, CommandRunner, FilterOutput};
+pub use save::{SaveJob, SaveOutcome, SaveQueue, SaveResult, SaveWriter};
 pub use search::{Search, SearchMatch};
-pub use syntax::{Language, SyntaxHighlighter, Theme, TokenStyle};
-pub use workspace::{BufferId, TabInfo, Workspace};
+pub use session::{Session, SessionTab};
+pub use settings::EditorSettings;
+pub use snippet::{Snippet, SnippetSession, TabStop};
+pub use syntax::{FileAssociation, FileAssociations, Language, SyntaxHighlighter, Theme, TokenStyle};
+pub use template::{TemplateVars, UndefinedVariable};
+pub use view_state::ViewState;
+pub use workspace::{BufferId, Pane, PaneSide, RecentFiles, TabInfo, TabLabel, Workspace, WorkspaceEvent};
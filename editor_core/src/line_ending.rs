@@ -0,0 +1,67 @@
+//! Line endings files are saved with.
+
+/// The line break a file is saved with. The buffer holds `\n` breaks; a
+/// CRLF file has its `\r` stripped on load and put back on save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    /// `\n`, as on Unix and macOS.
+    #[default]
+    Lf,
+    /// `\r\n`, as on Windows.
+    Crlf,
+}
+
+impl LineEnding {
+    /// Returns the name shown in the status bar.
+    pub fn label(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+        }
+    }
+
+    /// Returns the line break's text.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+
+    /// Returns the other line ending.
+    pub fn toggled(&self) -> Self {
+        match self {
+            LineEnding::Lf => LineEnding::Crlf,
+            LineEnding::Crlf => LineEnding::Lf,
+        }
+    }
+
+    /// Detects the line ending of `text`: the one most of its line breaks
+    /// use (LF on a tie), and whether the other one is used too. `None`
+    /// if it has no line breaks.
+    pub fn detect(text: &str) -> Option<(Self, bool)> {
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        match (lf, crlf) {
+            (0, 0) => None,
+            (lf, crlf) if crlf > lf => Some((LineEnding::Crlf, lf > 0)),
+            (_, crlf) => Some((LineEnding::Lf, crlf > 0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(LineEnding::detect("one line"), None);
+        assert_eq!(LineEnding::detect("a\nb\n"), Some((LineEnding::Lf, false)));
+        assert_eq!(LineEnding::detect("a\r\nb\r\n"), Some((LineEnding::Crlf, false)));
+        assert_eq!(LineEnding::detect("a\r\nb\r\nc\n"), Some((LineEnding::Crlf, true)));
+        assert_eq!(LineEnding::detect("a\r\nb\n"), Some((LineEnding::Lf, true)));
+        // A lone \r isn't a line break here
+        assert_eq!(LineEnding::detect("a\rb\n"), Some((LineEnding::Lf, false)));
+    }
+}
@@ -0,0 +1,276 @@
+//! Diagnostics from external linters and compilers.
+//!
+//! A [`ProblemMatcher`] turns lines of tool output into diagnostics using a
+//! regex with named capture groups:
+//!
+//! - `file`: path of the file, relative to the command's directory or absolute
+//! - `line`: 1-based line number
+//! - `col`: 1-based column (optional)
+//! - `severity`: e.g. "error", "warning", "note" (optional)
+//! - `code`: diagnostic code such as `E0425` (optional)
+//! - `message`: the message text
+//!
+//! Lines that don't match are ignored, so the matcher can be fed the raw
+//! combined output of a command.
+
+use crate::lsp_types::{Diagnostic, DiagnosticSeverity};
+use crate::template::{self, TemplateVars, UndefinedVariable};
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Placeholder in a linter command that is replaced by the quoted file path.
+/// Commands may also use the `${...}` placeholders of [`crate::template`].
+pub const FILE_PLACEHOLDER: &str = "{file}";
+
+/// A diagnostic reported for a file by an external tool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// The file as printed by the tool.
+    pub path: PathBuf,
+    /// The diagnostic, tagged with the matcher's source.
+    pub diagnostic: Diagnostic,
+}
+
+/// Parses tool output into diagnostics with a regex.
+#[derive(Debug, Clone)]
+pub struct ProblemMatcher {
+    /// Pattern with named capture groups (see the module docs).
+    pattern: Regex,
+    /// Source tag set on every diagnostic, e.g. "flake8".
+    source: String,
+    /// Severity used when the pattern has no `severity` group or it
+    /// doesn't match a known name.
+    default_severity: DiagnosticSeverity,
+}
+
+impl ProblemMatcher {
+    /// Creates a matcher from a regex with named groups. The pattern must
+    /// have at least `file`, `line` and `message` groups.
+    pub fn new(source: &str, pattern: &str, default_severity: DiagnosticSeverity) -> Result<Self, String> {
+        let pattern = Regex::new(pattern).map_err(|e| e.to_string())?;
+        for group in ["file", "line", "message"] {
+            if !pattern.capture_names().flatten().any(|name| name == group) {
+                return Err(format!("problem matcher pattern has no `{}` group", group));
+            }
+        }
+        Ok(Self {
+            pattern,
+            source: source.to_string(),
+            default_severity,
+        })
+    }
+
+    /// Matcher for the `file:line:col: severity: message` format used by
+    /// gcc, g++ and clang.
+    pub fn gcc(source: &str) -> Self {
+        Self::new(
+            source,
+            r"^(?P<file>(?:[A-Za-z]:)?[^:]+):(?P<line>\d+):(?:(?P<col>\d+):)?\s*(?P<severity>fatal error|error|warning|note):\s*(?P<message>.+)$",
+            DiagnosticSeverity::Error,
+        )
+        .expect("valid gcc pattern")
+    }
+
+    /// Matcher for rustc, cargo and clippy with `--message-format=short`,
+    /// e.g. `src/main.rs:2:5: error[E0425]: cannot find value`.
+    pub fn rustc_short(source: &str) -> Self {
+        Self::new(
+            source,
+            r"^(?P<file>(?:[A-Za-z]:)?[^:]+):(?P<line>\d+):(?P<col>\d+):\s*(?P<severity>error|warning|note|help)(?:\[(?P<code>[^\]]+)\])?:\s*(?P<message>.+)$",
+            DiagnosticSeverity::Error,
+        )
+        .expect("valid rustc pattern")
+    }
+
+    /// Matcher for flake8, pylint-style `file:line:col: CODE message` output.
+    pub fn flake8(source: &str) -> Self {
+        Self::new(
+            source,
+            r"^(?P<file>(?:[A-Za-z]:)?[^:]+):(?P<line>\d+):(?P<col>\d+):\s*(?P<code>[A-Z]+\d+)\s+(?P<message>.+)$",
+            DiagnosticSeverity::Warning,
+        )
+        .expect("valid flake8 pattern")
+    }
+
+    /// Returns the source tag set on matched diagnostics.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Parses one line of output.
+    pub fn match_line(&self, line: &str) -> Option<Problem> {
+        let caps = self.pattern.captures(line.trim_end_matches(['\n', '\r']))?;
+        let path = PathBuf::from(caps.name("file")?.as_str().trim());
+        let line = number(&caps, "line")?.saturating_sub(1);
+        let col = number(&caps, "col").unwrap_or(1).saturating_sub(1);
+        let severity = caps
+            .name("severity")
+            .and_then(|m| parse_severity(m.as_str()))
+            .unwrap_or(self.default_severity);
+        let message = caps.name("message")?.as_str().trim().to_string();
+
+        let mut diagnostic = Diagnostic::new(line, col, line, col + 1, severity, message);
+        diagnostic.code = caps.name("code").map(|m| m.as_str().to_string());
+        diagnostic.source = Some(self.source.clone());
+        Some(Problem { path, diagnostic })
+    }
+
+    /// Parses every matching line of `output`.
+    pub fn match_output<'a, I>(&self, lines: I) -> Vec<Problem>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        lines.into_iter().filter_map(|line| self.match_line(line)).collect()
+    }
+}
+
+/// Returns the capture `name` parsed as a number.
+fn number(caps: &Captures, name: &str) -> Option<usize> {
+    caps.name(name)?.as_str().parse().ok()
+}
+
+/// Maps a severity name printed by a tool to a severity.
+fn parse_severity(name: &str) -> Option<DiagnosticSeverity> {
+    let name = name.to_ascii_lowercase();
+    if name.contains("error") || name == "fatal" {
+        Some(DiagnosticSeverity::Error)
+    } else if name.starts_with("warn") {
+        Some(DiagnosticSeverity::Warning)
+    } else if name == "note" || name.starts_with("info") {
+        Some(DiagnosticSeverity::Information)
+    } else if name == "help" || name == "hint" {
+        Some(DiagnosticSeverity::Hint)
+    } else {
+        None
+    }
+}
+
+/// Groups problems by file, resolving relative paths against `cwd`.
+pub fn group_by_file(problems: Vec<Problem>, cwd: &Path) -> HashMap<PathBuf, Vec<Diagnostic>> {
+    let mut files: HashMap<PathBuf, Vec<Diagnostic>> = HashMap::new();
+    for problem in problems {
+        let path = if problem.path.is_absolute() {
+            problem.path
+        } else {
+            cwd.join(problem.path)
+        };
+        files.entry(path).or_default().push(problem.diagnostic);
+    }
+    files
+}
+
+/// An external linter run for files of one language.
+#[derive(Debug, Clone)]
+pub struct Linter {
+    /// Shell command; [`FILE_PLACEHOLDER`] is replaced by the file path.
+    pub command: String,
+    /// Parses the command's output.
+    pub matcher: ProblemMatcher,
+    /// How placeholders without a value are expanded.
+    pub undefined: UndefinedVariable,
+}
+
+impl Linter {
+    /// Creates a linter from a command and matcher.
+    pub fn new(command: &str, matcher: ProblemMatcher) -> Self {
+        Self {
+            command: command.to_string(),
+            matcher,
+            undefined: UndefinedVariable::default(),
+        }
+    }
+
+    /// Returns the command line for linting `vars.file`, with its
+    /// placeholders expanded.
+    pub fn command_for(&self, vars: &TemplateVars) -> Result<String, String> {
+        let command = template::expand(&self.command, vars, self.undefined)?;
+        let quoted = vars
+            .file
+            .as_ref()
+            .map(|path| format!("\"{}\"", path.display()))
+            .unwrap_or_default();
+        Ok(command.replace(FILE_PLACEHOLDER, &quoted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gcc_output() {
+        let matcher = ProblemMatcher::gcc("g++");
+        let output = "\
+main.cpp: In function 'int main()':
+main.cpp:5:13: error: 'y' was not declared in this scope
+    5 |     int x = y;
+      |             ^
+main.cpp:3:9: warning: unused variable 'z' [-Wunused-variable]
+/usr/include/foo.h:12: note: declared here";
+        let problems = matcher.match_output(output.lines());
+        assert_eq!(problems.len(), 3);
+
+        let first = &problems[0];
+        assert_eq!(first.path, PathBuf::from("main.cpp"));
+        assert_eq!((first.diagnostic.start_line, first.diagnostic.start_col), (4, 12));
+        assert_eq!(first.diagnostic.severity, DiagnosticSeverity::Error);
+        assert_eq!(first.diagnostic.message, "'y' was not declared in this scope");
+        assert_eq!(first.diagnostic.source.as_deref(), Some("g++"));
+
+        assert_eq!(problems[1].diagnostic.severity, DiagnosticSeverity::Warning);
+        // No column
+        assert_eq!(problems[2].path, PathBuf::from("/usr/include/foo.h"));
+        assert_eq!((problems[2].diagnostic.start_line, problems[2].diagnostic.start_col), (11, 0));
+        assert_eq!(problems[2].diagnostic.severity, DiagnosticSeverity::Information);
+    }
+
+    #[test]
+    fn test_clippy_short_output() {
+        let matcher = ProblemMatcher::rustc_short("clippy");
+        let output = "\
+    Checking demo v0.1.0 (/work/demo)
+src/main.rs:2:9: warning: unused variable: `x`
+src/lib.rs:10:5: error[E0425]: cannot find value `y` in this scope
+warning: `demo` (bin \"demo\") generated 1 warning";
+        let problems = matcher.match_output(output.lines());
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].diagnostic.message, "unused variable: `x`");
+        assert_eq!(problems[0].diagnostic.code, None);
+        assert_eq!(problems[1].path, PathBuf::from("src/lib.rs"));
+        assert_eq!(problems[1].diagnostic.severity, DiagnosticSeverity::Error);
+        assert_eq!(problems[1].diagnostic.code.as_deref(), Some("E0425"));
+    }
+
+    #[test]
+    fn test_custom_matcher_and_grouping() {
+        assert!(ProblemMatcher::new("x", r"(?P<file>\S+)", DiagnosticSeverity::Error).is_err());
+        assert!(ProblemMatcher::new("x", r"(", DiagnosticSeverity::Error).is_err());
+
+        let flake8 = ProblemMatcher::flake8("flake8");
+        let problems = flake8.match_output([
+            "app.py:1:1: F401 'os' imported but unused",
+            "/abs/util.py:7:80: E501 line too long (88 > 79 characters)",
+            "app.py:3:5: E225 missing whitespace around operator",
+        ]);
+        assert_eq!(problems[0].diagnostic.code.as_deref(), Some("F401"));
+        assert_eq!(problems[0].diagnostic.severity, DiagnosticSeverity::Warning);
+
+        let files = group_by_file(problems, Path::new("/work"));
+        assert_eq!(files[Path::new("/work/app.py")].len(), 2);
+        assert_eq!(files[Path::new("/abs/util.py")].len(), 1);
+
+        let mut linter = Linter::new("flake8 {file}", flake8);
+        let vars = TemplateVars {
+            file: Some(PathBuf::from("/work/app.py")),
+            workspace_root: Some(PathBuf::from("/work")),
+            ..TemplateVars::default()
+        };
+        assert_eq!(linter.command_for(&vars).unwrap(), "flake8 \"/work/app.py\"");
+
+        linter.command = "cd ${workspaceRoot} && flake8 ${fileBasename} ${env:CP_EDITOR_LINT_UNSET}".to_string();
+        assert_eq!(linter.command_for(&vars).unwrap(), "cd /work && flake8 app.py ");
+        linter.undefined = UndefinedVariable::Error;
+        assert!(linter.command_for(&vars).is_err());
+    }
+}
@@ -1,7 +1,7 @@
 //! LSP-related types for storing language server data in the editor.
 
-/// Diagnostic severity level.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Diagnostic severity level, ordered from most to least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum DiagnosticSeverity {
     Error,
     Warning,
@@ -9,6 +9,23 @@ pub enum DiagnosticSeverity {
     Hint,
 }
 
+impl DiagnosticSeverity {
+    /// Returns true if this severity is at least as severe as `min`.
+    pub fn is_at_least(self, min: DiagnosticSeverity) -> bool {
+        self.rank() <= min.rank()
+    }
+
+    /// Lower is more severe.
+    fn rank(self) -> u8 {
+        match self {
+            DiagnosticSeverity::Error => 0,
+            DiagnosticSeverity::Warning => 1,
+            DiagnosticSeverity::Information => 2,
+            DiagnosticSeverity::Hint => 3,
+        }
+    }
+}
+
 /// A diagnostic message (error, warning, etc.).
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Diagnostic {
@@ -98,6 +115,15 @@ impl HoverInfo {
     }
 }
 
+/// Signature help for the call the cursor is in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureHelp {
+    /// The active signature, e.g. `fn add(a: i32, b: i32) -> i32`.
+    pub label: String,
+    /// Character range of the active parameter within `label`.
+    pub active_parameter: Option<std::ops::Range<usize>>,
+}
+
 /// A completion item.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CompletionItem {
@@ -109,6 +135,8 @@ pub struct CompletionItem {
     pub detail: Option<String>,
     /// Text to insert when this item is selected.
     pub insert_text: Option<String>,
+    /// Whether `insert_text` is a snippet with tab stops.
+    pub is_snippet: bool,
 }
 
 /// Completion item kind.
@@ -327,8 +327,10 @@ pub struct StartupTiming {
     pub gpu_init: Option<Duration>,
     /// Time to load the font atlas.
     pub font_init: Option<Duration>,
-    /// Time to open a file.
+    /// Time to open a file; of a large file, its first screenful.
     pub file_open: Option<Duration>,
+    /// Time until a large file opened in the background loaded in full.
+    pub file_loaded: Option<Duration>,
     /// Time to first render.
     pub first_render: Option<Duration>,
     /// Time until ready for input.
@@ -343,6 +345,7 @@ impl StartupTiming {
             gpu_init: None,
             font_init: None,
             file_open: None,
+            file_loaded: None,
             first_render: None,
             ready_time: None,
         }
@@ -363,6 +366,11 @@ impl StartupTiming {
         self.file_open = Some(self.start_time.elapsed());
     }
 
+    /// Records the time a large file loaded in full, once.
+    pub fn record_file_loaded(&mut self) {
+        self.file_loaded.get_or_insert(self.start_time.elapsed());
+    }
+
     /// Records first render time.
     pub fn record_first_render(&mut self) {
         self.first_render = Some(self.start_time.elapsed());
@@ -397,6 +405,9 @@ impl StartupTiming {
         if let Some(d) = self.first_render {
             parts.push(format!("Render: {:.1}ms", d.as_secs_f64() * 1000.0));
         }
+        if let Some(d) = self.file_loaded {
+            parts.push(format!("Loaded: {:.1}ms", d.as_secs_f64() * 1000.0));
+        }
         if let Some(d) = self.ready_time {
             parts.push(format!("Total: {:.1}ms", d.as_secs_f64() * 1000.0));
         }
@@ -550,6 +561,7 @@ mod tests {
 #[cfg(test)]
 pub mod benchmarks {
     use crate::buffer::TextBuffer;
+    use crate::syntax::Language;
     use crate::Editor;
     use std::time::Instant;
 
@@ -675,6 +687,66 @@ pub mod benchmarks {
         assert!(nav_time.as_millis() < 2000, "Navigation too slow");
     }
 
+    /// Benchmark: A minified file, one line of about 1MB.
+    #[test]
+    fn bench_huge_single_line() {
+        let content = "{\"id\":42,\"name\":\"item\",\"tags\":[\"a\",\"b\"]},".repeat(25_000);
+        let content = format!("[{}]", content.trim_end_matches(','));
+        let len = content.chars().count();
+        let mut editor = Editor::new();
+        editor.set_buffer(TextBuffer::from_str(&content));
+        let mut settings = editor.settings();
+        settings.language = Some(Language::Json);
+
+        let start = Instant::now();
+        editor.set_settings(settings);
+        editor.reparse_syntax();
+        let highlight_time = start.elapsed();
+        assert!(editor.has_syntax_highlighting());
+
+        // What drawing a frame scrolled to the middle of the line reads
+        let start = Instant::now();
+        let scroll = len / 2;
+        for _ in 0..1_000 {
+            let rows = editor.visual_lines(0, 50);
+            assert_eq!(rows.len(), 1);
+            let layout = editor.line_layout(0);
+            assert!(layout.is_identity());
+            let text = editor.buffer().line_slice(0, scroll, scroll + 200);
+            assert_eq!(text.chars().count(), 200);
+            for col in scroll..scroll + 200 {
+                let _ = editor.highlight_color_at(0, col);
+                let _ = editor.bracket_color_at(0, col);
+            }
+        }
+        let render_time = start.elapsed();
+
+        let start = Instant::now();
+        editor.move_to_line_end(false);
+        for _ in 0..10_000 {
+            editor.move_left(false);
+            editor.move_word_left(false);
+            editor.move_down(false);
+            editor.move_up(false);
+        }
+        editor.move_to_line_start(false);
+        let nav_time = start.elapsed();
+
+        println!(
+            "Huge line ({} chars): highlight {:.2}ms, 1000 frames {:.2}ms, 40000 moves {:.2}ms",
+            len,
+            highlight_time.as_secs_f64() * 1000.0,
+            render_time.as_secs_f64() * 1000.0,
+            nav_time.as_secs_f64() * 1000.0
+        );
+
+        // The line is past the highlighting limit, so it is drawn plain
+        assert!(editor.highlighter().line_highlights(0).unwrap().spans().is_empty());
+        assert!(highlight_time.as_millis() < 5000, "Highlighting a huge line too slow");
+        assert!(render_time.as_millis() < 500, "Drawing a huge line too slow");
+        assert!(nav_time.as_millis() < 2000, "Moving on a huge line too slow");
+    }
+
     /// Benchmark: Undo/Redo operations.
     #[test]
     fn bench_undo_redo() {
@@ -682,7 +754,7 @@ pub mod benchmarks {
 
         // Create some history
         for i in 0..1000 {
-            editor.insert_char(('a' as u8 + (i % 26) as u8) as char);
+            editor.insert_char((b'a' + (i % 26) as u8) as char);
         }
 
         let start = Instant::now();
@@ -0,0 +1,245 @@
+//! Running external commands.
+//!
+//! Commands run through the platform shell on background threads. Their
+//! stdout and stderr are streamed back line by line over a channel that the
+//! UI polls once per frame, like background saves and project search.
+//! Filters, which transform a piece of text, run to completion instead.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+
+/// Something that happened while a command ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandEvent {
+    /// A line of output, from stdout or stderr, without its line ending.
+    Output(String),
+    /// The command exited with this status code (`None` if killed by a
+    /// signal).
+    Finished(Option<i32>),
+    /// The command couldn't be started.
+    Failed(String),
+}
+
+/// Runs one shell command at a time and collects its output.
+#[derive(Debug, Default)]
+pub struct CommandRunner {
+    /// Receives events from the running command.
+    events: Option<Receiver<CommandEvent>>,
+    /// The command line that is running or ran last.
+    command: Option<String>,
+    /// Directory the command runs in.
+    cwd: Option<PathBuf>,
+}
+
+impl CommandRunner {
+    /// Creates an idle runner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts `command` in `cwd` through the platform shell.
+    ///
+    /// A command that is still running keeps running, but its remaining
+    /// output is discarded.
+    pub fn start(&mut self, command: &str, cwd: &Path) {
+        let (sender, receiver) = channel();
+        self.events = Some(receiver);
+        self.command = Some(command.to_string());
+        self.cwd = Some(cwd.to_path_buf());
+
+        let command = command.to_string();
+        let cwd = cwd.to_path_buf();
+        thread::spawn(move || {
+            let event = match run(&command, &cwd, &sender) {
+                Ok(code) => CommandEvent::Finished(code),
+                Err(e) => CommandEvent::Failed(e.to_string()),
+            };
+            let _ = sender.send(event);
+        });
+    }
+
+    /// Returns true while a started command hasn't finished.
+    pub fn is_running(&self) -> bool {
+        self.events.is_some()
+    }
+
+    /// Returns the command line that is running or ran last.
+    pub fn command(&self) -> Option<&str> {
+        self.command.as_deref()
+    }
+
+    /// Returns the directory the last command ran in.
+    pub fn cwd(&self) -> Option<&Path> {
+        self.cwd.as_deref()
+    }
+
+    /// Collects the events received so far without blocking.
+    pub fn poll(&mut self) -> Vec<CommandEvent> {
+        let mut events = Vec::new();
+        let Some(receiver) = &self.events else {
+            return events;
+        };
+        loop {
+            match receiver.try_recv() {
+                Ok(event) => {
+                    let done = matches!(event, CommandEvent::Finished(_) | CommandEvent::Failed(_));
+                    events.push(event);
+                    if done {
+                        self.events = None;
+                        break;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.events = None;
+                    break;
+                }
+            }
+        }
+        events
+    }
+}
+
+/// Output of a command run as a filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterOutput {
+    /// Everything the command wrote to stdout.
+    pub stdout: String,
+    /// Everything the command wrote to stderr.
+    pub stderr: String,
+    /// Exit status code (`None` if killed by a signal).
+    pub code: Option<i32>,
+}
+
+impl FilterOutput {
+    /// Returns true if the command exited with status 0.
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Runs `command` in `cwd` through the platform shell with `input` on its
+/// stdin and waits for it to exit.
+///
+/// Blocks the calling thread, so it suits quick filters like `sort`.
+pub fn filter(command: &str, input: &str, cwd: &Path) -> io::Result<FilterOutput> {
+    let mut child = shell_command(command)
+        .current_dir(cwd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Feed stdin from another thread so a command that writes before it has
+    // read everything can't fill its stdout pipe and deadlock
+    let writer = child.stdin.take().map(|mut stdin| {
+        let input = input.to_string();
+        thread::spawn(move || {
+            // A command that ignores its input may exit before reading it
+            let _ = stdin.write_all(input.as_bytes());
+        })
+    });
+    let output = child.wait_with_output()?;
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+
+    Ok(FilterOutput {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        code: output.status.code(),
+    })
+}
+
+/// Runs `command` to completion, forwarding its output lines.
+fn run(command: &str, cwd: &Path, sender: &Sender<CommandEvent>) -> io::Result<Option<i32>> {
+    let mut child = shell_command(command)
+        .current_dir(cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let readers: Vec<_> = [
+        child.stdout.take().map(|s| Box::new(s) as Box<dyn Read + Send>),
+        child.stderr.take().map(|s| Box::new(s) as Box<dyn Read + Send>),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|stream| {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(CommandEvent::Output(line)).is_err() {
+                    break;
+                }
+            }
+        })
+    })
+    .collect();
+
+    // Forward all output before reporting the exit status
+    for reader in readers {
+        let _ = reader.join();
+    }
+    Ok(child.wait()?.code())
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.args(["/C", command]);
+    cmd
+}
+
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", command]);
+    cmd
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn run_to_end(runner: &mut CommandRunner) -> Vec<CommandEvent> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut events = Vec::new();
+        while runner.is_running() && Instant::now() < deadline {
+            events.extend(runner.poll());
+            thread::sleep(Duration::from_millis(5));
+        }
+        events
+    }
+
+    #[test]
+    fn test_collects_output_and_status() {
+        let mut runner = CommandRunner::new();
+        runner.start("echo one; echo two 1>&2; exit 3", Path::new("."));
+        let events = run_to_end(&mut runner);
+
+        assert!(events.contains(&CommandEvent::Output("one".to_string())));
+        assert!(events.contains(&CommandEvent::Output("two".to_string())));
+        assert_eq!(events.last(), Some(&CommandEvent::Finished(Some(3))));
+        assert_eq!(runner.command(), Some("echo one; echo two 1>&2; exit 3"));
+    }
+
+    #[test]
+    fn test_filter_pipes_input_through() {
+        let output = filter("tr a-z A-Z", "hello\nworld\n", Path::new(".")).unwrap();
+        assert!(output.success());
+        assert_eq!(output.stdout, "HELLO\nWORLD\n");
+
+        let output = filter("cat >/dev/null; echo oops 1>&2; exit 2", "ignored", Path::new(".")).unwrap();
+        assert!(!output.success());
+        assert_eq!(output.code, Some(2));
+        assert_eq!(output.stdout, "");
+        assert_eq!(output.stderr, "oops\n");
+    }
+}
@@ -0,0 +1,310 @@
+//! Background file saving.
+//!
+//! Saves run on a worker thread so a slow disk or network filesystem doesn't
+//! block the UI. Each job carries a snapshot of the buffer and the content
+//! version it was taken at; when the write finishes the version is compared
+//! with the buffer's current one, so edits made during the write keep the
+//! buffer marked as modified. Only one save per buffer may be in flight.
+
+use crate::buffer::TextBuffer;
+use crate::workspace::BufferId;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Writes a buffer snapshot to a path. Implemented for closures so tests can
+/// substitute slow or failing writers.
+pub trait SaveWriter: Send + 'static {
+    /// Writes `content` to `path`.
+    fn write(&mut self, path: &Path, content: &TextBuffer) -> io::Result<()>;
+}
+
+impl<F> SaveWriter for F
+where
+    F: FnMut(&Path, &TextBuffer) -> io::Result<()> + Send + 'static,
+{
+    fn write(&mut self, path: &Path, content: &TextBuffer) -> io::Result<()> {
+        self(path, content)
+    }
+}
+
+/// A snapshot of a buffer waiting to be written.
+#[derive(Debug, Clone)]
+pub struct SaveJob {
+    /// Buffer the snapshot was taken from.
+    pub buffer_id: BufferId,
+    /// Destination path.
+    pub path: PathBuf,
+    /// Buffer content at the time of the snapshot.
+    pub content: TextBuffer,
+    /// Content version of the snapshot.
+    pub version: u64,
+}
+
+/// The result of a finished save job.
+#[derive(Debug)]
+pub struct SaveResult {
+    /// Buffer the snapshot was taken from.
+    pub buffer_id: BufferId,
+    /// Destination path.
+    pub path: PathBuf,
+    /// Content version of the snapshot that was written.
+    pub version: u64,
+    /// Whether the write succeeded.
+    pub result: io::Result<()>,
+}
+
+/// What a finished save means for its buffer.
+#[derive(Debug)]
+pub enum SaveOutcome {
+    /// The buffer is saved and no longer modified.
+    Saved,
+    /// The write succeeded, but the buffer changed while it was running, so
+    /// newer edits are still unsaved.
+    SavedOlderVersion,
+    /// The buffer was closed before the write finished.
+    BufferClosed,
+    /// The write failed; the buffer stays modified.
+    Failed(io::Error),
+}
+
+/// Queue of save jobs processed in order on a worker thread.
+pub struct SaveQueue {
+    /// Sends jobs to the worker.
+    jobs: Sender<SaveJob>,
+    /// Receives finished jobs from the worker.
+    results: Receiver<SaveResult>,
+    /// Buffers with a save in flight.
+    in_flight: HashSet<BufferId>,
+    /// Error of the last failed save, per buffer, until it is retried.
+    errors: HashMap<BufferId, String>,
+}
+
+impl Default for SaveQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveQueue {
+    /// Creates a queue that writes to disk.
+    pub fn new() -> Self {
+        Self::with_writer(|path: &Path, content: &TextBuffer| content.save_to_file(path))
+    }
+
+    /// Creates a queue that writes with `writer`.
+    pub fn with_writer<W: SaveWriter>(mut writer: W) -> Self {
+        let (job_sender, job_receiver) = channel::<SaveJob>();
+        let (result_sender, result_receiver) = channel();
+
+        // One worker, so writes to the same path never overlap
+        thread::spawn(move || {
+            for job in job_receiver {
+                let result = writer.write(&job.path, &job.content);
+                let finished = SaveResult {
+                    buffer_id: job.buffer_id,
+                    path: job.path,
+                    version: job.version,
+                    result,
+                };
+                if result_sender.send(finished).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            jobs: job_sender,
+            results: result_receiver,
+            in_flight: HashSet::new(),
+            errors: HashMap::new(),
+        }
+    }
+
+    /// Queues a save. Returns false, without queueing, if a save for the
+    /// same buffer is already in flight.
+    pub fn submit(&mut self, job: SaveJob) -> bool {
+        if self.in_flight.contains(&job.buffer_id) {
+            return false;
+        }
+        let buffer_id = job.buffer_id;
+        if self.jobs.send(job).is_err() {
+            return false;
+        }
+        self.in_flight.insert(buffer_id);
+        self.errors.remove(&buffer_id);
+        true
+    }
+
+    /// Returns true if a save for `buffer_id` is in flight.
+    pub fn is_saving(&self, buffer_id: BufferId) -> bool {
+        self.in_flight.contains(&buffer_id)
+    }
+
+    /// Returns true if any save is in flight.
+    pub fn is_busy(&self) -> bool {
+        !self.in_flight.is_empty()
+    }
+
+    /// Returns the error of the last failed save for `buffer_id`, if it
+    /// hasn't been retried yet.
+    pub fn last_error(&self, buffer_id: BufferId) -> Option<&str> {
+        self.errors.get(&buffer_id).map(String::as_str)
+    }
+
+    /// Collects finished saves without blocking.
+    pub fn poll(&mut self) -> Vec<SaveResult> {
+        let mut finished = Vec::new();
+        while let Ok(result) = self.results.try_recv() {
+            finished.push(self.complete(result));
+        }
+        finished
+    }
+
+    /// Waits up to `timeout` for every in-flight save to finish, e.g. before
+    /// quitting. Returns the saves that finished.
+    pub fn wait_idle(&mut self, timeout: Duration) -> Vec<SaveResult> {
+        let deadline = Instant::now() + timeout;
+        let mut finished = Vec::new();
+        while self.is_busy() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.results.recv_timeout(remaining) {
+                Ok(result) => finished.push(self.complete(result)),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        finished
+    }
+
+    /// Clears the in-flight guard for a finished save and records failures.
+    fn complete(&mut self, result: SaveResult) -> SaveResult {
+        self.in_flight.remove(&result.buffer_id);
+        if let Err(e) = &result.result {
+            self.errors.insert(result.buffer_id, e.to_string());
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn job(buffer_id: BufferId, text: &str, version: u64) -> SaveJob {
+        SaveJob {
+            buffer_id,
+            path: PathBuf::from(format!("/virtual/{}.txt", buffer_id)),
+            content: TextBuffer::from_str(text),
+            version,
+        }
+    }
+
+    /// A writer that records what it wrote and blocks until released.
+    fn gated_writer() -> (impl SaveWriter, Arc<Mutex<Vec<String>>>, Sender<()>) {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let (release, gate) = channel::<()>();
+        let log = Arc::clone(&written);
+        let writer = move |_: &Path, content: &TextBuffer| {
+            let _ = gate.recv_timeout(TIMEOUT);
+            log.lock().unwrap().push(content.to_string());
+            Ok(())
+        };
+        (writer, written, release)
+    }
+
+    #[test]
+    fn test_jobs_run_in_order() {
+        let (writer, written, release) = gated_writer();
+        let mut queue = SaveQueue::with_writer(writer);
+
+        assert!(queue.submit(job(0, "a", 1)));
+        assert!(queue.submit(job(1, "b", 1)));
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+
+        let finished = queue.wait_idle(TIMEOUT);
+        let ids: Vec<_> = finished.iter().map(|r| r.buffer_id).collect();
+        assert_eq!(ids, vec![0, 1]);
+        assert_eq!(*written.lock().unwrap(), vec!["a", "b"]);
+        assert!(!queue.is_busy());
+    }
+
+    #[test]
+    fn test_in_flight_guard() {
+        let (writer, written, release) = gated_writer();
+        let mut queue = SaveQueue::with_writer(writer);
+
+        assert!(queue.submit(job(0, "first", 1)));
+        assert!(queue.is_saving(0));
+        // A second save of the same buffer is refused while the first runs
+        assert!(!queue.submit(job(0, "second", 2)));
+        // Other buffers aren't blocked
+        assert!(!queue.is_saving(1));
+
+        release.send(()).unwrap();
+        assert_eq!(queue.wait_idle(TIMEOUT).len(), 1);
+        assert!(!queue.is_saving(0));
+        assert_eq!(*written.lock().unwrap(), vec!["first"]);
+
+        // Once finished, the buffer can be saved again
+        assert!(queue.submit(job(0, "second", 2)));
+        release.send(()).unwrap();
+        queue.wait_idle(TIMEOUT);
+        assert_eq!(*written.lock().unwrap(), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn test_poll_does_not_block() {
+        let (writer, _written, release) = gated_writer();
+        let mut queue = SaveQueue::with_writer(writer);
+
+        queue.submit(job(0, "slow", 1));
+        // The write is still blocked; polling returns immediately
+        assert!(queue.poll().is_empty());
+        assert!(queue.is_saving(0));
+
+        release.send(()).unwrap();
+        let deadline = Instant::now() + TIMEOUT;
+        let mut finished = Vec::new();
+        while finished.is_empty() && Instant::now() < deadline {
+            finished = queue.poll();
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].version, 1);
+        assert!(finished[0].result.is_ok());
+    }
+
+    #[test]
+    fn test_failed_save_is_reported_and_retryable() {
+        let attempts = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&attempts);
+        let mut queue = SaveQueue::with_writer(move |_: &Path, _: &TextBuffer| {
+            let mut n = counter.lock().unwrap();
+            *n += 1;
+            if *n == 1 {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, "read-only"))
+            } else {
+                Ok(())
+            }
+        });
+
+        queue.submit(job(3, "text", 1));
+        let finished = queue.wait_idle(TIMEOUT);
+        assert!(finished[0].result.is_err());
+        assert_eq!(queue.last_error(3), Some("read-only"));
+        assert!(!queue.is_saving(3));
+
+        // Retrying clears the error
+        assert!(queue.submit(job(3, "text", 1)));
+        assert_eq!(queue.last_error(3), None);
+        assert!(queue.wait_idle(TIMEOUT)[0].result.is_ok());
+        assert_eq!(*attempts.lock().unwrap(), 2);
+    }
+}
@@ -1,6 +1,14 @@
 //! Search and replace functionality.
+//!
+//! Plain queries are found with a substring search; regex queries, and plain
+//! ones that need Unicode case folding, go through the `regex` crate. Both
+//! report positions in characters regardless of the text's encoding width.
 
 use crate::buffer::TextBuffer;
+use crate::viewport;
+use regex::{Regex, RegexBuilder};
+use std::borrow::Cow;
+use std::ops::Range;
 
 /// A search match in the buffer.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -41,6 +49,15 @@ pub struct Search {
     case_sensitive: bool,
     /// Whether to use regex search.
     use_regex: bool,
+    /// Compiled form of the current query.
+    regex: Option<Regex>,
+    /// Error from compiling the current query as a regex, if any.
+    error: Option<String>,
+    /// Character ranges (start, end) that matches are restricted to, sorted
+    /// and not overlapping. Empty means the whole buffer.
+    ranges: Vec<(usize, usize)>,
+    /// Whether replacements take on the case shape of the text they replace.
+    preserve_case: bool,
 }
 
 impl Default for Search {
@@ -58,6 +75,10 @@ impl Search {
             current_match: None,
             case_sensitive: false,
             use_regex: false,
+            regex: None,
+            error: None,
+            ranges: Vec::new(),
+            preserve_case: false,
         }
     }
 
@@ -92,6 +113,86 @@ impl Search {
         self.find_all(buffer);
     }
 
+    /// Returns whether the query is interpreted as a regular expression.
+    pub fn is_regex(&self) -> bool {
+        self.use_regex
+    }
+
+    /// Sets whether the query is interpreted as a regular expression.
+    pub fn set_use_regex(&mut self, use_regex: bool, buffer: &TextBuffer) {
+        if self.use_regex != use_regex {
+            self.use_regex = use_regex;
+            self.find_all(buffer);
+        }
+    }
+
+    /// Toggles regex mode and re-searches.
+    pub fn toggle_regex(&mut self, buffer: &TextBuffer) {
+        self.use_regex = !self.use_regex;
+        self.find_all(buffer);
+    }
+
+    /// Returns whether replacements preserve the case of the matched text.
+    pub fn is_preserve_case(&self) -> bool {
+        self.preserve_case
+    }
+
+    /// Sets whether replacements preserve the case of the matched text.
+    ///
+    /// Independent of case-sensitive matching; it only affects replacement text.
+    pub fn set_preserve_case(&mut self, preserve_case: bool) {
+        self.preserve_case = preserve_case;
+    }
+
+    /// Toggles case-preserving replacement.
+    pub fn toggle_preserve_case(&mut self) {
+        self.preserve_case = !self.preserve_case;
+    }
+
+    /// Returns the error from compiling the query as a regex, if it is invalid.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Returns the character ranges matches are restricted to; empty if
+    /// the search covers the whole buffer.
+    pub fn ranges(&self) -> &[(usize, usize)] {
+        &self.ranges
+    }
+
+    /// Restricts matches to the given character range (or lifts the
+    /// restriction with `None`) and re-searches.
+    pub fn set_range(&mut self, range: Option<(usize, usize)>, buffer: &TextBuffer) {
+        self.set_ranges(range.into_iter().collect(), buffer);
+    }
+
+    /// Restricts matches to several independent character ranges and
+    /// re-searches. A match must lie inside a single range, so one spanning
+    /// two adjacent ranges isn't found.
+    pub fn set_ranges(&mut self, ranges: Vec<(usize, usize)>, buffer: &TextBuffer) {
+        self.adjust_ranges(ranges);
+        self.find_all(buffer);
+    }
+
+    /// Updates the restriction ranges after an edit without re-searching.
+    pub fn adjust_ranges(&mut self, mut ranges: Vec<(usize, usize)>) {
+        ranges.sort_unstable();
+        self.ranges = ranges;
+    }
+
+    /// Returns true if a match spanning `start..end` lies inside the range
+    /// restriction. `next` is the first range that may still hold it; asked
+    /// in order of the match ends, the ranges are walked once in all.
+    fn in_range(&self, next: &mut usize, start: usize, end: usize) -> bool {
+        if self.ranges.is_empty() {
+            return true;
+        }
+        while self.ranges.get(*next).is_some_and(|&(_, hi)| hi < end) {
+            *next += 1;
+        }
+        self.ranges.get(*next).is_some_and(|&(lo, _)| start >= lo)
+    }
+
     /// Returns all matches.
     pub fn matches(&self) -> &[SearchMatch] {
         &self.matches
@@ -112,11 +213,14 @@ impl Search {
         self.current_match.map(|i| self.matches[i])
     }
 
-    /// Clears the search state.
+    /// Clears the search state. The range restriction is kept; use
+    /// `set_ranges(Vec::new(), ..)` to lift it.
     pub fn clear(&mut self) {
         self.query.clear();
         self.matches.clear();
         self.current_match = None;
+        self.regex = None;
+        self.error = None;
     }
 
     /// Returns true if there are any matches.
@@ -129,35 +233,72 @@ impl Search {
         !self.query.is_empty()
     }
 
+    /// Compiles the current query, escaping it unless regex mode is on.
+    fn compile(&self) -> Result<Regex, regex::Error> {
+        let pattern = if self.use_regex {
+            self.query.clone()
+        } else {
+            regex::escape(&self.query)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .multi_line(true)
+            .build()
+    }
+
     /// Finds all matches in the buffer.
     fn find_all(&mut self, buffer: &TextBuffer) -> usize {
         self.matches.clear();
         self.current_match = None;
+        self.regex = None;
+        self.error = None;
 
         if self.query.is_empty() {
             return 0;
         }
 
         let text = buffer.to_string();
-        let query = if self.case_sensitive {
-            self.query.clone()
-        } else {
-            self.query.to_lowercase()
-        };
-
-        let search_text = if self.case_sensitive {
-            text.clone()
+        let mut chars = CharIndex::default();
+        let mut next_range = 0;
+        if self.use_regex {
+            let regex = match self.compile() {
+                Ok(regex) => regex,
+                Err(e) => {
+                    self.error = Some(e.to_string());
+                    return 0;
+                }
+            };
+            for m in regex.find_iter(&text) {
+                let (start, end) = (chars.at(&text, m.start()), chars.at(&text, m.end()));
+                if self.in_range(&mut next_range, start, end) {
+                    self.matches.push(SearchMatch::new(start, end));
+                }
+            }
+            self.regex = Some(regex);
         } else {
-            text.to_lowercase()
-        };
-
-        // Find all occurrences
-        let mut start = 0;
-        while let Some(pos) = search_text[start..].find(&query) {
-            let match_start = start + pos;
-            let match_end = match_start + self.query.len();
-            self.matches.push(SearchMatch::new(match_start, match_end));
-            start = match_start + 1; // Allow overlapping matches
+            // Allow overlapping matches: resume one character after each match start
+            let mut start = self.ranges.first().map_or(0, |&(lo, _)| buffer.char_to_byte(lo));
+            let limit = self.ranges.last().map(|&(_, hi)| hi);
+            let mut finder = match self.literal_finder(&text) {
+                Ok(finder) => finder,
+                Err(e) => {
+                    self.error = Some(e.to_string());
+                    return 0;
+                }
+            };
+            while let Some(found) = finder.find_at(&text, start) {
+                let (match_start, match_end) = (chars.at(&text, found.start), chars.at(&text, found.end));
+                if limit.is_some_and(|hi| match_end > hi) {
+                    break;
+                }
+                if self.in_range(&mut next_range, match_start, match_end) {
+                    self.matches.push(SearchMatch::new(match_start, match_end));
+                }
+                start = found.start + text[found.start..].chars().next().map_or(1, char::len_utf8);
+                if start >= text.len() {
+                    break;
+                }
+            }
         }
 
         if !self.matches.is_empty() {
@@ -167,6 +308,94 @@ impl Search {
         self.matches.len()
     }
 
+    /// Picks how a plain query is found in `text`: a substring search when
+    /// case matters or the query is ASCII (compared with ASCII case folded),
+    /// else the escaped query as a case-insensitive regex.
+    fn literal_finder<'t>(&self, text: &'t str) -> Result<LiteralFinder<'t>, regex::Error> {
+        if self.case_sensitive {
+            Ok(LiteralFinder::Substring { haystack: Cow::Borrowed(text), needle: self.query.clone() })
+        } else if self.query.is_ascii() {
+            // ASCII folding keeps every byte where it was, so offsets carry over
+            Ok(LiteralFinder::Substring {
+                haystack: Cow::Owned(text.to_ascii_lowercase()),
+                needle: self.query.to_ascii_lowercase(),
+            })
+        } else {
+            self.compile().map(LiteralFinder::Regex)
+        }
+    }
+
+    /// Expands the replacement text for a single match.
+    ///
+    /// `text` holds the match at the byte range `matched`, with the
+    /// characters either side of it where there are any, so anchors and word
+    /// boundaries see what the search saw. In regex mode `$1`, `${1}` and
+    /// `${name}` are substituted from the match's capture groups and `$$`
+    /// produces a literal dollar sign. Use the braced form when the group
+    /// reference is followed by a word character (`${1}_x`, not `$1_x`).
+    /// Outside regex mode the replacement is literal. With preserve-case on,
+    /// the result is then recased to match the matched text (see
+    /// [`preserve_case`]).
+    pub fn expand_replacement(&self, text: &str, matched: Range<usize>, replacement: &str) -> String {
+        let expanded = match self.regex.as_ref().filter(|_| self.use_regex) {
+            Some(regex) => match regex.captures_at(text, matched.start) {
+                Some(caps) if caps.get(0).is_some_and(|m| m.start() == matched.start) => {
+                    let mut expanded = String::new();
+                    caps.expand(replacement, &mut expanded);
+                    expanded
+                }
+                _ => replacement.to_string(),
+            },
+            None => replacement.to_string(),
+        };
+        self.recase(&text[matched], expanded)
+    }
+
+    /// Returns every non-overlapping match paired with its expanded replacement,
+    /// in buffer order.
+    pub fn replacements(&self, buffer: &TextBuffer, replacement: &str) -> Vec<(SearchMatch, String)> {
+        let text = buffer.to_string();
+        let mut chars = CharIndex::default();
+        match self.regex.as_ref().filter(|_| self.use_regex) {
+            Some(regex) => {
+                let mut next_range = 0;
+                regex
+                    .captures_iter(&text)
+                    .filter_map(|caps| {
+                        let whole = caps.get(0).expect("group 0 always participates");
+                        let match_ = SearchMatch::new(chars.at(&text, whole.start()), chars.at(&text, whole.end()));
+                        if !self.in_range(&mut next_range, match_.start, match_.end) {
+                            return None;
+                        }
+                        let mut expanded = String::new();
+                        caps.expand(replacement, &mut expanded);
+                        Some((match_, self.recase(whole.as_str(), expanded)))
+                    })
+                    .collect()
+            }
+            None => {
+                let mut result: Vec<(SearchMatch, String)> = Vec::new();
+                for m in &self.matches {
+                    if result.last().is_some_and(|(prev, _)| m.start < prev.end) {
+                        continue;
+                    }
+                    let matched = &text[buffer.char_to_byte(m.start)..buffer.char_to_byte(m.end)];
+                    result.push((*m, self.recase(matched, replacement.to_string())));
+                }
+                result
+            }
+        }
+    }
+
+    /// Applies case preservation to an expanded replacement, if enabled.
+    fn recase(&self, matched: &str, replacement: String) -> String {
+        if self.preserve_case {
+            preserve_case(matched, &replacement)
+        } else {
+            replacement
+        }
+    }
+
     /// Moves to the next match, wrapping around.
     /// Returns the new current match position if any.
     pub fn next_match(&mut self) -> Option<SearchMatch> {
@@ -227,22 +456,111 @@ impl Search {
 
     /// Returns matches that overlap with the given line range.
     /// Useful for rendering only visible matches.
-    pub fn matches_in_range(&self, buffer: &TextBuffer, start_line: usize, end_line: usize) -> Vec<SearchMatch> {
+    pub fn matches_in_range(&self, buffer: &TextBuffer, start_line: usize, end_line: usize) -> &[SearchMatch] {
         if self.matches.is_empty() {
-            return Vec::new();
+            return &[];
+        }
+
+        let window = buffer.line_start(start_line)..buffer.line_start(end_line + 1);
+        viewport::overlapping(&self.matches, window, |m| m.start..m.end)
+    }
+}
+
+/// Finds a plain query in the text, see [`Search::literal_finder`].
+enum LiteralFinder<'t> {
+    /// Substring search of `needle` in `haystack`, which has the text's
+    /// byte offsets.
+    Substring { haystack: Cow<'t, str>, needle: String },
+    /// The escaped query, compiled case-insensitively.
+    Regex(Regex),
+}
+
+impl LiteralFinder<'_> {
+    /// Returns the byte range of the first match at or after `start`.
+    fn find_at(&mut self, text: &str, start: usize) -> Option<Range<usize>> {
+        match self {
+            LiteralFinder::Substring { haystack, needle } => {
+                haystack[start..].find(needle.as_str()).map(|pos| start + pos..start + pos + needle.len())
+            }
+            LiteralFinder::Regex(regex) => regex.find_at(text, start).map(|m| m.range()),
+        }
+    }
+}
+
+/// Converts byte offsets of a text to character offsets, counting only the
+/// characters between one offset asked for and the next.
+#[derive(Debug, Default)]
+struct CharIndex {
+    byte: usize,
+    char: usize,
+}
+
+impl CharIndex {
+    /// Returns the character offset of the byte offset `byte` in `text`.
+    fn at(&mut self, text: &str, byte: usize) -> usize {
+        if byte >= self.byte {
+            self.char += text[self.byte..byte].chars().count();
+        } else {
+            self.char -= text[byte..self.byte].chars().count();
         }
+        self.byte = byte;
+        self.char
+    }
+}
 
-        let range_start = buffer.line_start(start_line);
-        let range_end = if end_line >= buffer.len_lines() {
-            buffer.len_chars()
+/// Case shape of a piece of text, judged by its cased letters only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CaseShape {
+    /// No uppercase letters (`color`).
+    Lower,
+    /// Two or more letters, all uppercase (`COLOR`).
+    Upper,
+    /// Uppercase first letter, the rest lowercase (`Color`).
+    Capitalized,
+    /// Anything else (`colOR`, `iPhone`), or no cased letters at all.
+    Mixed,
+}
+
+fn case_shape(text: &str) -> CaseShape {
+    let mut cased = text.chars().filter(|c| c.is_lowercase() || c.is_uppercase());
+    let Some(first) = cased.next() else {
+        return CaseShape::Mixed;
+    };
+    let rest: Vec<char> = cased.collect();
+
+    if first.is_lowercase() {
+        if rest.iter().all(|c| c.is_lowercase()) {
+            CaseShape::Lower
         } else {
-            buffer.line_start(end_line + 1)
-        };
+            CaseShape::Mixed
+        }
+    } else if rest.is_empty() || rest.iter().all(|c| c.is_lowercase()) {
+        // A single uppercase letter reads as Capitalized, not shouting
+        CaseShape::Capitalized
+    } else if rest.iter().all(|c| c.is_uppercase()) {
+        CaseShape::Upper
+    } else {
+        CaseShape::Mixed
+    }
+}
 
-        self.matches.iter()
-            .filter(|m| m.end > range_start && m.start < range_end)
-            .copied()
-            .collect()
+/// Recases `replacement` to follow the case shape of `matched`.
+///
+/// Lowercase and UPPERCASE matches convert the whole replacement; a
+/// Capitalized match uppercases the replacement's first letter and leaves the
+/// rest as typed. Mixed-case matches keep the replacement unchanged.
+pub fn preserve_case(matched: &str, replacement: &str) -> String {
+    match case_shape(matched) {
+        CaseShape::Lower => replacement.to_lowercase(),
+        CaseShape::Upper => replacement.to_uppercase(),
+        CaseShape::Capitalized => {
+            let mut chars = replacement.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        }
+        CaseShape::Mixed => replacement.to_string(),
     }
 }
 
@@ -343,4 +661,143 @@ mod tests {
         let nearest = search.find_nearest(5).unwrap();
         assert_eq!(nearest.start, 6);
     }
+
+    #[test]
+    fn test_search_non_ascii_positions() {
+        let buffer = TextBuffer::from_str("héllo wörld HÉLLO");
+        let mut search = Search::new();
+
+        // A non-ASCII query folds case the Unicode way
+        search.set_query("héllo", &buffer);
+        assert_eq!(search.matches(), &[SearchMatch::new(0, 5), SearchMatch::new(12, 17)]);
+
+        // An ASCII one is found by substring, offsets still in characters
+        search.set_query("LD", &buffer);
+        assert_eq!(search.matches(), &[SearchMatch::new(9, 11)]);
+    }
+
+    #[test]
+    fn test_search_regex() {
+        let buffer = TextBuffer::from_str("foo_test bar_test baz");
+        let mut search = Search::new();
+        search.set_use_regex(true, &buffer);
+
+        let count = search.set_query(r"(\w+)_test", &buffer);
+        assert_eq!(count, 2);
+        assert_eq!(search.matches()[1], SearchMatch::new(9, 17));
+    }
+
+    #[test]
+    fn test_search_invalid_regex() {
+        let buffer = TextBuffer::from_str("a(b");
+        let mut search = Search::new();
+        search.set_use_regex(true, &buffer);
+
+        assert_eq!(search.set_query("(", &buffer), 0);
+        assert!(search.error().is_some());
+
+        // The same query is valid as plain text
+        search.toggle_regex(&buffer);
+        assert!(search.error().is_none());
+        assert_eq!(search.match_count(), 1);
+    }
+
+    #[test]
+    fn test_expand_replacement_captures() {
+        let buffer = TextBuffer::from_str("foo_test bar_test");
+        let mut search = Search::new();
+        search.set_use_regex(true, &buffer);
+        search.set_query(r"(?P<name>\w+)_test", &buffer);
+
+        assert_eq!(search.matches()[1], SearchMatch::new(9, 17));
+        assert_eq!(search.expand_replacement("foo_test bar_test", 9..17, "test_$1"), "test_bar");
+        assert_eq!(search.expand_replacement(" bar_test", 1..9, "${name}$$"), "bar$");
+
+        let all: Vec<String> = search
+            .replacements(&buffer, "test_${1}")
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect();
+        assert_eq!(all, vec!["test_foo", "test_bar"]);
+    }
+
+    #[test]
+    fn test_plain_replacement_is_literal() {
+        let buffer = TextBuffer::from_str("aaa");
+        let mut search = Search::new();
+        search.set_query("aa", &buffer);
+
+        // Overlapping matches are reported but only replaced once
+        assert_eq!(search.match_count(), 2);
+        let replacements = search.replacements(&buffer, "$1");
+        assert_eq!(replacements, vec![(SearchMatch::new(0, 2), "$1".to_string())]);
+    }
+
+    #[test]
+    fn test_search_in_range() {
+        let buffer = TextBuffer::from_str("a a\na a\na a");
+        let mut search = Search::new();
+        search.set_range(Some((4, 7)), &buffer);
+
+        assert_eq!(search.set_query("a", &buffer), 2);
+        assert_eq!(search.matches(), &[SearchMatch::new(4, 5), SearchMatch::new(6, 7)]);
+
+        search.set_range(None, &buffer);
+        assert_eq!(search.match_count(), 6);
+    }
+
+    #[test]
+    fn test_search_in_several_ranges() {
+        let buffer = TextBuffer::from_str("abab abab");
+        let mut search = Search::new();
+        // Adjacent ranges are independent: "ba" across their border isn't a match
+        search.set_ranges(vec![(5, 7), (0, 2), (2, 4), (7, 9)], &buffer);
+        assert_eq!(search.ranges(), &[(0, 2), (2, 4), (5, 7), (7, 9)]);
+
+        assert_eq!(search.set_query("ab", &buffer), 4);
+        assert_eq!(
+            search.matches(),
+            &[SearchMatch::new(0, 2), SearchMatch::new(2, 4), SearchMatch::new(5, 7), SearchMatch::new(7, 9)]
+        );
+        assert_eq!(search.set_query("ba", &buffer), 0);
+    }
+
+    #[test]
+    fn test_preserve_case_shapes() {
+        assert_eq!(preserve_case("color", "colour"), "colour");
+        assert_eq!(preserve_case("color", "Colour"), "colour");
+        assert_eq!(preserve_case("Color", "colour"), "Colour");
+        assert_eq!(preserve_case("COLOR", "colour"), "COLOUR");
+        assert_eq!(preserve_case("colOR", "colour"), "colour");
+        assert_eq!(preserve_case("colOR", "ColoUr"), "ColoUr");
+        // Capitalized keeps the rest of the replacement as typed
+        assert_eq!(preserve_case("Color", "myColour"), "MyColour");
+        // Single uppercase letter is Capitalized; no letters is Mixed
+        assert_eq!(preserve_case("A", "apple"), "Apple");
+        assert_eq!(preserve_case("123", "Abc"), "Abc");
+        assert_eq!(preserve_case("Color", ""), "");
+    }
+
+    #[test]
+    fn test_replacements_preserve_case() {
+        let buffer = TextBuffer::from_str("color Color COLOR");
+        let mut search = Search::new();
+        search.set_query("color", &buffer);
+        assert_eq!(search.match_count(), 3);
+
+        // Off by default
+        let plain: Vec<String> = search.replacements(&buffer, "colour").into_iter().map(|(_, t)| t).collect();
+        assert_eq!(plain, vec!["colour", "colour", "colour"]);
+
+        search.toggle_preserve_case();
+        let recased: Vec<String> = search.replacements(&buffer, "colour").into_iter().map(|(_, t)| t).collect();
+        assert_eq!(recased, vec!["colour", "Colour", "COLOUR"]);
+        assert_eq!(search.expand_replacement(" COLOR", 1..6, "colour"), "COLOUR");
+
+        // Applies after capture expansion in regex mode
+        search.set_use_regex(true, &buffer);
+        search.set_query(r"(c)olor", &buffer);
+        let regex: Vec<String> = search.replacements(&buffer, "${1}olour").into_iter().map(|(_, t)| t).collect();
+        assert_eq!(regex, vec!["colour", "Colour", "COLOUR"]);
+    }
 }
@@ -0,0 +1,29 @@
+//! The open tabs, kept across restarts.
+//!
+//! [`Workspace::save_session`](crate::Workspace::save_session) writes a
+//! [`Session`] to a JSON file when the editor quits and
+//! [`Workspace::restore_session`](crate::Workspace::restore_session) reopens
+//! it on the next launch. Only buffers backed by a file are kept; untitled
+//! buffers have nowhere to be reopened from.
+
+use crate::view_state::ViewState;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Open files in tab order, with the active one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// Open files, in tab order.
+    pub tabs: Vec<SessionTab>,
+    /// Index into `tabs` of the active tab.
+    pub active: Option<usize>,
+}
+
+/// An open file and how its view looked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionTab {
+    /// Path of the file.
+    pub path: PathBuf,
+    /// Cursor, selection, scroll position, folds and bookmarks.
+    pub view: ViewState,
+}
@@ -0,0 +1,43 @@
+//! Settings each buffer holds for itself.
+//!
+//! A [`Workspace`](crate::Workspace) has default [`EditorSettings`] that new
+//! and opened buffers start from; after that each [`Editor`](crate::Editor)
+//! keeps its own copy, so toggling word wrap or changing the indentation in
+//! one tab leaves the others alone.
+
+use crate::indent::IndentConfig;
+use crate::syntax::Language;
+
+/// Default wrap width in characters, until the view measures its own.
+pub const DEFAULT_WRAP_WIDTH: usize = 80;
+
+/// Word wrap, indentation, language and save clean-up of a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EditorSettings {
+    /// Whether long lines wrap.
+    pub word_wrap: bool,
+    /// Wrap width in characters, used when `word_wrap` is on.
+    pub wrap_width: usize,
+    /// Indentation style and width.
+    pub indent: IndentConfig,
+    /// Language to highlight the buffer as; `None` picks it from the file
+    /// name.
+    pub language: Option<Language>,
+    /// Whether saving strips spaces and tabs from the ends of lines.
+    pub trim_trailing_whitespace: bool,
+    /// Whether saving adds a line break at the end if there is none.
+    pub ensure_final_newline: bool,
+}
+
+impl Default for EditorSettings {
+    fn default() -> Self {
+        Self {
+            word_wrap: false,
+            wrap_width: DEFAULT_WRAP_WIDTH,
+            indent: IndentConfig::default(),
+            language: None,
+            trim_trailing_whitespace: false,
+            ensure_final_newline: false,
+        }
+    }
+}
@@ -0,0 +1,429 @@
+//! LSP snippets.
+//!
+//! Completion items may be snippets: text with tab stops the cursor jumps
+//! through with Tab.
+//!
+//! - `$1`, `${1}`: an empty tab stop
+//! - `${1:default}`: a tab stop with placeholder text, which may contain
+//!   further tab stops
+//! - `${1|one,two|}`: a choice; the first option is inserted
+//! - `$0`: where the cursor ends up; the end of the snippet if missing
+//! - `$name`, `${name:default}`: a variable, replaced by its default
+//!
+//! Tab stops with the same number are mirrored: they get the same text and
+//! are edited together. `\$`, `\}` and `\\` insert the character itself,
+//! and a `$` that doesn't start valid syntax is kept as is.
+
+use std::collections::HashMap;
+
+/// A parsed snippet: its text and where its tab stops are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    /// Text with the snippet syntax removed.
+    pub text: String,
+    /// Tab stops in the order Tab visits them, ending with `$0`.
+    pub tab_stops: Vec<TabStop>,
+}
+
+/// A tab stop of a snippet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabStop {
+    /// Its number; 0 is the final cursor position.
+    pub number: usize,
+    /// Character ranges of every occurrence, in text order.
+    pub ranges: Vec<(usize, usize)>,
+}
+
+/// Parses a snippet.
+pub fn parse(source: &str) -> Snippet {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        pos: 0,
+    };
+    let nodes = parser.nodes(false);
+
+    // Mirrors without their own placeholder get the first one's
+    let mut defaults = HashMap::new();
+    collect_defaults(&nodes, &mut defaults);
+
+    let mut render = Render {
+        defaults: &defaults,
+        text: String::new(),
+        len: 0,
+        stops: HashMap::new(),
+        expanding: Vec::new(),
+    };
+    render.nodes(&nodes);
+
+    let Render { text, len, stops, .. } = render;
+    let mut tab_stops: Vec<TabStop> = stops
+        .into_iter()
+        .map(|(number, ranges)| TabStop { number, ranges })
+        .collect();
+    // Ascending, with the final position last
+    tab_stops.sort_by_key(|stop| (stop.number == 0, stop.number));
+    if tab_stops.last().is_none_or(|stop| stop.number != 0) {
+        tab_stops.push(TabStop {
+            number: 0,
+            ranges: vec![(len, len)],
+        });
+    }
+    Snippet { text, tab_stops }
+}
+
+impl Snippet {
+    /// Returns the snippet as inserted on a line indented with `indent`:
+    /// every line after the first gets the indentation, and tabs become
+    /// `indent_unit`.
+    pub fn reindent(&self, indent: &str, indent_unit: &str) -> Snippet {
+        // New position of each old character position; text after a line
+        // break starts after the indentation
+        let mut map = Vec::with_capacity(self.text.len() + 1);
+        let mut text = String::with_capacity(self.text.len());
+        let mut len = 0;
+        for ch in self.text.chars() {
+            map.push(len);
+            let replacement = match ch {
+                '\n' => format!("\n{}", indent),
+                '\t' => indent_unit.to_string(),
+                _ => ch.to_string(),
+            };
+            len += replacement.chars().count();
+            text.push_str(&replacement);
+        }
+        map.push(len);
+        let tab_stops = self
+            .tab_stops
+            .iter()
+            .map(|stop| TabStop {
+                number: stop.number,
+                ranges: stop.ranges.iter().map(|&(start, end)| (map[start], map[end])).collect(),
+            })
+            .collect();
+        Snippet { text, tab_stops }
+    }
+}
+
+/// Part of a parsed snippet.
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    /// A tab stop, with its placeholder if it has one.
+    TabStop(usize, Option<Vec<Node>>),
+    /// A variable with its default.
+    Variable(Vec<Node>),
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    /// Parses up to the end, or up to an unescaped `}` inside a placeholder.
+    fn nodes(&mut self, in_placeholder: bool) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        let mut text = String::new();
+        while let Some(&ch) = self.chars.get(self.pos) {
+            match ch {
+                '\\' if matches!(self.peek(1), Some('$' | '}' | '\\')) => {
+                    text.push(self.chars[self.pos + 1]);
+                    self.pos += 2;
+                }
+                '}' if in_placeholder => break,
+                '$' => match self.dollar() {
+                    Some(node) => {
+                        if !text.is_empty() {
+                            nodes.push(Node::Text(std::mem::take(&mut text)));
+                        }
+                        nodes.push(node);
+                    }
+                    None => {
+                        text.push('$');
+                        self.pos += 1;
+                    }
+                },
+                _ => {
+                    text.push(ch);
+                    self.pos += 1;
+                }
+            }
+        }
+        if !text.is_empty() {
+            nodes.push(Node::Text(text));
+        }
+        nodes
+    }
+
+    /// Parses the syntax starting with the `$` at the current position.
+    /// Leaves the position alone and returns `None` if it isn't valid.
+    fn dollar(&mut self) -> Option<Node> {
+        let start = self.pos;
+        self.pos += 1;
+        let node = self.dollar_body();
+        if node.is_none() {
+            self.pos = start;
+        }
+        node
+    }
+
+    fn dollar_body(&mut self) -> Option<Node> {
+        if let Some(number) = self.number() {
+            return Some(Node::TabStop(number, None));
+        }
+        if let Some(_name) = self.name() {
+            return Some(Node::Variable(Vec::new()));
+        }
+        self.eat('{')?;
+        if let Some(number) = self.number() {
+            if self.eat('}').is_some() {
+                return Some(Node::TabStop(number, None));
+            }
+            if self.eat(':').is_some() {
+                let placeholder = self.nodes(true);
+                self.eat('}')?;
+                return Some(Node::TabStop(number, Some(placeholder)));
+            }
+            self.eat('|')?;
+            let first = self.choice()?;
+            return Some(Node::TabStop(number, Some(vec![Node::Text(first)])));
+        }
+        self.name()?;
+        if self.eat('}').is_some() {
+            return Some(Node::Variable(Vec::new()));
+        }
+        if self.eat(':').is_some() {
+            let default = self.nodes(true);
+            self.eat('}')?;
+            return Some(Node::Variable(default));
+        }
+        // A transform (`${name/regex/format/options}`) isn't applied
+        self.eat('/')?;
+        while let Some(&ch) = self.chars.get(self.pos) {
+            self.pos += if ch == '\\' { 2 } else { 1 };
+            if ch == '}' {
+                return Some(Node::Variable(Vec::new()));
+            }
+        }
+        None
+    }
+
+    /// Parses the options of a choice after its `|`, through the closing
+    /// `|}`, and returns the first.
+    fn choice(&mut self) -> Option<String> {
+        let mut options = vec![String::new()];
+        loop {
+            let ch = *self.chars.get(self.pos)?;
+            self.pos += 1;
+            match ch {
+                '\\' if matches!(self.peek(0), Some('$' | '}' | '\\' | ',' | '|')) => {
+                    options.last_mut().unwrap().push(self.chars[self.pos]);
+                    self.pos += 1;
+                }
+                ',' => options.push(String::new()),
+                '|' => {
+                    self.eat('}')?;
+                    return options.into_iter().next();
+                }
+                _ => options.last_mut().unwrap().push(ch),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek(0).is_some_and(|ch| ch.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits.parse().ok()
+    }
+
+    fn name(&mut self) -> Option<String> {
+        let start = self.pos;
+        if !self.peek(0).is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_') {
+            return None;
+        }
+        while self.peek(0).is_some_and(|ch| ch.is_ascii_alphanumeric() || ch == '_') {
+            self.pos += 1;
+        }
+        Some(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn eat(&mut self, expected: char) -> Option<()> {
+        (self.peek(0)? == expected).then(|| self.pos += 1)
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+}
+
+/// Records the first placeholder of each tab stop number.
+fn collect_defaults(nodes: &[Node], defaults: &mut HashMap<usize, Vec<Node>>) {
+    for node in nodes {
+        match node {
+            Node::TabStop(number, Some(placeholder)) => {
+                defaults.entry(*number).or_insert_with(|| placeholder.clone());
+                collect_defaults(placeholder, defaults);
+            }
+            Node::Variable(default) => collect_defaults(default, defaults),
+            _ => {}
+        }
+    }
+}
+
+struct Render<'a> {
+    defaults: &'a HashMap<usize, Vec<Node>>,
+    text: String,
+    /// Length of `text` in characters.
+    len: usize,
+    stops: HashMap<usize, Vec<(usize, usize)>>,
+    /// Tab stops whose placeholder is being rendered, so a placeholder
+    /// containing its own number doesn't repeat forever.
+    expanding: Vec<usize>,
+}
+
+impl Render<'_> {
+    fn nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            match node {
+                Node::Text(text) => {
+                    self.text.push_str(text);
+                    self.len += text.chars().count();
+                }
+                Node::TabStop(number, placeholder) => {
+                    let start = self.len;
+                    let placeholder = placeholder.as_ref().or_else(|| self.defaults.get(number));
+                    if let Some(placeholder) = placeholder.filter(|_| !self.expanding.contains(number)) {
+                        self.expanding.push(*number);
+                        self.nodes(placeholder);
+                        self.expanding.pop();
+                    }
+                    self.stops.entry(*number).or_default().push((start, self.len));
+                }
+                Node::Variable(default) => self.nodes(default),
+            }
+        }
+    }
+}
+
+/// The tab stops of an inserted snippet, tracked while it is filled in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnippetSession {
+    /// Buffer ranges of each tab stop, in the order Tab visits them.
+    stops: Vec<Vec<(usize, usize)>>,
+    /// Index of the tab stop being filled in.
+    current: usize,
+}
+
+impl SnippetSession {
+    /// Starts at the first of `stops`.
+    pub fn new(stops: Vec<Vec<(usize, usize)>>) -> Self {
+        Self { stops, current: 0 }
+    }
+
+    /// Returns the ranges of the tab stop being filled in.
+    pub fn current(&self) -> &[(usize, usize)] {
+        &self.stops[self.current]
+    }
+
+    /// Returns true if the last tab stop, the final cursor position, is
+    /// current.
+    pub fn is_at_end(&self) -> bool {
+        self.current + 1 >= self.stops.len()
+    }
+
+    /// Returns true if `pos` is in or at the edge of a range of the current
+    /// tab stop.
+    pub fn contains(&self, pos: usize) -> bool {
+        self.current().iter().any(|&(start, end)| (start..=end).contains(&pos))
+    }
+
+    /// Moves to the next tab stop and returns its ranges.
+    pub fn next_stop(&mut self) -> &[(usize, usize)] {
+        self.current = (self.current + 1).min(self.stops.len() - 1);
+        self.current()
+    }
+
+    /// Moves to the previous tab stop and returns its ranges.
+    pub fn prev_stop(&mut self) -> &[(usize, usize)] {
+        self.current = self.current.saturating_sub(1);
+        self.current()
+    }
+
+    /// Maps every range through an edit. `map(pos, is_start)` maps one end
+    /// of a range; text inserted at the start of a range of the current
+    /// tab stop is typed into it, so that start stays put.
+    pub fn map_ranges(&mut self, mut map: impl FnMut(usize, bool) -> usize) {
+        for (index, stop) in self.stops.iter_mut().enumerate() {
+            let current = index == self.current;
+            for range in stop.iter_mut() {
+                let start = map(range.0, current);
+                let end = map(range.1, false);
+                *range = (start, end.max(start));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stops(snippet: &Snippet) -> Vec<(usize, Vec<(usize, usize)>)> {
+        snippet.tab_stops.iter().map(|stop| (stop.number, stop.ranges.clone())).collect()
+    }
+
+    #[test]
+    fn test_tab_stops() {
+        let snippet = parse("fn ${1:name}($2) {\n\t$0\n}");
+        assert_eq!(snippet.text, "fn name() {\n\t\n}");
+        assert_eq!(stops(&snippet), [(1, vec![(3, 7)]), (2, vec![(8, 8)]), (0, vec![(13, 13)])]);
+
+        // Without $0 the snippet ends at its end
+        let snippet = parse("foo(${2:b}, ${1:a})");
+        assert_eq!(snippet.text, "foo(b, a)");
+        assert_eq!(stops(&snippet), [(1, vec![(7, 8)]), (2, vec![(4, 5)]), (0, vec![(9, 9)])]);
+        assert_eq!(stops(&parse("plain")), [(0, vec![(5, 5)])]);
+    }
+
+    #[test]
+    fn test_nested_and_mirrored_placeholders() {
+        let snippet = parse("${1:let ${2:x}} = $2; ${1}");
+        assert_eq!(snippet.text, "let x = x; let x");
+        assert_eq!(
+            stops(&snippet),
+            [(1, vec![(0, 5), (11, 16)]), (2, vec![(4, 5), (8, 9), (15, 16)]), (0, vec![(16, 16)])]
+        );
+
+        // A placeholder containing its own number doesn't loop
+        assert_eq!(parse("${1:a$1}").text, "a");
+    }
+
+    #[test]
+    fn test_escapes_choices_and_variables() {
+        assert_eq!(parse(r"\$1 costs \\ \}").text, r"$1 costs \ }");
+        assert_eq!(parse("${1:a\\}b}").text, "a}b");
+        // A `$` that starts nothing is kept
+        assert_eq!(parse("a $ b ${ c").text, "a $ b ${ c");
+
+        let snippet = parse("${1|one,two\\,three|}!");
+        assert_eq!(snippet.text, "one!");
+        assert_eq!(stops(&snippet)[0], (1, vec![(0, 3)]));
+
+        assert_eq!(parse("$TM_FILENAME ${TM_SELECTED_TEXT:sel} ${X/a/b/g}.").text, " sel .");
+    }
+
+    #[test]
+    fn test_reindent() {
+        let snippet = parse("if $1 {\n\t$0\n}").reindent("    ", "  ");
+        assert_eq!(snippet.text, "if  {\n      \n    }");
+        assert_eq!(stops(&snippet), [(1, vec![(3, 3)]), (0, vec![(12, 12)])]);
+
+        // A stop at the start of a line starts after the indentation
+        let snippet = parse("a\n${1:b}").reindent("  ", "\t");
+        assert_eq!(snippet.text, "a\n  b");
+        assert_eq!(stops(&snippet)[0], (1, vec![(4, 5)]));
+    }
+}
@@ -0,0 +1,201 @@
+//! File-type associations.
+//!
+//! Maps glob patterns to a language and a tab icon, so files the extension
+//! table doesn't know (`Dockerfile`, `*.conf`) are recognized, and so users
+//! can override the defaults. Associations are consulted before
+//! [`Language::from_path`].
+//!
+//! Patterns without a `/` match the file name, e.g. `*.test.ts`; patterns
+//! with a `/` match the end of the path, e.g. `.github/workflows/*.yml`.
+//! `*` matches any run of characters except `/`, `**` also matches `/`, and
+//! `?` matches one character. Matching is case-sensitive.
+
+use std::path::Path;
+
+use super::Language;
+
+/// One glob pattern and what files matching it are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileAssociation {
+    /// Glob pattern, e.g. `*.conf` or `Dockerfile`.
+    pub pattern: String,
+    /// Language of matching files.
+    pub language: Language,
+    /// Icon shown in the tab; the language's icon if `None`.
+    pub icon: Option<String>,
+}
+
+/// An ordered table of file-type associations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileAssociations {
+    entries: Vec<FileAssociation>,
+}
+
+impl Default for FileAssociations {
+    /// The built-in associations for well-known files without a useful
+    /// extension.
+    fn default() -> Self {
+        let mut associations = Self::empty();
+        associations.add("Dockerfile", Language::PlainText, Some("dkr"));
+        associations.add("Dockerfile.*", Language::PlainText, Some("dkr"));
+        associations.add("*.conf", Language::PlainText, Some("cfg"));
+        associations.add("CMakeLists.txt", Language::PlainText, Some("cmk"));
+        associations.add("Makefile", Language::PlainText, Some("mk"));
+        associations.add("*.mk", Language::PlainText, Some("mk"));
+        associations
+    }
+}
+
+impl FileAssociations {
+    /// Creates a table without any associations.
+    pub fn empty() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// Adds an association. Later associations take precedence over earlier
+    /// ones, so user entries added after the defaults override them.
+    pub fn add(&mut self, pattern: &str, language: Language, icon: Option<&str>) {
+        self.entries.push(FileAssociation {
+            pattern: pattern.to_string(),
+            language,
+            icon: icon.map(str::to_string),
+        });
+    }
+
+    /// Returns all associations, in the order they were added.
+    pub fn entries(&self) -> &[FileAssociation] {
+        &self.entries
+    }
+
+    /// Returns the association for `path`, if any matches.
+    pub fn find(&self, path: &Path) -> Option<&FileAssociation> {
+        self.entries.iter().rev().find(|entry| path_matches(&entry.pattern, path))
+    }
+
+    /// Returns the language of `path`: the associated one if an association
+    /// matches, otherwise the one its extension implies.
+    pub fn language(&self, path: &Path) -> Language {
+        self.find(path)
+            .map(|entry| entry.language)
+            .unwrap_or_else(|| Language::from_path(path))
+    }
+
+    /// Returns the tab icon for `path`.
+    pub fn icon(&self, path: &Path) -> &str {
+        match self.find(path) {
+            Some(entry) => entry.icon.as_deref().unwrap_or(entry.language.icon()),
+            None => Language::from_path(path).icon(),
+        }
+    }
+}
+
+/// Returns whether `path` matches `pattern`, as described in the module
+/// docs: the file name without a `/` in the pattern, whole trailing
+/// components with one.
+pub fn path_matches(pattern: &str, path: &Path) -> bool {
+    if !pattern.contains('/') {
+        return path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| glob_match(pattern, name));
+    }
+    let Some(path) = path.to_str() else {
+        return false;
+    };
+    let path = path.replace('\\', "/");
+    let pattern = pattern.trim_start_matches('/');
+    // Match whole trailing components only
+    glob_match(pattern, &path)
+        || path
+            .match_indices('/')
+            .any(|(i, _)| glob_match(pattern, &path[i + 1..]))
+}
+
+/// Matches `text` against a glob pattern (`*`, `**` and `?`).
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_chars(&pattern, &text)
+}
+
+fn glob_match_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => {
+            let crosses_slash = pattern.get(1) == Some(&'*');
+            let rest = &pattern[if crosses_slash { 2 } else { 1 }..];
+            // Try every split of the text, stopping at a `/` unless `**`
+            for skip in 0..=text.len() {
+                if glob_match_chars(rest, &text[skip..]) {
+                    return true;
+                }
+                if skip < text.len() && text[skip] == '/' && !crosses_slash {
+                    return false;
+                }
+            }
+            false
+        }
+        Some('?') => !text.is_empty() && text[0] != '/' && glob_match_chars(&pattern[1..], &text[1..]),
+        Some(&c) => text.first() == Some(&c) && glob_match_chars(&pattern[1..], &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.test.ts", "api.test.ts"));
+        assert!(glob_match("*.test.ts", ".test.ts"));
+        assert!(!glob_match("*.test.ts", "api.ts"));
+        assert!(!glob_match("*.test.ts", "api.test.tsx"));
+        assert!(glob_match("Dockerfile", "Dockerfile"));
+        assert!(!glob_match("Dockerfile", "dockerfile"));
+        assert!(glob_match("Dockerfile.*", "Dockerfile.dev"));
+        assert!(glob_match("file?.c", "file1.c"));
+        assert!(!glob_match("file?.c", "file.c"));
+        assert!(!glob_match("*.rs", "src/main.rs"));
+        assert!(glob_match("**.rs", "src/main.rs"));
+        assert!(glob_match("src/**/*.rs", "src/a/b/main.rs"));
+    }
+
+    #[test]
+    fn test_default_associations() {
+        let associations = FileAssociations::default();
+        assert_eq!(associations.icon(Path::new("/repo/Dockerfile")), "dkr");
+        assert_eq!(associations.icon(Path::new("/etc/nginx/nginx.conf")), "cfg");
+        assert_eq!(associations.icon(Path::new("CMakeLists.txt")), "cmk");
+        // Files without an association fall back to their extension
+        assert_eq!(associations.language(Path::new("main.rs")), Language::Rust);
+        assert_eq!(associations.icon(Path::new("main.rs")), "rs");
+        assert_eq!(associations.icon(Path::new("notes")), Language::PlainText.icon());
+    }
+
+    #[test]
+    fn test_association_overrides_extension() {
+        let mut associations = FileAssociations::default();
+        assert_eq!(associations.language(Path::new("tsconfig.json")), Language::Json);
+
+        associations.add("*.json", Language::JavaScript, None);
+        assert_eq!(associations.language(Path::new("tsconfig.json")), Language::JavaScript);
+        assert_eq!(associations.icon(Path::new("tsconfig.json")), "js");
+
+        // A later, more specific entry wins over an earlier one
+        associations.add("*.test.ts", Language::TypeScript, Some("tst"));
+        associations.add("*.conf", Language::C, None);
+        assert_eq!(associations.icon(Path::new("src/api.test.ts")), "tst");
+        assert_eq!(associations.icon(Path::new("src/api.ts")), "ts");
+        assert_eq!(associations.language(Path::new("x.conf")), Language::C);
+    }
+
+    #[test]
+    fn test_path_patterns() {
+        let mut associations = FileAssociations::empty();
+        associations.add(".github/workflows/*.yml", Language::PlainText, Some("ci"));
+        assert!(associations.find(Path::new("/repo/.github/workflows/test.yml")).is_some());
+        assert!(associations.find(Path::new(".github/workflows/test.yml")).is_some());
+        assert!(associations.find(Path::new("/repo/my.github/workflows/test.yml")).is_none());
+        assert!(associations.find(Path::new("/repo/.github/test.yml")).is_none());
+    }
+}
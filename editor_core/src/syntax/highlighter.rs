@@ -6,6 +6,11 @@ use super::language::Language;
 use super::theme::{Theme, TokenStyle};
 use tree_sitter::{Node, Parser, Tree, TreeCursor};
 
+/// Longest line, in bytes, that gets highlighted. Longer lines, as in
+/// minified files, are drawn in the plain foreground color: mapping every
+/// token on them to columns would cost more than the rest of the file.
+pub const MAX_HIGHLIGHTED_LINE_LEN: usize = 10_000;
+
 /// A highlighted span representing a range of text with a style.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct HighlightSpan {
@@ -69,6 +74,19 @@ impl Default for LineHighlights {
     }
 }
 
+/// A bracket and its nesting depth, for bracket-pair colorization.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BracketColor {
+    /// Line of the bracket (0-indexed).
+    pub line: usize,
+    /// Column of the bracket in characters (0-indexed).
+    pub col: usize,
+    /// Nesting depth; a pair's opening and closing brackets share a depth.
+    pub depth: usize,
+    /// Theme color for the depth.
+    pub color: [f32; 4],
+}
+
 /// Syntax highlighter using tree-sitter for incremental parsing.
 pub struct SyntaxHighlighter {
     /// Tree-sitter parser.
@@ -81,6 +99,8 @@ pub struct SyntaxHighlighter {
     theme: Theme,
     /// Cached line highlights.
     line_cache: Vec<LineHighlights>,
+    /// Cached bracket colors per line, as (column, color).
+    bracket_cache: Vec<Vec<(usize, [f32; 4])>>,
     /// Whether the cache is valid.
     cache_valid: bool,
 }
@@ -94,6 +114,7 @@ impl SyntaxHighlighter {
             language: Language::PlainText,
             theme: Theme::dark(),
             line_cache: Vec::new(),
+            bracket_cache: Vec::new(),
             cache_valid: false,
         }
     }
@@ -132,6 +153,21 @@ impl SyntaxHighlighter {
         self.language
     }
 
+    /// Returns the byte range of the smallest syntax node that strictly
+    /// contains `start..end`: covers it and is larger. `None` without a
+    /// parse tree or if no node is larger.
+    pub fn enclosing_node(&self, start: usize, end: usize) -> Option<std::ops::Range<usize>> {
+        let tree = self.tree.as_ref()?;
+        let mut node = tree.root_node().descendant_for_byte_range(start, end)?;
+        loop {
+            let range = node.byte_range();
+            if range.start <= start && range.end >= end && range.len() > end - start {
+                return Some(range);
+            }
+            node = node.parent()?;
+        }
+    }
+
     /// Parses the source code and updates the syntax tree.
     /// This performs a full parse.
     pub fn parse(&mut self, source: &str) {
@@ -196,6 +232,7 @@ impl SyntaxHighlighter {
     pub fn build_line_cache(&mut self, source: &str, line_count: usize) {
         self.line_cache.clear();
         self.line_cache.resize_with(line_count, LineHighlights::new);
+        self.bracket_cache.clear();
 
         let tree = match &self.tree {
             Some(t) => t,
@@ -231,9 +268,91 @@ impl SyntaxHighlighter {
             }
         }
 
+        // Bracket depths depend on the spans, so they're computed last
+        if !self.theme.bracket_colors.is_empty() {
+            self.bracket_cache.resize_with(line_count, Vec::new);
+            for bracket in self.bracket_depth_colors(source) {
+                if let Some(line) = self.bracket_cache.get_mut(bracket.line) {
+                    line.push((bracket.col, bracket.color));
+                }
+            }
+        }
+
         self.cache_valid = true;
     }
 
+    /// Computes the nesting depth and color of every bracket in `source`.
+    ///
+    /// Brackets inside strings, characters and comments (per the line cache)
+    /// are skipped. Depth resets to 0 at the start of each top-level syntax
+    /// node, so an unbalanced bracket doesn't shift the colors of the rest of
+    /// the file, and an unmatched closing bracket clamps the depth at 0.
+    pub fn bracket_depth_colors(&self, source: &str) -> Vec<BracketColor> {
+        let pairs = self.language.bracket_pairs();
+        // Start bytes of top-level nodes, in order
+        let mut scope_starts: Vec<usize> = Vec::new();
+        if let Some(tree) = &self.tree {
+            let root = tree.root_node();
+            let mut cursor = root.walk();
+            scope_starts.extend(root.children(&mut cursor).map(|node| node.start_byte()));
+        }
+        let mut next_scope = 0;
+
+        // Brackets on lines too long to highlight keep counting but aren't
+        // colored
+        let too_long = |start: usize| {
+            source[start..].find('\n').unwrap_or(source.len() - start) > MAX_HIGHLIGHTED_LINE_LEN
+        };
+        let mut plain_line = too_long(0);
+
+        let mut brackets = Vec::new();
+        let mut depth = 0usize;
+        let (mut line, mut col) = (0usize, 0usize);
+        for (byte, ch) in source.char_indices() {
+            while next_scope < scope_starts.len() && scope_starts[next_scope] <= byte {
+                depth = 0;
+                next_scope += 1;
+            }
+            if ch == '\n' {
+                line += 1;
+                col = 0;
+                plain_line = too_long(byte + 1);
+                continue;
+            }
+
+            let skipped = || {
+                self.line_cache.get(line).and_then(|hl| hl.style_at(col)).is_some_and(|style| {
+                    matches!(style, TokenStyle::String | TokenStyle::Char | TokenStyle::Comment)
+                })
+            };
+            let bracket_depth = if pairs.iter().any(|&(open, _)| open == ch) && !skipped() {
+                depth += 1;
+                Some(depth - 1)
+            } else if pairs.iter().any(|&(_, close)| close == ch) && !skipped() {
+                depth = depth.saturating_sub(1);
+                Some(depth)
+            } else {
+                None
+            };
+            if let Some(depth) = bracket_depth.filter(|_| !plain_line) {
+                if let Some(color) = self.theme.bracket_color(depth) {
+                    brackets.push(BracketColor { line, col, depth, color });
+                }
+            }
+            col += 1;
+        }
+        brackets
+    }
+
+    /// Returns the bracket color at a position, if a colorized bracket is there.
+    pub fn bracket_color_at(&self, line: usize, col: usize) -> Option<[f32; 4]> {
+        let brackets = self.bracket_cache.get(line)?;
+        brackets
+            .binary_search_by_key(&col, |&(c, _)| c)
+            .ok()
+            .map(|i| brackets[i].1)
+    }
+
     /// Recursively collects highlights from the tree.
     fn collect_highlights(
         cursor: &mut TreeCursor,
@@ -246,6 +365,16 @@ impl SyntaxHighlighter {
         loop {
             let node = cursor.node();
 
+            // Nodes on a line too long to highlight are skipped, children
+            // and all
+            let row = node.start_position().row;
+            if row == node.end_position().row && Self::is_long_line(source, line_starts, row) {
+                if !cursor.goto_next_sibling() {
+                    break;
+                }
+                continue;
+            }
+
             // Determine style based on node type
             if let Some(style) = Self::node_style_static(&node, language) {
                 Self::add_node_highlights_static(
@@ -484,17 +613,16 @@ impl SyntaxHighlighter {
     fn c_cpp_node_style_static(node: &Node, kind: &str) -> Option<TokenStyle> {
         match kind {
             // Keywords
-            "auto" | "break" | "case" | "const" | "continue" | "default" | "do"
-            | "else" | "enum" | "extern" | "for" | "goto" | "if" | "inline"
-            | "register" | "restrict" | "return" | "signed" | "sizeof" | "static"
-            | "struct" | "switch" | "typedef" | "union" | "unsigned" | "void"
-            | "volatile" | "while" => Some(TokenStyle::Keyword),
+            "auto" | "const" | "default" | "enum" | "extern" | "inline"
+            | "register" | "restrict" | "signed" | "sizeof" | "static"
+            | "struct" | "typedef" | "union" | "unsigned" | "void"
+            | "volatile" => Some(TokenStyle::Keyword),
 
             // C++ specific
             "class" | "namespace" | "template" | "typename" | "virtual" | "override"
             | "final" | "public" | "private" | "protected" | "friend" | "new"
             | "delete" | "this" | "throw" | "try" | "catch" | "using" | "constexpr"
-            | "nullptr" | "noexcept" | "decltype" | "explicit" | "mutable"
+            | "noexcept" | "decltype" | "explicit" | "mutable"
             | "operator" => Some(TokenStyle::Keyword),
 
             // Control flow
@@ -555,6 +683,14 @@ impl SyntaxHighlighter {
         false
     }
 
+    /// Returns whether line `row` is longer than
+    /// [`MAX_HIGHLIGHTED_LINE_LEN`].
+    fn is_long_line(source: &str, line_starts: &[usize], row: usize) -> bool {
+        let start = line_starts.get(row).copied().unwrap_or(source.len());
+        let end = line_starts.get(row + 1).map_or(source.len(), |&next| next - 1);
+        end.saturating_sub(start) > MAX_HIGHLIGHTED_LINE_LEN
+    }
+
     /// Adds highlight spans for a node (static version).
     fn add_node_highlights_static(
         node: &Node,
@@ -579,6 +715,9 @@ impl SyntaxHighlighter {
                 .get(row + 1)
                 .map(|&s| s.saturating_sub(1))
                 .unwrap_or(source.len());
+            if Self::is_long_line(source, line_starts, row) {
+                continue;
+            }
 
             let span_start = start_byte.max(line_start);
             let span_end = end_byte.min(line_end);
@@ -670,6 +809,72 @@ mod tests {
         assert!(highlighter.has_highlighting());
     }
 
+    /// Returns (line, col, depth) of each colorized bracket.
+    fn depths(highlighter: &SyntaxHighlighter, source: &str) -> Vec<(usize, usize, usize)> {
+        highlighter
+            .bracket_depth_colors(source)
+            .into_iter()
+            .map(|b| (b.line, b.col, b.depth))
+            .collect()
+    }
+
+    #[test]
+    fn test_bracket_depths() {
+        let mut highlighter = SyntaxHighlighter::new();
+        highlighter.set_language(Language::Rust);
+        let source = "fn f(a: [u8; 2]) {\n    g((a), \"(\");\n}\n";
+        highlighter.parse(source);
+        highlighter.build_line_cache(source, 4);
+
+        assert_eq!(
+            depths(&highlighter, source),
+            vec![
+                (0, 4, 0), (0, 8, 1), (0, 14, 1), (0, 15, 0), (0, 17, 0),
+                // The bracket inside the string literal is skipped
+                (1, 5, 1), (1, 6, 2), (1, 8, 2), (1, 14, 1),
+                (2, 0, 0),
+            ]
+        );
+        let theme = highlighter.theme();
+        assert_eq!(highlighter.bracket_color_at(1, 6), theme.bracket_color(2));
+        assert_eq!(highlighter.bracket_color_at(1, 7), None);
+    }
+
+    #[test]
+    fn test_bracket_depths_unbalanced() {
+        let mut highlighter = SyntaxHighlighter::new();
+        // Plain text has no tree, so depth only resets by clamping
+        let source = ")) (x";
+        assert_eq!(depths(&highlighter, source), vec![(0, 0, 0), (0, 1, 0), (0, 3, 0)]);
+
+        // An unclosed bracket in one item doesn't carry into the next
+        highlighter.set_language(Language::Rust);
+        let source = "fn a() { (\n}\nfn b() {}\n";
+        highlighter.parse(source);
+        highlighter.build_line_cache(source, 4);
+        let b_open = depths(&highlighter, source).into_iter().find(|&(line, col, _)| (line, col) == (2, 4));
+        assert_eq!(b_open, Some((2, 4, 0)));
+    }
+
+    #[test]
+    fn test_long_lines_are_plain() {
+        let mut highlighter = SyntaxHighlighter::new();
+        highlighter.set_language(Language::Rust);
+        let long = format!("const B: [&str; 3000] = [{}];", "\"b\", ".repeat(3000));
+        assert!(long.len() > MAX_HIGHLIGHTED_LINE_LEN);
+        let source = format!("const A: &str = \"a\";\n{}\nconst C: [u8; 1] = [1];\n", long);
+        highlighter.parse(&source);
+        highlighter.build_line_cache(&source, 4);
+
+        assert!(!highlighter.line_highlights(0).unwrap().spans().is_empty());
+        assert!(highlighter.line_highlights(1).unwrap().spans().is_empty());
+        assert_eq!(highlighter.color_at(1, 30), highlighter.theme().foreground);
+        assert_eq!(highlighter.bracket_color_at(1, 9), None);
+        // Lines after it are still highlighted
+        assert!(!highlighter.line_highlights(2).unwrap().spans().is_empty());
+        assert!(highlighter.bracket_color_at(2, 9).is_some());
+    }
+
     #[test]
     fn test_plain_text() {
         let mut highlighter = SyntaxHighlighter::new();
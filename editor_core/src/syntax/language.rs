@@ -5,8 +5,10 @@
 
 use std::path::Path;
 
+use crate::buffer::WordChars;
+
 /// Supported programming languages.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum Language {
     Rust,
     Python,
@@ -15,6 +17,7 @@ pub enum Language {
     C,
     Cpp,
     Json,
+    #[default]
     PlainText,
 }
 
@@ -37,7 +40,7 @@ impl Language {
     pub fn from_path(path: &Path) -> Self {
         path.extension()
             .and_then(|ext| ext.to_str())
-            .map(|ext| Self::from_extension(ext))
+            .map(Self::from_extension)
             .unwrap_or(Self::PlainText)
     }
 
@@ -77,6 +80,20 @@ impl Language {
         }
     }
 
+    /// Returns a short type label shown in front of tab names.
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Self::Rust => "rs",
+            Self::Python => "py",
+            Self::JavaScript => "js",
+            Self::TypeScript => "ts",
+            Self::C => "c",
+            Self::Cpp => "c++",
+            Self::Json => "{}",
+            Self::PlainText => "txt",
+        }
+    }
+
     /// Returns whether this language supports syntax highlighting.
     pub fn has_highlighting(&self) -> bool {
         !matches!(self, Self::PlainText)
@@ -111,17 +128,30 @@ impl Language {
         }
     }
 
+    /// Returns the characters that make up words in this language, for word
+    /// movement and selection.
+    pub fn word_chars(&self) -> WordChars {
+        match self {
+            // `$` is a valid identifier character
+            Self::JavaScript | Self::TypeScript => WordChars::new("$"),
+            _ => WordChars::default(),
+        }
+    }
+
     /// Returns the bracket pairs for this language.
     /// Used for bracket matching and auto-closing.
     pub fn bracket_pairs(&self) -> &'static [(char, char)] {
         // Most languages use the same bracket pairs
         &[('(', ')'), ('[', ']'), ('{', '}')]
     }
-}
 
-impl Default for Language {
-    fn default() -> Self {
-        Self::PlainText
+    /// Returns the quote characters that are auto-closed in this language.
+    pub fn quote_chars(&self) -> &'static [char] {
+        match self {
+            Self::JavaScript | Self::TypeScript | Self::PlainText => &['"', '\'', '`'],
+            Self::Json => &['"'],
+            _ => &['"', '\''],
+        }
     }
 }
 
@@ -2,10 +2,12 @@
 //!
 //! Provides incremental syntax highlighting using tree-sitter.
 
+mod association;
 mod highlighter;
 mod language;
 mod theme;
 
-pub use highlighter::{HighlightSpan, LineHighlights, SyntaxHighlighter};
+pub use association::{glob_match, path_matches, FileAssociation, FileAssociations};
+pub use highlighter::{BracketColor, HighlightSpan, LineHighlights, SyntaxHighlighter, MAX_HIGHLIGHTED_LINE_LEN};
 pub use language::Language;
 pub use theme::{Theme, TokenStyle};
@@ -55,6 +55,9 @@ pub struct Theme {
     pub background: Color,
     /// Default text color.
     pub foreground: Color,
+    /// Colors cycled through by bracket nesting depth; empty disables
+    /// bracket-pair colorization.
+    pub bracket_colors: Vec<Color>,
     /// Colors for each token style.
     colors: std::collections::HashMap<TokenStyle, Color>,
 }
@@ -66,6 +69,7 @@ impl Theme {
             name: name.to_string(),
             background: [0.102, 0.102, 0.122, 1.0],    // #1A1A1F
             foreground: [0.902, 0.902, 0.902, 1.0],    // #E6E6E6
+            bracket_colors: Vec::new(),
             colors: std::collections::HashMap::new(),
         }
     }
@@ -80,6 +84,15 @@ impl Theme {
         self.colors.get(&style).copied().unwrap_or(self.foreground)
     }
 
+    /// Returns the color for a bracket at nesting `depth`, cycling through
+    /// `bracket_colors`.
+    pub fn bracket_color(&self, depth: usize) -> Option<Color> {
+        if self.bracket_colors.is_empty() {
+            return None;
+        }
+        Some(self.bracket_colors[depth % self.bracket_colors.len()])
+    }
+
     /// Creates the default dark theme (similar to One Dark).
     pub fn dark() -> Self {
         let mut theme = Self::new("Dark");
@@ -136,6 +149,13 @@ impl Theme {
         // Default
         theme.set_color(TokenStyle::Default, theme.foreground);
 
+        // Brackets - gold, magenta, blue
+        theme.bracket_colors = vec![
+            [1.000, 0.843, 0.000, 1.0],    // #FFD700
+            [0.855, 0.439, 0.839, 1.0],    // #DA70D6
+            [0.090, 0.624, 1.000, 1.0],    // #179FFF
+        ];
+
         theme
     }
 
@@ -176,6 +196,13 @@ impl Theme {
         // Default
         theme.set_color(TokenStyle::Default, theme.foreground);
 
+        // Brackets - blue, green, brown
+        theme.bracket_colors = vec![
+            [0.016, 0.192, 1.000, 1.0],    // #0431FA
+            [0.192, 0.576, 0.192, 1.0],    // #319331
+            [0.482, 0.220, 0.078, 1.0],    // #7B3814
+        ];
+
         theme
     }
 }
@@ -207,6 +234,15 @@ mod tests {
         assert_eq!(theme.name, "Light");
     }
 
+    #[test]
+    fn test_bracket_color_cycles() {
+        let theme = Theme::dark();
+        let count = theme.bracket_colors.len();
+        assert_eq!(theme.bracket_color(0), theme.bracket_color(count));
+        assert_ne!(theme.bracket_color(0), theme.bracket_color(1));
+        assert_eq!(Theme::new("Test").bracket_color(0), None);
+    }
+
     #[test]
     fn test_fallback_color() {
         let theme = Theme::new("Test");
@@ -0,0 +1,150 @@
+//! Placeholder expansion in command templates.
+//!
+//! Linter commands and language server configs may contain `${name}`
+//! placeholders that are expanded right before the command is spawned:
+//!
+//! - `${file}`: path of the file
+//! - `${fileBasename}`: file name with extension, e.g. `main.rs`
+//! - `${fileBasenameNoExtension}`: file name without extension
+//! - `${fileDirname}`: directory containing the file
+//! - `${fileExtname}`: extension including the dot, e.g. `.rs`
+//! - `${workspaceRoot}`: project root of the file
+//! - `${line}`, `${column}`: 1-based cursor position
+//! - `${env:NAME}`: the environment variable `NAME`
+//!
+//! Values are substituted verbatim, so paths that may contain spaces should
+//! be quoted in shell commands, e.g. `flake8 "${file}"`. A `$` that doesn't
+//! start a complete placeholder is kept as is.
+
+use std::path::{Path, PathBuf};
+
+/// What to do with a placeholder that has no value, e.g. `${file}` for an
+/// untitled buffer or an unset environment variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UndefinedVariable {
+    /// Replace it with an empty string.
+    #[default]
+    Empty,
+    /// Fail the expansion, so the command isn't run.
+    Error,
+}
+
+/// Values for the placeholders of a template.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateVars {
+    /// The file the command runs for.
+    pub file: Option<PathBuf>,
+    /// The project root of the file.
+    pub workspace_root: Option<PathBuf>,
+    /// 1-based cursor line.
+    pub line: Option<usize>,
+    /// 1-based cursor column.
+    pub column: Option<usize>,
+}
+
+impl TemplateVars {
+    /// Returns the value of the placeholder `name`, or `None` if it is
+    /// unknown or has no value.
+    pub fn lookup(&self, name: &str) -> Option<String> {
+        if let Some(var) = name.strip_prefix("env:") {
+            return std::env::var(var).ok();
+        }
+        let file = self.file.as_deref();
+        match name {
+            "file" => file.map(display),
+            "fileBasename" => file.and_then(Path::file_name).map(display),
+            "fileBasenameNoExtension" => file.and_then(Path::file_stem).map(display),
+            "fileDirname" => file.and_then(Path::parent).map(display),
+            "fileExtname" => file
+                .map(|f| f.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default()),
+            "workspaceRoot" | "workspaceFolder" => self.workspace_root.as_deref().map(display),
+            "line" => self.line.map(|line| line.to_string()),
+            "column" => self.column.map(|col| col.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Returns a path-like value as a string.
+fn display(value: impl AsRef<Path>) -> String {
+    value.as_ref().display().to_string()
+}
+
+/// Expands the `${name}` placeholders in `template`.
+///
+/// Placeholders without a value are handled according to `undefined`;
+/// with [`UndefinedVariable::Error`] the error names the placeholder.
+pub fn expand(template: &str, vars: &TemplateVars, undefined: UndefinedVariable) -> Result<String, String> {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        result.push_str(&rest[..start]);
+        match vars.lookup(name) {
+            Some(value) => result.push_str(&value),
+            None if undefined == UndefinedVariable::Error => {
+                return Err(format!("`${{{}}}` is not defined", name));
+            }
+            None => {}
+        }
+        rest = &rest[start + 2 + len + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> TemplateVars {
+        TemplateVars {
+            file: Some(PathBuf::from("/work/demo/src/main.rs")),
+            workspace_root: Some(PathBuf::from("/work/demo")),
+            line: Some(12),
+            column: Some(4),
+        }
+    }
+
+    #[test]
+    fn test_expand_file_and_workspace_placeholders() {
+        let vars = vars();
+        let expanded = expand(
+            "cd ${workspaceRoot} && check \"${file}\" --name ${fileBasenameNoExtension}${fileExtname} --at ${line}:${column}",
+            &vars,
+            UndefinedVariable::Error,
+        );
+        assert_eq!(
+            expanded.unwrap(),
+            "cd /work/demo && check \"/work/demo/src/main.rs\" --name main.rs --at 12:4"
+        );
+        assert_eq!(
+            expand("${fileDirname}/${fileBasename}", &vars, UndefinedVariable::Error).unwrap(),
+            "/work/demo/src/main.rs"
+        );
+        // Incomplete placeholders are kept
+        assert_eq!(expand("cost $5 ${file", &vars, UndefinedVariable::Error).unwrap(), "cost $5 ${file");
+    }
+
+    #[test]
+    fn test_expand_env_var() {
+        std::env::set_var("CP_EDITOR_TEMPLATE_TEST", "--strict");
+        let expanded = expand("lint ${env:CP_EDITOR_TEMPLATE_TEST} ${fileBasename}", &vars(), UndefinedVariable::Error);
+        assert_eq!(expanded.unwrap(), "lint --strict main.rs");
+    }
+
+    #[test]
+    fn test_undefined_variables() {
+        let vars = TemplateVars::default();
+        let template = "run ${file} ${env:CP_EDITOR_TEMPLATE_UNSET} ${bogus}";
+        assert_eq!(expand(template, &vars, UndefinedVariable::Empty).unwrap(), "run   ");
+        assert_eq!(
+            expand(template, &vars, UndefinedVariable::Error).unwrap_err(),
+            "`${file}` is not defined"
+        );
+    }
+}
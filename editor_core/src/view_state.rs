@@ -0,0 +1,27 @@
+//! What a buffer's view looks like apart from its text.
+//!
+//! A [`ViewState`] holds where the cursor and selection are, how far the
+//! view is scrolled, which regions are folded and which lines are
+//! bookmarked, all as lines and columns so it stays meaningful across
+//! sessions. [`Editor::view_state`](crate::Editor::view_state) exports it
+//! and [`Editor::apply_view_state`](crate::Editor::apply_view_state)
+//! restores it, clamped to the text, e.g. when reopening a file.
+
+use serde::{Deserialize, Serialize};
+
+/// Cursor, selection, scroll, folds and bookmarks of a buffer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ViewState {
+    /// Line and column of the cursor.
+    pub cursor: (usize, usize),
+    /// Line and column of the selection's anchor, if there is a selection.
+    pub selection: Option<(usize, usize)>,
+    /// First visible line.
+    pub scroll_offset: usize,
+    /// First visible column.
+    pub horizontal_scroll: usize,
+    /// Start lines of the folded regions, ascending.
+    pub folds: Vec<usize>,
+    /// Bookmarked lines, ascending.
+    pub bookmarks: Vec<usize>,
+}
@@ -0,0 +1,47 @@
+//! Picking out what falls in the visible part of a buffer.
+//!
+//! Rendering looks up the search matches and selections overlapping the
+//! visible lines once per frame with [`overlapping`], instead of checking
+//! every one of them on every line.
+
+use std::ops::Range;
+
+/// Returns the items whose character ranges overlap `window`.
+///
+/// `items` must be in order of both where their ranges start and where
+/// they end, as search matches and merged selections are, so the first and
+/// last can be found by binary search. `span` gives the character range of
+/// an item.
+pub fn overlapping<T>(items: &[T], window: Range<usize>, span: impl Fn(&T) -> Range<usize>) -> &[T] {
+    &items[overlapping_indices(items, window, span)]
+}
+
+/// Returns the indices of the items [`overlapping`] returns, e.g. to keep
+/// only those in a reused `Vec`.
+pub fn overlapping_indices<T>(items: &[T], window: Range<usize>, span: impl Fn(&T) -> Range<usize>) -> Range<usize> {
+    let first = items.partition_point(|item| span(item).end <= window.start);
+    let last = first + items[first..].partition_point(|item| span(item).start < window.end);
+    first..last
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlapping() {
+        let items = [0..2, 4..6, 8..12, 15..16, 20..30];
+        let find = |window: Range<usize>| overlapping(&items, window, |r| r.clone()).to_vec();
+
+        assert_eq!(find(5..9), vec![4..6, 8..12]);
+        assert_eq!(overlapping_indices(&items, 5..9, |r| r.clone()), 1..3);
+        // Ranges touching the window without overlapping are left out
+        assert_eq!(find(6..8), Vec::<Range<usize>>::new());
+        assert_eq!(find(2..5), vec![4..6]);
+        // A range reaching across the whole window counts
+        assert_eq!(find(22..25), vec![20..30]);
+        assert_eq!(find(0..100), items.to_vec());
+        assert_eq!(find(30..40), Vec::<Range<usize>>::new());
+        assert_eq!(overlapping(&[] as &[Range<usize>], 0..10, |r| r.clone()), &[] as &[Range<usize>]);
+    }
+}
@@ -1,7 +1,12 @@
 //! Workspace management for multiple buffers/tabs.
 
 use crate::editor::Editor;
-use std::collections::VecDeque;
+use crate::save::{SaveJob, SaveOutcome, SaveResult};
+use crate::session::{Session, SessionTab};
+use crate::settings::EditorSettings;
+use crate::syntax::FileAssociations;
+use crate::view_state::ViewState;
+use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 
@@ -19,6 +24,173 @@ pub struct TabInfo {
     pub path: Option<PathBuf>,
     /// Whether the buffer has unsaved changes.
     pub is_modified: bool,
+    /// Short file-type label from the file associations.
+    pub icon: String,
+}
+
+/// A tab's name and label, borrowed from its buffer, so the tab bar can be
+/// drawn every frame without copying them.
+#[derive(Debug, Clone, Copy)]
+pub struct TabLabel<'a> {
+    /// Buffer ID.
+    pub id: BufferId,
+    /// Display name (filename or "Untitled").
+    pub name: &'a str,
+    /// Full file path, if any.
+    pub path: Option<&'a Path>,
+    /// Whether the buffer has unsaved changes.
+    pub is_modified: bool,
+    /// Short file-type label from the file associations.
+    pub icon: &'a str,
+}
+
+/// A change in which files the workspace has open, or a file being written.
+///
+/// Only buffers backed by a file produce events; untitled buffers have
+/// nothing to report until they are saved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorkspaceEvent {
+    /// A file was opened in a buffer.
+    BufferOpened { id: BufferId, path: PathBuf },
+    /// A buffer's file changed, e.g. by Save As.
+    BufferRetargeted {
+        id: BufferId,
+        old_path: PathBuf,
+        new_path: PathBuf,
+    },
+    /// A buffer backed by a file was closed.
+    BufferClosed { id: BufferId, path: PathBuf },
+    /// A buffer was written to its file. For a background save this comes
+    /// when the write finishes, possibly after the buffer was closed.
+    BufferSaved { id: BufferId, path: PathBuf },
+}
+
+/// Which half of a vertical split a pane takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaneSide {
+    /// The left half.
+    Left,
+    /// The right half.
+    Right,
+}
+
+impl PaneSide {
+    /// Returns the other half.
+    pub fn other(self) -> Self {
+        match self {
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+        }
+    }
+}
+
+/// The pane of a split that doesn't have the focus.
+///
+/// The focused pane shows the active buffer, whose editor holds its cursor
+/// and scroll position. This one keeps its own as a [`ViewState`], so both
+/// panes can show the same buffer at different places; moving the focus
+/// swaps them. Its buffer's editor tracks the view, so edits made in the
+/// focused pane move it along with the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pane {
+    /// Buffer the pane shows.
+    pub buffer: BufferId,
+    /// Cursor and scroll position of the pane, as of the last
+    /// [`Workspace::update_unfocused_pane`].
+    pub view: ViewState,
+    /// Content version of the buffer when `view` was last updated.
+    version: u64,
+}
+
+/// Default number of recent files remembered.
+pub const MAX_RECENT_FILES: usize = 20;
+
+/// Recently opened files, most recent first, without duplicates.
+///
+/// A list loaded from a JSON file is written back to it on every change,
+/// so it survives restarts.
+#[derive(Debug, Clone)]
+pub struct RecentFiles {
+    paths: Vec<PathBuf>,
+    capacity: usize,
+    /// JSON file the list is kept in, if any.
+    store: Option<PathBuf>,
+}
+
+impl Default for RecentFiles {
+    fn default() -> Self {
+        Self::new(MAX_RECENT_FILES)
+    }
+}
+
+impl RecentFiles {
+    /// Creates an empty list of at most `capacity` files, kept in memory.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            paths: Vec::new(),
+            capacity,
+            store: None,
+        }
+    }
+
+    /// Loads the list kept in the JSON file `store`. A missing or
+    /// unreadable file gives an empty list, which is written there on the
+    /// first change.
+    pub fn load(store: PathBuf, capacity: usize) -> Self {
+        let mut paths: Vec<PathBuf> = fs::read_to_string(&store)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        paths.truncate(capacity);
+        Self {
+            paths,
+            capacity,
+            store: Some(store),
+        }
+    }
+
+    /// Returns the files, most recent first.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Moves `path` to the front, adding it if it's new and forgetting the
+    /// oldest file when full.
+    pub fn add(&mut self, path: PathBuf) {
+        self.paths.retain(|p| p != &path);
+        self.paths.insert(0, path);
+        self.paths.truncate(self.capacity);
+        self.save();
+    }
+
+    /// Forgets files that no longer exist.
+    pub fn remove_missing(&mut self) {
+        let len = self.paths.len();
+        self.paths.retain(|path| path.exists());
+        if self.paths.len() != len {
+            self.save();
+        }
+    }
+
+    /// Forgets every file.
+    pub fn clear(&mut self) {
+        self.paths.clear();
+        self.save();
+    }
+
+    /// Writes the list to its JSON file, if it has one. Errors are ignored:
+    /// losing the list isn't worth failing the open that changed it.
+    fn save(&self) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if let Some(dir) = store.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Ok(json) = serde_json::to_string_pretty(&self.paths) {
+            let _ = fs::write(store, json);
+        }
+    }
 }
 
 /// Manages multiple editor buffers.
@@ -29,12 +201,22 @@ pub struct Workspace {
     active_buffer: Option<BufferId>,
     /// Order of tabs (buffer IDs in display order).
     tab_order: Vec<BufferId>,
+    /// Open buffer IDs in most-recently-used order (most recent first).
+    mru: Vec<BufferId>,
     /// Next buffer ID to assign.
     next_id: BufferId,
-    /// Recent files list (most recent first).
-    recent_files: VecDeque<PathBuf>,
-    /// Maximum number of recent files to track.
-    max_recent_files: usize,
+    /// Recently opened files.
+    recent_files: RecentFiles,
+    /// File lifecycle events not yet taken by [`Workspace::take_events`].
+    events: Vec<WorkspaceEvent>,
+    /// Language and icon associations, consulted before the extension.
+    associations: FileAssociations,
+    /// Settings new and opened buffers start with.
+    default_settings: EditorSettings,
+    /// The pane without the focus, while the editor area is split.
+    unfocused_pane: Option<Pane>,
+    /// Side of the focused pane; the left one when not split.
+    focused_side: PaneSide,
 }
 
 impl Default for Workspace {
@@ -50,9 +232,14 @@ impl Workspace {
             buffers: Vec::new(),
             active_buffer: None,
             tab_order: Vec::new(),
+            mru: Vec::new(),
             next_id: 0,
-            recent_files: VecDeque::new(),
-            max_recent_files: 10,
+            recent_files: RecentFiles::default(),
+            events: Vec::new(),
+            associations: FileAssociations::default(),
+            default_settings: EditorSettings::default(),
+            unfocused_pane: None,
+            focused_side: PaneSide::Left,
         }
     }
 
@@ -61,7 +248,8 @@ impl Workspace {
         let id = self.next_id;
         self.next_id += 1;
 
-        let editor = Editor::new();
+        let mut editor = Editor::new();
+        editor.set_settings(self.default_settings);
 
         // Ensure buffers vec is large enough
         if id >= self.buffers.len() {
@@ -69,10 +257,11 @@ impl Workspace {
         }
         self.buffers[id] = Some(editor);
         self.tab_order.push(id);
+        self.mru.push(id);
 
         // Set as active if no active buffer
         if self.active_buffer.is_none() {
-            self.active_buffer = Some(id);
+            self.activate(id);
         }
 
         id
@@ -84,7 +273,7 @@ impl Workspace {
 
         // Check if file is already open
         if let Some(existing_id) = self.find_buffer_by_path(path) {
-            self.active_buffer = Some(existing_id);
+            self.activate(existing_id);
             return Ok(existing_id);
         }
 
@@ -92,6 +281,7 @@ impl Workspace {
         self.next_id += 1;
 
         let mut editor = Editor::new();
+        editor.set_settings(self.default_settings);
         editor.open_file(path)?;
 
         // Add to recent files
@@ -102,8 +292,13 @@ impl Workspace {
             self.buffers.resize_with(id + 1, || None);
         }
         self.buffers[id] = Some(editor);
+        self.apply_association(id);
         self.tab_order.push(id);
-        self.active_buffer = Some(id);
+        self.activate(id);
+        self.events.push(WorkspaceEvent::BufferOpened {
+            id,
+            path: path.to_path_buf(),
+        });
 
         Ok(id)
     }
@@ -112,9 +307,19 @@ impl Workspace {
     pub fn open_file_in_current<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
         let path = path.as_ref();
 
-        if let Some(editor) = self.active_editor_mut() {
+        if let Some(id) = self.active_buffer {
+            let editor = self.get_buffer_mut(id).expect("active buffer exists");
+            let old_path = editor.file_path().map(Path::to_path_buf);
             editor.open_file(path)?;
+            self.apply_association(id);
             self.add_to_recent(path.to_path_buf());
+            if let Some(old_path) = old_path {
+                self.events.push(WorkspaceEvent::BufferClosed { id, path: old_path });
+            }
+            self.events.push(WorkspaceEvent::BufferOpened {
+                id,
+                path: path.to_path_buf(),
+            });
             Ok(())
         } else {
             // No active buffer, create one
@@ -167,13 +372,28 @@ impl Workspace {
     /// Sets the active buffer.
     pub fn set_active_buffer(&mut self, id: BufferId) -> bool {
         if self.buffers.get(id).map(|b| b.is_some()).unwrap_or(false) {
-            self.active_buffer = Some(id);
+            self.activate(id);
             true
         } else {
             false
         }
     }
 
+    /// Makes `id` the active buffer and moves it to the front of the MRU list.
+    fn activate(&mut self, id: BufferId) {
+        self.active_buffer = Some(id);
+        self.mru.retain(|&mru_id| mru_id != id);
+        self.mru.insert(0, id);
+    }
+
+    /// Returns open buffer IDs in most-recently-used order (most recent first).
+    ///
+    /// Buffers that have never been active are listed after those that have,
+    /// in the order they were opened.
+    pub fn mru_order(&self) -> &[BufferId] {
+        &self.mru
+    }
+
     /// Switches to the next tab.
     pub fn next_tab(&mut self) {
         if self.tab_order.len() <= 1 {
@@ -182,7 +402,7 @@ impl Workspace {
         if let Some(active) = self.active_buffer {
             if let Some(pos) = self.tab_order.iter().position(|&id| id == active) {
                 let next_pos = (pos + 1) % self.tab_order.len();
-                self.active_buffer = Some(self.tab_order[next_pos]);
+                self.activate(self.tab_order[next_pos]);
             }
         }
     }
@@ -199,7 +419,7 @@ impl Workspace {
                 } else {
                     pos - 1
                 };
-                self.active_buffer = Some(self.tab_order[prev_pos]);
+                self.activate(self.tab_order[prev_pos]);
             }
         }
     }
@@ -207,32 +427,45 @@ impl Workspace {
     /// Switches to a specific tab by index (0-based).
     pub fn switch_to_tab(&mut self, index: usize) {
         if index < self.tab_order.len() {
-            self.active_buffer = Some(self.tab_order[index]);
+            self.activate(self.tab_order[index]);
         }
     }
 
     /// Returns information about all tabs.
     pub fn tabs(&self) -> Vec<TabInfo> {
-        self.tab_order
-            .iter()
-            .filter_map(|&id| {
-                self.buffers.get(id).and_then(|opt| {
-                    opt.as_ref().map(|editor| TabInfo {
-                        id,
-                        name: editor
-                            .file_path()
-                            .and_then(|p| p.file_name())
-                            .and_then(|n| n.to_str())
-                            .map(|s| s.to_string())
-                            .unwrap_or_else(|| "Untitled".to_string()),
-                        path: editor.file_path().map(|p| p.to_path_buf()),
-                        is_modified: editor.is_modified(),
-                    })
-                })
+        self.tab_labels()
+            .map(|tab| TabInfo {
+                id: tab.id,
+                name: tab.name.to_string(),
+                path: tab.path.map(Path::to_path_buf),
+                is_modified: tab.is_modified,
+                icon: tab.icon.to_string(),
             })
             .collect()
     }
 
+    /// Returns the tabs' names and labels in tab order, without copying them.
+    pub fn tab_labels(&self) -> impl Iterator<Item = TabLabel<'_>> {
+        self.tab_order.iter().filter_map(|&id| {
+            self.buffers.get(id).and_then(|opt| {
+                opt.as_ref().map(|editor| TabLabel {
+                    id,
+                    name: editor
+                        .file_path()
+                        .and_then(|p| p.file_name())
+                        .and_then(|n| n.to_str())
+                        .unwrap_or("Untitled"),
+                    path: editor.file_path(),
+                    is_modified: editor.is_modified(),
+                    icon: match editor.file_path() {
+                        Some(path) => self.associations.icon(path),
+                        None => editor.language().icon(),
+                    },
+                })
+            })
+        })
+    }
+
     /// Returns the number of open tabs.
     pub fn tab_count(&self) -> usize {
         self.tab_order.len()
@@ -249,17 +482,32 @@ impl Workspace {
     /// Does not check for unsaved changes - caller should handle that.
     pub fn close_buffer(&mut self, id: BufferId) -> bool {
         if let Some(opt) = self.buffers.get_mut(id) {
-            if opt.is_some() {
-                *opt = None;
+            if let Some(editor) = opt.take() {
+                if let Some(path) = editor.file_path() {
+                    self.events.push(WorkspaceEvent::BufferClosed {
+                        id,
+                        path: path.to_path_buf(),
+                    });
+                }
+
+                // A pane left without its buffer closes
+                if self.unfocused_pane.as_ref().is_some_and(|pane| pane.buffer == id) {
+                    self.unsplit();
+                }
 
                 // Remove from tab order
                 if let Some(pos) = self.tab_order.iter().position(|&tab_id| tab_id == id) {
                     self.tab_order.remove(pos);
                 }
 
+                self.mru.retain(|&mru_id| mru_id != id);
+
                 // Update active buffer if necessary
                 if self.active_buffer == Some(id) {
-                    self.active_buffer = self.tab_order.first().copied();
+                    self.active_buffer = None;
+                    if let Some(&first) = self.tab_order.first() {
+                        self.activate(first);
+                    }
                 }
 
                 return true;
@@ -278,6 +526,43 @@ impl Workspace {
         None
     }
 
+    /// Closes every buffer without unsaved changes.
+    ///
+    /// Returns the modified buffers left open, in tab order, so a caller
+    /// closing them all can ask about each before closing it with
+    /// [`Workspace::close_buffer`].
+    pub fn close_saved(&mut self) -> Vec<BufferId> {
+        self.close_unmodified(None)
+    }
+
+    /// Closes every buffer but `keep` that has no unsaved changes.
+    ///
+    /// Returns the other modified buffers left open, like
+    /// [`Workspace::close_saved`].
+    pub fn close_others(&mut self, keep: BufferId) -> Vec<BufferId> {
+        self.close_unmodified(Some(keep))
+    }
+
+    /// Closes the unmodified buffers other than `keep` and returns the
+    /// modified ones other than `keep`.
+    fn close_unmodified(&mut self, keep: Option<BufferId>) -> Vec<BufferId> {
+        let mut modified = Vec::new();
+        for id in self.tab_order.clone() {
+            if Some(id) == keep {
+                continue;
+            }
+            if self.get_buffer(id).is_some_and(Editor::is_modified) {
+                modified.push(id);
+            } else {
+                self.close_buffer(id);
+            }
+        }
+        if let Some(keep) = keep {
+            self.set_active(keep);
+        }
+        modified
+    }
+
     /// Checks if any buffer has unsaved changes.
     pub fn has_unsaved_changes(&self) -> bool {
         self.tab_order.iter().any(|&id| {
@@ -306,40 +591,156 @@ impl Workspace {
 
     /// Saves the active buffer. Returns error if no path is set.
     pub fn save_active(&mut self) -> io::Result<()> {
-        if let Some(editor) = self.active_editor_mut() {
-            editor.save()
+        if let (Some(id), Some(editor)) = (self.active_buffer, self.active_editor_mut()) {
+            editor.save()?;
+            if let Some(path) = editor.file_path().map(Path::to_path_buf) {
+                self.events.push(WorkspaceEvent::BufferSaved { id, path });
+            }
+            Ok(())
         } else {
             Err(io::Error::new(io::ErrorKind::NotFound, "No active buffer"))
         }
     }
 
+    /// Takes a snapshot of a buffer for saving in the background.
+    /// Returns an `Other` error if the buffer has no file path and a
+    /// `WouldBlock` one while its file is still loading.
+    pub fn save_job(&self, id: BufferId) -> io::Result<SaveJob> {
+        let editor = self
+            .get_buffer(id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No such buffer"))?;
+        let path = editor
+            .file_path()
+            .ok_or_else(|| io::Error::other("No file path set"))?
+            .to_path_buf();
+        editor.check_fully_loaded()?;
+        let (content, version) = editor.save_snapshot();
+        Ok(SaveJob {
+            buffer_id: id,
+            path,
+            content,
+            version,
+        })
+    }
+
+    /// Applies a finished background save to its buffer.
+    pub fn finish_save(&mut self, result: SaveResult) -> SaveOutcome {
+        if let Err(e) = result.result {
+            return SaveOutcome::Failed(e);
+        }
+        self.events.push(WorkspaceEvent::BufferSaved {
+            id: result.buffer_id,
+            path: result.path,
+        });
+        let Some(editor) = self.get_buffer_mut(result.buffer_id) else {
+            return SaveOutcome::BufferClosed;
+        };
+        if editor.mark_saved(result.version) {
+            SaveOutcome::Saved
+        } else {
+            SaveOutcome::SavedOlderVersion
+        }
+    }
+
     /// Saves the active buffer to a new path.
     pub fn save_active_as<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
         let path = path.as_ref();
-        if let Some(editor) = self.active_editor_mut() {
+        if let Some(id) = self.active_buffer {
+            let editor = self.get_buffer_mut(id).expect("active buffer exists");
+            let old_path = editor.file_path().map(Path::to_path_buf);
             editor.save_as(path)?;
+            self.apply_association(id);
             self.add_to_recent(path.to_path_buf());
+            match old_path {
+                Some(old_path) if old_path != path => self.events.push(WorkspaceEvent::BufferRetargeted {
+                    id,
+                    old_path,
+                    new_path: path.to_path_buf(),
+                }),
+                Some(_) => {}
+                None => self.events.push(WorkspaceEvent::BufferOpened {
+                    id,
+                    path: path.to_path_buf(),
+                }),
+            }
+            self.events.push(WorkspaceEvent::BufferSaved {
+                id,
+                path: path.to_path_buf(),
+            });
             Ok(())
         } else {
             Err(io::Error::new(io::ErrorKind::NotFound, "No active buffer"))
         }
     }
 
+    /// Returns the file-type associations.
+    pub fn associations(&self) -> &FileAssociations {
+        &self.associations
+    }
+
+    /// Replaces the file-type associations and re-detects the language of
+    /// every open file.
+    pub fn set_associations(&mut self, associations: FileAssociations) {
+        self.associations = associations;
+        for id in 0..self.buffers.len() {
+            self.apply_association(id);
+        }
+    }
+
+    /// Returns the settings new and opened buffers start with.
+    pub fn default_settings(&self) -> EditorSettings {
+        self.default_settings
+    }
+
+    /// Sets the settings new and opened buffers start with. Open buffers
+    /// keep their own.
+    pub fn set_default_settings(&mut self, settings: EditorSettings) {
+        self.default_settings = settings;
+    }
+
+    /// Sets a buffer's language from the associations for its file, unless
+    /// the buffer overrides it.
+    fn apply_association(&mut self, id: BufferId) {
+        let Some(editor) = self.buffers.get_mut(id).and_then(Option::as_mut) else {
+            return;
+        };
+        let Some(path) = editor.file_path() else {
+            return;
+        };
+        if editor.settings().language.is_some() {
+            return;
+        }
+        let language = self.associations.language(path);
+        if editor.language() != language {
+            editor.set_language(language);
+        }
+    }
+
+    /// Takes the file lifecycle events recorded since the last call, oldest
+    /// first.
+    pub fn take_events(&mut self) -> Vec<WorkspaceEvent> {
+        std::mem::take(&mut self.events)
+    }
+
     /// Adds a path to the recent files list.
     fn add_to_recent(&mut self, path: PathBuf) {
-        // Remove if already present
-        self.recent_files.retain(|p| p != &path);
-        // Add to front
-        self.recent_files.push_front(path);
-        // Trim to max size
-        while self.recent_files.len() > self.max_recent_files {
-            self.recent_files.pop_back();
-        }
+        self.recent_files.add(path);
+    }
+
+    /// Returns the recent files list, most recent first.
+    pub fn recent_files(&self) -> &[PathBuf] {
+        self.recent_files.paths()
     }
 
-    /// Returns the recent files list.
-    pub fn recent_files(&self) -> &VecDeque<PathBuf> {
-        &self.recent_files
+    /// Returns the recent files that still exist, forgetting the others.
+    pub fn existing_recent_files(&mut self) -> &[PathBuf] {
+        self.recent_files.remove_missing();
+        self.recent_files.paths()
+    }
+
+    /// Replaces the recent files list, e.g. with one loaded from disk.
+    pub fn set_recent_files(&mut self, recent_files: RecentFiles) {
+        self.recent_files = recent_files;
     }
 
     /// Clears the recent files list.
@@ -347,6 +748,163 @@ impl Workspace {
         self.recent_files.clear();
     }
 
+    /// Returns whether the editor area is split in two panes.
+    pub fn is_split(&self) -> bool {
+        self.unfocused_pane.is_some()
+    }
+
+    /// Returns the side of the focused pane.
+    pub fn focused_side(&self) -> PaneSide {
+        self.focused_side
+    }
+
+    /// Returns the pane without the focus, if the editor area is split.
+    pub fn unfocused_pane(&self) -> Option<&Pane> {
+        self.unfocused_pane.as_ref()
+    }
+
+    /// Moves the unfocused pane's view as the edits made to its buffer since
+    /// the last call have moved it. The view is rebuilt only when the
+    /// buffer's content changed.
+    pub fn update_unfocused_pane(&mut self) {
+        let Some(mut pane) = self.unfocused_pane.take() else {
+            return;
+        };
+        if let Some(editor) = self.get_buffer(pane.buffer).filter(|e| e.content_version() != pane.version) {
+            if let Some(view) = editor.tracked_view() {
+                pane.view = view;
+            }
+            pane.version = editor.content_version();
+        }
+        self.unfocused_pane = Some(pane);
+    }
+
+    /// Splits the editor area in two panes showing the active buffer, at the
+    /// same place, and focuses the right one. Returns false if already split
+    /// or there is no buffer to show.
+    pub fn split(&mut self) -> bool {
+        if self.is_split() {
+            return false;
+        }
+        let Some((buffer, editor)) = self.active_buffer.zip(self.active_editor_mut()) else {
+            return false;
+        };
+        let view = editor.view_state();
+        editor.track_view(&view);
+        let version = editor.content_version();
+        self.unfocused_pane = Some(Pane { buffer, view, version });
+        self.focused_side = PaneSide::Right;
+        true
+    }
+
+    /// Goes back to a single pane, the focused one.
+    pub fn unsplit(&mut self) {
+        if let Some(pane) = self.unfocused_pane.take() {
+            if let Some(editor) = self.get_buffer_mut(pane.buffer) {
+                editor.untrack_view();
+            }
+        }
+        self.focused_side = PaneSide::Left;
+    }
+
+    /// Moves the focus to the pane on `side`, making its buffer active at
+    /// the place the pane showed. Returns false if that pane has the focus
+    /// already or the editor area isn't split.
+    pub fn focus_pane(&mut self, side: PaneSide) -> bool {
+        if side == self.focused_side {
+            return false;
+        }
+        let Some(mut pane) = self.unfocused_pane.take() else {
+            return false;
+        };
+        if let Some(view) = self.get_buffer_mut(pane.buffer).and_then(Editor::untrack_view) {
+            pane.view = view;
+        }
+        if let Some((buffer, editor)) = self.active_buffer.zip(self.active_editor_mut()) {
+            let view = editor.view_state();
+            editor.track_view(&view);
+            let version = editor.content_version();
+            self.unfocused_pane = Some(Pane { buffer, view, version });
+        }
+        self.activate(pane.buffer);
+        if let Some(editor) = self.get_buffer_mut(pane.buffer) {
+            editor.apply_view_state(&pane.view);
+        }
+        self.focused_side = side;
+        true
+    }
+
+    /// Returns the open files in tab order with their views, and which one
+    /// is active. Untitled buffers are left out.
+    pub fn session(&self) -> Session {
+        let mut session = Session::default();
+        for &id in &self.tab_order {
+            let Some(editor) = self.get_buffer(id) else {
+                continue;
+            };
+            let Some(path) = editor.file_path() else {
+                continue;
+            };
+            if self.active_buffer == Some(id) {
+                session.active = Some(session.tabs.len());
+            }
+            session.tabs.push(SessionTab {
+                path: path.to_path_buf(),
+                view: editor.view_state(),
+            });
+        }
+        session
+    }
+
+    /// Writes the open files to the JSON file `path`, as
+    /// [`Workspace::session`] lists them.
+    pub fn save_session(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(&self.session()).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+
+    /// Reopens the files of a session written by
+    /// [`Workspace::save_session`], with their views and the active tab.
+    /// Files that can't be opened anymore are skipped. An untouched untitled
+    /// buffer, like the one the editor starts with, makes way for them.
+    /// Returns the number of files opened.
+    pub fn restore_session(&mut self, path: &Path) -> io::Result<usize> {
+        let json = fs::read_to_string(path)?;
+        let session: Session = serde_json::from_str(&json).map_err(io::Error::other)?;
+
+        let blank = match self.tab_order[..] {
+            [id] => self
+                .get_buffer(id)
+                .filter(|editor| editor.file_path().is_none() && !editor.is_modified() && editor.buffer().is_empty())
+                .map(|_| id),
+            _ => None,
+        };
+        let opened: Vec<Option<BufferId>> = session
+            .tabs
+            .iter()
+            .map(|tab| {
+                let id = self.open_file(&tab.path).ok()?;
+                self.get_buffer_mut(id)?.apply_view_state(&tab.view);
+                Some(id)
+            })
+            .collect();
+        let count = opened.iter().flatten().count();
+        if count == 0 {
+            return Ok(0);
+        }
+
+        if let Some(blank) = blank {
+            self.close_buffer(blank);
+        }
+        if let Some(id) = session.active.and_then(|index| opened.get(index).copied().flatten()) {
+            self.activate(id);
+        }
+        Ok(count)
+    }
+
     /// Sets the active buffer by ID. Alias for set_active_buffer.
     pub fn set_active(&mut self, id: BufferId) -> bool {
         self.set_active_buffer(id)
@@ -377,6 +935,7 @@ impl Workspace {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::syntax::Language;
 
     #[test]
     fn test_new_workspace() {
@@ -446,6 +1005,52 @@ mod tests {
         assert_eq!(ws.active_buffer_id(), Some(id2));
     }
 
+    #[test]
+    fn test_close_others() {
+        let mut ws = Workspace::new();
+        let a = ws.new_buffer();
+        let b = ws.new_buffer();
+        let c = ws.new_buffer();
+        ws.get_buffer_mut(c).unwrap().insert_text("unsaved");
+
+        // The kept buffer stays even when it is modified; modified others
+        // are left for the caller to confirm
+        ws.get_buffer_mut(b).unwrap().insert_text("also unsaved");
+        assert_eq!(ws.close_others(b), vec![c]);
+        assert_eq!(ws.tabs().iter().map(|t| t.id).collect::<Vec<_>>(), vec![b, c]);
+        assert_eq!(ws.active_buffer_id(), Some(b));
+        assert!(ws.get_buffer(a).is_none());
+
+        // Once the caller closes those, only the kept buffer is left
+        ws.close_buffer(c);
+        assert_eq!(ws.tab_count(), 1);
+        assert_eq!(ws.active_buffer_id(), Some(b));
+    }
+
+    #[test]
+    fn test_close_saved() {
+        let mut ws = Workspace::new();
+        let a = ws.new_buffer();
+        let b = ws.new_buffer();
+        let c = ws.new_buffer();
+        let d = ws.new_buffer();
+        ws.get_buffer_mut(b).unwrap().insert_text("unsaved");
+        ws.get_buffer_mut(d).unwrap().insert_text("unsaved");
+        ws.set_active(a);
+
+        assert_eq!(ws.close_saved(), vec![b, d]);
+        assert_eq!(ws.tabs().iter().map(|t| t.id).collect::<Vec<_>>(), vec![b, d]);
+        assert!(ws.tabs().iter().all(|t| t.is_modified));
+        assert!(ws.get_buffer(c).is_none());
+        // The active buffer was closed, so another one is active
+        assert!(ws.active_buffer_id().is_some());
+
+        let e = ws.new_buffer();
+        assert_eq!(ws.close_saved(), vec![b, d]);
+        assert!(ws.get_buffer(e).is_none());
+        assert_eq!(ws.tab_count(), 2);
+    }
+
     #[test]
     fn test_tabs_info() {
         let mut ws = Workspace::new();
@@ -457,4 +1062,366 @@ mod tests {
         assert_eq!(tabs[0].name, "Untitled");
         assert_eq!(tabs[1].name, "Untitled");
     }
+
+    #[test]
+    fn test_mru_order() {
+        let mut ws = Workspace::new();
+        let id1 = ws.new_buffer();
+        let id2 = ws.new_buffer();
+        let id3 = ws.new_buffer();
+
+        // Never-activated buffers trail the active one in open order
+        assert_eq!(ws.mru_order(), &[id1, id2, id3]);
+
+        ws.set_active(id3);
+        assert_eq!(ws.mru_order(), &[id3, id1, id2]);
+
+        ws.set_active(id2);
+        assert_eq!(ws.mru_order(), &[id2, id3, id1]);
+
+        // Re-activating the current buffer is a no-op
+        ws.set_active(id2);
+        assert_eq!(ws.mru_order(), &[id2, id3, id1]);
+
+        ws.next_tab(); // id2 -> id3
+        assert_eq!(ws.mru_order(), &[id3, id2, id1]);
+    }
+
+    #[test]
+    fn test_mru_close_buffer() {
+        let mut ws = Workspace::new();
+        let id1 = ws.new_buffer();
+        let id2 = ws.new_buffer();
+        let id3 = ws.new_buffer();
+
+        ws.set_active(id3);
+        ws.set_active(id2);
+        ws.close_buffer(id3);
+        assert_eq!(ws.mru_order(), &[id2, id1]);
+
+        // Closing the active buffer activates the first tab and bumps it
+        ws.close_buffer(id2);
+        assert_eq!(ws.active_buffer_id(), Some(id1));
+        assert_eq!(ws.mru_order(), &[id1]);
+
+        ws.close_buffer(id1);
+        assert!(ws.mru_order().is_empty());
+        assert!(ws.active_buffer_id().is_none());
+    }
+
+    #[test]
+    fn test_finish_save_checks_version() {
+        let path = std::env::temp_dir().join(format!("cp_editor_ws_save_{}.txt", std::process::id()));
+        std::fs::write(&path, "hello").unwrap();
+        let mut ws = Workspace::new();
+        let id = ws.open_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let finished = |job: &SaveJob, result: io::Result<()>| SaveResult {
+            buffer_id: job.buffer_id,
+            path: job.path.clone(),
+            version: job.version,
+            result,
+        };
+
+        // Snapshot matches the buffer: saved
+        ws.get_buffer_mut(id).unwrap().insert_text("!");
+        let job = ws.save_job(id).unwrap();
+        assert_eq!(job.content.to_string(), "!hello");
+        assert!(matches!(ws.finish_save(finished(&job, Ok(()))), SaveOutcome::Saved));
+        assert!(!ws.get_buffer(id).unwrap().is_modified());
+
+        // Edited while the write ran: still modified
+        ws.get_buffer_mut(id).unwrap().insert_text("?");
+        let job = ws.save_job(id).unwrap();
+        ws.get_buffer_mut(id).unwrap().insert_text("?");
+        assert!(matches!(ws.finish_save(finished(&job, Ok(()))), SaveOutcome::SavedOlderVersion));
+        assert!(ws.get_buffer(id).unwrap().is_modified());
+
+        // Failed write: still modified
+        let job = ws.save_job(id).unwrap();
+        let error = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        assert!(matches!(ws.finish_save(finished(&job, Err(error))), SaveOutcome::Failed(_)));
+        assert!(ws.get_buffer(id).unwrap().is_modified());
+
+        // Buffer closed before the write finished
+        ws.close_buffer(id);
+        assert!(matches!(ws.finish_save(finished(&job, Ok(()))), SaveOutcome::BufferClosed));
+
+        // Every finished write is reported, the failed one aside
+        let saved = ws
+            .take_events()
+            .into_iter()
+            .filter(|event| matches!(event, WorkspaceEvent::BufferSaved { .. }))
+            .count();
+        assert_eq!(saved, 3);
+    }
+
+    #[test]
+    fn test_save_job_requires_path() {
+        let mut ws = Workspace::new();
+        let id = ws.new_buffer();
+        assert_eq!(ws.save_job(id).unwrap_err().kind(), io::ErrorKind::Other);
+        assert_eq!(ws.save_job(99).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_associations_set_language_and_icon() {
+        let dir = std::env::temp_dir().join(format!("cp_editor_assoc_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dockerfile = dir.join("Dockerfile");
+        let rc = dir.join("app.rc");
+        std::fs::write(&dockerfile, "FROM rust").unwrap();
+        std::fs::write(&rc, "{}").unwrap();
+
+        let mut ws = Workspace::new();
+        let docker_id = ws.open_file(&dockerfile).unwrap();
+        let rc_id = ws.open_file(&rc).unwrap();
+        let icons: Vec<String> = ws.tabs().into_iter().map(|tab| tab.icon).collect();
+        assert_eq!(icons, ["dkr", "txt"]);
+
+        // User associations apply to already open files too
+        let mut associations = FileAssociations::default();
+        associations.add("*.rc", Language::Json, None);
+        ws.set_associations(associations);
+        assert_eq!(ws.get_buffer(rc_id).unwrap().language(), Language::Json);
+        assert_eq!(ws.tabs()[1].icon, "{}");
+
+        // And to files saved under a new name
+        ws.set_active(docker_id);
+        let renamed = dir.join("settings.rc");
+        ws.save_active_as(&renamed).unwrap();
+        assert_eq!(ws.get_buffer(docker_id).unwrap().language(), Language::Json);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_recent_files() {
+        let dir = std::env::temp_dir().join(format!("cp_editor_recent_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files: Vec<PathBuf> = ["a.txt", "b.txt", "c.txt"].iter().map(|name| dir.join(name)).collect();
+        for file in &files {
+            std::fs::write(file, "").unwrap();
+        }
+        let store = dir.join("config").join("recent.json");
+
+        // Opening moves a file to the front without duplicating it
+        let mut ws = Workspace::new();
+        ws.set_recent_files(RecentFiles::load(store.clone(), 2));
+        ws.open_file(&files[0]).unwrap();
+        ws.open_file(&files[1]).unwrap();
+        ws.close_buffer(0);
+        ws.open_file(&files[0]).unwrap();
+        assert_eq!(ws.recent_files(), [files[0].clone(), files[1].clone()]);
+        // Full: the oldest goes
+        ws.open_file(&files[2]).unwrap();
+        assert_eq!(ws.recent_files(), [files[2].clone(), files[0].clone()]);
+
+        // Kept across restarts
+        let recent = RecentFiles::load(store.clone(), 10);
+        assert_eq!(recent.paths(), ws.recent_files());
+
+        // Deleted files drop out when listed
+        std::fs::remove_file(&files[2]).unwrap();
+        assert_eq!(ws.existing_recent_files(), [files[0].clone()]);
+        assert_eq!(RecentFiles::load(store, 10).paths(), [files[0].clone()]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_split_panes() {
+        let mut ws = Workspace::new();
+        assert!(!ws.split());
+        let a = ws.new_buffer();
+        ws.active_editor_mut().unwrap().insert_text("one\ntwo\nthree");
+
+        // Both panes start on the same buffer and place
+        assert!(ws.split());
+        assert!(!ws.split());
+        assert_eq!(ws.focused_side(), PaneSide::Right);
+        assert_eq!(ws.unfocused_pane().unwrap().buffer, a);
+
+        // Each keeps its own cursor
+        ws.active_editor_mut().unwrap().set_cursor_position(2, 1, false);
+        assert!(ws.focus_pane(PaneSide::Left));
+        assert!(!ws.focus_pane(PaneSide::Left));
+        assert_eq!(ws.active_editor().unwrap().view_state().cursor, (2, 5));
+        ws.active_editor_mut().unwrap().set_cursor_position(0, 2, false);
+        ws.focus_pane(PaneSide::Right);
+        assert_eq!(ws.active_editor().unwrap().view_state().cursor, (2, 1));
+        ws.focus_pane(PaneSide::Left);
+        assert_eq!(ws.active_editor().unwrap().view_state().cursor, (0, 2));
+
+        // Another buffer in the focused pane leaves the other alone
+        let b = ws.new_buffer();
+        ws.set_active_buffer(b);
+        ws.focus_pane(PaneSide::Right);
+        assert_eq!(ws.active_buffer_id(), Some(a));
+        assert_eq!(ws.unfocused_pane().unwrap().buffer, b);
+
+        // Closing the unfocused pane's buffer closes the pane
+        ws.close_buffer(b);
+        assert!(!ws.is_split());
+        assert_eq!(ws.focused_side(), PaneSide::Left);
+        assert_eq!(ws.active_buffer_id(), Some(a));
+    }
+
+    #[test]
+    fn test_edits_in_one_pane_move_the_other() {
+        let mut ws = Workspace::new();
+        ws.new_buffer();
+        ws.active_editor_mut().unwrap().insert_text("one\ntwo\nthree\nfour");
+        ws.active_editor_mut().unwrap().set_cursor_position(2, 3, false);
+        assert!(ws.split());
+
+        let view = |ws: &mut Workspace| {
+            ws.update_unfocused_pane();
+            ws.unfocused_pane().unwrap().view.clone()
+        };
+
+        // A line added above in the focused pane pushes the other's cursor
+        // down, and its view stays on the same text
+        let editor = ws.active_editor_mut().unwrap();
+        editor.set_cursor_position(0, 0, false);
+        editor.insert_text("zero\n");
+        assert_eq!(ws.unfocused_pane().unwrap().view.cursor, (2, 3));
+        let moved = view(&mut ws);
+        assert_eq!((moved.cursor, moved.scroll_offset), ((3, 3), 1));
+
+        // Its text deleted, the cursor lands where the deletion was
+        ws.active_editor_mut().unwrap().replace_range(2, 0, 4, 0, "");
+        assert_eq!(ws.active_editor().unwrap().buffer().to_string(), "zero\none\nfour");
+        assert_eq!(view(&mut ws).cursor, (2, 0));
+
+        // Moving the focus there goes to that place, and edits made there
+        // move the pane the focus left
+        ws.focus_pane(PaneSide::Left);
+        assert_eq!(ws.active_editor().unwrap().view_state().cursor, (2, 0));
+        assert_eq!(view(&mut ws).cursor, (1, 0));
+        let editor = ws.active_editor_mut().unwrap();
+        editor.set_cursor_position(0, 0, false);
+        editor.insert_text("\n");
+        assert_eq!(view(&mut ws).cursor, (2, 0));
+
+        // Undo moves it back
+        ws.active_editor_mut().unwrap().undo();
+        assert_eq!(view(&mut ws).cursor, (1, 0));
+        ws.unsplit();
+        assert!(ws.active_editor().unwrap().tracked_view().is_none());
+    }
+
+    #[test]
+    fn test_session_round_trip() {
+        let dir = std::env::temp_dir().join(format!("cp_editor_session_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+        std::fs::write(&a, "one\ntwo\nthree").unwrap();
+        std::fs::write(&b, "four").unwrap();
+        let store = dir.join("config").join("session.json");
+
+        let mut ws = Workspace::new();
+        ws.new_buffer();
+        ws.open_file(&a).unwrap();
+        ws.get_buffer_mut(1).unwrap().set_cursor_position(2, 3, false);
+        ws.open_file(&b).unwrap();
+        ws.switch_to_tab(1);
+        ws.save_session(&store).unwrap();
+        // The untitled buffer isn't kept
+        assert_eq!(ws.session().tabs.len(), 2);
+        assert_eq!(ws.session().active, Some(0));
+
+        // A fresh workspace's blank buffer gives way to the session
+        let mut restored = Workspace::new();
+        restored.new_buffer();
+        assert_eq!(restored.restore_session(&store).unwrap(), 2);
+        assert_eq!(restored.tab_count(), 2);
+        let editor = restored.active_editor().unwrap();
+        assert_eq!(editor.file_path(), Some(a.as_path()));
+        assert_eq!(editor.view_state().cursor, (2, 3));
+
+        // Missing files are skipped
+        std::fs::remove_file(&a).unwrap();
+        let mut restored = Workspace::new();
+        assert_eq!(restored.restore_session(&store).unwrap(), 1);
+        assert_eq!(restored.active_editor().unwrap().file_path(), Some(b.as_path()));
+        assert!(Workspace::new().restore_session(&dir.join("missing.json")).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_buffers_keep_their_own_settings() {
+        let dir = std::env::temp_dir().join(format!("cp_editor_settings_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let notes = dir.join("notes.txt");
+        let config = dir.join("config.rc");
+        std::fs::write(&notes, "notes").unwrap();
+        std::fs::write(&config, "{}").unwrap();
+
+        // New and opened buffers start from the defaults
+        let mut ws = Workspace::new();
+        ws.set_default_settings(EditorSettings {
+            word_wrap: true,
+            ..EditorSettings::default()
+        });
+        let untitled = ws.new_buffer();
+        let notes_id = ws.open_file(&notes).unwrap();
+        assert!(ws.get_buffer(untitled).unwrap().word_wrap());
+        assert!(ws.get_buffer(notes_id).unwrap().word_wrap());
+
+        // Two buffers hold different word wrap at the same time
+        ws.get_buffer_mut(notes_id).unwrap().toggle_word_wrap();
+        assert!(ws.get_buffer(untitled).unwrap().word_wrap());
+        assert!(!ws.get_buffer(notes_id).unwrap().word_wrap());
+
+        // Changing the defaults leaves open buffers alone
+        ws.set_default_settings(EditorSettings::default());
+        assert!(ws.get_buffer(untitled).unwrap().word_wrap());
+
+        // An overridden language wins over the associations
+        let config_id = ws.open_file(&config).unwrap();
+        let editor = ws.get_buffer_mut(config_id).unwrap();
+        editor.set_settings(EditorSettings {
+            language: Some(Language::Rust),
+            ..editor.settings()
+        });
+        let mut associations = FileAssociations::default();
+        associations.add("*.rc", Language::Json, None);
+        ws.set_associations(associations);
+        assert_eq!(ws.get_buffer(config_id).unwrap().language(), Language::Rust);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_lifecycle_events() {
+        let dir = std::env::temp_dir();
+        let a = dir.join(format!("cp_editor_ws_events_a_{}.txt", std::process::id()));
+        let b = dir.join(format!("cp_editor_ws_events_b_{}.txt", std::process::id()));
+        std::fs::write(&a, "text").unwrap();
+
+        let mut ws = Workspace::new();
+        let untitled = ws.new_buffer();
+        let id = ws.open_file(&a).unwrap();
+        // Opening an already open file is not a new open
+        ws.open_file(&a).unwrap();
+        ws.save_active_as(&b).unwrap();
+        ws.close_buffer(untitled);
+        ws.close_buffer(id);
+        let _ = std::fs::remove_file(&a);
+        let _ = std::fs::remove_file(&b);
+
+        assert_eq!(
+            ws.take_events(),
+            vec![
+                WorkspaceEvent::BufferOpened { id, path: a.clone() },
+                WorkspaceEvent::BufferRetargeted {
+                    id,
+                    old_path: a,
+                    new_path: b.clone(),
+                },
+                WorkspaceEvent::BufferSaved { id, path: b.clone() },
+                WorkspaceEvent::BufferClosed { id, path: b },
+            ]
+        );
+        assert!(ws.take_events().is_empty());
+    }
 }
@@ -1,6 +1,9 @@
 //! CP Editor - GPU-accelerated text editor.
 //!
-//! Usage: cp-editor [FILE]
+//! Usage: cp-editor [--wait] [FILE]
+//!
+//! With `--wait` the process exits once FILE's buffer is closed, with status
+//! 0 if it was saved and 1 otherwise, so it can be used as git's editor.
 
 use cp_editor_ui::{run, EditorApp};
 use std::env;
@@ -17,11 +20,15 @@ fn main() {
     log::info!("Starting CP Editor");
 
     // Parse command line arguments
-    let args: Vec<String> = env::args().collect();
-    let file_path = args.get(1).map(PathBuf::from);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let wait = args.iter().any(|arg| arg == "--wait");
+    let file_path = args.iter().find(|arg| !arg.starts_with("--")).map(PathBuf::from);
 
     // Create the application
     let mut app = EditorApp::new(16.0);
+    app.load_settings();
+    app.load_recent_files();
+    app.load_trusted_workspaces();
 
     // Open file if provided (replaces the default empty buffer)
     if let Some(ref path) = file_path {
@@ -30,6 +37,13 @@ fn main() {
             log::error!("Failed to open file '{:?}': {}", path, e);
         }
         app.perf_metrics.startup.record_file_open();
+    } else if !wait {
+        // Without a file, pick up where the last session left off
+        let restored = app.restore_session();
+        log::info!("Restored {} file(s) from the last session", restored);
+    }
+    if wait && !app.wait_for_active_buffer() {
+        log::warn!("--wait needs a file to wait on; ignoring it");
     }
 
     // Log startup time
@@ -37,7 +51,8 @@ fn main() {
     log::info!("Startup complete in {:.1}ms", startup_time.as_secs_f64() * 1000.0);
 
     // Run the application
-    run(app);
+    let code = run(app);
 
     log::info!("CP Editor exited");
+    std::process::exit(code);
 }
@@ -0,0 +1,431 @@
+//! What a language server supports.
+//!
+//! The server lists its capabilities in its `initialize` response. They are
+//! kept as [`Capabilities`], which answers the questions the UI asks before
+//! offering a feature or sending a request: is the request supported, which
+//! typed characters trigger completion, and how positions are counted.
+
+use lsp_types::{CodeActionProviderCapability, HoverProviderCapability, OneOf, PositionEncodingKind, ServerCapabilities};
+use serde_json::Value;
+
+/// Unit a server counts the columns of positions in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    /// Bytes of UTF-8.
+    Utf8,
+    /// UTF-16 code units, which servers use unless they say otherwise.
+    #[default]
+    Utf16,
+    /// Characters, as the editor counts columns.
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Returns the encoding named by `kind`, or `None` if it is unknown.
+    pub fn from_kind(kind: &PositionEncodingKind) -> Option<Self> {
+        match kind.as_str() {
+            "utf-8" => Some(Self::Utf8),
+            "utf-16" => Some(Self::Utf16),
+            "utf-32" => Some(Self::Utf32),
+            _ => None,
+        }
+    }
+
+    /// Returns the encoding's name in the protocol.
+    pub fn kind(&self) -> PositionEncodingKind {
+        match self {
+            Self::Utf8 => PositionEncodingKind::UTF8,
+            Self::Utf16 => PositionEncodingKind::UTF16,
+            Self::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    /// Converts character column `col` of `line` to this encoding. Columns
+    /// past the end of the line count as one unit per character.
+    pub fn encode_col(&self, line: &str, col: usize) -> u32 {
+        let mut units = 0;
+        let mut chars = line.chars();
+        for _ in 0..col {
+            units += match (self, chars.next()) {
+                (Self::Utf8, Some(ch)) => ch.len_utf8(),
+                (Self::Utf16, Some(ch)) => ch.len_utf16(),
+                _ => 1,
+            };
+        }
+        units as u32
+    }
+
+    /// Converts column `units` of `line` in this encoding to a character
+    /// column. A column inside a character is that character's.
+    pub fn decode_col(&self, line: &str, units: u32) -> usize {
+        let units = units as usize;
+        let mut count = 0;
+        let mut col = 0;
+        for ch in line.chars() {
+            count += match self {
+                Self::Utf8 => ch.len_utf8(),
+                Self::Utf16 => ch.len_utf16(),
+                Self::Utf32 => 1,
+            };
+            if count > units {
+                return col;
+            }
+            col += 1;
+        }
+        col + (units - count)
+    }
+}
+
+/// The capabilities of a language server the editor uses.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Capabilities {
+    /// `textDocument/hover`.
+    pub hover: bool,
+    /// `textDocument/completion`.
+    pub completion: bool,
+    /// Characters that request completion when typed.
+    pub completion_trigger_characters: Vec<String>,
+    /// `textDocument/signatureHelp`.
+    pub signature_help: bool,
+    /// Characters that request signature help when typed.
+    pub signature_help_trigger_characters: Vec<String>,
+    /// `textDocument/definition`.
+    pub definition: bool,
+    /// `textDocument/references`.
+    pub references: bool,
+    /// `textDocument/rename`.
+    pub rename: bool,
+    /// `textDocument/formatting`.
+    pub formatting: bool,
+    /// `textDocument/rangeFormatting`.
+    pub range_formatting: bool,
+    /// `textDocument/documentSymbol`.
+    pub document_symbols: bool,
+    /// `textDocument/codeAction`.
+    pub code_action: bool,
+    /// `textDocument/diagnostic`; servers without it push diagnostics.
+    pub pull_diagnostics: bool,
+    /// Unit of position columns.
+    pub position_encoding: PositionEncoding,
+}
+
+impl Capabilities {
+    /// Keeps what the editor uses of a server's capabilities.
+    pub fn new(caps: &ServerCapabilities) -> Self {
+        fn one_of<T>(provider: &Option<OneOf<bool, T>>) -> bool {
+            matches!(provider, Some(OneOf::Left(true) | OneOf::Right(_)))
+        }
+
+        let completion = caps.completion_provider.as_ref();
+        let signature_help = caps.signature_help_provider.as_ref();
+        Self {
+            hover: matches!(
+                caps.hover_provider,
+                Some(HoverProviderCapability::Simple(true) | HoverProviderCapability::Options(_))
+            ),
+            completion: completion.is_some(),
+            completion_trigger_characters: completion
+                .and_then(|c| c.trigger_characters.clone())
+                .unwrap_or_default(),
+            signature_help: signature_help.is_some(),
+            signature_help_trigger_characters: signature_help
+                .and_then(|s| s.trigger_characters.clone())
+                .unwrap_or_default(),
+            definition: one_of(&caps.definition_provider),
+            references: one_of(&caps.references_provider),
+            rename: one_of(&caps.rename_provider),
+            formatting: one_of(&caps.document_formatting_provider),
+            range_formatting: one_of(&caps.document_range_formatting_provider),
+            document_symbols: one_of(&caps.document_symbol_provider),
+            code_action: matches!(
+                caps.code_action_provider,
+                Some(CodeActionProviderCapability::Simple(true) | CodeActionProviderCapability::Options(_))
+            ),
+            pull_diagnostics: caps.diagnostic_provider.is_some(),
+            position_encoding: caps
+                .position_encoding
+                .as_ref()
+                .and_then(PositionEncoding::from_kind)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Parses the capabilities from the result of an `initialize` request.
+    /// Capabilities that don't parse count as none.
+    pub fn from_initialize_result(result: Option<Value>) -> Self {
+        let caps = result
+            .and_then(|mut v| v.get_mut("capabilities").map(Value::take))
+            .and_then(|v| serde_json::from_value::<ServerCapabilities>(v).ok())
+            .unwrap_or_default();
+        Self::new(&caps)
+    }
+
+    /// Returns true if typing `ch` should request completion.
+    pub fn is_completion_trigger(&self, ch: char) -> bool {
+        is_trigger(&self.completion_trigger_characters, ch)
+    }
+
+    /// Returns true if typing `ch` should request signature help.
+    pub fn is_signature_help_trigger(&self, ch: char) -> bool {
+        is_trigger(&self.signature_help_trigger_characters, ch)
+    }
+
+    /// Returns a summary of the supported features, for the log.
+    pub fn summary(&self) -> String {
+        let features = [
+            (self.hover, "hover"),
+            (self.completion, "completion"),
+            (self.signature_help, "signature help"),
+            (self.definition, "definition"),
+            (self.references, "references"),
+            (self.rename, "rename"),
+            (self.formatting, "formatting"),
+            (self.document_symbols, "symbols"),
+            (self.pull_diagnostics, "pull diagnostics"),
+        ];
+        let mut summary = features
+            .iter()
+            .filter(|(supported, _)| *supported)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>()
+            .join(", ");
+        if self.position_encoding != PositionEncoding::Utf16 {
+            summary.push_str(&format!(" ({})", self.position_encoding.kind().as_str()));
+        }
+        summary
+    }
+}
+
+fn is_trigger(characters: &[String], ch: char) -> bool {
+    characters.iter().any(|c| c.chars().eq(std::iter::once(ch)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Trimmed `initialize` result of rust-analyzer 2024-12.
+    fn rust_analyzer() -> Value {
+        json!({
+            "capabilities": {
+                "positionEncoding": "utf-32",
+                "textDocumentSync": {
+                    "openClose": true,
+                    "change": 2,
+                    "save": {}
+                },
+                "selectionRangeProvider": true,
+                "hoverProvider": true,
+                "completionProvider": {
+                    "resolveProvider": true,
+                    "triggerCharacters": [":", ".", "'", "("],
+                    "completionItem": {"labelDetailsSupport": false}
+                },
+                "signatureHelpProvider": {"triggerCharacters": ["(", ",", "<"]},
+                "definitionProvider": true,
+                "typeDefinitionProvider": true,
+                "implementationProvider": true,
+                "referencesProvider": true,
+                "documentHighlightProvider": true,
+                "documentSymbolProvider": true,
+                "workspaceSymbolProvider": true,
+                "codeActionProvider": {
+                    "codeActionKinds": ["", "quickfix", "refactor", "refactor.extract", "refactor.inline", "refactor.rewrite"],
+                    "resolveProvider": true
+                },
+                "codeLensProvider": {"resolveProvider": true},
+                "documentFormattingProvider": true,
+                "documentRangeFormattingProvider": false,
+                "documentOnTypeFormattingProvider": {
+                    "firstTriggerCharacter": "=",
+                    "moreTriggerCharacter": [".", ">", "{", "("]
+                },
+                "renameProvider": {"prepareProvider": true},
+                "foldingRangeProvider": true,
+                "declarationProvider": true,
+                "workspace": {
+                    "workspaceFolders": {"supported": true, "changeNotifications": true},
+                    "fileOperations": {
+                        "willRename": {
+                            "filters": [{"scheme": "file", "pattern": {"glob": "**/*.rs", "matches": "file"}}]
+                        }
+                    }
+                },
+                "callHierarchyProvider": true,
+                "semanticTokensProvider": {
+                    "legend": {"tokenTypes": ["comment", "keyword"], "tokenModifiers": ["documentation"]},
+                    "range": true,
+                    "full": {"delta": true}
+                },
+                "inlayHintProvider": {"resolveProvider": true},
+                "experimental": {"externalDocs": true, "hoverRange": true, "joinLines": true}
+            },
+            "serverInfo": {"name": "rust-analyzer", "version": "1.83.0 (90b35a6 2024-11-26)"}
+        })
+    }
+
+    /// Trimmed `initialize` result of pyright 1.1.
+    fn pyright() -> Value {
+        json!({
+            "capabilities": {
+                "textDocumentSync": 2,
+                "definitionProvider": {"workDoneProgress": true},
+                "declarationProvider": {"workDoneProgress": true},
+                "typeDefinitionProvider": {"workDoneProgress": true},
+                "referencesProvider": {"workDoneProgress": true},
+                "documentSymbolProvider": {"workDoneProgress": true},
+                "workspaceSymbolProvider": {"workDoneProgress": true},
+                "hoverProvider": {"workDoneProgress": true},
+                "documentHighlightProvider": {"workDoneProgress": true},
+                "renameProvider": {"prepareProvider": true, "workDoneProgress": true},
+                "completionProvider": {
+                    "triggerCharacters": [".", "[", "\"", "'"],
+                    "resolveProvider": true,
+                    "workDoneProgress": true,
+                    "completionItem": {"labelDetailsSupport": true}
+                },
+                "signatureHelpProvider": {
+                    "triggerCharacters": ["(", ",", ")"],
+                    "workDoneProgress": true
+                },
+                "codeActionProvider": {
+                    "codeActionKinds": ["quickfix", "source.organizeImports"],
+                    "workDoneProgress": true
+                },
+                "executeCommandProvider": {
+                    "commands": [],
+                    "workDoneProgress": true
+                },
+                "callHierarchyProvider": true,
+                "workspace": {
+                    "workspaceFolders": {"supported": true, "changeNotifications": true}
+                }
+            }
+        })
+    }
+
+    /// Trimmed `initialize` result of clangd 17.
+    fn clangd() -> Value {
+        json!({
+            "capabilities": {
+                "astProvider": true,
+                "callHierarchyProvider": true,
+                "clangdInlayHintsProvider": true,
+                "codeActionProvider": {"codeActionKinds": ["quickfix", "refactor", "info"]},
+                "compilationDatabase": {"automaticReload": true},
+                "completionProvider": {
+                    "allCommitCharacters": [" ", "\t", "(", ")", "[", "]", "{", "}", "<", ">", ":", ";", ",", "+", "-", "/", "*", "%", "^", "&", "#", "?", ".", "=", "\"", "'", "|"],
+                    "resolveProvider": false,
+                    "triggerCharacters": [".", "<", ">", ":", "\"", "/", "*"]
+                },
+                "declarationProvider": true,
+                "definitionProvider": true,
+                "documentFormattingProvider": true,
+                "documentHighlightProvider": true,
+                "documentLinkProvider": {"resolveProvider": false},
+                "documentOnTypeFormattingProvider": {
+                    "firstTriggerCharacter": "\n",
+                    "moreTriggerCharacter": []
+                },
+                "documentRangeFormattingProvider": true,
+                "documentSymbolProvider": true,
+                "executeCommandProvider": {
+                    "commands": ["clangd.applyFix", "clangd.applyTweak"]
+                },
+                "foldingRangeProvider": true,
+                "hoverProvider": true,
+                "implementationProvider": true,
+                "inlayHintProvider": true,
+                "memoryUsageProvider": true,
+                "positionEncoding": "utf-8",
+                "referencesProvider": true,
+                "renameProvider": {"prepareProvider": true},
+                "selectionRangeProvider": true,
+                "semanticTokensProvider": {
+                    "full": {"delta": true},
+                    "legend": {"tokenModifiers": ["declaration"], "tokenTypes": ["variable"]},
+                    "range": false
+                },
+                "signatureHelpProvider": {"triggerCharacters": ["(", ")", "{", "}", "<", ">", ","]},
+                "standardTypeHierarchyProvider": true,
+                "textDocumentSync": {
+                    "change": 2,
+                    "openClose": true,
+                    "save": true
+                },
+                "typeDefinitionProvider": true,
+                "typeHierarchyProvider": true,
+                "workspaceSymbolProvider": true
+            },
+            "serverInfo": {"name": "clangd", "version": "clangd version 17.0.6"}
+        })
+    }
+
+    #[test]
+    fn test_rust_analyzer_capabilities() {
+        let caps = Capabilities::from_initialize_result(Some(rust_analyzer()));
+        assert!(caps.hover && caps.completion && caps.signature_help);
+        assert!(caps.definition && caps.references && caps.rename && caps.document_symbols);
+        assert!(caps.formatting && caps.code_action);
+        assert!(!caps.range_formatting);
+        assert!(!caps.pull_diagnostics);
+        assert_eq!(caps.position_encoding, PositionEncoding::Utf32);
+        assert!(caps.is_completion_trigger('.'));
+        assert!(caps.is_completion_trigger(':'));
+        assert!(!caps.is_completion_trigger('a'));
+        assert!(caps.is_signature_help_trigger('<'));
+    }
+
+    #[test]
+    fn test_pyright_capabilities() {
+        let caps = Capabilities::from_initialize_result(Some(pyright()));
+        // Options objects count as support
+        assert!(caps.hover && caps.definition && caps.references && caps.rename);
+        assert!(!caps.formatting && !caps.range_formatting);
+        // No encoding means UTF-16
+        assert_eq!(caps.position_encoding, PositionEncoding::Utf16);
+        assert!(caps.is_completion_trigger('['));
+        assert!(caps.is_completion_trigger('"'));
+        assert!(!caps.is_completion_trigger(':'));
+        assert!(caps.is_signature_help_trigger(')'));
+        assert_eq!(caps.summary(), "hover, completion, signature help, definition, references, rename, symbols");
+    }
+
+    #[test]
+    fn test_clangd_capabilities() {
+        let caps = Capabilities::from_initialize_result(Some(clangd()));
+        assert!(caps.formatting && caps.range_formatting);
+        assert_eq!(caps.position_encoding, PositionEncoding::Utf8);
+        assert!(caps.is_completion_trigger('>'));
+        assert!(caps.is_signature_help_trigger('{'));
+        assert!(caps.summary().ends_with("(utf-8)"));
+    }
+
+    #[test]
+    fn test_missing_capabilities() {
+        assert_eq!(Capabilities::from_initialize_result(None), Capabilities::default());
+        let caps = Capabilities::from_initialize_result(Some(json!({
+            "capabilities": {"hoverProvider": false, "renameProvider": false}
+        })));
+        assert!(!caps.hover && !caps.rename && !caps.code_action);
+        assert_eq!(caps.summary(), "");
+    }
+
+    #[test]
+    fn test_position_encoding_columns() {
+        // 'é' is 2 bytes, '😀' 4 bytes and 2 UTF-16 units
+        let line = "aé😀b";
+        assert_eq!(PositionEncoding::Utf8.encode_col(line, 3), 7);
+        assert_eq!(PositionEncoding::Utf16.encode_col(line, 3), 4);
+        assert_eq!(PositionEncoding::Utf32.encode_col(line, 3), 3);
+        for encoding in [PositionEncoding::Utf8, PositionEncoding::Utf16, PositionEncoding::Utf32] {
+            for col in 0..=6 {
+                assert_eq!(encoding.decode_col(line, encoding.encode_col(line, col)), col, "{:?}", encoding);
+            }
+        }
+        // Inside a character is that character's column
+        assert_eq!(PositionEncoding::Utf8.decode_col(line, 5), 2);
+        assert_eq!(PositionEncoding::Utf16.decode_col(line, 3), 2);
+    }
+}
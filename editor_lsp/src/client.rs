@@ -3,12 +3,16 @@
 //! The client runs on a separate tokio runtime thread and communicates
 //! with the UI via channels.
 
+use crate::capabilities::{Capabilities, PositionEncoding};
 use crate::messages::{
     DocumentSymbol, LogLevel, LspNotification, LspRequest, LspResponse, RequestId,
 };
-use crate::transport::{self, AsyncTransport, JsonRpcMessage, JsonRpcNotification, JsonRpcResponse};
+use crate::transport::{
+    self, AsyncTransport, JsonRpcMessage, JsonRpcNotification, JsonRpcRequest, JsonRpcResponse,
+};
 use crate::types::{
-    CompletionItem, Diagnostic, HoverInfo, Location, Position, TextEdit, WorkspaceEdit,
+    CodeAction, CompletionItem, Diagnostic, HoverInfo, Location, Position, SignatureHelpInfo, TextEdit,
+    WorkspaceEdit,
 };
 use crossbeam_channel::{Receiver, Sender};
 use lsp_types::*;
@@ -145,6 +149,13 @@ impl LspHandle {
         id
     }
 
+    /// Requests signature help.
+    pub fn signature_help(&self, path: PathBuf, position: Position) -> RequestId {
+        let id = self.next_id();
+        let _ = self.send(LspRequest::SignatureHelp { id, path, position });
+        id
+    }
+
     /// Requests go to definition.
     pub fn goto_definition(&self, path: PathBuf, position: Position) -> RequestId {
         let id = self.next_id();
@@ -181,6 +192,38 @@ impl LspHandle {
         id
     }
 
+    /// Requests the code actions for `range`, telling the server which
+    /// `diagnostics` overlap it so it can offer their quick fixes.
+    pub fn code_action(&self, path: PathBuf, range: crate::types::Range, diagnostics: Vec<Diagnostic>) -> RequestId {
+        let id = self.next_id();
+        let _ = self.send(LspRequest::CodeAction {
+            id,
+            path,
+            range,
+            diagnostics,
+        });
+        id
+    }
+
+    /// Requests diagnostics for a document from a server that supports
+    /// pull diagnostics.
+    pub fn pull_diagnostics(&self, path: PathBuf) -> RequestId {
+        let id = self.next_id();
+        let _ = self.send(LspRequest::PullDiagnostics { id, path });
+        id
+    }
+
+    /// Asks the server to execute a command.
+    pub fn execute_command(&self, command: String, arguments: Vec<Value>) -> RequestId {
+        let id = self.next_id();
+        let _ = self.send(LspRequest::ExecuteCommand {
+            id,
+            command,
+            arguments,
+        });
+        id
+    }
+
     /// Shuts down the LSP server.
     pub fn shutdown(&self) {
         let _ = self.send(LspRequest::Shutdown);
@@ -228,6 +271,25 @@ impl LspClient {
         })
     }
 
+    /// Creates a client with no server behind it. Requests sent through its
+    /// handle are delivered to the returned receiver, so callers can check
+    /// what would have been sent to a server.
+    pub fn detached() -> (Self, Receiver<LspRequest>) {
+        let (request_tx, request_rx) = crossbeam_channel::unbounded();
+        let (_, response_rx) = crossbeam_channel::unbounded();
+        let (_, notification_rx) = crossbeam_channel::unbounded();
+        let client = Self {
+            handle: LspHandle {
+                request_tx,
+                next_id: Arc::new(AtomicU64::new(1)),
+            },
+            response_rx,
+            notification_rx,
+            running: Arc::new(AtomicBool::new(true)),
+        };
+        (client, request_rx)
+    }
+
     /// Returns a handle for sending requests.
     pub fn handle(&self) -> LspHandle {
         self.handle.clone()
@@ -274,6 +336,11 @@ enum SendMessage {
         method: String,
         params: Option<Value>,
     },
+    /// Reply to a server-initiated request.
+    Response {
+        id: transport::RequestId,
+        result: Value,
+    },
     Shutdown,
 }
 
@@ -349,6 +416,11 @@ async fn run_client(
                         log::error!("Failed to send notification: {}", e);
                     }
                 }
+                SendMessage::Response { id, result } => {
+                    if let Err(e) = transport_write.send_response(id, result).await {
+                        log::error!("Failed to send response: {}", e);
+                    }
+                }
                 SendMessage::Shutdown => {
                     let _ = transport_write.send_request(0i64, "shutdown", None).await;
                     let _ = transport_write.send_notification("exit", None).await;
@@ -363,6 +435,7 @@ async fn run_client(
     let read_pending = pending.clone();
     let read_response_tx = response_tx.clone();
     let read_notification_tx = notification_tx.clone();
+    let read_send_tx = send_tx.clone();
     let read_task = tokio::spawn(async move {
         while read_running.load(Ordering::SeqCst) {
             match transport_read.read_message().await {
@@ -372,6 +445,7 @@ async fn run_client(
                         &read_pending,
                         &read_response_tx,
                         &read_notification_tx,
+                        &read_send_tx,
                     )
                     .await;
                 }
@@ -446,13 +520,34 @@ async fn process_request(
     match request {
         LspRequest::Initialize { id, root_path } => {
             let rpc_id = next_id.fetch_add(1, Ordering::SeqCst) as i64;
-            let root_uri = Some(path_to_uri(&root_path));
-
+            let root_uri = path_to_uri(&root_path);
+            let workspace_name = root_path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            // `root_path`/`root_uri` are deprecated in favour of workspace folders,
+            // but older servers still rely on them, so send both.
+            #[allow(deprecated)]
             let params = InitializeParams {
                 process_id: Some(std::process::id()),
                 root_path: Some(root_path.to_string_lossy().to_string()),
-                root_uri,
+                root_uri: Some(root_uri.clone()),
+                workspace_folders: Some(vec![WorkspaceFolder {
+                    uri: root_uri,
+                    name: workspace_name,
+                }]),
                 capabilities: ClientCapabilities {
+                    // Columns are characters, so UTF-32 needs no conversion
+                    general: Some(GeneralClientCapabilities {
+                        position_encodings: Some(
+                            [PositionEncoding::Utf32, PositionEncoding::Utf8, PositionEncoding::Utf16]
+                                .iter()
+                                .map(PositionEncoding::kind)
+                                .collect(),
+                        ),
+                        ..Default::default()
+                    }),
                     text_document: Some(TextDocumentClientCapabilities {
                         hover: Some(HoverClientCapabilities {
                             dynamic_registration: Some(false),
@@ -470,6 +565,17 @@ async fn process_request(
                             }),
                             ..Default::default()
                         }),
+                        signature_help: Some(SignatureHelpClientCapabilities {
+                            dynamic_registration: Some(false),
+                            signature_information: Some(SignatureInformationSettings {
+                                documentation_format: Some(vec![MarkupKind::PlainText]),
+                                parameter_information: Some(ParameterInformationSettings {
+                                    label_offset_support: Some(true),
+                                }),
+                                active_parameter_support: Some(true),
+                            }),
+                            context_support: Some(false),
+                        }),
                         definition: Some(GotoCapability {
                             dynamic_registration: Some(false),
                             link_support: Some(true),
@@ -482,10 +588,49 @@ async fn process_request(
                             prepare_support: Some(true),
                             ..Default::default()
                         }),
+                        // Without literal support servers may only send bare commands
+                        code_action: Some(CodeActionClientCapabilities {
+                            dynamic_registration: Some(false),
+                            code_action_literal_support: Some(CodeActionLiteralSupport {
+                                code_action_kind: CodeActionKindLiteralSupport {
+                                    value_set: [
+                                        CodeActionKind::EMPTY,
+                                        CodeActionKind::QUICKFIX,
+                                        CodeActionKind::REFACTOR,
+                                        CodeActionKind::REFACTOR_EXTRACT,
+                                        CodeActionKind::REFACTOR_INLINE,
+                                        CodeActionKind::REFACTOR_REWRITE,
+                                        CodeActionKind::SOURCE,
+                                        CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                                    ]
+                                    .iter()
+                                    .map(|kind| kind.as_str().to_string())
+                                    .collect(),
+                                },
+                            }),
+                            is_preferred_support: Some(true),
+                            disabled_support: Some(true),
+                            ..Default::default()
+                        }),
                         publish_diagnostics: Some(PublishDiagnosticsClientCapabilities {
                             related_information: Some(true),
                             ..Default::default()
                         }),
+                        diagnostic: Some(DiagnosticClientCapabilities {
+                            dynamic_registration: Some(false),
+                            related_document_support: Some(false),
+                        }),
+                        ..Default::default()
+                    }),
+                    workspace: Some(WorkspaceClientCapabilities {
+                        apply_edit: Some(true),
+                        workspace_edit: Some(WorkspaceEditClientCapabilities {
+                            document_changes: Some(true),
+                            ..Default::default()
+                        }),
+                        execute_command: Some(DynamicRegistrationClientCapabilities {
+                            dynamic_registration: Some(false),
+                        }),
                         ..Default::default()
                     }),
                     ..Default::default()
@@ -594,6 +739,18 @@ async fn process_request(
             )
             .await;
         }
+        LspRequest::SignatureHelp { id, path, position } => {
+            send_text_document_request(
+                "textDocument/signatureHelp",
+                id,
+                path,
+                position,
+                send_tx,
+                pending,
+                next_id,
+            )
+            .await;
+        }
         LspRequest::GotoDefinition { id, path, position } => {
             send_text_document_request(
                 "textDocument/definition",
@@ -706,9 +863,111 @@ async fn process_request(
                 original_id: id,
             });
         }
+        LspRequest::CodeAction {
+            id,
+            path,
+            range,
+            diagnostics,
+        } => {
+            let rpc_id = next_id.fetch_add(1, Ordering::SeqCst) as i64;
+            let uri = path_to_uri(&path);
+            let params = CodeActionParams {
+                text_document: TextDocumentIdentifier { uri },
+                range: range.into(),
+                context: CodeActionContext {
+                    diagnostics: diagnostics.into_iter().map(Into::into).collect(),
+                    only: None,
+                    trigger_kind: Some(CodeActionTriggerKind::INVOKED),
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            };
+
+            {
+                let mut pending = pending.lock().await;
+                pending.insert(
+                    transport::RequestId::Number(rpc_id),
+                    PendingRequest {
+                        method: "textDocument/codeAction".to_string(),
+                        original_id: id,
+                    },
+                );
+            }
+
+            let _ = send_tx.send(SendMessage::Request {
+                id: rpc_id,
+                method: "textDocument/codeAction".to_string(),
+                params: Some(serde_json::to_value(params).unwrap()),
+                original_id: id,
+            });
+        }
+        LspRequest::PullDiagnostics { id, path } => {
+            let rpc_id = next_id.fetch_add(1, Ordering::SeqCst) as i64;
+            let uri = path_to_uri(&path);
+            let params = DocumentDiagnosticParams {
+                text_document: TextDocumentIdentifier { uri },
+                identifier: None,
+                previous_result_id: None,
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            };
+
+            {
+                let mut pending = pending.lock().await;
+                pending.insert(
+                    transport::RequestId::Number(rpc_id),
+                    PendingRequest {
+                        method: "textDocument/diagnostic".to_string(),
+                        original_id: id,
+                    },
+                );
+            }
+
+            let _ = send_tx.send(SendMessage::Request {
+                id: rpc_id,
+                method: "textDocument/diagnostic".to_string(),
+                params: Some(serde_json::to_value(params).unwrap()),
+                original_id: id,
+            });
+        }
+        LspRequest::ExecuteCommand {
+            id,
+            command,
+            arguments,
+        } => {
+            let rpc_id = next_id.fetch_add(1, Ordering::SeqCst) as i64;
+
+            {
+                let mut pending = pending.lock().await;
+                pending.insert(
+                    transport::RequestId::Number(rpc_id),
+                    PendingRequest {
+                        method: "workspace/executeCommand".to_string(),
+                        original_id: id,
+                    },
+                );
+            }
+
+            let _ = send_tx.send(SendMessage::Request {
+                id: rpc_id,
+                method: "workspace/executeCommand".to_string(),
+                params: Some(execute_command_params(command, arguments)),
+                original_id: id,
+            });
+        }
     }
 }
 
+/// Builds the params for a `workspace/executeCommand` request.
+fn execute_command_params(command: String, arguments: Vec<Value>) -> Value {
+    let params = ExecuteCommandParams {
+        command,
+        arguments,
+        work_done_progress_params: Default::default(),
+    };
+    serde_json::to_value(params).unwrap()
+}
+
 /// Helper to send a text document position request.
 async fn send_text_document_request(
     method: &str,
@@ -751,6 +1010,7 @@ async fn handle_server_message(
     pending: &Arc<tokio::sync::Mutex<HashMap<transport::RequestId, PendingRequest>>>,
     response_tx: &Sender<LspResponse>,
     notification_tx: &Sender<LspNotification>,
+    send_tx: &mpsc::UnboundedSender<SendMessage>,
 ) {
     if let Some(parsed) = transport::parse_message(&msg) {
         match parsed {
@@ -761,13 +1021,58 @@ async fn handle_server_message(
                 handle_notification(notif, notification_tx);
             }
             JsonRpcMessage::Request(req) => {
-                // Server-initiated requests (like workspace/configuration)
-                log::debug!("Server request: {} (id: {:?})", req.method, req.id);
+                handle_server_request(req, notification_tx, send_tx);
             }
         }
     }
 }
 
+/// Handles a request initiated by the server.
+fn handle_server_request(
+    req: JsonRpcRequest,
+    notification_tx: &Sender<LspNotification>,
+    send_tx: &mpsc::UnboundedSender<SendMessage>,
+) {
+    match req.method.as_str() {
+        "workspace/applyEdit" => {
+            // The edit is queued for the UI before we reply; `applied: true`
+            // promises it will be applied on the UI's next event poll.
+            let (notification, result) = handle_apply_edit(req.params);
+            if let Some(notification) = notification {
+                let _ = notification_tx.send(notification);
+            }
+            let _ = send_tx.send(SendMessage::Response { id: req.id, result });
+        }
+        _ => {
+            // Other server-initiated requests (like workspace/configuration)
+            log::debug!("Server request: {} (id: {:?})", req.method, req.id);
+        }
+    }
+}
+
+/// Converts `workspace/applyEdit` params into a notification for the UI and
+/// the result to send back to the server.
+fn handle_apply_edit(params: Option<Value>) -> (Option<LspNotification>, Value) {
+    let parsed = params.and_then(|p| serde_json::from_value::<ApplyWorkspaceEditParams>(p).ok());
+    let response = match &parsed {
+        Some(_) => ApplyWorkspaceEditResponse {
+            applied: true,
+            failure_reason: None,
+            failed_change: None,
+        },
+        None => ApplyWorkspaceEditResponse {
+            applied: false,
+            failure_reason: Some("Invalid workspace/applyEdit params".to_string()),
+            failed_change: None,
+        },
+    };
+    let notification = parsed.map(|p| LspNotification::ApplyEdit {
+        label: p.label,
+        edit: convert_workspace_edit(p.edit),
+    });
+    (notification, serde_json::to_value(response).unwrap())
+}
+
 /// Handles a response from the server.
 async fn handle_response(
     resp: JsonRpcResponse,
@@ -791,16 +1096,10 @@ async fn handle_response(
         }
     } else {
         match req_info.method.as_str() {
-            "initialize" => {
-                let caps: InitializeResult = match resp.result {
-                    Some(v) => serde_json::from_value(v).unwrap_or_default(),
-                    None => InitializeResult::default(),
-                };
-                LspResponse::Initialized {
-                    id: req_info.original_id,
-                    capabilities_summary: format_capabilities(&caps.capabilities),
-                }
-            }
+            "initialize" => LspResponse::Initialized {
+                id: req_info.original_id,
+                capabilities: Capabilities::from_initialize_result(resp.result),
+            },
             "textDocument/hover" => {
                 let hover: Option<Hover> = resp
                     .result
@@ -817,6 +1116,13 @@ async fn handle_response(
                     items,
                 }
             }
+            "textDocument/signatureHelp" => LspResponse::SignatureHelp {
+                id: req_info.original_id,
+                help: resp
+                    .result
+                    .and_then(|v| serde_json::from_value::<SignatureHelp>(v).ok())
+                    .map(SignatureHelpInfo::from),
+            },
             "textDocument/definition" => {
                 let locations = parse_location_response(resp.result);
                 LspResponse::GotoDefinition {
@@ -848,6 +1154,18 @@ async fn handle_response(
                     symbols,
                 }
             }
+            "textDocument/codeAction" => LspResponse::CodeActions {
+                id: req_info.original_id,
+                actions: parse_code_actions(resp.result),
+            },
+            "textDocument/diagnostic" => LspResponse::PullDiagnostics {
+                id: req_info.original_id,
+                diagnostics: parse_diagnostic_report(resp.result),
+            },
+            "workspace/executeCommand" => LspResponse::ExecuteCommand {
+                id: req_info.original_id,
+                result: resp.result,
+            },
             _ => {
                 log::debug!("Unhandled response method: {}", req_info.method);
                 return;
@@ -927,32 +1245,6 @@ fn handle_notification(notif: JsonRpcNotification, notification_tx: &Sender<LspN
     }
 }
 
-/// Formats server capabilities as a summary string.
-fn format_capabilities(caps: &ServerCapabilities) -> String {
-    let mut features = Vec::new();
-
-    if caps.hover_provider.is_some() {
-        features.push("hover");
-    }
-    if caps.completion_provider.is_some() {
-        features.push("completion");
-    }
-    if caps.definition_provider.is_some() {
-        features.push("definition");
-    }
-    if caps.references_provider.is_some() {
-        features.push("references");
-    }
-    if caps.rename_provider.is_some() {
-        features.push("rename");
-    }
-    if caps.document_symbol_provider.is_some() {
-        features.push("symbols");
-    }
-
-    features.join(", ")
-}
-
 /// Converts LSP hover to our type.
 fn convert_hover(hover: Hover) -> HoverInfo {
     let contents = match hover.contents {
@@ -998,6 +1290,24 @@ fn parse_completion_response(result: Option<Value>) -> Vec<CompletionItem> {
     vec![]
 }
 
+/// Parses a `textDocument/diagnostic` response. Returns `None` for an
+/// "unchanged" report (or an unparseable one), meaning the diagnostics
+/// already shown are still current.
+fn parse_diagnostic_report(result: Option<Value>) -> Option<Vec<Diagnostic>> {
+    let report = serde_json::from_value::<DocumentDiagnosticReportResult>(result?).ok()?;
+    match report {
+        DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(full)) => Some(
+            full.full_document_diagnostic_report
+                .items
+                .into_iter()
+                .map(|d| d.into())
+                .collect(),
+        ),
+        DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(_)) => None,
+        DocumentDiagnosticReportResult::Partial(_) => None,
+    }
+}
+
 /// Parses location response (definition, references).
 fn parse_location_response(result: Option<Value>) -> Vec<Location> {
     let Some(value) = result else {
@@ -1049,9 +1359,68 @@ fn convert_workspace_edit(edit: lsp_types::WorkspaceEdit) -> WorkspaceEdit {
         }
     }
 
+    // Servers that see `documentChanges` support send versioned edits instead
+    let document_edits = match edit.document_changes {
+        Some(DocumentChanges::Edits(edits)) => edits,
+        Some(DocumentChanges::Operations(ops)) => ops
+            .into_iter()
+            .filter_map(|op| match op {
+                DocumentChangeOperation::Edit(edit) => Some(edit),
+                DocumentChangeOperation::Op(_) => None,
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    for doc_edit in document_edits {
+        let path = uri_to_path(&doc_edit.text_document.uri);
+        let edits: Vec<TextEdit> = doc_edit
+            .edits
+            .into_iter()
+            .map(|e| match e {
+                OneOf::Left(edit) => edit.into(),
+                OneOf::Right(annotated) => annotated.text_edit.into(),
+            })
+            .collect();
+        changes.push((path, edits));
+    }
+
     WorkspaceEdit { changes }
 }
 
+/// Parses a code action response. Bare commands become actions without an
+/// edit; actions the server marked disabled are left out.
+fn parse_code_actions(result: Option<Value>) -> Vec<CodeAction> {
+    let Some(actions) = result.and_then(|v| serde_json::from_value::<CodeActionResponse>(v).ok()) else {
+        return vec![];
+    };
+    actions
+        .into_iter()
+        .filter_map(|action| match action {
+            CodeActionOrCommand::Command(command) => Some(CodeAction {
+                title: command.title,
+                kind: None,
+                edit: None,
+                command: Some(command.command),
+                arguments: command.arguments.unwrap_or_default(),
+            }),
+            CodeActionOrCommand::CodeAction(action) if action.disabled.is_none() => {
+                let (command, arguments) = match action.command {
+                    Some(command) => (Some(command.command), command.arguments.unwrap_or_default()),
+                    None => (None, Vec::new()),
+                };
+                Some(CodeAction {
+                    title: action.title,
+                    kind: action.kind.map(|kind| kind.as_str().to_string()),
+                    edit: action.edit.map(convert_workspace_edit),
+                    command,
+                    arguments,
+                })
+            }
+            CodeActionOrCommand::CodeAction(_) => None,
+        })
+        .collect()
+}
+
 /// Parses document symbols response.
 fn parse_document_symbols(result: Option<Value>) -> Vec<DocumentSymbol> {
     let Some(value) = result else {
@@ -1095,3 +1464,266 @@ fn convert_document_symbol(sym: lsp_types::DocumentSymbol) -> DocumentSymbol {
             .collect(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_execute_command_params() {
+        let params = execute_command_params(
+            "rust-analyzer.applySourceChange".to_string(),
+            vec![json!({"label": "fix"}), json!(3)],
+        );
+        assert_eq!(
+            params,
+            json!({
+                "command": "rust-analyzer.applySourceChange",
+                "arguments": [{"label": "fix"}, 3],
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_code_actions() {
+        let result = json!([
+            {
+                "title": "Import `HashMap`",
+                "kind": "quickfix",
+                "edit": {
+                    "changes": {
+                        "file:///tmp/main.rs": [{
+                            "range": {
+                                "start": {"line": 0, "character": 0},
+                                "end": {"line": 0, "character": 0}
+                            },
+                            "newText": "use std::collections::HashMap;\n"
+                        }]
+                    }
+                }
+            },
+            {
+                "title": "Run test",
+                "command": "rust-analyzer.runSingle",
+                "arguments": [{"label": "test it"}]
+            },
+            {
+                "title": "Extract into function",
+                "kind": "refactor.extract",
+                "disabled": {"reason": "No expression selected"}
+            },
+            {
+                "title": "Organize imports",
+                "kind": "source.organizeImports",
+                "command": {"title": "Organize", "command": "organize", "arguments": [1]}
+            }
+        ]);
+
+        let actions = parse_code_actions(Some(result));
+        let titles: Vec<&str> = actions.iter().map(|a| a.title.as_str()).collect();
+        assert_eq!(titles, vec!["Import `HashMap`", "Run test", "Organize imports"]);
+
+        let edit = actions[0].edit.as_ref().unwrap();
+        assert_eq!(edit.changes[0].0, PathBuf::from("/tmp/main.rs"));
+        assert_eq!(edit.changes[0].1[0].new_text, "use std::collections::HashMap;\n");
+        assert_eq!(actions[0].kind.as_deref(), Some("quickfix"));
+        assert_eq!(actions[0].command, None);
+
+        // A bare command, and an action whose edit is left to its command
+        assert_eq!(actions[1].edit, None);
+        assert_eq!(actions[1].command.as_deref(), Some("rust-analyzer.runSingle"));
+        assert_eq!(actions[1].arguments, vec![json!({"label": "test it"})]);
+        assert_eq!(actions[2].command.as_deref(), Some("organize"));
+        assert_eq!(actions[2].arguments, vec![json!(1)]);
+
+        assert!(parse_code_actions(None).is_empty());
+        assert!(parse_code_actions(Some(json!(null))).is_empty());
+    }
+
+    #[test]
+    fn test_apply_edit_response() {
+        let params = json!({
+            "label": "Extract variable",
+            "edit": {
+                "changes": {
+                    "file:///tmp/main.rs": [{
+                        "range": {
+                            "start": {"line": 1, "character": 4},
+                            "end": {"line": 1, "character": 7}
+                        },
+                        "newText": "value"
+                    }]
+                }
+            }
+        });
+
+        let (notification, result) = handle_apply_edit(Some(params));
+        assert_eq!(result, json!({"applied": true}));
+
+        let Some(LspNotification::ApplyEdit { label, edit }) = notification else {
+            panic!("expected an ApplyEdit notification");
+        };
+        assert_eq!(label.as_deref(), Some("Extract variable"));
+        assert_eq!(edit.changes.len(), 1);
+        assert_eq!(edit.changes[0].0, PathBuf::from("/tmp/main.rs"));
+        assert_eq!(edit.changes[0].1[0].new_text, "value");
+        assert_eq!(edit.changes[0].1[0].range.start, Position::new(1, 4));
+    }
+
+    #[test]
+    fn test_edit_newlines_normalized() {
+        let edit = |new_text: &str| {
+            json!({
+                "range": {
+                    "start": {"line": 0, "character": 0},
+                    "end": {"line": 0, "character": 0}
+                },
+                "newText": new_text
+            })
+        };
+        let params = json!({
+            "edit": {
+                "changes": {"file:///tmp/main.rs": [edit("fn a() {\r\n}\r\n")]},
+                "documentChanges": [{
+                    "textDocument": {"uri": "file:///tmp/lib.rs", "version": 1},
+                    "edits": [edit("a\rb\r\nc\n")]
+                }]
+            }
+        });
+
+        let (notification, _) = handle_apply_edit(Some(params));
+        let Some(LspNotification::ApplyEdit { edit, .. }) = notification else {
+            panic!("expected an ApplyEdit notification");
+        };
+        let texts: Vec<&str> = edit.changes.iter().map(|(_, edits)| edits[0].new_text.as_str()).collect();
+        assert_eq!(texts, ["fn a() {\n}\n", "a\nb\nc\n"]);
+    }
+
+    #[test]
+    fn test_apply_edit_invalid_params() {
+        let (notification, result) = handle_apply_edit(Some(json!({"bogus": true})));
+        assert!(notification.is_none());
+        assert_eq!(result["applied"], json!(false));
+    }
+
+    #[test]
+    fn test_server_apply_edit_request() {
+        let (notification_tx, notification_rx) = crossbeam_channel::unbounded();
+        let (send_tx, mut send_rx) = mpsc::unbounded_channel();
+
+        let req = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: transport::RequestId::Number(7),
+            method: "workspace/applyEdit".to_string(),
+            params: Some(json!({
+                "edit": {
+                    "changes": {
+                        "file:///tmp/lib.rs": [{
+                            "range": {
+                                "start": {"line": 0, "character": 0},
+                                "end": {"line": 0, "character": 3}
+                            },
+                            "newText": "pub"
+                        }]
+                    }
+                }
+            })),
+        };
+        handle_server_request(req, &notification_tx, &send_tx);
+
+        match notification_rx.try_recv() {
+            Ok(LspNotification::ApplyEdit { label, edit }) => {
+                assert!(label.is_none());
+                assert_eq!(edit.changes[0].1[0].new_text, "pub");
+            }
+            _ => panic!("expected an ApplyEdit notification"),
+        }
+        match send_rx.try_recv() {
+            Ok(SendMessage::Response { id, result }) => {
+                assert_eq!(id, transport::RequestId::Number(7));
+                assert_eq!(result, json!({"applied": true}));
+            }
+            _ => panic!("expected a response to the server"),
+        }
+    }
+
+    #[test]
+    fn test_parse_full_diagnostic_report() {
+        let result = json!({
+            "kind": "full",
+            "resultId": "7",
+            "items": [
+                {
+                    "range": {
+                        "start": {"line": 2, "character": 4},
+                        "end": {"line": 2, "character": 9}
+                    },
+                    "severity": 1,
+                    "code": "E0425",
+                    "source": "rustc",
+                    "message": "cannot find value `x`"
+                },
+                {
+                    "range": {
+                        "start": {"line": 5, "character": 0},
+                        "end": {"line": 5, "character": 3}
+                    },
+                    "message": "unused import"
+                }
+            ]
+        });
+
+        let diagnostics = parse_diagnostic_report(Some(result)).unwrap();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].range.start, Position::new(2, 4));
+        assert_eq!(diagnostics[0].range.end, Position::new(2, 9));
+        assert_eq!(diagnostics[0].severity, crate::types::DiagnosticSeverity::Error);
+        assert_eq!(diagnostics[0].code.as_deref(), Some("E0425"));
+        assert_eq!(diagnostics[0].source.as_deref(), Some("rustc"));
+        assert_eq!(diagnostics[0].message, "cannot find value `x`");
+        assert_eq!(diagnostics[1].message, "unused import");
+
+        // An empty full report clears the diagnostics
+        let empty = parse_diagnostic_report(Some(json!({"kind": "full", "items": []})));
+        assert_eq!(empty.map(|d| d.len()), Some(0));
+    }
+
+    #[test]
+    fn test_parse_unchanged_diagnostic_report() {
+        let result = json!({"kind": "unchanged", "resultId": "7"});
+        assert!(parse_diagnostic_report(Some(result)).is_none());
+        assert!(parse_diagnostic_report(None).is_none());
+    }
+
+    #[test]
+    fn test_parse_signature_help() {
+        let result = json!({
+            "signatures": [
+                {
+                    "label": "fn add(a: i32, b: i32) -> i32",
+                    "parameters": [{"label": "a: i32"}, {"label": "b: i32"}]
+                },
+                {
+                    // Offsets count UTF-16 units: the emoji is two of them
+                    "label": "f(\u{1F600}: u8, n: u8)",
+                    "parameters": [{"label": [2, 8]}, {"label": [10, 15]}],
+                    "activeParameter": 0
+                }
+            ],
+            "activeSignature": 0,
+            "activeParameter": 1
+        });
+        let help = SignatureHelpInfo::from(serde_json::from_value::<SignatureHelp>(result).unwrap());
+        assert_eq!(help.signatures[0].parameters, [7..13, 15..21]);
+        assert_eq!(help.signatures[1].parameters, [2..7, 9..14]);
+
+        let (signature, parameter) = help.active().unwrap();
+        assert_eq!(signature.label, "fn add(a: i32, b: i32) -> i32");
+        assert_eq!(parameter, Some(15..21));
+
+        // A signature's own active parameter wins over the help's
+        let help = SignatureHelpInfo { active_signature: 1, ..help };
+        assert_eq!(help.active().unwrap().1, Some(2..7));
+    }
+}
@@ -4,14 +4,16 @@
 //! All LSP operations run on a separate thread, communicating with the UI
 //! via channels.
 
+pub mod capabilities;
 pub mod client;
 pub mod messages;
 pub mod transport;
 pub mod types;
 
+pub use capabilities::{Capabilities, PositionEncoding};
 pub use client::{LspClient, LspHandle, ServerConfig};
 pub use messages::{LspNotification, LspRequest, LspResponse};
 pub use types::{
-    CompletionItem, CompletionKind, Diagnostic, DiagnosticSeverity, HoverInfo, Location,
-    Position, Range, TextEdit, WorkspaceEdit,
+    CodeAction, CompletionItem, CompletionKind, Diagnostic, DiagnosticSeverity, HoverInfo, Location,
+    Position, Range, SignatureHelpInfo, SignatureInfo, TextEdit, WorkspaceEdit,
 };
@@ -2,7 +2,11 @@
 //!
 //! These messages are sent over channels between the UI thread and LSP thread.
 
-use crate::types::{CompletionItem, Diagnostic, HoverInfo, Location, Position, WorkspaceEdit};
+use crate::capabilities::Capabilities;
+use crate::types::{
+    CodeAction, CompletionItem, Diagnostic, HoverInfo, Location, Position, Range, SignatureHelpInfo, WorkspaceEdit,
+};
+use serde_json::Value;
 use std::path::PathBuf;
 
 /// Request ID for correlating responses.
@@ -51,6 +55,12 @@ pub enum LspRequest {
         path: PathBuf,
         position: Position,
     },
+    /// Request signature help for the call around a position.
+    SignatureHelp {
+        id: RequestId,
+        path: PathBuf,
+        position: Position,
+    },
     /// Request go to definition.
     GotoDefinition {
         id: RequestId,
@@ -76,6 +86,25 @@ pub enum LspRequest {
         id: RequestId,
         path: PathBuf,
     },
+    /// Request the code actions for a range, given the diagnostics that
+    /// overlap it.
+    CodeAction {
+        id: RequestId,
+        path: PathBuf,
+        range: Range,
+        diagnostics: Vec<Diagnostic>,
+    },
+    /// Pull diagnostics for a document (`textDocument/diagnostic`).
+    PullDiagnostics {
+        id: RequestId,
+        path: PathBuf,
+    },
+    /// Run a server command (e.g. one attached to a code action).
+    ExecuteCommand {
+        id: RequestId,
+        command: String,
+        arguments: Vec<Value>,
+    },
 }
 
 /// Responses from LSP client to UI.
@@ -84,8 +113,8 @@ pub enum LspResponse {
     /// Server initialized successfully.
     Initialized {
         id: RequestId,
-        /// Server capabilities description.
-        capabilities_summary: String,
+        /// What the server supports.
+        capabilities: Capabilities,
     },
     /// Initialization failed.
     InitializeFailed {
@@ -102,6 +131,11 @@ pub enum LspResponse {
         id: RequestId,
         items: Vec<CompletionItem>,
     },
+    /// Signature help response. `None` if the position isn't in a call.
+    SignatureHelp {
+        id: RequestId,
+        help: Option<SignatureHelpInfo>,
+    },
     /// Go to definition response.
     GotoDefinition {
         id: RequestId,
@@ -122,6 +156,22 @@ pub enum LspResponse {
         id: RequestId,
         symbols: Vec<DocumentSymbol>,
     },
+    /// Code actions response; empty if the server offers none.
+    CodeActions {
+        id: RequestId,
+        actions: Vec<CodeAction>,
+    },
+    /// Pull diagnostics response. `None` if the server reported that the
+    /// previous diagnostics are unchanged.
+    PullDiagnostics {
+        id: RequestId,
+        diagnostics: Option<Vec<Diagnostic>>,
+    },
+    /// Execute command response.
+    ExecuteCommand {
+        id: RequestId,
+        result: Option<Value>,
+    },
     /// Generic error response.
     Error {
         id: RequestId,
@@ -154,6 +204,12 @@ pub enum LspNotification {
         level: LogLevel,
         message: String,
     },
+    /// Server asked the editor to apply a workspace edit (`workspace/applyEdit`).
+    /// The client has already replied that the edit was applied.
+    ApplyEdit {
+        label: Option<String>,
+        edit: WorkspaceEdit,
+    },
 }
 
 /// Log level for server messages.
@@ -121,6 +121,17 @@ impl TransportWriter {
         self.send_message(&serde_json::to_value(request)?).await
     }
 
+    /// Sends a successful JSON-RPC response to a server-initiated request.
+    pub async fn send_response(&mut self, id: RequestId, result: Value) -> std::io::Result<()> {
+        let response = JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        };
+        self.send_message(&serde_json::to_value(response)?).await
+    }
+
     /// Sends a JSON-RPC notification.
     pub async fn send_notification(
         &mut self,
@@ -3,6 +3,7 @@
 //! These types are simplified versions of lsp-types for use in the editor.
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::path::PathBuf;
 
 /// A position in a text document.
@@ -93,6 +94,17 @@ pub enum DiagnosticSeverity {
     Hint,
 }
 
+impl From<DiagnosticSeverity> for lsp_types::DiagnosticSeverity {
+    fn from(severity: DiagnosticSeverity) -> Self {
+        match severity {
+            DiagnosticSeverity::Error => Self::ERROR,
+            DiagnosticSeverity::Warning => Self::WARNING,
+            DiagnosticSeverity::Information => Self::INFORMATION,
+            DiagnosticSeverity::Hint => Self::HINT,
+        }
+    }
+}
+
 impl From<lsp_types::DiagnosticSeverity> for DiagnosticSeverity {
     fn from(severity: lsp_types::DiagnosticSeverity) -> Self {
         match severity {
@@ -138,6 +150,19 @@ impl From<lsp_types::Diagnostic> for Diagnostic {
     }
 }
 
+impl From<Diagnostic> for lsp_types::Diagnostic {
+    fn from(diag: Diagnostic) -> Self {
+        Self {
+            range: diag.range.into(),
+            severity: Some(diag.severity.into()),
+            code: diag.code.map(lsp_types::NumberOrString::String),
+            source: diag.source,
+            message: diag.message,
+            ..Default::default()
+        }
+    }
+}
+
 /// Hover information.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct HoverInfo {
@@ -147,6 +172,103 @@ pub struct HoverInfo {
     pub range: Option<Range>,
 }
 
+/// One signature of a callable, from signature help.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureInfo {
+    /// The signature as displayed, e.g. `fn add(a: i32, b: i32) -> i32`.
+    pub label: String,
+    /// Character ranges of the parameters within `label`.
+    pub parameters: Vec<std::ops::Range<usize>>,
+    /// Active parameter of this signature, overriding the one of the help.
+    pub active_parameter: Option<usize>,
+}
+
+/// Signature help for the call around a position.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureHelpInfo {
+    /// The signatures of the called function, one per overload.
+    pub signatures: Vec<SignatureInfo>,
+    /// Index of the signature that matches the call best.
+    pub active_signature: usize,
+    /// Index of the parameter the position is in.
+    pub active_parameter: Option<usize>,
+}
+
+impl SignatureHelpInfo {
+    /// Returns the active signature and the character range of its active
+    /// parameter in the label, if any.
+    pub fn active(&self) -> Option<(&SignatureInfo, Option<std::ops::Range<usize>>)> {
+        let signature = self
+            .signatures
+            .get(self.active_signature)
+            .or_else(|| self.signatures.first())?;
+        let parameter = signature
+            .active_parameter
+            .or(self.active_parameter)
+            .and_then(|i| signature.parameters.get(i).cloned());
+        Some((signature, parameter))
+    }
+}
+
+impl From<lsp_types::SignatureHelp> for SignatureHelpInfo {
+    fn from(help: lsp_types::SignatureHelp) -> Self {
+        let signatures = help
+            .signatures
+            .into_iter()
+            .map(|signature| {
+                let parameters = parameter_ranges(&signature.label, signature.parameters.unwrap_or_default());
+                SignatureInfo {
+                    label: signature.label,
+                    parameters,
+                    active_parameter: signature.active_parameter.map(|i| i as usize),
+                }
+            })
+            .collect();
+        Self {
+            signatures,
+            active_signature: help.active_signature.unwrap_or(0) as usize,
+            active_parameter: help.active_parameter.map(|i| i as usize),
+        }
+    }
+}
+
+/// Returns the character ranges of `parameters` within a signature label.
+/// Parameters are given either as a substring of the label, searched for
+/// after the previous parameter, or as UTF-16 offsets into it.
+fn parameter_ranges(label: &str, parameters: Vec<lsp_types::ParameterInformation>) -> Vec<std::ops::Range<usize>> {
+    let char_index = |byte: usize| label[..byte].chars().count();
+    let utf16_to_char = |offset: u32| {
+        let mut units = 0;
+        label
+            .chars()
+            .position(|ch| {
+                let at = units >= offset as usize;
+                units += ch.len_utf16();
+                at
+            })
+            .unwrap_or(label.chars().count())
+    };
+    // Parameter names also appear in the function name, so search from
+    // the parameter list on
+    let mut search_from = label.find('(').unwrap_or(0);
+    let mut ranges = Vec::new();
+    for parameter in parameters {
+        match parameter.label {
+            lsp_types::ParameterLabel::Simple(name) => {
+                let Some(start) = label[search_from..].find(&name).map(|i| i + search_from) else {
+                    continue;
+                };
+                search_from = start + name.len();
+                ranges.push(char_index(start)..char_index(search_from));
+            }
+            lsp_types::ParameterLabel::LabelOffsets([start, end]) => {
+                ranges.push(utf16_to_char(start)..utf16_to_char(end));
+            }
+        }
+    }
+    ranges
+}
+
 /// Completion item kind.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum CompletionKind {
@@ -261,14 +383,42 @@ impl From<lsp_types::TextEdit> for TextEdit {
     fn from(edit: lsp_types::TextEdit) -> Self {
         Self {
             range: edit.range.into(),
-            new_text: edit.new_text,
+            new_text: normalize_newlines(edit.new_text),
         }
     }
 }
 
+/// Turns the `\r\n` and lone `\r` line breaks some servers put in edit
+/// text into the `\n` the editor inserts, so edits add no stray `\r`.
+fn normalize_newlines(text: String) -> String {
+    if text.contains('\r') {
+        text.replace("\r\n", "\n").replace('\r', "\n")
+    } else {
+        text
+    }
+}
+
 /// A workspace edit (changes to multiple files).
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct WorkspaceEdit {
     /// Edits per file.
     pub changes: Vec<(PathBuf, Vec<TextEdit>)>,
 }
+
+/// A fix or refactoring offered for a range (`textDocument/codeAction`).
+///
+/// Applying it means applying its edit, if any, then running its command,
+/// if any, with `workspace/executeCommand`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CodeAction {
+    /// Title shown in the menu, e.g. "Import `HashMap`".
+    pub title: String,
+    /// Kind, e.g. `quickfix` or `refactor.extract`.
+    pub kind: Option<String>,
+    /// Edit the action makes.
+    pub edit: Option<WorkspaceEdit>,
+    /// Server command to run after the edit.
+    pub command: Option<String>,
+    /// Arguments of the command.
+    pub arguments: Vec<Value>,
+}
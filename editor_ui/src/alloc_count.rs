@@ -0,0 +1,79 @@
+//! Counting heap allocations.
+//!
+//! [`CountingAllocator`] wraps the system allocator and counts the
+//! allocations made on each thread, so a test can check how many a piece of
+//! code makes, e.g. that drawing a frame doesn't allocate for every line.
+//! The unit tests install it; other binaries can install it with the
+//! `alloc-count` feature:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: cp_editor_ui::alloc_count::CountingAllocator = cp_editor_ui::alloc_count::CountingAllocator;
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    /// Allocations made on this thread.
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The system allocator, counting allocations per thread.
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    fn count() {
+        // Allocations while the thread is torn down go uncounted
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::count();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::count();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::count();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// Returns the number of allocations `f` made on this thread, with what it
+/// returned. Only counts if [`CountingAllocator`] is the global allocator.
+pub fn count<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_this_thread() {
+        let (v, allocations) = count(|| vec![1, 2, 3]);
+        assert_eq!(allocations, 1);
+        let ((), allocations) = count(|| drop(v));
+        assert_eq!(allocations, 0);
+        // Another thread's allocations aren't counted here
+        let (_, allocations) = count(|| std::thread::spawn(|| (0..100).map(|i| vec![i]).collect::<Vec<_>>()).join());
+        assert!(allocations < 100);
+    }
+}
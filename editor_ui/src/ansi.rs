@@ -0,0 +1,267 @@
+//! ANSI escape sequences in process output.
+//!
+//! Compilers and linters color their output with SGR sequences
+//! (`ESC [ ... m`). [`parse_line`] splits a line of output into runs of text
+//! with the color those sequences set, so panels showing process output can
+//! draw it colored instead of showing the raw escapes. Foreground colors and
+//! bold are supported; other SGR attributes and any other escape sequences
+//! (cursor movement, OSC hyperlinks, ...) are stripped.
+
+/// Escape character that starts every sequence.
+const ESC: char = '\x1b';
+
+/// Foreground color of a run of output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnsiColor {
+    /// The panel's normal text color.
+    Default,
+    /// One of the 16 basic colors: 0-7 normal, 8-15 bright.
+    Palette(u8),
+    /// A 256-color or truecolor value.
+    Rgb(u8, u8, u8),
+}
+
+/// The basic 16 colors, as RGB.
+const PALETTE: [[u8; 3]; 16] = [
+    [0x1e, 0x1e, 0x1e], // black
+    [0xef, 0x53, 0x50], // red
+    [0x66, 0xbb, 0x6a], // green
+    [0xff, 0xc1, 0x07], // yellow
+    [0x42, 0xa5, 0xf5], // blue
+    [0xba, 0x68, 0xc8], // magenta
+    [0x26, 0xc6, 0xda], // cyan
+    [0xe0, 0xe0, 0xe0], // white
+    [0x80, 0x80, 0x80], // bright black
+    [0xff, 0x80, 0x80], // bright red
+    [0x9c, 0xe6, 0x9c], // bright green
+    [0xff, 0xe0, 0x82], // bright yellow
+    [0x90, 0xca, 0xf9], // bright blue
+    [0xe1, 0xa5, 0xee], // bright magenta
+    [0x80, 0xde, 0xea], // bright cyan
+    [0xff, 0xff, 0xff], // bright white
+];
+
+impl AnsiColor {
+    /// Returns the color as RGBA, using `default` for [`AnsiColor::Default`].
+    pub fn rgba(self, default: [f32; 4]) -> [f32; 4] {
+        let [r, g, b] = match self {
+            AnsiColor::Default => return default,
+            AnsiColor::Palette(index) => PALETTE[index as usize % PALETTE.len()],
+            AnsiColor::Rgb(r, g, b) => [r, g, b],
+        };
+        [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0]
+    }
+
+    /// Maps a 256-color index to a color.
+    fn from_256(index: u8) -> Self {
+        match index {
+            0..=15 => AnsiColor::Palette(index),
+            // 6x6x6 color cube
+            16..=231 => {
+                let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+                let i = index - 16;
+                AnsiColor::Rgb(level(i / 36), level(i / 6 % 6), level(i % 6))
+            }
+            // Grayscale ramp
+            _ => {
+                let gray = 8 + (index - 232) * 10;
+                AnsiColor::Rgb(gray, gray, gray)
+            }
+        }
+    }
+}
+
+/// A run of output text drawn in one color.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnsiRun {
+    /// The text, without escape sequences.
+    pub text: String,
+    /// Its foreground color.
+    pub color: AnsiColor,
+}
+
+/// Text attributes set by SGR sequences.
+#[derive(Debug, Clone, Copy)]
+struct Style {
+    color: AnsiColor,
+    bold: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            color: AnsiColor::Default,
+            bold: false,
+        }
+    }
+}
+
+impl Style {
+    /// Returns the color text is drawn in. Bold brightens the normal basic
+    /// colors, as most terminals do.
+    fn effective_color(&self) -> AnsiColor {
+        match self.color {
+            AnsiColor::Palette(index) if self.bold && index < 8 => AnsiColor::Palette(index + 8),
+            color => color,
+        }
+    }
+
+    /// Applies the parameters of one SGR sequence.
+    fn apply(&mut self, params: &str) {
+        let mut codes = params.split([';', ':']).map(|p| p.parse::<u16>().unwrap_or(0));
+        // `ESC [ m` is a reset, like `ESC [ 0 m`
+        if params.is_empty() {
+            *self = Style::default();
+            return;
+        }
+        while let Some(code) = codes.next() {
+            match code {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                30..=37 => self.color = AnsiColor::Palette((code - 30) as u8),
+                39 => self.color = AnsiColor::Default,
+                90..=97 => self.color = AnsiColor::Palette((code - 90) as u8 + 8),
+                38 | 48 => {
+                    let color = match codes.next() {
+                        Some(5) => codes.next().map(|index| AnsiColor::from_256(index as u8)),
+                        Some(2) => {
+                            let mut channel = || codes.next().unwrap_or(0) as u8;
+                            Some(AnsiColor::Rgb(channel(), channel(), channel()))
+                        }
+                        _ => None,
+                    };
+                    // Backgrounds aren't drawn, but their arguments are skipped
+                    if let (38, Some(color)) = (code, color) {
+                        self.color = color;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Splits a line of output into colored runs, dropping escape sequences.
+///
+/// Each line is parsed on its own, starting with the default style, so a
+/// color left on by one line doesn't leak into the next. Empty runs are
+/// omitted and adjacent runs with the same color are merged.
+pub fn parse_line(line: &str) -> Vec<AnsiRun> {
+    let mut runs: Vec<AnsiRun> = Vec::new();
+    let mut style = Style::default();
+    let mut chars = line.chars().peekable();
+
+    let mut push = |text: String, color: AnsiColor| {
+        if text.is_empty() {
+            return;
+        }
+        match runs.last_mut() {
+            Some(last) if last.color == color => last.text.push_str(&text),
+            _ => runs.push(AnsiRun { text, color }),
+        }
+    };
+
+    let mut text = String::new();
+    while let Some(ch) = chars.next() {
+        if ch != ESC {
+            text.push(ch);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters, then a final byte in @..~
+            Some('[') => {
+                let mut params = String::new();
+                let mut terminator = None;
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        terminator = Some(c);
+                        break;
+                    }
+                    params.push(c);
+                }
+                if terminator == Some('m') {
+                    push(std::mem::take(&mut text), style.effective_color());
+                    style.apply(&params);
+                }
+            }
+            // OSC, e.g. hyperlinks: ends with BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == ESC && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // Other two-character sequences
+            _ => {}
+        }
+    }
+    push(text, style.effective_color());
+    runs
+}
+
+/// Returns a line of output with its escape sequences removed, e.g. before
+/// matching it against a problem matcher.
+pub fn strip(line: &str) -> String {
+    if !line.contains(ESC) {
+        return line.to_string();
+    }
+    parse_line(line).into_iter().map(|run| run.text).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(text: &str, color: AnsiColor) -> AnsiRun {
+        AnsiRun {
+            text: text.to_string(),
+            color,
+        }
+    }
+
+    #[test]
+    fn test_red_error_and_reset() {
+        let runs = parse_line("\x1b[31merror\x1b[0m: expected `;`");
+        assert_eq!(
+            runs,
+            vec![run("error", AnsiColor::Palette(1)), run(": expected `;`", AnsiColor::Default)]
+        );
+
+        // Bold brightens the color; `ESC [ m` also resets
+        let runs = parse_line("\x1b[1;31merror[E0308]\x1b[m rest");
+        assert_eq!(runs, vec![run("error[E0308]", AnsiColor::Palette(9)), run(" rest", AnsiColor::Default)]);
+        assert_eq!(AnsiColor::Palette(1).rgba([0.0; 4]), [0xef as f32 / 255.0, 0x53 as f32 / 255.0, 0x50 as f32 / 255.0, 1.0]);
+        assert_eq!(AnsiColor::Default.rgba([0.5; 4]), [0.5; 4]);
+    }
+
+    #[test]
+    fn test_extended_colors() {
+        let runs = parse_line("\x1b[38;5;196mA\x1b[38;2;1;2;3mB\x1b[48;5;4;92mC\x1b[39mD");
+        assert_eq!(
+            runs,
+            vec![
+                run("A", AnsiColor::Rgb(255, 0, 0)),
+                run("B", AnsiColor::Rgb(1, 2, 3)),
+                run("C", AnsiColor::Palette(10)),
+                run("D", AnsiColor::Default),
+            ]
+        );
+        assert_eq!(AnsiColor::from_256(244), AnsiColor::Rgb(128, 128, 128));
+    }
+
+    #[test]
+    fn test_unsupported_sequences_are_stripped() {
+        // Cursor movement, erase line and an OSC 8 hyperlink
+        let line = "\x1b[2K\x1b[1Gsrc/main.rs:\x1b]8;;file:///src/main.rs\x1b\\2\x1b]8;;\x07:5: \x1b[4munderlined\x1b[24m";
+        assert_eq!(strip(line), "src/main.rs:2:5: underlined");
+        assert_eq!(parse_line(line).len(), 1);
+        assert_eq!(strip("plain"), "plain");
+        assert!(parse_line("").is_empty());
+    }
+}
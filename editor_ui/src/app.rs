@@ -1,14 +1,48 @@
 //! Main editor application with GPU rendering.
 
-use crate::gpu_renderer::GpuRenderer;
-use crate::input::{EditorCommand, InputHandler};
-use crate::lsp::{language_id_from_path, LspEvent, LspManager};
-use crate::notifications::NotificationManager;
-use cp_editor_core::lsp_types::{CompletionItem, DiagnosticSeverity};
+use crate::ansi;
+use crate::code_actions::CodeActionMenu;
+use crate::commands::{CommandCategory, CommandContext, CommandInfo, CommandRegistry, Handler};
+use crate::cursor_blink::CursorBlink;
+use crate::diagnostics_popup::DiagnosticsPopup;
+use crate::disk_banner::{self, BannerAction};
+use crate::font::GlyphAtlas;
+use crate::gpu_renderer::{DrawList, GpuRenderer};
+use crate::gutter::{format_line_number, GutterAction, GutterColumn, GutterConfig, GutterLayout, MAX_LINE_NUMBER_DIGITS};
+use crate::input::{block_mode_command, EditorCommand, InputHandler};
+use crate::input_gate::{self, DialogSpan, InputKind, InputRoute, Modal, ReplayQueue};
+use crate::popup_anchor::{HoverAnchor, PopupAnchor};
+use crate::lsp::{self, language_id_from_path, FileEdits, LspEvent, LspFeature, LspManager};
+use crate::notifications::{NotificationManager, NotificationType};
+use crate::problems::{self, ProblemEntry, ProblemsPanel};
+use crate::project_search::{replace_in_file, ProjectSearch};
+use crate::settings::Settings;
+use crate::tab_menu::{TabMenu, TabMenuLayout};
+use crate::tab_switcher::TabSwitcher;
+use crate::text_field::TextField;
+use crate::trust::TrustStore;
+use crate::window_geometry::{self, MonitorRect, WindowGeometry};
+use crate::zen::ZenMode;
+use cp_editor_core::lsp_types::{CompletionItem, DiagnosticSeverity, SignatureHelp};
 use cp_editor_core::perf::PerfMetrics;
-use cp_editor_core::Workspace;
-use std::path::PathBuf;
-use std::sync::Arc;
+use cp_editor_core::editor::MAX_OCCURRENCE_CURSORS;
+use cp_editor_core::diff::unified_diff;
+use cp_editor_core::export;
+use cp_editor_core::freshness::format_age;
+use cp_editor_core::fuzzy;
+use cp_editor_core::lint::group_by_file;
+use cp_editor_core::runner;
+use cp_editor_core::invisible;
+use cp_editor_core::viewport;
+use cp_editor_core::workspace::MAX_RECENT_FILES;
+use cp_editor_core::{
+    BufferId, ChangeKind, CommandEvent, CommandRunner, Editor, EditorSettings, Encoding, Linter, ProblemMatcher, RecentFiles, SaveOutcome, SaveQueue,
+    Pane, PaneSide, SaveCleanup, SelectionGranularity, TabLabel, TemplateVars, TextBuffer, VisualLine, Workspace, WorkspaceEvent,
+};
+use cp_editor_lsp::{Position, PositionEncoding, Range};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant};
 use winit::application::ApplicationHandler;
 use winit::dpi::{PhysicalPosition, PhysicalSize};
@@ -17,17 +51,91 @@ use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
 use winit::keyboard::{Key, ModifiersState, NamedKey};
 use winit::window::{Window, WindowId};
 
-/// Cursor blink interval in milliseconds.
-const CURSOR_BLINK_INTERVAL_MS: u64 = 530;
+/// Default size of the UI font used by the window chrome, in points.
+pub const DEFAULT_UI_FONT_SIZE: f32 = 13.0;
+
+/// Smallest and largest editor font size zooming allows.
+const MIN_FONT_SIZE: f32 = 6.0;
+const MAX_FONT_SIZE: f32 = 48.0;
+
+/// How much one zoom step changes the editor font size.
+const ZOOM_STEP: f32 = 1.0;
+
+/// Vertical padding around the text of the tab bar, search bar and status
+/// bar, in pixels.
+const TAB_BAR_PADDING: f32 = 5.0;
+const SEARCH_BAR_PADDING: f32 = 7.0;
+const STATUS_BAR_PADDING: f32 = 3.0;
+const DISK_BANNER_PADDING: f32 = 3.0;
+
+/// Opacity of the underlines and gutter markers of stale diagnostics,
+/// relative to fresh ones.
+const STALE_DIAGNOSTIC_ALPHA: f32 = 0.4;
+
+/// Width of a notification, the space around and between notifications and
+/// the padding around their text, in pixels.
+const NOTIFICATION_WIDTH: f32 = 300.0;
+const NOTIFICATION_MARGIN: f32 = 8.0;
+const NOTIFICATION_PADDING: f32 = 12.0;
+
+/// Metrics of the UI font, which the window chrome is laid out with.
+///
+/// The chrome doesn't depend on the editor font, so zooming the buffer text
+/// leaves the tab bar, status bar and popups as they are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChromeMetrics {
+    /// Width of one character of the UI font.
+    pub char_width: f32,
+    /// Line height of the UI font.
+    pub line_height: f32,
+}
+
+impl ChromeMetrics {
+    /// Takes the metrics of a UI font atlas.
+    pub fn from_atlas(atlas: &GlyphAtlas) -> Self {
+        Self {
+            char_width: atlas.char_width,
+            line_height: atlas.line_height,
+        }
+    }
+
+    /// Height of the tab bar.
+    pub fn tab_bar_height(&self) -> f32 {
+        (self.line_height + 2.0 * TAB_BAR_PADDING).ceil()
+    }
+
+    /// Height of the search/replace/goto input bar.
+    pub fn search_bar_height(&self) -> f32 {
+        (self.line_height + 2.0 * SEARCH_BAR_PADDING).ceil()
+    }
+
+    /// Height of the input fields in the input bar.
+    pub fn input_field_height(&self) -> f32 {
+        (self.line_height + 4.0).ceil()
+    }
+
+    /// Height of the status bar.
+    pub fn status_bar_height(&self) -> f32 {
+        (self.line_height + 2.0 * STATUS_BAR_PADDING).ceil()
+    }
+
+    /// Height of the banner over a buffer whose file changed on disk.
+    pub fn disk_banner_height(&self) -> f32 {
+        (self.line_height + 2.0 * DISK_BANNER_PADDING).ceil()
+    }
+}
 
-/// Tab bar height in pixels.
-const TAB_BAR_HEIGHT: f32 = 28.0;
+/// How often open files are checked for changes made outside the editor.
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
-/// Search bar height in pixels.
-const SEARCH_BAR_HEIGHT: f32 = 32.0;
+/// How long quitting waits for in-flight saves to finish.
+const SAVE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
-/// Status bar height in pixels.
-const STATUS_BAR_HEIGHT: f32 = 24.0;
+/// Lines of linter output kept for the lint panel.
+const MAX_LINT_OUTPUT_LINES: usize = 2000;
+
+/// Rows shown in the lint output panel.
+const LINT_PANEL_ROWS: usize = 10;
 
 /// Input mode for the editor.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -42,17 +150,97 @@ pub enum InputMode {
     GoToLine,
     /// Rename symbol mode (F2).
     Rename,
+    /// Project-wide search mode (Ctrl+Shift+F).
+    ProjectSearch,
+    /// Filter the selection through a shell command (Alt+F).
+    FilterCommand,
+    /// Pick a recently opened file to reopen (Ctrl+R).
+    RecentFiles,
+    /// Step through the diagnostics of every open buffer (Alt+P).
+    Problems,
+    /// Pick the encoding to read the file again in (Ctrl+Alt+R).
+    Encodings,
+}
+
+/// Which buffers are saved before an external command runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SaveBeforeRun {
+    /// Run without saving.
+    Off,
+    /// Save the active buffer.
+    Active,
+    /// Save every modified buffer.
+    #[default]
+    All,
+}
+
+/// The file the process waits on when started with `--wait`, e.g. as git's
+/// editor: the process exits once its buffer is closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WaitTarget {
+    /// The waited-on file, as the buffer reports it.
+    pub path: PathBuf,
+    /// Whether the file was written since it was opened.
+    pub saved: bool,
+    /// Whether its buffer was closed.
+    pub closed: bool,
+}
+
+/// Text copied from several cursors, one piece per cursor.
+///
+/// The system clipboard only gets the pieces joined with newlines; this keeps
+/// the pieces so a paste with the same number of cursors can hand each cursor
+/// its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorClipboard {
+    /// Text put on the system clipboard.
+    pub text: String,
+    /// The selected text of each cursor, in buffer order.
+    pub pieces: Vec<String>,
+}
+
+impl CursorClipboard {
+    /// Creates a clipboard entry from per-cursor pieces.
+    pub fn new(pieces: Vec<String>) -> Self {
+        Self {
+            text: pieces.join("\n"),
+            pieces,
+        }
+    }
+}
+
+/// Progress through a step-through replace, where each match is confirmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplaceWalk {
+    /// Number of matches when the walk started.
+    pub total: usize,
+    /// Matches visited (replaced or skipped) so far.
+    pub visited: usize,
+    /// Matches replaced so far.
+    pub replaced: usize,
+}
+
+impl ReplaceWalk {
+    /// Returns how many of the original matches are still to be visited.
+    pub fn remaining(&self) -> usize {
+        self.total.saturating_sub(self.visited)
+    }
+
+    /// Returns the prompt shown in the input bar.
+    pub fn prompt(&self) -> String {
+        format!("Replace? (y/n/a) {} of {}", self.visited + 1, self.total)
+    }
 }
 
-/// Pending dialog action after unsaved changes confirmation.
-#[derive(Debug, Clone)]
-pub enum PendingAction {
-    /// Close the specified buffer.
-    CloseBuffer(cp_editor_core::BufferId),
-    /// Quit the application.
-    Quit,
-    /// Open a file (after closing current unsaved).
-    OpenFile,
+/// Answer to a step-through replace prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaceAnswer {
+    /// Replace the current match and move to the next.
+    Replace,
+    /// Move to the next match without replacing.
+    Skip,
+    /// Replace the current match and all remaining ones.
+    All,
 }
 
 /// The main editor application.
@@ -61,60 +249,149 @@ pub struct EditorApp {
     pub workspace: Workspace,
     /// Input handler.
     pub input_handler: InputHandler,
-    /// Font size.
+    /// Font size of the buffer text; zooming changes it.
     pub font_size: f32,
-    /// Left margin for line numbers.
-    pub line_number_margin: f32,
-    /// Whether the cursor is currently visible (for blinking).
-    pub cursor_visible: bool,
-    /// Last time the cursor blink state changed.
-    pub last_cursor_blink: Instant,
-    /// Whether the cursor should blink (disabled during typing).
-    pub cursor_blink_enabled: bool,
-    /// Pending action requiring confirmation.
-    pub pending_action: Option<PendingAction>,
-    /// Whether a file dialog is currently open.
-    pub dialog_open: bool,
+    /// Font size the editor started with, restored by resetting the zoom.
+    pub default_font_size: f32,
+    /// Font size of the window chrome: tab bar, status bar, notifications
+    /// and popups.
+    pub ui_font_size: f32,
+    /// Layout metrics of the window chrome, from the UI font.
+    pub chrome: ChromeMetrics,
+    /// Gutter columns and separator shown left of the text.
+    pub gutter: GutterConfig,
+    /// Cursor blink timing (solid while typing or moving the cursor).
+    pub cursor_blink: CursorBlink,
     /// Current input mode.
     pub input_mode: InputMode,
     /// Search query text.
-    pub search_text: String,
+    pub search_text: TextField,
     /// Replace text.
-    pub replace_text: String,
+    pub replace_text: TextField,
+    /// Step-through replace in progress, if any.
+    pub replace_walk: Option<ReplaceWalk>,
     /// Go to line text.
-    pub goto_text: String,
+    pub goto_text: TextField,
+    /// Why the go to line text was rejected, until it is edited.
+    pub goto_error: Option<String>,
     /// Rename symbol text.
-    pub rename_text: String,
+    pub rename_text: TextField,
+    /// Shell command the selection is filtered through.
+    pub filter_text: TextField,
+    /// Project search query text.
+    pub project_search_text: TextField,
+    /// Project-wide search and its results.
+    pub project_search: ProjectSearch,
+    /// Replacement for project-wide replace.
+    pub project_replace_text: TextField,
+    /// Text narrowing the recent files picker.
+    pub recent_text: TextField,
+    /// Recent files matching `recent_text`, best match first.
+    pub recent_matches: Vec<PathBuf>,
+    /// Index of the selected file in `recent_matches`.
+    pub recent_selected: usize,
+    /// Text narrowing the encoding picker.
+    pub encoding_text: TextField,
+    /// Encodings matching `encoding_text`, best match first.
+    pub encoding_matches: Vec<Encoding>,
+    /// Index of the selected encoding in `encoding_matches`.
+    pub encoding_selected: usize,
+    /// The Problems panel and its snapshot of the diagnostics.
+    pub problems: ProblemsPanel,
+    /// Text filtering the Problems panel.
+    pub problems_text: TextField,
     /// Which input field is focused (0 = search, 1 = replace).
     pub focused_field: usize,
     /// LSP manager for language server integration.
     pub lsp_manager: LspManager,
+    /// Folders trusted to run language servers in.
+    pub trust: TrustStore,
     /// Last mouse position for hover (screen coordinates).
     pub hover_mouse_pos: Option<(f32, f32)>,
     /// Last hover request time.
     pub hover_request_time: Option<Instant>,
     /// Whether we're waiting for a hover response.
     pub hover_pending: bool,
+    /// What the hover popup is drawn at, set when hover is requested.
+    pub hover_anchor: Option<HoverAnchor>,
     /// Whether the completion popup is visible.
     pub completion_visible: bool,
     /// Selected completion item index.
     pub completion_selected: usize,
-    /// Position where completion was triggered (line, col).
-    pub completion_trigger_pos: Option<(usize, usize)>,
+    /// Position where completion was triggered.
+    pub completion_anchor: Option<PopupAnchor>,
+    /// Just inside the parenthesis of the call signature help is shown for.
+    pub signature_anchor: Option<PopupAnchor>,
+    /// Where code actions were asked for, set until the menu closes.
+    pub code_action_anchor: Option<PopupAnchor>,
+    /// Menu of the code actions offered at `code_action_anchor`, if open.
+    pub code_actions: Option<CodeActionMenu>,
     /// Notification manager for user feedback.
     pub notifications: NotificationManager,
+    /// Per-cursor pieces of the last multi-cursor copy, if any.
+    pub cursor_clipboard: Option<CursorClipboard>,
+    /// Background save jobs.
+    pub save_queue: SaveQueue,
+    /// When open files were last checked for changes on disk.
+    pub disk_checked_at: Instant,
+    /// External linters by LSP language id, run on save for languages
+    /// without a language server and on demand with RunLinter.
+    pub linters: HashMap<String, Linter>,
+    /// Runs the current linter command.
+    pub lint_runner: CommandRunner,
+    /// Buffers saved before running an external command, so it sees the
+    /// latest code.
+    pub save_before_run: SaveBeforeRun,
+    /// Output of the last linter run, with any ANSI color sequences.
+    pub lint_output: Vec<String>,
+    /// File being linted and the matcher for its output.
+    pub lint_target: Option<(PathBuf, ProblemMatcher)>,
+    /// Whether the lint output panel is shown.
+    pub show_lint_panel: bool,
+    /// Least severe diagnostic level shown in every buffer.
+    pub diagnostic_display_min: DiagnosticSeverity,
+    /// Popup listing the diagnostics on one line, if open.
+    pub diagnostics_popup: Option<DiagnosticsPopup>,
+    /// Ctrl+Tab most-recently-used buffer switcher.
+    pub tab_switcher: TabSwitcher,
+    /// Dropdown listing every buffer, for when the tabs overflow.
+    pub tab_menu: TabMenu,
+    /// Whether Ctrl+Tab shows the MRU switcher popup; when disabled it cycles
+    /// tabs in display order instead.
+    pub tab_switcher_popup: bool,
     /// Whether a document change is waiting to be sent to LSP.
     pub pending_lsp_change: bool,
     /// Timestamp of the last buffered document change.
     pub last_lsp_change: Option<Instant>,
     /// Debounce duration for LSP didChange.
     pub lsp_change_debounce: Duration,
+    /// How much older than the last edit diagnostics may be before they
+    /// are drawn dimmed as out of date.
+    pub stale_diagnostics_after: Duration,
+    /// Active buffer and content version waiting for its change markers to
+    /// be recomputed, and when that version was first seen.
+    line_changes_pending: Option<(BufferId, u64, Instant)>,
+    /// How long the text must stay unchanged before the change markers are
+    /// recomputed.
+    pub line_changes_debounce: Duration,
+    /// Whether change markers ignore changes that are only in whitespace.
+    pub diff_ignore_whitespace: bool,
     /// Performance metrics.
     pub perf_metrics: PerfMetrics,
     /// Whether to show performance metrics in status bar.
     pub show_perf_metrics: bool,
     /// Frame start time for measuring frame duration.
     frame_start: Option<Instant>,
+    /// File the process waits on (`--wait`), if any.
+    pub wait: Option<WaitTarget>,
+    /// Distraction-free mode settings and state.
+    pub zen: ZenMode,
+    /// Width of the window, which the text is centered in.
+    pub viewport_width: f32,
+    /// Widest the text gets, in characters. In a wider window the gutter
+    /// and the text are centered with padding on both sides; `None` lets
+    /// the text span the whole window.
+    pub max_content_width: Option<usize>,
 }
 
 impl EditorApp {
@@ -128,32 +405,74 @@ impl EditorApp {
             workspace,
             input_handler: InputHandler::new(),
             font_size,
-            line_number_margin: 60.0,
-            cursor_visible: true,
-            last_cursor_blink: Instant::now(),
-            cursor_blink_enabled: true,
-            pending_action: None,
-            dialog_open: false,
+            default_font_size: font_size,
+            ui_font_size: DEFAULT_UI_FONT_SIZE,
+            chrome: ChromeMetrics::from_atlas(&GlyphAtlas::new(DEFAULT_UI_FONT_SIZE)),
+            gutter: GutterConfig::default(),
+            cursor_blink: CursorBlink::new(Instant::now()),
             input_mode: InputMode::Normal,
-            search_text: String::new(),
-            replace_text: String::new(),
-            goto_text: String::new(),
-            rename_text: String::new(),
+            search_text: TextField::new(),
+            replace_text: TextField::new(),
+            replace_walk: None,
+            // Only what line:col input is made of
+            goto_text: TextField::with_filter(|c| c.is_ascii_digit() || c == ':' || c == '-'),
+            goto_error: None,
+            // Only identifier characters
+            rename_text: TextField::with_filter(|c| c.is_alphanumeric() || c == '_'),
+            filter_text: TextField::new(),
+            project_search_text: TextField::new(),
+            project_search: ProjectSearch::new(),
+            project_replace_text: TextField::new(),
+            recent_text: TextField::new(),
+            recent_matches: Vec::new(),
+            recent_selected: 0,
+            encoding_text: TextField::new(),
+            encoding_matches: Vec::new(),
+            encoding_selected: 0,
+            problems: ProblemsPanel::new(),
+            problems_text: TextField::new(),
             focused_field: 0,
             lsp_manager: LspManager::new(),
+            trust: TrustStore::default(),
             hover_mouse_pos: None,
             hover_request_time: None,
             hover_pending: false,
+            hover_anchor: None,
             completion_visible: false,
             completion_selected: 0,
-            completion_trigger_pos: None,
+            completion_anchor: None,
+            signature_anchor: None,
+            code_action_anchor: None,
+            code_actions: None,
             notifications: NotificationManager::new(),
+            cursor_clipboard: None,
+            save_queue: SaveQueue::new(),
+            disk_checked_at: Instant::now(),
+            linters: default_linters(),
+            lint_runner: CommandRunner::new(),
+            save_before_run: SaveBeforeRun::default(),
+            lint_output: Vec::new(),
+            lint_target: None,
+            show_lint_panel: false,
+            diagnostic_display_min: DiagnosticSeverity::Hint,
+            diagnostics_popup: None,
+            tab_switcher: TabSwitcher::new(),
+            tab_menu: TabMenu::new(),
+            tab_switcher_popup: true,
             pending_lsp_change: false,
             last_lsp_change: None,
             lsp_change_debounce: Duration::from_millis(40),
+            stale_diagnostics_after: Duration::from_secs(2),
+            line_changes_pending: None,
+            line_changes_debounce: Duration::from_millis(300),
+            diff_ignore_whitespace: false,
             perf_metrics: PerfMetrics::new(),
             show_perf_metrics: false,
             frame_start: None,
+            wait: None,
+            zen: ZenMode::new(),
+            viewport_width: 0.0,
+            max_content_width: None,
         }
     }
 
@@ -197,23 +516,37 @@ impl EditorApp {
         for event in events {
             self.handle_lsp_event(event);
         }
+        for (_, editor) in self.workspace.editors_mut() {
+            editor.update_diagnostics_staleness(self.stale_diagnostics_after);
+        }
     }
 
     /// Handles an LSP event.
     fn handle_lsp_event(&mut self, event: LspEvent) {
         match event {
-            LspEvent::Diagnostics { path, diagnostics } => {
+            LspEvent::Diagnostics { path, mut diagnostics } => {
+                let encoding = self.lsp_encoding(&path);
                 // Find the editor for this path and set diagnostics
                 if let Some((_id, editor)) = self.workspace.editors_mut().find(|(_, e)| {
                     e.file_path() == Some(path.as_path())
                 }) {
+                    for d in &mut diagnostics {
+                        d.start_col = decode_lsp_col(encoding, editor.buffer(), d.start_line, d.start_col);
+                        d.end_col = decode_lsp_col(encoding, editor.buffer(), d.end_line, d.end_col);
+                    }
+                    editor.set_diagnostic_display_min(self.diagnostic_display_min);
                     editor.set_diagnostics(diagnostics);
+                    editor.mark_diagnostics_published();
                     log::debug!("Updated diagnostics for {:?}", path);
                 }
             }
             LspEvent::Hover { path, info } => {
-                // Find the editor for this path and set hover info
+                // Find the editor for this path and set hover info, unless
+                // the hover was dismissed while waiting
                 self.hover_pending = false;
+                if self.hover_anchor.is_none() {
+                    return;
+                }
                 if let Some((_, editor)) = self.workspace.editors_mut().find(|(_, e)| {
                     e.file_path() == Some(path.as_path())
                 }) {
@@ -221,82 +554,57 @@ impl EditorApp {
                 }
             }
             LspEvent::Completion { path, items } => {
+                // Text typed while waiting; none without a trigger position
+                let prefix = self.completion_prefix();
                 // Find the editor for this path and set completions
                 if let Some((_, editor)) = self.workspace.editors_mut().find(|(_, e)| {
                     e.file_path() == Some(path.as_path())
                 }) {
                     let has_items = !items.is_empty();
                     editor.set_completions(items);
-                    // Show completion popup if we have items
-                    if has_items {
-                        self.completion_visible = true;
-                        self.completion_selected = 0;
-                    } else {
-                        self.completion_visible = false;
+                    // Without a trigger position the list hangs off the cursor
+                    if has_items && self.completion_anchor.is_none() {
+                        let pos = editor.cursor_position();
+                        self.completion_anchor = Some(PopupAnchor::new(editor.buffer(), pos.line, pos.col));
                     }
+                    let matched = editor.filter_completions(prefix.as_deref().unwrap_or(""));
+                    // Show completion popup if we have items
+                    self.completion_visible = matched > 0;
+                    self.completion_selected = 0;
+                }
+            }
+            LspEvent::SignatureHelp { path, help } => {
+                // Unless it was dismissed while waiting; no help means the
+                // cursor isn't in a call
+                if self.signature_anchor.is_none() {
+                    return;
+                }
+                if help.is_none() {
+                    self.hide_signature_help();
+                    return;
+                }
+                if let Some((_, editor)) = self.workspace.editors_mut().find(|(_, e)| {
+                    e.file_path() == Some(path.as_path())
+                }) {
+                    editor.set_signature_help(help);
                 }
             }
             LspEvent::GotoDefinition { path: _, locations } => {
                 // Jump to the first location
                 if let Some((def_path, line, col)) = locations.into_iter().next() {
+                    let encoding = self.lsp_encoding(&def_path);
                     // Open the file and go to the location
                     if let Ok(id) = self.workspace.open_file(&def_path) {
                         self.workspace.set_active(id);
                         if let Some(editor) = self.workspace.active_editor_mut() {
+                            let col = decode_lsp_col(encoding, editor.buffer(), line, col);
                             editor.go_to_line_col(line + 1, col + 1);
                         }
                     }
                 }
             }
             LspEvent::Rename { edits } => {
-                // Apply workspace edits from rename
-                let mut total_edits = 0;
-                let mut files_changed = 0;
-
-                // Store original active buffer to restore later
-                let original_active = self.workspace.active_buffer_id();
-
-                for (path, file_edits) in edits {
-                    // First, find if file is already open (separate scope to release borrow)
-                    let existing_id = {
-                        self.workspace.editors()
-                            .find(|(_, e)| e.file_path() == Some(path.as_path()))
-                            .map(|(id, _)| id)
-                    };
-
-                    // Open or use existing
-                    let editor_id = if let Some(id) = existing_id {
-                        Some(id)
-                    } else if let Ok(id) = self.workspace.open_file(&path) {
-                        Some(id)
-                    } else {
-                        log::error!("Failed to open file for rename: {:?}", path);
-                        None
-                    };
-
-                    if let Some(id) = editor_id {
-                        // Set this buffer as active to get mutable access
-                        self.workspace.set_active(id);
-                        if let Some(editor) = self.workspace.active_editor_mut() {
-                            // Apply edits in reverse order to preserve positions
-                            let mut sorted_edits = file_edits;
-                            sorted_edits.sort_by(|a, b| {
-                                (b.0, b.1).cmp(&(a.0, a.1))
-                            });
-                            for (start_line, start_col, end_line, end_col, new_text) in sorted_edits {
-                                editor.replace_range(start_line, start_col, end_line, end_col, &new_text);
-                                total_edits += 1;
-                            }
-                            files_changed += 1;
-                        }
-                    }
-                }
-
-                // Restore original active buffer
-                if let Some(id) = original_active {
-                    self.workspace.set_active(id);
-                }
-
+                let (total_edits, files_changed) = self.apply_workspace_edits(edits);
                 if total_edits > 0 {
                     self.notifications.success(format!(
                         "Renamed: {} occurrences in {} file(s)",
@@ -304,6 +612,30 @@ impl EditorApp {
                     ));
                 }
             }
+            LspEvent::CodeActions { path, actions } => {
+                // Unless the menu was dismissed while waiting
+                if self.code_action_anchor.is_none()
+                    || self.workspace.active_editor().and_then(Editor::file_path) != Some(path.as_path())
+                {
+                    return;
+                }
+                self.code_actions = CodeActionMenu::open(path, actions);
+                if self.code_actions.is_none() {
+                    self.code_action_anchor = None;
+                    self.notifications.info("No code actions available");
+                }
+            }
+            LspEvent::ApplyEdit { label, edits } => {
+                let (total_edits, files_changed) = self.apply_workspace_edits(edits);
+                if total_edits > 0 {
+                    self.notifications.info(format!(
+                        "{}: {} edit(s) in {} file(s)",
+                        label.as_deref().unwrap_or("Applied edit"),
+                        total_edits,
+                        files_changed
+                    ));
+                }
+            }
             LspEvent::ServerReady { language } => {
                 log::info!("LSP server ready for {}", language);
             }
@@ -313,489 +645,2809 @@ impl EditorApp {
         }
     }
 
+    /// Applies per-file edits from the language server, opening files that
+    /// aren't open yet. Returns (edits applied, files changed).
+    fn apply_workspace_edits(&mut self, edits: Vec<FileEdits>) -> (usize, usize) {
+        let mut total_edits = 0;
+        let mut files_changed = 0;
+
+        // Opening a file makes it active; restore the original afterwards
+        let original_active = self.workspace.active_buffer_id();
+
+        for (path, file_edits) in edits {
+            let encoding = self.lsp_encoding(&path);
+            // First, find if file is already open (separate scope to release borrow)
+            let existing_id = {
+                self.workspace.editors()
+                    .find(|(_, e)| e.file_path() == Some(path.as_path()))
+                    .map(|(id, _)| id)
+            };
+
+            // Open or use existing
+            let editor_id = if let Some(id) = existing_id {
+                Some(id)
+            } else if let Ok(id) = self.workspace.open_file(&path) {
+                Some(id)
+            } else {
+                log::error!("Failed to open file for edit: {:?}", path);
+                None
+            };
+
+            if let Some(id) = editor_id {
+                if let Some(editor) = self.workspace.get_buffer_mut(id) {
+                    // Apply edits in reverse order to preserve positions
+                    let mut sorted_edits = file_edits;
+                    sorted_edits.sort_by(|a, b| {
+                        (b.0, b.1).cmp(&(a.0, a.1))
+                    });
+                    for (start_line, start_col, end_line, end_col, new_text) in sorted_edits {
+                        let start_col = decode_lsp_col(encoding, editor.buffer(), start_line, start_col);
+                        let end_col = decode_lsp_col(encoding, editor.buffer(), end_line, end_col);
+                        editor.replace_range(start_line, start_col, end_line, end_col, &new_text);
+                        total_edits += 1;
+                    }
+                    files_changed += 1;
+                }
+            }
+        }
+
+        // Restore original active buffer
+        if let Some(id) = original_active {
+            self.workspace.set_active(id);
+        }
+
+        (total_edits, files_changed)
+    }
+
     /// Notifies LSP that the active document changed.
     pub fn notify_lsp_document_change(&mut self) {
         self.pending_lsp_change = true;
         self.last_lsp_change = Some(Instant::now());
+        let threshold = self.stale_diagnostics_after;
+        if let Some(editor) = self.workspace.active_editor_mut() {
+            editor.update_diagnostics_staleness(threshold);
+        }
     }
 
-    /// Notifies LSP that a file was opened.
-    pub fn notify_lsp_file_opened(&mut self) {
-        if let Some(editor) = self.workspace.active_editor() {
-            if let Some(path) = editor.file_path() {
-                // Set workspace root if not already set (use parent directory of opened file)
-                if self.lsp_manager.workspace_root().is_none() {
-                    if let Some(parent) = path.parent() {
-                        // Try to find a project root (Cargo.toml, package.json, .git, etc.)
-                        let workspace_root = find_project_root(parent).unwrap_or_else(|| parent.to_path_buf());
-                        self.lsp_manager.set_workspace_root(Some(workspace_root));
+    /// Sends the LSP didOpen/didClose notifications for files the workspace
+    /// opened, closed or saved under a new name since the last call.
+    ///
+    /// This is the only place documents are opened and closed on the
+    /// language servers, so every workspace path that changes which files
+    /// are open is covered by the events it emits.
+    pub fn handle_workspace_events(&mut self) {
+        for event in self.workspace.take_events() {
+            match event {
+                WorkspaceEvent::BufferOpened { id, path } => {
+                    self.warn_bidi_controls(id, &path);
+                    self.report_mixed_indentation(id, &path);
+                    self.lsp_open_document(id, &path);
+                }
+                WorkspaceEvent::BufferRetargeted { id, old_path, new_path } => {
+                    self.lsp_close_document(&old_path);
+                    self.lsp_open_document(id, &new_path);
+                }
+                WorkspaceEvent::BufferClosed { path, .. } => {
+                    self.lsp_close_document(&path);
+                    if let Some(wait) = self.wait.as_mut().filter(|wait| wait.path == path) {
+                        wait.closed = true;
                     }
                 }
-
-                if let Some(lang) = language_id_from_path(path) {
-                    let text = editor.buffer().to_string();
-                    let path = path.to_path_buf();
-                    self.lsp_manager.did_open(&path, lang, &text);
+                WorkspaceEvent::BufferSaved { path, .. } => {
+                    if let Some(wait) = self.wait.as_mut().filter(|wait| wait.path == path) {
+                        wait.saved = true;
+                    }
                 }
             }
         }
     }
 
-    /// Notifies LSP that a file was saved.
-    pub fn notify_lsp_file_saved(&mut self) {
-        if let Some(editor) = self.workspace.active_editor() {
-            if let Some(path) = editor.file_path() {
-                if let Some(lang) = language_id_from_path(path) {
-                    let path = path.to_path_buf();
-                    self.lsp_manager.did_save(&path, lang);
-                }
-            }
+    /// Warns if an opened file contains bidi control characters, which can
+    /// make its text display differently from what it is.
+    fn warn_bidi_controls(&mut self, id: BufferId, path: &Path) {
+        let Some(editor) = self.workspace.get_buffer(id) else {
+            return;
+        };
+        let count = editor.invisible_counts().bidi;
+        if count > 0 {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("File");
+            self.notifications.warning(format!(
+                "{} has {} bidi control characters (Alt+B strips, Alt+Shift+B escapes)",
+                name, count
+            ));
         }
     }
 
-    /// Notifies LSP that a file was closed.
-    pub fn notify_lsp_file_closed(&mut self, path: &PathBuf) {
-        if let Some(lang) = language_id_from_path(path) {
-            self.flush_pending_lsp_changes(true);
-            self.lsp_manager.did_close(path, lang);
+    /// Says which indentation an opened file that mixes tabs and spaces
+    /// was taken to use.
+    fn report_mixed_indentation(&mut self, id: BufferId, path: &Path) {
+        let Some(editor) = self.workspace.get_buffer(id).filter(|e| e.has_mixed_indentation()) else {
+            return;
+        };
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("File");
+        let label = editor.indent_config().label();
+        self.notifications.info(format!("{} mixes tabs and spaces; detected {}", name, label));
+    }
+
+    /// Makes the process wait on the active buffer's file (`--wait`).
+    /// Returns false if the active buffer has no file.
+    pub fn wait_for_active_buffer(&mut self) -> bool {
+        let Some(path) = self.workspace.active_editor().and_then(Editor::file_path) else {
+            return false;
+        };
+        self.wait = Some(WaitTarget {
+            path: path.to_path_buf(),
+            saved: false,
+            closed: false,
+        });
+        true
+    }
+
+    /// Returns true once the waited-on buffer is closed and nothing else is
+    /// left to save, so the process can exit.
+    pub fn wait_finished(&self) -> bool {
+        self.wait.as_ref().is_some_and(|wait| wait.closed)
+            && !self.save_queue.is_busy()
+            && !self.workspace.has_unsaved_changes()
+    }
+
+    /// Returns the process exit code: 1 if the waited-on file was never
+    /// saved (git takes that as an aborted commit), otherwise 0.
+    pub fn exit_code(&self) -> i32 {
+        match &self.wait {
+            Some(wait) if !wait.saved => 1,
+            _ => 0,
         }
     }
 
-    /// Flushes any buffered didChange to LSP (debounced unless forced).
-    pub fn flush_pending_lsp_changes(&mut self, force: bool) {
-        if !self.pending_lsp_change {
-            return;
+    /// Opens the buffer `id`, backed by `path`, on its language server.
+    fn lsp_open_document(&mut self, id: BufferId, path: &Path) {
+        // Set workspace root if not already set (use parent directory of opened file)
+        if self.lsp_manager.workspace_root().is_none() {
+            if let Some(parent) = path.parent() {
+                // Try to find a project root (Cargo.toml, package.json, .git, etc.)
+                let workspace_root = find_project_root(parent).unwrap_or_else(|| parent.to_path_buf());
+                self.lsp_manager.set_workspace_trusted(self.trust.is_trusted(&workspace_root));
+                self.lsp_manager.set_workspace_root(Some(workspace_root));
+            }
         }
 
-        if !force {
-            if let Some(last) = self.last_lsp_change {
-                if last.elapsed() < self.lsp_change_debounce {
-                    return;
-                }
-            } else {
-                return;
+        // A large file is opened on the server once all of it loaded
+        let editor = self.workspace.get_buffer_mut(id).filter(|e| e.is_fully_loaded());
+        if let (Some(lang), Some(editor)) = (language_id_from_path(path), editor) {
+            let text = editor.buffer().to_string();
+            editor.mark_change_sent();
+            self.lsp_manager.did_open(path, lang, &text);
+        }
+
+        // The first server held back asks for trust
+        if self.lsp_manager.take_trust_request() {
+            if let Some(root) = self.lsp_manager.workspace_root() {
+                let message = format!(
+                    "Language servers are off in untrusted folder {}; run \"Trust Workspace\" to start them",
+                    root.display()
+                );
+                self.notifications.warning(message);
             }
         }
+    }
 
-        if let Some(editor) = self.workspace.active_editor_mut() {
-            if let Some(path) = editor.file_path().map(|p| p.to_path_buf()) {
-                if let Some(lang) = language_id_from_path(&path) {
-                    let text = editor.buffer().to_string();
-                    editor.increment_document_version();
-                    let version = editor.document_version();
-                    self.lsp_manager.did_change(&path, lang, version, &text);
-                }
+    /// Trusts the workspace root to run language servers, starting the
+    /// ones held back and opening the open files on them.
+    pub fn trust_workspace(&mut self) {
+        let Some(root) = self.lsp_manager.workspace_root().map(Path::to_path_buf) else {
+            self.notifications.info("No workspace yet: open a file in it first");
+            return;
+        };
+        self.trust.trust(&root);
+        self.lsp_manager.set_workspace_trusted(true);
+        for tab in self.workspace.tabs() {
+            if let Some(path) = tab.path {
+                self.lsp_open_document(tab.id, &path);
             }
         }
+        self.notifications.success(format!("Trusted {}", root.display()));
+    }
 
-        self.pending_lsp_change = false;
+    /// Closes `path` on its language server.
+    fn lsp_close_document(&mut self, path: &Path) {
+        if let Some(lang) = language_id_from_path(path) {
+            self.lsp_manager.did_close(path, lang);
+        }
     }
 
-    /// Requests hover info from LSP at the current cursor position.
-    pub fn request_hover(&mut self) {
-        if let Some(editor) = self.workspace.active_editor() {
-            if let Some(path) = editor.file_path() {
-                if let Some(lang) = language_id_from_path(path) {
-                    let pos = editor.cursor_position();
-                    let path = path.to_path_buf();
-                    self.lsp_manager.hover(&path, lang, pos.line, pos.col);
-                }
-            }
+    /// Notifies LSP that a file was saved.
+    pub fn notify_lsp_file_saved(&mut self) {
+        if let Some(path) = self.workspace.active_editor().and_then(|e| e.file_path()) {
+            let path = path.to_path_buf();
+            self.notify_lsp_path_saved(&path);
         }
     }
 
-    /// Requests completions from LSP at the current cursor position.
-    pub fn request_completions(&mut self) {
-        if let Some(editor) = self.workspace.active_editor() {
-            if let Some(path) = editor.file_path() {
-                if let Some(lang) = language_id_from_path(path) {
-                    let pos = editor.cursor_position();
-                    let path = path.to_path_buf();
-                    self.lsp_manager.completion(&path, lang, pos.line, pos.col);
-                }
-            }
+    /// Notifies LSP that the file at `path` was saved.
+    fn notify_lsp_path_saved(&mut self, path: &Path) {
+        if let Some(lang) = language_id_from_path(path) {
+            self.lsp_manager.did_save(path, lang);
         }
     }
 
-    /// Requests go to definition from LSP at the current cursor position.
-    pub fn request_goto_definition(&mut self) {
-        if let Some(editor) = self.workspace.active_editor() {
-            if let Some(path) = editor.file_path() {
-                if let Some(lang) = language_id_from_path(path) {
-                    let pos = editor.cursor_position();
-                    let path = path.to_path_buf();
-                    self.lsp_manager.goto_definition(&path, lang, pos.line, pos.col);
+    /// Starts saving a buffer in the background.
+    ///
+    /// Returns an `Other` error if the buffer has no file path (the caller
+    /// should ask for one). Does nothing if a save of the buffer is already
+    /// running, and only warns if its encoding can't represent some of its
+    /// characters.
+    pub fn save_in_background(&mut self, id: BufferId) -> std::io::Result<()> {
+        if self.save_queue.is_saving(id) {
+            self.notifications.info("Save already in progress");
+            return Ok(());
+        }
+        if let Some(Err(e)) = self.workspace.get_buffer(id).map(Editor::check_encodable) {
+            self.notifications.warning(format!("{}; written as '?'", e));
+        }
+        if let Some(editor) = self.workspace.get_buffer_mut(id).filter(|e| e.file_path().is_some() && e.is_fully_loaded()) {
+            let cleanup = editor.clean_up_for_save();
+            if let Some(message) = describe_save_cleanup(cleanup) {
+                self.notifications.info(message);
+                if self.workspace.active_buffer_id() == Some(id) {
+                    self.notify_lsp_document_change();
                 }
             }
         }
+        self.flush_pending_lsp_changes(true);
+        let job = self.workspace.save_job(id)?;
+        self.save_queue.submit(job);
+        Ok(())
     }
 
-    /// Updates hover state based on mouse position.
-    /// Call this when the mouse moves to potentially trigger a hover request.
-    pub fn update_hover(&mut self, screen_x: f32, screen_y: f32, char_width: f32, line_height: f32) {
-        // Delay before showing hover (500ms)
-        const HOVER_DELAY_MS: u64 = 500;
-
-        let (line, col) = self.screen_to_buffer_position(screen_x, screen_y, char_width, line_height);
-
-        // Check if we moved to a different position
-        let should_clear = self.hover_mouse_pos.map(|(prev_x, prev_y)| {
-            let (prev_line, prev_col) = self.screen_to_buffer_position(prev_x, prev_y, char_width, line_height);
-            prev_line != line || prev_col != col
-        }).unwrap_or(true);
+    /// Returns true if the buffer has a save in flight.
+    pub fn is_saving(&self, id: BufferId) -> bool {
+        self.save_queue.is_saving(id)
+    }
 
-        if should_clear {
-            // Clear existing hover info if we moved
-            if let Some(editor) = self.workspace.active_editor_mut() {
-                editor.clear_hover_info();
-            }
-            self.hover_mouse_pos = Some((screen_x, screen_y));
-            self.hover_request_time = Some(Instant::now());
-            self.hover_pending = false;
+    /// Appends the text of large files loaded since the last call, showing
+    /// how far along they are. Returns true while any is loading.
+    pub fn poll_loading(&mut self) -> bool {
+        // Runs every frame; most of the time nothing is loading
+        let workspace = &self.workspace;
+        if !workspace.tab_labels().any(|tab| workspace.get_buffer(tab.id).is_some_and(|e| e.is_loading())) {
+            return false;
         }
-
-        // Check if we should trigger a hover request
-        if !self.hover_pending {
-            if let Some(request_time) = self.hover_request_time {
-                if request_time.elapsed() >= Duration::from_millis(HOVER_DELAY_MS) {
-                    // Send hover request at this position
-                    if let Some(editor) = self.workspace.active_editor() {
-                        if let Some(path) = editor.file_path() {
-                            if let Some(lang) = language_id_from_path(path) {
-                                let path = path.to_path_buf();
-                                self.lsp_manager.hover(&path, lang, line, col);
-                                self.hover_pending = true;
-                            }
-                        }
+        let mut loading = false;
+        for tab in self.workspace.tabs() {
+            let Some(editor) = self.workspace.get_buffer_mut(tab.id) else {
+                continue;
+            };
+            if !editor.is_loading() {
+                continue;
+            }
+            let result = editor.poll_loading();
+            let progress = editor.loading_progress();
+            let loaded = editor.is_fully_loaded();
+            let prefix = format!("Loading {}", tab.name);
+            match result {
+                // Loading stopped with part of the file open, which stays
+                // until the error is dismissed
+                Err(e) => self.notifications.replace_sticky(
+                    &prefix,
+                    format!("Failed to load {}: {}; only its start is open", tab.name, e),
+                    NotificationType::Error,
+                ),
+                Ok(_) if loaded => {
+                    self.notifications
+                        .replace(&prefix, format!("Loaded {}", tab.name), NotificationType::Success);
+                    self.perf_metrics.startup.record_file_loaded();
+                    if let Some(path) = tab.path {
+                        self.lsp_open_document(tab.id, &path);
                     }
                 }
+                Ok(_) => {
+                    let percent = (progress.unwrap_or(0.0) * 100.0) as u32;
+                    let message = format!("{}\u{2026} {}%", prefix, percent);
+                    self.notifications.replace(&prefix, message, NotificationType::Info);
+                    loading = true;
+                }
             }
         }
+        loading
     }
 
-    /// Clears the hover state.
-    pub fn clear_hover(&mut self) {
-        self.hover_mouse_pos = None;
-        self.hover_request_time = None;
-        self.hover_pending = false;
-        if let Some(editor) = self.workspace.active_editor_mut() {
-            editor.clear_hover_info();
+    /// Applies finished background saves. Returns true if a redraw is needed.
+    pub fn poll_saves(&mut self) -> bool {
+        let finished = self.save_queue.poll();
+        let changed = !finished.is_empty();
+        for result in finished {
+            self.apply_save_result(result);
         }
+        changed
     }
 
-    /// Triggers auto-completion at the current cursor position.
-    pub fn trigger_completion(&mut self) {
-        if let Some(editor) = self.workspace.active_editor() {
-            if let Some(path) = editor.file_path() {
-                if let Some(lang) = language_id_from_path(path) {
-                    let pos = editor.cursor_position();
-                    let path = path.to_path_buf();
-                    self.completion_trigger_pos = Some((pos.line, pos.col));
-                    self.lsp_manager.completion(&path, lang, pos.line, pos.col);
-                }
+    /// Checks the open files for changes made outside the editor. A file
+    /// changed under unsaved edits raises the conflict banner over its
+    /// buffer; an unmodified buffer just reloads. Buffers with a save in
+    /// flight are skipped, since the change is likely that save's.
+    /// Returns true if any buffer changed.
+    pub fn check_disk_changes(&mut self) -> bool {
+        self.disk_checked_at = Instant::now();
+        let active = self.workspace.active_buffer_id();
+        let mut changed = false;
+        for tab in self.workspace.tabs() {
+            if tab.path.is_none() || self.save_queue.is_saving(tab.id) {
+                continue;
+            }
+            let Some(editor) = self.workspace.get_buffer_mut(tab.id) else {
+                continue;
+            };
+            if editor.has_disk_conflict() || !editor.is_fully_loaded() || !editor.disk_changed() {
+                continue;
+            }
+            changed = true;
+            if editor.is_modified() {
+                editor.raise_disk_conflict();
+            } else if let Err(e) = editor.reload_from_disk() {
+                self.notifications.error(format!("Failed to reload {}: {}", tab.name, e));
+            } else if Some(tab.id) == active {
+                self.notify_lsp_document_change();
             }
         }
+        changed
     }
 
-    /// Moves to the next completion item.
-    pub fn completion_next(&mut self) {
-        if let Some(editor) = self.workspace.active_editor() {
-            let count = editor.completions().len();
-            if count > 0 {
-                self.completion_selected = (self.completion_selected + 1) % count;
-            }
+    /// Returns the height of the conflict banner over the active buffer:
+    /// one row while its file has changed on disk under unsaved edits.
+    pub fn disk_banner_height(&self) -> f32 {
+        if self.workspace.active_editor().is_some_and(Editor::has_disk_conflict) {
+            self.chrome.disk_banner_height()
+        } else {
+            0.0
         }
     }
 
-    /// Moves to the previous completion item.
-    pub fn completion_prev(&mut self) {
-        if let Some(editor) = self.workspace.active_editor() {
-            let count = editor.completions().len();
-            if count > 0 {
-                if self.completion_selected == 0 {
-                    self.completion_selected = count - 1;
-                } else {
-                    self.completion_selected -= 1;
-                }
+    /// Returns whether `(x, y)` is on the conflict banner, which spans the
+    /// focused pane.
+    pub fn is_in_disk_banner(&self, x: f32, y: f32) -> bool {
+        let top = self.content_y_offset() - self.disk_banner_height();
+        let (left, width) = self.pane_area(self.viewport_width);
+        (top..self.content_y_offset()).contains(&y) && (left..left + width).contains(&x)
+    }
+
+    /// Returns where the conflict banner's text starts.
+    fn disk_banner_text_left(&self) -> f32 {
+        self.pane_area(self.viewport_width).0 + 8.0
+    }
+
+    /// Handles a click at `x` on the conflict banner, running the action
+    /// under it. Returns whether there was one.
+    pub fn handle_disk_banner_click(&mut self, x: f32) -> bool {
+        match disk_banner::action_at(x, self.disk_banner_text_left(), self.chrome.char_width) {
+            Some(action) => {
+                self.handle_disk_banner_action(action);
+                true
             }
+            None => false,
         }
     }
 
-    /// Accepts the currently selected completion.
-    pub fn accept_completion(&mut self) {
-        if !self.completion_visible {
+    /// Resolves the conflict between the active buffer and its changed file.
+    pub fn handle_disk_banner_action(&mut self, action: BannerAction) {
+        let Some(editor) = self.workspace.active_editor_mut() else {
             return;
-        }
-
-        let insert_text = if let Some(editor) = self.workspace.active_editor() {
-            let completions = editor.completions();
-            if self.completion_selected < completions.len() {
-                let item = &completions[self.completion_selected];
-                Some(item.insert_text.clone().unwrap_or_else(|| item.label.clone()))
-            } else {
-                None
-            }
-        } else {
-            None
         };
-
-        if let Some(text) = insert_text {
-            // Delete from trigger position to current position, then insert
-            if let Some((trigger_line, trigger_col)) = self.completion_trigger_pos {
-                if let Some(editor) = self.workspace.active_editor_mut() {
-                    let pos = editor.cursor_position();
-                    // Only insert if we're on the same line
-                    if pos.line == trigger_line && pos.col >= trigger_col {
-                        // Delete the partial text typed so far
-                        for _ in trigger_col..pos.col {
-                            editor.delete_backward();
-                        }
-                        // Insert the completion text
-                        editor.insert_text(&text);
+        let Some(path) = editor.file_path().map(Path::to_path_buf) else {
+            return;
+        };
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        match action {
+            BannerAction::Compare => {
+                let on_disk = match TextBuffer::from_file(&path) {
+                    Ok(buffer) => buffer.to_string(),
+                    Err(e) => {
+                        self.notifications.error(format!("Failed to read {}: {}", name, e));
+                        return;
                     }
+                };
+                let buffer_name = format!("{} (unsaved)", name);
+                let disk_name = format!("{} (on disk)", name);
+                let diff = unified_diff(&editor.buffer().to_string(), &on_disk, &buffer_name, &disk_name, 3);
+                if diff.is_empty() {
+                    self.notifications.info(format!("{} on disk matches the buffer", name));
+                    return;
+                }
+                self.flush_pending_lsp_changes(true);
+                let id = self.workspace.new_buffer();
+                if let Some(editor) = self.workspace.get_buffer_mut(id) {
+                    editor.set_buffer(TextBuffer::from_str(&diff));
                 }
+                self.workspace.set_active_buffer(id);
             }
+            BannerAction::Reload => match editor.reload_from_disk() {
+                Ok(()) => {
+                    self.notify_lsp_document_change();
+                    self.notifications.info(format!("Reloaded {} (undo restores your edits)", name));
+                }
+                Err(e) => self.notifications.error(format!("Failed to reload {}: {}", name, e)),
+            },
+            BannerAction::Keep => editor.keep_local_changes(),
         }
-
-        self.hide_completion();
     }
 
-    /// Hides the completion popup.
-    pub fn hide_completion(&mut self) {
-        self.completion_visible = false;
-        self.completion_selected = 0;
-        self.completion_trigger_pos = None;
-        if let Some(editor) = self.workspace.active_editor_mut() {
-            editor.clear_completions();
+    /// Waits for in-flight saves before quitting, so no write is cut short.
+    pub fn finish_pending_saves(&mut self) {
+        for result in self.save_queue.wait_idle(SAVE_SHUTDOWN_TIMEOUT) {
+            self.apply_save_result(result);
         }
     }
 
-    /// Opens the search bar.
-    pub fn open_search(&mut self) {
-        self.input_mode = InputMode::Search;
-        self.focused_field = 0;
-        // Pre-fill with selection if any
-        if let Some(editor) = self.workspace.active_editor() {
-            if let Some(selected) = editor.selected_text() {
-                if !selected.contains('\n') {
-                    self.search_text = selected;
-                }
+    /// Updates the buffer and reports the outcome of a finished save.
+    fn apply_save_result(&mut self, result: cp_editor_core::SaveResult) {
+        let path = result.path.clone();
+        let filename = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("File")
+            .to_string();
+        match self.workspace.finish_save(result) {
+            SaveOutcome::Saved | SaveOutcome::BufferClosed => {
+                self.notify_lsp_path_saved(&path);
+                self.lint_on_save(&path);
+                self.notifications.success(format!("Saved: {}", filename));
+            }
+            SaveOutcome::SavedOlderVersion => {
+                self.notify_lsp_path_saved(&path);
+                self.lint_on_save(&path);
+                self.notifications.info(format!(
+                    "Saved older version of {}; newer changes are unsaved",
+                    filename
+                ));
+            }
+            SaveOutcome::Failed(e) => {
+                log::error!("Failed to save {:?}: {}", path, e);
+                self.notifications.error(format!(
+                    "Failed to save {}: {} (Ctrl+S to retry)",
+                    filename, e
+                ));
             }
         }
-        // Perform search immediately if there's text
-        if !self.search_text.is_empty() {
-            if let Some(editor) = self.workspace.active_editor_mut() {
-                editor.find(&self.search_text);
-            }
+    }
+
+    /// Runs the linter for a saved file if its language has no language
+    /// server to provide diagnostics.
+    fn lint_on_save(&mut self, path: &Path) {
+        let Some(lang) = language_id_from_path(path) else {
+            return;
+        };
+        if self.linters.contains_key(lang) && (!self.lsp_manager.has_client(lang) || self.lsp_manager.is_excluded(path, lang)) {
+            self.run_linter(path);
         }
     }
 
-    /// Opens the replace bar.
-    pub fn open_replace(&mut self) {
-        self.input_mode = InputMode::Replace;
-        self.focused_field = 0;
-        // Pre-fill with selection if any
-        if let Some(editor) = self.workspace.active_editor() {
-            if let Some(selected) = editor.selected_text() {
-                if !selected.contains('\n') {
-                    self.search_text = selected;
-                }
+    /// Saves buffers according to `save_before_run` and flushes pending LSP
+    /// changes before an external command runs. Waits for the saves, since
+    /// the command reads the files from disk.
+    pub fn prepare_run(&mut self) {
+        self.flush_pending_lsp_changes(true);
+        let ids = match self.save_before_run {
+            SaveBeforeRun::Off => Vec::new(),
+            SaveBeforeRun::Active => self
+                .workspace
+                .active_buffer_id()
+                .into_iter()
+                .filter(|&id| self.workspace.get_buffer(id).is_some_and(Editor::is_modified))
+                .collect(),
+            SaveBeforeRun::All => self.workspace.modified_buffers(),
+        };
+        for id in ids {
+            // Untitled buffers can't be saved without a dialog; skip them
+            if self.workspace.get_buffer(id).is_some_and(|e| e.file_path().is_none()) {
+                continue;
             }
-        }
-        // Perform search immediately if there's text
-        if !self.search_text.is_empty() {
-            if let Some(editor) = self.workspace.active_editor_mut() {
-                editor.find(&self.search_text);
+            if let Err(e) = self.save_in_background(id) {
+                log::error!("Failed to save before run: {}", e);
+                self.notifications.error(format!("Failed to save: {}", e));
             }
         }
+        self.finish_pending_saves();
     }
 
-    /// Opens the go to line dialog.
-    pub fn open_goto_line(&mut self) {
-        self.input_mode = InputMode::GoToLine;
-        self.goto_text.clear();
-    }
-
-    /// Opens the rename symbol dialog.
-    pub fn open_rename(&mut self) {
-        // Get the word under cursor to pre-fill the rename text
-        if let Some(editor) = self.workspace.active_editor() {
-            if let Some(word) = editor.word_under_cursor() {
-                self.rename_text = word;
-            } else {
-                self.rename_text.clear();
+    /// Runs the configured linter on `path` in its project root.
+    /// Returns false if no linter is configured for the file's language.
+    pub fn run_linter(&mut self, path: &Path) -> bool {
+        let Some(linter) = language_id_from_path(path).and_then(|lang| self.linters.get(lang)) else {
+            return false;
+        };
+        let cwd = path
+            .parent()
+            .map(|parent| find_project_root(parent).unwrap_or_else(|| parent.to_path_buf()))
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        let cursor = self
+            .workspace
+            .active_editor()
+            .filter(|editor| editor.file_path() == Some(path))
+            .map(Editor::cursor_position);
+        let vars = TemplateVars {
+            file: Some(path.to_path_buf()),
+            workspace_root: Some(cwd.clone()),
+            line: cursor.map(|pos| pos.line + 1),
+            column: cursor.map(|pos| pos.col + 1),
+        };
+        let command = match linter.command_for(&vars) {
+            Ok(command) => command,
+            Err(e) => {
+                self.notifications.error(format!("Linter command: {}", e));
+                return true;
             }
-        }
-        self.input_mode = InputMode::Rename;
+        };
+        let matcher = linter.matcher.clone();
+
+        log::info!("Running linter: {} (in {:?})", command, cwd);
+        self.lint_output.clear();
+        self.lint_target = Some((path.to_path_buf(), matcher));
+        self.lint_runner.start(&command, &cwd);
+        true
     }
 
-    /// Requests rename from LSP.
-    pub fn request_rename(&mut self, new_name: &str) {
-        if let Some(editor) = self.workspace.active_editor() {
-            if let Some(path) = editor.file_path() {
-                if let Some(lang) = language_id_from_path(path) {
-                    let pos = editor.cursor_position();
-                    let path = path.to_path_buf();
-                    self.lsp_manager.rename(&path, lang, pos.line, pos.col, new_name);
+    /// Collects linter output and applies diagnostics when the run ends.
+    /// Returns true if a redraw is needed.
+    pub fn poll_linter(&mut self) -> bool {
+        let events = self.lint_runner.poll();
+        let changed = !events.is_empty();
+        for event in events {
+            match event {
+                CommandEvent::Output(line) => {
+                    if self.lint_output.len() < MAX_LINT_OUTPUT_LINES {
+                        self.lint_output.push(line);
+                    }
+                }
+                CommandEvent::Finished(code) => {
+                    log::debug!("Linter exited with {:?}", code);
+                    self.apply_lint_results();
+                }
+                CommandEvent::Failed(e) => {
+                    self.lint_target = None;
+                    self.notifications.error(format!("Failed to run linter: {}", e));
                 }
             }
         }
-        self.input_mode = InputMode::Normal;
+        changed
     }
 
-    /// Closes the search/replace/goto bar.
-    pub fn close_input_bar(&mut self) {
-        if self.input_mode != InputMode::Normal {
-            self.input_mode = InputMode::Normal;
-            // Clear search highlighting
-            if let Some(editor) = self.workspace.active_editor_mut() {
-                editor.clear_search();
-            }
-        } else {
-            // If already in normal mode, collapse cursors
-            if let Some(editor) = self.workspace.active_editor_mut() {
-                editor.collapse_cursors();
-                editor.exit_block_selection();
+    /// Parses the finished linter's output into diagnostics for open buffers.
+    fn apply_lint_results(&mut self) {
+        let Some((target, matcher)) = self.lint_target.take() else {
+            return;
+        };
+        let cwd = self.lint_runner.cwd().map(Path::to_path_buf).unwrap_or_default();
+        let output: Vec<String> = self.lint_output.iter().map(|line| ansi::strip(line)).collect();
+        let problems = matcher.match_output(output.iter().map(String::as_str));
+        let count = problems.len();
+        let mut files = group_by_file(problems, &cwd);
+        // The linted file's old results are cleared even if it is now clean
+        files.entry(target).or_default();
+
+        for (path, diagnostics) in files {
+            if let Some((_, editor)) = self
+                .workspace
+                .editors_mut()
+                .find(|(_, e)| e.file_path().is_some_and(|p| same_file(p, &path)))
+            {
+                editor.set_diagnostic_display_min(self.diagnostic_display_min);
+                editor.merge_diagnostics(matcher.source(), diagnostics);
             }
         }
+        if count > 0 {
+            self.notifications.warning(format!("{}: {} problem(s)", matcher.source(), count));
+        }
     }
 
-    /// Returns true if in any input mode.
-    pub fn is_input_mode(&self) -> bool {
-        self.input_mode != InputMode::Normal
-    }
+    /// Flushes any buffered didChange to LSP (debounced unless forced).
+    pub fn flush_pending_lsp_changes(&mut self, force: bool) {
+        // A document must be open on the server before its changes are sent
+        self.handle_workspace_events();
+        if !self.pending_lsp_change {
+            return;
+        }
 
-    /// Returns the current content area Y offset (accounting for tab bar and search bar).
-    pub fn content_y_offset(&self) -> f32 {
-        let mut offset = TAB_BAR_HEIGHT;
-        if self.input_mode != InputMode::Normal {
-            offset += SEARCH_BAR_HEIGHT;
+        if !force {
+            if let Some(last) = self.last_lsp_change {
+                if last.elapsed() < self.lsp_change_debounce {
+                    return;
+                }
+            } else {
+                return;
+            }
         }
-        offset
-    }
 
-    /// Opens a file, creating a new tab.
-    pub fn open_file(&mut self, path: PathBuf) {
-        if let Err(e) = self.workspace.open_file(&path) {
-            log::error!("Failed to open file {:?}: {}", path, e);
+        if let Some(editor) = self.workspace.active_editor_mut() {
+            if let Some(path) = editor.file_path().map(|p| p.to_path_buf()) {
+                // Nothing to send if the edits since cancel out
+                if let Some(lang) = language_id_from_path(&path).filter(|_| editor.has_unsent_changes()) {
+                    let text = editor.buffer().to_string();
+                    editor.increment_document_version();
+                    editor.mark_change_sent();
+                    let version = editor.document_version();
+                    self.lsp_manager.did_change(&path, lang, version, &text);
+                }
+            }
         }
+
+        self.pending_lsp_change = false;
     }
 
-    /// Resets the cursor blink state (makes cursor visible and restarts timer).
-    pub fn reset_cursor_blink(&mut self) {
-        self.cursor_visible = true;
-        self.last_cursor_blink = Instant::now();
+    /// Returns the language of the active buffer, if it is a file a
+    /// language server could know.
+    fn active_language(&self) -> Option<&'static str> {
+        self.workspace.active_editor()?.file_path().and_then(language_id_from_path)
     }
 
-    /// Updates the cursor blink state. Returns true if a redraw is needed.
-    pub fn update_cursor_blink(&mut self) -> bool {
-        if !self.cursor_blink_enabled {
+    /// Returns true if the active buffer's file is kept from its language
+    /// server by the `lsp` settings.
+    pub fn active_file_lsp_excluded(&self) -> bool {
+        let Some(path) = self.workspace.active_editor().and_then(Editor::file_path) else {
             return false;
-        }
-
-        let elapsed = self.last_cursor_blink.elapsed();
-        if elapsed >= Duration::from_millis(CURSOR_BLINK_INTERVAL_MS) {
-            self.cursor_visible = !self.cursor_visible;
-            self.last_cursor_blink = Instant::now();
-            true
-        } else {
-            false
-        }
+        };
+        language_id_from_path(path).is_some_and(|lang| self.lsp_manager.is_excluded(path, lang))
     }
 
-    /// Converts screen coordinates to buffer position.
-    pub fn screen_to_buffer_position(
-        &self,
-        x: f32,
-        y: f32,
-        char_width: f32,
-        line_height: f32,
-    ) -> (usize, usize) {
-        // Adjust y for tab bar and search bar
-        let y = y - self.content_y_offset();
-        if y < 0.0 {
-            return (0, 0);
+    /// Lets the active buffer's file reach its language server for the rest
+    /// of the session, although the `lsp` settings exclude it.
+    pub fn enable_lsp_for_active_file(&mut self) {
+        if !self.active_file_lsp_excluded() {
+            self.notifications.info("The language server isn't off for this file");
+            return;
         }
+        let (Some(id), Some(path)) = (
+            self.workspace.active_buffer_id(),
+            self.workspace.active_editor().and_then(Editor::file_path).map(Path::to_path_buf),
+        ) else {
+            return;
+        };
+        self.lsp_manager.reenable(&path);
+        self.lsp_open_document(id, &path);
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        self.notifications.info(format!("Language server enabled for {} until restart", name));
+    }
 
-        if let Some(editor) = self.workspace.active_editor() {
-            let scroll_offset = editor.scroll_offset();
-            let buffer = editor.buffer();
-
-            // Calculate which line was clicked
-            let screen_line = (y / line_height).floor() as usize;
-            let buffer_line = scroll_offset + screen_line;
-            let buffer_line = buffer_line.min(buffer.len_lines().saturating_sub(1));
+    /// Returns the path and language of the active buffer, and its cursor
+    /// position as the language server counts it.
+    fn lsp_cursor_position(&self) -> Option<(PathBuf, &'static str, Position)> {
+        let editor = self.workspace.active_editor()?;
+        let path = editor.file_path()?;
+        // Excluded files are treated like files no server knows
+        let lang = language_id_from_path(path).filter(|lang| !self.lsp_manager.is_excluded(path, lang))?;
+        let pos = editor.cursor_position();
+        Some((path.to_path_buf(), lang, self.lsp_position(lang, pos.line, pos.col)))
+    }
 
-            // Calculate which column was clicked
-            let horizontal_scroll = editor.horizontal_scroll();
-            let text_x = (x - self.line_number_margin).max(0.0);
-            let col = (text_x / char_width).round() as usize + horizontal_scroll;
+    /// Returns (line, col) of the active buffer as a position of the server
+    /// for `language`.
+    fn lsp_position(&self, language: &str, line: usize, col: usize) -> Position {
+        let text = self
+            .workspace
+            .active_editor()
+            .and_then(|editor| editor.buffer().line(line))
+            .unwrap_or_default();
+        self.lsp_manager.position(language, &text, line, col)
+    }
 
-            // Clamp column to line length
-            let line_len = buffer.line_len_chars(buffer_line);
-            let col = col.min(line_len);
+    /// Returns the unit the server for the file at `path` counts columns in.
+    fn lsp_encoding(&self, path: &Path) -> PositionEncoding {
+        language_id_from_path(path)
+            .map(|lang| self.lsp_manager.position_encoding(lang))
+            .unwrap_or_default()
+    }
 
-            (buffer_line, col)
-        } else {
-            (0, 0)
+    /// Returns true if the server for `language` supports `feature`. If it
+    /// runs but doesn't, says so instead of sending a request nothing
+    /// answers.
+    fn lsp_feature_available(&mut self, language: &str, feature: LspFeature) -> bool {
+        if self.lsp_manager.supports(language, feature) {
+            return true;
+        }
+        if self.lsp_manager.has_client(language) {
+            self.notifications
+                .info(format!("{} isn't supported by the {} language server", feature.name(), language));
         }
+        false
     }
 
-    /// Returns whether click is in tab bar area.
-    pub fn is_in_tab_bar(&self, y: f32) -> bool {
-        y < TAB_BAR_HEIGHT
+    /// Requests hover info from LSP at the current cursor position.
+    pub fn request_hover(&mut self) {
+        let Some((path, lang, position)) = self.lsp_cursor_position() else {
+            return;
+        };
+        if !self.lsp_feature_available(lang, LspFeature::Hover) {
+            return;
+        }
+        if let Some(editor) = self.workspace.active_editor() {
+            let pos = editor.cursor_position();
+            self.hover_anchor = Some(HoverAnchor::Text(PopupAnchor::new(editor.buffer(), pos.line, pos.col)));
+        }
+        self.lsp_manager.hover(&path, lang, position);
     }
 
-    /// Returns whether click is in search bar area.
-    pub fn is_in_search_bar(&self, y: f32) -> bool {
-        self.input_mode != InputMode::Normal && y >= TAB_BAR_HEIGHT && y < TAB_BAR_HEIGHT + SEARCH_BAR_HEIGHT
+    /// Requests completions from LSP at the current cursor position.
+    pub fn request_completions(&mut self) {
+        let Some((path, lang, position)) = self.lsp_cursor_position() else {
+            return;
+        };
+        if self.lsp_manager.supports(lang, LspFeature::Completion) {
+            self.lsp_manager.completion(&path, lang, position);
+        }
     }
 
-    /// Handles a click in the tab bar, returns the tab index if clicked on a tab.
-    pub fn handle_tab_bar_click(&self, x: f32, char_width: f32) -> Option<usize> {
-        let tabs = self.workspace.tabs();
-        let mut current_x = 4.0; // Initial padding
-
-        for (index, tab) in tabs.iter().enumerate() {
-            // Calculate tab width based on name length + padding + close button
-            let tab_width = (tab.name.len() as f32 + 4.0) * char_width + 24.0;
-
-            if x >= current_x && x < current_x + tab_width {
-                return Some(index);
+    /// Requests signature help from LSP at the current cursor position.
+    /// The popup stays anchored where it was first requested, so typing
+    /// further arguments updates it in place.
+    pub fn request_signature_help(&mut self) {
+        // The server needs the text with the typed `(` or `,`
+        self.flush_pending_lsp_changes(true);
+        let Some((path, lang, position)) = self.lsp_cursor_position() else {
+            return;
+        };
+        if !self.lsp_manager.supports(lang, LspFeature::SignatureHelp) {
+            return;
+        }
+        if let Some(editor) = self.workspace.active_editor() {
+            if self.signature_anchor.is_none() {
+                let pos = editor.cursor_position();
+                self.signature_anchor = Some(PopupAnchor::new(editor.buffer(), pos.line, pos.col));
             }
+        }
+        self.lsp_manager.signature_help(&path, lang, position);
+    }
 
-            current_x += tab_width + 4.0; // Tab spacing
+    /// Hides the signature help popup.
+    pub fn hide_signature_help(&mut self) {
+        self.signature_anchor = None;
+        if let Some(editor) = self.workspace.active_editor_mut() {
+            editor.clear_signature_help();
         }
+    }
 
-        None
+    /// Requests go to definition from LSP at the current cursor position.
+    pub fn request_goto_definition(&mut self) {
+        let Some((path, lang, position)) = self.lsp_cursor_position() else {
+            return;
+        };
+        if self.lsp_feature_available(lang, LspFeature::GotoDefinition) {
+            self.lsp_manager.goto_definition(&path, lang, position);
+        }
     }
 
-    /// Renders the editor to the GPU renderer.
-    pub fn render(&self, renderer: &mut GpuRenderer) {
-        renderer.clear();
+    /// Updates hover state based on mouse position.
+    /// Call this when the mouse moves to potentially trigger a hover request.
+    pub fn update_hover(&mut self, screen_x: f32, screen_y: f32, char_width: f32, line_height: f32) {
+        // Delay before showing hover (500ms)
+        const HOVER_DELAY_MS: u64 = 500;
 
-        let line_height = renderer.atlas().line_height;
-        let char_width = renderer.atlas().char_width;
-        let (viewport_width, viewport_height) = renderer.dimensions();
-        let content_y = self.content_y_offset();
+        // Only the focused pane has hovers
+        if self.pane_at(screen_x) != self.workspace.focused_side() {
+            self.clear_hover();
+            return;
+        }
 
-        // Draw tab bar background
-        renderer.draw_rect(
-            0.0,
-            0.0,
-            viewport_width as f32,
-            TAB_BAR_HEIGHT,
-            renderer.colors.tab_bar_bg,
-        );
+        let (line, col) = self.screen_to_buffer_position(screen_x, screen_y, char_width, line_height);
 
-        // Draw tabs
-        let tabs = self.workspace.tabs();
-        let active_index = self.workspace.active_tab_index();
-        let mut tab_x = 4.0;
+        // Check if we moved to a different position
+        let should_clear = self.hover_mouse_pos.map(|(prev_x, prev_y)| {
+            let (prev_line, prev_col) = self.screen_to_buffer_position(prev_x, prev_y, char_width, line_height);
+            prev_line != line || prev_col != col
+        }).unwrap_or(true);
 
-        for (index, tab) in tabs.iter().enumerate() {
-            let is_active = Some(index) == active_index;
-            let tab_width = (tab.name.len() as f32 + 4.0) * char_width + 24.0;
+        if should_clear {
+            // Clear existing hover info if we moved
+            if let Some(editor) = self.workspace.active_editor_mut() {
+                editor.clear_hover_info();
+            }
+            self.hover_mouse_pos = Some((screen_x, screen_y));
+            self.hover_request_time = Some(Instant::now());
+            self.hover_pending = false;
+            self.hover_anchor = None;
+        }
+
+        // Check if we should trigger a hover request
+        if !self.hover_pending {
+            if let Some(request_time) = self.hover_request_time {
+                if request_time.elapsed() >= Duration::from_millis(HOVER_DELAY_MS) {
+                    // Send hover request at this position
+                    if let Some(editor) = self.workspace.active_editor() {
+                        if let Some(path) = editor.file_path() {
+                            if let Some(lang) = language_id_from_path(path) {
+                                if self.lsp_manager.supports(lang, LspFeature::Hover) {
+                                    let path = path.to_path_buf();
+                                    self.hover_anchor = Some(HoverAnchor::Mouse {
+                                        x: screen_x,
+                                        y: screen_y,
+                                        scroll: (editor.scroll_offset(), editor.horizontal_scroll()),
+                                    });
+                                    let position = self.lsp_position(lang, line, col);
+                                    self.lsp_manager.hover(&path, lang, position);
+                                    self.hover_pending = true;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Splits the editor area into two panes showing the active buffer, or
+    /// goes back to one pane showing the focused one.
+    pub fn toggle_split(&mut self) {
+        self.flush_pending_lsp_changes(true);
+        self.clear_hover();
+        if self.workspace.is_split() {
+            self.workspace.unsplit();
+        } else {
+            self.workspace.split();
+        }
+    }
+
+    /// Moves the focus to the pane on `side` of a split. Returns whether
+    /// the focus moved.
+    pub fn focus_pane(&mut self, side: PaneSide) -> bool {
+        if !self.workspace.is_split() || side == self.workspace.focused_side() {
+            return false;
+        }
+        self.flush_pending_lsp_changes(true);
+        self.clear_hover();
+        self.workspace.focus_pane(side)
+    }
+
+    /// Clears the hover state.
+    pub fn clear_hover(&mut self) {
+        self.hover_mouse_pos = None;
+        self.hover_request_time = None;
+        self.hover_pending = false;
+        self.hover_anchor = None;
+        if let Some(editor) = self.workspace.active_editor_mut() {
+            editor.clear_hover_info();
+        }
+    }
+
+    /// Dismisses the popups whose anchor scrolled out of view or whose
+    /// text changed under them.
+    pub fn update_popup_anchors(&mut self) {
+        let Some(editor) = self.workspace.active_editor() else {
+            return;
+        };
+        let scroll = (editor.scroll_offset(), editor.horizontal_scroll());
+        let visible_lines = editor.visible_lines();
+        let hover_valid = self
+            .hover_anchor
+            .as_ref()
+            .is_none_or(|anchor| anchor.is_valid(editor.buffer(), scroll, visible_lines));
+        let completion_valid = self
+            .completion_anchor
+            .as_ref()
+            .is_none_or(|anchor| anchor.is_valid(editor.buffer()) && anchor.is_in_view(scroll.0, visible_lines));
+        let signature_valid = self
+            .signature_anchor
+            .as_ref()
+            .is_none_or(|anchor| anchor.is_valid(editor.buffer()) && anchor.is_in_view(scroll.0, visible_lines));
+        let code_actions_valid = self
+            .code_action_anchor
+            .as_ref()
+            .is_none_or(|anchor| anchor.is_valid(editor.buffer()) && anchor.is_in_view(scroll.0, visible_lines));
+        if !hover_valid {
+            self.clear_hover();
+        }
+        if !completion_valid {
+            self.hide_completion();
+        }
+        if !signature_valid {
+            self.hide_signature_help();
+        }
+        if !code_actions_valid {
+            self.hide_code_actions();
+        }
+    }
+
+    /// Triggers auto-completion at the current cursor position.
+    pub fn trigger_completion(&mut self) {
+        let Some((path, lang, position)) = self.lsp_cursor_position() else {
+            return;
+        };
+        if !self.lsp_feature_available(lang, LspFeature::Completion) {
+            return;
+        }
+        if let Some(editor) = self.workspace.active_editor() {
+            let pos = editor.cursor_position();
+            self.completion_anchor = Some(PopupAnchor::new(editor.buffer(), pos.line, pos.col));
+        }
+        self.lsp_manager.completion(&path, lang, position);
+    }
+
+    /// Updates the completion and signature help popups after `ch` was
+    /// typed. The server's trigger characters request them; other
+    /// characters narrow the completion list.
+    pub fn typed_char(&mut self, ch: char) {
+        let language = self.active_language();
+        let is_trigger =
+            |f: fn(&LspManager, &str, char) -> bool| language.is_some_and(|lang| f(&self.lsp_manager, lang, ch));
+        let completion = is_trigger(LspManager::is_completion_trigger);
+        let signature_help = is_trigger(LspManager::is_signature_help_trigger);
+        if completion {
+            // The server needs the text with the trigger character
+            self.flush_pending_lsp_changes(true);
+            self.trigger_completion();
+        } else if self.completion_visible {
+            self.refilter_completions();
+        }
+        if signature_help {
+            self.request_signature_help();
+        } else if ch == ')' {
+            self.hide_signature_help();
+        }
+    }
+
+    /// Returns the text typed since completion was triggered, or `None` if
+    /// the cursor has left it.
+    fn completion_prefix(&self) -> Option<String> {
+        let anchor = self.completion_anchor.as_ref()?;
+        let editor = self.workspace.active_editor()?;
+        let pos = editor.cursor_position();
+        if pos.line != anchor.line || pos.col < anchor.col {
+            return None;
+        }
+        let start = editor.buffer().line_col_to_char(anchor.line, anchor.col);
+        Some((start..start + pos.col - anchor.col).filter_map(|i| editor.buffer().char_at(i)).collect())
+    }
+
+    /// Narrows the completion list to the text typed since it was
+    /// triggered, keeping the selected item if it still matches. Hides the
+    /// list once the cursor leaves the typed text, and asks the server
+    /// again when nothing matches any more.
+    pub fn refilter_completions(&mut self) {
+        let Some(prefix) = self.completion_prefix() else {
+            self.hide_completion();
+            return;
+        };
+        let Some(editor) = self.workspace.active_editor_mut() else {
+            return;
+        };
+        let selected = editor.completions().get(self.completion_selected).map(|item| item.label.clone());
+        let matched = editor.filter_completions(&prefix);
+        self.completion_selected = selected
+            .and_then(|label| editor.completions().iter().position(|item| item.label == label))
+            .unwrap_or(0);
+        self.completion_visible = matched > 0;
+        if matched == 0 {
+            // The server needs the typed text
+            self.flush_pending_lsp_changes(true);
+            self.request_completions();
+        }
+    }
+
+    /// Moves to the next completion item.
+    pub fn completion_next(&mut self) {
+        if let Some(editor) = self.workspace.active_editor() {
+            let count = editor.completions().len();
+            if count > 0 {
+                self.completion_selected = (self.completion_selected + 1) % count;
+            }
+        }
+    }
+
+    /// Moves to the previous completion item.
+    pub fn completion_prev(&mut self) {
+        if let Some(editor) = self.workspace.active_editor() {
+            let count = editor.completions().len();
+            if count > 0 {
+                if self.completion_selected == 0 {
+                    self.completion_selected = count - 1;
+                } else {
+                    self.completion_selected -= 1;
+                }
+            }
+        }
+    }
+
+    /// Accepts the currently selected completion.
+    pub fn accept_completion(&mut self) {
+        if !self.completion_visible {
+            return;
+        }
+
+        let insert_text = if let Some(editor) = self.workspace.active_editor() {
+            let completions = editor.completions();
+            if self.completion_selected < completions.len() {
+                let item = &completions[self.completion_selected];
+                Some((item.insert_text.clone().unwrap_or_else(|| item.label.clone()), item.is_snippet))
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        if let Some((text, is_snippet)) = insert_text {
+            // Delete from trigger position to current position, then insert
+            if let Some(PopupAnchor { line: trigger_line, col: trigger_col, .. }) = self.completion_anchor {
+                if let Some(editor) = self.workspace.active_editor_mut() {
+                    let pos = editor.cursor_position();
+                    // Only insert if we're on the same line
+                    if pos.line == trigger_line && pos.col >= trigger_col {
+                        // Delete the partial text typed so far
+                        for _ in trigger_col..pos.col {
+                            editor.delete_backward();
+                        }
+                        // Insert the completion text
+                        if is_snippet {
+                            editor.insert_snippet(&text);
+                        } else {
+                            editor.insert_text(&text);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.hide_completion();
+    }
+
+    /// Hides the completion popup.
+    pub fn hide_completion(&mut self) {
+        self.completion_visible = false;
+        self.completion_selected = 0;
+        self.completion_anchor = None;
+        if let Some(editor) = self.workspace.active_editor_mut() {
+            editor.clear_completions();
+        }
+    }
+
+    /// Opens the search bar.
+    pub fn open_search(&mut self) {
+        self.input_mode = InputMode::Search;
+        self.focused_field = 0;
+        self.prepare_search_from_selection();
+        // Perform search immediately if there's text
+        if !self.search_text.is_empty() {
+            if let Some(editor) = self.workspace.active_editor_mut() {
+                editor.find(self.search_text.text());
+            }
+        }
+    }
+
+    /// Pre-fills the query from a single-line selection, or restricts the
+    /// search to a multi-line selection.
+    fn prepare_search_from_selection(&mut self) {
+        if let Some(editor) = self.workspace.active_editor_mut() {
+            // With several cursors each selection is searched on its own
+            if editor.has_multiple_cursors() && editor.set_search_in_selection() {
+                return;
+            }
+            if let Some(selected) = editor.selected_text() {
+                if selected.contains('\n') {
+                    editor.set_search_in_selection();
+                } else {
+                    self.search_text.set_text(selected);
+                }
+            }
+        }
+    }
+
+    /// Opens the replace bar.
+    pub fn open_replace(&mut self) {
+        self.input_mode = InputMode::Replace;
+        self.focused_field = 0;
+        self.prepare_search_from_selection();
+        // Perform search immediately if there's text
+        if !self.search_text.is_empty() {
+            if let Some(editor) = self.workspace.active_editor_mut() {
+                editor.find(self.search_text.text());
+            }
+        }
+    }
+
+    /// Opens the go to line dialog.
+    pub fn open_goto_line(&mut self) {
+        self.input_mode = InputMode::GoToLine;
+        self.goto_text.clear();
+        self.goto_error = None;
+    }
+
+    /// Jumps to the line and column typed in the go to line bar and closes
+    /// it. Malformed input keeps the bar open, showing what is wrong.
+    /// Returns whether it jumped.
+    pub fn submit_goto_line(&mut self) -> bool {
+        let Some(editor) = self.workspace.active_editor_mut() else {
+            return false;
+        };
+        let current_line = editor.cursor_position().line + 1;
+        match parse_goto_target(self.goto_text.text(), current_line, editor.buffer().len_lines()) {
+            Ok(GotoTarget { line, col: Some(col) }) => editor.go_to_line_col(line, col),
+            Ok(GotoTarget { line, col: None }) => editor.go_to_line(line),
+            Err(message) => {
+                self.goto_error = Some(message);
+                return false;
+            }
+        };
+        self.close_input_bar();
+        true
+    }
+
+    /// Opens the filter command prompt, keeping the last command.
+    /// Returns false (and stays in normal mode) if nothing is selected.
+    pub fn open_filter_command(&mut self) -> bool {
+        if !self.workspace.active_editor().is_some_and(Editor::has_selection) {
+            return false;
+        }
+        self.input_mode = InputMode::FilterCommand;
+        true
+    }
+
+    /// Replaces the selection with the stdout of `command`, which gets the
+    /// selected text on stdin. The replacement is one undo step and is left
+    /// selected.
+    ///
+    /// A trailing newline in the output is dropped unless the selection
+    /// ended with one. If the command fails or exits with a non-zero status
+    /// the text is left alone and an error is shown.
+    /// Returns true if the selection was replaced.
+    pub fn filter_selection_through_command(&mut self, command: &str) -> bool {
+        let Some(editor) = self.workspace.active_editor() else {
+            return false;
+        };
+        let (Some(selected), Some((start, _))) = (editor.get_selected_text(), editor.selected_range()) else {
+            self.notifications.info("Select text to filter");
+            return false;
+        };
+        let cwd = editor
+            .file_path()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+
+        log::info!("Filtering selection through: {} (in {:?})", command, cwd);
+        let output = match runner::filter(command, &selected, &cwd) {
+            Ok(output) => output,
+            Err(e) => {
+                self.notifications.error(format!("Failed to run {}: {}", command, e));
+                return false;
+            }
+        };
+        if !output.success() {
+            let status = output.code.map_or_else(|| "was killed".to_string(), |code| format!("exited with {}", code));
+            let detail = output.stderr.lines().next().map(|line| format!(": {}", line)).unwrap_or_default();
+            self.notifications.error(format!("{} {}{}", command, status, detail));
+            return false;
+        }
+
+        let mut text = output.stdout.as_str();
+        if !selected.ends_with('\n') {
+            text = text.strip_suffix('\n').map_or(text, |t| t.strip_suffix('\r').unwrap_or(t));
+        }
+        let Some(editor) = self.workspace.active_editor_mut() else {
+            return false;
+        };
+        editor.insert_text(text);
+        let (line, col) = editor.buffer().char_to_line_col(start);
+        let end = editor.cursor_position();
+        editor.set_cursor_position(line, col, false);
+        editor.set_cursor_position(end.line, end.col, true);
+        true
+    }
+
+    /// Opens the rename symbol dialog, if the server supports renaming.
+    pub fn open_rename(&mut self) {
+        if let Some(lang) = self.active_language() {
+            if !self.lsp_feature_available(lang, LspFeature::Rename) {
+                return;
+            }
+        }
+        // Get the word under cursor to pre-fill the rename text
+        if let Some(editor) = self.workspace.active_editor() {
+            if let Some(word) = editor.word_under_cursor() {
+                self.rename_text.set_text(word);
+            } else {
+                self.rename_text.clear();
+            }
+        }
+        self.input_mode = InputMode::Rename;
+    }
+
+    /// Requests rename from LSP.
+    pub fn request_rename(&mut self, new_name: &str) {
+        if let Some((path, lang, position)) = self.lsp_cursor_position() {
+            if self.lsp_manager.supports_rename(lang) {
+                self.lsp_manager.rename(&path, lang, position, new_name);
+            }
+        }
+        self.input_mode = InputMode::Normal;
+    }
+
+    /// Asks the server for the code actions of the selection, or of the
+    /// cursor, with the diagnostics that overlap it. The menu opens by the
+    /// cursor once they arrive.
+    pub fn request_code_actions(&mut self) {
+        self.hide_code_actions();
+        let Some((path, lang, _)) = self.lsp_cursor_position().filter(|(_, lang, _)| self.lsp_manager.has_client(lang))
+        else {
+            self.notifications.info("No code actions available");
+            return;
+        };
+        if !self.lsp_feature_available(lang, LspFeature::CodeAction) {
+            return;
+        }
+        // The server needs the text the actions are for
+        self.flush_pending_lsp_changes(true);
+        let Some(editor) = self.workspace.active_editor() else {
+            return;
+        };
+        let buffer = editor.buffer();
+        let pos = editor.cursor_position();
+        let (start, end) = match editor.selected_range() {
+            Some((start, end)) => (buffer.char_to_line_col(start), buffer.char_to_line_col(end)),
+            None => ((pos.line, pos.col), (pos.line, pos.col)),
+        };
+        let range = |start: (usize, usize), end: (usize, usize)| {
+            Range::new(self.lsp_position(lang, start.0, start.1), self.lsp_position(lang, end.0, end.1))
+        };
+        let diagnostics = editor
+            .diagnostics()
+            .iter()
+            .filter(|d| (d.start_line, d.start_col) <= end && start <= (d.end_line, d.end_col))
+            .map(|d| lsp::to_lsp_diagnostic(d, range((d.start_line, d.start_col), (d.end_line, d.end_col))))
+            .collect();
+        let range = range(start, end);
+        self.code_action_anchor = Some(PopupAnchor::new(buffer, pos.line, pos.col));
+        self.lsp_manager.code_action(&path, lang, range, diagnostics);
+    }
+
+    /// Closes the code actions menu, or stops waiting for it.
+    pub fn hide_code_actions(&mut self) {
+        self.code_actions = None;
+        self.code_action_anchor = None;
+    }
+
+    /// Applies the selected code action and closes the menu: its edits
+    /// first, then its command, whose changes the server sends back as a
+    /// workspace edit.
+    pub fn apply_selected_code_action(&mut self) {
+        let Some(menu) = self.code_actions.take() else {
+            return;
+        };
+        self.code_action_anchor = None;
+        let language = language_id_from_path(menu.path());
+        let action = menu.into_selected();
+        if !action.edits.is_empty() {
+            let (total_edits, files_changed) = self.apply_workspace_edits(action.edits);
+            self.notify_lsp_document_change();
+            if total_edits > 0 {
+                self.notifications.info(format!(
+                    "{}: {} edit(s) in {} file(s)",
+                    action.title, total_edits, files_changed
+                ));
+            }
+        }
+        if let (Some(command), Some(language)) = (action.command, language) {
+            // The command works on the server's copy of the text
+            self.flush_pending_lsp_changes(true);
+            self.lsp_manager.execute_command(language, &command, action.arguments);
+        }
+    }
+
+    /// Opens the project search bar, pre-filled from a single-line selection.
+    pub fn open_project_search(&mut self) {
+        self.input_mode = InputMode::ProjectSearch;
+        self.focused_field = 0;
+        if let Some(selected) = self.workspace.active_editor().and_then(|e| e.selected_text()) {
+            if !selected.contains('\n') {
+                self.project_search_text.set_text(selected);
+            }
+        }
+    }
+
+    /// Reads the user settings from `settings.json` in the config
+    /// directory.
+    pub fn load_settings(&mut self) {
+        if let Some(dir) = config_dir() {
+            self.apply_settings(Settings::load(&dir.join("settings.json")));
+        }
+    }
+
+    /// Applies user settings to the parts of the app they configure.
+    pub fn apply_settings(&mut self, settings: Settings) {
+        self.input_handler.mouse = settings.mouse;
+        self.notifications.settings = settings.notifications;
+        self.lsp_manager.set_exclusions(settings.lsp.disabled_languages, settings.lsp.exclude_globs);
+        self.workspace.set_default_settings(EditorSettings {
+            trim_trailing_whitespace: settings.save.trim_trailing_whitespace,
+            ensure_final_newline: settings.save.ensure_final_newline,
+            ..self.workspace.default_settings()
+        });
+    }
+
+    /// Reads the folders trusted to run language servers from the config
+    /// directory.
+    pub fn load_trusted_workspaces(&mut self) {
+        if let Some(dir) = config_dir() {
+            self.trust = TrustStore::load(dir.join("trusted.json"));
+        }
+    }
+
+    /// Keeps the recent files list in the config directory, so it survives
+    /// restarts.
+    pub fn load_recent_files(&mut self) {
+        if let Some(dir) = config_dir() {
+            let recent = RecentFiles::load(dir.join("recent.json"), MAX_RECENT_FILES);
+            self.workspace.set_recent_files(recent);
+        }
+    }
+
+    /// Writes the open files to `session.json` in the config directory,
+    /// for [`EditorApp::restore_session`] on the next launch. A `--wait`
+    /// launch edits a single file for another program and leaves the
+    /// session alone.
+    pub fn save_session(&self) {
+        if self.wait.is_some() {
+            return;
+        }
+        if let Some(dir) = config_dir() {
+            if let Err(e) = self.workspace.save_session(&dir.join("session.json")) {
+                log::warn!("Failed to save session: {}", e);
+            }
+        }
+    }
+
+    /// Reopens the files open when the editor last quit. Returns the number
+    /// of files opened.
+    pub fn restore_session(&mut self) -> usize {
+        let Some(dir) = config_dir() else {
+            return 0;
+        };
+        self.workspace.restore_session(&dir.join("session.json")).unwrap_or(0)
+    }
+
+    /// Opens the recent files picker, listing the recent files that still
+    /// exist. Returns false (and stays in normal mode) if there are none.
+    pub fn open_recent_files(&mut self) -> bool {
+        if self.workspace.existing_recent_files().is_empty() {
+            self.notifications.info("No recent files");
+            return false;
+        }
+        self.input_mode = InputMode::RecentFiles;
+        self.recent_text.clear();
+        self.filter_recent_files();
+        true
+    }
+
+    /// Narrows the recent files picker to the files whose path fuzzy-matches
+    /// its text, best match first, and selects the first.
+    pub fn filter_recent_files(&mut self) {
+        let mut scored: Vec<(i32, &PathBuf)> = self
+            .workspace
+            .recent_files()
+            .iter()
+            .filter_map(|path| fuzzy::score(self.recent_text.text(), &path.display().to_string()).map(|score| (score, path)))
+            .collect();
+        // Stable, so equal matches stay most recent first
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        self.recent_matches = scored.into_iter().map(|(_, path)| path.clone()).collect();
+        self.recent_selected = 0;
+    }
+
+    /// Moves the recent files picker's selection by `delta`, wrapping around.
+    pub fn select_recent_file(&mut self, delta: isize) {
+        let len = self.recent_matches.len() as isize;
+        if len > 0 {
+            self.recent_selected = (self.recent_selected as isize + delta).rem_euclid(len) as usize;
+        }
+    }
+
+    /// Opens the file selected in the recent files picker and closes it.
+    pub fn open_selected_recent_file(&mut self) {
+        let Some(path) = self.recent_matches.get(self.recent_selected).cloned() else {
+            return;
+        };
+        self.close_input_bar();
+        if let Err(err) = self.workspace.open_file(&path) {
+            self.notifications.error(format!("Failed to open {}: {}", path.display(), err));
+        }
+    }
+
+    /// Opens the encoding picker for reading the active file again in
+    /// another encoding, the current one selected. Returns false (and
+    /// stays in normal mode) if the buffer has no file or unsaved changes
+    /// a reopen would lose.
+    pub fn open_encoding_picker(&mut self) -> bool {
+        let Some(editor) = self.workspace.active_editor() else {
+            return false;
+        };
+        if editor.file_path().is_none() {
+            self.notifications.info("Nothing to reopen: the buffer has no file");
+            return false;
+        }
+        if editor.is_modified() {
+            self.notifications.error("Save or undo the changes before reopening");
+            return false;
+        }
+        let current = editor.encoding();
+        self.input_mode = InputMode::Encodings;
+        self.encoding_text.clear();
+        self.filter_encodings();
+        self.encoding_selected = self.encoding_matches.iter().position(|&e| e == current).unwrap_or(0);
+        true
+    }
+
+    /// Narrows the encoding picker to the encodings whose name
+    /// fuzzy-matches its text, best match first, and selects the first.
+    pub fn filter_encodings(&mut self) {
+        let mut scored: Vec<(i32, Encoding)> = Encoding::ALL
+            .into_iter()
+            .filter_map(|encoding| fuzzy::score(self.encoding_text.text(), encoding.label()).map(|score| (score, encoding)))
+            .collect();
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        self.encoding_matches = scored.into_iter().map(|(_, encoding)| encoding).collect();
+        self.encoding_selected = 0;
+    }
+
+    /// Moves the encoding picker's selection by `delta`, wrapping around.
+    pub fn select_encoding(&mut self, delta: isize) {
+        let len = self.encoding_matches.len() as isize;
+        if len > 0 {
+            self.encoding_selected = (self.encoding_selected as isize + delta).rem_euclid(len) as usize;
+        }
+    }
+
+    /// Reads the active file again in the encoding selected in the picker
+    /// and closes it.
+    pub fn reopen_with_selected_encoding(&mut self) {
+        let Some(&encoding) = self.encoding_matches.get(self.encoding_selected) else {
+            return;
+        };
+        self.close_input_bar();
+        let Some(editor) = self.workspace.active_editor_mut() else {
+            return;
+        };
+        match editor.reopen_with_encoding(encoding) {
+            Ok(()) => {
+                self.notifications.info(format!("Reopened as {}", encoding.label()));
+                self.notify_lsp_document_change();
+            }
+            Err(e) => self.notifications.error(format!("Failed to reopen as {}: {}", encoding.label(), e)),
+        }
+    }
+
+    /// Opens the Problems panel on a fresh snapshot of the diagnostics.
+    pub fn open_problems(&mut self) {
+        self.refresh_problems();
+        self.input_mode = InputMode::Problems;
+    }
+
+    /// Retakes the Problems panel's snapshot of the diagnostics.
+    pub fn refresh_problems(&mut self) {
+        let problems = self.collect_problems();
+        self.problems.refresh(problems);
+    }
+
+    /// Returns the diagnostics of every open buffer, files shown relative
+    /// to the project root.
+    pub fn collect_problems(&self) -> Vec<ProblemEntry> {
+        let root = self.project_search_root();
+        let mut entries = Vec::new();
+        for (id, editor) in self.workspace.editors() {
+            let path = editor.file_path();
+            let file = match path {
+                Some(path) => {
+                    let relative = root.as_deref().and_then(|root| path.strip_prefix(root).ok());
+                    relative.unwrap_or(path).display().to_string()
+                }
+                None => "Untitled".to_string(),
+            };
+            entries.extend(editor.diagnostics().iter().map(|diagnostic| ProblemEntry {
+                buffer: id,
+                path: path.map(Path::to_path_buf),
+                file: file.clone(),
+                line: diagnostic.start_line,
+                col: diagnostic.start_col,
+                severity: diagnostic.severity,
+                message: diagnostic.message.clone(),
+                source: diagnostic.source.clone(),
+            }));
+        }
+        entries
+    }
+
+    /// Goes to the diagnostic selected in the Problems panel and closes it.
+    /// A buffer closed since the snapshot is reopened from its file.
+    pub fn open_selected_problem(&mut self) {
+        let Some(entry) = self.problems.selected().cloned() else {
+            return;
+        };
+        let open = self.workspace.editors().any(|(id, _)| id == entry.buffer);
+        let id = match &entry.path {
+            _ if open => entry.buffer,
+            Some(path) => match self.workspace.open_file(path) {
+                Ok(id) => id,
+                Err(e) => {
+                    self.notifications.error(format!("Failed to open {}: {}", path.display(), e));
+                    return;
+                }
+            },
+            None => {
+                self.notifications.info(format!("{} was closed", entry.file));
+                return;
+            }
+        };
+        self.workspace.set_active(id);
+        if let Some(editor) = self.workspace.active_editor_mut() {
+            editor.go_to_line_col(entry.line + 1, entry.col + 1);
+        }
+        self.close_input_bar();
+    }
+
+    /// Writes the diagnostics the Problems panel shows to `path`: JSON for
+    /// a `.json` file, `path:line:col: severity: message` lines otherwise.
+    /// Outside the panel the snapshot is retaken first. Returns the number
+    /// of diagnostics written.
+    pub fn export_problems(&mut self, path: &Path) -> std::io::Result<usize> {
+        if self.input_mode != InputMode::Problems {
+            self.refresh_problems();
+        }
+        let shown = self.problems.shown();
+        let text = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+            problems::to_json(&shown)
+        } else {
+            problems::to_text(&shown)
+        };
+        std::fs::write(path, text)?;
+        Ok(shown.len())
+    }
+
+    /// Returns the directory project search walks: the LSP workspace root,
+    /// else the active file's project root, else the current directory.
+    pub fn project_search_root(&self) -> Option<PathBuf> {
+        if let Some(root) = self.lsp_manager.workspace_root() {
+            return Some(root.to_path_buf());
+        }
+        let from_file = self
+            .workspace
+            .active_editor()
+            .and_then(|e| e.file_path())
+            .and_then(|path| path.parent())
+            .map(|parent| find_project_root(parent).unwrap_or_else(|| parent.to_path_buf()));
+        from_file.or_else(|| std::env::current_dir().ok())
+    }
+
+    /// Starts a project search for the current query on a background thread.
+    pub fn run_project_search(&mut self) {
+        let Some(root) = self.project_search_root() else {
+            self.notifications.error("No folder to search");
+            return;
+        };
+        let query = self.project_search_text.text().to_string();
+        self.project_search.start(root, &query);
+    }
+
+    /// Collects streamed project search results. Returns true if a redraw is needed.
+    pub fn poll_project_search(&mut self) -> bool {
+        self.project_search.poll()
+    }
+
+    /// Opens the selected project search result at its line and column.
+    pub fn open_project_search_result(&mut self) {
+        let Some(hit) = self.project_search.selected().cloned() else {
+            return;
+        };
+        match self.workspace.open_file(&hit.path) {
+            Ok(id) => {
+                self.workspace.set_active(id);
+                if let Some(editor) = self.workspace.active_editor_mut() {
+                    editor.go_to_line_col(hit.line + 1, hit.col + 1);
+                }
+                self.close_input_bar();
+            }
+            Err(e) => {
+                self.notifications.error(format!("Failed to open {}: {}", hit.path.display(), e));
+            }
+        }
+    }
+
+    /// Replaces the last project search's matches in every file with the
+    /// replacement text. Open files are edited through their editor so each
+    /// can be undone; other files are rewritten on disk. Files changed on
+    /// disk since the search are skipped.
+    pub fn replace_in_project(&mut self) {
+        if self.project_search.is_running() || self.project_search.query() != self.project_search_text.text() {
+            self.notifications.info("Run the search before replacing");
+            return;
+        }
+        let query = self.project_search.query().to_string();
+        let replacement = self.project_replace_text.text().to_string();
+        let active = self.workspace.active_buffer_id();
+
+        let mut total = 0;
+        let mut files_changed = 0;
+        let mut conflicts = Vec::new();
+        for file in self.project_search.hits_by_file() {
+            if file.changed_on_disk() {
+                conflicts.push(file.path.clone());
+                continue;
+            }
+            let count = match self.workspace.find_by_path(&file.path) {
+                Some(id) => {
+                    let count = self
+                        .workspace
+                        .get_buffer_mut(id)
+                        .map_or(0, |editor| editor.replace_on_lines(&file.lines, &query, &replacement));
+                    if count > 0 && Some(id) == active {
+                        self.notify_lsp_document_change();
+                    }
+                    count
+                }
+                None => match replace_in_file(&file, &query, &replacement) {
+                    Ok(count) => count,
+                    Err(e) => {
+                        log::error!("Failed to replace in {:?}: {}", file.path, e);
+                        self.notifications.error(format!("Failed to replace in {}: {}", file.path.display(), e));
+                        0
+                    }
+                },
+            };
+            if count > 0 {
+                total += count;
+                files_changed += 1;
+            }
+        }
+
+        if total > 0 {
+            self.notifications.success(format!(
+                "Replaced {} occurrences in {} file(s)",
+                total, files_changed
+            ));
+        } else if conflicts.is_empty() {
+            self.notifications.info("No matches to replace");
+        }
+        if !conflicts.is_empty() {
+            let names: Vec<String> = conflicts
+                .iter()
+                .map(|path| path.file_name().map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().into_owned()))
+                .collect();
+            self.notifications.warning(format!(
+                "Skipped {} file(s) changed on disk since the search: {}",
+                conflicts.len(),
+                names.join(", ")
+            ));
+        }
+
+        // Refresh the results so they reflect the replaced text
+        self.run_project_search();
+    }
+
+    /// Returns the input bar's focused field, if it is open.
+    pub fn focused_field_mut(&mut self) -> Option<&mut TextField> {
+        Some(match self.input_mode {
+            InputMode::Search => &mut self.search_text,
+            InputMode::Replace if self.focused_field == 0 => &mut self.search_text,
+            InputMode::Replace => &mut self.replace_text,
+            InputMode::GoToLine => &mut self.goto_text,
+            InputMode::Rename => &mut self.rename_text,
+            InputMode::FilterCommand => &mut self.filter_text,
+            InputMode::ProjectSearch if self.focused_field == 0 => &mut self.project_search_text,
+            InputMode::ProjectSearch => &mut self.project_replace_text,
+            InputMode::RecentFiles => &mut self.recent_text,
+            InputMode::Problems => &mut self.problems_text,
+            InputMode::Encodings => &mut self.encoding_text,
+            InputMode::Normal => return None,
+        })
+    }
+
+    /// Catches up with an edit of the focused field: the search runs
+    /// again, the go to line error goes and the pickers narrow their lists.
+    pub fn focused_field_changed(&mut self) {
+        match self.input_mode {
+            InputMode::Search | InputMode::Replace if self.focused_field == 0 => {
+                if let Some(editor) = self.workspace.active_editor_mut() {
+                    editor.find(self.search_text.text());
+                }
+            }
+            InputMode::GoToLine => self.goto_error = None,
+            InputMode::RecentFiles => self.filter_recent_files(),
+            InputMode::Problems => self.problems.set_text(self.problems_text.text()),
+            InputMode::Encodings => self.filter_encodings(),
+            _ => {}
+        }
+    }
+
+    /// Closes the search/replace/goto bar.
+    pub fn close_input_bar(&mut self) {
+        if self.input_mode != InputMode::Normal {
+            self.input_mode = InputMode::Normal;
+            self.replace_walk = None;
+            self.project_search.cancel();
+            // Clear search highlighting
+            if let Some(editor) = self.workspace.active_editor_mut() {
+                editor.clear_search();
+            }
+        } else if self.signature_anchor.is_some() {
+            self.hide_signature_help();
+        } else {
+            // If already in normal mode, collapse cursors
+            if let Some(editor) = self.workspace.active_editor_mut() {
+                editor.collapse_cursors();
+                editor.exit_block_selection();
+            }
+        }
+    }
+
+    /// Starts a step-through replace from the current match.
+    /// Returns false if there is nothing to replace.
+    pub fn start_replace_walk(&mut self) -> bool {
+        let Some(editor) = self.workspace.active_editor_mut() else {
+            return false;
+        };
+        let total = editor.search_matches().len();
+        if total == 0 {
+            self.notifications.info("No matches to replace");
+            return false;
+        }
+        // Make sure a match is selected before the first prompt
+        if editor.current_search_match().is_none() {
+            editor.find_next();
+        }
+        self.replace_walk = Some(ReplaceWalk {
+            total,
+            visited: 0,
+            replaced: 0,
+        });
+        true
+    }
+
+    /// Applies an answer to the step-through replace prompt.
+    pub fn replace_walk_step(&mut self, answer: ReplaceAnswer) {
+        let Some(mut walk) = self.replace_walk else {
+            return;
+        };
+        let Some(editor) = self.workspace.active_editor_mut() else {
+            return;
+        };
+        match answer {
+            ReplaceAnswer::Replace => {
+                if editor.replace_current(self.replace_text.text()) {
+                    walk.replaced += 1;
+                }
+                walk.visited += 1;
+            }
+            ReplaceAnswer::Skip => {
+                editor.find_next();
+                walk.visited += 1;
+            }
+            ReplaceAnswer::All => {
+                walk.replaced += editor.replace_matches_from_current(self.replace_text.text(), walk.remaining());
+                walk.visited = walk.total;
+            }
+        }
+        let exhausted = editor.search_matches().is_empty();
+        self.replace_walk = Some(walk);
+        if walk.remaining() == 0 || exhausted {
+            self.finish_replace_walk();
+        }
+    }
+
+    /// Ends the step-through replace and reports how many matches were replaced.
+    pub fn finish_replace_walk(&mut self) {
+        if let Some(walk) = self.replace_walk.take() {
+            self.notifications.info(format!("Replaced {} of {}", walk.replaced, walk.total));
+        }
+    }
+
+    /// Returns true if in any input mode.
+    pub fn is_input_mode(&self) -> bool {
+        self.input_mode != InputMode::Normal
+    }
+
+    /// Sets the editor font size, clamped to the zoom range. The chrome keeps
+    /// its own size. Returns true if the size changed.
+    pub fn set_font_size(&mut self, font_size: f32) -> bool {
+        let font_size = font_size.clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+        if font_size == self.font_size {
+            return false;
+        }
+        self.font_size = font_size;
+        true
+    }
+
+    /// Turns zen mode on or off. Returns whether it is on.
+    pub fn toggle_zen_mode(&mut self) -> bool {
+        let blink = if self.zen.is_active() {
+            self.zen.exit()
+        } else {
+            self.zen.enter(self.cursor_blink.is_enabled())
+        };
+        self.cursor_blink.set_enabled(blink);
+        self.zen.is_active()
+    }
+
+    /// Returns the height of the tab bar, which zen mode hides.
+    pub fn tab_bar_height(&self) -> f32 {
+        if self.zen.is_active() {
+            0.0
+        } else {
+            self.chrome.tab_bar_height()
+        }
+    }
+
+    /// Returns the height of the status bar, which zen mode hides.
+    pub fn status_bar_height(&self) -> f32 {
+        if self.zen.is_active() {
+            0.0
+        } else {
+            self.chrome.status_bar_height()
+        }
+    }
+
+    /// Returns the left edge and the width of the focused pane in a window
+    /// `viewport_width` wide: all of it, or half when split. Zen mode shows
+    /// only the focused pane.
+    pub fn pane_area(&self, viewport_width: f32) -> (f32, f32) {
+        if !self.workspace.is_split() || self.zen.is_active() {
+            return (0.0, viewport_width);
+        }
+        let half = (viewport_width / 2.0).floor();
+        match self.workspace.focused_side() {
+            PaneSide::Left => (0.0, half),
+            PaneSide::Right => (half, viewport_width - half),
+        }
+    }
+
+    /// Returns the side of the pane at `x`, which is the focused one
+    /// unless the editor area is split.
+    pub fn pane_at(&self, x: f32) -> PaneSide {
+        let focused = self.workspace.focused_side();
+        if !self.workspace.is_split() || self.zen.is_active() {
+            return focused;
+        }
+        let (left, width) = self.pane_area(self.viewport_width);
+        if (left..left + width).contains(&x) {
+            focused
+        } else {
+            focused.other()
+        }
+    }
+
+    /// Returns the left edge and the width of the text in a window
+    /// `viewport_width` wide: right of the gutter of the focused pane, or
+    /// the centered column without a gutter in zen mode.
+    pub fn text_area(&self, char_width: f32, viewport_width: f32) -> (f32, f32) {
+        if self.zen.is_active() {
+            return self.zen.text_column(char_width, viewport_width);
+        }
+        let (pane_left, pane_width) = self.pane_area(viewport_width);
+        let left = pane_left + self.content_padding(char_width, pane_width) + self.gutter_layout(char_width).width();
+        let mut width = (pane_left + pane_width - left).max(0.0);
+        if let Some(max_columns) = self.max_content_width {
+            width = width.min(max_columns as f32 * char_width);
+        }
+        (left, width)
+    }
+
+    /// Returns the padding left of the gutter in a window `viewport_width`
+    /// wide, which centers the gutter and at most `max_content_width`
+    /// characters of text.
+    fn content_padding(&self, char_width: f32, viewport_width: f32) -> f32 {
+        let Some(max_columns) = self.max_content_width else {
+            return 0.0;
+        };
+        let width = self.gutter_layout(char_width).width() + max_columns as f32 * char_width;
+        ((viewport_width - width) / 2.0).max(0.0)
+    }
+
+    /// Returns the current content area X offset: where the gutter starts,
+    /// or the text in zen mode, which has no gutter.
+    pub fn content_x_offset(&self, char_width: f32) -> f32 {
+        if self.zen.is_active() {
+            return self.text_left(char_width);
+        }
+        let (pane_left, pane_width) = self.pane_area(self.viewport_width);
+        pane_left + self.content_padding(char_width, pane_width)
+    }
+
+    /// Returns the left edge of the text in the window.
+    pub fn text_left(&self, char_width: f32) -> f32 {
+        self.text_area(char_width, self.viewport_width).0
+    }
+
+    /// Returns the current content area Y offset (accounting for tab bar,
+    /// search bar and conflict banner).
+    pub fn content_y_offset(&self) -> f32 {
+        let mut offset = self.tab_bar_height();
+        if self.input_mode != InputMode::Normal {
+            offset += self.chrome.search_bar_height();
+        }
+        offset + self.disk_banner_height()
+    }
+
+    /// Cycles the minimum displayed diagnostic severity
+    /// (all -> info and above -> warnings and above -> errors only -> all)
+    /// and applies it to every open buffer.
+    pub fn cycle_diagnostic_filter(&mut self) -> DiagnosticSeverity {
+        self.diagnostic_display_min = match self.diagnostic_display_min {
+            DiagnosticSeverity::Hint => DiagnosticSeverity::Information,
+            DiagnosticSeverity::Information => DiagnosticSeverity::Warning,
+            DiagnosticSeverity::Warning => DiagnosticSeverity::Error,
+            DiagnosticSeverity::Error => DiagnosticSeverity::Hint,
+        };
+        for (_, editor) in self.workspace.editors_mut() {
+            editor.set_diagnostic_display_min(self.diagnostic_display_min);
+        }
+        self.diagnostic_display_min
+    }
+
+    /// Toggles whether change markers ignore whitespace-only changes in
+    /// every open buffer. Returns the new setting.
+    pub fn toggle_diff_ignore_whitespace(&mut self) -> bool {
+        self.diff_ignore_whitespace = !self.diff_ignore_whitespace;
+        for (_, editor) in self.workspace.editors_mut() {
+            editor.set_diff_ignore_whitespace(self.diff_ignore_whitespace);
+        }
+        self.diff_ignore_whitespace
+    }
+
+    /// Recomputes the active buffer's change markers once its text has
+    /// stayed the same for the debounce duration. Returns true while an
+    /// update is still waiting, so the caller keeps drawing frames.
+    pub fn update_line_changes(&mut self) -> bool {
+        let Some(id) = self.workspace.active_buffer_id() else {
+            return false;
+        };
+        let ignore_whitespace = self.diff_ignore_whitespace;
+        let Some(editor) = self.workspace.active_editor_mut() else {
+            return false;
+        };
+        editor.set_diff_ignore_whitespace(ignore_whitespace);
+        if !editor.line_changes_stale() {
+            self.line_changes_pending = None;
+            return false;
+        }
+
+        let version = editor.content_version();
+        match self.line_changes_pending {
+            Some((pending_id, pending_version, since)) if pending_id == id && pending_version == version => {
+                if since.elapsed() >= self.line_changes_debounce {
+                    editor.update_line_changes();
+                    self.line_changes_pending = None;
+                }
+            }
+            _ => self.line_changes_pending = Some((id, version, Instant::now())),
+        }
+        true
+    }
+
+    /// Returns the selected text of the active buffer for the primary
+    /// selection, or None if nothing is selected or the mouse settings turn
+    /// the primary selection off.
+    pub fn primary_selection_text(&self) -> Option<String> {
+        if !self.input_handler.mouse.primary_selection {
+            return None;
+        }
+        let pieces = self.workspace.active_editor()?.cursor_selected_texts()?;
+        Some(pieces.join("\n"))
+    }
+
+    /// Copies (or cuts) the selection of every cursor in the active buffer.
+    /// Returns the text for the system clipboard, or None if nothing is
+    /// selected.
+    pub fn copy_selections(&mut self, cut: bool) -> Option<String> {
+        let editor = self.workspace.active_editor_mut()?;
+        let pieces = if cut {
+            editor.cut_selections()?
+        } else {
+            editor.cursor_selected_texts()?
+        };
+        let clipboard = CursorClipboard::new(pieces);
+        let text = clipboard.text.clone();
+        self.cursor_clipboard = (clipboard.pieces.len() > 1).then_some(clipboard);
+        Some(text)
+    }
+
+    /// Returns the lines of the selection, or the whole buffer, formatted
+    /// for pasting elsewhere: numbered, or as a Markdown code block tagged
+    /// with the buffer's language. Tells the user how many lines that is.
+    pub fn copy_for_sharing(&mut self, markdown: bool) -> Option<String> {
+        let editor = self.workspace.active_editor()?;
+        let buffer = editor.buffer();
+        let lines = export::selected_lines(buffer, editor.selected_range());
+        let count = lines.len();
+        let text = if markdown {
+            let language = editor.file_path().and_then(language_id_from_path).unwrap_or("");
+            export::markdown_code_block(buffer, lines, language)
+        } else {
+            export::with_line_numbers(buffer, lines)
+        };
+        let plural = if count == 1 { "" } else { "s" };
+        let how = if markdown { "as a Markdown code block" } else { "with line numbers" };
+        self.notifications.info(format!("Copied {} line{} {}", count, plural, how));
+        Some(text)
+    }
+
+    /// Pastes system clipboard text into the active buffer. If the text is
+    /// still what a multi-cursor copy put there, each cursor gets its own
+    /// piece when the cursor counts match. A block selection gets one line
+    /// of the text per selected line.
+    pub fn paste_text(&mut self, text: &str) {
+        let Some(editor) = self.workspace.active_editor_mut() else {
+            return;
+        };
+        if editor.is_block_selection_mode() {
+            editor.paste_block(text);
+            return;
+        }
+        match &self.cursor_clipboard {
+            Some(clipboard) if clipboard.text == text => editor.paste_each(&clipboard.pieces),
+            _ => editor.paste(text),
+        }
+    }
+
+    /// Opens a file, creating a new tab.
+    pub fn open_file(&mut self, path: PathBuf) {
+        if let Err(e) = self.workspace.open_file(&path) {
+            log::error!("Failed to open file {:?}: {}", path, e);
+        }
+    }
+
+    /// Marks the cursor as recently active: shows it and pauses blinking.
+    pub fn reset_cursor_blink(&mut self) {
+        self.cursor_blink.record_activity(Instant::now());
+    }
+
+    /// Updates the cursor blink state. Returns true if a redraw is needed.
+    pub fn update_cursor_blink(&mut self) -> bool {
+        self.cursor_blink.update(Instant::now())
+    }
+
+    /// Converts screen coordinates to buffer position.
+    pub fn screen_to_buffer_position(
+        &self,
+        x: f32,
+        y: f32,
+        char_width: f32,
+        line_height: f32,
+    ) -> (usize, usize) {
+        // Adjust y for tab bar and search bar
+        let y = y - self.content_y_offset();
+        if y < 0.0 {
+            return (0, 0);
+        }
+
+        if let Some(editor) = self.workspace.active_editor() {
+            let scroll_offset = editor.scroll_offset();
+            let buffer = editor.buffer();
+
+            // Calculate which screen row was clicked; below the last line
+            // clicks go to the last row
+            let screen_line = (y / line_height).floor() as usize;
+            let rows = editor.visual_lines(scroll_offset, screen_line + 1);
+            let Some(row) = rows.last().copied() else {
+                let last_line = buffer.len_lines().saturating_sub(1);
+                return (last_line, buffer.line_len_chars(last_line));
+            };
+
+            // Calculate which column was clicked
+            let horizontal_scroll = editor.horizontal_scroll();
+            let text_x = (x - self.text_left(char_width)).max(0.0);
+            let layout = editor.line_layout(row.line);
+            let display_col = (text_x / char_width).round() as usize + horizontal_scroll + layout.display_col(row.start_col);
+            let col = layout.col_at(display_col);
+
+            // Clamp column to the row; the wrap point itself is on the next row
+            let row_end = if row.continues {
+                row.end_col.saturating_sub(1).max(row.start_col)
+            } else {
+                buffer.line_len_chars(row.line)
+            };
+            (row.line, col.min(row_end))
+        } else {
+            (0, 0)
+        }
+    }
+
+    /// Computes the gutter layout for the active buffer.
+    pub fn gutter_layout(&self, char_width: f32) -> GutterLayout {
+        let line_count = self
+            .workspace
+            .active_editor()
+            .map(|e| e.buffer().len_lines())
+            .unwrap_or(1);
+        GutterLayout::compute(&self.gutter, char_width, line_count)
+    }
+
+    /// Runs a gutter click action on a buffer line.
+    pub fn handle_gutter_action(&mut self, action: GutterAction, line: usize) {
+        let Some(editor) = self.workspace.active_editor_mut() else {
+            return;
+        };
+        match action {
+            GutterAction::ToggleBookmark => {
+                editor.toggle_bookmark(line);
+            }
+            GutterAction::ToggleFold => {
+                if editor.fold_manager().regions().is_empty() {
+                    editor.detect_folds();
+                }
+                editor.toggle_fold_at_line(line);
+            }
+            GutterAction::ShowDiagnostics => {
+                self.show_line_diagnostics(line);
+            }
+        }
+    }
+
+    /// Opens the diagnostics popup for `line` of the active buffer.
+    /// Returns false if the line has no diagnostics.
+    pub fn show_line_diagnostics(&mut self, line: usize) -> bool {
+        self.diagnostics_popup = self
+            .workspace
+            .active_editor()
+            .and_then(|editor| DiagnosticsPopup::open(line, &editor.diagnostics_on_line(line)));
+        self.diagnostics_popup.is_some()
+    }
+
+    /// Closes the diagnostics popup.
+    pub fn hide_line_diagnostics(&mut self) {
+        self.diagnostics_popup = None;
+    }
+
+    /// Moves the cursor to the selected diagnostic and closes the popup.
+    pub fn goto_selected_line_diagnostic(&mut self) {
+        let Some(popup) = self.diagnostics_popup.take() else {
+            return;
+        };
+        let Some(entry) = popup.selected() else {
+            return;
+        };
+        if let Some(editor) = self.workspace.active_editor_mut() {
+            editor.go_to_line_col(popup.line() + 1, entry.col + 1);
+        }
+    }
+
+    /// Returns whether click is in tab bar area.
+    pub fn is_in_tab_bar(&self, y: f32) -> bool {
+        y < self.tab_bar_height()
+    }
+
+    /// Returns whether `y` is above or below the rows of text, where a drag
+    /// keeps scrolling.
+    pub fn is_outside_text_rows(&self, y: f32, line_height: f32) -> bool {
+        let Some(editor) = self.workspace.active_editor() else {
+            return false;
+        };
+        let top = self.content_y_offset();
+        y < top || y >= top + editor.visible_lines() as f32 * line_height
+    }
+
+    /// Returns the buffer position a drag to (x, y) reaches.
+    ///
+    /// Above or below the text the position is one line past the visible
+    /// ones, so moving the cursor there scrolls. A block selection follows
+    /// the pointer past the end of short lines, unless lines wrap.
+    pub fn drag_position(&self, x: f32, y: f32, char_width: f32, line_height: f32, block: bool) -> (usize, usize) {
+        let Some(editor) = self.workspace.active_editor() else {
+            return (0, 0);
+        };
+        let buffer = editor.buffer();
+        let text_x = (x - self.text_left(char_width)).max(0.0);
+        let display_col = (text_x / char_width).round() as usize + editor.horizontal_scroll();
+
+        let top = self.content_y_offset();
+        let line = if y < top {
+            editor.scroll_offset().saturating_sub(1)
+        } else if self.is_outside_text_rows(y, line_height) {
+            (editor.scroll_offset() + editor.visible_lines()).min(buffer.len_lines().saturating_sub(1))
+        } else {
+            let (line, col) = self.screen_to_buffer_position(x, y, char_width, line_height);
+            if block && !editor.word_wrap() {
+                return (line, editor.line_layout(line).col_at(display_col));
+            }
+            return (line, col);
+        };
+        let virtual_col = editor.line_layout(line).col_at(display_col);
+        if block {
+            (line, virtual_col)
+        } else {
+            (line, virtual_col.min(buffer.line_len_chars(line)))
+        }
+    }
+
+    /// Returns whether click is in search bar area.
+    pub fn is_in_search_bar(&self, y: f32) -> bool {
+        let top = self.tab_bar_height();
+        self.input_mode != InputMode::Normal && (top..top + self.chrome.search_bar_height()).contains(&y)
+    }
+
+    /// Returns the width of the overflow menu button at the end of the tab bar.
+    fn tab_menu_button_width(&self) -> f32 {
+        3.0 * self.chrome.char_width
+    }
+
+    /// Returns whether the tabs are wider than the window.
+    pub fn tabs_overflow(&self, viewport_width: f32) -> bool {
+        let char_width = self.chrome.char_width;
+        let total: f32 = self.workspace.tab_labels().map(|tab| Self::tab_width(&tab, char_width) + 4.0).sum();
+        4.0 + total > viewport_width
+    }
+
+    /// Returns whether (x, y) is on the overflow menu button, which is only
+    /// shown while the tabs overflow.
+    pub fn is_on_tab_menu_button(&self, x: f32, y: f32, viewport_width: f32) -> bool {
+        self.is_in_tab_bar(y) && x >= viewport_width - self.tab_menu_button_width() && self.tabs_overflow(viewport_width)
+    }
+
+    /// Opens the overflow menu, or closes it if it is open.
+    pub fn toggle_tab_menu(&mut self) {
+        if self.tab_menu.is_open() {
+            self.tab_menu.close();
+        } else {
+            self.tab_menu.open(&self.workspace.tabs(), self.workspace.active_buffer_id());
+        }
+    }
+
+    /// Returns where the overflow menu is drawn.
+    pub fn tab_menu_layout(&self, viewport_width: f32) -> TabMenuLayout {
+        let ChromeMetrics { char_width, line_height } = self.chrome;
+        self.tab_menu.layout(char_width, line_height, viewport_width, self.tab_bar_height())
+    }
+
+    /// Handles a click while the overflow menu is open: an entry switches
+    /// to its buffer, anywhere else just closes the menu. Returns false if
+    /// the menu isn't open, so the click is handled as usual.
+    pub fn handle_tab_menu_click(&mut self, x: f32, y: f32, viewport_width: f32) -> bool {
+        if !self.tab_menu.is_open() {
+            return false;
+        }
+        let layout = self.tab_menu_layout(viewport_width);
+        let entries = self.tab_menu.entries();
+        if let Some(row) = layout.row_at(x, y, entries.len()) {
+            let id = entries[row].id;
+            self.workspace.set_active(id);
+        }
+        self.tab_menu.close();
+        true
+    }
+
+    /// Updates which overflow menu entry is under the mouse. Returns true
+    /// if that changed.
+    pub fn update_tab_menu_hover(&mut self, x: f32, y: f32, viewport_width: f32) -> bool {
+        let layout = self.tab_menu_layout(viewport_width);
+        let row = layout.row_at(x, y, self.tab_menu.entries().len());
+        self.tab_menu.set_hovered(row)
+    }
+
+    /// Returns a tab's width: type label, name, padding and close button.
+    fn tab_width(tab: &TabLabel, char_width: f32) -> f32 {
+        (tab.icon.chars().count() as f32 + tab.name.len() as f32 + 5.0) * char_width + 24.0
+    }
+
+    /// Handles a click in the tab bar, returns the tab index if clicked on a tab.
+    pub fn handle_tab_bar_click(&self, x: f32) -> Option<usize> {
+        let char_width = self.chrome.char_width;
+        let mut current_x = 4.0; // Initial padding
+
+        for (index, tab) in self.workspace.tab_labels().enumerate() {
+            let tab_width = Self::tab_width(&tab, char_width);
+
+            if x >= current_x && x < current_x + tab_width {
+                return Some(index);
+            }
+
+            current_x += tab_width + 4.0; // Tab spacing
+        }
+
+        None
+    }
+
+    /// Draws the editor into `renderer`'s frame.
+    pub fn render(&self, renderer: &mut DrawList) {
+        renderer.clear();
+
+        let line_height = renderer.atlas().line_height;
+        let char_width = renderer.atlas().char_width;
+        let (viewport_width, viewport_height) = renderer.dimensions();
+        let content_y = self.content_y_offset();
+        let status_bar_height = self.status_bar_height();
+
+        // Draw tab bar, unless zen mode hides it
+        if !self.zen.is_active() {
+            self.render_tab_bar(renderer, viewport_width as f32);
+        }
+
+        // Draw search/replace/goto bar if active
+        if self.input_mode != InputMode::Normal {
+            self.render_input_bar(renderer, viewport_width as f32);
+        }
+
+        // Draw the conflict banner if the file changed under unsaved edits
+        if self.disk_banner_height() > 0.0 {
+            self.render_disk_banner(renderer, viewport_width as f32);
+        }
+
+        // Get active editor for rendering
+        let Some(editor) = self.workspace.active_editor() else {
+            return;
+        };
+
+        // Draw gutter background (below tab bar and search bar, above status
+        // bar); zen mode has no gutter
+        let gutter = self.gutter_layout(char_width);
+        let show_gutter = !self.zen.is_active();
+        let (text_left, text_width) = self.text_area(char_width, viewport_width as f32);
+        let gutter_x = text_left - gutter.width();
+        if show_gutter {
+            let content_height = viewport_height as f32 - content_y - status_bar_height;
+            renderer.draw_rect(gutter_x, content_y, gutter.width(), content_height, renderer.colors.line_number_bg);
+            if let Some((separator_x, separator_width)) = gutter.separator_line() {
+                renderer.draw_rect(
+                    gutter_x + separator_x,
+                    content_y,
+                    separator_width,
+                    content_height,
+                    renderer.colors.line_number,
+                );
+            }
+        }
+
+        let smooth_scroll = editor.smooth_scroll();
+        let horizontal_scroll = editor.horizontal_scroll();
+        // Columns that fit in the text area
+        let visible_cols = (text_width / char_width) as usize + 1;
+        let visible_lines = editor.visible_lines();
+        let buffer = editor.buffer();
+
+        // Calculate smooth scroll offset
+        let scroll_frac = smooth_scroll - smooth_scroll.floor();
+        let base_scroll_line = smooth_scroll.floor() as usize;
+
+        // Lists refilled each frame, keeping their memory between frames
+        let mut scratch = std::mem::take(&mut renderer.scratch);
+
+        // Get cursor positions for selection rendering (multi-cursor support)
+        let cursor_pos = editor.cursor_position();
+        editor.cursor_positions_into(&mut scratch.cursors);
+        let block_selection = editor.get_block_selection().copied();
+        let current_match = editor.current_search_match();
+
+        // Screen rows; with word wrap a line can take several of them
+        editor.visual_lines_into(base_scroll_line, visible_lines + 1, &mut scratch.rows);
+        let rows = &scratch.rows;
+
+        // Selections, search matches and diagnostics on the visible lines,
+        // looked up once for the frame rather than for each line
+        let last_line = rows.last().map_or(base_scroll_line, |row| row.line);
+        editor.selection_ranges_in_range(base_scroll_line, last_line, &mut scratch.selections);
+        let selection_ranges = &scratch.selections;
+        let search_matches = editor.search_matches_in_range(base_scroll_line, last_line);
+        editor.diagnostics_in_range(base_scroll_line, last_line, &mut scratch.diagnostics);
+        let diagnostics = scratch.diagnostics.iter().map(|&i| &editor.diagnostics()[i]);
+        let row_y = |row: usize| content_y + (row as f32 - scroll_frac) * line_height;
+        // Screen row and column a buffer position is drawn at
+        let screen_position = |line: usize, col: usize| {
+            let row = rows.iter().position(|r| r.line == line && r.contains(col))?;
+            let layout = editor.line_layout(line);
+            let col = (layout.display_col(col) - layout.display_col(rows[row].start_col)).checked_sub(horizontal_scroll)?;
+            Some((row, col))
+        };
+
+        // Draw visible lines
+        for (screen_line, row) in rows.iter().enumerate() {
+            let buffer_line = row.line;
+            // Display columns of the line, where invisible characters are
+            // drawn as wide boxes
+            let layout = editor.line_layout(buffer_line);
+            let row_start = layout.display_col(row.start_col);
+            // Clips a column range of the line to this row, in screen columns
+            let visible_span = |start: usize, end: usize| {
+                let (start, end) = row.clip(start, end)?;
+                let start = layout.display_col(row.start_col + start) - row_start;
+                let end = layout.display_col(row.start_col + end) - row_start;
+                Some((start.saturating_sub(horizontal_scroll), end.saturating_sub(horizontal_scroll)))
+            };
+
+            // Apply fractional scroll offset, accounting for tab bar and search bar
+            let y = row_y(screen_line);
+
+            // Line numbers and markers only go on a line's first row
+            if show_gutter && row.is_first() {
+                self.render_gutter_line(renderer, &gutter, gutter_x, editor, buffer_line, y, char_width, line_height);
+            }
+
+            // Draw search match highlights for this line
+            let line_start = buffer.line_start(buffer_line);
+            let line_end = buffer.line_end(buffer_line);
+            for m in viewport::overlapping(search_matches, line_start..line_end + 1, |m| m.start..m.end) {
+                // Check if match overlaps this line
+                if m.start < line_end + 1 && m.end > line_start {
+                    let match_start_on_line = m.start.saturating_sub(line_start);
+                    let match_end_on_line = if m.end < line_end + 1 {
+                        m.end - line_start
+                    } else {
+                        line_end - line_start + 1
+                    };
+
+                    let Some((visible_match_start, visible_match_end)) =
+                        visible_span(match_start_on_line, match_end_on_line)
+                    else {
+                        continue;
+                    };
+
+                    if visible_match_end > visible_match_start {
+                        let match_x = text_left + visible_match_start as f32 * char_width;
+                        let match_width = (visible_match_end - visible_match_start) as f32 * char_width;
+
+                        // Use brighter color for current match
+                        let color = if Some(*m) == current_match {
+                            renderer.colors.search_match_current
+                        } else {
+                            renderer.colors.search_match
+                        };
+
+                        renderer.draw_rect(match_x, y, match_width, line_height, color);
+                    }
+                }
+            }
+
+            // Draw selection backgrounds for this line (all cursors)
+            let line_selections = viewport::overlapping(selection_ranges, line_start..line_end + 1, |&(start, end)| start..end);
+            for &(sel_start, sel_end) in line_selections {
+                // Check if selection overlaps this line
+                if sel_start < line_end + 1 && sel_end > line_start {
+                    let sel_start_on_line = sel_start.saturating_sub(line_start);
+                    let sel_end_on_line = if sel_end < line_end + 1 {
+                        sel_end - line_start
+                    } else {
+                        line_end - line_start + 1
+                    };
+
+                    let Some((visible_sel_start, visible_sel_end)) = visible_span(sel_start_on_line, sel_end_on_line)
+                    else {
+                        continue;
+                    };
+
+                    if visible_sel_end > 0 {
+                        let sel_x = text_left + visible_sel_start as f32 * char_width;
+                        let sel_width = (visible_sel_end - visible_sel_start) as f32 * char_width;
+
+                        renderer.draw_rect(
+                            sel_x,
+                            y,
+                            sel_width.max(char_width * 0.5),
+                            line_height,
+                            renderer.colors.selection,
+                        );
+                    }
+                }
+            }
+
+            // Draw block selection for this line (if active)
+            if let Some(ref block) = block_selection {
+                let (top, bottom) = block.bounds();
+                if buffer_line >= top.line && buffer_line <= bottom.line {
+                    if let Some((visible_start, visible_end)) = block
+                        .col_range(buffer, buffer_line)
+                        .and_then(|(start_col, end_col)| visible_span(start_col, end_col))
+                    {
+                        if visible_end > visible_start {
+                            let block_x = text_left + visible_start as f32 * char_width;
+                            let block_width = (visible_end - visible_start) as f32 * char_width;
+
+                            renderer.draw_rect(
+                                block_x,
+                                y,
+                                block_width.max(char_width * 0.5),
+                                line_height,
+                                renderer.colors.selection,
+                            );
+                        }
+                    }
+                }
+            }
+
+            // Draw line text with syntax highlighting, only the columns in
+            // view, so a very long line costs no more than a short one
+            {
+                let x = text_left;
+                let char_width = renderer.atlas().char_width;
+                let first_col = row.start_col + horizontal_scroll;
+                let end_col = row.end_col.min(first_col + visible_cols);
+                let row_text = buffer.line_slice_chars(buffer_line, first_col, end_col);
+
+                if !layout.is_identity() {
+                    // Boxes make the columns uneven; place each character
+                    // at its display column, starting from the one at the
+                    // left edge
+                    let start = layout.col_at(row_start + horizontal_scroll).saturating_sub(1).max(row.start_col);
+                    let row_chars = buffer.line_slice_chars(buffer_line, start, row.end_col.min(start + visible_cols + 1));
+                    for (col, ch) in (start..).zip(row_chars) {
+                        let Some(screen_col) = (layout.display_col(col) - row_start).checked_sub(horizontal_scroll)
+                        else {
+                            continue;
+                        };
+                        if screen_col >= visible_cols {
+                            break;
+                        }
+                        let char_x = x + screen_col as f32 * char_width;
+                        if invisible::is_invisible(ch) {
+                            Self::render_invisible_char(renderer, ch, char_x, y, char_width, line_height);
+                        } else if editor.has_syntax_highlighting() {
+                            let color = editor
+                                .bracket_color_at(buffer_line, col)
+                                .unwrap_or_else(|| editor.highlight_color_at(buffer_line, col));
+                            renderer.draw_char(ch, char_x, y, color);
+                        } else {
+                            renderer.draw_char(ch, char_x, y, renderer.colors.text);
+                        }
+                    }
+                } else if editor.has_syntax_highlighting() {
+                    // Check if syntax highlighting is available
+                    // Draw each character with its highlight color
+                    for (i, ch) in row_text.enumerate() {
+                        let col = first_col + i;
+                        let color = editor
+                            .bracket_color_at(buffer_line, col)
+                            .unwrap_or_else(|| editor.highlight_color_at(buffer_line, col));
+                        let char_x = x + i as f32 * char_width;
+                        renderer.draw_char(ch, char_x, y, color);
+                    }
+                } else {
+                    // No highlighting, draw with default color
+                    renderer.draw_chars(row_text, x, y, renderer.colors.text);
+                }
+            }
+
+            // Draw diagnostic underlines for this line
+            for diagnostic in diagnostics.clone().filter(|d| d.on_line(buffer_line)) {
+                // Determine color based on severity
+                let mut color = match diagnostic.severity {
+                    DiagnosticSeverity::Error => renderer.colors.diagnostic_error,
+                    DiagnosticSeverity::Warning => renderer.colors.diagnostic_warning,
+                    DiagnosticSeverity::Information => renderer.colors.diagnostic_info,
+                    DiagnosticSeverity::Hint => renderer.colors.diagnostic_hint,
+                };
+                if editor.diagnostics_stale() {
+                    color[3] *= STALE_DIAGNOSTIC_ALPHA;
+                }
+
+                // Calculate the start and end columns on this line
+                let diag_start_col = if diagnostic.start_line == buffer_line {
+                    diagnostic.start_col
+                } else {
+                    0
+                };
+                let diag_end_col = if diagnostic.end_line == buffer_line {
+                    diagnostic.end_col
+                } else {
+                    buffer.line_len_chars(buffer_line)
+                };
+
+                let Some((visible_start, visible_end)) = visible_span(diag_start_col, diag_end_col) else {
+                    continue;
+                };
+
+                if visible_end > visible_start {
+                    let underline_x = text_left + visible_start as f32 * char_width;
+                    let underline_width = (visible_end - visible_start) as f32 * char_width;
+
+                    // Use squiggly underline for errors/warnings, simple underline for info/hints
+                    match diagnostic.severity {
+                        DiagnosticSeverity::Error | DiagnosticSeverity::Warning => {
+                            renderer.draw_squiggle(underline_x, y, underline_width, line_height, color);
+                        }
+                        _ => {
+                            renderer.draw_underline(underline_x, y, underline_width, line_height, color);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Draw the other pane of a split
+        if let Some(pane) = self.workspace.unfocused_pane().filter(|_| !self.zen.is_active()) {
+            // The banner is over the focused pane only
+            let top = content_y - self.disk_banner_height();
+            let content_height = viewport_height as f32 - top - status_bar_height;
+            let rows = &mut scratch.pane_rows;
+            self.render_unfocused_pane(renderer, pane, rows, viewport_width as f32, top, content_height);
+        }
+
+        // Draw bracket match highlighting
+        if let Some((bracket_pos, match_pos)) = editor.matching_bracket_at_cursor() {
+            // Helper to draw bracket highlight at a position
+            let draw_bracket_highlight = |renderer: &mut DrawList, char_pos: usize| {
+                let (line, col) = buffer.char_to_line_col(char_pos);
+                if let Some((screen_line, screen_col)) = screen_position(line, col) {
+                    let x = text_left + screen_col as f32 * char_width;
+                    let y = row_y(screen_line);
+
+                    if y >= content_y && y < viewport_height as f32 {
+                        renderer.draw_rect(x, y, char_width, line_height, renderer.colors.bracket_match);
+                    }
+                }
+            };
+
+            draw_bracket_highlight(renderer, bracket_pos);
+            draw_bracket_highlight(renderer, match_pos);
+        }
+
+        // Draw all cursors (multi-cursor support)
+        if self.cursor_blink.should_draw() {
+            for &(cursor_line, cursor_col) in &scratch.cursors {
+                if let Some((cursor_screen_line, cursor_screen_col)) = screen_position(cursor_line, cursor_col) {
+                    let cursor_x = text_left + cursor_screen_col as f32 * char_width;
+                    let cursor_y = row_y(cursor_screen_line);
+
+                    // Only draw if cursor is within visible area
+                    if cursor_y >= content_y && cursor_y < viewport_height as f32 {
+                        renderer.draw_rect(cursor_x, cursor_y, 2.0, line_height, renderer.colors.cursor);
+                    }
+                }
+            }
+        }
+
+        // Popups are placed from their buffer anchor every frame, so they
+        // move with their text while scrolling. Screen point of the bottom
+        // left corner of an anchor's character cell:
+        let anchor_point = |line: usize, col: usize| {
+            screen_position(line, col).map(|(row, col)| (text_left + col as f32 * char_width, row_y(row + 1)))
+        };
+
+        // Draw hover popup if we have hover info
+        if let (Some(hover_info), Some(anchor)) = (editor.hover_info(), &self.hover_anchor) {
+            let point = match anchor {
+                HoverAnchor::Mouse { x, y, .. } => Some((*x, *y)),
+                HoverAnchor::Text(anchor) => anchor_point(anchor.line, anchor.col),
+            };
+            if let Some((x, y)) = point {
+                self.render_hover_popup(renderer, &hover_info.contents, x, y, viewport_width as f32, viewport_height as f32);
+            }
+        }
+
+        // Draw signature help above the call it is for
+        if let (Some(help), Some(anchor)) = (editor.signature_help(), &self.signature_anchor) {
+            if let Some((x, y)) = anchor_point(anchor.line, anchor.col) {
+                self.render_signature_help_popup(renderer, help, x, y, viewport_width as f32, viewport_height as f32);
+            }
+        }
+
+        // Draw completion popup below where completion was triggered
+        if self.completion_visible {
+            let completions = editor.completions();
+            let (line, col) = self
+                .completion_anchor
+                .as_ref()
+                .map_or((cursor_pos.line, cursor_pos.col), |anchor| (anchor.line, anchor.col));
+            if let (false, Some((popup_x, popup_y))) = (completions.is_empty(), anchor_point(line, col)) {
+                self.render_completion_popup(
+                    renderer,
+                    completions,
+                    self.completion_selected,
+                    popup_x,
+                    popup_y,
+                    viewport_width as f32,
+                    viewport_height as f32,
+                    line_height,
+                );
+            }
+        }
+
+        // Draw the code actions menu below where it was asked for
+        if let (Some(menu), Some(anchor)) = (&self.code_actions, &self.code_action_anchor) {
+            if let Some((x, y)) = anchor_point(anchor.line, anchor.col) {
+                self.render_code_actions_menu(
+                    renderer,
+                    menu,
+                    x,
+                    y,
+                    viewport_width as f32,
+                    viewport_height as f32,
+                    line_height,
+                );
+            }
+        }
+
+        // Draw line diagnostics popup under its line
+        if let Some(popup) = &self.diagnostics_popup {
+            let popup_y = match rows.iter().rposition(|row| row.line == popup.line()) {
+                Some(row) => row_y(row + 1),
+                None => content_y + ((popup.line() as f32 - smooth_scroll) + 1.0) * line_height,
+            };
+            self.render_diagnostics_popup(
+                renderer,
+                popup,
+                text_left,
+                popup_y,
+                viewport_width as f32,
+                viewport_height as f32,
+                line_height,
+            );
+        }
+
+        // Draw lint output above the status bar
+        if self.show_lint_panel {
+            self.render_lint_panel(renderer, viewport_width as f32, viewport_height as f32, char_width, line_height);
+        }
+
+        // Draw status bar at the bottom, unless zen mode hides it
+        if !self.zen.is_active() {
+            self.render_status_bar(renderer, viewport_width as f32, viewport_height as f32);
+        }
+
+        // Draw project search results, recent files or problems below the input bar
+        if self.input_mode == InputMode::ProjectSearch {
+            self.render_project_search_results(renderer, viewport_width as f32, viewport_height as f32, char_width, line_height);
+        }
+        if self.input_mode == InputMode::RecentFiles {
+            let labels = self.recent_matches.iter().map(|path| path.display().to_string());
+            let (width, height) = (viewport_width as f32, viewport_height as f32);
+            self.render_result_list(renderer, width, height, char_width, line_height, labels, self.recent_selected);
+        }
+        if self.input_mode == InputMode::Encodings {
+            let current = self.workspace.active_editor().map(Editor::encoding);
+            let labels = self.encoding_matches.iter().map(|&encoding| {
+                if Some(encoding) == current {
+                    format!("{} (current)", encoding.label())
+                } else {
+                    encoding.label().to_string()
+                }
+            });
+            let (width, height) = (viewport_width as f32, viewport_height as f32);
+            self.render_result_list(renderer, width, height, char_width, line_height, labels, self.encoding_selected);
+        }
+        if self.input_mode == InputMode::Problems {
+            let labels = self.problems.rows().into_iter().map(|row| row.label);
+            let (width, height) = (viewport_width as f32, viewport_height as f32);
+            self.render_result_list(renderer, width, height, char_width, line_height, labels, self.problems.selected_row());
+        }
+
+        // Draw Ctrl+Tab switcher on top of everything but notifications
+        if self.tab_switcher.is_active() {
+            self.render_tab_switcher(renderer, viewport_width as f32, viewport_height as f32);
+        }
+
+        // Draw the tab overflow menu over the text
+        if self.tab_menu.is_open() {
+            self.render_tab_menu(renderer, viewport_width as f32);
+        }
+
+        // Draw notifications in top-right corner
+        self.render_notifications(renderer, viewport_width as f32);
+
+        renderer.scratch = scratch;
+    }
+
+    /// Renders the tab bar with the open buffers' tabs.
+    fn render_tab_bar(&self, renderer: &mut DrawList, viewport_width: f32) {
+        let tab_bar_height = self.chrome.tab_bar_height();
+
+        // Draw tab bar background
+        renderer.draw_rect(
+            0.0,
+            0.0,
+            viewport_width,
+            tab_bar_height,
+            renderer.colors.tab_bar_bg,
+        );
+
+        // Draw tabs; when they overflow, the ones that don't fit before the
+        // menu button are left out
+        let active_index = self.workspace.active_tab_index();
+        let mut tab_x = 4.0;
+        let tab_text_y = (tab_bar_height - self.chrome.line_height) / 2.0;
+        let overflow = self.tabs_overflow(viewport_width);
+        let tabs_end = if overflow {
+            viewport_width - self.tab_menu_button_width()
+        } else {
+            viewport_width
+        };
+
+        for (index, tab) in self.workspace.tab_labels().enumerate() {
+            let is_active = Some(index) == active_index;
+            let tab_width = Self::tab_width(&tab, self.chrome.char_width);
+            if tab_x + tab_width > tabs_end {
+                break;
+            }
 
             // Tab background
             let bg_color = if is_active {
@@ -803,343 +3455,485 @@ impl EditorApp {
             } else {
                 renderer.colors.tab_inactive_bg
             };
-            renderer.draw_rect(tab_x, 2.0, tab_width, TAB_BAR_HEIGHT - 4.0, bg_color);
+            renderer.draw_rect(tab_x, 2.0, tab_width, tab_bar_height - 4.0, bg_color);
 
             // Tab text (with modified indicator)
-            let display_name = if tab.is_modified {
-                format!("● {}", tab.name)
-            } else {
-                tab.name.clone()
-            };
             let text_color = if is_active {
                 renderer.colors.text
             } else {
                 renderer.colors.line_number
             };
-            renderer.draw_text(&display_name, tab_x + 8.0, 6.0, text_color);
+            // File-type label, dimmed, in front of the name
+            renderer.draw_ui_text(tab.icon, tab_x + 8.0, tab_text_y, renderer.colors.line_number);
+            let mut name_x = tab_x + 8.0 + (tab.icon.chars().count() as f32 + 1.0) * self.chrome.char_width;
+            if tab.is_modified {
+                renderer.draw_ui_text("● ", name_x, tab_text_y, text_color);
+                name_x += 2.0 * self.chrome.char_width;
+            }
+            renderer.draw_ui_text(tab.name, name_x, tab_text_y, text_color);
 
             tab_x += tab_width + 4.0;
         }
 
+        // Overflow menu button: a "v" chevron at the end of the tab bar
+        if overflow {
+            let button_width = self.tab_menu_button_width();
+            let button_x = viewport_width - button_width;
+            let bg = if self.tab_menu.is_open() {
+                renderer.colors.tab_active_bg
+            } else {
+                renderer.colors.tab_inactive_bg
+            };
+            renderer.draw_rect(button_x, 2.0, button_width, tab_bar_height - 4.0, bg);
+            renderer.draw_ui_text("v", button_x + self.chrome.char_width, tab_text_y, renderer.colors.text);
+        }
+
         // Draw separator line below tab bar
         renderer.draw_rect(
             0.0,
-            TAB_BAR_HEIGHT - 1.0,
-            viewport_width as f32,
+            tab_bar_height - 1.0,
+            viewport_width,
             1.0,
             renderer.colors.line_number,
         );
+    }
 
-        // Draw search/replace/goto bar if active
-        if self.input_mode != InputMode::Normal {
-            self.render_input_bar(renderer, viewport_width as f32, char_width, line_height);
+    /// Renders the tab overflow menu under the end of the tab bar. The
+    /// hovered entry's full path is shown in a tooltip to its left.
+    fn render_tab_menu(&self, renderer: &mut DrawList, viewport_width: f32) {
+        let ChromeMetrics { char_width, line_height } = self.chrome;
+        const DOT_SIZE: f32 = 6.0;
+
+        let layout = self.tab_menu_layout(viewport_width);
+        let (x, y, width, height) = (layout.x, layout.y, layout.width, layout.height);
+        renderer.draw_rect(x, y, width, height, renderer.colors.completion_bg);
+        let border = renderer.colors.completion_border;
+        renderer.draw_rect(x, y, width, 1.0, border);
+        renderer.draw_rect(x, y + height - 1.0, width, 1.0, border);
+        renderer.draw_rect(x, y, 1.0, height, border);
+        renderer.draw_rect(x + width - 1.0, y, 1.0, height, border);
+
+        let max_chars = ((width - 2.0 * layout.padding) / char_width) as usize;
+        for (index, entry) in self.tab_menu.entries().iter().enumerate() {
+            let row_y = layout.row_y(index);
+            if Some(index) == self.tab_menu.hovered_index() {
+                renderer.draw_rect(x + 1.0, row_y, width - 2.0, line_height, renderer.colors.completion_selected_bg);
+            }
+
+            // Modified dot (drawn as a rect; the font atlas is ASCII-only)
+            let text_x = x + layout.padding;
+            if entry.is_modified {
+                renderer.draw_rect(
+                    text_x + (char_width - DOT_SIZE) / 2.0,
+                    row_y + (line_height - DOT_SIZE) / 2.0,
+                    DOT_SIZE,
+                    DOT_SIZE,
+                    renderer.colors.text,
+                );
+            }
+
+            let color = if entry.is_active {
+                renderer.colors.text
+            } else {
+                renderer.colors.line_number
+            };
+            let name: String = entry.name.chars().take(max_chars.saturating_sub(2)).collect();
+            renderer.draw_ui_text(&name, text_x + 2.0 * char_width, row_y, color);
         }
 
-        // Get active editor for rendering
-        let Some(editor) = self.workspace.active_editor() else {
+        // Full path of the hovered entry
+        let Some(index) = self.tab_menu.hovered_index() else {
+            return;
+        };
+        let Some(path) = self.tab_menu.hovered().and_then(|entry| entry.path.as_ref()) else {
             return;
         };
+        let path = path.display().to_string();
+        let tip_width = (path.chars().count() as f32 * char_width + 2.0 * layout.padding).min(x);
+        let tip_x = x - tip_width;
+        let tip_y = layout.row_y(index);
+        renderer.draw_rect(tip_x, tip_y, tip_width, line_height, renderer.colors.hover_bg);
+        let tip_chars = ((tip_width - 2.0 * layout.padding) / char_width).max(0.0) as usize;
+        // Keep the end of a long path, which names the file
+        let skip = path.chars().count().saturating_sub(tip_chars);
+        let shown: String = path.chars().skip(skip).collect();
+        renderer.draw_ui_text(&shown, tip_x + layout.padding, tip_y, renderer.colors.text);
+    }
 
-        // Draw line number background (below tab bar and search bar, above status bar)
-        renderer.draw_rect(
-            0.0,
-            content_y,
-            self.line_number_margin,
-            viewport_height as f32 - content_y - STATUS_BAR_HEIGHT,
-            renderer.colors.line_number_bg,
-        );
+    /// Renders the project search result list as a panel over the text area.
+    fn render_project_search_results(
+        &self,
+        renderer: &mut DrawList,
+        viewport_width: f32,
+        viewport_height: f32,
+        char_width: f32,
+        line_height: f32,
+    ) {
+        let results = self.project_search.results();
+        let root = self.project_search.root();
+        let labels = results.iter().map(|hit| hit.label(root));
+        let selected = self.project_search.selected_index();
+        self.render_result_list(renderer, viewport_width, viewport_height, char_width, line_height, labels, selected);
+    }
 
-        let smooth_scroll = editor.smooth_scroll();
-        let horizontal_scroll = editor.horizontal_scroll();
-        let visible_lines = editor.visible_lines();
-        let buffer = editor.buffer();
-        let total_lines = buffer.len_lines();
+    /// Renders a list of results as a panel over the top of the text area,
+    /// scrolled so the selected row stays visible.
+    #[allow(clippy::too_many_arguments)]
+    fn render_result_list(
+        &self,
+        renderer: &mut DrawList,
+        viewport_width: f32,
+        viewport_height: f32,
+        char_width: f32,
+        line_height: f32,
+        labels: impl ExactSizeIterator<Item = String>,
+        selected: usize,
+    ) {
+        const PADDING: f32 = 8.0;
 
-        // Calculate smooth scroll offset
-        let scroll_frac = smooth_scroll - smooth_scroll.floor();
-        let base_scroll_line = smooth_scroll.floor() as usize;
+        if labels.len() == 0 {
+            return;
+        }
 
-        // Get cursor positions for selection rendering (multi-cursor support)
-        let cursor_pos = editor.cursor_position();
-        let all_cursor_positions = editor.all_cursor_positions();
-        let all_selection_ranges = editor.all_selection_ranges();
-        let block_selection = editor.get_block_selection().copied();
+        let panel_y = self.content_y_offset();
+        let max_height = (viewport_height - panel_y - self.status_bar_height()) / 2.0;
+        let max_rows = (((max_height - 2.0 * PADDING) / line_height) as usize).max(1);
+        let rows = labels.len().min(max_rows);
+        let panel_height = rows as f32 * line_height + 2.0 * PADDING;
 
-        // Get search matches for visible lines
-        let search_matches = editor.search_matches_in_range(base_scroll_line, base_scroll_line + visible_lines);
-        let current_match = editor.current_search_match();
+        renderer.draw_rect(0.0, panel_y, viewport_width, panel_height, renderer.colors.completion_bg);
+        renderer.draw_rect(0.0, panel_y + panel_height - 1.0, viewport_width, 1.0, renderer.colors.completion_border);
 
-        // Draw visible lines
-        for screen_line in 0..=visible_lines {
-            let buffer_line = base_scroll_line + screen_line;
-            if buffer_line >= total_lines {
-                break;
+        let first = selected.saturating_sub(rows - 1);
+        let max_chars = ((viewport_width - 2.0 * PADDING) / char_width) as usize;
+
+        for (row, (index, label)) in labels.enumerate().skip(first).take(rows).enumerate() {
+            let row_y = panel_y + PADDING + row as f32 * line_height;
+            if index == selected {
+                renderer.draw_rect(0.0, row_y, viewport_width, line_height, renderer.colors.completion_selected_bg);
             }
+            let label: String = label.chars().take(max_chars).collect();
+            renderer.draw_text(&label, PADDING, row_y, renderer.colors.text);
+        }
+    }
 
-            // Apply fractional scroll offset, accounting for tab bar and search bar
-            let y = content_y + (screen_line as f32 - scroll_frac) * line_height;
+    /// Renders the last lines of linter output above the status bar.
+    fn render_lint_panel(
+        &self,
+        renderer: &mut DrawList,
+        viewport_width: f32,
+        viewport_height: f32,
+        char_width: f32,
+        line_height: f32,
+    ) {
+        const PADDING: f32 = 8.0;
 
-            // Draw line number
-            let line_num_str = format!("{:>4}", buffer_line + 1);
-            renderer.draw_text(&line_num_str, 4.0, y, renderer.colors.line_number);
+        let panel_height = (LINT_PANEL_ROWS + 1) as f32 * line_height + 2.0 * PADDING;
+        let panel_y = (viewport_height - self.status_bar_height() - panel_height).max(self.content_y_offset());
 
-            // Draw search match highlights for this line
-            let line_start = buffer.line_start(buffer_line);
-            let line_end = buffer.line_end(buffer_line);
-            for m in &search_matches {
-                // Check if match overlaps this line
-                if m.start < line_end + 1 && m.end > line_start {
-                    let match_start_on_line = if m.start > line_start {
-                        m.start - line_start
-                    } else {
-                        0
-                    };
-                    let match_end_on_line = if m.end < line_end + 1 {
-                        m.end - line_start
-                    } else {
-                        line_end - line_start + 1
-                    };
+        renderer.draw_rect(0.0, panel_y, viewport_width, panel_height, renderer.colors.completion_bg);
+        renderer.draw_rect(0.0, panel_y, viewport_width, 1.0, renderer.colors.completion_border);
 
-                    // Apply horizontal scroll offset
-                    let visible_match_start = match_start_on_line.saturating_sub(horizontal_scroll);
-                    let visible_match_end = match_end_on_line.saturating_sub(horizontal_scroll);
+        let max_chars = ((viewport_width - 2.0 * PADDING) / char_width) as usize;
+        let title = match self.lint_runner.command() {
+            Some(command) if self.lint_runner.is_running() => format!("Lint: {} (running...)", command),
+            Some(command) => format!("Lint: {}", command),
+            None => "Lint: no linter has run (F7 to lint the current file)".to_string(),
+        };
+        let title: String = title.chars().take(max_chars).collect();
+        let mut row_y = panel_y + PADDING;
+        renderer.draw_text(&title, PADDING, row_y, renderer.colors.line_number);
+
+        let first = self.lint_output.len().saturating_sub(LINT_PANEL_ROWS);
+        for line in &self.lint_output[first..] {
+            row_y += line_height;
+            // Draw the tool's colors, cut off at the panel's edge
+            let mut x = PADDING;
+            let mut remaining = max_chars;
+            for run in ansi::parse_line(line) {
+                let text: String = run.text.chars().take(remaining).collect();
+                let len = text.chars().count();
+                renderer.draw_text(&text, x, row_y, run.color.rgba(renderer.colors.text));
+                x += len as f32 * char_width;
+                remaining -= len;
+                if remaining == 0 {
+                    break;
+                }
+            }
+        }
+    }
 
-                    if visible_match_end > visible_match_start {
-                        let match_x = self.line_number_margin + visible_match_start as f32 * char_width;
-                        let match_width = (visible_match_end - visible_match_start) as f32 * char_width;
+    /// Renders the Ctrl+Tab switcher centered in the window.
+    fn render_tab_switcher(
+        &self,
+        renderer: &mut DrawList,
+        viewport_width: f32,
+        viewport_height: f32,
+    ) {
+        let ChromeMetrics { char_width, line_height } = self.chrome;
+        const PADDING: f32 = 8.0;
+        const DOT_SIZE: f32 = 6.0;
+        const MIN_WIDTH: f32 = 200.0;
 
-                        // Use brighter color for current match
-                        let color = if Some(*m) == current_match {
-                            renderer.colors.search_match_current
-                        } else {
-                            renderer.colors.search_match
-                        };
+        let tabs = self.workspace.tabs();
+        let rows: Vec<_> = self
+            .tab_switcher
+            .entries()
+            .iter()
+            .filter_map(|&id| tabs.iter().find(|tab| tab.id == id))
+            .collect();
+        if rows.is_empty() {
+            return;
+        }
 
-                        renderer.draw_rect(match_x, y, match_width, line_height, color);
-                    }
-                }
-            }
+        // Leave room on the left of each name for the modified dot
+        let name_x_offset = DOT_SIZE + PADDING;
+        let max_name_len = rows.iter().map(|tab| tab.name.chars().count()).max().unwrap_or(0);
+        let popup_width = (max_name_len as f32 * char_width + name_x_offset + 2.0 * PADDING)
+            .max(MIN_WIDTH)
+            .min(viewport_width - 2.0 * PADDING);
+        let popup_height = rows.len() as f32 * line_height + 2.0 * PADDING;
 
-            // Draw selection backgrounds for this line (all cursors)
-            let line_start = buffer.line_start(buffer_line);
-            let line_end = buffer.line_end(buffer_line);
+        let popup_x = ((viewport_width - popup_width) / 2.0).max(0.0);
+        let popup_y = ((viewport_height - popup_height) / 2.0).max(self.content_y_offset());
 
-            for selection_range in &all_selection_ranges {
-                if let Some((sel_start, sel_end)) = selection_range {
-                    // Check if selection overlaps this line
-                    if *sel_start < line_end + 1 && *sel_end > line_start {
-                        let sel_start_on_line = if *sel_start > line_start {
-                            *sel_start - line_start
-                        } else {
-                            0
-                        };
-                        let sel_end_on_line = if *sel_end < line_end + 1 {
-                            *sel_end - line_start
-                        } else {
-                            line_end - line_start + 1
-                        };
+        // Background and border
+        renderer.draw_rect(popup_x, popup_y, popup_width, popup_height, renderer.colors.completion_bg);
+        let border = renderer.colors.completion_border;
+        renderer.draw_rect(popup_x, popup_y, popup_width, 1.0, border);
+        renderer.draw_rect(popup_x, popup_y + popup_height - 1.0, popup_width, 1.0, border);
+        renderer.draw_rect(popup_x, popup_y, 1.0, popup_height, border);
+        renderer.draw_rect(popup_x + popup_width - 1.0, popup_y, 1.0, popup_height, border);
 
-                        // Apply horizontal scroll offset to selection
-                        let visible_sel_start = sel_start_on_line.saturating_sub(horizontal_scroll);
-                        let visible_sel_end = sel_end_on_line.saturating_sub(horizontal_scroll);
+        let max_chars = ((popup_width - name_x_offset - 2.0 * PADDING) / char_width) as usize;
+        let selected = self.tab_switcher.selected_index();
 
-                        if visible_sel_end > 0 {
-                            let sel_x = self.line_number_margin + visible_sel_start as f32 * char_width;
-                            let sel_width = (visible_sel_end - visible_sel_start) as f32 * char_width;
+        for (index, tab) in rows.iter().enumerate() {
+            let row_y = popup_y + PADDING + index as f32 * line_height;
 
-                            renderer.draw_rect(
-                                sel_x,
-                                y,
-                                sel_width.max(char_width * 0.5),
-                                line_height,
-                                renderer.colors.selection,
-                            );
-                        }
-                    }
-                }
+            if index == selected {
+                renderer.draw_rect(
+                    popup_x + 1.0,
+                    row_y,
+                    popup_width - 2.0,
+                    line_height,
+                    renderer.colors.completion_selected_bg,
+                );
             }
 
-            // Draw block selection for this line (if active)
-            if let Some(ref block) = block_selection {
-                let (top, bottom) = block.bounds();
-                if buffer_line >= top.line && buffer_line <= bottom.line {
-                    if let Some((start_col, end_col)) = block.col_range(buffer, buffer_line) {
-                        // Apply horizontal scroll offset
-                        let visible_start = start_col.saturating_sub(horizontal_scroll);
-                        let visible_end = end_col.saturating_sub(horizontal_scroll);
+            // Modified dot (drawn as a rect; the font atlas is ASCII-only)
+            if tab.is_modified {
+                renderer.draw_rect(
+                    popup_x + PADDING,
+                    row_y + (line_height - DOT_SIZE) / 2.0,
+                    DOT_SIZE,
+                    DOT_SIZE,
+                    renderer.colors.text,
+                );
+            }
 
-                        if visible_end > visible_start {
-                            let block_x = self.line_number_margin + visible_start as f32 * char_width;
-                            let block_width = (visible_end - visible_start) as f32 * char_width;
+            let name: String = tab.name.chars().take(max_chars).collect();
+            renderer.draw_ui_text(&name, popup_x + PADDING + name_x_offset, row_y, renderer.colors.text);
+        }
+    }
 
-                            renderer.draw_rect(
-                                block_x,
-                                y,
-                                block_width.max(char_width * 0.5),
-                                line_height,
-                                renderer.colors.selection,
-                            );
-                        }
-                    }
+    /// Draws an invisible character as a box holding its code point.
+    fn render_invisible_char(renderer: &mut DrawList, ch: char, x: f32, y: f32, char_width: f32, line_height: f32) {
+        let color = renderer.colors.diagnostic_warning;
+        let width = invisible::display_width(ch) as f32 * char_width - 2.0;
+        let (x, top, height) = (x + 1.0, y + 2.0, line_height - 4.0);
+        renderer.draw_rect(x, top, width, 1.0, color);
+        renderer.draw_rect(x, top + height - 1.0, width, 1.0, color);
+        renderer.draw_rect(x, top, 1.0, height, color);
+        renderer.draw_rect(x + width - 1.0, top, 1.0, height, color);
+        renderer.draw_text(&invisible::label(ch), x - 1.0 + char_width, y, color);
+    }
+
+    /// Draws the pane of a split that doesn't have the focus: its line
+    /// numbers and text where it was left scrolled, and a line between the
+    /// panes. Cursors, selections and popups only show in the focused pane.
+    /// `rows` is filled with its screen rows.
+    fn render_unfocused_pane(
+        &self,
+        renderer: &mut DrawList,
+        pane: &Pane,
+        rows: &mut Vec<VisualLine>,
+        viewport_width: f32,
+        top: f32,
+        height: f32,
+    ) {
+        let Some(editor) = self.workspace.get_buffer(pane.buffer) else {
+            return;
+        };
+        let char_width = renderer.atlas().char_width;
+        let line_height = renderer.atlas().line_height;
+        let (focused_left, focused_width) = self.pane_area(viewport_width);
+        let (left, width, divider_x) = match self.workspace.focused_side() {
+            PaneSide::Left => (focused_width, viewport_width - focused_width, focused_width),
+            PaneSide::Right => (0.0, focused_left, focused_left),
+        };
+        renderer.draw_rect(left, top, width, height, renderer.colors.background);
+
+        let gutter = GutterLayout::compute(&self.gutter, char_width, editor.buffer().len_lines());
+        renderer.draw_rect(left, top, gutter.width(), height, renderer.colors.line_number_bg);
+        let text_left = left + gutter.width();
+        let visible_cols = ((left + width - text_left) / char_width).max(0.0) as usize;
+        let visible_rows = (height / line_height).ceil() as usize;
+
+        editor.visual_lines_into(pane.view.scroll_offset, visible_rows, rows);
+        for (screen_line, row) in rows.iter().enumerate() {
+            let y = top + screen_line as f32 * line_height;
+            if row.is_first() {
+                self.render_gutter_line(renderer, &gutter, left, editor, row.line, y, char_width, line_height);
+            }
+            let first_col = row.start_col + pane.view.horizontal_scroll;
+            let end_col = row.end_col.min(first_col + visible_cols);
+            let row_text = editor.buffer().line_slice_chars(row.line, first_col, end_col);
+            if editor.has_syntax_highlighting() {
+                for (i, ch) in row_text.enumerate() {
+                    let col = first_col + i;
+                    let color = editor
+                        .bracket_color_at(row.line, col)
+                        .unwrap_or_else(|| editor.highlight_color_at(row.line, col));
+                    renderer.draw_char(ch, text_left + i as f32 * char_width, y, color);
                 }
+            } else {
+                renderer.draw_chars(row_text, text_left, y, renderer.colors.text);
             }
+        }
 
-            // Draw line text with syntax highlighting
-            if let Some(line_text) = buffer.line(buffer_line) {
-                let x = self.line_number_margin;
-                let char_width = renderer.atlas().char_width;
+        renderer.draw_rect(divider_x, top, 1.0, height, renderer.colors.line_number);
+    }
 
-                // Check if syntax highlighting is available
-                if editor.has_syntax_highlighting() {
-                    // Draw each character with its highlight color
-                    for (i, ch) in line_text.chars().skip(horizontal_scroll).enumerate() {
-                        let col = horizontal_scroll + i;
-                        let color = editor.highlight_color_at(buffer_line, col);
-                        let char_x = x + i as f32 * char_width;
-                        renderer.draw_char(ch, char_x, y, color);
+    /// Renders the gutter columns for one buffer line at screen row `y`,
+    /// with the gutter starting at `gutter_x`.
+    #[allow(clippy::too_many_arguments)]
+    fn render_gutter_line(
+        &self,
+        renderer: &mut DrawList,
+        gutter: &GutterLayout,
+        gutter_x: f32,
+        editor: &Editor,
+        buffer_line: usize,
+        y: f32,
+        char_width: f32,
+        line_height: f32,
+    ) {
+        for region in gutter.regions() {
+            let region_x = gutter_x + region.x;
+            match region.column {
+                GutterColumn::Bookmarks => {
+                    if editor.has_bookmark(buffer_line) {
+                        let height = line_height * 0.6;
+                        renderer.draw_rect(
+                            region_x + (region.width - char_width) / 2.0,
+                            y + (line_height - height) / 2.0,
+                            char_width,
+                            height,
+                            renderer.colors.cursor,
+                        );
                     }
-                } else {
-                    // No highlighting, draw with default color
-                    let visible_text: String = line_text.chars().skip(horizontal_scroll).collect();
-                    renderer.draw_text(&visible_text, x, y, renderer.colors.text);
                 }
-            }
-
-            // Draw diagnostic underlines for this line
-            for diagnostic in editor.diagnostics_on_line(buffer_line) {
-                // Determine color based on severity
-                let color = match diagnostic.severity {
-                    DiagnosticSeverity::Error => renderer.colors.diagnostic_error,
-                    DiagnosticSeverity::Warning => renderer.colors.diagnostic_warning,
-                    DiagnosticSeverity::Information => renderer.colors.diagnostic_info,
-                    DiagnosticSeverity::Hint => renderer.colors.diagnostic_hint,
-                };
-
-                // Calculate the start and end columns on this line
-                let diag_start_col = if diagnostic.start_line == buffer_line {
-                    diagnostic.start_col
-                } else {
-                    0
-                };
-                let diag_end_col = if diagnostic.end_line == buffer_line {
-                    diagnostic.end_col
-                } else {
-                    buffer.line_len_chars(buffer_line)
-                };
-
-                // Adjust for horizontal scroll
-                let visible_start = diag_start_col.saturating_sub(horizontal_scroll);
-                let visible_end = diag_end_col.saturating_sub(horizontal_scroll);
-
-                if visible_end > visible_start {
-                    let underline_x = self.line_number_margin + visible_start as f32 * char_width;
-                    let underline_width = (visible_end - visible_start) as f32 * char_width;
-
-                    // Use squiggly underline for errors/warnings, simple underline for info/hints
-                    match diagnostic.severity {
-                        DiagnosticSeverity::Error | DiagnosticSeverity::Warning => {
-                            renderer.draw_squiggle(underline_x, y, underline_width, line_height, color);
-                        }
-                        _ => {
-                            renderer.draw_underline(underline_x, y, underline_width, line_height, color);
+                GutterColumn::Diagnostics => {
+                    // Marker for the most severe displayed diagnostic on the line
+                    let severity = editor
+                        .visible_diagnostics()
+                        .filter(|d| d.on_line(buffer_line))
+                        .map(|d| d.severity)
+                        .reduce(|a, b| if b.is_at_least(a) { b } else { a });
+                    if let Some(severity) = severity {
+                        let mut color = match severity {
+                            DiagnosticSeverity::Error => renderer.colors.diagnostic_error,
+                            DiagnosticSeverity::Warning => renderer.colors.diagnostic_warning,
+                            DiagnosticSeverity::Information => renderer.colors.diagnostic_info,
+                            DiagnosticSeverity::Hint => renderer.colors.diagnostic_hint,
+                        };
+                        if editor.diagnostics_stale() {
+                            color[3] *= STALE_DIAGNOSTIC_ALPHA;
                         }
+                        let size = (char_width * 0.8).min(line_height * 0.6);
+                        renderer.draw_rect(
+                            region_x + (region.width - size) / 2.0,
+                            y + (line_height - size) / 2.0,
+                            size,
+                            size,
+                            color,
+                        );
                     }
                 }
-            }
-        }
-
-        // Draw bracket match highlighting
-        if let Some((bracket_pos, match_pos)) = editor.matching_bracket_at_cursor() {
-            // Helper to draw bracket highlight at a position
-            let draw_bracket_highlight = |renderer: &mut GpuRenderer, char_pos: usize| {
-                let (line, col) = buffer.char_to_line_col(char_pos);
-                if line >= base_scroll_line
-                    && line <= base_scroll_line + visible_lines
-                    && col >= horizontal_scroll
-                {
-                    let screen_line = line as f32 - smooth_scroll;
-                    let screen_col = col - horizontal_scroll;
-                    let x = self.line_number_margin + screen_col as f32 * char_width;
-                    let y = content_y + screen_line * line_height;
-
-                    if y >= content_y && y < viewport_height as f32 {
-                        renderer.draw_rect(x, y, char_width, line_height, renderer.colors.bracket_match);
+                GutterColumn::LineNumbers => {
+                    let digits = GutterLayout::line_number_digits(editor.buffer().len_lines());
+                    let mut buf = [0; MAX_LINE_NUMBER_DIGITS];
+                    let text = format_line_number(buffer_line + 1, digits, &mut buf);
+                    let x = region_x + (region.width - digits as f32 * char_width) / 2.0;
+                    renderer.draw_text(text, x, y, renderer.colors.line_number);
+
+                    // Change bar against the saved text, in the left padding
+                    if let Some(kind) = editor.line_change_status(buffer_line) {
+                        Self::render_change_marker(
+                            renderer,
+                            kind,
+                            editor.is_whitespace_only_change(buffer_line),
+                            region_x,
+                            y,
+                            line_height,
+                        );
                     }
                 }
-            };
-
-            draw_bracket_highlight(renderer, bracket_pos);
-            draw_bracket_highlight(renderer, match_pos);
-        }
-
-        // Draw all cursors (multi-cursor support)
-        if self.cursor_visible {
-            for (cursor_line, cursor_col) in &all_cursor_positions {
-                if *cursor_line >= base_scroll_line
-                    && *cursor_line <= base_scroll_line + visible_lines
-                    && *cursor_col >= horizontal_scroll
-                {
-                    let cursor_screen_line = *cursor_line as f32 - smooth_scroll;
-                    let cursor_screen_col = *cursor_col - horizontal_scroll;
-                    let cursor_x = self.line_number_margin + cursor_screen_col as f32 * char_width;
-                    let cursor_y = content_y + cursor_screen_line * line_height;
-
-                    // Only draw if cursor is within visible area
-                    if cursor_y >= content_y && cursor_y < viewport_height as f32 {
-                        renderer.draw_rect(cursor_x, cursor_y, 2.0, line_height, renderer.colors.cursor);
+                GutterColumn::Folds => {
+                    if editor.is_fold_start(buffer_line) {
+                        let marker = if editor.is_line_folded(buffer_line) { "+" } else { "-" };
+                        let x = region_x + (region.width - char_width) / 2.0;
+                        renderer.draw_text(marker, x, y, renderer.colors.line_number);
                     }
                 }
             }
         }
+    }
 
-        // Draw hover popup if we have hover info
-        if let Some(hover_info) = editor.hover_info() {
-            if let Some((mouse_x, mouse_y)) = self.hover_mouse_pos {
-                self.render_hover_popup(renderer, &hover_info.contents, mouse_x, mouse_y, viewport_width as f32, viewport_height as f32, char_width, line_height);
+    /// Renders the change marker for one line at the left edge of the line
+    /// numbers: a bar for added and modified lines (hollow if only whitespace
+    /// changed) and a tick at the bottom of the line for removed lines below.
+    fn render_change_marker(
+        renderer: &mut DrawList,
+        kind: ChangeKind,
+        whitespace_only: bool,
+        x: f32,
+        y: f32,
+        line_height: f32,
+    ) {
+        const WIDTH: f32 = 3.0;
+        let color = match kind {
+            ChangeKind::Added => renderer.colors.change_added,
+            ChangeKind::Modified => renderer.colors.change_modified,
+            ChangeKind::Deleted => renderer.colors.change_deleted,
+        };
+        match kind {
+            ChangeKind::Deleted => {
+                renderer.draw_rect(x, y + line_height - WIDTH / 2.0, WIDTH * 2.0, WIDTH, color);
             }
-        }
-
-        // Draw completion popup if visible
-        if self.completion_visible {
-            let completions = editor.completions();
-            if !completions.is_empty() {
-                // Calculate popup position near the cursor
-                let popup_x = self.line_number_margin + (cursor_pos.col - horizontal_scroll) as f32 * char_width;
-                let popup_y = content_y + ((cursor_pos.line as f32 - smooth_scroll) + 1.0) * line_height;
-
-                self.render_completion_popup(
-                    renderer,
-                    completions,
-                    self.completion_selected,
-                    popup_x,
-                    popup_y,
-                    viewport_width as f32,
-                    viewport_height as f32,
-                    char_width,
-                    line_height,
-                );
+            _ if whitespace_only => {
+                renderer.draw_rect(x, y, WIDTH, 1.0, color);
+                renderer.draw_rect(x, y + line_height - 1.0, WIDTH, 1.0, color);
+                renderer.draw_rect(x, y, 1.0, line_height, color);
+                renderer.draw_rect(x + WIDTH - 1.0, y, 1.0, line_height, color);
             }
+            _ => renderer.draw_rect(x, y, WIDTH, line_height, color),
         }
-
-        // Draw status bar at the bottom
-        self.render_status_bar(renderer, viewport_width as f32, viewport_height as f32, char_width, line_height);
-
-        // Draw notifications in top-right corner
-        self.render_notifications(renderer, viewport_width as f32, char_width, line_height);
     }
 
     /// Renders the hover information popup.
+    #[allow(clippy::too_many_arguments)]
     fn render_hover_popup(
         &self,
-        renderer: &mut GpuRenderer,
+        renderer: &mut DrawList,
         content: &str,
         mouse_x: f32,
         mouse_y: f32,
         viewport_width: f32,
         viewport_height: f32,
-        char_width: f32,
-        line_height: f32,
     ) {
+        let ChromeMetrics { char_width, line_height } = self.chrome;
         const PADDING: f32 = 8.0;
         const MAX_WIDTH: f32 = 500.0;
         const MAX_HEIGHT: f32 = 300.0;
@@ -1171,55 +3965,232 @@ impl EditorApp {
         popup_x = popup_x.max(4.0);
         popup_y = popup_y.max(self.content_y_offset() + 4.0);
 
-        // Draw popup background
+        // Draw popup background
+        renderer.draw_rect(popup_x, popup_y, popup_width, popup_height, renderer.colors.hover_bg);
+
+        // Draw border
+        let border_width = 1.0;
+        // Top border
+        renderer.draw_rect(popup_x, popup_y, popup_width, border_width, renderer.colors.hover_border);
+        // Bottom border
+        renderer.draw_rect(popup_x, popup_y + popup_height - border_width, popup_width, border_width, renderer.colors.hover_border);
+        // Left border
+        renderer.draw_rect(popup_x, popup_y, border_width, popup_height, renderer.colors.hover_border);
+        // Right border
+        renderer.draw_rect(popup_x + popup_width - border_width, popup_y, border_width, popup_height, renderer.colors.hover_border);
+
+        // Draw text content (limited to visible lines)
+        let max_visible_lines = ((MAX_HEIGHT - 2.0 * PADDING) / line_height) as usize;
+        let text_x = popup_x + PADDING;
+        let mut text_y = popup_y + PADDING;
+
+        for line in lines.iter().take(max_visible_lines) {
+            // Truncate long lines
+            let max_chars = ((MAX_WIDTH - 2.0 * PADDING) / char_width) as usize;
+            let display_line: String = line.chars().take(max_chars).collect();
+            renderer.draw_ui_text(&display_line, text_x, text_y, renderer.colors.text);
+            text_y += line_height;
+        }
+
+        // Show "..." if content is truncated
+        if lines.len() > max_visible_lines {
+            renderer.draw_ui_text("...", text_x, text_y, renderer.colors.line_number);
+        }
+    }
+
+    /// Renders the signature help popup above the line whose bottom-left
+    /// corner is at (`x`, `y`), or below it if it doesn't fit above.
+    fn render_signature_help_popup(
+        &self,
+        renderer: &mut DrawList,
+        help: &SignatureHelp,
+        x: f32,
+        y: f32,
+        viewport_width: f32,
+        viewport_height: f32,
+    ) {
+        let ChromeMetrics { char_width, line_height } = self.chrome;
+        const PADDING: f32 = 6.0;
+
+        let popup_width = help.label.chars().count() as f32 * char_width + 2.0 * PADDING;
+        let popup_height = line_height + 2.0 * PADDING;
+        let mut popup_x = x;
+        let mut popup_y = y - line_height - popup_height;
+        if popup_y < self.content_y_offset() {
+            popup_y = y;
+        }
+        if popup_x + popup_width > viewport_width {
+            popup_x = viewport_width - popup_width - 4.0;
+        }
+        popup_x = popup_x.max(4.0);
+        popup_y = popup_y.min(viewport_height - popup_height);
+
+        renderer.draw_rect(popup_x, popup_y, popup_width, popup_height, renderer.colors.hover_bg);
+        let border_width = 1.0;
+        renderer.draw_rect(popup_x, popup_y, popup_width, border_width, renderer.colors.hover_border);
+        renderer.draw_rect(popup_x, popup_y + popup_height - border_width, popup_width, border_width, renderer.colors.hover_border);
+        renderer.draw_rect(popup_x, popup_y, border_width, popup_height, renderer.colors.hover_border);
+        renderer.draw_rect(popup_x + popup_width - border_width, popup_y, border_width, popup_height, renderer.colors.hover_border);
+
+        let text_x = popup_x + PADDING;
+        let text_y = popup_y + PADDING;
+        // Emphasize the parameter being typed
+        if let Some(parameter) = &help.active_parameter {
+            renderer.draw_rect(
+                text_x + parameter.start as f32 * char_width,
+                text_y,
+                parameter.len() as f32 * char_width,
+                line_height,
+                renderer.colors.completion_selected_bg,
+            );
+        }
+        renderer.draw_ui_text(&help.label, text_x, text_y, renderer.colors.text);
+    }
+
+    /// Renders the code actions menu with its top-left at (`x`, `y`), one
+    /// title per row, flipping above the line if it doesn't fit below.
+    #[allow(clippy::too_many_arguments)]
+    fn render_code_actions_menu(
+        &self,
+        renderer: &mut DrawList,
+        menu: &CodeActionMenu,
+        x: f32,
+        y: f32,
+        viewport_width: f32,
+        viewport_height: f32,
+        anchor_height: f32,
+    ) {
+        let ChromeMetrics { char_width, line_height } = self.chrome;
+        const PADDING: f32 = 4.0;
+
+        let max_len = menu.entries().iter().map(|e| e.title.chars().count()).max().unwrap_or(0);
+        let popup_width = max_len as f32 * char_width + 2.0 * PADDING;
+        let popup_height = menu.entries().len() as f32 * line_height + 2.0 * PADDING;
+
+        let mut popup_x = x;
+        let mut popup_y = y;
+        if popup_x + popup_width > viewport_width {
+            popup_x = viewport_width - popup_width - 4.0;
+        }
+        if popup_y + popup_height > viewport_height {
+            popup_y = y - popup_height - anchor_height;
+        }
+        popup_x = popup_x.max(4.0);
+        popup_y = popup_y.max(self.content_y_offset() + 4.0);
+
+        renderer.draw_rect(popup_x, popup_y, popup_width, popup_height, renderer.colors.completion_bg);
+        let border_width = 1.0;
+        renderer.draw_rect(popup_x, popup_y, popup_width, border_width, renderer.colors.completion_border);
+        renderer.draw_rect(popup_x, popup_y + popup_height - border_width, popup_width, border_width, renderer.colors.completion_border);
+        renderer.draw_rect(popup_x, popup_y, border_width, popup_height, renderer.colors.completion_border);
+        renderer.draw_rect(popup_x + popup_width - border_width, popup_y, border_width, popup_height, renderer.colors.completion_border);
+
+        let mut text_y = popup_y + PADDING;
+        for (i, entry) in menu.entries().iter().enumerate() {
+            if i == menu.selected_index() {
+                renderer.draw_rect(
+                    popup_x + border_width,
+                    text_y,
+                    popup_width - 2.0 * border_width,
+                    line_height,
+                    renderer.colors.completion_selected_bg,
+                );
+            }
+            renderer.draw_ui_text(&entry.title, popup_x + PADDING, text_y, renderer.colors.text);
+            text_y += line_height;
+        }
+    }
+
+    /// Renders the line diagnostics popup with its top-left at (`x`, `y`),
+    /// flipping above the line if it doesn't fit below.
+    #[allow(clippy::too_many_arguments)]
+    fn render_diagnostics_popup(
+        &self,
+        renderer: &mut DrawList,
+        popup: &DiagnosticsPopup,
+        x: f32,
+        y: f32,
+        viewport_width: f32,
+        viewport_height: f32,
+        anchor_height: f32,
+    ) {
+        let ChromeMetrics { char_width, line_height } = self.chrome;
+        const PADDING: f32 = 6.0;
+
+        let max_len = popup
+            .entries()
+            .iter()
+            .flat_map(|e| std::iter::once(&e.header).chain(e.lines.iter()))
+            .map(|l| l.len())
+            .max()
+            .unwrap_or(0);
+        let popup_width = max_len as f32 * char_width + 2.0 * PADDING;
+        let popup_height = popup.row_count() as f32 * line_height + 2.0 * PADDING;
+
+        let mut popup_x = x;
+        let mut popup_y = y;
+        if popup_x + popup_width > viewport_width {
+            popup_x = viewport_width - popup_width - 4.0;
+        }
+        if popup_y + popup_height > viewport_height {
+            popup_y = y - popup_height - anchor_height;
+        }
+        popup_x = popup_x.max(4.0);
+        popup_y = popup_y.max(self.content_y_offset() + 4.0);
+
         renderer.draw_rect(popup_x, popup_y, popup_width, popup_height, renderer.colors.hover_bg);
-
-        // Draw border
         let border_width = 1.0;
-        // Top border
         renderer.draw_rect(popup_x, popup_y, popup_width, border_width, renderer.colors.hover_border);
-        // Bottom border
         renderer.draw_rect(popup_x, popup_y + popup_height - border_width, popup_width, border_width, renderer.colors.hover_border);
-        // Left border
         renderer.draw_rect(popup_x, popup_y, border_width, popup_height, renderer.colors.hover_border);
-        // Right border
         renderer.draw_rect(popup_x + popup_width - border_width, popup_y, border_width, popup_height, renderer.colors.hover_border);
 
-        // Draw text content (limited to visible lines)
-        let max_visible_lines = ((MAX_HEIGHT - 2.0 * PADDING) / line_height) as usize;
         let text_x = popup_x + PADDING;
         let mut text_y = popup_y + PADDING;
+        for (i, entry) in popup.entries().iter().enumerate() {
+            let rows = 1 + entry.lines.len();
+            if i == popup.selected_index() && popup.entries().len() > 1 {
+                renderer.draw_rect(
+                    popup_x + border_width,
+                    text_y,
+                    popup_width - 2.0 * border_width,
+                    rows as f32 * line_height,
+                    renderer.colors.completion_selected_bg,
+                );
+            }
 
-        for line in lines.iter().take(max_visible_lines) {
-            // Truncate long lines
-            let max_chars = ((MAX_WIDTH - 2.0 * PADDING) / char_width) as usize;
-            let display_line: String = line.chars().take(max_chars).collect();
-            renderer.draw_text(&display_line, text_x, text_y, renderer.colors.text);
+            let color = match entry.severity {
+                DiagnosticSeverity::Error => renderer.colors.diagnostic_error,
+                DiagnosticSeverity::Warning => renderer.colors.diagnostic_warning,
+                DiagnosticSeverity::Information => renderer.colors.diagnostic_info,
+                DiagnosticSeverity::Hint => renderer.colors.diagnostic_hint,
+            };
+            renderer.draw_ui_text(&entry.header, text_x, text_y, color);
             text_y += line_height;
-        }
-
-        // Show "..." if content is truncated
-        if lines.len() > max_visible_lines {
-            renderer.draw_text("...", text_x, text_y, renderer.colors.line_number);
+            for line in &entry.lines {
+                renderer.draw_ui_text(line, text_x, text_y, renderer.colors.text);
+                text_y += line_height;
+            }
         }
     }
 
     /// Renders the completion popup.
+    #[allow(clippy::too_many_arguments)]
     fn render_completion_popup(
         &self,
-        renderer: &mut GpuRenderer,
+        renderer: &mut DrawList,
         items: &[CompletionItem],
         selected: usize,
         x: f32,
         y: f32,
         viewport_width: f32,
         viewport_height: f32,
-        char_width: f32,
-        line_height: f32,
+        anchor_height: f32,
     ) {
+        let ChromeMetrics { char_width, line_height } = self.chrome;
         const PADDING: f32 = 4.0;
         const MAX_VISIBLE_ITEMS: usize = 10;
-        const ITEM_HEIGHT: f32 = 20.0;
+        let item_height = (line_height + 4.0).ceil();
 
         if items.is_empty() {
             return;
@@ -1229,7 +4200,7 @@ impl EditorApp {
         let visible_items = items.len().min(MAX_VISIBLE_ITEMS);
         let max_label_len = items.iter().map(|i| i.label.len()).max().unwrap_or(10).max(20);
         let popup_width = (max_label_len as f32 * char_width) + 2.0 * PADDING + 24.0; // Extra space for icon
-        let popup_height = visible_items as f32 * ITEM_HEIGHT + 2.0 * PADDING;
+        let popup_height = visible_items as f32 * item_height + 2.0 * PADDING;
 
         // Position popup - try below cursor first
         let mut popup_x = x;
@@ -1242,7 +4213,7 @@ impl EditorApp {
 
         // Adjust if popup would go off the bottom edge - show above cursor
         if popup_y + popup_height > viewport_height {
-            popup_y = y - popup_height - line_height;
+            popup_y = y - popup_height - anchor_height;
         }
 
         // Ensure popup stays on screen
@@ -1280,7 +4251,7 @@ impl EditorApp {
                     popup_x + border_width,
                     item_y,
                     popup_width - 2.0 * border_width,
-                    ITEM_HEIGHT,
+                    item_height,
                     renderer.colors.completion_selected_bg,
                 );
             }
@@ -1305,7 +4276,7 @@ impl EditorApp {
             }).unwrap_or('?');
 
             let kind_color = renderer.colors.line_number;
-            renderer.draw_char(kind_char, popup_x + PADDING + 4.0, item_y + 2.0, kind_color);
+            renderer.draw_ui_char(kind_char, popup_x + PADDING + 4.0, item_y + 2.0, kind_color);
 
             // Draw label
             let label_color = if is_selected {
@@ -1315,125 +4286,259 @@ impl EditorApp {
             };
             let max_label_chars = ((popup_width - 2.0 * PADDING - 24.0) / char_width) as usize;
             let display_label: String = item.label.chars().take(max_label_chars).collect();
-            renderer.draw_text(&display_label, text_x, item_y + 2.0, label_color);
+            renderer.draw_ui_text(&display_label, text_x, item_y + 2.0, label_color);
 
-            item_y += ITEM_HEIGHT;
+            item_y += item_height;
         }
 
         // Draw scroll indicator if needed
         if items.len() > MAX_VISIBLE_ITEMS {
             let indicator = format!("{}/{}", selected + 1, items.len());
             let indicator_x = popup_x + popup_width - (indicator.len() as f32 * char_width) - PADDING;
-            renderer.draw_text(&indicator, indicator_x, popup_y + popup_height - line_height - PADDING, renderer.colors.line_number);
+            renderer.draw_ui_text(&indicator, indicator_x, popup_y + popup_height - line_height - PADDING, renderer.colors.line_number);
+        }
+    }
+
+    /// Returns the search status shown in the input bar, tagged with the
+    /// active search mode.
+    fn search_status_text(editor: &Editor) -> Option<String> {
+        let mut tags = String::new();
+        if editor.search().is_regex() {
+            tags.push_str("[.*] ");
+        }
+        match editor.search_region_count() {
+            0 => {}
+            1 => tags.push_str("[in selection] "),
+            _ => tags.push_str("[in selections] "),
+        }
+        if editor.search().is_preserve_case() {
+            tags.push_str("[preserve case] ");
+        }
+        match editor.search_status() {
+            Some(status) => Some(format!("{}{}", tags, status)),
+            None if !tags.is_empty() => Some(tags.trim_end().to_string()),
+            None => None,
+        }
+    }
+
+    /// Renders the conflict banner over the focused pane: what happened and
+    /// the underlined actions.
+    fn render_disk_banner(&self, renderer: &mut DrawList, viewport_width: f32) {
+        let ChromeMetrics { char_width, line_height } = self.chrome;
+        let height = self.disk_banner_height();
+        let top = self.content_y_offset() - height;
+        let (left, width) = self.pane_area(viewport_width);
+        renderer.draw_rect(left, top, width, height, renderer.colors.search_bar_bg);
+        renderer.draw_rect(left, top + height - 1.0, width, 1.0, renderer.colors.diagnostic_warning);
+
+        let text_left = self.disk_banner_text_left();
+        let text_y = top + (height - line_height) / 2.0;
+        renderer.draw_ui_text(disk_banner::MESSAGE, text_left, text_y, renderer.colors.text);
+        for (i, (action, span)) in disk_banner::action_spans().into_iter().enumerate() {
+            let x = text_left + span.start as f32 * char_width;
+            let label_width = span.len() as f32 * char_width;
+            renderer.draw_ui_text(action.label(), x, text_y, renderer.colors.diagnostic_warning);
+            renderer.draw_rect(x, text_y + line_height - 1.0, label_width, 1.0, renderer.colors.diagnostic_warning);
+            if let Some(separator) = disk_banner::separator_after(i) {
+                renderer.draw_ui_text(separator, x + label_width, text_y, renderer.colors.line_number);
+            }
         }
     }
 
     /// Renders the search/replace/goto input bar.
-    fn render_input_bar(&self, renderer: &mut GpuRenderer, viewport_width: f32, char_width: f32, line_height: f32) {
-        let bar_y = TAB_BAR_HEIGHT;
+    fn render_input_bar(&self, renderer: &mut DrawList, viewport_width: f32) {
+        let ChromeMetrics { char_width, line_height } = self.chrome;
+        let bar_y = self.tab_bar_height();
+        let bar_height = self.chrome.search_bar_height();
 
         // Draw bar background
-        renderer.draw_rect(0.0, bar_y, viewport_width, SEARCH_BAR_HEIGHT, renderer.colors.search_bar_bg);
+        renderer.draw_rect(0.0, bar_y, viewport_width, bar_height, renderer.colors.search_bar_bg);
 
         // Draw separator line
-        renderer.draw_rect(0.0, bar_y + SEARCH_BAR_HEIGHT - 1.0, viewport_width, 1.0, renderer.colors.line_number);
+        renderer.draw_rect(0.0, bar_y + bar_height - 1.0, viewport_width, 1.0, renderer.colors.line_number);
 
         let padding = 8.0;
-        let field_height = 22.0;
-        let field_y = bar_y + (SEARCH_BAR_HEIGHT - field_height) / 2.0;
+        let field_height = self.chrome.input_field_height();
+        let field_y = bar_y + (bar_height - field_height) / 2.0;
         let text_y = field_y + (field_height - line_height) / 2.0;
 
         match self.input_mode {
             InputMode::Search => {
                 // Draw "Find:" label
-                renderer.draw_text("Find:", padding, text_y, renderer.colors.text);
+                renderer.draw_ui_text("Find:", padding, text_y, renderer.colors.text);
                 let label_width = 5.0 * char_width + padding;
 
                 // Draw search input field
                 let field_x = label_width + padding;
                 let field_width = 200.0;
-                self.draw_input_field(renderer, field_x, field_y, field_width, field_height, &self.search_text, self.focused_field == 0, char_width, line_height);
+                self.draw_input_field(renderer, field_x, field_y, field_width, field_height, &self.search_text, self.focused_field == 0);
 
                 // Draw status
                 if let Some(editor) = self.workspace.active_editor() {
-                    if let Some(status) = editor.search_status() {
+                    if let Some(status) = Self::search_status_text(editor) {
                         let status_x = field_x + field_width + padding;
-                        renderer.draw_text(&status, status_x, text_y, renderer.colors.line_number);
+                        renderer.draw_ui_text(&status, status_x, text_y, renderer.colors.line_number);
                     }
                 }
             }
             InputMode::Replace => {
                 // Draw "Find:" label and field
-                renderer.draw_text("Find:", padding, text_y, renderer.colors.text);
+                renderer.draw_ui_text("Find:", padding, text_y, renderer.colors.text);
                 let label_width = 5.0 * char_width + padding;
                 let field_x = label_width + padding;
                 let field_width = 150.0;
-                self.draw_input_field(renderer, field_x, field_y, field_width, field_height, &self.search_text, self.focused_field == 0, char_width, line_height);
+                self.draw_input_field(renderer, field_x, field_y, field_width, field_height, &self.search_text, self.focused_field == 0);
 
                 // Draw "Replace:" label and field
                 let replace_label_x = field_x + field_width + padding * 2.0;
-                renderer.draw_text("Replace:", replace_label_x, text_y, renderer.colors.text);
+                renderer.draw_ui_text("Replace:", replace_label_x, text_y, renderer.colors.text);
                 let replace_field_x = replace_label_x + 8.0 * char_width + padding;
-                self.draw_input_field(renderer, replace_field_x, field_y, field_width, field_height, &self.replace_text, self.focused_field == 1, char_width, line_height);
-
-                // Draw status
-                if let Some(editor) = self.workspace.active_editor() {
-                    if let Some(status) = editor.search_status() {
-                        let status_x = replace_field_x + field_width + padding;
-                        renderer.draw_text(&status, status_x, text_y, renderer.colors.line_number);
+                self.draw_input_field(renderer, replace_field_x, field_y, field_width, field_height, &self.replace_text, self.focused_field == 1);
+
+                // Draw the step-through prompt, or the search status
+                let status_x = replace_field_x + field_width + padding;
+                if let Some(walk) = &self.replace_walk {
+                    renderer.draw_ui_text(&walk.prompt(), status_x, text_y, renderer.colors.text);
+                } else if let Some(editor) = self.workspace.active_editor() {
+                    if let Some(status) = Self::search_status_text(editor) {
+                        renderer.draw_ui_text(&status, status_x, text_y, renderer.colors.line_number);
                     }
                 }
             }
             InputMode::GoToLine => {
                 // Draw "Go to line:" label
-                renderer.draw_text("Go to line:", padding, text_y, renderer.colors.text);
+                renderer.draw_ui_text("Go to line:", padding, text_y, renderer.colors.text);
                 let label_width = 11.0 * char_width + padding;
 
                 // Draw input field
                 let field_x = label_width + padding;
                 let field_width = 80.0;
-                self.draw_input_field(renderer, field_x, field_y, field_width, field_height, &self.goto_text, true, char_width, line_height);
+                self.draw_input_field(renderer, field_x, field_y, field_width, field_height, &self.goto_text, true);
 
-                // Draw line count info
+                // Draw line count info, or what is wrong with the input
                 if let Some(editor) = self.workspace.active_editor() {
-                    let total_lines = editor.buffer().len_lines();
-                    let info = format!("of {}", total_lines);
                     let info_x = field_x + field_width + padding;
-                    renderer.draw_text(&info, info_x, text_y, renderer.colors.line_number);
+                    if let Some(error) = &self.goto_error {
+                        renderer.draw_ui_text(error, info_x, text_y, renderer.colors.diagnostic_error);
+                    } else {
+                        let total_lines = editor.buffer().len_lines();
+                        let info = format!("of {} (line:col, :col, -n from end)", total_lines);
+                        renderer.draw_ui_text(&info, info_x, text_y, renderer.colors.line_number);
+                    }
                 }
             }
             InputMode::Rename => {
                 // Draw "Rename:" label
-                renderer.draw_text("Rename to:", padding, text_y, renderer.colors.text);
+                renderer.draw_ui_text("Rename to:", padding, text_y, renderer.colors.text);
                 let label_width = 10.0 * char_width + padding;
 
                 // Draw input field
                 let field_x = label_width + padding;
                 let field_width = 200.0;
-                self.draw_input_field(renderer, field_x, field_y, field_width, field_height, &self.rename_text, true, char_width, line_height);
+                self.draw_input_field(renderer, field_x, field_y, field_width, field_height, &self.rename_text, true);
 
                 // Draw hint
                 let hint = "(Enter to confirm, Esc to cancel)";
                 let hint_x = field_x + field_width + padding;
-                renderer.draw_text(hint, hint_x, text_y, renderer.colors.line_number);
+                renderer.draw_ui_text(hint, hint_x, text_y, renderer.colors.line_number);
+            }
+            InputMode::ProjectSearch => {
+                // Draw "Search project:" label
+                renderer.draw_ui_text("Search project:", padding, text_y, renderer.colors.text);
+                let label_width = 15.0 * char_width + padding;
+
+                // Draw query field
+                let field_x = label_width + padding;
+                let field_width = 200.0;
+                self.draw_input_field(renderer, field_x, field_y, field_width, field_height, &self.project_search_text, self.focused_field == 0);
+
+                // Draw "Replace:" label and field
+                let replace_label_x = field_x + field_width + padding * 2.0;
+                renderer.draw_ui_text("Replace:", replace_label_x, text_y, renderer.colors.text);
+                let replace_field_x = replace_label_x + 8.0 * char_width + padding;
+                self.draw_input_field(renderer, replace_field_x, field_y, field_width, field_height, &self.project_replace_text, self.focused_field == 1);
+
+                // Draw result count
+                let status_x = replace_field_x + field_width + padding;
+                renderer.draw_ui_text(&self.project_search.status_text(), status_x, text_y, renderer.colors.line_number);
+            }
+            InputMode::FilterCommand => {
+                // Draw "Filter through:" label
+                renderer.draw_ui_text("Filter through:", padding, text_y, renderer.colors.text);
+                let label_width = 15.0 * char_width + padding;
+
+                // Draw command field
+                let field_x = label_width + padding;
+                let field_width = 300.0;
+                self.draw_input_field(renderer, field_x, field_y, field_width, field_height, &self.filter_text, true);
+
+                // Draw hint
+                let hint = "(Enter to run, Esc to cancel)";
+                let hint_x = field_x + field_width + padding;
+                renderer.draw_ui_text(hint, hint_x, text_y, renderer.colors.line_number);
+            }
+            InputMode::RecentFiles => {
+                // Draw "Open recent:" label
+                renderer.draw_ui_text("Open recent:", padding, text_y, renderer.colors.text);
+                let label_width = 12.0 * char_width + padding;
+
+                // Draw filter field
+                let field_x = label_width + padding;
+                let field_width = 300.0;
+                self.draw_input_field(renderer, field_x, field_y, field_width, field_height, &self.recent_text, true);
+
+                // Draw match count
+                let count = format!("{} of {}", self.recent_matches.len(), self.workspace.recent_files().len());
+                let count_x = field_x + field_width + padding;
+                renderer.draw_ui_text(&count, count_x, text_y, renderer.colors.line_number);
+            }
+            InputMode::Encodings => {
+                // Draw "Reopen with encoding:" label
+                renderer.draw_ui_text("Reopen with encoding:", padding, text_y, renderer.colors.text);
+                let label_width = 21.0 * char_width + padding;
+
+                // Draw filter field
+                let field_x = label_width + padding;
+                let field_width = 200.0;
+                self.draw_input_field(renderer, field_x, field_y, field_width, field_height, &self.encoding_text, true);
+
+                // Draw hint
+                let hint = "(Enter to reopen, unsaved changes are lost)";
+                let hint_x = field_x + field_width + padding;
+                renderer.draw_ui_text(hint, hint_x, text_y, renderer.colors.line_number);
+            }
+            InputMode::Problems => {
+                // Draw "Problems:" label
+                renderer.draw_ui_text("Problems:", padding, text_y, renderer.colors.text);
+                let label_width = 9.0 * char_width + padding;
+
+                // Draw filter field
+                let field_x = label_width + padding;
+                let field_width = 200.0;
+                self.draw_input_field(renderer, field_x, field_y, field_width, field_height, &self.problems_text, true);
+
+                // Draw counts and the keys that change the list
+                let status = format!("{} (Alt+E/W/H severities, Alt+G group, Alt+R refresh)", self.problems.status_text());
+                let status_x = field_x + field_width + padding;
+                renderer.draw_ui_text(&status, status_x, text_y, renderer.colors.line_number);
             }
             InputMode::Normal => {}
         }
     }
 
     /// Draws an input field.
+    #[allow(clippy::too_many_arguments)]
     fn draw_input_field(
         &self,
-        renderer: &mut GpuRenderer,
+        renderer: &mut DrawList,
         x: f32,
         y: f32,
         width: f32,
         height: f32,
-        text: &str,
+        field: &TextField,
         focused: bool,
-        char_width: f32,
-        line_height: f32,
     ) {
+        let ChromeMetrics { char_width, line_height } = self.chrome;
         // Draw field background
         renderer.draw_rect(x, y, width, height, renderer.colors.input_field_bg);
 
@@ -1452,16 +4557,26 @@ impl EditorApp {
         // Right border
         renderer.draw_rect(x + width - 1.0, y, 1.0, height, border_color);
 
-        // Draw text
+        // Draw text, scrolled to keep the cursor in view
         let text_x = x + 4.0;
         let text_y = y + (height - line_height) / 2.0;
         let max_chars = ((width - 8.0) / char_width) as usize;
-        let display_text: String = text.chars().take(max_chars).collect();
-        renderer.draw_text(&display_text, text_x, text_y, renderer.colors.text);
-
-        // Draw cursor if focused
-        if focused && self.cursor_visible {
-            let cursor_x = text_x + display_text.len() as f32 * char_width;
+        let first = field.scroll(max_chars);
+        // Column at which the text at a byte offset is drawn
+        let column = |offset: usize| field.text()[..offset].chars().count().saturating_sub(first).min(max_chars) as f32;
+        let display_text: String = field.text().chars().skip(first).take(max_chars).collect();
+
+        // Draw selection and cursor if focused
+        if focused {
+            if let Some(range) = field.selection() {
+                let start_x = text_x + column(range.start) * char_width;
+                let end_x = text_x + column(range.end) * char_width;
+                renderer.draw_rect(start_x, text_y, end_x - start_x, line_height, renderer.colors.selection);
+            }
+        }
+        renderer.draw_ui_text(&display_text, text_x, text_y, renderer.colors.text);
+        if focused && self.cursor_blink.should_draw() {
+            let cursor_x = text_x + column(field.cursor()) * char_width;
             renderer.draw_rect(cursor_x, text_y, 2.0, line_height, renderer.colors.cursor);
         }
     }
@@ -1469,18 +4584,18 @@ impl EditorApp {
     /// Renders the status bar at the bottom of the window.
     fn render_status_bar(
         &self,
-        renderer: &mut GpuRenderer,
+        renderer: &mut DrawList,
         viewport_width: f32,
         viewport_height: f32,
-        char_width: f32,
-        line_height: f32,
     ) {
-        let bar_y = viewport_height - STATUS_BAR_HEIGHT;
+        let ChromeMetrics { char_width, line_height } = self.chrome;
+        let bar_height = self.chrome.status_bar_height();
+        let bar_y = viewport_height - bar_height;
         let padding = 8.0;
-        let text_y = bar_y + (STATUS_BAR_HEIGHT - line_height) / 2.0;
+        let text_y = bar_y + (bar_height - line_height) / 2.0;
 
         // Draw status bar background
-        renderer.draw_rect(0.0, bar_y, viewport_width, STATUS_BAR_HEIGHT, renderer.colors.tab_bar_bg);
+        renderer.draw_rect(0.0, bar_y, viewport_width, bar_height, renderer.colors.tab_bar_bg);
 
         // Draw separator line above status bar
         renderer.draw_rect(0.0, bar_y, viewport_width, 1.0, renderer.colors.line_number);
@@ -1492,12 +4607,47 @@ impl EditorApp {
 
             // Language indicator
             let lang_name = editor.language().name();
-            renderer.draw_text(lang_name, left_x, text_y, renderer.colors.line_number);
+            renderer.draw_ui_text(lang_name, left_x, text_y, renderer.colors.line_number);
             left_x += (lang_name.len() as f32 + 2.0) * char_width;
 
-            // Encoding (always UTF-8 for now)
-            renderer.draw_text("UTF-8", left_x, text_y, renderer.colors.line_number);
-            left_x += 7.0 * char_width;
+            // The encoding the file is saved in
+            let encoding = editor.encoding().label();
+            renderer.draw_ui_text(encoding, left_x, text_y, renderer.colors.line_number);
+            left_x += (encoding.len() as f32 + 2.0) * char_width;
+
+            // Mixed line endings are saved as they are until converted
+            let line_ending = if editor.has_mixed_line_endings() { "Mixed" } else { editor.line_ending().label() };
+            renderer.draw_ui_text(line_ending, left_x, text_y, renderer.colors.line_number);
+            left_x += (line_ending.len() as f32 + 2.0) * char_width;
+
+            // The buffer's own indentation and word wrap
+            let settings = editor.settings();
+            let indent_text = settings.indent.label();
+            renderer.draw_ui_text(&indent_text, left_x, text_y, renderer.colors.line_number);
+            left_x += (indent_text.len() as f32 + 2.0) * char_width;
+            if settings.word_wrap {
+                renderer.draw_ui_text("Wrap", left_x, text_y, renderer.colors.line_number);
+                left_x += 6.0 * char_width;
+            }
+
+            // Files kept from the language server by the settings
+            if self.active_file_lsp_excluded() {
+                let text = "LSP off (excluded)";
+                renderer.draw_ui_text(text, left_x, text_y, renderer.colors.line_number);
+                left_x += (text.len() as f32 + 2.0) * char_width;
+            }
+
+            // Invisible characters, drawn as boxes in the text
+            let invisible = editor.invisible_counts();
+            if invisible.total > 0 {
+                let text = if invisible.bidi > 0 {
+                    format!("Invisible:{} (bidi:{})", invisible.total, invisible.bidi)
+                } else {
+                    format!("Invisible:{}", invisible.total)
+                };
+                renderer.draw_ui_text(&text, left_x, text_y, renderer.colors.diagnostic_warning);
+                left_x += (text.len() as f32 + 2.0) * char_width;
+            }
 
             // Performance metrics (if enabled)
             if self.show_perf_metrics {
@@ -1508,36 +4658,110 @@ impl EditorApp {
                     self.perf_metrics.typing_latency.average_ms(),
                     self.perf_metrics.memory_stats.buffer_mb(),
                 );
-                renderer.draw_text(&perf_text, left_x, text_y, [0.6, 0.8, 0.6, 1.0]);
+                renderer.draw_ui_text(&perf_text, left_x, text_y, [0.6, 0.8, 0.6, 1.0]);
             }
 
             // Right side: Cursor position
             let cursor = editor.cursor_position();
             let pos_text = format!("Ln {}, Col {}", cursor.line + 1, cursor.col + 1);
             let pos_x = viewport_width - padding - pos_text.len() as f32 * char_width;
-            renderer.draw_text(&pos_text, pos_x, text_y, renderer.colors.text);
-
-            // Modified indicator (if modified)
-            if editor.is_modified() {
+            renderer.draw_ui_text(&pos_text, pos_x, text_y, renderer.colors.text);
+
+            // Save in progress, or modified indicator (if modified)
+            let mut right_x = pos_x;
+            let active_id = self.workspace.active_buffer_id();
+            let save_error = active_id.and_then(|id| self.save_queue.last_error(id));
+            if active_id.is_some_and(|id| self.save_queue.is_saving(id)) {
+                let saving_text = "Saving...";
+                right_x -= (saving_text.len() as f32 + 3.0) * char_width;
+                renderer.draw_ui_text(saving_text, right_x, text_y, renderer.colors.line_number);
+            } else if save_error.is_some() && editor.is_modified() {
+                let failed_text = "Save failed (Ctrl+S to retry)";
+                right_x -= (failed_text.len() as f32 + 3.0) * char_width;
+                renderer.draw_ui_text(failed_text, right_x, text_y, renderer.colors.diagnostic_error);
+            } else if editor.is_modified() {
                 let mod_text = "Modified";
-                let mod_x = pos_x - (mod_text.len() as f32 + 3.0) * char_width;
-                renderer.draw_text(mod_text, mod_x, text_y, [0.9, 0.7, 0.3, 1.0]);
+                right_x -= (mod_text.len() as f32 + 3.0) * char_width;
+                renderer.draw_ui_text(mod_text, right_x, text_y, [0.9, 0.7, 0.3, 1.0]);
+            }
+
+            // Diagnostic counts (only severities that pass the display filter)
+            let counts = [
+                (DiagnosticSeverity::Error, "E", renderer.colors.diagnostic_error),
+                (DiagnosticSeverity::Warning, "W", renderer.colors.diagnostic_warning),
+                (DiagnosticSeverity::Information, "I", renderer.colors.diagnostic_info),
+                (DiagnosticSeverity::Hint, "H", renderer.colors.diagnostic_hint),
+            ];
+            for (severity, prefix, color) in counts.into_iter().rev() {
+                let count = editor.visible_diagnostic_count(severity);
+                if count == 0 {
+                    continue;
+                }
+                let text = format!("{}:{}", prefix, count);
+                right_x -= (text.len() as f32 + 2.0) * char_width;
+                renderer.draw_ui_text(&text, right_x, text_y, color);
+            }
+            if editor.diagnostics_stale() {
+                let stale_text = "diagnostics out of date";
+                right_x -= (stale_text.len() as f32 + 2.0) * char_width;
+                renderer.draw_ui_text(stale_text, right_x, text_y, renderer.colors.line_number);
+            }
+
+            // When the file was last saved
+            if let Some(saved) = editor.times().last_saved {
+                let saved_text = format!("Saved {}", format_age(saved.elapsed()));
+                right_x -= (saved_text.len() as f32 + 2.0) * char_width;
+                renderer.draw_ui_text(&saved_text, right_x, text_y, renderer.colors.line_number);
+            }
+        }
+    }
+
+    /// Height of a notification.
+    fn notification_height(&self) -> f32 {
+        (self.chrome.line_height + 2.0 * NOTIFICATION_PADDING).ceil()
+    }
+
+    /// Returns the index, in [`NotificationManager::visible`] order, of the
+    /// notification drawn at (`x`, `y`) at `now`.
+    pub fn notification_at(&self, x: f32, y: f32, viewport_width: f32, now: Instant) -> Option<usize> {
+        let left = viewport_width - NOTIFICATION_WIDTH - NOTIFICATION_MARGIN;
+        if x < left || x >= left + NOTIFICATION_WIDTH {
+            return None;
+        }
+        let height = self.notification_height();
+        let mut top = self.tab_bar_height() + NOTIFICATION_MARGIN;
+        for (index, notification) in self.notifications.visible().enumerate() {
+            if notification.visibility(now) <= 0.0 {
+                continue;
+            }
+            if y >= top && y < top + height {
+                return Some(index);
             }
+            top += height + NOTIFICATION_MARGIN;
+        }
+        None
+    }
+
+    /// Dismisses the notification clicked at (`x`, `y`). Returns whether
+    /// the click was on one.
+    pub fn dismiss_notification_at(&mut self, x: f32, y: f32, viewport_width: f32) -> bool {
+        match self.notification_at(x, y, viewport_width, Instant::now()) {
+            Some(index) => self.notifications.dismiss(index),
+            None => false,
         }
     }
 
     /// Renders notifications in the top-right corner.
-    fn render_notifications(&self, renderer: &mut GpuRenderer, viewport_width: f32, char_width: f32, line_height: f32) {
-        const NOTIFICATION_WIDTH: f32 = 300.0;
-        const NOTIFICATION_HEIGHT: f32 = 40.0;
-        const NOTIFICATION_MARGIN: f32 = 8.0;
-        const NOTIFICATION_PADDING: f32 = 12.0;
+    fn render_notifications(&self, renderer: &mut DrawList, viewport_width: f32) {
+        let ChromeMetrics { char_width, line_height } = self.chrome;
+        let notification_height = self.notification_height();
 
-        let start_y = TAB_BAR_HEIGHT + NOTIFICATION_MARGIN;
+        let start_y = self.tab_bar_height() + NOTIFICATION_MARGIN;
         let mut y = start_y;
+        let now = Instant::now();
 
         for notification in self.notifications.visible() {
-            let visibility = notification.visibility();
+            let visibility = notification.visibility(now);
             if visibility <= 0.0 {
                 continue;
             }
@@ -1551,23 +4775,23 @@ impl EditorApp {
             text_color[3] *= visibility;
 
             // Draw background
-            renderer.draw_rect(x, y, NOTIFICATION_WIDTH, NOTIFICATION_HEIGHT, bg_color);
+            renderer.draw_rect(x, y, NOTIFICATION_WIDTH, notification_height, bg_color);
 
             // Draw border
             let border_color = [0.0, 0.0, 0.0, 0.3 * visibility];
             renderer.draw_rect(x, y, NOTIFICATION_WIDTH, 1.0, border_color);
-            renderer.draw_rect(x, y + NOTIFICATION_HEIGHT - 1.0, NOTIFICATION_WIDTH, 1.0, border_color);
-            renderer.draw_rect(x, y, 1.0, NOTIFICATION_HEIGHT, border_color);
-            renderer.draw_rect(x + NOTIFICATION_WIDTH - 1.0, y, 1.0, NOTIFICATION_HEIGHT, border_color);
+            renderer.draw_rect(x, y + notification_height - 1.0, NOTIFICATION_WIDTH, 1.0, border_color);
+            renderer.draw_rect(x, y, 1.0, notification_height, border_color);
+            renderer.draw_rect(x + NOTIFICATION_WIDTH - 1.0, y, 1.0, notification_height, border_color);
 
             // Draw text (truncate if too long)
             let text_x = x + NOTIFICATION_PADDING;
-            let text_y = y + (NOTIFICATION_HEIGHT - line_height) / 2.0;
+            let text_y = y + (notification_height - line_height) / 2.0;
             let max_chars = ((NOTIFICATION_WIDTH - 2.0 * NOTIFICATION_PADDING) / char_width) as usize;
             let display_text: String = notification.message.chars().take(max_chars).collect();
-            renderer.draw_text(&display_text, text_x, text_y, text_color);
+            renderer.draw_ui_text(&display_text, text_x, text_y, text_color);
 
-            y += NOTIFICATION_HEIGHT + NOTIFICATION_MARGIN;
+            y += notification_height + NOTIFICATION_MARGIN;
         }
     }
 
@@ -1597,12 +4821,10 @@ struct GpuState {
     renderer: GpuRenderer,
     /// Current DPI scale factor.
     scale_factor: f64,
-    /// Base font size (before DPI scaling).
-    base_font_size: f32,
 }
 
 impl GpuState {
-    fn new(window: Arc<Window>, font_size: f32) -> Self {
+    fn new(window: Arc<Window>, font_size: f32, ui_font_size: f32) -> Self {
         let size = window.inner_size();
         let scale_factor = window.scale_factor();
 
@@ -1653,6 +4875,7 @@ impl GpuState {
 
         // Scale font size by DPI factor for crisp text on high-DPI displays
         let scaled_font_size = font_size * scale_factor as f32;
+        let scaled_ui_font_size = ui_font_size * scale_factor as f32;
         log::info!("DPI scale factor: {:.2}, font size: {:.1} -> {:.1}", scale_factor, font_size, scaled_font_size);
 
         let renderer = GpuRenderer::new(
@@ -1662,6 +4885,7 @@ impl GpuState {
             size.width.max(1),
             size.height.max(1),
             scaled_font_size,
+            scaled_ui_font_size,
         );
 
         Self {
@@ -1672,7 +4896,6 @@ impl GpuState {
             size,
             renderer,
             scale_factor,
-            base_font_size: font_size,
         }
     }
 
@@ -1732,6 +4955,69 @@ impl GpuState {
     fn char_width(&self) -> f32 {
         self.renderer.atlas().char_width
     }
+
+    /// Returns the layout metrics of the UI font.
+    fn chrome_metrics(&self) -> ChromeMetrics {
+        ChromeMetrics::from_atlas(self.renderer.ui_atlas())
+    }
+
+    /// Regenerates the editor font atlas at `font_size`, scaled for the
+    /// display.
+    fn set_font_size(&mut self, font_size: f32) {
+        let scaled = font_size * self.scale_factor as f32;
+        self.renderer.set_font_size(&self.device, &self.queue, scaled);
+    }
+}
+
+/// Which tabs a bulk close closes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CloseTabs {
+    All,
+    /// All but the active one.
+    Others,
+    /// Those without unsaved changes.
+    Saved,
+}
+
+/// How often a drag held above or below the text scrolls by a line.
+const DRAG_SCROLL_INTERVAL: Duration = Duration::from_millis(40);
+
+/// Counts clicks in quick succession at the same buffer position.
+#[derive(Debug, Clone, Copy, Default)]
+struct ClickCounter {
+    /// When and where the last click was.
+    last: Option<(Instant, usize, usize)>,
+    /// Clicks in the current run.
+    count: usize,
+}
+
+impl ClickCounter {
+    /// Registers a click at `line` and `col`. Returns 1 for a single click,
+    /// 2 for a double click and 3 for a triple click, counting clicks at
+    /// most `interval` apart; a fourth click starts over.
+    fn click(&mut self, now: Instant, line: usize, col: usize, interval: Duration) -> usize {
+        let repeated = self
+            .last
+            .is_some_and(|(at, l, c)| (l, c) == (line, col) && now.duration_since(at) <= interval);
+        self.count = if repeated && self.count < 3 { self.count + 1 } else { 1 };
+        self.last = Some((now, line, col));
+        self.count
+    }
+}
+
+/// What moving the mouse with the left button held does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MouseDrag {
+    /// The button isn't held.
+    None,
+    /// Extends the selection.
+    Select,
+    /// Alt was held on press and the pointer hasn't moved yet: releasing
+    /// toggles a cursor at the press position, moving starts a block
+    /// selection there.
+    AltPress { line: usize, col: usize },
+    /// Extends a block selection.
+    Block,
 }
 
 /// Application state wrapper for winit 0.30.
@@ -1742,713 +5028,877 @@ struct AppState {
     modifiers: ModifiersState,
     /// Current mouse position.
     mouse_position: PhysicalPosition<f64>,
-    /// Whether the left mouse button is pressed (for drag selection).
-    mouse_dragging: bool,
+    /// What dragging with the left mouse button does.
+    mouse_drag: MouseDrag,
+    /// Where the left button went down, until the pointer moves past the
+    /// drag threshold.
+    press_position: Option<PhysicalPosition<f64>>,
+    /// When a drag outside the text last scrolled.
+    drag_scrolled_at: Instant,
+    /// Recent clicks, for telling double and triple clicks apart.
+    clicks: ClickCounter,
+    /// What the last click selected, which dragging extends by.
+    selection_granularity: SelectionGranularity,
+    /// Position of the click that started the selection.
+    granularity_anchor: (usize, usize),
+    /// Text typed while a modal had the input.
+    replay: ReplayQueue,
+    /// The system dialog that is up, or whose input is still arriving.
+    dialog: DialogSpan,
+    /// Shows the system dialogs.
+    dialogs: Box<dyn SystemDialogs>,
+    /// The window's position and size when not maximized, kept up to date
+    /// for saving on quit.
+    window_geometry: Option<WindowGeometry>,
 }
 
-impl AppState {
-    fn new(app: EditorApp) -> Self {
-        Self {
-            app,
-            gpu: None,
-            window: None,
-            modifiers: ModifiersState::empty(),
-            mouse_position: PhysicalPosition::new(0.0, 0.0),
-            mouse_dragging: false,
-        }
-    }
-
-    fn handle_mouse_click(&mut self, extend_selection: bool) {
-        if let Some(gpu) = &self.gpu {
-            // Check if click is in tab bar
-            if self.app.is_in_tab_bar(self.mouse_position.y as f32) {
-                if let Some(tab_index) = self
-                    .app
-                    .handle_tab_bar_click(self.mouse_position.x as f32, gpu.char_width())
-                {
-                    self.app.flush_pending_lsp_changes(true);
-                    self.app.workspace.switch_to_tab(tab_index);
-                    self.update_window_title();
-                }
-                return;
-            }
-
-            let (line, col) = self.app.screen_to_buffer_position(
-                self.mouse_position.x as f32,
-                self.mouse_position.y as f32,
-                gpu.char_width(),
-                gpu.line_height(),
-            );
-            if let Some(editor) = self.app.workspace.active_editor_mut() {
-                editor.set_cursor_position(line, col, extend_selection);
-            }
-            self.app.reset_cursor_blink();
-        }
-    }
-
-    fn handle_mouse_drag(&mut self) {
-        // Don't drag in tab bar or search bar
-        if self.app.is_in_tab_bar(self.mouse_position.y as f32)
-            || self.app.is_in_search_bar(self.mouse_position.y as f32) {
-            return;
-        }
+/// The system's file and message dialogs. Each blocks until answered.
+trait SystemDialogs {
+    /// Asks for a file to open.
+    fn pick_file(&mut self, dialog: rfd::FileDialog) -> Option<PathBuf>;
+    /// Asks where to save a file.
+    fn save_file(&mut self, dialog: rfd::FileDialog) -> Option<PathBuf>;
+    /// Asks a question, returning the button pressed.
+    fn ask(&mut self, dialog: rfd::MessageDialog) -> rfd::MessageDialogResult;
+}
 
-        if let Some(gpu) = &self.gpu {
-            let (line, col) = self.app.screen_to_buffer_position(
-                self.mouse_position.x as f32,
-                self.mouse_position.y as f32,
-                gpu.char_width(),
-                gpu.line_height(),
-            );
-            if let Some(editor) = self.app.workspace.active_editor_mut() {
-                editor.set_cursor_position(line, col, true);
-            }
-        }
-    }
+/// The dialogs of the desktop, shown with rfd.
+struct RfdDialogs;
 
-    /// Handles keyboard input when in input mode (search/replace/goto).
-    /// Returns true if the key was handled.
-    fn handle_input_mode_key(&mut self, key: &Key, _event_loop: &ActiveEventLoop) -> bool {
-        match key {
-            Key::Named(NamedKey::Backspace) => {
-                match self.app.input_mode {
-                    InputMode::Search | InputMode::Replace if self.app.focused_field == 0 => {
-                        self.app.search_text.pop();
-                        // Update search incrementally
-                        if let Some(editor) = self.app.workspace.active_editor_mut() {
-                            editor.find(&self.app.search_text);
-                        }
-                    }
-                    InputMode::Replace if self.app.focused_field == 1 => {
-                        self.app.replace_text.pop();
-                    }
-                    InputMode::GoToLine => {
-                        self.app.goto_text.pop();
-                    }
-                    InputMode::Rename => {
-                        self.app.rename_text.pop();
-                    }
-                    _ => {}
-                }
-                true
-            }
-            Key::Named(NamedKey::Enter) => {
-                match self.app.input_mode {
-                    InputMode::Search => {
-                        // Find next on Enter
-                        if let Some(editor) = self.app.workspace.active_editor_mut() {
-                            editor.find_next();
-                        }
-                    }
-                    InputMode::Replace => {
-                        if self.app.focused_field == 0 {
-                            // Move to replace field
-                            self.app.focused_field = 1;
-                        } else {
-                            // Perform replacement
-                            if self.modifiers.shift_key() {
-                                // Replace all with Shift+Enter
-                                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                                    let count = editor.replace_all(&self.app.replace_text);
-                                    log::info!("Replaced {} occurrences", count);
-                                    if count > 0 {
-                                        self.app.notifications.success(format!("Replaced {} occurrence{}", count, if count == 1 { "" } else { "s" }));
-                                    } else {
-                                        self.app.notifications.info("No matches to replace");
-                                    }
-                                }
-                                self.app.notify_lsp_document_change();
-                                self.update_window_title();
-                            } else {
-                                // Replace current
-                                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                                    if editor.replace_current(&self.app.replace_text) {
-                                        self.app.notifications.info("Replaced match");
-                                    }
-                                }
-                                self.app.notify_lsp_document_change();
-                                self.update_window_title();
-                            }
-                        }
-                    }
-                    InputMode::GoToLine => {
-                        // Go to the specified line
-                        if let Ok(line_num) = self.app.goto_text.parse::<usize>() {
-                            if let Some(editor) = self.app.workspace.active_editor_mut() {
-                                editor.go_to_line(line_num);
-                            }
-                            self.app.close_input_bar();
-                        }
-                    }
-                    InputMode::Rename => {
-                        // Request rename with the new name
-                        if !self.app.rename_text.is_empty() {
-                            let new_name = self.app.rename_text.clone();
-                            self.app.request_rename(&new_name);
-                        } else {
-                            self.app.close_input_bar();
-                        }
-                    }
-                    _ => {}
-                }
-                true
-            }
-            Key::Named(NamedKey::Tab) => {
-                // Switch between search and replace fields
-                if self.app.input_mode == InputMode::Replace {
-                    self.app.focused_field = if self.app.focused_field == 0 { 1 } else { 0 };
-                }
-                true
-            }
-            Key::Character(ch) => {
-                if !self.modifiers.control_key() && !self.modifiers.alt_key() {
-                    if let Some(c) = ch.chars().next() {
-                        match self.app.input_mode {
-                            InputMode::Search | InputMode::Replace if self.app.focused_field == 0 => {
-                                self.app.search_text.push(c);
-                                // Update search incrementally
-                                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                                    editor.find(&self.app.search_text);
-                                }
-                            }
-                            InputMode::Replace if self.app.focused_field == 1 => {
-                                self.app.replace_text.push(c);
-                            }
-                            InputMode::GoToLine => {
-                                // Only allow digits
-                                if c.is_ascii_digit() {
-                                    self.app.goto_text.push(c);
-                                }
-                            }
-                            InputMode::Rename => {
-                                // Allow valid identifier characters
-                                if c.is_alphanumeric() || c == '_' {
-                                    self.app.rename_text.push(c);
-                                }
-                            }
-                            _ => {}
-                        }
-                        return true;
-                    }
-                }
-                false
-            }
-            _ => false,
+impl SystemDialogs for RfdDialogs {
+    fn pick_file(&mut self, dialog: rfd::FileDialog) -> Option<PathBuf> {
+        dialog.pick_file()
+    }
+
+    fn save_file(&mut self, dialog: rfd::FileDialog) -> Option<PathBuf> {
+        dialog.save_file()
+    }
+
+    fn ask(&mut self, dialog: rfd::MessageDialog) -> rfd::MessageDialogResult {
+        dialog.show()
+    }
+}
+
+impl AppState {
+    fn new(app: EditorApp) -> Self {
+        Self {
+            app,
+            gpu: None,
+            window: None,
+            modifiers: ModifiersState::empty(),
+            mouse_position: PhysicalPosition::new(0.0, 0.0),
+            mouse_drag: MouseDrag::None,
+            press_position: None,
+            drag_scrolled_at: Instant::now(),
+            clicks: ClickCounter::default(),
+            selection_granularity: SelectionGranularity::Char,
+            granularity_anchor: (0, 0),
+            replay: ReplayQueue::new(),
+            dialog: DialogSpan::new(),
+            dialogs: Box::new(RfdDialogs),
+            window_geometry: None,
         }
     }
 
-    fn execute_command(&mut self, command: EditorCommand, _event_loop: &ActiveEventLoop) -> bool {
-        match command {
-            EditorCommand::Save => {
-                self.app.flush_pending_lsp_changes(true);
-                if let Err(e) = self.app.workspace.save_active() {
-                    if e.kind() == std::io::ErrorKind::Other {
-                        // No file path - trigger Save As
-                        self.show_save_as_dialog();
-                    } else {
-                        log::error!("Failed to save: {}", e);
-                        self.app.notifications.error(format!("Failed to save: {}", e));
-                    }
-                } else {
-                    // Notify LSP about the saved file
-                    self.app.notify_lsp_file_saved();
-                    // Get file name for notification
-                    let filename = self.app.workspace.active_editor()
-                        .and_then(|e| e.file_path())
-                        .and_then(|p| p.file_name())
-                        .and_then(|n| n.to_str())
-                        .unwrap_or("File");
-                    self.app.notifications.success(format!("Saved: {}", filename));
-                }
-                self.update_window_title();
-                false
-            }
-            EditorCommand::SaveAs => {
-                self.app.flush_pending_lsp_changes(true);
-                self.show_save_as_dialog();
-                false
-            }
-            EditorCommand::OpenFile => {
-                self.show_open_file_dialog();
-                false
-            }
-            EditorCommand::NewFile => {
-                self.app.workspace.new_buffer();
-                self.update_window_title();
-                false
-            }
-            EditorCommand::CloseTab => {
-                self.close_active_tab();
-                false
+    /// Writes the window's placement to `window.json` in the config
+    /// directory, for the next launch to open the window there.
+    fn save_window_geometry(&self) {
+        let (Some(window), Some(geometry)) = (&self.window, self.window_geometry) else {
+            return;
+        };
+        let geometry = WindowGeometry {
+            maximized: window.is_maximized(),
+            scale_factor: window.scale_factor(),
+            ..geometry
+        };
+        if let Some(dir) = config_dir() {
+            if let Err(e) = geometry.save(&dir.join("window.json")) {
+                log::warn!("Failed to save window placement: {}", e);
             }
-            EditorCommand::Quit => {
-                if self.app.workspace.has_unsaved_changes() {
-                    // Show confirmation dialog
-                    let result = rfd::MessageDialog::new()
-                        .set_title("Unsaved Changes")
-                        .set_description("You have unsaved changes. Are you sure you want to quit?")
-                        .set_buttons(rfd::MessageButtons::YesNo)
-                        .show();
+        }
+    }
 
-                    if result != rfd::MessageDialogResult::Yes {
-                        return false; // User cancelled, don't quit
-                    }
-                }
-                self.shutdown_lsp();
-                true
-            }
-            EditorCommand::NextTab => {
-                self.app.flush_pending_lsp_changes(true);
-                self.app.workspace.next_tab();
-                self.update_window_title();
-                false
-            }
-            EditorCommand::PrevTab => {
-                self.app.flush_pending_lsp_changes(true);
-                self.app.workspace.prev_tab();
-                self.update_window_title();
-                false
+    /// Records the window's position and size while it isn't maximized,
+    /// so a maximized window is saved with the place it returns to.
+    fn track_window_geometry(&mut self) {
+        let Some(window) = &self.window else {
+            return;
+        };
+        if window.is_maximized() {
+            return;
+        }
+        let size = window.inner_size();
+        let position = window.outer_position().unwrap_or_default();
+        self.window_geometry = Some(WindowGeometry {
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+            maximized: false,
+            scale_factor: window.scale_factor(),
+        });
+    }
+
+    /// Handles a left click. With Alt held the click adds a cursor, or
+    /// removes the one already there, once the button is released without
+    /// dragging; a plain click leaves a single cursor. A double click
+    /// selects the word under the pointer and a triple click the line.
+    fn handle_mouse_click(&mut self, extend_selection: bool) {
+        self.mouse_drag = MouseDrag::None;
+        self.press_position = Some(self.mouse_position);
+        // Notifications are drawn over everything, and a click dismisses one
+        let (x, y) = (self.mouse_position.x as f32, self.mouse_position.y as f32);
+        if let Some(gpu) = &self.gpu {
+            if self.app.dismiss_notification_at(x, y, gpu.size.width as f32) {
+                self.press_position = None;
+                return;
             }
-            EditorCommand::SwitchToTab(index) => {
+        }
+        // A click in the other pane of a split moves the focus there
+        if !self.app.tab_menu.is_open() && y >= self.app.content_y_offset() && self.app.focus_pane(self.app.pane_at(x)) {
+            self.update_visible_dimensions();
+            self.update_window_title();
+        }
+        if let Some(gpu) = &self.gpu {
+            // The overflow menu takes the click while it is open
+            let (x, y, viewport_width) = (self.mouse_position.x as f32, self.mouse_position.y as f32, gpu.size.width as f32);
+            if self.app.tab_menu.is_open() {
                 self.app.flush_pending_lsp_changes(true);
-                self.app.workspace.switch_to_tab(index);
-                self.update_window_title();
-                false
-            }
-            EditorCommand::InsertChar(ch) => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    // Use auto-bracket for opening brackets
-                    if matches!(ch, '(' | '[' | '{') {
-                        editor.insert_char_with_auto_bracket(ch);
-                    } else {
-                        editor.insert_char(ch);
-                    }
-                }
-                self.app.notify_lsp_document_change();
-                self.update_window_title();
-                false
-            }
-            EditorCommand::InsertNewline => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.insert_newline();
-                }
-                self.app.notify_lsp_document_change();
-                self.update_window_title();
-                false
-            }
-            EditorCommand::DeleteBackward => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.delete_backward();
-                }
-                self.app.notify_lsp_document_change();
+                self.app.handle_tab_menu_click(x, y, viewport_width);
                 self.update_window_title();
-                false
-            }
-            EditorCommand::DeleteForward => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.delete_forward();
-                }
-                self.app.notify_lsp_document_change();
-                self.update_window_title();
-                false
-            }
-            EditorCommand::MoveLeft => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_left(false);
-                }
-                false
+                return;
             }
-            EditorCommand::MoveRight => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_right(false);
-                }
-                false
+            if self.app.is_on_tab_menu_button(x, y, viewport_width) {
+                self.app.toggle_tab_menu();
+                return;
             }
-            EditorCommand::MoveUp => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_up(false);
+
+            // The conflict banner takes clicks on its actions
+            if self.app.is_in_disk_banner(x, y) {
+                if self.app.handle_disk_banner_click(x) {
+                    self.update_visible_dimensions();
+                    self.update_window_title();
                 }
-                false
+                return;
             }
-            EditorCommand::MoveDown => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_down(false);
+
+            // Check if click is in tab bar
+            if self.app.is_in_tab_bar(self.mouse_position.y as f32) {
+                if let Some(tab_index) = self
+                    .app
+                    .handle_tab_bar_click(self.mouse_position.x as f32)
+                {
+                    self.app.flush_pending_lsp_changes(true);
+                    self.app.workspace.switch_to_tab(tab_index);
+                    self.update_window_title();
                 }
-                false
+                return;
             }
-            EditorCommand::MoveWordLeft => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_word_left(false);
+
+            let (mouse_x, mouse_y) = (self.mouse_position.x as f32, self.mouse_position.y as f32);
+            let (line, col) = self.app.screen_to_buffer_position(
+                mouse_x,
+                mouse_y,
+                gpu.char_width(),
+                gpu.line_height(),
+            );
+
+            // Gutter columns with an action handle the click themselves
+            let in_content = mouse_y >= self.app.content_y_offset() && !self.app.is_in_search_bar(mouse_y);
+            if in_content {
+                let gutter_action = if self.app.zen.is_active() {
+                    None
+                } else {
+                    let gutter_x = mouse_x - self.app.content_x_offset(gpu.char_width());
+                    self.app.gutter_layout(gpu.char_width()).click_action(gutter_x)
+                };
+                if let Some(action) = gutter_action {
+                    self.app.handle_gutter_action(action, line);
+                    return;
                 }
-                false
             }
-            EditorCommand::MoveWordRight => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_word_right(false);
+
+            if self.modifiers.alt_key() {
+                let (line, col) = self.app.drag_position(mouse_x, mouse_y, gpu.char_width(), gpu.line_height(), true);
+                self.mouse_drag = MouseDrag::AltPress { line, col };
+                self.clicks = ClickCounter::default();
+            } else if let Some(editor) = self.app.workspace.active_editor_mut() {
+                let interval = self.app.input_handler.mouse.multi_click_interval();
+                let granularity = match self.clicks.click(Instant::now(), line, col, interval) {
+                    _ if extend_selection => SelectionGranularity::Char,
+                    2 => SelectionGranularity::Word,
+                    3 => SelectionGranularity::Line,
+                    _ => SelectionGranularity::Char,
+                };
+                editor.collapse_cursors();
+                editor.exit_block_selection();
+                if granularity == SelectionGranularity::Char {
+                    editor.set_cursor_position(line, col, extend_selection);
+                } else {
+                    editor.select_units((line, col), line, col, granularity);
                 }
-                false
+                self.selection_granularity = granularity;
+                self.granularity_anchor = (line, col);
+                self.mouse_drag = MouseDrag::Select;
             }
-            EditorCommand::MoveToLineStart => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_to_line_start(false);
-                }
-                false
+            self.app.reset_cursor_blink();
+        }
+    }
+
+    /// Handles a mouse move with the left button held: extends the
+    /// selection, or the block selection of an Alt+drag. Nothing happens
+    /// until the pointer moves past the drag threshold.
+    fn handle_mouse_drag(&mut self) {
+        let Some(gpu) = &self.gpu else {
+            return;
+        };
+        if let Some(press) = self.press_position {
+            let now = (self.mouse_position.x as f32, self.mouse_position.y as f32);
+            if !self.app.input_handler.mouse.starts_drag((press.x as f32, press.y as f32), now) {
+                return;
             }
-            EditorCommand::MoveToLineStartSmart => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_to_line_start_smart(false);
+            self.press_position = None;
+        }
+        let block = matches!(self.mouse_drag, MouseDrag::AltPress { .. } | MouseDrag::Block);
+        let (line, col) = self.app.drag_position(
+            self.mouse_position.x as f32,
+            self.mouse_position.y as f32,
+            gpu.char_width(),
+            gpu.line_height(),
+            block,
+        );
+        let Some(editor) = self.app.workspace.active_editor_mut() else {
+            return;
+        };
+        match self.mouse_drag {
+            MouseDrag::None => {}
+            MouseDrag::Select => match self.selection_granularity {
+                SelectionGranularity::Char => editor.set_cursor_position(line, col, true),
+                granularity => editor.select_units(self.granularity_anchor, line, col, granularity),
+            },
+            MouseDrag::AltPress { line: press_line, col: press_col } => {
+                if (line, col) != (press_line, press_col) {
+                    editor.collapse_cursors();
+                    editor.set_cursor_position(press_line, press_col, false);
+                    editor.start_block_selection();
+                    editor.extend_block_selection(line, col);
+                    editor.scroll_to_cursor();
+                    self.mouse_drag = MouseDrag::Block;
                 }
-                false
             }
-            EditorCommand::MoveToLineEnd => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_to_line_end(false);
-                }
-                false
+            MouseDrag::Block => {
+                editor.extend_block_selection(line, col);
+                editor.scroll_to_cursor();
             }
-            EditorCommand::MovePageUp => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_page_up(false);
+        }
+    }
+
+    /// Handles a key press. Returns true if the app should exit.
+    fn handle_key_press(&mut self, logical_key: &Key) -> bool {
+        // A modal takes the keys first; the editor never sees them
+        let text = match logical_key {
+            Key::Character(text) if !self.modifiers.control_key() && !self.modifiers.alt_key() => Some(text.as_str()),
+            _ => None,
+        };
+        let kind = if text.is_some() { InputKind::Text } else { InputKind::Key };
+        match input_gate::route(self.modal(), kind) {
+            InputRoute::Editor => {}
+            InputRoute::Modal => {
+                // Escape closes the tab overflow menu
+                if *logical_key == Key::Named(NamedKey::Escape) {
+                    self.app.tab_menu.close();
+                    if let Some(window) = &self.window {
+                        window.request_redraw();
+                    }
                 }
-                false
+                return false;
             }
-            EditorCommand::MovePageDown => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_page_down(false);
+            InputRoute::Held => {
+                if let Some(text) = text {
+                    self.replay.hold(text, Instant::now());
                 }
-                false
+                return false;
             }
-            EditorCommand::MoveToBufferStart => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_to_buffer_start(false);
-                }
-                false
+            InputRoute::Dropped => return false,
+        }
+
+        // Escape dismisses the tab switcher without switching
+        if self.app.tab_switcher.is_active() && *logical_key == Key::Named(NamedKey::Escape) {
+            self.app.tab_switcher.cancel();
+            if let Some(window) = &self.window {
+                window.request_redraw();
             }
-            EditorCommand::MoveToBufferEnd => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_to_buffer_end(false);
+            return false;
+        }
+
+        // Navigate or dismiss the line diagnostics popup
+        if self.app.diagnostics_popup.is_some() {
+            let handled = match logical_key {
+                Key::Named(NamedKey::ArrowDown) => {
+                    if let Some(popup) = &mut self.app.diagnostics_popup {
+                        popup.select_next();
+                    }
+                    true
                 }
-                false
-            }
-            EditorCommand::SelectLeft => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_left(true);
+                Key::Named(NamedKey::ArrowUp) => {
+                    if let Some(popup) = &mut self.app.diagnostics_popup {
+                        popup.select_prev();
+                    }
+                    true
                 }
-                false
-            }
-            EditorCommand::SelectRight => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_right(true);
+                Key::Named(NamedKey::Enter) => {
+                    self.app.goto_selected_line_diagnostic();
+                    true
                 }
-                false
-            }
-            EditorCommand::SelectUp => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_up(true);
+                Key::Named(NamedKey::Escape) => {
+                    self.app.hide_line_diagnostics();
+                    true
                 }
-                false
-            }
-            EditorCommand::SelectDown => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_down(true);
+                Key::Named(NamedKey::Shift | NamedKey::Control | NamedKey::Alt | NamedKey::Super) => false,
+                _ => {
+                    // Any other key closes the popup and is handled normally
+                    self.app.hide_line_diagnostics();
+                    false
                 }
-                false
-            }
-            EditorCommand::SelectWordLeft => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_word_left(true);
+            };
+            if handled {
+                if let Some(window) = &self.window {
+                    window.request_redraw();
                 }
-                false
+                return false;
             }
-            EditorCommand::SelectWordRight => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_word_right(true);
+        }
+
+        // Navigate, apply or dismiss the code actions menu
+        if self.app.code_actions.is_some() {
+            let handled = match logical_key {
+                Key::Named(NamedKey::ArrowDown) => {
+                    if let Some(menu) = &mut self.app.code_actions {
+                        menu.select_next();
+                    }
+                    true
                 }
-                false
-            }
-            EditorCommand::SelectToLineStart => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_to_line_start(true);
+                Key::Named(NamedKey::ArrowUp) => {
+                    if let Some(menu) = &mut self.app.code_actions {
+                        menu.select_prev();
+                    }
+                    true
                 }
-                false
-            }
-            EditorCommand::SelectToLineStartSmart => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_to_line_start_smart(true);
+                Key::Named(NamedKey::Enter) => {
+                    self.app.apply_selected_code_action();
+                    self.update_window_title();
+                    true
                 }
-                false
-            }
-            EditorCommand::SelectToLineEnd => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_to_line_end(true);
+                Key::Named(NamedKey::Escape) => {
+                    self.app.hide_code_actions();
+                    true
                 }
-                false
-            }
-            EditorCommand::SelectPageUp => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_page_up(true);
+                Key::Named(NamedKey::Shift | NamedKey::Control | NamedKey::Alt | NamedKey::Super) => false,
+                _ => {
+                    // Any other key closes the menu and is handled normally
+                    self.app.hide_code_actions();
+                    false
                 }
-                false
-            }
-            EditorCommand::SelectPageDown => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_page_down(true);
+            };
+            if handled {
+                if let Some(window) = &self.window {
+                    window.request_redraw();
                 }
-                false
+                return false;
             }
-            EditorCommand::SelectToBufferStart => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_to_buffer_start(true);
+        }
+
+        // Handle completion navigation first
+        if self.app.completion_visible {
+            match logical_key {
+                Key::Named(NamedKey::ArrowDown) => {
+                    self.app.completion_next();
+                    self.app.reset_cursor_blink();
+                    if let Some(window) = &self.window {
+                        window.request_redraw();
+                    }
+                    return false;
                 }
-                false
-            }
-            EditorCommand::SelectToBufferEnd => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_to_buffer_end(true);
+                Key::Named(NamedKey::ArrowUp) => {
+                    self.app.completion_prev();
+                    self.app.reset_cursor_blink();
+                    if let Some(window) = &self.window {
+                        window.request_redraw();
+                    }
+                    return false;
                 }
-                false
-            }
-            EditorCommand::SelectAll => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.select_all();
+                Key::Named(NamedKey::Enter) | Key::Named(NamedKey::Tab) => {
+                    self.app.accept_completion();
+                    self.app.notify_lsp_document_change();
+                    self.update_window_title();
+                    self.app.reset_cursor_blink();
+                    if let Some(window) = &self.window {
+                        window.request_redraw();
+                    }
+                    return false;
                 }
-                false
-            }
-            EditorCommand::DuplicateLine => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.duplicate_line();
+                Key::Named(NamedKey::Escape) => {
+                    self.app.hide_completion();
+                    if let Some(window) = &self.window {
+                        window.request_redraw();
+                    }
+                    return false;
                 }
-                self.app.notify_lsp_document_change();
-                self.update_window_title();
-                false
-            }
-            EditorCommand::MoveLineUp => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_line_up();
+                // Typing a word or backspacing narrows the list
+                Key::Character(text)
+                    if !self.modifiers.control_key()
+                        && !self.modifiers.alt_key()
+                        && text.chars().all(|ch| ch.is_alphanumeric() || ch == '_') => {}
+                Key::Named(NamedKey::Backspace) => {}
+                _ => {
+                    // Any other key hides completion
+                    self.app.hide_completion();
                 }
-                self.app.notify_lsp_document_change();
-                self.update_window_title();
-                false
             }
-            EditorCommand::MoveLineDown => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.move_line_down();
+        }
+
+        // Handle input mode (search/replace/goto) first
+        if self.app.is_input_mode() {
+            let handled = self.handle_input_mode_key(logical_key);
+            if handled {
+                self.app.reset_cursor_blink();
+                if let Some(window) = &self.window {
+                    window.request_redraw();
                 }
-                self.app.notify_lsp_document_change();
-                self.update_window_title();
-                false
-            }
-            EditorCommand::ToggleBlockSelection => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.toggle_block_selection();
+            } else {
+                // Check for commands that should work in input mode (Escape, F3)
+                if let Some(command) = self
+                    .app
+                    .input_handler
+                    .handle_key_event_new(logical_key, ElementState::Pressed)
+                {
+                    match command {
+                        EditorCommand::CloseSearch
+                        | EditorCommand::FindNext
+                        | EditorCommand::FindPrev => {
+                            if command_registry().execute(self, &command) {
+                                return true;
+                            }
+                            if let Some(window) = &self.window {
+                                window.request_redraw();
+                            }
+                        }
+                        _ => {}
+                    }
                 }
-                false
             }
-            EditorCommand::AddCursorAbove => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.add_cursor_above();
+        } else {
+            // Normal mode - regular command handling
+            if let Some(command) = self
+                .app
+                .input_handler
+                .handle_key_event_new(logical_key, ElementState::Pressed)
+            {
+                let block_mode = self.app.workspace.active_editor().is_some_and(|e| e.is_block_selection_mode());
+                let command = if block_mode { block_mode_command(command) } else { command };
+                if command_registry().execute(self, &command) {
+                    return true;
                 }
-                false
-            }
-            EditorCommand::AddCursorBelow => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.add_cursor_below();
+                if let Some(window) = &self.window {
+                    window.request_redraw();
                 }
-                false
             }
-            EditorCommand::CollapseCursors => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.collapse_cursors();
-                    // Also exit block selection mode
-                    editor.exit_block_selection();
+
+            // Handle character input for text
+            if let Key::Character(ch) = logical_key {
+                if !self.modifiers.control_key() && !self.modifiers.alt_key() {
+                    if let Some(c) = ch.chars().next() {
+                        if let Some(command) = self.app.input_handler.handle_char_input(c) {
+                            // Record keypress for typing latency measurement
+                            self.app.record_keypress();
+                            command_registry().execute(self, &command);
+                            if let Some(window) = &self.window {
+                                window.request_redraw();
+                            }
+                        }
+                    }
                 }
-                false
             }
-            EditorCommand::Undo => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.undo();
+        }
+        false
+    }
+
+    /// Returns the modal that has the input instead of the editor, if any.
+    fn modal(&self) -> Option<Modal> {
+        if let Some(modal) = self.dialog.modal() {
+            Some(modal)
+        } else if self.app.tab_menu.is_open() {
+            Some(Modal::Menu)
+        } else {
+            None
+        }
+    }
+
+    /// Shows a system dialog through `show`, as the modal `modal`.
+    /// Returns what it answered, or `None` if a dialog is already up.
+    ///
+    /// The dialog blocks the event loop, so input the window got meanwhile
+    /// arrives only after it returned; until the loop has caught up with
+    /// that input, the dialog keeps it.
+    fn run_dialog<T>(&mut self, modal: Modal, show: impl FnOnce(&mut dyn SystemDialogs) -> T) -> Option<T> {
+        if self.dialog.is_open() {
+            return None;
+        }
+        self.dialog.open(modal);
+        let answer = show(self.dialogs.as_mut());
+        self.dialog.close(Instant::now());
+        Some(answer)
+    }
+
+    /// Ends a closed dialog's hold on the input once the event loop has
+    /// delivered what was queued during it.
+    fn finish_dialog(&mut self) {
+        if let Some(closed_at) = self.dialog.drained() {
+            self.end_modal(closed_at);
+        }
+    }
+
+    /// Replays the text typed just after a modal closed at `closed_at`,
+    /// once the modal no longer has the input.
+    fn end_modal(&mut self, closed_at: Instant) {
+        for text in self.replay.take_after_close(closed_at) {
+            for ch in text.chars() {
+                if let Some(command) = self.app.input_handler.handle_char_input(ch) {
+                    command_registry().execute(self, &command);
                 }
-                self.app.notify_lsp_document_change();
-                self.update_window_title();
-                false
             }
-            EditorCommand::Redo => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.redo();
+        }
+    }
+
+    /// Types text an input method committed into the active buffer, unless
+    /// its file is still loading, as typed characters are.
+    fn commit_ime_text(&mut self, text: &str) {
+        let Some(editor) = self.app.workspace.active_editor_mut() else {
+            return;
+        };
+        if !editor.is_fully_loaded() {
+            self.buffer_loading();
+            return;
+        }
+        editor.insert_text(text);
+        self.app.notify_lsp_document_change();
+        self.app.reset_cursor_blink();
+        self.update_window_title();
+    }
+
+    /// Handles keyboard input when in input mode (search/replace/goto).
+    /// Returns true if the key was handled.
+    fn handle_input_mode_key(&mut self, key: &Key) -> bool {
+        // A step-through replace takes all keys until it ends
+        if self.app.replace_walk.is_some() {
+            let answer = match key {
+                Key::Named(NamedKey::Enter) => Some(ReplaceAnswer::Replace),
+                Key::Character(ch) if ch.eq_ignore_ascii_case("y") => Some(ReplaceAnswer::Replace),
+                Key::Character(ch) if ch.eq_ignore_ascii_case("n") => Some(ReplaceAnswer::Skip),
+                Key::Character(ch) if ch.eq_ignore_ascii_case("a") => Some(ReplaceAnswer::All),
+                Key::Named(NamedKey::Escape) => {
+                    self.app.finish_replace_walk();
+                    None
                 }
+                _ => None,
+            };
+            if let Some(answer) = answer {
+                self.app.replace_walk_step(answer);
                 self.app.notify_lsp_document_change();
                 self.update_window_title();
-                false
             }
-            EditorCommand::Copy => {
-                if let Some(editor) = self.app.workspace.active_editor() {
-                    if let Some(text) = editor.get_selected_text() {
-                        if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                            if clipboard.set_text(&text).is_err() {
-                                self.app.notifications.error("Failed to copy to clipboard");
+            return true;
+        }
+
+        // Typing, deleting and moving within the focused field
+        if let Some(changed) = self.edit_focused_field(key) {
+            if changed {
+                self.app.focused_field_changed();
+            }
+            return true;
+        }
+
+        match key {
+            Key::Named(NamedKey::Enter) => {
+                match self.app.input_mode {
+                    InputMode::Search => {
+                        // Find next on Enter
+                        if let Some(editor) = self.app.workspace.active_editor_mut() {
+                            editor.find_next();
+                        }
+                    }
+                    InputMode::Replace => {
+                        if self.modifiers.alt_key() {
+                            // Alt+Enter steps through matches, confirming each
+                            self.app.start_replace_walk();
+                        } else if self.app.focused_field == 0 {
+                            // Move to replace field
+                            self.app.focused_field = 1;
+                        } else {
+                            // Perform replacement
+                            if self.modifiers.shift_key() {
+                                // Replace all with Shift+Enter
+                                if let Some(editor) = self.app.workspace.active_editor_mut() {
+                                    let count = editor.replace_all(self.app.replace_text.text());
+                                    log::info!("Replaced {} occurrences", count);
+                                    if count > 0 {
+                                        self.app.notifications.success(format!("Replaced {} occurrence{}", count, if count == 1 { "" } else { "s" }));
+                                    } else {
+                                        self.app.notifications.info("No matches to replace");
+                                    }
+                                }
+                                self.app.notify_lsp_document_change();
+                                self.update_window_title();
+                            } else {
+                                // Replace current
+                                if let Some(editor) = self.app.workspace.active_editor_mut() {
+                                    if editor.replace_current(self.app.replace_text.text()) {
+                                        self.app.notifications.info("Replaced match");
+                                    }
+                                }
+                                self.app.notify_lsp_document_change();
+                                self.update_window_title();
                             }
                         }
                     }
-                }
-                false
-            }
-            EditorCommand::Cut => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    if let Some(text) = editor.cut_selection() {
-                        if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                            if clipboard.set_text(&text).is_err() {
-                                self.app.notifications.error("Failed to copy to clipboard");
+                    InputMode::GoToLine => {
+                        self.app.submit_goto_line();
+                    }
+                    InputMode::Rename => {
+                        // Request rename with the new name
+                        if !self.app.rename_text.is_empty() {
+                            let new_name = self.app.rename_text.text().to_string();
+                            self.app.request_rename(&new_name);
+                        } else {
+                            self.app.close_input_bar();
+                        }
+                    }
+                    InputMode::FilterCommand => {
+                        if !self.app.filter_text.text().trim().is_empty() {
+                            let command = self.app.filter_text.text().to_string();
+                            self.app.close_input_bar();
+                            if self.app.filter_selection_through_command(&command) {
+                                self.app.notify_lsp_document_change();
+                                self.update_window_title();
                             }
                         }
                     }
-                }
-                self.app.notify_lsp_document_change();
-                self.update_window_title();
-                false
-            }
-            EditorCommand::Paste => {
-                if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                    if let Ok(text) = clipboard.get_text() {
-                        if let Some(editor) = self.app.workspace.active_editor_mut() {
-                            editor.paste(&text);
+                    InputMode::ProjectSearch if self.modifiers.shift_key() => {
+                        // Shift+Enter replaces every result
+                        self.app.replace_in_project();
+                        self.update_window_title();
+                    }
+                    InputMode::ProjectSearch => {
+                        // Enter runs a changed query, otherwise opens the selected result
+                        let stale = self.app.project_search.query() != self.app.project_search_text.text();
+                        if stale || self.app.project_search.results().is_empty() {
+                            self.app.run_project_search();
+                        } else {
+                            self.app.open_project_search_result();
+                            self.update_window_title();
                         }
-                        self.app.notify_lsp_document_change();
+                    }
+                    InputMode::RecentFiles => {
+                        self.app.open_selected_recent_file();
+                        self.update_window_title();
+                    }
+                    InputMode::Problems => {
+                        self.app.open_selected_problem();
                         self.update_window_title();
                     }
+                    InputMode::Encodings => self.app.reopen_with_selected_encoding(),
+                    _ => {}
                 }
-                false
+                true
             }
-            EditorCommand::ToggleComment => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.toggle_comment();
-                }
-                self.app.notify_lsp_document_change();
-                self.update_window_title();
-                false
+            Key::Named(NamedKey::ArrowDown) if self.app.input_mode == InputMode::ProjectSearch => {
+                self.app.project_search.select_next();
+                true
             }
-            EditorCommand::ToggleWordWrap => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.toggle_word_wrap();
-                    let state = if editor.word_wrap() { "enabled" } else { "disabled" };
-                    self.app.notifications.info(format!("Word wrap {}", state));
-                }
-                false
+            Key::Named(NamedKey::ArrowUp) if self.app.input_mode == InputMode::ProjectSearch => {
+                self.app.project_search.select_prev();
+                true
             }
-            EditorCommand::ToggleFold => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    // Detect folds if not already done
-                    if editor.fold_manager().regions().is_empty() {
-                        editor.detect_folds();
-                    }
-                    if editor.toggle_fold_at_cursor() {
-                        let (line, _) = editor.buffer().char_to_line_col(editor.cursor_char_index());
-                        let state = if editor.is_line_folded(line) { "folded" } else { "unfolded" };
-                        self.app.notifications.info(format!("Code region {}", state));
-                    }
-                }
-                false
+            Key::Named(NamedKey::ArrowDown) if self.app.input_mode == InputMode::RecentFiles => {
+                self.app.select_recent_file(1);
+                true
             }
-            EditorCommand::FoldAll => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.detect_folds();
-                    editor.fold_all();
-                    self.app.notifications.info("All regions folded");
-                }
-                false
+            Key::Named(NamedKey::ArrowUp) if self.app.input_mode == InputMode::RecentFiles => {
+                self.app.select_recent_file(-1);
+                true
             }
-            EditorCommand::UnfoldAll => {
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.unfold_all();
-                    self.app.notifications.info("All regions unfolded");
-                }
-                false
+            Key::Named(NamedKey::ArrowDown) if self.app.input_mode == InputMode::Encodings => {
+                self.app.select_encoding(1);
+                true
             }
-            EditorCommand::ScrollUp(lines) => {
-                let start = Instant::now();
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    let current = editor.scroll_offset();
-                    editor.set_scroll_offset(current.saturating_sub(lines as usize));
-                }
-                self.app.perf_metrics.scroll_perf.record_scroll(start.elapsed(), lines as u32);
-                false
+            Key::Named(NamedKey::ArrowUp) if self.app.input_mode == InputMode::Encodings => {
+                self.app.select_encoding(-1);
+                true
             }
-            EditorCommand::ScrollDown(lines) => {
-                let start = Instant::now();
-                if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    let current = editor.scroll_offset();
-                    editor.set_scroll_offset(current + lines as usize);
-                }
-                self.app.perf_metrics.scroll_perf.record_scroll(start.elapsed(), lines as u32);
-                false
+            Key::Named(NamedKey::ArrowDown) if self.app.input_mode == InputMode::Problems => {
+                self.app.problems.select(1);
+                true
             }
-            EditorCommand::OpenSearch => {
-                self.app.open_search();
-                false
+            Key::Named(NamedKey::ArrowUp) if self.app.input_mode == InputMode::Problems => {
+                self.app.problems.select(-1);
+                true
             }
-            EditorCommand::OpenReplace => {
-                self.app.open_replace();
-                false
+            Key::Named(NamedKey::Tab) => {
+                // Switch between search and replace fields
+                if matches!(self.app.input_mode, InputMode::Replace | InputMode::ProjectSearch) {
+                    self.app.focused_field = if self.app.focused_field == 0 { 1 } else { 0 };
+                }
+                true
             }
-            EditorCommand::FindNext => {
+            Key::Character(ch)
+                if self.modifiers.alt_key()
+                    && matches!(self.app.input_mode, InputMode::Search | InputMode::Replace)
+                    && ch.eq_ignore_ascii_case("r") =>
+            {
+                // Alt+R toggles regex mode
                 if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.find_next();
+                    editor.toggle_search_regex();
                 }
-                false
+                true
             }
-            EditorCommand::FindPrev => {
+            Key::Character(ch)
+                if self.modifiers.alt_key()
+                    && self.app.input_mode == InputMode::Replace
+                    && ch.eq_ignore_ascii_case("p") =>
+            {
+                // Alt+P toggles case-preserving replace
                 if let Some(editor) = self.app.workspace.active_editor_mut() {
-                    editor.find_prev();
+                    editor.toggle_search_preserve_case();
                 }
-                false
+                true
+            }
+            Key::Character(ch) if self.modifiers.alt_key() && self.app.input_mode == InputMode::Problems => {
+                // Alt+E/W/H show or hide a severity, Alt+G regroups and
+                // Alt+R retakes the snapshot
+                match ch.to_lowercase().as_str() {
+                    "e" => self.app.problems.toggle_severity(DiagnosticSeverity::Error),
+                    "w" => self.app.problems.toggle_severity(DiagnosticSeverity::Warning),
+                    "h" => self.app.problems.toggle_severity(DiagnosticSeverity::Hint),
+                    "g" => self.app.problems.toggle_grouping(),
+                    "r" => self.app.refresh_problems(),
+                    _ => {}
+                }
+                true
             }
-            EditorCommand::CloseSearch => {
-                self.app.close_input_bar();
+            _ => false,
+        }
+    }
+
+    /// Applies an editing key to the input bar's focused field: typing,
+    /// Backspace and Delete, moving with the arrows, Home and End (Ctrl by
+    /// word, Shift selecting), Ctrl+A to select all and Ctrl+C, Ctrl+X and
+    /// Ctrl+V for the clipboard. Returns `None` if the key doesn't edit
+    /// text, otherwise whether the text changed.
+    fn edit_focused_field(&mut self, key: &Key) -> Option<bool> {
+        let (ctrl, shift, alt) = (self.modifiers.control_key(), self.modifiers.shift_key(), self.modifiers.alt_key());
+        // The clipboard is read before the field is borrowed
+        let pasted = match key {
+            Key::Character(ch) if ctrl && !alt && ch.eq_ignore_ascii_case("v") => {
+                arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()).ok()
+            }
+            _ => None,
+        };
+        let mut copied = None;
+        let field = self.app.focused_field_mut()?;
+        let changed = match key {
+            Key::Named(NamedKey::Backspace) => field.backspace(),
+            Key::Named(NamedKey::Delete) => field.delete(),
+            Key::Named(NamedKey::ArrowLeft) if ctrl => {
+                field.move_word_left(shift);
                 false
             }
-            EditorCommand::GoToLine => {
-                self.app.open_goto_line();
+            Key::Named(NamedKey::ArrowLeft) => {
+                field.move_left(shift);
                 false
             }
-            EditorCommand::GotoDefinition => {
-                self.app.request_goto_definition();
+            Key::Named(NamedKey::ArrowRight) if ctrl => {
+                field.move_word_right(shift);
                 false
             }
-            EditorCommand::TriggerCompletion => {
-                self.app.trigger_completion();
+            Key::Named(NamedKey::ArrowRight) => {
+                field.move_right(shift);
                 false
             }
-            EditorCommand::RenameSymbol => {
-                self.app.open_rename();
+            Key::Named(NamedKey::Home) => {
+                field.move_home(shift);
                 false
             }
-            EditorCommand::TogglePerfMetrics => {
-                self.app.toggle_perf_metrics();
-                let state = if self.app.show_perf_metrics { "enabled" } else { "disabled" };
-                self.app.notifications.info(format!("Performance metrics {}", state));
+            Key::Named(NamedKey::End) => {
+                field.move_end(shift);
                 false
             }
+            Key::Named(NamedKey::Space) if !ctrl && !alt => field.insert(" "),
+            Key::Character(ch) if ctrl && !alt => match ch.to_lowercase().as_str() {
+                "a" => {
+                    field.select_all();
+                    false
+                }
+                "c" => {
+                    copied = field.selected_text().map(str::to_string);
+                    false
+                }
+                "x" => {
+                    copied = field.selected_text().map(str::to_string);
+                    copied.is_some() && field.backspace()
+                }
+                "v" => pasted.is_some_and(|text| field.paste(&text)),
+                _ => return None,
+            },
+            Key::Character(ch) if !ctrl && !alt => field.insert(ch),
+            _ => return None,
+        };
+        if let Some(text) = copied {
+            self.set_clipboard(&text);
         }
+        Some(changed)
     }
 
-    fn show_open_file_dialog(&mut self) {
-        if self.app.dialog_open {
+    fn execute_command(&mut self, command: EditorCommand, _event_loop: &ActiveEventLoop) -> bool {
+        command_registry().execute(self, &command)
+    }
+
+    /// Puts the selection on the primary selection, which the middle
+    /// button pastes on Linux, unless the mouse settings turn that off.
+    fn sync_primary_selection(&mut self) {
+        let Some(text) = self.app.primary_selection_text() else {
             return;
+        };
+        #[cfg(target_os = "linux")]
+        {
+            use arboard::{LinuxClipboardKind, SetExtLinux};
+            if let Ok(mut clipboard) = arboard::Clipboard::new() {
+                let _ = clipboard.set().clipboard(LinuxClipboardKind::Primary).text(text);
+            }
         }
-        self.app.dialog_open = true;
+        #[cfg(not(target_os = "linux"))]
+        let _ = text;
+    }
 
-        let dialog = rfd::FileDialog::new()
-            .set_title("Open File")
-            .pick_file();
+    /// Puts `text` on the system clipboard.
+    fn set_clipboard(&mut self, text: &str) {
+        if let Ok(mut clipboard) = arboard::Clipboard::new() {
+            if clipboard.set_text(text).is_err() {
+                self.app.notifications.error("Failed to copy to clipboard");
+            }
+        }
+    }
 
-        self.app.dialog_open = false;
+    fn show_open_file_dialog(&mut self) {
+        let Some(dialog) = self.run_dialog(Modal::Dialog, |dialogs| {
+            dialogs.pick_file(rfd::FileDialog::new().set_title("Open File"))
+        }) else {
+            return;
+        };
 
         match dialog {
             Some(path) => {
                 if let Err(e) = self.app.workspace.open_file(&path) {
                     log::error!("Failed to open file: {}", e);
-                } else {
-                    // Notify LSP about the newly opened file
-                    self.app.notify_lsp_file_opened();
                 }
                 self.update_window_title();
                 if let Some(window) = &self.window {
@@ -2462,16 +5912,11 @@ impl AppState {
     }
 
     fn show_save_as_dialog(&mut self) {
-        if self.app.dialog_open {
+        let Some(dialog) = self.run_dialog(Modal::Dialog, |dialogs| {
+            dialogs.save_file(rfd::FileDialog::new().set_title("Save As"))
+        }) else {
             return;
-        }
-        self.app.dialog_open = true;
-
-        let dialog = rfd::FileDialog::new()
-            .set_title("Save As")
-            .save_file();
-
-        self.app.dialog_open = false;
+        };
 
         match dialog {
             Some(path) => {
@@ -2479,12 +5924,15 @@ impl AppState {
                     .and_then(|n| n.to_str())
                     .unwrap_or("File")
                     .to_string();
+                if let Some(Err(e)) = self.app.workspace.active_editor().map(Editor::check_encodable) {
+                    self.app.notifications.warning(format!("{}; written as '?'", e));
+                }
                 if let Err(e) = self.app.workspace.save_active_as(&path) {
                     log::error!("Failed to save file: {}", e);
                     self.app.notifications.error(format!("Failed to save: {}", e));
                 } else {
-                    // Notify LSP about the saved file (and open it if new)
-                    self.app.notify_lsp_file_opened();
+                    // The server must know the new path before it is saved
+                    self.app.handle_workspace_events();
                     self.app.notify_lsp_file_saved();
                     self.app.notifications.success(format!("Saved: {}", filename));
                 }
@@ -2497,65 +5945,104 @@ impl AppState {
                 log::info!("Save dialog cancelled or unavailable (try: apt install zenity)");
             }
         }
-    }
+    }
+
+    /// Asks where to export the Problems panel's diagnostics and writes
+    /// them there.
+    fn show_export_problems_dialog(&mut self) {
+        let Some(dialog) = self.run_dialog(Modal::Dialog, |dialogs| {
+            let dialog = rfd::FileDialog::new()
+                .set_title("Export Diagnostics")
+                .set_file_name("diagnostics.txt")
+                .add_filter("Compiler-style text", &["txt"])
+                .add_filter("JSON", &["json"]);
+            dialogs.save_file(dialog)
+        }) else {
+            return;
+        };
+
+        let Some(path) = dialog else {
+            log::info!("Export dialog cancelled or unavailable (try: apt install zenity)");
+            return;
+        };
+        match self.app.export_problems(&path) {
+            Ok(count) => {
+                let plural = if count == 1 { "" } else { "s" };
+                self.app.notifications.success(format!("Exported {} diagnostic{} to {}", count, plural, path.display()));
+            }
+            Err(e) => self.app.notifications.error(format!("Failed to export diagnostics: {}", e)),
+        }
+    }
+
+    /// Asks whether to save the active buffer's changes before closing it.
+    /// Returns true if it may be closed: it was saved, the changes are to
+    /// be discarded, or there were none.
+    fn confirm_close_active(&mut self) -> bool {
+        let Some(editor) = self.app.workspace.active_editor() else {
+            return true;
+        };
+        if !editor.is_modified() {
+            return true;
+        }
+        let file_name = editor
+            .file_path()
+            .and_then(|p| p.file_name())
+            .and_then(|n| n.to_str())
+            .unwrap_or("Untitled")
+            .to_string();
+
+        // Show confirmation dialog with Save/Don't Save/Cancel options
+        let result = self.run_dialog(Modal::Confirmation, |dialogs| {
+            let dialog = rfd::MessageDialog::new()
+                .set_title("Unsaved Changes")
+                .set_description(format!(
+                    "Do you want to save the changes to \"{}\"?",
+                    file_name
+                ))
+                .set_buttons(rfd::MessageButtons::YesNoCancel);
+            dialogs.ask(dialog)
+        });
 
-    fn close_active_tab(&mut self) {
-        if let Some(editor) = self.app.workspace.active_editor() {
-            if editor.is_modified() {
-                let file_name = editor
-                    .file_path()
-                    .and_then(|p| p.file_name())
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("Untitled");
-
-                // Show confirmation dialog with Save/Don't Save/Cancel options
-                let result = rfd::MessageDialog::new()
-                    .set_title("Unsaved Changes")
-                    .set_description(&format!(
-                        "Do you want to save the changes to \"{}\"?",
-                        file_name
-                    ))
-                    .set_buttons(rfd::MessageButtons::YesNoCancel)
-                    .show();
-
-                match result {
-                    rfd::MessageDialogResult::Yes => {
-                        // Save before closing
-                        if let Err(e) = self.app.workspace.save_active() {
-                            if e.kind() == std::io::ErrorKind::Other {
-                                // No file path - trigger Save As
-                                self.show_save_as_dialog();
-                                return; // Don't close yet - SaveAs will handle it
-                            } else {
-                                log::error!("Failed to save: {}", e);
-                                return; // Save failed, don't close
-                            }
-                        }
-                        self.app.notify_lsp_file_saved();
+        match result {
+            Some(rfd::MessageDialogResult::Yes) => {
+                let Some(id) = self.app.workspace.active_buffer_id() else {
+                    return true;
+                };
+                match self.app.save_in_background(id) {
+                    // No file path - trigger Save As
+                    Err(e) if e.kind() == std::io::ErrorKind::Other => {
+                        self.show_save_as_dialog();
+                        false // Don't close yet - SaveAs will handle it
                     }
-                    rfd::MessageDialogResult::No => {
-                        // Don't save, proceed with closing
+                    Err(e) => {
+                        log::error!("Failed to save: {}", e);
+                        self.app.notifications.error(format!("Failed to save: {}", e));
+                        false
                     }
-                    _ => {
-                        // Cancel - don't close
-                        return;
+                    Ok(()) => {
+                        // Closing drops the buffer, so the write must finish
+                        // first; a failed one was reported and keeps it open
+                        self.app.finish_pending_saves();
+                        !self.app.workspace.get_buffer(id).is_some_and(Editor::is_modified)
                     }
                 }
             }
+            // Don't save, proceed with closing
+            Some(rfd::MessageDialogResult::No) => true,
+            // Cancel - don't close
+            _ => false,
         }
+    }
 
-        // Notify LSP about the document being closed before dropping it
-        if let Some(path) = self
-            .app
-            .workspace
-            .active_editor()
-            .and_then(|e| e.file_path().map(|p| p.to_path_buf()))
-        {
-            self.app.flush_pending_lsp_changes(true);
-            self.app.notify_lsp_file_closed(&path);
+    fn close_active_tab(&mut self) {
+        if !self.confirm_close_active() {
+            return;
         }
 
+        // Send edits the server hasn't seen before the document is closed
+        self.app.flush_pending_lsp_changes(true);
         self.app.workspace.close_active_buffer();
+        self.app.handle_workspace_events();
 
         // If no buffers left, create a new one
         if self.app.workspace.tab_count() == 0 {
@@ -2565,19 +6052,51 @@ impl AppState {
         self.update_window_title();
     }
 
-    /// Flushes pending LSP changes and closes all open LSP documents.
-    fn shutdown_lsp(&mut self) {
+    /// Closes several tabs at once. Saved buffers close right away; for
+    /// each modified one, unless only saved tabs are closed, the user is
+    /// asked whether to save it, and cancelling stops there.
+    fn close_tabs(&mut self, which: CloseTabs) {
+        // Send edits the server hasn't seen before the documents are closed
         self.app.flush_pending_lsp_changes(true);
-        let open_paths: Vec<PathBuf> = self
-            .app
-            .workspace
-            .editors()
-            .filter_map(|(_, editor)| editor.file_path().map(|p| p.to_path_buf()))
-            .collect();
+        let active = self.app.workspace.active_buffer_id();
+        let modified = match (which, active) {
+            (CloseTabs::All, _) | (CloseTabs::Others, None) => self.app.workspace.close_saved(),
+            (CloseTabs::Others, Some(keep)) => self.app.workspace.close_others(keep),
+            (CloseTabs::Saved, _) => {
+                self.app.workspace.close_saved();
+                Vec::new()
+            }
+        };
+        self.app.handle_workspace_events();
+
+        for id in modified {
+            // Show the buffer being asked about
+            self.app.workspace.set_active(id);
+            self.update_window_title();
+            if !self.confirm_close_active() {
+                break;
+            }
+            self.app.flush_pending_lsp_changes(true);
+            self.app.workspace.close_buffer(id);
+            self.app.handle_workspace_events();
+        }
+        if which == CloseTabs::Others {
+            if let Some(keep) = active {
+                self.app.workspace.set_active(keep);
+            }
+        }
 
-        for path in open_paths {
-            self.app.notify_lsp_file_closed(&path);
+        // If no buffers left, create a new one
+        if self.app.workspace.tab_count() == 0 {
+            self.app.workspace.new_buffer();
         }
+        self.update_window_title();
+    }
+
+    /// Flushes pending LSP changes and closes all open LSP documents.
+    fn shutdown_lsp(&mut self) {
+        self.app.flush_pending_lsp_changes(true);
+        self.app.handle_workspace_events();
         self.app.lsp_manager.shutdown_all();
     }
 
@@ -2591,14 +6110,16 @@ impl AppState {
         if let Some(gpu) = &self.gpu {
             if let Some(window) = &self.window {
                 let size = window.inner_size();
+                self.app.viewport_width = size.width as f32;
                 // Account for tab bar, search bar (if active), and status bar
-                let mut content_height = size.height as f32 - TAB_BAR_HEIGHT - STATUS_BAR_HEIGHT;
+                let mut content_height = size.height as f32 - self.app.tab_bar_height() - self.app.status_bar_height();
                 if self.app.input_mode != InputMode::Normal {
-                    content_height -= SEARCH_BAR_HEIGHT;
+                    content_height -= self.app.chrome.search_bar_height();
                 }
+                content_height -= self.app.disk_banner_height();
                 let visible_lines = (content_height / gpu.line_height()) as usize;
-                let visible_cols =
-                    ((size.width as f32 - self.app.line_number_margin) / gpu.char_width()) as usize;
+                let (_, text_width) = self.app.text_area(gpu.char_width(), size.width as f32);
+                let visible_cols = (text_width / gpu.char_width()) as usize;
 
                 if let Some(editor) = self.app.workspace.active_editor_mut() {
                     editor.set_visible_lines(visible_lines.max(1));
@@ -2611,12 +6132,1062 @@ impl AppState {
     }
 }
 
+impl CommandContext for AppState {
+    fn active_editor(&mut self) -> Option<&mut Editor> {
+        self.app.workspace.active_editor_mut()
+    }
+
+    fn cursor_activity(&mut self) {
+        self.app.reset_cursor_blink();
+    }
+
+    fn buffer_modified(&mut self) {
+        self.app.notify_lsp_document_change();
+        self.update_window_title();
+    }
+
+    fn buffer_loading(&mut self) {
+        self.app.notifications.info("The file is still loading; it can be edited once it has");
+    }
+}
+
+/// A go-to-line target: 1-based line and optional column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct GotoTarget {
+    line: usize,
+    /// Column, if one was given.
+    col: Option<usize>,
+}
+
+/// Parses go to line input: `42`, `42:10`, `:15` for a column on
+/// `current_line`, or `-1` counting back from the last of `line_count`
+/// lines. Returns what is wrong with malformed or out of range input.
+fn parse_goto_target(text: &str, current_line: usize, line_count: usize) -> Result<GotoTarget, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Enter a line, line:col or :col".to_string());
+    }
+    let (line_text, col_text) = match text.split_once(':') {
+        Some((line, col)) => (line, Some(col)),
+        None => (text, None),
+    };
+    let out_of_range = || format!("Line {} is out of range (1-{})", line_text, line_count);
+    let line = if line_text.is_empty() {
+        current_line
+    } else if let Some(back) = line_text.strip_prefix('-') {
+        let back: usize = back.parse().map_err(|_| format!("Not a line number: {}", line_text))?;
+        if back == 0 || back > line_count {
+            return Err(out_of_range());
+        }
+        line_count + 1 - back
+    } else {
+        let line: usize = line_text.parse().map_err(|_| format!("Not a line number: {}", line_text))?;
+        if line == 0 || line > line_count {
+            return Err(out_of_range());
+        }
+        line
+    };
+    let col = match col_text {
+        None => None,
+        Some("") => return Err("Enter a column after ':'".to_string()),
+        Some(col_text) => match col_text.parse::<usize>() {
+            Ok(col) if col > 0 => Some(col),
+            _ => return Err(format!("Not a column: {}", col_text)),
+        },
+    };
+    Ok(GotoTarget { line, col })
+}
+
+/// Describes what cleaning up a buffer for saving changed, e.g. "Trimmed
+/// 12 lines", or `None` if nothing did.
+fn describe_save_cleanup(cleanup: SaveCleanup) -> Option<String> {
+    let trimmed = match cleanup.trimmed_lines {
+        0 => None,
+        1 => Some("Trimmed 1 line".to_string()),
+        n => Some(format!("Trimmed {} lines", n)),
+    };
+    match (trimmed, cleanup.added_final_newline) {
+        (None, false) => None,
+        (None, true) => Some("Added final newline".to_string()),
+        (Some(trimmed), false) => Some(trimmed),
+        (Some(trimmed), true) => Some(format!("{}, added final newline", trimmed)),
+    }
+}
+
+/// Converts column `units` of `line`, counted in `encoding`, to a character
+/// column of `buffer`.
+fn decode_lsp_col(encoding: PositionEncoding, buffer: &TextBuffer, line: usize, units: usize) -> usize {
+    if encoding == PositionEncoding::Utf32 {
+        return units;
+    }
+    encoding.decode_col(&buffer.line(line).unwrap_or_default(), units as u32)
+}
+
+/// Returns the registry of every editor command.
+fn command_registry() -> &'static CommandRegistry<AppState> {
+    static REGISTRY: OnceLock<CommandRegistry<AppState>> = OnceLock::new();
+    REGISTRY.get_or_init(build_command_registry)
+}
+
+/// Registers every editor command with its handler and metadata.
+fn build_command_registry() -> CommandRegistry<AppState> {
+    use CommandCategory as Cat;
+    use EditorCommand as Cmd;
+
+    let mut r: CommandRegistry<AppState> = CommandRegistry::new();
+
+    // File operations
+    r.register(
+        Cmd::Save,
+        CommandInfo::new("Save", Cat::File).editor().recordable(),
+        Handler::App(|s, _| {
+            // The write runs in the background; poll_saves reports the result
+            if let Some(id) = s.app.workspace.active_buffer_id() {
+                if let Err(e) = s.app.save_in_background(id) {
+                    if e.kind() == std::io::ErrorKind::Other {
+                        // No file path - trigger Save As
+                        s.show_save_as_dialog();
+                    } else {
+                        log::error!("Failed to save: {}", e);
+                        s.app.notifications.error(format!("Failed to save: {}", e));
+                    }
+                }
+            }
+            s.update_window_title();
+            false
+        }),
+    );
+    r.register(
+        Cmd::SaveAs,
+        CommandInfo::new("Save As", Cat::File).editor(),
+        Handler::App(|s, _| {
+            s.app.flush_pending_lsp_changes(true);
+            s.show_save_as_dialog();
+            false
+        }),
+    );
+    r.register(
+        Cmd::OpenFile,
+        CommandInfo::new("Open File", Cat::File),
+        Handler::App(|s, _| {
+            s.show_open_file_dialog();
+            false
+        }),
+    );
+    r.register(
+        Cmd::ReopenRecent,
+        CommandInfo::new("Open Recent File", Cat::File),
+        Handler::App(|s, _| {
+            s.app.open_recent_files();
+            false
+        }),
+    );
+    r.register(
+        Cmd::ReopenWithEncoding,
+        CommandInfo::new("Reopen with Encoding...", Cat::File).editor(),
+        Handler::App(|s, _| {
+            s.app.open_encoding_picker();
+            false
+        }),
+    );
+    r.register(
+        Cmd::NewFile,
+        CommandInfo::new("New File", Cat::File),
+        Handler::App(|s, _| {
+            s.app.workspace.new_buffer();
+            s.update_window_title();
+            false
+        }),
+    );
+    r.register(
+        Cmd::CloseTab,
+        CommandInfo::new("Close Tab", Cat::File),
+        Handler::App(|s, _| {
+            s.close_active_tab();
+            false
+        }),
+    );
+    r.register(
+        Cmd::CloseAllTabs,
+        CommandInfo::new("Close All Tabs", Cat::File),
+        Handler::App(|s, _| {
+            s.close_tabs(CloseTabs::All);
+            false
+        }),
+    );
+    r.register(
+        Cmd::CloseOtherTabs,
+        CommandInfo::new("Close Other Tabs", Cat::File),
+        Handler::App(|s, _| {
+            s.close_tabs(CloseTabs::Others);
+            false
+        }),
+    );
+    r.register(
+        Cmd::CloseSavedTabs,
+        CommandInfo::new("Close Saved Tabs", Cat::File),
+        Handler::App(|s, _| {
+            s.close_tabs(CloseTabs::Saved);
+            false
+        }),
+    );
+    r.register(
+        Cmd::Quit,
+        CommandInfo::new("Quit", Cat::File),
+        Handler::App(|s, _| {
+            // Let running saves finish so their buffers count as saved
+            s.app.finish_pending_saves();
+            s.app.handle_workspace_events();
+            if s.app.workspace.has_unsaved_changes() {
+                // Show confirmation dialog
+                let result = s.run_dialog(Modal::Confirmation, |dialogs| {
+                    let dialog = rfd::MessageDialog::new()
+                        .set_title("Unsaved Changes")
+                        .set_description("You have unsaved changes. Are you sure you want to quit?")
+                        .set_buttons(rfd::MessageButtons::YesNo);
+                    dialogs.ask(dialog)
+                });
+
+                if result != Some(rfd::MessageDialogResult::Yes) {
+                    return false; // User cancelled, don't quit
+                }
+            }
+            s.app.save_session();
+            s.save_window_geometry();
+            s.shutdown_lsp();
+            true
+        }),
+    );
+
+    // Tab operations
+    r.register(
+        Cmd::NextTab,
+        CommandInfo::new("Next Tab", Cat::Tabs),
+        Handler::App(|s, _| {
+            s.app.flush_pending_lsp_changes(true);
+            if s.app.tab_switcher_popup {
+                s.app.tab_switcher.advance(s.app.workspace.mru_order(), true);
+            } else {
+                s.app.workspace.next_tab();
+                s.update_window_title();
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::PrevTab,
+        CommandInfo::new("Previous Tab", Cat::Tabs),
+        Handler::App(|s, _| {
+            s.app.flush_pending_lsp_changes(true);
+            if s.app.tab_switcher_popup {
+                s.app.tab_switcher.advance(s.app.workspace.mru_order(), false);
+            } else {
+                s.app.workspace.prev_tab();
+                s.update_window_title();
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::SwitchToTab(0),
+        CommandInfo::new("Switch to Tab", Cat::Tabs).recordable(),
+        Handler::App(|s, command| {
+            if let Cmd::SwitchToTab(index) = *command {
+                s.app.flush_pending_lsp_changes(true);
+                s.app.workspace.switch_to_tab(index);
+                s.update_window_title();
+            }
+            false
+        }),
+    );
+
+    // Text input and deletion
+    r.register(
+        Cmd::InsertChar(' '),
+        CommandInfo::edit("Type Character", Cat::Edit),
+        Handler::Editor(|editor, command| {
+            let Cmd::InsertChar(ch) = *command else {
+                return;
+            };
+            if ch == '\t' && editor.next_tab_stop() {
+                // Tab jumps between the tab stops of a snippet being filled in
+            } else if editor.is_block_selection_mode() {
+                // Type at every line of the block
+                editor.insert_text_at_block(ch.encode_utf8(&mut [0; 4]));
+            } else if ch == '\t' && editor.has_multiline_selection() {
+                // Tab with a multi-line selection indents it instead of replacing it
+                editor.indent_selection();
+            } else {
+                // Brackets and quotes are auto-closed and typed over
+                editor.insert_char_with_auto_bracket(ch);
+            }
+        }),
+    )
+    .then(|s, command| {
+        if let Cmd::InsertChar(ch) = command {
+            s.app.typed_char(*ch);
+        }
+    });
+    r.register(
+        Cmd::InsertNewline,
+        CommandInfo::edit("New Line", Cat::Edit),
+        Handler::Editor(|editor, _| {
+            // Break every line of a block selection
+            editor.block_to_cursors();
+            editor.insert_newline();
+        }),
+    );
+    r.register(
+        Cmd::DeleteBackward,
+        CommandInfo::edit("Delete Backward", Cat::Edit),
+        Handler::Editor(|editor, _| {
+            // Delete the column on every line of a block selection
+            editor.block_to_cursors();
+            editor.delete_backward();
+        }),
+    )
+    .then(|s, _| {
+        if s.app.completion_visible {
+            s.app.refilter_completions();
+        }
+    });
+    r.register(
+        Cmd::DeleteForward,
+        CommandInfo::edit("Delete Forward", Cat::Edit),
+        Handler::Editor(|editor, _| {
+            // Delete the column on every line of a block selection
+            editor.block_to_cursors();
+            editor.delete_forward();
+        }),
+    );
+
+    // Cursor movement
+    let motion = CommandInfo::motion;
+    // Going to a line's start or end twice stays there
+    let jump = |name, category| CommandInfo::motion(name, category).once();
+    r.register(Cmd::MoveLeft, motion("Cursor Left", Cat::Cursor), Handler::Editor(|e, _| e.move_left(false)));
+    r.register(Cmd::MoveRight, motion("Cursor Right", Cat::Cursor), Handler::Editor(|e, _| e.move_right(false)));
+    r.register(Cmd::MoveUp, motion("Cursor Up", Cat::Cursor), Handler::Editor(|e, _| e.move_up(false)));
+    r.register(Cmd::MoveDown, motion("Cursor Down", Cat::Cursor), Handler::Editor(|e, _| e.move_down(false)));
+    r.register(Cmd::MoveWordLeft, motion("Word Left", Cat::Cursor), Handler::Editor(|e, _| e.move_word_left(false)));
+    r.register(Cmd::MoveWordRight, motion("Word Right", Cat::Cursor), Handler::Editor(|e, _| e.move_word_right(false)));
+    r.register(
+        Cmd::MoveSubWordLeft,
+        motion("Sub-word Left", Cat::Cursor),
+        Handler::Editor(|e, _| e.move_subword_left(false)),
+    );
+    r.register(
+        Cmd::MoveSubWordRight,
+        motion("Sub-word Right", Cat::Cursor),
+        Handler::Editor(|e, _| e.move_subword_right(false)),
+    );
+    r.register(Cmd::MoveToLineStart, jump("Line Start", Cat::Cursor), Handler::Editor(|e, _| e.move_to_line_start(false)));
+    r.register(
+        Cmd::MoveToLineStartSmart,
+        motion("Smart Home", Cat::Cursor),
+        Handler::Editor(|e, _| e.move_to_line_start_smart(false)),
+    );
+    r.register(Cmd::MoveToLineEnd, jump("Line End", Cat::Cursor), Handler::Editor(|e, _| e.move_to_line_end(false)));
+    r.register(Cmd::MovePageUp, motion("Page Up", Cat::Cursor), Handler::Editor(|e, _| e.move_page_up(false)));
+    r.register(Cmd::MovePageDown, motion("Page Down", Cat::Cursor), Handler::Editor(|e, _| e.move_page_down(false)));
+    r.register(
+        Cmd::MoveToBufferStart,
+        jump("Buffer Start", Cat::Cursor),
+        Handler::Editor(|e, _| e.move_to_buffer_start(false)),
+    );
+    r.register(Cmd::MoveToBufferEnd, jump("Buffer End", Cat::Cursor), Handler::Editor(|e, _| e.move_to_buffer_end(false)));
+
+    // Selection
+    r.register(Cmd::SelectLeft, motion("Select Left", Cat::Selection), Handler::Editor(|e, _| e.move_left(true)));
+    r.register(Cmd::SelectRight, motion("Select Right", Cat::Selection), Handler::Editor(|e, _| e.move_right(true)));
+    r.register(Cmd::SelectUp, motion("Select Up", Cat::Selection), Handler::Editor(|e, _| e.move_up(true)));
+    r.register(Cmd::SelectDown, motion("Select Down", Cat::Selection), Handler::Editor(|e, _| e.move_down(true)));
+    r.register(
+        Cmd::SelectWordLeft,
+        motion("Select Word Left", Cat::Selection),
+        Handler::Editor(|e, _| e.move_word_left(true)),
+    );
+    r.register(
+        Cmd::SelectWordRight,
+        motion("Select Word Right", Cat::Selection),
+        Handler::Editor(|e, _| e.move_word_right(true)),
+    );
+    r.register(
+        Cmd::SelectSubWordLeft,
+        motion("Select Sub-word Left", Cat::Selection),
+        Handler::Editor(|e, _| e.move_subword_left(true)),
+    );
+    r.register(
+        Cmd::SelectSubWordRight,
+        motion("Select Sub-word Right", Cat::Selection),
+        Handler::Editor(|e, _| e.move_subword_right(true)),
+    );
+    r.register(
+        Cmd::SelectToLineStart,
+        jump("Select to Line Start", Cat::Selection),
+        Handler::Editor(|e, _| e.move_to_line_start(true)),
+    );
+    r.register(
+        Cmd::SelectToLineStartSmart,
+        motion("Select to Smart Home", Cat::Selection),
+        Handler::Editor(|e, _| e.move_to_line_start_smart(true)),
+    );
+    r.register(
+        Cmd::SelectToLineEnd,
+        jump("Select to Line End", Cat::Selection),
+        Handler::Editor(|e, _| e.move_to_line_end(true)),
+    );
+    r.register(Cmd::SelectPageUp, motion("Select Page Up", Cat::Selection), Handler::Editor(|e, _| e.move_page_up(true)));
+    r.register(
+        Cmd::SelectPageDown,
+        motion("Select Page Down", Cat::Selection),
+        Handler::Editor(|e, _| e.move_page_down(true)),
+    );
+    r.register(
+        Cmd::SelectToBufferStart,
+        jump("Select to Buffer Start", Cat::Selection),
+        Handler::Editor(|e, _| e.move_to_buffer_start(true)),
+    );
+    r.register(
+        Cmd::SelectToBufferEnd,
+        jump("Select to Buffer End", Cat::Selection),
+        Handler::Editor(|e, _| e.move_to_buffer_end(true)),
+    );
+    r.register(
+        Cmd::ExpandSelection,
+        motion("Expand Selection", Cat::Selection),
+        Handler::Editor(|e, _| {
+            e.expand_selection();
+        }),
+    );
+    r.register(
+        Cmd::ShrinkSelection,
+        motion("Shrink Selection", Cat::Selection),
+        Handler::Editor(|e, _| {
+            e.shrink_selection();
+        }),
+    );
+    r.register(Cmd::SelectAll, jump("Select All", Cat::Selection), Handler::Editor(|e, _| e.select_all()));
+    r.register(
+        Cmd::ToggleBlockSelection,
+        motion("Toggle Block Selection", Cat::Selection),
+        Handler::Editor(|e, _| e.toggle_block_selection()),
+    );
+    r.register(
+        Cmd::BlockSelectUp,
+        motion("Block Select Up", Cat::Selection),
+        Handler::Editor(|e, _| e.move_block_corner(-1, 0)),
+    );
+    r.register(
+        Cmd::BlockSelectDown,
+        motion("Block Select Down", Cat::Selection),
+        Handler::Editor(|e, _| e.move_block_corner(1, 0)),
+    );
+    // From the palette these start a block too; Alt+Shift+Left/Right run
+    // them only while one is active, see input::block_mode_command
+    r.register(
+        Cmd::BlockSelectLeft,
+        motion("Block Select Left", Cat::Selection),
+        Handler::Editor(|e, _| e.move_block_corner(0, -1)),
+    );
+    r.register(
+        Cmd::BlockSelectRight,
+        motion("Block Select Right", Cat::Selection),
+        Handler::Editor(|e, _| e.move_block_corner(0, 1)),
+    );
+
+    // Line operations
+    r.register(Cmd::DuplicateLine, CommandInfo::edit("Duplicate Line", Cat::Edit), Handler::Editor(|e, _| e.duplicate_line()));
+    r.register(Cmd::MoveLineUp, CommandInfo::edit("Move Line Up", Cat::Edit), Handler::Editor(|e, _| e.move_line_up()));
+    r.register(Cmd::MoveLineDown, CommandInfo::edit("Move Line Down", Cat::Edit), Handler::Editor(|e, _| e.move_line_down()));
+    r.register(Cmd::DeleteLine, CommandInfo::edit("Delete Line", Cat::Edit), Handler::Editor(|e, _| e.delete_line()));
+    r.register(
+        Cmd::InsertLineBelow,
+        CommandInfo::edit("Insert Line Below", Cat::Edit),
+        Handler::Editor(|e, _| e.insert_line_below()),
+    );
+    r.register(
+        Cmd::InsertLineAbove,
+        CommandInfo::edit("Insert Line Above", Cat::Edit),
+        Handler::Editor(|e, _| e.insert_line_above()),
+    );
+
+    // Multi-cursor
+    r.register(
+        Cmd::AddCursorAbove,
+        motion("Add Cursor Above", Cat::MultiCursor),
+        Handler::Editor(|e, _| e.add_cursor_above()),
+    );
+    r.register(
+        Cmd::AddCursorBelow,
+        motion("Add Cursor Below", Cat::MultiCursor),
+        Handler::Editor(|e, _| e.add_cursor_below()),
+    );
+    r.register(
+        Cmd::SelectNextOccurrence,
+        motion("Select Next Occurrence", Cat::MultiCursor),
+        Handler::Editor(|e, _| {
+            e.select_next_occurrence();
+        }),
+    );
+    r.register(
+        Cmd::SelectAllOccurrences,
+        jump("Select All Occurrences", Cat::MultiCursor),
+        Handler::App(|s, _| {
+            if let Some(editor) = s.app.workspace.active_editor_mut() {
+                let found = editor.select_all_occurrences();
+                if found > MAX_OCCURRENCE_CURSORS {
+                    s.app.notifications.warning(format!(
+                        "Selected the first {} of {} occurrences",
+                        MAX_OCCURRENCE_CURSORS, found
+                    ));
+                }
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::CollapseCursors,
+        jump("Collapse Cursors", Cat::MultiCursor),
+        Handler::Editor(|e, _| {
+            e.collapse_cursors();
+            // Also exit block selection mode
+            e.exit_block_selection();
+        }),
+    );
+
+    // Undo/Redo
+    r.register(Cmd::Undo, CommandInfo::edit("Undo", Cat::Edit), Handler::Editor(|e, _| e.undo()));
+    r.register(Cmd::Redo, CommandInfo::edit("Redo", Cat::Edit), Handler::Editor(|e, _| e.redo()));
+
+    // Clipboard
+    r.register(
+        Cmd::Copy,
+        CommandInfo::new("Copy", Cat::Clipboard).editor().recordable(),
+        Handler::App(|s, _| {
+            if let Some(text) = s.app.copy_selections(false) {
+                s.set_clipboard(&text);
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::CopyWithLineNumbers,
+        CommandInfo::new("Copy with Line Numbers", Cat::Clipboard).editor(),
+        Handler::App(|s, _| {
+            if let Some(text) = s.app.copy_for_sharing(false) {
+                s.set_clipboard(&text);
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::CopyAsMarkdownCodeBlock,
+        CommandInfo::new("Copy as Markdown Code Block", Cat::Clipboard).editor(),
+        Handler::App(|s, _| {
+            if let Some(text) = s.app.copy_for_sharing(true) {
+                s.set_clipboard(&text);
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::Cut,
+        CommandInfo::edit("Cut", Cat::Clipboard),
+        Handler::App(|s, _| {
+            if let Some(text) = s.app.copy_selections(true) {
+                s.set_clipboard(&text);
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::Paste,
+        CommandInfo::edit("Paste", Cat::Clipboard),
+        Handler::App(|s, _| {
+            if let Ok(mut clipboard) = arboard::Clipboard::new() {
+                if let Ok(text) = clipboard.get_text() {
+                    s.app.paste_text(&text);
+                }
+            }
+            false
+        }),
+    );
+
+    // Code editing
+    r.register(Cmd::Indent, CommandInfo::edit("Indent", Cat::Edit), Handler::Editor(|e, _| e.indent_selection()));
+    r.register(
+        Cmd::Dedent,
+        CommandInfo::edit("Dedent", Cat::Edit),
+        Handler::Editor(|e, _| {
+            // Shift+Tab goes back a tab stop in a snippet being filled in
+            if !e.prev_tab_stop() {
+                e.dedent_selection();
+            }
+        }),
+    );
+    r.register(
+        Cmd::ToggleComment,
+        CommandInfo::edit("Toggle Comment", Cat::Edit),
+        Handler::Editor(|e, _| e.toggle_comment()),
+    );
+    r.register(
+        Cmd::FilterSelection,
+        CommandInfo::new("Filter Selection", Cat::Edit).editor(),
+        Handler::App(|s, _| {
+            if !s.app.open_filter_command() {
+                s.app.notifications.info("Select text to filter");
+            }
+            false
+        }),
+    );
+    let bidi: fn(&mut AppState, &EditorCommand) -> bool = |s, command| {
+        if let Some(editor) = s.app.workspace.active_editor_mut() {
+            let count = if *command == Cmd::StripBidiControls {
+                editor.strip_bidi_controls()
+            } else {
+                editor.escape_bidi_controls()
+            };
+            if count == 0 {
+                s.app.notifications.info("No bidi control characters");
+            }
+        }
+        false
+    };
+    r.register(Cmd::StripBidiControls, CommandInfo::edit("Strip Bidi Controls", Cat::Edit).once(), Handler::App(bidi));
+    r.register(Cmd::EscapeBidiControls, CommandInfo::edit("Escape Bidi Controls", Cat::Edit).once(), Handler::App(bidi));
+
+    // Scrolling
+    r.register(
+        Cmd::ScrollUp(0.0),
+        CommandInfo::new("Scroll Up", Cat::View).editor().repeatable(),
+        Handler::App(|s, command| {
+            if let Cmd::ScrollUp(lines) = *command {
+                let start = Instant::now();
+                if let Some(editor) = s.app.workspace.active_editor_mut() {
+                    let current = editor.scroll_offset();
+                    editor.set_scroll_offset(current.saturating_sub(lines as usize));
+                }
+                s.app.perf_metrics.scroll_perf.record_scroll(start.elapsed(), lines as u32);
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::ScrollDown(0.0),
+        CommandInfo::new("Scroll Down", Cat::View).editor().repeatable(),
+        Handler::App(|s, command| {
+            if let Cmd::ScrollDown(lines) = *command {
+                let start = Instant::now();
+                if let Some(editor) = s.app.workspace.active_editor_mut() {
+                    let current = editor.scroll_offset();
+                    editor.set_scroll_offset(current + lines as usize);
+                }
+                s.app.perf_metrics.scroll_perf.record_scroll(start.elapsed(), lines as u32);
+            }
+            false
+        }),
+    );
+    // Wrapped lines fit the view, so there is nothing to scroll sideways
+    r.register(
+        Cmd::ScrollLeft(0.0),
+        CommandInfo::new("Scroll Left", Cat::View).editor().repeatable(),
+        Handler::Editor(|editor, command| {
+            if let Cmd::ScrollLeft(columns) = *command {
+                if !editor.settings().word_wrap {
+                    editor.set_horizontal_scroll(editor.horizontal_scroll().saturating_sub(columns as usize));
+                }
+            }
+        }),
+    );
+    r.register(
+        Cmd::ScrollRight(0.0),
+        CommandInfo::new("Scroll Right", Cat::View).editor().repeatable(),
+        Handler::Editor(|editor, command| {
+            if let Cmd::ScrollRight(columns) = *command {
+                if !editor.settings().word_wrap {
+                    editor.set_horizontal_scroll(editor.horizontal_scroll() + columns as usize);
+                }
+            }
+        }),
+    );
+
+    // Search & Replace
+    r.register(
+        Cmd::OpenSearch,
+        CommandInfo::new("Find", Cat::Search).editor(),
+        Handler::App(|s, _| {
+            s.app.open_search();
+            false
+        }),
+    );
+    r.register(
+        Cmd::OpenReplace,
+        CommandInfo::new("Replace", Cat::Search).editor(),
+        Handler::App(|s, _| {
+            s.app.open_replace();
+            false
+        }),
+    );
+    r.register(
+        Cmd::FindNext,
+        motion("Find Next", Cat::Search),
+        Handler::Editor(|e, _| {
+            e.find_next();
+        }),
+    );
+    r.register(
+        Cmd::FindPrev,
+        motion("Find Previous", Cat::Search),
+        Handler::Editor(|e, _| {
+            e.find_prev();
+        }),
+    );
+    r.register(
+        Cmd::CloseSearch,
+        CommandInfo::new("Close Search", Cat::Search),
+        Handler::App(|s, _| {
+            s.app.close_input_bar();
+            false
+        }),
+    );
+    r.register(
+        Cmd::OpenProjectSearch,
+        CommandInfo::new("Find in Files", Cat::Search),
+        Handler::App(|s, _| {
+            s.app.open_project_search();
+            false
+        }),
+    );
+
+    // Navigation
+    r.register(
+        Cmd::GoToLine,
+        CommandInfo::new("Go to Line", Cat::Navigation).editor(),
+        Handler::App(|s, _| {
+            s.app.open_goto_line();
+            false
+        }),
+    );
+    r.register(
+        Cmd::JumpToMatchingBracket,
+        motion("Go to Matching Bracket", Cat::Navigation),
+        Handler::Editor(|e, _| {
+            e.goto_matching_bracket(false);
+        }),
+    );
+    r.register(
+        Cmd::SelectToMatchingBracket,
+        motion("Select to Matching Bracket", Cat::Selection),
+        Handler::Editor(|e, _| {
+            e.goto_matching_bracket(true);
+        }),
+    );
+
+    // LSP commands
+    r.register(
+        Cmd::GotoDefinition,
+        CommandInfo::new("Go to Definition", Cat::Language).editor().cursor(),
+        Handler::App(|s, _| {
+            s.app.request_goto_definition();
+            false
+        }),
+    );
+    r.register(
+        Cmd::TriggerCompletion,
+        CommandInfo::new("Trigger Completion", Cat::Language).editor(),
+        Handler::App(|s, _| {
+            s.app.trigger_completion();
+            false
+        }),
+    );
+    r.register(
+        Cmd::RenameSymbol,
+        CommandInfo::new("Rename Symbol", Cat::Language).editor(),
+        Handler::App(|s, _| {
+            s.app.open_rename();
+            false
+        }),
+    );
+    r.register(
+        Cmd::ShowCodeActions,
+        CommandInfo::new("Show Code Actions", Cat::Language).editor(),
+        Handler::App(|s, _| {
+            s.app.request_code_actions();
+            false
+        }),
+    );
+    r.register(
+        Cmd::TrustWorkspace,
+        CommandInfo::new("Trust Workspace", Cat::Language),
+        Handler::App(|s, _| {
+            s.app.trust_workspace();
+            false
+        }),
+    );
+    r.register(
+        Cmd::EnableLspForFile,
+        CommandInfo::new("Enable Language Server for This File", Cat::Language).editor(),
+        Handler::App(|s, _| {
+            s.app.enable_lsp_for_active_file();
+            false
+        }),
+    );
+
+    // Diagnostics
+    r.register(
+        Cmd::CycleDiagnosticFilter,
+        CommandInfo::new("Cycle Diagnostic Filter", Cat::Diagnostics),
+        Handler::App(|s, _| {
+            let label = match s.app.cycle_diagnostic_filter() {
+                DiagnosticSeverity::Hint => "all",
+                DiagnosticSeverity::Information => "info and above",
+                DiagnosticSeverity::Warning => "warnings and above",
+                DiagnosticSeverity::Error => "errors only",
+            };
+            s.app.notifications.info(format!("Showing diagnostics: {}", label));
+            false
+        }),
+    );
+    r.register(
+        Cmd::ShowLineDiagnostics,
+        CommandInfo::new("Show Line Diagnostics", Cat::Diagnostics).editor(),
+        Handler::App(|s, _| {
+            let line = s.app.workspace.active_editor().map(|e| e.cursor_position().line);
+            if let Some(line) = line {
+                if !s.app.show_line_diagnostics(line) {
+                    s.app.notifications.info("No diagnostics on this line");
+                }
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::RunLinter,
+        CommandInfo::new("Run Linter", Cat::Diagnostics).editor(),
+        Handler::App(|s, _| {
+            // Linters read the file from disk, so it needs a path
+            let path = s.app.workspace.active_editor().and_then(|e| e.file_path().map(Path::to_path_buf));
+            match path {
+                Some(path) => {
+                    s.app.prepare_run();
+                    // Saving may already have started the linter for this file
+                    let started = s.app.lint_runner.is_running()
+                        && s.app.lint_target.as_ref().is_some_and(|(target, _)| *target == path);
+                    if started || s.app.run_linter(&path) {
+                        s.app.show_lint_panel = true;
+                    } else {
+                        s.app.notifications.info("No linter configured for this file type");
+                    }
+                }
+                None => s.app.notifications.info("Save the file before linting"),
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::ToggleLintPanel,
+        CommandInfo::new("Toggle Lint Panel", Cat::Diagnostics),
+        Handler::App(|s, _| {
+            s.app.show_lint_panel = !s.app.show_lint_panel;
+            false
+        }),
+    );
+    r.register(
+        Cmd::ShowProblems,
+        CommandInfo::new("Show Problems", Cat::Diagnostics),
+        Handler::App(|s, _| {
+            s.app.open_problems();
+            false
+        }),
+    );
+    r.register(
+        Cmd::ExportDiagnostics,
+        CommandInfo::new("Export Diagnostics...", Cat::Diagnostics),
+        Handler::App(|s, _| {
+            s.show_export_problems_dialog();
+            false
+        }),
+    );
+    r.register(
+        Cmd::ToggleDiffIgnoreWhitespace,
+        CommandInfo::new("Toggle Whitespace Changes", Cat::Diagnostics),
+        Handler::App(|s, _| {
+            let state = if s.app.toggle_diff_ignore_whitespace() { "ignored" } else { "shown" };
+            s.app.notifications.info(format!("Whitespace-only changes {}", state));
+            false
+        }),
+    );
+
+    // Code folding
+    r.register(
+        Cmd::ToggleFold,
+        CommandInfo::new("Toggle Fold", Cat::Folding).editor().cursor().recordable(),
+        Handler::App(|s, _| {
+            if let Some(editor) = s.app.workspace.active_editor_mut() {
+                // Detect folds if not already done
+                if editor.fold_manager().regions().is_empty() {
+                    editor.detect_folds();
+                }
+                if editor.toggle_fold_at_cursor() {
+                    let (line, _) = editor.buffer().char_to_line_col(editor.cursor_char_index());
+                    let state = if editor.is_line_folded(line) { "folded" } else { "unfolded" };
+                    s.app.notifications.info(format!("Code region {}", state));
+                }
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::FoldAll,
+        CommandInfo::new("Fold All", Cat::Folding).editor().recordable(),
+        Handler::App(|s, _| {
+            if let Some(editor) = s.app.workspace.active_editor_mut() {
+                editor.detect_folds();
+                editor.fold_all();
+                s.app.notifications.info("All regions folded");
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::UnfoldAll,
+        CommandInfo::new("Unfold All", Cat::Folding).editor().recordable(),
+        Handler::App(|s, _| {
+            if let Some(editor) = s.app.workspace.active_editor_mut() {
+                editor.unfold_all();
+                s.app.notifications.info("All regions unfolded");
+            }
+            false
+        }),
+    );
+
+    // View
+    r.register(
+        Cmd::ToggleWordWrap,
+        CommandInfo::new("Toggle Word Wrap", Cat::View).editor(),
+        Handler::App(|s, _| {
+            if let Some(editor) = s.app.workspace.active_editor_mut() {
+                editor.toggle_word_wrap();
+                let state = if editor.word_wrap() { "enabled" } else { "disabled" };
+                s.app.notifications.info(format!("Word wrap {}", state));
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::CycleIndentation,
+        CommandInfo::new("Cycle Indentation", Cat::Edit).editor(),
+        Handler::App(|s, _| {
+            if let Some(editor) = s.app.workspace.active_editor_mut() {
+                let indent = editor.indent_config().next();
+                editor.set_indent_config(indent);
+                s.app.notifications.info(format!("Indentation: {}", indent.label()));
+            }
+            false
+        }),
+    );
+    r.register(
+        Cmd::ConvertLineEndings,
+        CommandInfo::new("Convert Line Endings", Cat::Edit).editor(),
+        Handler::App(|s, _| {
+            if let Some(editor) = s.app.workspace.active_editor_mut() {
+                // Mixed endings become the one most lines use
+                let line_ending = if editor.has_mixed_line_endings() {
+                    editor.line_ending()
+                } else {
+                    editor.line_ending().toggled()
+                };
+                editor.convert_line_endings(line_ending);
+                s.app.notifications.info(format!("Line endings: {}", line_ending.label()));
+                s.app.notify_lsp_document_change();
+                s.update_window_title();
+            }
+            false
+        }),
+    );
+    let zoom: fn(&mut AppState, &EditorCommand) -> bool = |s, command| {
+        let font_size = match command {
+            Cmd::ZoomIn => s.app.font_size + ZOOM_STEP,
+            Cmd::ZoomOut => s.app.font_size - ZOOM_STEP,
+            _ => s.app.default_font_size,
+        };
+        if s.app.set_font_size(font_size) {
+            if let Some(gpu) = &mut s.gpu {
+                gpu.set_font_size(s.app.font_size);
+            }
+            s.update_visible_dimensions();
+        }
+        false
+    };
+    r.register(Cmd::ZoomIn, CommandInfo::new("Zoom In", Cat::View).repeatable(), Handler::App(zoom));
+    r.register(Cmd::ZoomOut, CommandInfo::new("Zoom Out", Cat::View).repeatable(), Handler::App(zoom));
+    r.register(Cmd::ResetZoom, CommandInfo::new("Reset Zoom", Cat::View), Handler::App(zoom));
+    r.register(
+        Cmd::ToggleZenMode,
+        CommandInfo::new("Toggle Zen Mode", Cat::View),
+        Handler::App(|s, _| {
+            s.app.toggle_zen_mode();
+            s.update_visible_dimensions();
+            false
+        }),
+    );
+    r.register(
+        Cmd::ToggleSplit,
+        CommandInfo::new("Toggle Split", Cat::View),
+        Handler::App(|s, _| {
+            s.app.toggle_split();
+            s.update_visible_dimensions();
+            s.update_window_title();
+            false
+        }),
+    );
+    let focus_pane: fn(&mut AppState, &EditorCommand) -> bool = |s, command| {
+        let side = if matches!(command, Cmd::FocusLeftPane) { PaneSide::Left } else { PaneSide::Right };
+        if s.app.focus_pane(side) {
+            s.update_visible_dimensions();
+            s.update_window_title();
+        }
+        false
+    };
+    r.register(Cmd::FocusLeftPane, CommandInfo::new("Focus Left Pane", Cat::View), Handler::App(focus_pane));
+    r.register(Cmd::FocusRightPane, CommandInfo::new("Focus Right Pane", Cat::View), Handler::App(focus_pane));
+    r.register(
+        Cmd::TogglePerfMetrics,
+        CommandInfo::new("Toggle Perf Metrics", Cat::View),
+        Handler::App(|s, _| {
+            s.app.toggle_perf_metrics();
+            let state = if s.app.show_perf_metrics { "enabled" } else { "disabled" };
+            s.app.notifications.info(format!("Performance metrics {}", state));
+            false
+        }),
+    );
+
+    r
+}
+
 impl ApplicationHandler for AppState {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if self.window.is_none() {
-            let window_attributes = Window::default_attributes()
-                .with_title(&self.app.window_title())
-                .with_inner_size(PhysicalSize::new(1280u32, 720u32));
+            // Open where the window was last time, placed before it is
+            // created so it doesn't jump there after the first frame
+            let monitors: Vec<MonitorRect> = event_loop.available_monitors().map(|m| MonitorRect::of(&m)).collect();
+            let primary = event_loop.primary_monitor().map(|m| MonitorRect::of(&m)).or(monitors.first().copied());
+            let geometry = config_dir()
+                .and_then(|dir| WindowGeometry::load(&dir.join("window.json")))
+                .and_then(|saved| window_geometry::place(&saved, &monitors))
+                .or_else(|| primary.as_ref().map(window_geometry::centered));
+            let (width, height) = window_geometry::DEFAULT_SIZE;
+            let mut window_attributes = Window::default_attributes()
+                .with_title(self.app.window_title())
+                .with_inner_size(PhysicalSize::new(width, height));
+            if let Some(geometry) = geometry {
+                window_attributes = window_attributes
+                    .with_position(PhysicalPosition::new(geometry.x, geometry.y))
+                    .with_inner_size(PhysicalSize::new(geometry.width, geometry.height))
+                    .with_maximized(geometry.maximized);
+            }
+            self.window_geometry = geometry.map(|geometry| WindowGeometry { maximized: false, ..geometry });
 
             let window = Arc::new(
                 event_loop
@@ -2624,7 +7195,8 @@ impl ApplicationHandler for AppState {
                     .expect("Failed to create window"),
             );
 
-            let gpu = GpuState::new(window.clone(), self.app.font_size);
+            let gpu = GpuState::new(window.clone(), self.app.font_size, self.app.ui_font_size);
+            self.app.chrome = gpu.chrome_metrics();
 
             self.window = Some(window.clone());
             self.gpu = Some(gpu);
@@ -2642,18 +7214,25 @@ impl ApplicationHandler for AppState {
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
         match event {
             WindowEvent::CloseRequested => {
+                // Let running saves finish so their buffers count as saved
+                self.app.finish_pending_saves();
+                self.app.handle_workspace_events();
                 if self.app.workspace.has_unsaved_changes() {
                     // Show confirmation dialog
-                    let result = rfd::MessageDialog::new()
-                        .set_title("Unsaved Changes")
-                        .set_description("You have unsaved changes. Are you sure you want to quit?")
-                        .set_buttons(rfd::MessageButtons::YesNo)
-                        .show();
-
-                    if result != rfd::MessageDialogResult::Yes {
+                    let result = self.run_dialog(Modal::Confirmation, |dialogs| {
+                        let dialog = rfd::MessageDialog::new()
+                            .set_title("Unsaved Changes")
+                            .set_description("You have unsaved changes. Are you sure you want to quit?")
+                            .set_buttons(rfd::MessageButtons::YesNo);
+                        dialogs.ask(dialog)
+                    });
+
+                    if result != Some(rfd::MessageDialogResult::Yes) {
                         return; // User cancelled, don't quit
                     }
                 }
+                self.app.save_session();
+                self.save_window_geometry();
                 self.shutdown_lsp();
                 event_loop.exit();
             }
@@ -2663,11 +7242,15 @@ impl ApplicationHandler for AppState {
                         gpu.resize(new_size);
                     }
                     self.update_visible_dimensions();
+                    self.track_window_geometry();
                 }
                 if let Some(window) = &self.window {
                     window.request_redraw();
                 }
             }
+            WindowEvent::Moved(_) => {
+                self.track_window_geometry();
+            }
             WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
                 // Handle DPI change (e.g., moving window between monitors)
                 if let Some(gpu) = &mut self.gpu {
@@ -2682,131 +7265,38 @@ impl ApplicationHandler for AppState {
                 self.app
                     .input_handler
                     .update_modifiers_state(self.modifiers);
-            }
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        state,
-                        logical_key,
-                        repeat,
-                        ..
-                    },
-                ..
-            } => {
-                if state == ElementState::Pressed {
-                    // Handle completion navigation first
-                    if self.app.completion_visible {
-                        match &logical_key {
-                            Key::Named(NamedKey::ArrowDown) => {
-                                self.app.completion_next();
-                                self.app.reset_cursor_blink();
-                                if let Some(window) = &self.window {
-                                    window.request_redraw();
-                                }
-                                return;
-                            }
-                            Key::Named(NamedKey::ArrowUp) => {
-                                self.app.completion_prev();
-                                self.app.reset_cursor_blink();
-                                if let Some(window) = &self.window {
-                                    window.request_redraw();
-                                }
-                                return;
-                            }
-                            Key::Named(NamedKey::Enter) | Key::Named(NamedKey::Tab) => {
-                                self.app.accept_completion();
-                                self.app.notify_lsp_document_change();
-                                self.update_window_title();
-                                self.app.reset_cursor_blink();
-                                if let Some(window) = &self.window {
-                                    window.request_redraw();
-                                }
-                                return;
-                            }
-                            Key::Named(NamedKey::Escape) => {
-                                self.app.hide_completion();
-                                if let Some(window) = &self.window {
-                                    window.request_redraw();
-                                }
-                                return;
-                            }
-                            _ => {
-                                // Any other key hides completion
-                                self.app.hide_completion();
-                            }
-                        }
-                    }
-
-                    // Handle input mode (search/replace/goto) first
-                    if self.app.is_input_mode() {
-                        let handled = self.handle_input_mode_key(&logical_key, event_loop);
-                        if handled {
-                            self.app.reset_cursor_blink();
-                            if let Some(window) = &self.window {
-                                window.request_redraw();
-                            }
-                        } else {
-                            // Check for commands that should work in input mode (Escape, F3)
-                            if let Some(command) = self
-                                .app
-                                .input_handler
-                                .handle_key_event_new(&logical_key, state)
-                            {
-                                match command {
-                                    EditorCommand::CloseSearch
-                                    | EditorCommand::FindNext
-                                    | EditorCommand::FindPrev => {
-                                        if self.execute_command(command, event_loop) {
-                                            event_loop.exit();
-                                        }
-                                        self.app.reset_cursor_blink();
-                                        if let Some(window) = &self.window {
-                                            window.request_redraw();
-                                        }
-                                    }
-                                    _ => {}
-                                }
-                            }
-                        }
-                    } else {
-                        // Normal mode - regular command handling
-                        if let Some(command) = self
-                            .app
-                            .input_handler
-                            .handle_key_event_new(&logical_key, state)
-                        {
-                            if self.execute_command(command, event_loop) {
-                                event_loop.exit();
-                            }
-                            self.app.reset_cursor_blink();
-                            if let Some(window) = &self.window {
-                                window.request_redraw();
-                            }
-                        }
-
-                        // Handle character input for text
-                        if let Key::Character(ch) = &logical_key {
-                            if !self.modifiers.control_key() && !self.modifiers.alt_key() {
-                                if let Some(c) = ch.chars().next() {
-                                    if let Some(command) = self.app.input_handler.handle_char_input(c) {
-                                        // Record keypress for typing latency measurement
-                                        self.app.record_keypress();
-                                        self.execute_command(command, event_loop);
-                                        self.app.reset_cursor_blink();
-                                        if let Some(window) = &self.window {
-                                            window.request_redraw();
-                                        }
-                                    }
-                                }
-                            }
-                        }
+
+                // Releasing the modifier commits the Ctrl+Tab switch
+                if self.app.tab_switcher.is_active() && !self.app.input_handler.is_primary_modifier() {
+                    if let Some(id) = self.app.tab_switcher.commit() {
+                        self.app.workspace.set_active(id);
+                        self.update_window_title();
+                    }
+                    if let Some(window) = &self.window {
+                        window.request_redraw();
                     }
                 }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state,
+                        logical_key,
+                        repeat,
+                        ..
+                    },
+                ..
+            } => {
+                if state == ElementState::Pressed && self.handle_key_press(&logical_key) {
+                    event_loop.exit();
+                }
 
                 if repeat {
                     log::trace!("Key repeat: {:?}", logical_key);
                 }
             }
+            WindowEvent::MouseWheel { .. } | WindowEvent::MouseInput { .. }
+                if input_gate::route(self.modal(), InputKind::Mouse) == InputRoute::Dropped => {}
             WindowEvent::MouseWheel { delta, .. } => {
                 if let Some(command) = self.app.input_handler.handle_scroll(delta) {
                     self.execute_command(command, event_loop);
@@ -2840,12 +7330,12 @@ impl ApplicationHandler for AppState {
                     }
                     Ime::Commit(text) => {
                         self.app.input_handler.ime.end_composition();
-                        if let Some(editor) = self.app.workspace.active_editor_mut() {
-                            editor.insert_text(&text);
+                        match input_gate::route(self.modal(), InputKind::Text) {
+                            InputRoute::Editor => {}
+                            InputRoute::Held => return self.replay.hold(&text, Instant::now()),
+                            InputRoute::Modal | InputRoute::Dropped => return,
                         }
-                        self.app.notify_lsp_document_change();
-                        self.app.reset_cursor_blink();
-                        self.update_window_title();
+                        self.commit_ime_text(&text);
                         if let Some(window) = &self.window {
                             window.request_redraw();
                         }
@@ -2858,7 +7348,14 @@ impl ApplicationHandler for AppState {
             }
             WindowEvent::CursorMoved { position, .. } => {
                 self.mouse_position = position;
-                if self.mouse_dragging {
+                if self.app.tab_menu.is_open() {
+                    let viewport_width = self.gpu.as_ref().map_or(0.0, |gpu| gpu.size.width as f32);
+                    if self.app.update_tab_menu_hover(position.x as f32, position.y as f32, viewport_width) {
+                        if let Some(window) = &self.window {
+                            window.request_redraw();
+                        }
+                    }
+                } else if self.mouse_drag != MouseDrag::None {
                     self.handle_mouse_drag();
                     if let Some(window) = &self.window {
                         window.request_redraw();
@@ -2877,13 +7374,31 @@ impl ApplicationHandler for AppState {
                     }
                 }
             }
+            WindowEvent::Focused(true) => {
+                // The Problems panel catches up on what changed meanwhile
+                if self.app.input_mode == InputMode::Problems {
+                    self.app.refresh_problems();
+                    if let Some(window) = &self.window {
+                        window.request_redraw();
+                    }
+                }
+                // Files are most likely changed elsewhere while the window
+                // is in the background
+                if self.app.check_disk_changes() {
+                    self.update_visible_dimensions();
+                    self.update_window_title();
+                    if let Some(window) = &self.window {
+                        window.request_redraw();
+                    }
+                }
+            }
             WindowEvent::MouseInput { state, button, .. } => {
                 if button == MouseButton::Left {
                     match state {
                         ElementState::Pressed => {
-                            self.mouse_dragging = true;
-                            // Clear hover on click
+                            // Clear hover and popups on click
                             self.app.clear_hover();
+                            self.app.hide_line_diagnostics();
                             let extend = self.modifiers.shift_key();
                             self.handle_mouse_click(extend);
                             if let Some(window) = &self.window {
@@ -2891,7 +7406,20 @@ impl ApplicationHandler for AppState {
                             }
                         }
                         ElementState::Released => {
-                            self.mouse_dragging = false;
+                            // Alt+Click without dragging toggles a cursor
+                            if let MouseDrag::AltPress { line, col } = self.mouse_drag {
+                                if let Some(editor) = self.app.workspace.active_editor_mut() {
+                                    editor.toggle_cursor_at(line, col);
+                                }
+                                self.app.reset_cursor_blink();
+                                if let Some(window) = &self.window {
+                                    window.request_redraw();
+                                }
+                            } else if self.mouse_drag != MouseDrag::None {
+                                self.sync_primary_selection();
+                            }
+                            self.mouse_drag = MouseDrag::None;
+                            self.press_position = None;
                         }
                     }
                 }
@@ -2900,17 +7428,62 @@ impl ApplicationHandler for AppState {
                 // Start frame timing
                 self.app.begin_frame();
 
+                // Open and close documents on the language servers
+                self.app.handle_workspace_events();
+
                 // Poll LSP for events (non-blocking)
                 self.app.poll_lsp();
 
+                // Collect streamed project search results
+                let search_needs_redraw = self.app.poll_project_search() || self.app.project_search.is_running();
+
+                // Collect linter output
+                let lint_needs_redraw = self.app.poll_linter() || self.app.lint_runner.is_running();
+
+                // Append the text of large files loaded in the background
+                let loading_needs_redraw = self.app.poll_loading();
+
+                // Apply finished background saves
+                let saves_need_redraw = self.app.poll_saves() || self.app.save_queue.is_busy();
+                if saves_need_redraw {
+                    self.update_window_title();
+                }
+
+                // Pick up changes other programs made to open files
+                if self.app.disk_checked_at.elapsed() >= DISK_CHECK_INTERVAL && self.app.check_disk_changes() {
+                    self.update_visible_dimensions();
+                    self.update_window_title();
+                }
+
                 // Send debounced document changes
                 self.app.flush_pending_lsp_changes(false);
 
+                // With --wait, closing the waited-on buffer ends the process
+                if self.app.wait_finished() {
+                    self.shutdown_lsp();
+                    event_loop.exit();
+                    return;
+                }
+
+                // Recompute change markers once typing pauses
+                let changes_need_redraw = self.app.update_line_changes();
+
+                // Keep scrolling while a drag holds the pointer above or
+                // below the text
+                let drag_needs_redraw = self.mouse_drag != MouseDrag::None
+                    && self.gpu.as_ref().is_some_and(|gpu| {
+                        self.app.is_outside_text_rows(self.mouse_position.y as f32, gpu.line_height())
+                    });
+                if drag_needs_redraw && self.drag_scrolled_at.elapsed() >= DRAG_SCROLL_INTERVAL {
+                    self.handle_mouse_drag();
+                    self.drag_scrolled_at = Instant::now();
+                }
+
                 // Update cursor blink
                 let blink_needs_redraw = self.app.update_cursor_blink();
 
                 // Update notifications (expire old ones)
-                let notifications_need_redraw = self.app.notifications.update();
+                let notifications_need_redraw = self.app.notifications.update(Instant::now());
 
                 // Update smooth scroll animation and syntax highlighting cache
                 let scroll_needs_redraw = self
@@ -2926,11 +7499,18 @@ impl ApplicationHandler for AppState {
                     })
                     .unwrap_or(false);
 
+                // Popups whose text scrolled away or changed go away
+                self.app.update_popup_anchors();
+                self.app.workspace.update_unfocused_pane();
+
                 // Update memory stats periodically
                 self.app.update_memory_stats();
 
                 if let Some(gpu) = &mut self.gpu {
                     gpu.render(&self.app);
+                    if self.app.perf_metrics.startup.first_render.is_none() {
+                        self.app.perf_metrics.startup.record_first_render();
+                    }
                 }
 
                 // End frame timing
@@ -2938,7 +7518,17 @@ impl ApplicationHandler for AppState {
 
                 // Request next frame for continuous animations
                 if let Some(window) = &self.window {
-                    if blink_needs_redraw || scroll_needs_redraw || notifications_need_redraw || self.app.cursor_blink_enabled {
+                    if blink_needs_redraw
+                        || scroll_needs_redraw
+                        || notifications_need_redraw
+                        || search_needs_redraw
+                        || saves_need_redraw
+                        || loading_needs_redraw
+                        || lint_needs_redraw
+                        || changes_need_redraw
+                        || drag_needs_redraw
+                        || self.app.cursor_blink.is_enabled()
+                    {
                         window.request_redraw();
                     }
                 }
@@ -2950,20 +7540,6 @@ impl ApplicationHandler for AppState {
                     self.app.notifications.error(format!("Failed to open dropped file: {}", e));
                 } else {
                     self.app.notifications.info(format!("Opened: {}", path.display()));
-                    // Start LSP for the opened file
-                    if let Some(editor) = self.app.workspace.active_editor() {
-                        if let Some(file_path) = editor.file_path() {
-                            if let Some(lang_id) = language_id_from_path(file_path) {
-                                if let Some(parent) = file_path.parent() {
-                                    if let Some(root) = find_project_root(parent) {
-                                        self.app.lsp_manager.set_workspace_root(Some(root));
-                                        self.app.lsp_manager.start_client(&lang_id);
-                                        self.app.lsp_manager.did_open(file_path, &lang_id, &editor.buffer().to_string());
-                                    }
-                                }
-                            }
-                        }
-                    }
                     self.update_window_title();
                 }
                 if let Some(window) = &self.window {
@@ -2981,13 +7557,36 @@ impl ApplicationHandler for AppState {
             _ => {}
         }
     }
+
+    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+        // Everything queued while a dialog was up has arrived by now
+        self.finish_dialog();
+    }
 }
 
-/// Runs the editor application.
-pub fn run(app: EditorApp) {
+/// Runs the editor until its window closes and returns the process exit
+/// code (see [`EditorApp::exit_code`]).
+pub fn run(app: EditorApp) -> i32 {
     let event_loop = EventLoop::new().expect("Failed to create event loop");
     let mut state = AppState::new(app);
     event_loop.run_app(&mut state).expect("Event loop error");
+    state.app.exit_code()
+}
+
+/// Returns the directory the editor keeps its own files in:
+/// `%APPDATA%\cp-editor` on Windows, `~/Library/Application Support/cp-editor`
+/// on macOS and `$XDG_CONFIG_HOME/cp-editor` or `~/.config/cp-editor`
+/// elsewhere.
+fn config_dir() -> Option<PathBuf> {
+    let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let base = if cfg!(windows) {
+        var("APPDATA")?
+    } else if cfg!(target_os = "macos") {
+        var("HOME")?.join("Library").join("Application Support")
+    } else {
+        var("XDG_CONFIG_HOME").or_else(|| Some(var("HOME")?.join(".config")))?
+    };
+    Some(base.join("cp-editor"))
 }
 
 /// Finds the project root directory by looking for common project markers.
@@ -3017,3 +7616,1928 @@ fn find_project_root(start_dir: &std::path::Path) -> Option<PathBuf> {
         }
     }
 }
+
+/// Returns true if two paths name the same file, resolving symlinks and
+/// `..` when both exist.
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Linters used for languages that usually have no language server set up.
+fn default_linters() -> HashMap<String, Linter> {
+    let mut linters = HashMap::new();
+    linters.insert(
+        "python".to_string(),
+        Linter::new("flake8 {file}", ProblemMatcher::flake8("flake8")),
+    );
+    linters.insert(
+        "c".to_string(),
+        Linter::new("gcc -fsyntax-only -Wall {file}", ProblemMatcher::gcc("gcc")),
+    );
+    linters.insert(
+        "cpp".to_string(),
+        Linter::new("g++ -fsyntax-only -Wall {file}", ProblemMatcher::gcc("g++")),
+    );
+    linters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc_count;
+    use crate::settings::MouseSettings;
+    use cp_editor_core::{Diagnostic, Encoding, IndentConfig, Language, LineEnding, TextBuffer};
+    use std::cell::RefCell;
+    use std::fs;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_prepare_run_saves_active_buffer() {
+        let dir = std::env::temp_dir().join(format!("cp_editor_save_before_run_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.txt");
+        fs::write(&path, "old").unwrap();
+
+        let mut app = EditorApp::new(14.0);
+        app.save_before_run = SaveBeforeRun::Active;
+        let id = app.workspace.open_file(&path).unwrap();
+        app.workspace.set_active(id);
+        app.workspace.active_editor_mut().unwrap().insert_text("new ");
+
+        app.prepare_run();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new old");
+        assert!(!app.workspace.active_editor().unwrap().is_modified());
+
+        // With saving off the edit stays in the buffer only
+        app.save_before_run = SaveBeforeRun::Off;
+        app.workspace.active_editor_mut().unwrap().insert_text("x");
+        app.prepare_run();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new old");
+        assert!(app.workspace.active_editor().unwrap().is_modified());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parse_goto_target() {
+        let target = |line, col| Ok(GotoTarget { line, col });
+        assert_eq!(parse_goto_target("42", 7, 100), target(42, None));
+        assert_eq!(parse_goto_target("42:10", 7, 100), target(42, Some(10)));
+        assert_eq!(parse_goto_target(":15", 7, 100), target(7, Some(15)));
+        assert_eq!(parse_goto_target("-1", 7, 100), target(100, None));
+        assert_eq!(parse_goto_target("-100:3", 7, 100), target(1, Some(3)));
+        assert_eq!(parse_goto_target("", 7, 100).unwrap_err(), "Enter a line, line:col or :col");
+        assert_eq!(parse_goto_target("0", 7, 100).unwrap_err(), "Line 0 is out of range (1-100)");
+        assert_eq!(parse_goto_target("101", 7, 100).unwrap_err(), "Line 101 is out of range (1-100)");
+        assert_eq!(parse_goto_target("-101", 7, 100).unwrap_err(), "Line -101 is out of range (1-100)");
+        assert_eq!(parse_goto_target("4-2", 7, 100).unwrap_err(), "Not a line number: 4-2");
+        assert_eq!(parse_goto_target("4:", 7, 100).unwrap_err(), "Enter a column after ':'");
+        assert_eq!(parse_goto_target("4:0", 7, 100).unwrap_err(), "Not a column: 0");
+        assert_eq!(parse_goto_target("4:1:2", 7, 100).unwrap_err(), "Not a column: 1:2");
+    }
+
+    #[test]
+    fn test_goto_line_col_input() {
+        let mut app = EditorApp::new(14.0);
+        app.workspace.active_editor_mut().unwrap().insert_text("one\ntwo\nthree");
+        let mut state = AppState::new(app);
+        let type_goto = |state: &mut AppState, text: &str| {
+            state.app.open_goto_line();
+            for c in text.chars() {
+                state.handle_key_press(&Key::Character(c.to_string().into()));
+            }
+            state.handle_key_press(&Key::Named(NamedKey::Enter));
+        };
+
+        type_goto(&mut state, "2:3");
+        assert_eq!(state.app.input_mode, InputMode::Normal);
+        let position = state.app.workspace.active_editor().unwrap().cursor_position();
+        assert_eq!((position.line, position.col), (1, 2));
+
+        type_goto(&mut state, "-1");
+        assert_eq!(state.app.workspace.active_editor().unwrap().cursor_position().line, 2);
+
+        // Malformed input keeps the bar open and says why
+        type_goto(&mut state, "9");
+        assert_eq!(state.app.input_mode, InputMode::GoToLine);
+        assert_eq!(state.app.goto_error.as_deref(), Some("Line 9 is out of range (1-3)"));
+        state.handle_key_press(&Key::Named(NamedKey::Backspace));
+        assert_eq!(state.app.goto_error, None);
+    }
+
+    #[test]
+    fn test_save_cleans_up_whitespace() {
+        let dir = std::env::temp_dir().join(format!("cp_editor_save_cleanup_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.txt");
+        fs::write(&path, "a \nb\t\nc").unwrap();
+
+        let mut app = EditorApp::new(14.0);
+        app.apply_settings(Settings::parse(r#"{ "save": { "trim_trailing_whitespace": true, "ensure_final_newline": true } }"#).unwrap());
+        let id = app.workspace.open_file(&path).unwrap();
+        app.workspace.set_active(id);
+        app.save_in_background(id).unwrap();
+        app.finish_pending_saves();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\nc\n");
+        let messages: Vec<_> = app.notifications.visible().map(|n| n.message.clone()).collect();
+        assert!(messages.contains(&"Trimmed 2 lines, added final newline".to_string()));
+
+        // Undo restores the whitespace in the buffer
+        app.workspace.active_editor_mut().unwrap().undo();
+        assert_eq!(app.workspace.active_editor().unwrap().buffer().to_string(), "a \nb\t\nc");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_screen_to_buffer_position_with_word_wrap() {
+        let mut app = EditorApp::new(14.0);
+        let (char_width, line_height) = (10.0, 20.0);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.insert_text("aaaa bbbb cccc\nend");
+        editor.set_wrap_width(10);
+        editor.set_word_wrap(true);
+        editor.set_cursor_position(0, 0, false);
+
+        let text_x = app.gutter_layout(char_width).width();
+        let y = |row: f32| app.content_y_offset() + (row + 0.5) * line_height;
+        // The second row of the first line starts at its wrap point
+        assert_eq!(app.screen_to_buffer_position(text_x, y(1.0), char_width, line_height), (0, 10));
+        assert_eq!(app.screen_to_buffer_position(text_x + 20.0, y(1.0), char_width, line_height), (0, 12));
+        // Past the end of a wrapped row stays on that row
+        assert_eq!(app.screen_to_buffer_position(text_x + 500.0, y(0.0), char_width, line_height), (0, 9));
+        assert_eq!(app.screen_to_buffer_position(text_x + 10.0, y(2.0), char_width, line_height), (1, 1));
+        assert_eq!(app.screen_to_buffer_position(text_x, y(9.0), char_width, line_height), (1, 0));
+    }
+
+    #[test]
+    fn test_screen_to_buffer_position_with_invisible_chars() {
+        let mut app = EditorApp::new(14.0);
+        let (char_width, line_height) = (10.0, 20.0);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        // The zero-width space is drawn as a box six columns wide
+        editor.insert_text("a\u{200B}bc");
+
+        let text_x = app.gutter_layout(char_width).width();
+        let y = app.content_y_offset() + 0.5 * line_height;
+        let col_at = |screen_col: f32| app.screen_to_buffer_position(text_x + screen_col * char_width, y, char_width, line_height);
+        assert_eq!(col_at(1.0), (0, 1));
+        // A click in the box goes to its nearer side
+        assert_eq!(col_at(3.0), (0, 1));
+        assert_eq!(col_at(5.0), (0, 2));
+        // After the box, columns are shifted by its width
+        assert_eq!(col_at(8.0), (0, 3));
+        assert_eq!(col_at(9.0), (0, 4));
+    }
+
+    #[test]
+    fn test_every_command_is_registered_once() {
+        // Numbers the variants; adding one fails to compile until it is
+        // numbered here, and this test until it is registered
+        fn number(command: &EditorCommand) -> usize {
+            use EditorCommand as C;
+            match command {
+                C::Save => 0,
+                C::SaveAs => 1,
+                C::OpenFile => 2,
+                C::ReopenRecent => 3,
+                C::ReopenWithEncoding => 4,
+                C::NewFile => 5,
+                C::CloseTab => 6,
+                C::CloseAllTabs => 7,
+                C::CloseOtherTabs => 8,
+                C::CloseSavedTabs => 9,
+                C::Quit => 10,
+                C::NextTab => 11,
+                C::PrevTab => 12,
+                C::SwitchToTab(_) => 13,
+                C::InsertChar(_) => 14,
+                C::InsertNewline => 15,
+                C::DeleteBackward => 16,
+                C::DeleteForward => 17,
+                C::MoveLeft => 18,
+                C::MoveRight => 19,
+                C::MoveUp => 20,
+                C::MoveDown => 21,
+                C::MoveWordLeft => 22,
+                C::MoveWordRight => 23,
+                C::MoveSubWordLeft => 24,
+                C::MoveSubWordRight => 25,
+                C::MoveToLineStart => 26,
+                C::MoveToLineStartSmart => 27,
+                C::MoveToLineEnd => 28,
+                C::MovePageUp => 29,
+                C::MovePageDown => 30,
+                C::MoveToBufferStart => 31,
+                C::MoveToBufferEnd => 32,
+                C::SelectLeft => 33,
+                C::SelectRight => 34,
+                C::SelectUp => 35,
+                C::SelectDown => 36,
+                C::SelectWordLeft => 37,
+                C::SelectWordRight => 38,
+                C::SelectSubWordLeft => 39,
+                C::SelectSubWordRight => 40,
+                C::SelectToLineStart => 41,
+                C::SelectToLineStartSmart => 42,
+                C::SelectToLineEnd => 43,
+                C::SelectPageUp => 44,
+                C::SelectPageDown => 45,
+                C::SelectToBufferStart => 46,
+                C::SelectToBufferEnd => 47,
+                C::ExpandSelection => 48,
+                C::ShrinkSelection => 49,
+                C::SelectAll => 50,
+                C::DuplicateLine => 51,
+                C::MoveLineUp => 52,
+                C::MoveLineDown => 53,
+                C::DeleteLine => 54,
+                C::InsertLineBelow => 55,
+                C::InsertLineAbove => 56,
+                C::ToggleBlockSelection => 57,
+                C::BlockSelectUp => 58,
+                C::BlockSelectDown => 59,
+                C::BlockSelectLeft => 60,
+                C::BlockSelectRight => 61,
+                C::AddCursorAbove => 62,
+                C::AddCursorBelow => 63,
+                C::SelectNextOccurrence => 64,
+                C::SelectAllOccurrences => 65,
+                C::CollapseCursors => 66,
+                C::Undo => 67,
+                C::Redo => 68,
+                C::Copy => 69,
+                C::CopyWithLineNumbers => 70,
+                C::CopyAsMarkdownCodeBlock => 71,
+                C::Cut => 72,
+                C::Paste => 73,
+                C::ScrollUp(_) => 74,
+                C::ScrollDown(_) => 75,
+                C::ScrollLeft(_) => 76,
+                C::ScrollRight(_) => 77,
+                C::OpenSearch => 78,
+                C::OpenReplace => 79,
+                C::FindNext => 80,
+                C::FindPrev => 81,
+                C::CloseSearch => 82,
+                C::OpenProjectSearch => 83,
+                C::GoToLine => 84,
+                C::JumpToMatchingBracket => 85,
+                C::SelectToMatchingBracket => 86,
+                C::GotoDefinition => 87,
+                C::TriggerCompletion => 88,
+                C::RenameSymbol => 89,
+                C::ShowCodeActions => 90,
+                C::TrustWorkspace => 91,
+                C::EnableLspForFile => 92,
+                C::Indent => 93,
+                C::Dedent => 94,
+                C::ToggleComment => 95,
+                C::ToggleWordWrap => 96,
+                C::CycleIndentation => 97,
+                C::ConvertLineEndings => 98,
+                C::FilterSelection => 99,
+                C::StripBidiControls => 100,
+                C::EscapeBidiControls => 101,
+                C::CycleDiagnosticFilter => 102,
+                C::ShowLineDiagnostics => 103,
+                C::RunLinter => 104,
+                C::ToggleLintPanel => 105,
+                C::ShowProblems => 106,
+                C::ExportDiagnostics => 107,
+                C::ToggleDiffIgnoreWhitespace => 108,
+                C::ToggleFold => 109,
+                C::FoldAll => 110,
+                C::UnfoldAll => 111,
+                C::ZoomIn => 112,
+                C::ZoomOut => 113,
+                C::ResetZoom => 114,
+                C::ToggleZenMode => 115,
+                C::ToggleSplit => 116,
+                C::FocusLeftPane => 117,
+                C::FocusRightPane => 118,
+                C::TogglePerfMetrics => 119,
+            }
+        }
+        let registry = command_registry();
+        let mut numbers: Vec<usize> = registry.iter().map(|entry| number(&entry.command)).collect();
+        numbers.sort_unstable();
+        assert_eq!(numbers, (0..120).collect::<Vec<_>>());
+
+        // Names identify commands in lists
+        let names: std::collections::HashSet<_> = registry.iter().map(|entry| entry.info.name).collect();
+        assert_eq!(names.len(), registry.len());
+
+        // Edits are made on a buffer, keep the cursor solid and can be replayed
+        for entry in registry.iter().filter(|entry| entry.info.modifies_buffer) {
+            let info = entry.info;
+            assert!(info.needs_editor && info.cursor_activity && info.recordable, "{}", info.name);
+        }
+        let info = |command: EditorCommand| *registry.info(&command).unwrap();
+        assert!(info(EditorCommand::ToggleComment).modifies_buffer);
+        assert!(!info(EditorCommand::ToggleFold).modifies_buffer);
+        assert!(info(EditorCommand::InsertChar('x')).repeatable);
+        assert!(!info(EditorCommand::Quit).recordable);
+        assert!(!info(EditorCommand::ScrollDown(3.0)).cursor_activity);
+        assert_eq!(info(EditorCommand::SwitchToTab(4)).category, CommandCategory::Tabs);
+    }
+
+    #[test]
+    fn test_click_counter() {
+        let mut clicks = ClickCounter::default();
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let interval = MouseSettings::default().multi_click_interval();
+        assert_eq!(clicks.click(at(0), 3, 4, interval), 1);
+        assert_eq!(clicks.click(at(200), 3, 4, interval), 2);
+        assert_eq!(clicks.click(at(450), 3, 4, interval), 3);
+        // A fourth click starts over
+        assert_eq!(clicks.click(at(500), 3, 4, interval), 1);
+
+        // Too slow, or somewhere else
+        assert_eq!(clicks.click(at(1000), 3, 4, interval), 1);
+        assert_eq!(clicks.click(at(1100), 3, 5, interval), 1);
+        assert_eq!(clicks.click(at(1200), 3, 5, interval), 2);
+
+        // A shorter interval needs quicker clicks
+        let interval = Duration::from_millis(100);
+        assert_eq!(clicks.click(at(2000), 3, 4, interval), 1);
+        assert_eq!(clicks.click(at(2200), 3, 4, interval), 1);
+        assert_eq!(clicks.click(at(2250), 3, 4, interval), 2);
+    }
+
+    #[test]
+    fn test_primary_selection_text() {
+        let mut app = EditorApp::new(14.0);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.insert_text("hello world");
+        assert_eq!(app.primary_selection_text(), None);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.set_cursor_position(0, 0, false);
+        editor.set_cursor_position(0, 5, true);
+        assert_eq!(app.primary_selection_text().as_deref(), Some("hello"));
+
+        app.apply_settings(Settings::parse(r#"{ "mouse": { "primary_selection": false } }"#).unwrap());
+        assert_eq!(app.primary_selection_text(), None);
+    }
+
+    #[test]
+    fn test_scroll_sideways() {
+        let mut app = EditorApp::new(14.0);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.insert_text(&"x".repeat(300));
+        editor.set_horizontal_scroll(0);
+        let mut state = AppState::new(app);
+        let registry = command_registry();
+        registry.execute(&mut state, &EditorCommand::ScrollRight(30.0));
+        registry.execute(&mut state, &EditorCommand::ScrollLeft(10.0));
+        assert_eq!(state.app.workspace.active_editor().unwrap().horizontal_scroll(), 20);
+        registry.execute(&mut state, &EditorCommand::ScrollLeft(50.0));
+        assert_eq!(state.app.workspace.active_editor().unwrap().horizontal_scroll(), 0);
+
+        // Wrapped lines don't scroll sideways
+        registry.execute(&mut state, &EditorCommand::ToggleWordWrap);
+        registry.execute(&mut state, &EditorCommand::ScrollRight(30.0));
+        assert_eq!(state.app.workspace.active_editor().unwrap().horizontal_scroll(), 0);
+    }
+
+    #[test]
+    fn test_drag_position() {
+        let mut app = EditorApp::new(14.0);
+        let (char_width, line_height) = (10.0, 20.0);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        let text: Vec<String> = (0..100).map(|i| format!("line {}", i)).collect();
+        editor.insert_text(&text.join("\n"));
+        editor.set_visible_lines(10);
+        editor.set_scroll_offset(20);
+
+        let text_x = app.gutter_layout(char_width).width();
+        let top = app.content_y_offset();
+        let y = |row: f32| top + (row + 0.5) * line_height;
+        let x = text_x + 12.0 * char_width;
+        // A plain drag stops at the end of the line, a block one doesn't
+        assert_eq!(app.drag_position(x, y(2.0), char_width, line_height, false), (22, 7));
+        assert_eq!(app.drag_position(x, y(2.0), char_width, line_height, true), (22, 12));
+
+        // Above and below the text the drag reaches one line further
+        assert!(app.is_outside_text_rows(top - 1.0, line_height));
+        assert!(app.is_outside_text_rows(y(10.0), line_height));
+        assert!(!app.is_outside_text_rows(y(9.0), line_height));
+        assert_eq!(app.drag_position(x, top - 5.0, char_width, line_height, false), (19, 7));
+        assert_eq!(app.drag_position(x, y(12.0), char_width, line_height, true), (30, 12));
+        app.workspace.active_editor_mut().unwrap().set_scroll_offset(95);
+        assert_eq!(app.drag_position(x, y(12.0), char_width, line_height, false), (99, 7));
+    }
+
+    #[test]
+    fn test_popups_follow_their_anchor() {
+        let mut app = EditorApp::new(14.0);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        let text: Vec<String> = (0..50).map(|i| format!("item{}.", i)).collect();
+        editor.insert_text(&text.join("\n"));
+        editor.set_visible_lines(10);
+        editor.set_cursor_position(5, 6, false);
+        editor.set_scroll_offset(0);
+
+        let anchor_at_cursor = |app: &EditorApp| {
+            let editor = app.workspace.active_editor().unwrap();
+            let pos = editor.cursor_position();
+            PopupAnchor::new(editor.buffer(), pos.line, pos.col)
+        };
+        let open_completion = |app: &mut EditorApp| {
+            app.completion_anchor = Some(anchor_at_cursor(app));
+            app.completion_visible = true;
+        };
+
+        // Typing after the trigger and scrolling a little keep the popup
+        open_completion(&mut app);
+        app.workspace.active_editor_mut().unwrap().insert_text("len");
+        app.workspace.active_editor_mut().unwrap().set_scroll_offset(3);
+        app.update_popup_anchors();
+        assert!(app.completion_visible);
+
+        // Scrolling the trigger line out of view dismisses it
+        app.workspace.active_editor_mut().unwrap().set_scroll_offset(6);
+        app.update_popup_anchors();
+        assert!(!app.completion_visible);
+        assert!(app.completion_anchor.is_none());
+
+        // So does an edit before the trigger, such as a workspace edit
+        app.workspace.active_editor_mut().unwrap().set_scroll_offset(0);
+        open_completion(&mut app);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.set_cursor_position(0, 0, false);
+        editor.insert_text("// header\n");
+        app.update_popup_anchors();
+        assert!(!app.completion_visible);
+
+        // A mouse hover goes away on any scroll, a keyboard one doesn't
+        app.hover_anchor = Some(HoverAnchor::Mouse { x: 0.0, y: 0.0, scroll: (0, 0) });
+        app.update_popup_anchors();
+        assert!(app.hover_anchor.is_some());
+        app.workspace.active_editor_mut().unwrap().set_scroll_offset(1);
+        app.update_popup_anchors();
+        assert!(app.hover_anchor.is_none());
+        app.workspace.active_editor_mut().unwrap().set_cursor_position(4, 2, false);
+        app.hover_anchor = Some(HoverAnchor::Text(anchor_at_cursor(&app)));
+        app.workspace.active_editor_mut().unwrap().set_scroll_offset(2);
+        app.update_popup_anchors();
+        assert!(app.hover_anchor.is_some());
+    }
+
+    #[test]
+    fn test_tab_overflow_menu() {
+        let mut app = EditorApp::new(14.0);
+        let char_width = app.chrome.char_width;
+        let first = app.workspace.active_buffer_id().unwrap();
+        let second = app.workspace.new_buffer();
+        let third = app.workspace.new_buffer();
+        app.workspace.get_buffer_mut(second).unwrap().insert_text("changed");
+        app.workspace.set_active(first);
+
+        // Three "Untitled" tabs fit in a wide window, not in a narrow one
+        let tab = EditorApp::tab_width(&app.workspace.tab_labels().next().unwrap(), char_width);
+        let narrow = 2.0 * tab;
+        assert!(!app.tabs_overflow(10.0 * tab));
+        assert!(app.tabs_overflow(narrow));
+        let tab_bar_y = app.chrome.tab_bar_height() / 2.0;
+        assert!(app.is_on_tab_menu_button(narrow - 1.0, tab_bar_y, narrow));
+        assert!(!app.is_on_tab_menu_button(narrow - 1.0, tab_bar_y, 10.0 * tab));
+
+        app.toggle_tab_menu();
+        let listed: Vec<_> = app.tab_menu.entries().iter().map(|e| (e.id, e.is_modified, e.is_active)).collect();
+        assert_eq!(listed, [(first, false, true), (second, true, false), (third, false, false)]);
+
+        // Picking an entry switches to its buffer and closes the menu
+        let layout = app.tab_menu_layout(narrow);
+        let (x, y) = (layout.x + 2.0, layout.row_y(2) + 1.0);
+        assert!(app.update_tab_menu_hover(x, y, narrow));
+        assert!(app.handle_tab_menu_click(x, y, narrow));
+        assert_eq!(app.workspace.active_buffer_id(), Some(third));
+        assert!(!app.tab_menu.is_open());
+        assert!(!app.handle_tab_menu_click(x, y, narrow));
+
+        // Clicking elsewhere only closes it
+        app.toggle_tab_menu();
+        assert!(app.handle_tab_menu_click(0.0, 500.0, narrow));
+        assert_eq!(app.workspace.active_buffer_id(), Some(third));
+        assert!(!app.tab_menu.is_open());
+    }
+
+    #[test]
+    fn test_line_changes_are_debounced() {
+        let mut app = EditorApp::new(14.0);
+        app.line_changes_debounce = Duration::from_millis(20);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.set_buffer(TextBuffer::from_str("a\nb"));
+        editor.insert_text("x");
+
+        // The first frame after an edit only starts the wait
+        assert!(app.update_line_changes());
+        assert_eq!(app.workspace.active_editor().unwrap().line_change_status(0), None);
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(app.update_line_changes());
+        assert_eq!(
+            app.workspace.active_editor().unwrap().line_change_status(0),
+            Some(ChangeKind::Modified)
+        );
+        assert!(!app.update_line_changes());
+
+        // The setting reaches every buffer and marks its changes stale
+        assert!(app.toggle_diff_ignore_whitespace());
+        let editor = app.workspace.active_editor().unwrap();
+        assert!(editor.diff_ignores_whitespace());
+        assert!(editor.line_changes_stale());
+    }
+
+    #[test]
+    fn test_copy_for_sharing() {
+        let mut app = EditorApp::new(14.0);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.insert_text("a\nb\nc\n");
+        editor.set_cursor_position(1, 1, false);
+        editor.set_cursor_position(2, 0, true);
+
+        assert_eq!(app.copy_for_sharing(false).as_deref(), Some("2 | b\n"));
+        // An untitled buffer has no language to tag the block with
+        app.workspace.active_editor_mut().unwrap().clear_selection();
+        assert_eq!(app.copy_for_sharing(true).as_deref(), Some("```\na\nb\nc\n```\n"));
+        let messages: Vec<_> = app.notifications.visible().map(|n| n.message.clone()).collect();
+        // Newest first
+        assert_eq!(messages, ["Copied 3 lines as a Markdown code block", "Copied 1 line with line numbers"]);
+    }
+
+    #[test]
+    fn test_tab_indents_selected_lines() {
+        let mut app = EditorApp::new(14.0);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.set_indent_config(IndentConfig::tabs(4));
+        editor.insert_text("a\nb\nc");
+        editor.set_cursor_position(0, 0, false);
+        editor.set_cursor_position(1, 1, true);
+        let mut state = AppState::new(app);
+        let text = |state: &AppState| state.app.workspace.active_editor().unwrap().buffer().to_string();
+
+        // Tab indents the lines with the configured unit, keeping them selected
+        state.handle_key_press(&Key::Named(NamedKey::Tab));
+        assert_eq!(text(&state), "\ta\n\tb\nc");
+        let selected = state.app.workspace.active_editor().unwrap().selected_text();
+        assert_eq!(selected.as_deref(), Some("\ta\n\tb"));
+
+        // Shift+Tab takes it back off, each press one undo step
+        state.app.input_handler.update_modifiers_state(ModifiersState::SHIFT);
+        state.handle_key_press(&Key::Named(NamedKey::Tab));
+        assert_eq!(text(&state), "a\nb\nc");
+        command_registry().execute(&mut state, &EditorCommand::Undo);
+        assert_eq!(text(&state), "\ta\n\tb\nc");
+
+        // Without a selection Tab types the unit at the cursor
+        state.app.input_handler.update_modifiers_state(ModifiersState::empty());
+        state.app.workspace.active_editor_mut().unwrap().set_cursor_position(2, 1, false);
+        state.handle_key_press(&Key::Named(NamedKey::Tab));
+        assert_eq!(text(&state), "\ta\n\tb\nc\t");
+    }
+
+    #[test]
+    fn test_alt_shift_arrows_grow_a_block() {
+        let mut state = AppState::new(EditorApp::new(14.0));
+        state.app.workspace.active_editor_mut().unwrap().insert_text("abc\ndef\nghi");
+        state.app.workspace.active_editor_mut().unwrap().set_cursor_position(0, 0, false);
+        state.app.input_handler.update_modifiers_state(ModifiersState::ALT | ModifiersState::SHIFT);
+        state.handle_key_press(&Key::Named(NamedKey::ArrowDown));
+        // Right moves the block's corner rather than expanding the selection
+        state.handle_key_press(&Key::Named(NamedKey::ArrowRight));
+        state.handle_key_press(&Key::Named(NamedKey::ArrowRight));
+        let editor = state.app.workspace.active_editor().unwrap();
+        let block = editor.get_block_selection().unwrap();
+        assert_eq!(block.bounds(), (cp_editor_core::Position::new(0, 0), cp_editor_core::Position::new(1, 2)));
+        assert_eq!(editor.block_selected_text().unwrap(), ["ab", "de"]);
+    }
+
+    #[test]
+    fn test_block_selection_keys_edit_every_row() {
+        let mut state = AppState::new(EditorApp::new(14.0));
+        let select_column = |state: &mut AppState, width: usize| {
+            let editor = state.app.workspace.active_editor_mut().unwrap();
+            editor.set_cursor_position(0, 1, false);
+            editor.start_block_selection();
+            editor.extend_block_selection(2, 1 + width);
+        };
+        let text = |state: &AppState| state.app.workspace.active_editor().unwrap().buffer().to_string();
+        state.app.workspace.active_editor_mut().unwrap().insert_text("abc
+def
+ghi");
+        state.app.input_handler.update_modifiers_state(ModifiersState::empty());
+
+        // Typing replaces the block on every row, then goes on at each
+        select_column(&mut state, 1);
+        state.handle_key_press(&Key::Character("x".into()));
+        state.handle_key_press(&Key::Character("y".into()));
+        assert_eq!(text(&state), "axyc
+dxyf
+gxyi");
+        let editor = state.app.workspace.active_editor().unwrap();
+        assert!(!editor.is_block_selection_mode());
+        assert_eq!(editor.cursor_count(), 3);
+
+        // Delete and Backspace at a zero-width column work on every row
+        select_column(&mut state, 0);
+        state.handle_key_press(&Key::Named(NamedKey::Delete));
+        assert_eq!(text(&state), "ayc
+dyf
+gyi");
+        select_column(&mut state, 0);
+        state.handle_key_press(&Key::Named(NamedKey::Backspace));
+        assert_eq!(text(&state), "yc
+yf
+yi");
+        assert!(!state.app.workspace.active_editor().unwrap().is_block_selection_mode());
+    }
+
+    #[test]
+    fn test_input_bar_field_editing() {
+        let mut state = AppState::new(EditorApp::new(14.0));
+        state.app.workspace.active_editor_mut().unwrap().insert_text("foo bar\nfoo baz");
+        state.app.open_search();
+        let type_text = |state: &mut AppState, text: &str| {
+            for ch in text.chars() {
+                let key = if ch == ' ' { Key::Named(NamedKey::Space) } else { Key::Character(ch.to_string().into()) };
+                state.handle_key_press(&key);
+            }
+        };
+        let matches = |state: &AppState| state.app.workspace.active_editor().unwrap().search_matches().len();
+
+        // The arrows move within the field, and the search follows each edit
+        type_text(&mut state, "bz");
+        state.handle_key_press(&Key::Named(NamedKey::ArrowLeft));
+        type_text(&mut state, "a");
+        assert_eq!(state.app.search_text.text(), "baz");
+        state.handle_key_press(&Key::Named(NamedKey::Home));
+        type_text(&mut state, "foo ");
+        assert_eq!(state.app.search_text.text(), "foo baz");
+        assert_eq!(matches(&state), 1);
+
+        // Ctrl+A selects the field's text and Backspace deletes it
+        state.modifiers = ModifiersState::CONTROL;
+        state.handle_key_press(&Key::Character("a".into()));
+        state.modifiers = ModifiersState::empty();
+        assert_eq!(state.app.search_text.selected_text(), Some("foo baz"));
+        state.handle_key_press(&Key::Named(NamedKey::Backspace));
+        assert!(state.app.search_text.is_empty());
+        type_text(&mut state, "foo");
+        assert_eq!(matches(&state), 2);
+        state.app.close_input_bar();
+
+        // Go to line keeps only what line:col input is made of
+        state.app.open_goto_line();
+        type_text(&mut state, "1a:2");
+        assert_eq!(state.app.goto_text.text(), "1:2");
+        state.modifiers = ModifiersState::SHIFT;
+        state.handle_key_press(&Key::Named(NamedKey::ArrowLeft));
+        state.modifiers = ModifiersState::empty();
+        state.handle_key_press(&Key::Named(NamedKey::Backspace));
+        assert_eq!(state.app.goto_text.text(), "1:");
+        // The editor's text is untouched
+        assert_eq!(state.app.workspace.active_editor().unwrap().buffer().to_string(), "foo bar\nfoo baz");
+    }
+
+    #[test]
+    fn test_convert_line_endings() {
+        let mut state = AppState::new(EditorApp::new(14.0));
+        state.app.workspace.active_editor_mut().unwrap().insert_text("a\nb");
+        state.app.input_handler.update_modifiers_state(ModifiersState::ALT);
+        state.handle_key_press(&Key::Character("e".into()));
+        let editor = state.app.workspace.active_editor().unwrap();
+        assert_eq!(editor.line_ending(), LineEnding::Crlf);
+        assert!(editor.is_modified());
+        let message = state.app.notifications.visible().last().map(|n| n.message.clone());
+        assert_eq!(message.as_deref(), Some("Line endings: CRLF"));
+    }
+
+    #[test]
+    fn test_cycle_indentation() {
+        let mut state = AppState::new(EditorApp::new(14.0));
+        command_registry().execute(&mut state, &EditorCommand::CycleIndentation);
+        let editor = state.app.workspace.active_editor_mut().unwrap();
+        assert_eq!(editor.indent_config(), IndentConfig::spaces(8));
+        // Tab and new lines follow it
+        editor.insert_text("x");
+        editor.insert_newline();
+        editor.insert_char('\t');
+        assert_eq!(editor.buffer().to_string(), "x\n        ");
+        let message = state.app.notifications.visible().next().map(|n| n.message.clone());
+        assert_eq!(message.as_deref(), Some("Indentation: Spaces: 8"));
+    }
+
+    /// Draws a few frames of `visible_lines` rows of highlighted code with
+    /// search matches, selections and diagnostics in view, and returns the
+    /// allocations made by the last one.
+    fn steady_frame_allocations(visible_lines: usize) -> usize {
+        let mut app = EditorApp::new(14.0);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.insert_text(&"fn main() { let value = compute(1, 2); println!(\"{}\", value); }\n".repeat(500));
+        editor.set_language(Language::Rust);
+        editor.set_visible_lines(visible_lines);
+        editor.find("compute");
+        editor.set_cursor_position(0, 17, false);
+        editor.select_all_occurrences();
+        let diagnostics = (0..200)
+            .map(|line| Diagnostic::new(line, 16, line, 21, DiagnosticSeverity::Warning, "unused".to_string()))
+            .collect();
+        editor.set_diagnostics(diagnostics);
+
+        let mut draw = DrawList::new(800, 2000, 14.0, 13.0);
+        for _ in 0..3 {
+            app.render(&mut draw);
+        }
+        alloc_count::count(|| app.render(&mut draw)).1
+    }
+
+    #[test]
+    fn test_split_frame_allocates_only_the_other_gutter() {
+        let mut app = EditorApp::new(14.0);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.insert_text(&"fn main() {}\n".repeat(100));
+        editor.toggle_bookmark(3);
+        editor.set_visible_lines(40);
+        let mut draw = DrawList::new(800, 1000, 14.0, 13.0);
+        let mut steady = |app: &mut EditorApp| {
+            for _ in 0..3 {
+                app.workspace.update_unfocused_pane();
+                app.render(&mut draw);
+            }
+            alloc_count::count(|| app.render(&mut draw)).1
+        };
+        let single = steady(&mut app);
+        // The other pane's gutter layout is all its view costs a frame
+        app.workspace.split();
+        assert_eq!(steady(&mut app), single + 1);
+    }
+
+    #[test]
+    fn test_steady_frame_allocations_dont_grow_with_lines() {
+        let short = steady_frame_allocations(10);
+        let tall = steady_frame_allocations(80);
+        assert_eq!(short, tall, "drawing allocates for every line");
+        assert!(tall <= 8, "a steady frame made {} allocations", tall);
+    }
+
+    #[test]
+    fn test_split_panes() {
+        let mut state = AppState::new(EditorApp::new(14.0));
+        state.app.viewport_width = 800.0;
+        assert_eq!(state.app.pane_area(800.0), (0.0, 800.0));
+
+        // Ctrl+\\ splits, focusing the right half
+        state.app.input_handler.update_modifiers_state(ModifiersState::CONTROL);
+        state.handle_key_press(&Key::Character("\\".into()));
+        assert!(state.app.workspace.is_split());
+        assert_eq!(state.app.pane_area(800.0), (400.0, 400.0));
+        assert_eq!(state.app.pane_at(100.0), PaneSide::Left);
+        assert_eq!(state.app.pane_at(500.0), PaneSide::Right);
+        let (text_left, text_width) = state.app.text_area(8.0, 800.0);
+        assert!(text_left > 400.0 && text_left + text_width <= 800.0);
+
+        // Alt+Left moves the focus; zen mode shows only the focused pane
+        state.app.input_handler.update_modifiers_state(ModifiersState::ALT);
+        state.handle_key_press(&Key::Named(NamedKey::ArrowLeft));
+        assert_eq!(state.app.workspace.focused_side(), PaneSide::Left);
+        assert_eq!(state.app.pane_area(800.0), (0.0, 400.0));
+        command_registry().execute(&mut state, &EditorCommand::ToggleZenMode);
+        assert_eq!(state.app.pane_area(800.0), (0.0, 800.0));
+        command_registry().execute(&mut state, &EditorCommand::ToggleZenMode);
+
+        command_registry().execute(&mut state, &EditorCommand::ToggleSplit);
+        assert!(!state.app.workspace.is_split());
+        assert_eq!(state.app.pane_area(800.0), (0.0, 800.0));
+    }
+
+    /// Cancels every system dialog, recording which were shown.
+    struct CancelledDialogs {
+        shown: Rc<RefCell<Vec<&'static str>>>,
+    }
+
+    impl SystemDialogs for CancelledDialogs {
+        fn pick_file(&mut self, _dialog: rfd::FileDialog) -> Option<PathBuf> {
+            self.shown.borrow_mut().push("open");
+            None
+        }
+
+        fn save_file(&mut self, _dialog: rfd::FileDialog) -> Option<PathBuf> {
+            self.shown.borrow_mut().push("save");
+            None
+        }
+
+        fn ask(&mut self, _dialog: rfd::MessageDialog) -> rfd::MessageDialogResult {
+            self.shown.borrow_mut().push("ask");
+            rfd::MessageDialogResult::Cancel
+        }
+    }
+
+    /// Answers every question with `answer`, cancelling file dialogs.
+    struct AnsweringDialogs {
+        answer: rfd::MessageDialogResult,
+    }
+
+    impl SystemDialogs for AnsweringDialogs {
+        fn pick_file(&mut self, _dialog: rfd::FileDialog) -> Option<PathBuf> {
+            None
+        }
+
+        fn save_file(&mut self, _dialog: rfd::FileDialog) -> Option<PathBuf> {
+            None
+        }
+
+        fn ask(&mut self, _dialog: rfd::MessageDialog) -> rfd::MessageDialogResult {
+            self.answer.clone()
+        }
+    }
+
+    #[test]
+    fn test_saving_on_close_waits_for_the_write() {
+        let dir = std::env::temp_dir().join(format!("cp_editor_save_on_close_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.txt");
+        fs::write(&path, "old\n").unwrap();
+        let mut app = EditorApp::new(14.0);
+        app.workspace.open_file(&path).unwrap();
+        app.workspace.active_editor_mut().unwrap().insert_text("new ");
+        let mut state = AppState::new(app);
+        state.dialogs = Box::new(AnsweringDialogs { answer: rfd::MessageDialogResult::Yes });
+        let message = |state: &AppState| state.app.notifications.visible().next().map(|n| n.message.clone());
+
+        // The file is written before its tab closes
+        command_registry().execute(&mut state, &EditorCommand::CloseTab);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new old\n");
+        assert_eq!(state.app.workspace.tab_count(), 1);
+        assert_eq!(state.app.workspace.active_editor().unwrap().file_path(), None);
+        assert_eq!(message(&state).as_deref(), Some("Saved: notes.txt"));
+
+        // A failed write is reported and keeps the tab open
+        state.app.workspace.open_file(&path).unwrap();
+        state.app.workspace.active_editor_mut().unwrap().insert_text("newer ");
+        fs::remove_dir_all(&dir).unwrap();
+        command_registry().execute(&mut state, &EditorCommand::CloseTab);
+        assert_eq!(state.app.workspace.active_editor().unwrap().file_path(), Some(path.as_path()));
+        assert!(message(&state).is_some_and(|m| m.starts_with("Failed to save notes.txt")));
+    }
+
+    #[test]
+    fn test_ime_text_waits_for_loading_file() {
+        let path = std::env::temp_dir().join(format!("cp_editor_ime_loading_{}.txt", std::process::id()));
+        fs::write(&path, "line\n".repeat(50_000)).unwrap();
+        let mut state = AppState::new(EditorApp::new(14.0));
+        fn editor(state: &mut AppState) -> &mut Editor {
+            state.app.workspace.active_editor_mut().unwrap()
+        }
+        editor(&mut state).open_file_streaming_from(&path, 0).unwrap();
+
+        // Committed text is turned away like typing while the file loads
+        state.commit_ime_text("日本");
+        assert!(!editor(&mut state).is_modified());
+        let message = state.app.notifications.visible().next().map(|n| n.message.clone());
+        assert_eq!(message.as_deref(), Some("The file is still loading; it can be edited once it has"));
+
+        while !editor(&mut state).is_fully_loaded() {
+            editor(&mut state).poll_loading().unwrap();
+            std::thread::yield_now();
+        }
+        state.commit_ime_text("日本");
+        assert!(editor(&mut state).buffer().to_string().starts_with("日本line\n"));
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_typing_during_save_as_leaves_buffer_alone() {
+        let mut app = EditorApp::new(14.0);
+        app.workspace.active_editor_mut().unwrap().insert_text("draft");
+        let mut state = AppState::new(app);
+        let text = |state: &AppState| state.app.workspace.active_editor().unwrap().buffer().to_string();
+
+        // The Save As dialog's own event loop hands the window the file
+        // name and Enter typed into it: they go nowhere
+        state.dialog.open(Modal::Dialog);
+        for key in ["n", "o", "t", "e", "s"] {
+            assert!(!state.handle_key_press(&Key::Character(key.into())));
+        }
+        assert!(!state.handle_key_press(&Key::Named(NamedKey::Backspace)));
+        assert!(!state.handle_key_press(&Key::Named(NamedKey::Enter)));
+        state.dialog.close(Instant::now());
+        state.finish_dialog();
+        assert_eq!(text(&state), "draft");
+
+        // Once the event loop caught up, typing goes to the buffer
+        state.handle_key_press(&Key::Character("!".into()));
+        assert_eq!(text(&state), "draft!");
+    }
+
+    #[test]
+    fn test_typing_after_unsaved_changes_prompt_reaches_buffer() {
+        let mut app = EditorApp::new(14.0);
+        app.workspace.active_editor_mut().unwrap().insert_text("draft");
+        let mut state = AppState::new(app);
+        let shown = Rc::new(RefCell::new(Vec::new()));
+        state.dialogs = Box::new(CancelledDialogs { shown: shown.clone() });
+        let text = |state: &AppState| state.app.workspace.active_editor().unwrap().buffer().to_string();
+
+        // Closing asks about the changes; cancelled, the tab stays
+        command_registry().execute(&mut state, &EditorCommand::CloseTab);
+        assert_eq!(*shown.borrow(), ["ask"]);
+        assert_eq!(state.app.workspace.tab_count(), 1);
+
+        // The Enter that answered it goes nowhere, and text typed right
+        // after it closed waits for the event loop to catch up
+        assert!(!state.handle_key_press(&Key::Named(NamedKey::Enter)));
+        assert!(!state.handle_key_press(&Key::Character("o".into())));
+        assert!(!state.handle_key_press(&Key::Character("k".into())));
+        assert_eq!(text(&state), "draft");
+        state.finish_dialog();
+        assert_eq!(text(&state), "draftok");
+    }
+
+    #[test]
+    fn test_reopen_with_chosen_encoding() {
+        let path = std::env::temp_dir().join(format!("cp_editor_reopen_encoding_{}.txt", std::process::id()));
+        fs::write(&path, "hi\n").unwrap();
+        let mut app = EditorApp::new(14.0);
+        app.workspace.open_file(&path).unwrap();
+        let mut state = AppState::new(app);
+        let message = |state: &AppState| state.app.notifications.visible().next().map(|n| n.message.clone());
+        let encoding = |state: &AppState| state.app.workspace.active_editor().unwrap().encoding();
+
+        // The picker lists every encoding, the current one selected
+        command_registry().execute(&mut state, &EditorCommand::ReopenWithEncoding);
+        assert_eq!(state.app.input_mode, InputMode::Encodings);
+        assert_eq!(state.app.encoding_matches, Encoding::ALL);
+        assert_eq!(state.app.encoding_selected, 0);
+        for key in ["b", "o", "m"] {
+            state.handle_key_press(&Key::Character(key.into()));
+        }
+        assert_eq!(state.app.encoding_matches, [Encoding::Utf8Bom]);
+        state.handle_key_press(&Key::Named(NamedKey::Enter));
+        assert_eq!(state.app.input_mode, InputMode::Normal);
+        assert_eq!(encoding(&state), Encoding::Utf8Bom);
+        assert_eq!(message(&state).as_deref(), Some("Reopened as UTF-8 BOM"));
+
+        // Three bytes aren't UTF-16, so the file stays as it was
+        command_registry().execute(&mut state, &EditorCommand::ReopenWithEncoding);
+        assert_eq!(state.app.encoding_selected, 1);
+        state.handle_key_press(&Key::Named(NamedKey::ArrowDown));
+        state.handle_key_press(&Key::Named(NamedKey::Enter));
+        assert_eq!(encoding(&state), Encoding::Utf8Bom);
+        assert!(message(&state).unwrap().starts_with("Failed to reopen as UTF-16 LE"));
+
+        // Unsaved changes aren't thrown away
+        state.app.workspace.active_editor_mut().unwrap().insert_text("x");
+        command_registry().execute(&mut state, &EditorCommand::ReopenWithEncoding);
+        assert_eq!(state.app.input_mode, InputMode::Normal);
+        assert_eq!(message(&state).as_deref(), Some("Save or undo the changes before reopening"));
+        assert_eq!(state.app.workspace.active_editor().unwrap().buffer().to_string(), "xhi\n");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_save_warns_of_characters_the_encoding_lacks() {
+        let path = std::env::temp_dir().join(format!("cp_editor_save_latin1_{}.txt", std::process::id()));
+        fs::write(&path, b"caf\xe9\n").unwrap();
+        let mut app = EditorApp::new(14.0);
+        app.workspace.open_file(&path).unwrap();
+        app.workspace.active_editor_mut().unwrap().insert_text("\u{20ac}");
+        let mut state = AppState::new(app);
+
+        command_registry().execute(&mut state, &EditorCommand::Save);
+        let notification = state.app.notifications.visible().next().unwrap();
+        assert_eq!(notification.notification_type, NotificationType::Warning);
+        assert_eq!(notification.message, "Latin-1 can't represent '\u{20ac}' (U+20AC); written as '?'");
+        state.app.finish_pending_saves();
+        assert!(!state.app.workspace.active_editor().unwrap().is_modified());
+        assert_eq!(fs::read(&path).unwrap(), b"?caf\xe9\n");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_disk_conflict_banner() {
+        let dir = std::env::temp_dir().join(format!("cp_editor_disk_conflict_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (edited, clean) = (dir.join("edited.txt"), dir.join("clean.txt"));
+        fs::write(&edited, "one\ntwo\n").unwrap();
+        fs::write(&clean, "same\n").unwrap();
+        let mut app = EditorApp::new(14.0);
+        let clean_id = app.workspace.open_file(&clean).unwrap();
+        let edited_id = app.workspace.open_file(&edited).unwrap();
+        app.workspace.set_active_buffer(edited_id);
+        app.workspace.active_editor_mut().unwrap().insert_text("mine ");
+        app.viewport_width = 800.0;
+        let mut state = AppState::new(app);
+        let plain_offset = state.app.content_y_offset();
+        assert!(!state.app.check_disk_changes());
+
+        // Sizes change, so the change shows even with coarse timestamps
+        fs::write(&edited, "one\ntwo\nthree\n").unwrap();
+        fs::write(&clean, "changed\n").unwrap();
+        assert!(state.app.check_disk_changes());
+        // The unmodified buffer just reloads
+        let clean_editor = state.app.workspace.get_buffer(clean_id).unwrap();
+        assert_eq!(clean_editor.buffer().to_string(), "changed\n");
+        assert!(!clean_editor.has_disk_conflict());
+        // The edited one gets the banner, one row over its text only
+        assert!(state.app.workspace.active_editor().unwrap().has_disk_conflict());
+        let banner = state.app.chrome.disk_banner_height();
+        assert_eq!(state.app.content_y_offset(), plain_offset + banner);
+        state.app.workspace.set_active_buffer(clean_id);
+        assert_eq!(state.app.content_y_offset(), plain_offset);
+        state.app.workspace.set_active_buffer(edited_id);
+        assert_eq!(state.app.content_y_offset(), plain_offset + banner);
+        assert!(state.app.is_in_disk_banner(10.0, plain_offset + 1.0));
+        assert!(!state.app.is_in_disk_banner(10.0, plain_offset + banner));
+
+        // Keep mine dismisses it until the file changes again
+        state.app.handle_disk_banner_action(BannerAction::Keep);
+        assert_eq!(state.app.content_y_offset(), plain_offset);
+        assert!(!state.app.check_disk_changes());
+        fs::write(&edited, "one\ntwo\nthree\nfour\n").unwrap();
+        assert!(state.app.check_disk_changes());
+        assert!(state.app.workspace.active_editor().unwrap().has_disk_conflict());
+
+        // Compare opens the diff in a new tab, leaving the conflict as is
+        state.app.handle_disk_banner_action(BannerAction::Compare);
+        let diff = state.app.workspace.active_editor().unwrap().buffer().to_string();
+        assert!(diff.starts_with("--- edited.txt (unsaved)\n+++ edited.txt (on disk)\n"));
+        assert!(diff.contains("\n-mine one\n+one\n"));
+        assert!(diff.contains("\n+four\n"));
+        assert!(state.app.workspace.get_buffer(edited_id).unwrap().has_disk_conflict());
+
+        // Clicking Reload takes the file's text, undoably
+        state.app.workspace.set_active_buffer(edited_id);
+        let reload = disk_banner::action_spans()[1].1.start as f32;
+        let x = state.app.disk_banner_text_left() + (reload + 0.5) * state.app.chrome.char_width;
+        assert!(state.app.handle_disk_banner_click(x));
+        let editor = state.app.workspace.active_editor_mut().unwrap();
+        assert_eq!(editor.buffer().to_string(), "one\ntwo\nthree\nfour\n");
+        assert!(!editor.has_disk_conflict());
+        assert!(!editor.is_modified());
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "mine one\ntwo\n");
+        assert_eq!(state.app.content_y_offset(), plain_offset);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_reopen_recent_file() {
+        let dir = std::env::temp_dir().join(format!("cp_editor_reopen_recent_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (main, lib, gone) = (dir.join("main.rs"), dir.join("lib.rs"), dir.join("gone.rs"));
+        for path in [&main, &lib, &gone] {
+            fs::write(path, "").unwrap();
+        }
+
+        let mut app = EditorApp::new(14.0);
+        assert!(!app.open_recent_files());
+        for path in [&main, &gone, &lib] {
+            let id = app.workspace.open_file(path).unwrap();
+            app.workspace.close_buffer(id);
+        }
+        fs::remove_file(&gone).unwrap();
+
+        // Ctrl+R lists the files that still exist, most recent first
+        let mut state = AppState::new(app);
+        command_registry().execute(&mut state, &EditorCommand::ReopenRecent);
+        let mut app = state.app;
+        assert_eq!(app.input_mode, InputMode::RecentFiles);
+        assert_eq!(app.recent_matches, [lib.clone(), main.clone()]);
+
+        // Typing narrows the list; Enter opens the selected file
+        app.recent_text.set_text("main");
+        app.filter_recent_files();
+        assert_eq!(app.recent_matches, [main.as_path()]);
+        app.open_selected_recent_file();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert_eq!(app.workspace.active_editor().unwrap().file_path(), Some(main.as_path()));
+        assert_eq!(app.workspace.recent_files()[0], main);
+    }
+
+    #[test]
+    fn test_problems_panel() {
+        use cp_editor_core::Diagnostic;
+
+        let dir = std::env::temp_dir().join(format!("cp_editor_problems_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.rs"), dir.join("b.rs"));
+        fs::write(&a, "one\ntwo\nthree\n").unwrap();
+        fs::write(&b, "four\nfive\n").unwrap();
+
+        let mut app = EditorApp::new(14.0);
+        let a_id = app.workspace.open_file(&a).unwrap();
+        app.workspace.active_editor_mut().unwrap().set_diagnostics(vec![
+            Diagnostic::new(2, 1, 2, 3, DiagnosticSeverity::Error, "boom".to_string()),
+            Diagnostic::new(0, 0, 0, 1, DiagnosticSeverity::Hint, "tip".to_string()),
+        ]);
+        app.workspace.open_file(&b).unwrap();
+        let warning = Diagnostic::new(1, 2, 1, 4, DiagnosticSeverity::Warning, "meh".to_string());
+        app.workspace.active_editor_mut().unwrap().set_diagnostics(vec![warning.clone()]);
+
+        let mut state = AppState::new(app);
+        command_registry().execute(&mut state, &EditorCommand::ShowProblems);
+        assert_eq!(state.app.input_mode, InputMode::Problems);
+        assert_eq!(state.app.problems.snapshot().len(), 3);
+
+        // New diagnostics wait for a refresh
+        let error = Diagnostic::new(0, 0, 0, 1, DiagnosticSeverity::Error, "late".to_string());
+        state.app.workspace.active_editor_mut().unwrap().set_diagnostics(vec![warning, error]);
+        assert_eq!(state.app.problems.shown().len(), 3);
+        state.modifiers = ModifiersState::ALT;
+        state.handle_key_press(&Key::Character("r".into()));
+        assert_eq!(state.app.problems.shown().len(), 4);
+
+        // Alt+H hides hints, Alt+G sorts by severity, typing filters
+        state.handle_key_press(&Key::Character("h".into()));
+        state.handle_key_press(&Key::Character("g".into()));
+        state.modifiers = ModifiersState::empty();
+        let messages = |app: &EditorApp| app.problems.shown().iter().map(|e| e.message.clone()).collect::<Vec<_>>();
+        assert_eq!(messages(&state.app), ["boom", "late", "meh"]);
+        state.handle_key_press(&Key::Character("o".into()));
+        assert_eq!(messages(&state.app), ["boom"]);
+
+        // Exports write the filtered list
+        let txt = dir.join("problems.txt");
+        assert_eq!(state.app.export_problems(&txt).unwrap(), 1);
+        let text = fs::read_to_string(&txt).unwrap();
+        assert!(text.ends_with("a.rs:3:2: error: boom\n"), "{}", text);
+        let json = dir.join("problems.json");
+        state.app.export_problems(&json).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json).unwrap()).unwrap();
+        assert_eq!(parsed[0]["line"], 3);
+        assert_eq!(parsed[0]["severity"], "error");
+
+        // Enter goes to the selected diagnostic
+        state.handle_key_press(&Key::Named(NamedKey::Enter));
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(state.app.input_mode, InputMode::Normal);
+        assert_eq!(state.app.workspace.active_buffer_id(), Some(a_id));
+        assert_eq!(state.app.workspace.active_editor().unwrap().cursor_position(), cp_editor_core::Position::new(2, 1));
+    }
+
+    #[test]
+    fn test_multi_cursor_copy_and_paste() {
+        let mut app = EditorApp::new(14.0);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.insert_text("ab ab ab");
+        editor.set_cursor_position(0, 0, false);
+        editor.set_cursor_position(0, 2, true);
+        assert_eq!(editor.select_all_occurrences(), 3);
+
+        let text = app.copy_selections(true).unwrap();
+        assert_eq!(text, "ab\nab\nab");
+        assert_eq!(app.workspace.active_editor().unwrap().buffer().to_string(), "  ");
+
+        // Same number of cursors: each gets its own piece
+        app.paste_text(&text);
+        assert_eq!(app.workspace.active_editor().unwrap().buffer().to_string(), "ab ab ab");
+
+        // Text from elsewhere goes to every cursor as is
+        app.paste_text("x");
+        assert_eq!(app.workspace.active_editor().unwrap().buffer().to_string(), "abx abx abx");
+
+        // Fewer cursors: the whole copied text goes to each one
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.collapse_cursors();
+        editor.move_to_buffer_end(false);
+        app.paste_text(&text);
+        assert_eq!(
+            app.workspace.active_editor().unwrap().buffer().to_string(),
+            "abx abx abxab\nab\nab"
+        );
+
+        // A single-cursor copy forgets the pieces
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.set_cursor_position(0, 0, false);
+        editor.set_cursor_position(0, 1, true);
+        assert_eq!(app.copy_selections(false).as_deref(), Some("a"));
+        assert!(app.cursor_clipboard.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_filter_selection_through_command() {
+        let mut app = EditorApp::new(14.0);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.insert_text("keep\ncherry\napple\nbanana\nkeep");
+        editor.set_cursor_position(1, 0, false);
+        editor.set_cursor_position(3, 6, true);
+
+        // sort's trailing newline is dropped since the selection had none
+        assert!(app.filter_selection_through_command("sort"));
+        let editor = app.workspace.active_editor_mut().unwrap();
+        assert_eq!(editor.buffer().to_string(), "keep\napple\nbanana\ncherry\nkeep");
+        assert_eq!(editor.get_selected_text().as_deref(), Some("apple\nbanana\ncherry"));
+
+        // Filtering the result again through a stub that reverses the lines
+        assert!(app.filter_selection_through_command("sed '1!G;h;$!d'"));
+        let editor = app.workspace.active_editor_mut().unwrap();
+        assert_eq!(editor.buffer().to_string(), "keep\ncherry\nbanana\napple\nkeep");
+
+        // One undo step per filter
+        editor.undo();
+        assert_eq!(editor.buffer().to_string(), "keep\napple\nbanana\ncherry\nkeep");
+
+        // A failing command leaves the text alone
+        assert!(!app.filter_selection_through_command("echo partial; exit 1"));
+        assert_eq!(
+            app.workspace.active_editor().unwrap().buffer().to_string(),
+            "keep\napple\nbanana\ncherry\nkeep"
+        );
+    }
+
+    #[test]
+    fn test_wait_for_buffer() {
+        let dir = std::env::temp_dir().join(format!("cp_editor_wait_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("COMMIT_EDITMSG");
+        fs::write(&path, "\n# Please enter the commit message").unwrap();
+
+        // Closed without saving: git should abort
+        let mut app = EditorApp::new(14.0);
+        let id = app.workspace.open_file(&path).unwrap();
+        app.workspace.set_active(id);
+        assert!(app.wait_for_active_buffer());
+        app.handle_workspace_events();
+        assert!(!app.wait_finished());
+        app.workspace.close_buffer(id);
+        app.handle_workspace_events();
+        assert!(app.wait_finished());
+        assert_eq!(app.exit_code(), 1);
+
+        // Saved, then closed
+        let mut app = EditorApp::new(14.0);
+        let id = app.workspace.open_file(&path).unwrap();
+        app.workspace.set_active(id);
+        app.wait_for_active_buffer();
+        app.workspace.active_editor_mut().unwrap().insert_text("Fix the bug");
+        app.save_in_background(id).unwrap();
+        app.finish_pending_saves();
+        // Closing other files doesn't end the wait
+        let other = app.workspace.new_buffer();
+        app.workspace.close_buffer(other);
+        app.handle_workspace_events();
+        assert!(!app.wait_finished());
+        app.workspace.close_buffer(id);
+        app.handle_workspace_events();
+        assert!(app.wait_finished());
+        assert_eq!(app.exit_code(), 0);
+        assert!(fs::read_to_string(&path).unwrap().starts_with("Fix the bug"));
+
+        // Without --wait the exit code is always 0
+        assert_eq!(EditorApp::new(14.0).exit_code(), 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_zoom_keeps_chrome_size() {
+        let mut app = EditorApp::new(14.0);
+        let chrome = app.chrome;
+        let status_bar_height = chrome.status_bar_height();
+
+        assert!(app.set_font_size(22.0));
+        assert_eq!(app.font_size, 22.0);
+        assert_eq!(app.chrome, chrome);
+        assert_eq!(app.chrome.status_bar_height(), status_bar_height);
+        assert_eq!(app.content_y_offset(), chrome.tab_bar_height());
+
+        // Zooming is clamped
+        app.set_font_size(1000.0);
+        assert_eq!(app.font_size, MAX_FONT_SIZE);
+        assert!(!app.set_font_size(MAX_FONT_SIZE + 1.0));
+
+        // The chrome is laid out from the UI font alone
+        let ui = ChromeMetrics::from_atlas(&GlyphAtlas::new(DEFAULT_UI_FONT_SIZE));
+        assert_eq!(ui, chrome);
+        assert!(ui.status_bar_height() > ui.line_height);
+        assert!(ui.search_bar_height() > ui.input_field_height());
+    }
+
+    #[test]
+    fn test_zen_mode_layout() {
+        let mut app = EditorApp::new(14.0);
+        let (char_width, line_height) = (10.0, 20.0);
+        app.workspace.active_editor_mut().unwrap().insert_text("let zen = true;");
+        app.viewport_width = 1200.0;
+        app.zen.max_columns = 80;
+        let gutter_width = app.gutter_layout(char_width).width();
+        assert_eq!(app.text_area(char_width, 1200.0), (gutter_width, 1200.0 - gutter_width));
+
+        // The text is centered at the max width, without chrome or gutter
+        assert!(app.toggle_zen_mode());
+        assert_eq!(app.text_area(char_width, 1200.0), (200.0, 800.0));
+        assert_eq!(app.text_area(char_width, 600.0), (0.0, 600.0));
+        assert_eq!(app.content_y_offset(), 0.0);
+        assert_eq!(app.status_bar_height(), 0.0);
+        assert!(!app.is_in_tab_bar(1.0));
+        assert!(!app.cursor_blink.is_enabled());
+        let y = 0.5 * line_height;
+        assert_eq!(app.screen_to_buffer_position(200.0 + 4.0 * char_width, y, char_width, line_height), (0, 4));
+        assert_eq!(app.screen_to_buffer_position(100.0, y, char_width, line_height), (0, 0));
+
+        // Turning it off brings the previous layout back
+        assert!(!app.toggle_zen_mode());
+        assert_eq!(app.text_area(char_width, 1200.0), (gutter_width, 1200.0 - gutter_width));
+        assert_eq!(app.content_y_offset(), app.chrome.tab_bar_height());
+        assert_eq!(app.status_bar_height(), app.chrome.status_bar_height());
+        assert!(app.cursor_blink.is_enabled());
+    }
+
+    #[test]
+    fn test_click_dismisses_notification() {
+        let mut app = EditorApp::new(14.0);
+        app.apply_settings(Settings::parse(r#"{ "notifications": { "sticky_errors": true } }"#).unwrap());
+        app.notifications.error("Build failed");
+        app.notifications.success("Saved");
+        let now = Instant::now();
+        let viewport_width = 1000.0;
+        let x = viewport_width - NOTIFICATION_MARGIN - NOTIFICATION_WIDTH / 2.0;
+        let first = app.tab_bar_height() + NOTIFICATION_MARGIN + 1.0;
+        let second = first + app.notification_height() + NOTIFICATION_MARGIN;
+        assert_eq!(app.notification_at(x, first, viewport_width, now), Some(0));
+        assert_eq!(app.notification_at(x, second, viewport_width, now), Some(1));
+        assert_eq!(app.notification_at(10.0, first, viewport_width, now), None);
+
+        // The sticky error outlives the other, until clicked
+        app.notifications.update(now + Duration::from_secs(60));
+        assert_eq!(app.notification_at(x, second, viewport_width, now), None);
+        assert!(app.dismiss_notification_at(x, first, viewport_width));
+        assert!(!app.notifications.has_notifications());
+        assert!(!app.dismiss_notification_at(x, first, viewport_width));
+    }
+
+    #[test]
+    fn test_max_content_width_centers_text() {
+        let mut app = EditorApp::new(14.0);
+        let (char_width, line_height) = (10.0, 20.0);
+        app.workspace.active_editor_mut().unwrap().insert_text("let padded = true;");
+        app.viewport_width = 1200.0;
+        let gutter_width = app.gutter_layout(char_width).width();
+        assert_eq!(app.content_x_offset(char_width), 0.0);
+
+        // Gutter and 60 columns of text are centered
+        app.max_content_width = Some(60);
+        let padding = (1200.0 - gutter_width - 600.0) / 2.0;
+        assert_eq!(app.content_x_offset(char_width), padding);
+        assert_eq!(app.text_area(char_width, 1200.0), (padding + gutter_width, 600.0));
+
+        // Hit-testing subtracts the padding
+        let text_left = padding + gutter_width;
+        let y = app.content_y_offset() + 0.5 * line_height;
+        assert_eq!(app.screen_to_buffer_position(text_left + 4.0 * char_width, y, char_width, line_height), (0, 4));
+        assert_eq!(app.screen_to_buffer_position(text_left + 4.4 * char_width, y, char_width, line_height), (0, 4));
+        assert_eq!(app.screen_to_buffer_position(text_left + 4.6 * char_width, y, char_width, line_height), (0, 5));
+        // In the padding or the gutter clicks go to the start of the line
+        assert_eq!(app.screen_to_buffer_position(padding / 2.0, y, char_width, line_height), (0, 0));
+        assert_eq!(app.screen_to_buffer_position(text_left - 1.0, y, char_width, line_height), (0, 0));
+        // Right of the text column clicks go to the end of the line
+        assert_eq!(app.screen_to_buffer_position(1190.0, y, char_width, line_height), (0, 18));
+        assert_eq!(app.drag_position(text_left + 4.0 * char_width, y, char_width, line_height, false), (0, 4));
+
+        // A window narrower than the max width gets no padding
+        app.viewport_width = 500.0;
+        assert_eq!(app.content_x_offset(char_width), 0.0);
+        assert_eq!(app.text_area(char_width, 500.0), (gutter_width, 500.0 - gutter_width));
+
+        // Zen mode keeps its own column
+        app.viewport_width = 1200.0;
+        app.zen.max_columns = 80;
+        app.toggle_zen_mode();
+        assert_eq!(app.content_x_offset(char_width), 200.0);
+        assert_eq!(app.text_area(char_width, 1200.0), (200.0, 800.0));
+    }
+
+    #[test]
+    fn test_lsp_document_lifecycle() {
+        use cp_editor_lsp::{LspClient, LspRequest};
+
+        let dir = std::env::temp_dir().join(format!("cp_editor_lsp_lifecycle_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let old_path = dir.join("old.rs");
+        let new_path = dir.join("new.rs");
+        fs::write(&old_path, "fn main() {}").unwrap();
+
+        let mut app = EditorApp::new(14.0);
+        let (client, requests) = LspClient::detached();
+        app.lsp_manager.insert_client("rust", client);
+
+        let id = app.workspace.open_file(&old_path).unwrap();
+        app.workspace.set_active(id);
+        app.handle_workspace_events();
+        app.workspace.save_active_as(&new_path).unwrap();
+        app.handle_workspace_events();
+        app.workspace.close_active_buffer();
+        app.handle_workspace_events();
+        let _ = fs::remove_dir_all(&dir);
+
+        let sent: Vec<String> = requests
+            .try_iter()
+            .filter_map(|request| match request {
+                LspRequest::DidOpen { path, .. } => Some(format!("open {}", path.display())),
+                LspRequest::DidClose { path } => Some(format!("close {}", path.display())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                format!("open {}", old_path.display()),
+                format!("close {}", old_path.display()),
+                format!("open {}", new_path.display()),
+                format!("close {}", new_path.display()),
+            ]
+        );
+        assert!(!app.lsp_manager.is_open(&new_path));
+    }
+
+    #[test]
+    fn test_untrusted_workspace_holds_servers_back() {
+        use cp_editor_lsp::{LspClient, LspRequest};
+
+        let dir = std::env::temp_dir().join(format!("cp_editor_lsp_trust_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Cargo.toml"), "").unwrap();
+        let path = dir.join("main.rs");
+        fs::write(&path, "fn main() {}").unwrap();
+
+        // Nothing is started in an untrusted folder, and the user is asked
+        let mut app = EditorApp::new(14.0);
+        let id = app.workspace.open_file(&path).unwrap();
+        app.workspace.set_active(id);
+        app.handle_workspace_events();
+        assert!(!app.lsp_manager.has_client("rust"));
+        assert!(!app.lsp_manager.is_open(&path));
+        let message = app.notifications.visible().next().map(|n| n.message.clone()).unwrap();
+        assert!(message.contains("Trust Workspace"), "{}", message);
+
+        // Trusting it opens the open files on the server
+        let (client, requests) = LspClient::detached();
+        app.lsp_manager.insert_client("rust", client);
+        app.trust_workspace();
+        assert!(app.lsp_manager.is_workspace_trusted());
+        assert!(app.trust.is_trusted(&dir));
+        assert!(app.lsp_manager.is_open(&path));
+        let _ = fs::remove_dir_all(&dir);
+        let opened = requests.try_iter().any(|request| matches!(request, LspRequest::DidOpen { .. }));
+        assert!(opened);
+    }
+
+    #[test]
+    fn test_excluded_file_makes_no_lsp_calls() {
+        use cp_editor_lsp::LspClient;
+
+        let dir = std::env::temp_dir().join(format!("cp_editor_lsp_excluded_{}", std::process::id()));
+        fs::create_dir_all(dir.join("gen")).unwrap();
+        let path = dir.join("gen").join("big.rs");
+        fs::write(&path, "fn main() {}").unwrap();
+
+        let mut app = EditorApp::new(14.0);
+        app.apply_settings(Settings::parse(r#"{ "lsp": { "exclude_globs": ["gen/*.rs"] } }"#).unwrap());
+        app.lsp_manager.set_workspace_root(Some(dir.clone()));
+        let (client, requests) = LspClient::detached();
+        app.lsp_manager.insert_client("rust", client);
+        let id = app.workspace.open_file(&path).unwrap();
+        app.workspace.set_active(id);
+        app.handle_workspace_events();
+        assert!(app.active_file_lsp_excluded());
+
+        app.workspace.active_editor_mut().unwrap().insert_text("x");
+        app.notify_lsp_document_change();
+        app.flush_pending_lsp_changes(true);
+        app.request_hover();
+        app.trigger_completion();
+        app.request_signature_help();
+        app.request_goto_definition();
+        assert!(!app.lsp_manager.is_open(&path));
+        assert_eq!(requests.try_iter().count(), 0);
+        // Like a file without a server, nothing says the server lacks a feature
+        assert_eq!(app.notifications.visible().count(), 0);
+
+        // Re-enabling it for the session opens it on the server
+        app.enable_lsp_for_active_file();
+        assert!(!app.active_file_lsp_excluded());
+        assert!(app.lsp_manager.is_open(&path));
+        app.request_hover();
+        assert_eq!(requests.try_iter().count(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_signature_help_lifecycle() {
+        use cp_editor_core::SignatureHelp;
+        use cp_editor_lsp::{LspClient, LspRequest};
+
+        let dir = std::env::temp_dir().join(format!("cp_editor_signature_help_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.rs");
+        fs::write(&path, "").unwrap();
+
+        let mut app = EditorApp::new(14.0);
+        let (client, requests) = LspClient::detached();
+        app.lsp_manager.insert_client("rust", client);
+        let id = app.workspace.open_file(&path).unwrap();
+        app.workspace.set_active(id);
+        app.handle_workspace_events();
+        let _ = fs::remove_dir_all(&dir);
+
+        app.workspace.active_editor_mut().unwrap().insert_text("add(");
+        app.notify_lsp_document_change();
+        app.request_signature_help();
+        // The typed parenthesis reaches the server before the request
+        let sent: Vec<String> = requests
+            .try_iter()
+            .filter_map(|request| match request {
+                LspRequest::DidChange { text, .. } => Some(format!("change {}", text)),
+                LspRequest::SignatureHelp { position, .. } => Some(format!("help {}:{}", position.line, position.character)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sent, ["change add(", "help 0:4"]);
+
+        let help = SignatureHelp {
+            label: "fn add(a: i32, b: i32) -> i32".to_string(),
+            active_parameter: Some(7..13),
+        };
+        app.handle_lsp_event(LspEvent::SignatureHelp { path: path.clone(), help: Some(help.clone()) });
+        assert_eq!(app.workspace.active_editor().unwrap().signature_help(), Some(&help));
+
+        // Further arguments keep the popup where the call starts
+        app.workspace.active_editor_mut().unwrap().insert_text("1, ");
+        app.request_signature_help();
+        app.update_popup_anchors();
+        assert_eq!(app.signature_anchor.as_ref().map(|anchor| (anchor.line, anchor.col)), Some((0, 4)));
+
+        // Escape dismisses it before collapsing cursors, and a late
+        // response doesn't bring it back
+        app.close_input_bar();
+        assert!(app.signature_anchor.is_none());
+        assert!(app.workspace.active_editor().unwrap().signature_help().is_none());
+        app.handle_lsp_event(LspEvent::SignatureHelp { path: path.clone(), help: Some(help.clone()) });
+        assert!(app.workspace.active_editor().unwrap().signature_help().is_none());
+
+        // Outside a call the server has no help to give
+        app.request_signature_help();
+        app.handle_lsp_event(LspEvent::SignatureHelp { path, help: None });
+        assert!(app.signature_anchor.is_none());
+    }
+
+    #[test]
+    fn test_completion_filters_as_you_type() {
+        use cp_editor_core::CompletionItem;
+        use cp_editor_lsp::{LspClient, LspRequest};
+
+        let dir = std::env::temp_dir().join(format!("cp_editor_completion_filter_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.rs");
+        fs::write(&path, "").unwrap();
+
+        let mut app = EditorApp::new(14.0);
+        let (client, requests) = LspClient::detached();
+        app.lsp_manager.insert_client("rust", client);
+        let id = app.workspace.open_file(&path).unwrap();
+        app.workspace.set_active(id);
+        let _ = fs::remove_dir_all(&dir);
+
+        let item = |label: &str| CompletionItem {
+            label: label.to_string(),
+            kind: None,
+            detail: None,
+            insert_text: None,
+            is_snippet: false,
+        };
+        let shown = |app: &EditorApp| {
+            let editor = app.workspace.active_editor().unwrap();
+            editor.completions().iter().map(|item| item.label.clone()).collect::<Vec<_>>()
+        };
+        let completion_requests = || requests.try_iter().filter(|r| matches!(r, LspRequest::Completion { .. })).count();
+
+        app.workspace.active_editor_mut().unwrap().insert_text("v.");
+        app.trigger_completion();
+        assert_eq!(completion_requests(), 1);
+        // The response arrives after `m` was typed
+        app.workspace.active_editor_mut().unwrap().insert_text("m");
+        let items = vec![item("len"), item("flat_map"), item("map"), item("iter_mut")];
+        app.handle_lsp_event(LspEvent::Completion { path: path.clone(), items });
+        assert!(app.completion_visible);
+        assert_eq!(shown(&app), ["map", "flat_map", "iter_mut"]);
+
+        // The selection follows its item while the list narrows
+        app.completion_next();
+        app.workspace.active_editor_mut().unwrap().insert_text("a");
+        app.refilter_completions();
+        assert_eq!(shown(&app), ["map", "flat_map"]);
+        assert_eq!(app.completion_selected, 1);
+        app.workspace.active_editor_mut().unwrap().insert_text("p");
+        app.refilter_completions();
+        assert_eq!(shown(&app), ["map", "flat_map"]);
+        assert_eq!(app.completion_selected, 1);
+        // Backspacing widens it again
+        app.workspace.active_editor_mut().unwrap().delete_backward();
+        app.workspace.active_editor_mut().unwrap().delete_backward();
+        app.refilter_completions();
+        assert_eq!(shown(&app), ["map", "flat_map", "iter_mut"]);
+        assert_eq!(completion_requests(), 0);
+
+        // Nothing matching asks the server again
+        app.workspace.active_editor_mut().unwrap().insert_text("z");
+        app.refilter_completions();
+        assert!(!app.completion_visible);
+        assert_eq!(completion_requests(), 1);
+        assert!(app.completion_anchor.is_some());
+
+        // Deleting past the trigger position hides the list
+        app.workspace.active_editor_mut().unwrap().delete_backward();
+        app.workspace.active_editor_mut().unwrap().delete_backward();
+        app.workspace.active_editor_mut().unwrap().delete_backward();
+        app.refilter_completions();
+        assert!(app.completion_anchor.is_none());
+        assert!(shown(&app).is_empty());
+    }
+
+    #[test]
+    fn test_code_actions_menu() {
+        use crate::code_actions::CodeActionEntry;
+        use cp_editor_core::Diagnostic;
+        use cp_editor_lsp::{LspClient, LspRequest};
+        use serde_json::json;
+
+        let dir = std::env::temp_dir().join(format!("cp_editor_code_actions_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.rs");
+        fs::write(&path, "let map = HashMap::new();\n").unwrap();
+
+        let mut app = EditorApp::new(14.0);
+        let id = app.workspace.open_file(&path).unwrap();
+        app.workspace.set_active(id);
+        let _ = fs::remove_dir_all(&dir);
+        let no_actions = |app: &EditorApp| {
+            app.notifications.visible().filter(|n| n.message == "No code actions available").count()
+        };
+
+        // Without a server there is nothing to ask
+        app.request_code_actions();
+        assert_eq!(no_actions(&app), 1);
+        assert!(app.code_action_anchor.is_none());
+
+        let (client, requests) = LspClient::detached();
+        app.lsp_manager.insert_client("rust", client);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.set_diagnostics(vec![
+            Diagnostic::new(0, 10, 0, 17, DiagnosticSeverity::Error, "use of undeclared type `HashMap`".to_string()),
+            Diagnostic::new(0, 4, 0, 7, DiagnosticSeverity::Warning, "unused variable: `map`".to_string()),
+        ]);
+        editor.set_cursor_position(0, 12, false);
+
+        // The request is for the cursor, with the diagnostics under it
+        app.request_code_actions();
+        let (range, diagnostics) = requests
+            .try_iter()
+            .find_map(|request| match request {
+                LspRequest::CodeAction { range, diagnostics, .. } => Some((range, diagnostics)),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(range, Range::new(Position::new(0, 12), Position::new(0, 12)));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "use of undeclared type `HashMap`");
+
+        // Nothing offered
+        app.handle_lsp_event(LspEvent::CodeActions { path: path.clone(), actions: Vec::new() });
+        assert!(app.code_actions.is_none());
+        assert_eq!(no_actions(&app), 2);
+
+        let import = CodeActionEntry {
+            title: "Import `HashMap`".to_string(),
+            edits: vec![(path.clone(), vec![(0, 0, 0, 0, "use std::collections::HashMap;\n".to_string())])],
+            command: None,
+            arguments: Vec::new(),
+        };
+        let run = CodeActionEntry {
+            title: "Run".to_string(),
+            edits: Vec::new(),
+            command: Some("test.run".to_string()),
+            arguments: vec![json!(1)],
+        };
+
+        // Picking an action applies its edit
+        app.request_code_actions();
+        app.handle_lsp_event(LspEvent::CodeActions { path: path.clone(), actions: vec![run.clone(), import.clone()] });
+        app.code_actions.as_mut().unwrap().select_next();
+        app.apply_selected_code_action();
+        assert!(app.code_actions.is_none() && app.code_action_anchor.is_none());
+        let text = app.workspace.active_editor().unwrap().text();
+        assert_eq!(text, "use std::collections::HashMap;\nlet map = HashMap::new();\n");
+
+        // or runs its command on the server
+        app.request_code_actions();
+        app.handle_lsp_event(LspEvent::CodeActions { path: path.clone(), actions: vec![run] });
+        app.apply_selected_code_action();
+        let executed = requests.try_iter().find_map(|request| match request {
+            LspRequest::ExecuteCommand { command, arguments, .. } => Some((command, arguments)),
+            _ => None,
+        });
+        assert_eq!(executed, Some(("test.run".to_string(), vec![json!(1)])));
+
+        // Actions arriving after the menu was dismissed are dropped
+        app.request_code_actions();
+        app.hide_code_actions();
+        app.handle_lsp_event(LspEvent::CodeActions { path, actions: vec![import] });
+        assert!(app.code_actions.is_none());
+    }
+
+    #[test]
+    fn test_snippet_completion_tab_stops() {
+        let mut app = EditorApp::new(14.0);
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.insert_text("v.");
+        let anchor = PopupAnchor::new(editor.buffer(), 0, 2);
+        editor.insert_text("ma");
+        editor.set_completions(vec![CompletionItem {
+            label: "map".to_string(),
+            kind: None,
+            detail: None,
+            insert_text: Some("map(|${1:x}| ${2:x})$0".to_string()),
+            is_snippet: true,
+        }]);
+        app.completion_anchor = Some(anchor);
+        app.completion_visible = true;
+        app.accept_completion();
+
+        let editor = app.workspace.active_editor().unwrap();
+        assert_eq!(editor.buffer().to_string(), "v.map(|x| x)");
+        assert_eq!(editor.selected_text().as_deref(), Some("x"));
+
+        // Tab and Shift+Tab move between the tab stops instead of indenting
+        let mut state = AppState::new(app);
+        let registry = command_registry();
+        let selected = |state: &AppState| state.app.workspace.active_editor().unwrap().cursor_position().col;
+        registry.execute(&mut state, &EditorCommand::InsertChar('\t'));
+        assert_eq!(selected(&state), 11);
+        registry.execute(&mut state, &EditorCommand::Dedent);
+        assert_eq!(selected(&state), 8);
+        registry.execute(&mut state, &EditorCommand::InsertChar('\t'));
+        registry.execute(&mut state, &EditorCommand::InsertChar('\t'));
+        assert_eq!(selected(&state), 12);
+
+        // Past the last tab stop Tab indents again
+        registry.execute(&mut state, &EditorCommand::InsertChar('\t'));
+        assert_eq!(state.app.workspace.active_editor().unwrap().buffer().to_string(), "v.map(|x| x)    ");
+    }
+
+    #[test]
+    fn test_stale_diagnostics() {
+        let dir = std::env::temp_dir().join(format!("cp_editor_stale_diagnostics_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.rs");
+        fs::write(&path, "fn main() {}\n").unwrap();
+
+        let mut app = EditorApp::new(14.0);
+        let id = app.workspace.open_file(&path).unwrap();
+        app.workspace.set_active(id);
+        let _ = fs::remove_dir_all(&dir);
+        app.stale_diagnostics_after = Duration::ZERO;
+        let tick = || std::thread::sleep(Duration::from_millis(2));
+        let stale = |app: &EditorApp| app.workspace.active_editor().unwrap().diagnostics_stale();
+        let publish = |app: &mut EditorApp| {
+            let diagnostic = cp_editor_core::Diagnostic::new(0, 3, 0, 7, DiagnosticSeverity::Warning, "unused".to_string());
+            app.handle_lsp_event(LspEvent::Diagnostics { path: path.clone(), diagnostics: vec![diagnostic] });
+            app.poll_lsp();
+        };
+
+        publish(&mut app);
+        assert!(!stale(&app));
+
+        // An edit after the diagnostics makes them stale until the server
+        // publishes for the new text
+        tick();
+        app.workspace.active_editor_mut().unwrap().insert_text("x");
+        app.notify_lsp_document_change();
+        assert!(stale(&app));
+        tick();
+        app.flush_pending_lsp_changes(true);
+        app.poll_lsp();
+        assert!(stale(&app));
+        tick();
+        publish(&mut app);
+        assert!(!stale(&app));
+    }
+
+    #[test]
+    fn test_reverted_edit_sends_no_change() {
+        let dir = std::env::temp_dir().join(format!("cp_editor_noop_change_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.rs");
+        fs::write(&path, "fn main() {}\n").unwrap();
+
+        let mut app = EditorApp::new(14.0);
+        let id = app.workspace.open_file(&path).unwrap();
+        app.workspace.set_active(id);
+        let _ = fs::remove_dir_all(&dir);
+        app.flush_pending_lsp_changes(true);
+        let version = |app: &EditorApp| app.workspace.active_editor().unwrap().document_version();
+
+        // Typing a character and deleting it again leaves nothing to send
+        let editor = app.workspace.active_editor_mut().unwrap();
+        editor.insert_text("x");
+        editor.delete_backward();
+        app.notify_lsp_document_change();
+        app.flush_pending_lsp_changes(true);
+        assert_eq!(version(&app), 0);
+
+        // A real change is sent once
+        app.workspace.active_editor_mut().unwrap().insert_text("y");
+        app.notify_lsp_document_change();
+        app.flush_pending_lsp_changes(true);
+        assert_eq!(version(&app), 1);
+        app.notify_lsp_document_change();
+        app.flush_pending_lsp_changes(true);
+        assert_eq!(version(&app), 1);
+    }
+
+    #[test]
+    fn test_server_capabilities_gate_requests() {
+        use cp_editor_lsp::{Capabilities, LspClient, LspRequest, PositionEncoding};
+
+        let dir = std::env::temp_dir().join(format!("cp_editor_lsp_capabilities_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.rs");
+        fs::write(&path, "").unwrap();
+
+        let mut app = EditorApp::new(14.0);
+        let (client, requests) = LspClient::detached();
+        app.lsp_manager.insert_client("rust", client);
+        let id = app.workspace.open_file(&path).unwrap();
+        app.workspace.set_active(id);
+        let _ = fs::remove_dir_all(&dir);
+        let type_char = |app: &mut EditorApp, ch: char| {
+            app.workspace.active_editor_mut().unwrap().insert_char(ch);
+            app.notify_lsp_document_change();
+            app.typed_char(ch);
+        };
+
+        // Until the server said what it supports, everything is assumed to be
+        assert!(app.lsp_manager.supports_rename("rust"));
+        assert!(!app.lsp_manager.supports_rename("python"));
+
+        app.lsp_manager.set_capabilities(
+            "rust",
+            Capabilities {
+                completion: true,
+                completion_trigger_characters: vec![".".to_string()],
+                signature_help: true,
+                signature_help_trigger_characters: vec!["(".to_string()],
+                hover: true,
+                ..Capabilities::default()
+            },
+        );
+        assert_eq!(app.lsp_manager.completion_trigger_characters("rust"), ["."]);
+        assert!(!app.lsp_manager.supports_formatting("rust"));
+        assert_eq!(app.lsp_manager.position_encoding("rust"), PositionEncoding::Utf16);
+
+        // Unsupported requests aren't sent, and the user is told why
+        let _ = requests.try_iter().count();
+        app.open_rename();
+        assert_eq!(app.input_mode, InputMode::Normal);
+        app.request_goto_definition();
+        let messages: Vec<_> = app.notifications.visible().map(|n| n.message.clone()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Go to definition isn't supported by the rust language server",
+                "Rename isn't supported by the rust language server",
+            ]
+        );
+
+        // The server's trigger characters request completion and signature
+        // help, with positions counted in its encoding
+        type_char(&mut app, '😀');
+        type_char(&mut app, '.');
+        type_char(&mut app, ',');
+        type_char(&mut app, '(');
+        let sent: Vec<_> = requests
+            .try_iter()
+            .filter_map(|request| match request {
+                LspRequest::Completion { position, .. } => Some(("completion", position.character)),
+                LspRequest::SignatureHelp { position, .. } => Some(("signature help", position.character)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sent, [("completion", 3), ("signature help", 5)]);
+        assert!(app.completion_anchor.is_some());
+
+        // Columns from the server are converted back to characters
+        app.lsp_manager.set_capabilities(
+            "rust",
+            Capabilities {
+                position_encoding: PositionEncoding::Utf8,
+                ..Capabilities::default()
+            },
+        );
+        let diagnostic = cp_editor_core::Diagnostic::new(0, 4, 0, 5, DiagnosticSeverity::Error, "expected".to_string());
+        app.handle_lsp_event(LspEvent::Diagnostics { path, diagnostics: vec![diagnostic] });
+        let diagnostics = app.workspace.active_editor().unwrap().diagnostics_on_line(0);
+        assert_eq!((diagnostics[0].start_col, diagnostics[0].end_col), (1, 2));
+    }
+}
@@ -0,0 +1,112 @@
+//! Menu of the code actions a language server offers at the cursor.
+//!
+//! Ctrl+. asks the server for the quick fixes and refactorings of the
+//! cursor or selection, passing along the diagnostics under it. Their
+//! titles are listed by the cursor; picking one applies its workspace edit
+//! the way a rename is applied, then runs its command on the server, if it
+//! has one.
+
+use crate::lsp::FileEdits;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// A code action as listed in the menu.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeActionEntry {
+    /// Title shown in the menu.
+    pub title: String,
+    /// Edits the action makes, per file.
+    pub edits: Vec<FileEdits>,
+    /// Server command to run after the edits, if any.
+    pub command: Option<String>,
+    /// Arguments of the command.
+    pub arguments: Vec<Value>,
+}
+
+/// State of an open code actions menu.
+#[derive(Debug, Clone)]
+pub struct CodeActionMenu {
+    /// File the actions were offered for.
+    path: PathBuf,
+    /// Entries in the order the server sent them.
+    entries: Vec<CodeActionEntry>,
+    /// Index of the selected entry.
+    selected: usize,
+}
+
+impl CodeActionMenu {
+    /// Opens a menu listing `entries`, offered for the file at `path`.
+    ///
+    /// Returns `None` if there are no actions to show.
+    pub fn open(path: PathBuf, entries: Vec<CodeActionEntry>) -> Option<Self> {
+        if entries.is_empty() {
+            return None;
+        }
+        Some(Self {
+            path,
+            entries,
+            selected: 0,
+        })
+    }
+
+    /// Returns the file the actions were offered for.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the entries in display order.
+    pub fn entries(&self) -> &[CodeActionEntry] {
+        &self.entries
+    }
+
+    /// Returns the index of the selected entry.
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    /// Selects the next entry, wrapping around.
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % self.entries.len();
+    }
+
+    /// Selects the previous entry, wrapping around.
+    pub fn select_prev(&mut self) {
+        let len = self.entries.len();
+        self.selected = (self.selected + len - 1) % len;
+    }
+
+    /// Closes the menu, returning the selected entry.
+    pub fn into_selected(mut self) -> CodeActionEntry {
+        self.entries.swap_remove(self.selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(title: &str) -> CodeActionEntry {
+        CodeActionEntry {
+            title: title.to_string(),
+            edits: Vec::new(),
+            command: None,
+            arguments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_menu_selection() {
+        assert!(CodeActionMenu::open(PathBuf::from("a.rs"), Vec::new()).is_none());
+
+        let entries = vec![entry("Import `HashMap`"), entry("Qualify `HashMap`"), entry("Add dependency")];
+        let mut menu = CodeActionMenu::open(PathBuf::from("a.rs"), entries).unwrap();
+        assert_eq!(menu.path(), Path::new("a.rs"));
+        assert_eq!(menu.selected_index(), 0);
+        menu.select_prev();
+        assert_eq!(menu.selected_index(), 2);
+        menu.select_next();
+        menu.select_next();
+        assert_eq!(menu.selected_index(), 1);
+        assert_eq!(menu.into_selected().title, "Qualify `HashMap`");
+    }
+}
@@ -0,0 +1,416 @@
+//! Command registry.
+//!
+//! Every [`EditorCommand`] is registered once, with a handler and metadata
+//! describing it: its name, its category, and how running it affects the
+//! editor. Dispatch goes through [`CommandRegistry::execute`], which does
+//! the work common to whole groups of commands based on that metadata, so
+//! a command that edits the buffer only has to say so to have the language
+//! server and window title told about the change. Anything that lists
+//! commands (a palette, key bindings, macros) reads them from the registry.
+
+use crate::input::EditorCommand;
+use cp_editor_core::Editor;
+use std::collections::HashMap;
+use std::mem::{discriminant, Discriminant};
+
+/// Group a command is listed under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CommandCategory {
+    File,
+    Tabs,
+    Edit,
+    Cursor,
+    Selection,
+    MultiCursor,
+    Clipboard,
+    Search,
+    Navigation,
+    Language,
+    Diagnostics,
+    Folding,
+    View,
+}
+
+impl CommandCategory {
+    /// Returns the category's display name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::File => "File",
+            Self::Tabs => "Tabs",
+            Self::Edit => "Edit",
+            Self::Cursor => "Cursor",
+            Self::Selection => "Selection",
+            Self::MultiCursor => "Multi-Cursor",
+            Self::Clipboard => "Clipboard",
+            Self::Search => "Search",
+            Self::Navigation => "Navigation",
+            Self::Language => "Language",
+            Self::Diagnostics => "Diagnostics",
+            Self::Folding => "Folding",
+            Self::View => "View",
+        }
+    }
+}
+
+/// What is known about a command without running it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandInfo {
+    /// Display name, e.g. "Duplicate Line".
+    pub name: &'static str,
+    /// Group it is listed under.
+    pub category: CommandCategory,
+    /// Whether it does nothing without an active buffer.
+    pub needs_editor: bool,
+    /// Whether it changes the text of the buffer.
+    pub modifies_buffer: bool,
+    /// Whether it edits or moves the cursor, which keeps the cursor solid
+    /// instead of blinking.
+    pub cursor_activity: bool,
+    /// Whether running it several times in a row makes sense, e.g. with a
+    /// repeat count.
+    pub repeatable: bool,
+    /// Whether a macro may record it. Commands that open dialogs, quit or
+    /// only change how things are shown aren't recorded.
+    pub recordable: bool,
+}
+
+impl CommandInfo {
+    /// A command that needs nothing and changes nothing of the buffer.
+    pub const fn new(name: &'static str, category: CommandCategory) -> Self {
+        Self {
+            name,
+            category,
+            needs_editor: false,
+            modifies_buffer: false,
+            cursor_activity: false,
+            repeatable: false,
+            recordable: false,
+        }
+    }
+
+    /// A command that moves the cursor or selection of the active buffer:
+    /// repeatable and recordable.
+    pub const fn motion(name: &'static str, category: CommandCategory) -> Self {
+        Self::new(name, category).editor().cursor().repeatable().recordable()
+    }
+
+    /// A command that edits the text of the active buffer: a motion that
+    /// also modifies the buffer.
+    pub const fn edit(name: &'static str, category: CommandCategory) -> Self {
+        Self::motion(name, category).modifies()
+    }
+
+    /// Marks it as needing an active buffer.
+    pub const fn editor(mut self) -> Self {
+        self.needs_editor = true;
+        self
+    }
+
+    /// Marks it as changing the text of the buffer.
+    pub const fn modifies(mut self) -> Self {
+        self.modifies_buffer = true;
+        self
+    }
+
+    /// Marks it as cursor activity.
+    pub const fn cursor(mut self) -> Self {
+        self.cursor_activity = true;
+        self
+    }
+
+    /// Marks it as repeatable.
+    pub const fn repeatable(mut self) -> Self {
+        self.repeatable = true;
+        self
+    }
+
+    /// Marks it as recordable in a macro.
+    pub const fn recordable(mut self) -> Self {
+        self.recordable = true;
+        self
+    }
+
+    /// Marks it as not repeatable; for motions that jump to a fixed place.
+    pub const fn once(mut self) -> Self {
+        self.repeatable = false;
+        self
+    }
+}
+
+/// What commands run against.
+pub trait CommandContext {
+    /// Returns the active buffer's editor, if any.
+    fn active_editor(&mut self) -> Option<&mut Editor>;
+
+    /// Called before a command that is cursor activity runs.
+    fn cursor_activity(&mut self);
+
+    /// Called after a command that modifies the buffer ran.
+    fn buffer_modified(&mut self);
+
+    /// Called instead of a command that would modify the buffer while its
+    /// file is still loading.
+    fn buffer_loading(&mut self);
+}
+
+/// Runs a command.
+pub enum Handler<C> {
+    /// Runs on the active editor; the command isn't run without one.
+    Editor(fn(&mut Editor, &EditorCommand)),
+    /// Runs on the context. Returns true if the application should quit.
+    App(fn(&mut C, &EditorCommand) -> bool),
+}
+
+/// A registered command.
+pub struct Command<C> {
+    /// The command, with placeholder values for any arguments.
+    pub command: EditorCommand,
+    /// Its metadata.
+    pub info: CommandInfo,
+    handler: Handler<C>,
+    /// Runs after the handler and the work the metadata implies.
+    after: Option<fn(&mut C, &EditorCommand)>,
+}
+
+impl<C> Command<C> {
+    /// Sets what runs after the command and the work its metadata implies,
+    /// e.g. reacting to the edited text once the server was told about it.
+    pub fn then(&mut self, after: fn(&mut C, &EditorCommand)) -> &mut Self {
+        self.after = Some(after);
+        self
+    }
+}
+
+/// Every command with its handler and metadata.
+pub struct CommandRegistry<C> {
+    commands: Vec<Command<C>>,
+    /// Index of each variant's command.
+    index: HashMap<Discriminant<EditorCommand>, usize>,
+}
+
+impl<C> Default for CommandRegistry<C> {
+    fn default() -> Self {
+        Self {
+            commands: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+}
+
+impl<C: CommandContext> CommandRegistry<C> {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the variant of `command`; any arguments it has are
+    /// placeholders. Panics if the variant is already registered.
+    pub fn register(&mut self, command: EditorCommand, info: CommandInfo, handler: Handler<C>) -> &mut Command<C> {
+        let previous = self.index.insert(discriminant(&command), self.commands.len());
+        assert!(previous.is_none(), "{:?} is registered twice", command);
+        self.commands.push(Command {
+            command,
+            info,
+            handler,
+            after: None,
+        });
+        self.commands.last_mut().unwrap()
+    }
+
+    /// Returns the registered command of `command`'s variant.
+    pub fn get(&self, command: &EditorCommand) -> Option<&Command<C>> {
+        self.index.get(&discriminant(command)).map(|&i| &self.commands[i])
+    }
+
+    /// Returns the metadata of `command`'s variant.
+    pub fn info(&self, command: &EditorCommand) -> Option<&CommandInfo> {
+        self.get(command).map(|entry| &entry.info)
+    }
+
+    /// Returns every command, in registration order.
+    pub fn iter(&self) -> impl Iterator<Item = &Command<C>> {
+        self.commands.iter()
+    }
+
+    /// Returns the number of registered commands.
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Returns true if nothing is registered.
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Runs `command`, and the work common to commands like it. Returns
+    /// true if the application should quit.
+    pub fn execute(&self, ctx: &mut C, command: &EditorCommand) -> bool {
+        let Some(entry) = self.get(command) else {
+            log::warn!("Unregistered command {:?}", command);
+            return false;
+        };
+        if entry.info.needs_editor && ctx.active_editor().is_none() {
+            return false;
+        }
+        // Edits wait for the whole file, so nothing is edited out of place
+        if entry.info.modifies_buffer && ctx.active_editor().is_some_and(|editor| !editor.is_fully_loaded()) {
+            ctx.buffer_loading();
+            return false;
+        }
+        if entry.info.cursor_activity {
+            ctx.cursor_activity();
+        }
+        let quit = match entry.handler {
+            Handler::Editor(handler) => {
+                if let Some(editor) = ctx.active_editor() {
+                    handler(editor, command);
+                }
+                false
+            }
+            Handler::App(handler) => handler(ctx, command),
+        };
+        if entry.info.modifies_buffer {
+            ctx.buffer_modified();
+        }
+        if let Some(after) = entry.after {
+            after(ctx, command);
+        }
+        quit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Context {
+        editor: Option<Editor>,
+        log: Vec<String>,
+    }
+
+    impl CommandContext for Context {
+        fn active_editor(&mut self) -> Option<&mut Editor> {
+            self.editor.as_mut()
+        }
+
+        fn cursor_activity(&mut self) {
+            self.log.push("activity".to_string());
+        }
+
+        fn buffer_modified(&mut self) {
+            self.log.push("modified".to_string());
+        }
+
+        fn buffer_loading(&mut self) {
+            self.log.push("loading".to_string());
+        }
+    }
+
+    fn registry() -> CommandRegistry<Context> {
+        let mut registry: CommandRegistry<Context> = CommandRegistry::new();
+        registry.register(
+            EditorCommand::InsertChar(' '),
+            CommandInfo::edit("Type Character", CommandCategory::Edit),
+            Handler::Editor(|editor, command| {
+                if let EditorCommand::InsertChar(ch) = command {
+                    editor.insert_char(*ch);
+                }
+            }),
+        )
+        .then(|ctx, _| ctx.log.push("after".to_string()));
+        registry.register(
+            EditorCommand::MoveLeft,
+            CommandInfo::motion("Cursor Left", CommandCategory::Cursor),
+            Handler::Editor(|editor, _| editor.move_left(false)),
+        );
+        registry.register(
+            EditorCommand::Quit,
+            CommandInfo::new("Quit", CommandCategory::File),
+            Handler::App(|ctx, _| {
+                ctx.log.push("quit".to_string());
+                true
+            }),
+        );
+        registry
+    }
+
+    #[test]
+    fn test_metadata_drives_dispatch() {
+        let registry = registry();
+        let mut ctx = Context {
+            editor: Some(Editor::new()),
+            ..Context::default()
+        };
+
+        // Arguments are passed on; the edit is reported before the hook
+        assert!(!registry.execute(&mut ctx, &EditorCommand::InsertChar('x')));
+        assert_eq!(ctx.editor.as_ref().unwrap().buffer().to_string(), "x");
+        assert_eq!(ctx.log, ["activity", "modified", "after"]);
+
+        ctx.log.clear();
+        registry.execute(&mut ctx, &EditorCommand::MoveLeft);
+        assert_eq!(ctx.log, ["activity"]);
+
+        ctx.log.clear();
+        assert!(registry.execute(&mut ctx, &EditorCommand::Quit));
+        assert_eq!(ctx.log, ["quit"]);
+
+        // Unregistered commands do nothing
+        ctx.log.clear();
+        assert!(!registry.execute(&mut ctx, &EditorCommand::Paste));
+        assert!(ctx.log.is_empty());
+    }
+
+    #[test]
+    fn test_editor_commands_need_an_editor() {
+        let registry = registry();
+        let mut ctx = Context::default();
+        registry.execute(&mut ctx, &EditorCommand::InsertChar('x'));
+        assert!(ctx.log.is_empty());
+        assert!(registry.execute(&mut ctx, &EditorCommand::Quit));
+    }
+
+    #[test]
+    fn test_edits_wait_for_loading() {
+        let path = std::env::temp_dir().join(format!("cp_editor_commands_loading_{}.txt", std::process::id()));
+        std::fs::write(&path, "a".repeat(200_000)).unwrap();
+        let mut editor = Editor::new();
+        editor.open_file_streaming_from(&path, 0).unwrap();
+        let registry = registry();
+        let mut ctx = Context {
+            editor: Some(editor),
+            ..Context::default()
+        };
+
+        registry.execute(&mut ctx, &EditorCommand::InsertChar('x'));
+        assert_eq!(ctx.log, ["loading"]);
+        // Moving around doesn't have to wait
+        ctx.log.clear();
+        registry.execute(&mut ctx, &EditorCommand::MoveLeft);
+        assert_eq!(ctx.log, ["activity"]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_lookup_ignores_arguments() {
+        let registry = registry();
+        assert_eq!(registry.len(), 3);
+        let info = registry.info(&EditorCommand::InsertChar('q')).unwrap();
+        assert_eq!(info.name, "Type Character");
+        assert!(info.modifies_buffer && info.repeatable && info.recordable);
+        let info = registry.info(&EditorCommand::Quit).unwrap();
+        assert!(!info.needs_editor && !info.recordable);
+    }
+
+    #[test]
+    #[should_panic(expected = "registered twice")]
+    fn test_registering_twice_panics() {
+        let mut registry = registry();
+        registry.register(
+            EditorCommand::InsertChar('a'),
+            CommandInfo::edit("Again", CommandCategory::Edit),
+            Handler::Editor(|_, _| {}),
+        );
+    }
+}
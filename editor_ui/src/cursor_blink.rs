@@ -0,0 +1,194 @@
+//! Cursor blink timing.
+//!
+//! The caret blinks on a fixed interval, but stays solid while the user is
+//! typing or moving it: any activity forces it visible and suspends blinking
+//! until a short idle delay has passed, after which blinking resumes with a
+//! full visible phase.
+
+use std::time::{Duration, Instant};
+
+/// Default time between blink phase changes.
+pub const DEFAULT_BLINK_INTERVAL: Duration = Duration::from_millis(530);
+
+/// Default idle time after the last activity before blinking resumes.
+pub const DEFAULT_IDLE_DELAY: Duration = Duration::from_millis(1000);
+
+/// Blink state for the text cursor.
+#[derive(Debug, Clone)]
+pub struct CursorBlink {
+    /// Whether blinking is enabled at all. When disabled the cursor is solid.
+    enabled: bool,
+    /// Time between phase changes.
+    interval: Duration,
+    /// How long blinking stays suspended after activity.
+    idle_delay: Duration,
+    /// Whether the cursor is in its visible phase.
+    visible: bool,
+    /// When the current phase started.
+    phase_start: Instant,
+    /// Time of the last typing or cursor movement.
+    last_activity: Option<Instant>,
+}
+
+impl CursorBlink {
+    /// Creates blink state with the default timings, starting visible at `now`.
+    pub fn new(now: Instant) -> Self {
+        Self::with_timing(DEFAULT_BLINK_INTERVAL, DEFAULT_IDLE_DELAY, now)
+    }
+
+    /// Creates blink state with custom timings, starting visible at `now`.
+    pub fn with_timing(interval: Duration, idle_delay: Duration, now: Instant) -> Self {
+        Self {
+            enabled: true,
+            interval,
+            idle_delay,
+            visible: true,
+            phase_start: now,
+            last_activity: None,
+        }
+    }
+
+    /// Returns whether blinking is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enables or disables blinking. Disabling leaves the cursor solid.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.visible = true;
+        }
+    }
+
+    /// Records typing or cursor movement at `now`: shows the cursor and
+    /// suspends blinking until the idle delay has passed.
+    pub fn record_activity(&mut self, now: Instant) {
+        self.last_activity = Some(now);
+        self.visible = true;
+        self.phase_start = now;
+    }
+
+    /// Returns whether blinking is suspended by recent activity.
+    pub fn is_suspended(&self, now: Instant) -> bool {
+        self.last_activity
+            .is_some_and(|at| now.saturating_duration_since(at) < self.idle_delay)
+    }
+
+    /// Returns whether the cursor is currently blinking (as opposed to solid).
+    pub fn should_blink(&self, now: Instant) -> bool {
+        self.enabled && !self.is_suspended(now)
+    }
+
+    /// Returns whether the cursor should be drawn.
+    pub fn should_draw(&self) -> bool {
+        self.visible
+    }
+
+    /// Advances the blink phase to `now`. Returns true if visibility changed.
+    pub fn update(&mut self, now: Instant) -> bool {
+        if !self.should_blink(now) {
+            return false;
+        }
+
+        // The visible phase after a suspension starts when the suspension ends,
+        // not when the activity happened.
+        let phase_start = match self.last_activity {
+            Some(at) => self.phase_start.max(at + self.idle_delay),
+            None => self.phase_start,
+        };
+        if now.saturating_duration_since(phase_start) >= self.interval {
+            self.visible = !self.visible;
+            self.phase_start = now;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(500);
+    const IDLE: Duration = Duration::from_millis(1000);
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_blinks_on_interval() {
+        let t0 = Instant::now();
+        let mut blink = CursorBlink::with_timing(INTERVAL, IDLE, t0);
+
+        assert!(blink.should_draw());
+        assert!(!blink.update(t0 + ms(499)));
+        assert!(blink.update(t0 + ms(500)));
+        assert!(!blink.should_draw());
+        assert!(blink.update(t0 + ms(1000)));
+        assert!(blink.should_draw());
+    }
+
+    #[test]
+    fn test_activity_forces_visible_and_suspends() {
+        let t0 = Instant::now();
+        let mut blink = CursorBlink::with_timing(INTERVAL, IDLE, t0);
+        blink.update(t0 + ms(500));
+        assert!(!blink.should_draw());
+
+        // Typing while hidden shows the cursor immediately
+        blink.record_activity(t0 + ms(600));
+        assert!(blink.should_draw());
+        assert!(blink.is_suspended(t0 + ms(1599)));
+        assert!(!blink.should_blink(t0 + ms(1599)));
+
+        // No blinking during the suspension, even past the interval
+        assert!(!blink.update(t0 + ms(1200)));
+        assert!(!blink.update(t0 + ms(1599)));
+        assert!(blink.should_draw());
+    }
+
+    #[test]
+    fn test_resumes_with_full_visible_phase() {
+        let t0 = Instant::now();
+        let mut blink = CursorBlink::with_timing(INTERVAL, IDLE, t0);
+        blink.record_activity(t0);
+
+        // Suspension ends at 1000ms; the visible phase lasts until 1500ms
+        assert!(blink.should_blink(t0 + ms(1000)));
+        assert!(!blink.update(t0 + ms(1000)));
+        assert!(!blink.update(t0 + ms(1499)));
+        assert!(blink.should_draw());
+        assert!(blink.update(t0 + ms(1500)));
+        assert!(!blink.should_draw());
+    }
+
+    #[test]
+    fn test_continuous_activity_keeps_cursor_solid() {
+        let t0 = Instant::now();
+        let mut blink = CursorBlink::with_timing(INTERVAL, IDLE, t0);
+
+        // Key repeat every 30ms for 3 seconds
+        for step in 0..100 {
+            let now = t0 + ms(step * 30);
+            blink.record_activity(now);
+            assert!(!blink.update(now + ms(15)));
+            assert!(blink.should_draw());
+        }
+    }
+
+    #[test]
+    fn test_disabled_is_solid() {
+        let t0 = Instant::now();
+        let mut blink = CursorBlink::with_timing(INTERVAL, IDLE, t0);
+        blink.update(t0 + ms(500));
+        assert!(!blink.should_draw());
+
+        blink.set_enabled(false);
+        assert!(blink.should_draw());
+        assert!(!blink.update(t0 + ms(5000)));
+        assert!(blink.should_draw());
+    }
+}
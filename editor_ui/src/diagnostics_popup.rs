@@ -0,0 +1,225 @@
+//! Popup listing every diagnostic on a line.
+//!
+//! Opened from the diagnostics gutter column or the ShowLineDiagnostics
+//! command. Entries are sorted most severe first and their messages are
+//! wrapped so nothing is cut off, unlike the single-line hover.
+
+use cp_editor_core::{Diagnostic, DiagnosticSeverity};
+
+/// Column width messages are wrapped to.
+pub const WRAP_COLUMNS: usize = 72;
+
+/// Indent of wrapped message lines under an entry's header.
+const MESSAGE_INDENT: &str = "  ";
+
+/// One diagnostic as shown in the popup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticEntry {
+    /// Severity, used for the entry's color.
+    pub severity: DiagnosticSeverity,
+    /// Column the diagnostic starts at on the popup's line.
+    pub col: usize,
+    /// Severity, source and code, e.g. "Error rustc(E0425)".
+    pub header: String,
+    /// The full message, wrapped and indented.
+    pub lines: Vec<String>,
+}
+
+/// Returns the display name of a severity.
+pub fn severity_label(severity: DiagnosticSeverity) -> &'static str {
+    match severity {
+        DiagnosticSeverity::Error => "Error",
+        DiagnosticSeverity::Warning => "Warning",
+        DiagnosticSeverity::Information => "Info",
+        DiagnosticSeverity::Hint => "Hint",
+    }
+}
+
+/// Word-wraps `text` to at most `width` characters per line.
+///
+/// Existing line breaks are kept and words longer than `width` are split.
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut current = String::new();
+        let mut current_len = 0;
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            // Split words that can't fit on a line of their own
+            while word.len() > width {
+                if current_len > 0 {
+                    lines.push(std::mem::take(&mut current));
+                    current_len = 0;
+                }
+                let rest = word.split_off(width);
+                lines.push(word.into_iter().collect());
+                word = rest;
+            }
+            if current_len > 0 && current_len + 1 + word.len() > width {
+                lines.push(std::mem::take(&mut current));
+                current_len = 0;
+            }
+            if current_len > 0 {
+                current.push(' ');
+                current_len += 1;
+            }
+            current_len += word.len();
+            current.extend(word);
+        }
+        lines.push(current);
+    }
+
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+/// Builds the popup entries for the diagnostics on one line.
+///
+/// Entries are ordered most severe first, then by column; messages are
+/// wrapped to `width` columns including the indent.
+pub fn build_entries(diagnostics: &[&Diagnostic], width: usize) -> Vec<DiagnosticEntry> {
+    let mut sorted: Vec<&Diagnostic> = diagnostics.to_vec();
+    sorted.sort_by_key(|d| (d.severity, d.start_col));
+
+    let message_width = width.saturating_sub(MESSAGE_INDENT.len());
+    sorted
+        .into_iter()
+        .map(|d| {
+            let mut header = severity_label(d.severity).to_string();
+            match (&d.source, &d.code) {
+                (Some(source), Some(code)) => header.push_str(&format!(" {}({})", source, code)),
+                (Some(source), None) => header.push_str(&format!(" {}", source)),
+                (None, Some(code)) => header.push_str(&format!(" [{}]", code)),
+                (None, None) => {}
+            }
+            let lines = wrap_text(&d.message, message_width)
+                .into_iter()
+                .map(|line| format!("{}{}", MESSAGE_INDENT, line))
+                .collect();
+            DiagnosticEntry {
+                severity: d.severity,
+                col: d.start_col,
+                header,
+                lines,
+            }
+        })
+        .collect()
+}
+
+/// State of an open line diagnostics popup.
+#[derive(Debug, Clone)]
+pub struct DiagnosticsPopup {
+    /// Line the popup is anchored to.
+    line: usize,
+    /// Entries in display order.
+    entries: Vec<DiagnosticEntry>,
+    /// Index of the selected entry.
+    selected: usize,
+}
+
+impl DiagnosticsPopup {
+    /// Opens a popup for the diagnostics on `line`.
+    ///
+    /// Returns `None` if there are no diagnostics to show.
+    pub fn open(line: usize, diagnostics: &[&Diagnostic]) -> Option<Self> {
+        let entries = build_entries(diagnostics, WRAP_COLUMNS);
+        if entries.is_empty() {
+            return None;
+        }
+        Some(Self {
+            line,
+            entries,
+            selected: 0,
+        })
+    }
+
+    /// Returns the line the popup is anchored to.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the entries in display order.
+    pub fn entries(&self) -> &[DiagnosticEntry] {
+        &self.entries
+    }
+
+    /// Returns the index of the selected entry.
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    /// Returns the selected entry.
+    pub fn selected(&self) -> Option<&DiagnosticEntry> {
+        self.entries.get(self.selected)
+    }
+
+    /// Selects the next entry, wrapping around.
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % self.entries.len();
+    }
+
+    /// Selects the previous entry, wrapping around.
+    pub fn select_prev(&mut self) {
+        let len = self.entries.len();
+        self.selected = (self.selected + len - 1) % len;
+    }
+
+    /// Returns the number of text rows the popup needs.
+    pub fn row_count(&self) -> usize {
+        self.entries.iter().map(|e| 1 + e.lines.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diag(severity: DiagnosticSeverity, col: usize, message: &str) -> Diagnostic {
+        Diagnostic::new(3, col, 3, col + 1, severity, message.to_string())
+    }
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(wrap_text("one two three four", 9), vec!["one two", "three", "four"]);
+        assert_eq!(wrap_text("first\nsecond line", 20), vec!["first", "second line"]);
+        // Overlong words are split
+        assert_eq!(wrap_text("abcdefghij xy", 4), vec!["abcd", "efgh", "ij", "xy"]);
+        assert_eq!(wrap_text("", 10), vec![""]);
+    }
+
+    #[test]
+    fn test_entries_sorted_by_severity() {
+        let hint = diag(DiagnosticSeverity::Hint, 0, "consider this");
+        let late_error = diag(DiagnosticSeverity::Error, 8, "second error");
+        let warning = diag(DiagnosticSeverity::Warning, 2, "unused");
+        let early_error = diag(DiagnosticSeverity::Error, 1, "first error");
+
+        let entries = build_entries(&[&hint, &late_error, &warning, &early_error], WRAP_COLUMNS);
+        let messages: Vec<&str> = entries.iter().map(|e| e.lines[0].trim()).collect();
+        assert_eq!(messages, vec!["first error", "second error", "unused", "consider this"]);
+    }
+
+    #[test]
+    fn test_entry_header_and_wrapping() {
+        let mut d = diag(DiagnosticSeverity::Error, 4, "cannot find value `x` in this scope");
+        d.source = Some("rustc".to_string());
+        d.code = Some("E0425".to_string());
+
+        let entries = build_entries(&[&d], 20);
+        assert_eq!(entries[0].header, "Error rustc(E0425)");
+        assert_eq!(entries[0].lines, vec!["  cannot find value", "  `x` in this scope"]);
+        assert!(entries[0].lines.iter().all(|l| l.len() <= 20));
+
+        let mut popup = DiagnosticsPopup::open(3, &[&d, &d]).unwrap();
+        assert_eq!(popup.row_count(), 4);
+        popup.select_prev();
+        assert_eq!(popup.selected_index(), 1);
+        popup.select_next();
+        assert_eq!(popup.selected_index(), 0);
+        assert!(DiagnosticsPopup::open(3, &[]).is_none());
+    }
+}
@@ -0,0 +1,98 @@
+//! Banner over a buffer whose file changed on disk under unsaved edits.
+//!
+//! The banner reads "File changed on disk — Compare · Reload · Keep mine".
+//! Clicking an action resolves the conflict: Compare opens a diff of the
+//! file's new text against the buffer, Reload replaces the buffer with the
+//! file as one undo step and Keep mine dismisses the banner until the file
+//! changes again.
+
+use std::ops::Range;
+
+/// Text before the actions.
+pub const MESSAGE: &str = "File changed on disk \u{2014} ";
+
+/// Text between two actions.
+const SEPARATOR: &str = " \u{b7} ";
+
+/// What clicking a part of the banner does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BannerAction {
+    /// Open a diff of the file against the buffer.
+    Compare,
+    /// Replace the buffer with the file.
+    Reload,
+    /// Keep the buffer's edits.
+    Keep,
+}
+
+impl BannerAction {
+    /// The actions, in the order they are shown.
+    pub const ALL: [BannerAction; 3] = [BannerAction::Compare, BannerAction::Reload, BannerAction::Keep];
+
+    /// Returns the action's label.
+    pub fn label(&self) -> &'static str {
+        match self {
+            BannerAction::Compare => "Compare",
+            BannerAction::Reload => "Reload",
+            BannerAction::Keep => "Keep mine",
+        }
+    }
+}
+
+/// Returns each action with the character columns its label takes up,
+/// counted from the start of the banner's text.
+pub fn action_spans() -> Vec<(BannerAction, Range<usize>)> {
+    let mut col = MESSAGE.chars().count();
+    BannerAction::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, &action)| {
+            if i > 0 {
+                col += SEPARATOR.chars().count();
+            }
+            let start = col;
+            col += action.label().chars().count();
+            (action, start..col)
+        })
+        .collect()
+}
+
+/// Returns the separator drawn after the action at `index`, if any.
+pub fn separator_after(index: usize) -> Option<&'static str> {
+    (index + 1 < BannerAction::ALL.len()).then_some(SEPARATOR)
+}
+
+/// Returns the action whose label is at `x`, for banner text drawn from
+/// `left` with characters `char_width` wide.
+pub fn action_at(x: f32, left: f32, char_width: f32) -> Option<BannerAction> {
+    if x < left {
+        return None;
+    }
+    let col = ((x - left) / char_width) as usize;
+    action_spans()
+        .into_iter()
+        .find(|(_, span)| span.contains(&col))
+        .map(|(action, _)| action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_at() {
+        let message = MESSAGE.chars().count() as f32;
+        // "Compare" starts right after the message
+        assert_eq!(action_at(message * 10.0 + 5.0, 0.0, 10.0), Some(BannerAction::Compare));
+        assert_eq!(action_at(5.0, 0.0, 10.0), None);
+        // Past "Compare · ", on "Reload"
+        assert_eq!(action_at(100.0 + (message + 10.0) * 10.0, 100.0, 10.0), Some(BannerAction::Reload));
+        // On the separator
+        assert_eq!(action_at((message + 8.0) * 10.0, 0.0, 10.0), None);
+        let spans = action_spans();
+        let keep = &spans[2].1;
+        assert_eq!(action_at(keep.end as f32 * 10.0 - 1.0, 0.0, 10.0), Some(BannerAction::Keep));
+        assert_eq!(action_at(keep.end as f32 * 10.0, 0.0, 10.0), None);
+        assert_eq!(action_at(50.0, 100.0, 10.0), None);
+    }
+}
@@ -0,0 +1,24 @@
+//! Memory the render path reuses from frame to frame.
+//!
+//! Each frame looks up the screen rows, selections, cursors and
+//! diagnostics in view. The
+//! lists they go into are kept in a [`FrameScratch`] on the
+//! [`DrawList`](crate::gpu_renderer::DrawList) and refilled every frame, so
+//! once they have grown to fit the window, drawing doesn't allocate them.
+
+use cp_editor_core::VisualLine;
+
+/// Lists filled anew each frame, keeping their memory.
+#[derive(Debug, Default)]
+pub struct FrameScratch {
+    /// Screen rows of the focused pane.
+    pub rows: Vec<VisualLine>,
+    /// Screen rows of the other pane of a split.
+    pub pane_rows: Vec<VisualLine>,
+    /// Selected ranges on the visible lines.
+    pub selections: Vec<(usize, usize)>,
+    /// Cursor positions, as (line, column).
+    pub cursors: Vec<(usize, usize)>,
+    /// Indices of the diagnostics on the visible lines.
+    pub diagnostics: Vec<usize>,
+}
@@ -3,6 +3,7 @@
 //! Renders text directly on the GPU using instanced quads.
 
 use crate::font::GlyphAtlas;
+use crate::frame::FrameScratch;
 use wgpu::util::DeviceExt;
 
 /// Colors for the editor UI.
@@ -32,6 +33,9 @@ pub struct Colors {
     pub completion_selected_bg: [f32; 4],
     pub completion_border: [f32; 4],
     pub bracket_match: [f32; 4],
+    pub change_added: [f32; 4],
+    pub change_modified: [f32; 4],
+    pub change_deleted: [f32; 4],
 }
 
 impl Default for Colors {
@@ -61,6 +65,9 @@ impl Default for Colors {
             completion_selected_bg: [0.25, 0.35, 0.55, 1.0], // Blue highlight for selected
             completion_border: [0.3, 0.3, 0.35, 1.0],       // Same as hover border
             bracket_match: [0.4, 0.6, 0.8, 0.4],            // Light blue highlight for matching brackets
+            change_added: [0.400, 0.733, 0.416, 1.0],       // #66BB6A - Green
+            change_modified: [0.259, 0.647, 0.961, 1.0],    // #42A5F5 - Blue
+            change_deleted: [0.937, 0.325, 0.314, 1.0],     // #EF5350 - Red
         }
     }
 }
@@ -113,52 +120,32 @@ impl Uniforms {
     }
 }
 
-/// GPU-based text and shape renderer.
-pub struct GpuRenderer {
-    /// Glyph atlas.
-    atlas: GlyphAtlas,
-    /// Viewport width.
-    width: u32,
-    /// Viewport height.
-    height: u32,
-    /// Colors.
-    pub colors: Colors,
+/// Which glyph atlas a text draw call uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextAtlas {
+    /// Buffer text, at the zoomable editor font size.
+    Editor,
+    /// Window chrome (tab bar, status bar, popups), at the UI font size.
+    Ui,
+}
 
-    // GPU resources
-    render_pipeline: wgpu::RenderPipeline,
-    rect_pipeline: wgpu::RenderPipeline,
-    uniform_buffer: wgpu::Buffer,
-    uniform_bind_group: wgpu::BindGroup,
+/// A glyph atlas uploaded to the GPU.
+struct AtlasTexture {
     #[allow(dead_code)]
-    atlas_texture: wgpu::Texture,
-    atlas_bind_group: wgpu::BindGroup,
-    
-    /// Vertices for text glyphs (rendered with atlas texture).
-    text_vertices: Vec<Vertex>,
-    /// Vertices for solid rectangles (background, cursor, selection).
-    rect_vertices: Vec<Vertex>,
-    
-    /// Maximum number of vertices in buffers.
-    max_vertices: usize,
-    text_vertex_buffer: wgpu::Buffer,
-    rect_vertex_buffer: wgpu::Buffer,
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
 }
 
-impl GpuRenderer {
-    /// Creates a new GPU renderer.
-    pub fn new(
+impl AtlasTexture {
+    /// Uploads `atlas`.
+    fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        surface_format: wgpu::TextureFormat,
-        width: u32,
-        height: u32,
-        font_size: f32,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        atlas: &GlyphAtlas,
     ) -> Self {
-        let atlas = GlyphAtlas::new(font_size);
-        let colors = Colors::default();
-
-        // Create glyph atlas texture
-        let atlas_texture = device.create_texture(&wgpu::TextureDescriptor {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Glyph Atlas"),
             size: wgpu::Extent3d {
                 width: atlas.width,
@@ -176,7 +163,7 @@ impl GpuRenderer {
         // Upload atlas data
         queue.write_texture(
             wgpu::TexelCopyTextureInfo {
-                texture: &atlas_texture,
+                texture: &texture,
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
@@ -194,7 +181,93 @@ impl GpuRenderer {
             },
         );
 
-        let atlas_view = atlas_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Atlas Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+
+        Self {
+            texture,
+            bind_group,
+        }
+    }
+}
+
+/// Maximum number of vertices of each kind drawn in a frame.
+const MAX_VERTICES: usize = 65536;
+
+/// The geometry of a frame, queued by the draw calls until it is rendered.
+///
+/// Drawing needs no GPU, so a frame can be drawn without a window, as the
+/// tests do. The vertex lists keep their memory from frame to frame.
+pub struct DrawList {
+    /// Glyph atlas of the buffer text.
+    editor_atlas: GlyphAtlas,
+    /// Glyph atlas of the window chrome.
+    ui_atlas: GlyphAtlas,
+    /// Viewport width.
+    width: u32,
+    /// Viewport height.
+    height: u32,
+    /// Colors.
+    pub colors: Colors,
+    /// Buffers the render path fills each frame.
+    pub scratch: FrameScratch,
+
+    /// Vertices for buffer text glyphs (rendered with the editor atlas).
+    text_vertices: Vec<Vertex>,
+    /// Vertices for chrome text glyphs (rendered with the UI atlas).
+    ui_text_vertices: Vec<Vertex>,
+    /// Vertices for solid rectangles (background, cursor, selection).
+    rect_vertices: Vec<Vertex>,
+}
+
+/// GPU-based text and shape renderer. Draw calls go to its [`DrawList`].
+pub struct GpuRenderer {
+    /// The frame being drawn.
+    draw: DrawList,
+    /// Glyph atlas of the buffer text, on the GPU.
+    editor_font: AtlasTexture,
+    /// Glyph atlas of the window chrome, on the GPU.
+    ui_font: AtlasTexture,
+
+    // GPU resources
+    render_pipeline: wgpu::RenderPipeline,
+    rect_pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    atlas_bind_group_layout: wgpu::BindGroupLayout,
+    atlas_sampler: wgpu::Sampler,
+
+    text_vertex_buffer: wgpu::Buffer,
+    ui_text_vertex_buffer: wgpu::Buffer,
+    rect_vertex_buffer: wgpu::Buffer,
+}
+
+impl GpuRenderer {
+    /// Creates a new GPU renderer.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface_format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        font_size: f32,
+        ui_font_size: f32,
+    ) -> Self {
+        let draw = DrawList::new(width, height, font_size, ui_font_size);
+
         let atlas_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
@@ -262,20 +335,8 @@ impl GpuRenderer {
             }],
         });
 
-        let atlas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Atlas Bind Group"),
-            layout: &atlas_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&atlas_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&atlas_sampler),
-                },
-            ],
-        });
+        let editor_font = AtlasTexture::new(device, queue, &atlas_bind_group_layout, &atlas_sampler, &draw.editor_atlas);
+        let ui_font = AtlasTexture::new(device, queue, &atlas_bind_group_layout, &atlas_sampler, &draw.ui_atlas);
 
         // Create shaders
         let text_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -378,7 +439,7 @@ impl GpuRenderer {
         });
 
         // Create vertex buffers with initial capacity
-        let max_vertices = 65536;
+        let max_vertices = MAX_VERTICES;
         let text_vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Text Vertex Buffer"),
             size: (max_vertices * std::mem::size_of::<Vertex>()) as u64,
@@ -386,6 +447,13 @@ impl GpuRenderer {
             mapped_at_creation: false,
         });
 
+        let ui_text_vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("UI Text Vertex Buffer"),
+            size: (max_vertices * std::mem::size_of::<Vertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
         let rect_vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Rect Vertex Buffer"),
             size: (max_vertices * std::mem::size_of::<Vertex>()) as u64,
@@ -394,27 +462,33 @@ impl GpuRenderer {
         });
 
         Self {
-            atlas,
-            width,
-            height,
-            colors,
+            draw,
+            editor_font,
+            ui_font,
             render_pipeline,
             rect_pipeline,
             uniform_buffer,
             uniform_bind_group,
-            atlas_texture,
-            atlas_bind_group,
-            text_vertices: Vec::with_capacity(max_vertices),
-            rect_vertices: Vec::with_capacity(max_vertices),
-            max_vertices,
+            atlas_bind_group_layout,
+            atlas_sampler,
             text_vertex_buffer,
+            ui_text_vertex_buffer,
             rect_vertex_buffer,
         }
     }
 
-    /// Returns the glyph atlas.
-    pub fn atlas(&self) -> &GlyphAtlas {
-        &self.atlas
+    /// Rebuilds the buffer text atlas at a new size, e.g. when zooming.
+    pub fn set_font_size(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, font_size: f32) {
+        self.draw.editor_atlas = GlyphAtlas::new(font_size);
+        self.editor_font =
+            AtlasTexture::new(device, queue, &self.atlas_bind_group_layout, &self.atlas_sampler, &self.draw.editor_atlas);
+    }
+
+    /// Rebuilds the window chrome atlas at a new size.
+    pub fn set_ui_font_size(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, font_size: f32) {
+        self.draw.ui_atlas = GlyphAtlas::new(font_size);
+        self.ui_font =
+            AtlasTexture::new(device, queue, &self.atlas_bind_group_layout, &self.atlas_sampler, &self.draw.ui_atlas);
     }
 
     /// Resizes the renderer.
@@ -422,23 +496,150 @@ impl GpuRenderer {
         if width == 0 || height == 0 {
             return;
         }
-        self.width = width;
-        self.height = height;
+        self.draw.width = width;
+        self.draw.height = height;
 
         // Update projection matrix
         let uniforms = Uniforms::new(width as f32, height as f32);
         queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
     }
 
+    /// Renders all queued geometry.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+    ) {
+        // Upload vertices to GPU
+        if !self.draw.rect_vertices.is_empty() {
+            queue.write_buffer(
+                &self.rect_vertex_buffer,
+                0,
+                bytemuck::cast_slice(&self.draw.rect_vertices),
+            );
+        }
+
+        if !self.draw.text_vertices.is_empty() {
+            queue.write_buffer(
+                &self.text_vertex_buffer,
+                0,
+                bytemuck::cast_slice(&self.draw.text_vertices),
+            );
+        }
+
+        if !self.draw.ui_text_vertices.is_empty() {
+            queue.write_buffer(
+                &self.ui_text_vertex_buffer,
+                0,
+                bytemuck::cast_slice(&self.draw.ui_text_vertices),
+            );
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Main Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: self.draw.colors.background[0] as f64,
+                            g: self.draw.colors.background[1] as f64,
+                            b: self.draw.colors.background[2] as f64,
+                            a: self.draw.colors.background[3] as f64,
+                        }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            // Draw rectangles first (backgrounds, selections)
+            if !self.draw.rect_vertices.is_empty() {
+                render_pass.set_pipeline(&self.rect_pipeline);
+                render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.rect_vertex_buffer.slice(..));
+                render_pass.draw(0..self.draw.rect_vertices.len() as u32, 0..1);
+            }
+
+            // Draw text on top, chrome text last
+            if !self.draw.text_vertices.is_empty() {
+                render_pass.set_pipeline(&self.render_pipeline);
+                render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+                render_pass.set_bind_group(1, &self.editor_font.bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.text_vertex_buffer.slice(..));
+                render_pass.draw(0..self.draw.text_vertices.len() as u32, 0..1);
+            }
+            if !self.draw.ui_text_vertices.is_empty() {
+                render_pass.set_pipeline(&self.render_pipeline);
+                render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+                render_pass.set_bind_group(1, &self.ui_font.bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.ui_text_vertex_buffer.slice(..));
+                render_pass.draw(0..self.draw.ui_text_vertices.len() as u32, 0..1);
+            }
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+impl std::ops::Deref for GpuRenderer {
+    type Target = DrawList;
+
+    fn deref(&self) -> &DrawList {
+        &self.draw
+    }
+}
+
+impl std::ops::DerefMut for GpuRenderer {
+    fn deref_mut(&mut self) -> &mut DrawList {
+        &mut self.draw
+    }
+}
+
+impl DrawList {
+    /// Creates an empty frame, rasterizing the glyph atlases.
+    pub fn new(width: u32, height: u32, font_size: f32, ui_font_size: f32) -> Self {
+        Self {
+            editor_atlas: GlyphAtlas::new(font_size),
+            ui_atlas: GlyphAtlas::new(ui_font_size),
+            width,
+            height,
+            colors: Colors::default(),
+            scratch: FrameScratch::default(),
+            text_vertices: Vec::with_capacity(MAX_VERTICES),
+            ui_text_vertices: Vec::with_capacity(MAX_VERTICES),
+            rect_vertices: Vec::with_capacity(MAX_VERTICES),
+        }
+    }
+
+    /// Returns the glyph atlas of the buffer text.
+    pub fn atlas(&self) -> &GlyphAtlas {
+        &self.editor_atlas
+    }
+
+    /// Returns the glyph atlas of the window chrome.
+    pub fn ui_atlas(&self) -> &GlyphAtlas {
+        &self.ui_atlas
+    }
+
     /// Clears all queued vertices.
     pub fn clear(&mut self) {
         self.text_vertices.clear();
+        self.ui_text_vertices.clear();
         self.rect_vertices.clear();
     }
 
     /// Draws a filled rectangle.
     pub fn draw_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: [f32; 4]) {
-        if self.rect_vertices.len() + 6 > self.max_vertices {
+        if self.rect_vertices.len() + 6 > MAX_VERTICES {
             return; // Buffer full
         }
 
@@ -458,9 +659,23 @@ impl GpuRenderer {
         ]);
     }
 
-    /// Draws a single character.
+    /// Draws a single character of buffer text.
     pub fn draw_char(&mut self, ch: char, x: f32, y: f32, color: [f32; 4]) {
-        let glyph = match self.atlas.get_glyph(ch) {
+        self.draw_char_in(TextAtlas::Editor, ch, x, y, color);
+    }
+
+    /// Draws a single character of window chrome text.
+    pub fn draw_ui_char(&mut self, ch: char, x: f32, y: f32, color: [f32; 4]) {
+        self.draw_char_in(TextAtlas::Ui, ch, x, y, color);
+    }
+
+    /// Draws a single character with the glyphs of `atlas`.
+    pub fn draw_char_in(&mut self, atlas: TextAtlas, ch: char, x: f32, y: f32, color: [f32; 4]) {
+        let (atlas, vertices) = match atlas {
+            TextAtlas::Editor => (&self.editor_atlas, &mut self.text_vertices),
+            TextAtlas::Ui => (&self.ui_atlas, &mut self.ui_text_vertices),
+        };
+        let glyph = match atlas.get_glyph(ch) {
             Some(g) => g,
             None => return,
         };
@@ -469,13 +684,13 @@ impl GpuRenderer {
             return;
         }
 
-        if self.text_vertices.len() + 6 > self.max_vertices {
+        if vertices.len() + 6 > MAX_VERTICES {
             return; // Buffer full
         }
 
         // Calculate screen position
         let gx = x + glyph.offset_x;
-        let baseline_y = y + self.atlas.ascent;
+        let baseline_y = y + atlas.ascent;
         let gy = baseline_y - glyph.offset_y - glyph.height as f32;
 
         let x0 = gx;
@@ -484,15 +699,15 @@ impl GpuRenderer {
         let y1 = gy + glyph.height as f32;
 
         // Texture coordinates (normalized)
-        let atlas_width = self.atlas.width as f32;
-        let atlas_height = self.atlas.height as f32;
+        let atlas_width = atlas.width as f32;
+        let atlas_height = atlas.height as f32;
         let u0 = glyph.atlas_x as f32 / atlas_width;
         let v0 = glyph.atlas_y as f32 / atlas_height;
         let u1 = (glyph.atlas_x + glyph.width) as f32 / atlas_width;
         let v1 = (glyph.atlas_y + glyph.height) as f32 / atlas_height;
 
         // Two triangles forming a quad
-        self.text_vertices.extend_from_slice(&[
+        vertices.extend_from_slice(&[
             Vertex { position: [x0, y0], tex_coords: [u0, v0], color },
             Vertex { position: [x1, y0], tex_coords: [u1, v0], color },
             Vertex { position: [x1, y1], tex_coords: [u1, v1], color },
@@ -502,11 +717,43 @@ impl GpuRenderer {
         ]);
     }
 
-    /// Draws a string at the given position.
-    pub fn draw_text(&mut self, text: &str, mut x: f32, y: f32, color: [f32; 4]) {
-        for ch in text.chars() {
-            self.draw_char(ch, x, y, color);
-            x += self.atlas.char_width;
+    /// Draws a string of buffer text at the given position.
+    pub fn draw_text(&mut self, text: &str, x: f32, y: f32, color: [f32; 4]) {
+        self.draw_text_in(TextAtlas::Editor, text, x, y, color);
+    }
+
+    /// Draws a string of window chrome text at the given position.
+    pub fn draw_ui_text(&mut self, text: &str, x: f32, y: f32, color: [f32; 4]) {
+        self.draw_text_in(TextAtlas::Ui, text, x, y, color);
+    }
+
+    /// Draws a string with the glyphs of `atlas`.
+    pub fn draw_text_in(&mut self, atlas: TextAtlas, text: &str, x: f32, y: f32, color: [f32; 4]) {
+        self.draw_chars_in(atlas, text.chars(), x, y, color);
+    }
+
+    /// Draws characters of buffer text side by side, e.g. straight from
+    /// the rope without collecting them into a string first.
+    pub fn draw_chars(&mut self, chars: impl IntoIterator<Item = char>, x: f32, y: f32, color: [f32; 4]) {
+        self.draw_chars_in(TextAtlas::Editor, chars, x, y, color);
+    }
+
+    /// Draws characters side by side with the glyphs of `atlas`.
+    pub fn draw_chars_in(
+        &mut self,
+        atlas: TextAtlas,
+        chars: impl IntoIterator<Item = char>,
+        mut x: f32,
+        y: f32,
+        color: [f32; 4],
+    ) {
+        let char_width = match atlas {
+            TextAtlas::Editor => self.editor_atlas.char_width,
+            TextAtlas::Ui => self.ui_atlas.char_width,
+        };
+        for ch in chars {
+            self.draw_char_in(atlas, ch, x, y, color);
+            x += char_width;
         }
     }
 
@@ -559,74 +806,4 @@ impl GpuRenderer {
     pub fn dimensions(&self) -> (u32, u32) {
         (self.width, self.height)
     }
-
-    /// Renders all queued geometry.
-    pub fn render(
-        &self,
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        view: &wgpu::TextureView,
-    ) {
-        // Upload vertices to GPU
-        if !self.rect_vertices.is_empty() {
-            queue.write_buffer(
-                &self.rect_vertex_buffer,
-                0,
-                bytemuck::cast_slice(&self.rect_vertices),
-            );
-        }
-
-        if !self.text_vertices.is_empty() {
-            queue.write_buffer(
-                &self.text_vertex_buffer,
-                0,
-                bytemuck::cast_slice(&self.text_vertices),
-            );
-        }
-
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
-        });
-
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Main Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: self.colors.background[0] as f64,
-                            g: self.colors.background[1] as f64,
-                            b: self.colors.background[2] as f64,
-                            a: self.colors.background[3] as f64,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
-
-            // Draw rectangles first (backgrounds, selections)
-            if !self.rect_vertices.is_empty() {
-                render_pass.set_pipeline(&self.rect_pipeline);
-                render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-                render_pass.set_vertex_buffer(0, self.rect_vertex_buffer.slice(..));
-                render_pass.draw(0..self.rect_vertices.len() as u32, 0..1);
-            }
-
-            // Draw text on top
-            if !self.text_vertices.is_empty() {
-                render_pass.set_pipeline(&self.render_pipeline);
-                render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-                render_pass.set_bind_group(1, &self.atlas_bind_group, &[]);
-                render_pass.set_vertex_buffer(0, self.text_vertex_buffer.slice(..));
-                render_pass.draw(0..self.text_vertices.len() as u32, 0..1);
-            }
-        }
-
-        queue.submit(std::iter::once(encoder.finish()));
-    }
 }
@@ -0,0 +1,356 @@
+//! Gutter layout.
+//!
+//! The gutter is the strip left of the text made of optional columns
+//! (bookmarks, diagnostics, line numbers, fold markers) and a separator.
+//! The same layout is used for rendering and for routing mouse clicks, so the
+//! two can't drift apart.
+
+/// A column in the gutter, in left-to-right display order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GutterColumn {
+    /// Bookmark markers.
+    Bookmarks,
+    /// Diagnostic severity markers.
+    Diagnostics,
+    /// Line numbers.
+    LineNumbers,
+    /// Fold open/closed markers.
+    Folds,
+}
+
+impl GutterColumn {
+    /// All columns in display order.
+    pub const ALL: [GutterColumn; 4] = [
+        GutterColumn::Bookmarks,
+        GutterColumn::Diagnostics,
+        GutterColumn::LineNumbers,
+        GutterColumn::Folds,
+    ];
+
+    /// Returns the action a click on this column triggers, if any.
+    ///
+    /// Clicks on line numbers have no gutter action and place the cursor
+    /// like a click in the text.
+    pub fn click_action(self) -> Option<GutterAction> {
+        match self {
+            GutterColumn::Bookmarks => Some(GutterAction::ToggleBookmark),
+            GutterColumn::Diagnostics => Some(GutterAction::ShowDiagnostics),
+            GutterColumn::LineNumbers => None,
+            GutterColumn::Folds => Some(GutterAction::ToggleFold),
+        }
+    }
+}
+
+/// Action triggered by clicking a gutter column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GutterAction {
+    /// Toggle a bookmark on the clicked line.
+    ToggleBookmark,
+    /// Show the diagnostics on the clicked line.
+    ShowDiagnostics,
+    /// Toggle the fold starting on the clicked line.
+    ToggleFold,
+}
+
+/// How the gutter is visually separated from the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GutterSeparator {
+    /// A thin vertical line followed by a half-cell gap.
+    #[default]
+    Line,
+    /// A one-cell blank gap.
+    Gap,
+    /// No separator; the text starts right after the last column.
+    None,
+}
+
+/// Which gutter features are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GutterConfig {
+    /// Show the bookmark column.
+    pub bookmarks: bool,
+    /// Show the diagnostics column.
+    pub diagnostics: bool,
+    /// Show line numbers.
+    pub line_numbers: bool,
+    /// Show the fold marker column.
+    pub folds: bool,
+    /// Separator between gutter and text.
+    pub separator: GutterSeparator,
+}
+
+impl Default for GutterConfig {
+    fn default() -> Self {
+        Self {
+            bookmarks: true,
+            diagnostics: true,
+            line_numbers: true,
+            folds: true,
+            separator: GutterSeparator::default(),
+        }
+    }
+}
+
+impl GutterConfig {
+    /// Returns whether the given column is enabled.
+    pub fn is_enabled(&self, column: GutterColumn) -> bool {
+        match column {
+            GutterColumn::Bookmarks => self.bookmarks,
+            GutterColumn::Diagnostics => self.diagnostics,
+            GutterColumn::LineNumbers => self.line_numbers,
+            GutterColumn::Folds => self.folds,
+        }
+    }
+}
+
+/// Horizontal extent of one gutter column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GutterRegion {
+    /// Which column this is.
+    pub column: GutterColumn,
+    /// Left edge in pixels.
+    pub x: f32,
+    /// Width in pixels.
+    pub width: f32,
+}
+
+impl GutterRegion {
+    /// Returns whether `x` falls inside this column.
+    pub fn contains(&self, x: f32) -> bool {
+        x >= self.x && x < self.x + self.width
+    }
+}
+
+/// Computed gutter layout for a buffer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GutterLayout {
+    /// Enabled columns in display order.
+    regions: Vec<GutterRegion>,
+    /// Separator style.
+    separator: GutterSeparator,
+    /// Left edge of the separator.
+    separator_x: f32,
+    /// Total gutter width; the text starts here.
+    width: f32,
+}
+
+/// Marker columns are this many cells wide.
+const MARKER_CELLS: f32 = 2.0;
+/// Line numbers reserve room for at least this many digits.
+const MIN_LINE_NUMBER_DIGITS: usize = 4;
+/// Horizontal padding on each side of the line numbers, in pixels.
+const LINE_NUMBER_PADDING: f32 = 4.0;
+/// Width of the separator line, in pixels.
+const SEPARATOR_LINE_WIDTH: f32 = 1.0;
+/// Longest line number text: the digits of `usize::MAX`.
+pub const MAX_LINE_NUMBER_DIGITS: usize = 20;
+
+/// Writes `number` right-aligned in `width` columns into `buf` and returns
+/// the text, as `format!("{:>width$}", number)` would without allocating;
+/// it runs for every visible line each frame.
+pub fn format_line_number(number: usize, width: usize, buf: &mut [u8; MAX_LINE_NUMBER_DIGITS]) -> &str {
+    let mut start = buf.len();
+    let mut rest = number;
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (rest % 10) as u8;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+    let padded = start.min(buf.len().saturating_sub(width));
+    buf[padded..start].fill(b' ');
+    std::str::from_utf8(&buf[padded..]).expect("digits and spaces are ASCII")
+}
+
+impl GutterLayout {
+    /// Computes the layout for a buffer with `line_count` lines.
+    pub fn compute(config: &GutterConfig, char_width: f32, line_count: usize) -> Self {
+        let mut regions = Vec::with_capacity(GutterColumn::ALL.len());
+        let mut x = 0.0;
+
+        for column in GutterColumn::ALL {
+            if !config.is_enabled(column) {
+                continue;
+            }
+            let width = match column {
+                GutterColumn::LineNumbers => {
+                    Self::line_number_digits(line_count) as f32 * char_width + 2.0 * LINE_NUMBER_PADDING
+                }
+                _ => MARKER_CELLS * char_width,
+            };
+            regions.push(GutterRegion { column, x, width });
+            x += width;
+        }
+
+        let separator_x = x;
+        let separator_width = match config.separator {
+            GutterSeparator::Line => SEPARATOR_LINE_WIDTH + char_width / 2.0,
+            GutterSeparator::Gap => char_width,
+            GutterSeparator::None => 0.0,
+        };
+
+        Self {
+            regions,
+            separator: config.separator,
+            separator_x,
+            width: x + separator_width,
+        }
+    }
+
+    /// Number of digits reserved for line numbers.
+    pub fn line_number_digits(line_count: usize) -> usize {
+        (line_count.max(1).ilog10() as usize + 1).max(MIN_LINE_NUMBER_DIGITS)
+    }
+
+    /// Returns the total gutter width (the x where text starts).
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    /// Returns the enabled columns in display order.
+    pub fn regions(&self) -> &[GutterRegion] {
+        &self.regions
+    }
+
+    /// Returns the region for a column, if it is enabled.
+    pub fn region(&self, column: GutterColumn) -> Option<&GutterRegion> {
+        self.regions.iter().find(|r| r.column == column)
+    }
+
+    /// Returns the x position and width of the separator line, if one is drawn.
+    pub fn separator_line(&self) -> Option<(f32, f32)> {
+        match self.separator {
+            GutterSeparator::Line => Some((self.separator_x, SEPARATOR_LINE_WIDTH)),
+            _ => None,
+        }
+    }
+
+    /// Returns whether `x` is inside the gutter (columns or separator).
+    pub fn contains(&self, x: f32) -> bool {
+        x >= 0.0 && x < self.width
+    }
+
+    /// Returns the column at `x`, or `None` over the separator or the text.
+    pub fn hit_test(&self, x: f32) -> Option<GutterColumn> {
+        self.regions.iter().find(|r| r.contains(x)).map(|r| r.column)
+    }
+
+    /// Returns the click action for a click at `x`, if any.
+    pub fn click_action(&self, x: f32) -> Option<GutterAction> {
+        self.hit_test(x).and_then(GutterColumn::click_action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CW: f32 = 10.0;
+
+    #[test]
+    fn test_default_layout_widths() {
+        let layout = GutterLayout::compute(&GutterConfig::default(), CW, 100);
+
+        let columns: Vec<_> = layout.regions().iter().map(|r| r.column).collect();
+        assert_eq!(columns, GutterColumn::ALL);
+
+        // 20 + 20 + (4 digits * 10 + 8) + 20, then 1px line + half a cell
+        assert_eq!(layout.region(GutterColumn::LineNumbers).unwrap().x, 40.0);
+        assert_eq!(layout.region(GutterColumn::LineNumbers).unwrap().width, 48.0);
+        assert_eq!(layout.region(GutterColumn::Folds).unwrap().x, 88.0);
+        assert_eq!(layout.separator_line(), Some((108.0, 1.0)));
+        assert_eq!(layout.width(), 114.0);
+    }
+
+    #[test]
+    fn test_line_number_width_grows_with_line_count() {
+        assert_eq!(GutterLayout::line_number_digits(0), 4);
+        assert_eq!(GutterLayout::line_number_digits(9999), 4);
+        assert_eq!(GutterLayout::line_number_digits(10_000), 5);
+
+        let config = GutterConfig {
+            bookmarks: false,
+            diagnostics: false,
+            folds: false,
+            separator: GutterSeparator::None,
+            ..GutterConfig::default()
+        };
+        assert_eq!(GutterLayout::compute(&config, CW, 50).width(), 48.0);
+        assert_eq!(GutterLayout::compute(&config, CW, 123_456).width(), 68.0);
+    }
+
+    #[test]
+    fn test_format_line_number() {
+        let mut buf = [0; MAX_LINE_NUMBER_DIGITS];
+        for (number, width) in [(1, 4), (42, 4), (12345, 4), (0, 1), (7, 0), (usize::MAX, 4), (9, 30)] {
+            assert_eq!(
+                format_line_number(number, width, &mut buf),
+                format!("{:>w$}", number, w = width.min(MAX_LINE_NUMBER_DIGITS))
+            );
+        }
+    }
+
+    #[test]
+    fn test_separator_styles() {
+        let mut config = GutterConfig {
+            bookmarks: false,
+            diagnostics: false,
+            line_numbers: false,
+            folds: true,
+            separator: GutterSeparator::Gap,
+        };
+        let gap = GutterLayout::compute(&config, CW, 1);
+        assert_eq!(gap.width(), 30.0);
+        assert!(gap.separator_line().is_none());
+
+        config.separator = GutterSeparator::None;
+        assert_eq!(GutterLayout::compute(&config, CW, 1).width(), 20.0);
+    }
+
+    #[test]
+    fn test_empty_gutter() {
+        let config = GutterConfig {
+            bookmarks: false,
+            diagnostics: false,
+            line_numbers: false,
+            folds: false,
+            separator: GutterSeparator::None,
+        };
+        let layout = GutterLayout::compute(&config, CW, 10);
+        assert_eq!(layout.width(), 0.0);
+        assert!(!layout.contains(0.0));
+        assert_eq!(layout.hit_test(0.0), None);
+    }
+
+    #[test]
+    fn test_hit_test_dispatch() {
+        let layout = GutterLayout::compute(&GutterConfig::default(), CW, 100);
+
+        assert_eq!(layout.click_action(0.0), Some(GutterAction::ToggleBookmark));
+        assert_eq!(layout.click_action(19.9), Some(GutterAction::ToggleBookmark));
+        assert_eq!(layout.click_action(20.0), Some(GutterAction::ShowDiagnostics));
+        assert_eq!(layout.hit_test(60.0), Some(GutterColumn::LineNumbers));
+        assert_eq!(layout.click_action(60.0), None);
+        assert_eq!(layout.click_action(100.0), Some(GutterAction::ToggleFold));
+
+        // Separator is part of the gutter but has no column
+        assert!(layout.contains(110.0));
+        assert_eq!(layout.hit_test(110.0), None);
+        // Text area
+        assert!(!layout.contains(114.0));
+        assert_eq!(layout.hit_test(200.0), None);
+    }
+
+    #[test]
+    fn test_hit_test_skips_disabled_columns() {
+        let config = GutterConfig {
+            bookmarks: false,
+            ..GutterConfig::default()
+        };
+        let layout = GutterLayout::compute(&config, CW, 100);
+        assert!(layout.region(GutterColumn::Bookmarks).is_none());
+        assert_eq!(layout.click_action(0.0), Some(GutterAction::ShowDiagnostics));
+    }
+}
@@ -3,6 +3,11 @@
 use winit::event::{ElementState, MouseScrollDelta};
 use winit::keyboard::{Key, ModifiersState, NamedKey};
 
+use crate::settings::MouseSettings;
+
+/// Pixels of a trackpad scroll that make a line or column.
+const PIXELS_PER_LINE: f32 = 20.0;
+
 /// IME (Input Method Editor) composition state.
 /// This tracks the state of text being composed through an IME.
 #[derive(Debug, Default, Clone)]
@@ -50,14 +55,26 @@ impl ImeState {
 }
 
 /// Represents an editor command.
+///
+/// Each variant is registered with its handler and metadata in the
+/// [`crate::commands`] registry.
 #[derive(Debug, Clone, PartialEq)]
 pub enum EditorCommand {
     // File operations
     Save,
     SaveAs,
     OpenFile,
+    /// Pick a recently opened file to open again.
+    ReopenRecent,
+    /// Pick an encoding to read the file again in.
+    ReopenWithEncoding,
     NewFile,
     CloseTab,
+    CloseAllTabs,
+    /// Close every tab but the active one.
+    CloseOtherTabs,
+    /// Close the tabs without unsaved changes.
+    CloseSavedTabs,
     Quit,
 
     // Tab operations
@@ -80,6 +97,10 @@ pub enum EditorCommand {
     MoveDown,
     MoveWordLeft,
     MoveWordRight,
+    /// Move to the previous camelCase hump or snake_case part.
+    MoveSubWordLeft,
+    /// Move to the next camelCase hump or snake_case part.
+    MoveSubWordRight,
     MoveToLineStart,
     MoveToLineStartSmart,
     MoveToLineEnd,
@@ -95,6 +116,8 @@ pub enum EditorCommand {
     SelectDown,
     SelectWordLeft,
     SelectWordRight,
+    SelectSubWordLeft,
+    SelectSubWordRight,
     SelectToLineStart,
     SelectToLineStartSmart,
     SelectToLineEnd,
@@ -102,19 +125,35 @@ pub enum EditorCommand {
     SelectPageDown,
     SelectToBufferStart,
     SelectToBufferEnd,
+    ExpandSelection,
+    ShrinkSelection,
     SelectAll,
 
     // Line operations
     DuplicateLine,
     MoveLineUp,
     MoveLineDown,
+    /// Delete the cursor line, or every line the selection touches.
+    DeleteLine,
+    /// Open a line below the cursor line without splitting it.
+    InsertLineBelow,
+    /// Open a line above the cursor line without splitting it.
+    InsertLineAbove,
 
     // Block selection
     ToggleBlockSelection,
+    /// Move the block selection's cursor corner, starting a block first if
+    /// there is none.
+    BlockSelectUp,
+    BlockSelectDown,
+    BlockSelectLeft,
+    BlockSelectRight,
 
     // Multi-cursor
     AddCursorAbove,
     AddCursorBelow,
+    SelectNextOccurrence,
+    SelectAllOccurrences,
     CollapseCursors,
 
     // Undo/Redo
@@ -123,12 +162,20 @@ pub enum EditorCommand {
 
     // Clipboard
     Copy,
+    /// Copy the selected lines, or the buffer, with line numbers.
+    CopyWithLineNumbers,
+    /// Copy the selected lines, or the buffer, as a Markdown code block.
+    CopyAsMarkdownCodeBlock,
     Cut,
     Paste,
 
     // Scrolling
     ScrollUp(f32),
     ScrollDown(f32),
+    /// Scroll toward the start of the lines by a number of columns.
+    ScrollLeft(f32),
+    /// Scroll toward the end of the lines by a number of columns.
+    ScrollRight(f32),
 
     // Search & Replace
     OpenSearch,
@@ -136,24 +183,63 @@ pub enum EditorCommand {
     FindNext,
     FindPrev,
     CloseSearch,
+    OpenProjectSearch,
 
     // Navigation
     GoToLine,
+    /// Move to the bracket matching the one at the cursor, or to the close
+    /// of the enclosing pair.
+    JumpToMatchingBracket,
+    /// Select a bracket pair and what it encloses.
+    SelectToMatchingBracket,
 
     // LSP commands
     GotoDefinition,
     TriggerCompletion,
     RenameSymbol,
+    /// List the server's quick fixes and refactorings for the cursor.
+    ShowCodeActions,
+    TrustWorkspace,
+    EnableLspForFile,
 
     // Code editing
+    Indent,
+    Dedent,
     ToggleComment,
     ToggleWordWrap,
+    /// Step the buffer's indentation through 2, 4 and 8 spaces and tabs.
+    CycleIndentation,
+    /// Switch the file between LF and CRLF, or normalize mixed line endings.
+    ConvertLineEndings,
+    FilterSelection,
+    /// Remove bidi control characters from the buffer.
+    StripBidiControls,
+    /// Replace bidi control characters with `\u{XXXX}` escapes.
+    EscapeBidiControls,
+
+    // Diagnostics
+    CycleDiagnosticFilter,
+    ShowLineDiagnostics,
+    RunLinter,
+    ToggleLintPanel,
+    ShowProblems,
+    ExportDiagnostics,
+    ToggleDiffIgnoreWhitespace,
 
     // Code folding
     ToggleFold,
     FoldAll,
     UnfoldAll,
 
+    // View
+    ZoomIn,
+    ZoomOut,
+    ResetZoom,
+    ToggleZenMode,
+    ToggleSplit,
+    FocusLeftPane,
+    FocusRightPane,
+
     // Performance
     TogglePerfMetrics,
 }
@@ -163,6 +249,8 @@ pub struct InputHandler {
     modifiers: ModifiersState,
     /// IME composition state.
     pub ime: ImeState,
+    /// How the wheel scrolls.
+    pub mouse: MouseSettings,
 }
 
 impl Default for InputHandler {
@@ -176,6 +264,7 @@ impl InputHandler {
         Self {
             modifiers: ModifiersState::empty(),
             ime: ImeState::new(),
+            mouse: MouseSettings::default(),
         }
     }
 
@@ -183,32 +272,13 @@ impl InputHandler {
         self.modifiers = modifiers;
     }
 
-    fn is_primary_modifier(&self) -> bool {
-        #[cfg(target_os = "macos")]
-        {
-            self.modifiers.super_key()
-        }
-        #[cfg(not(target_os = "macos"))]
-        {
-            self.modifiers.control_key()
-        }
-    }
-
-    fn is_shift(&self) -> bool {
-        self.modifiers.shift_key()
-    }
-
-    fn is_alt(&self) -> bool {
-        self.modifiers.alt_key()
+    pub fn is_primary_modifier(&self) -> bool {
+        is_primary(self.modifiers)
     }
 
     /// Handle character input (for text entry).
     pub fn handle_char_input(&self, ch: char) -> Option<EditorCommand> {
-        // Skip control characters and characters that are handled by key events
-        if ch.is_control() || self.is_primary_modifier() {
-            return None;
-        }
-        Some(EditorCommand::InsertChar(ch))
+        char_command(ch, self.modifiers)
     }
 
     /// Handle key events using the new winit 0.30 API.
@@ -220,202 +290,465 @@ impl InputHandler {
         if state != ElementState::Pressed {
             return None;
         }
+        key_command(key, self.modifiers)
+    }
 
-        let primary = self.is_primary_modifier();
-        let shift = self.is_shift();
-        let alt = self.is_alt();
-
-        match key {
-            Key::Named(NamedKey::Enter) => Some(EditorCommand::InsertNewline),
-            Key::Named(NamedKey::Backspace) => Some(EditorCommand::DeleteBackward),
-            Key::Named(NamedKey::Delete) => Some(EditorCommand::DeleteForward),
-            Key::Named(NamedKey::ArrowLeft) => {
-                if primary && shift {
-                    Some(EditorCommand::SelectWordLeft)
-                } else if primary {
-                    Some(EditorCommand::MoveWordLeft)
-                } else if shift {
-                    Some(EditorCommand::SelectLeft)
-                } else {
-                    Some(EditorCommand::MoveLeft)
-                }
-            }
-            Key::Named(NamedKey::ArrowRight) => {
-                if primary && shift {
-                    Some(EditorCommand::SelectWordRight)
-                } else if primary {
-                    Some(EditorCommand::MoveWordRight)
-                } else if shift {
-                    Some(EditorCommand::SelectRight)
-                } else {
-                    Some(EditorCommand::MoveRight)
-                }
-            }
-            Key::Named(NamedKey::ArrowUp) => {
-                if primary && alt {
-                    // Ctrl+Alt+Up: Add cursor above
-                    Some(EditorCommand::AddCursorAbove)
-                } else if alt && shift {
-                    // Alt+Shift+Up: Extend block selection up
-                    Some(EditorCommand::SelectUp)
-                } else if alt {
-                    Some(EditorCommand::MoveLineUp)
-                } else if shift {
-                    Some(EditorCommand::SelectUp)
-                } else {
-                    Some(EditorCommand::MoveUp)
-                }
+    /// Maps a wheel or trackpad scroll to a scroll command along its
+    /// dominant axis, following the mouse settings.
+    pub fn handle_scroll(&self, delta: MouseScrollDelta) -> Option<EditorCommand> {
+        let (x, y) = match delta {
+            MouseScrollDelta::LineDelta(x, y) => (x * self.mouse.wheel_lines_per_notch, y * self.mouse.wheel_lines_per_notch),
+            MouseScrollDelta::PixelDelta(pos) => (pos.x as f32 / PIXELS_PER_LINE, pos.y as f32 / PIXELS_PER_LINE),
+        };
+        let sign = if self.mouse.invert_scroll { -1.0 } else { 1.0 };
+        let (x, y) = (x * sign * self.mouse.horizontal_scroll_multiplier, y * sign);
+        if x.abs() > y.abs() {
+            if x > 0.0 {
+                Some(EditorCommand::ScrollLeft(x.abs()))
+            } else {
+                Some(EditorCommand::ScrollRight(x.abs()))
             }
-            Key::Named(NamedKey::ArrowDown) => {
-                if primary && alt {
-                    // Ctrl+Alt+Down: Add cursor below
-                    Some(EditorCommand::AddCursorBelow)
-                } else if alt && shift {
-                    // Alt+Shift+Down: Extend block selection down
-                    Some(EditorCommand::SelectDown)
-                } else if alt {
-                    Some(EditorCommand::MoveLineDown)
-                } else if shift {
-                    Some(EditorCommand::SelectDown)
-                } else {
-                    Some(EditorCommand::MoveDown)
-                }
+        } else if y > 0.0 {
+            Some(EditorCommand::ScrollUp(y.abs()))
+        } else if y < 0.0 {
+            Some(EditorCommand::ScrollDown(y.abs()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns true if the platform's primary modifier is held: Cmd on macOS,
+/// Ctrl elsewhere.
+pub fn is_primary(modifiers: ModifiersState) -> bool {
+    if cfg!(target_os = "macos") {
+        modifiers.super_key()
+    } else {
+        modifiers.control_key()
+    }
+}
+
+/// Maps typed text to the command inserting it. Control characters and
+/// text typed with the primary modifier held come as key presses instead.
+pub fn char_command(ch: char, modifiers: ModifiersState) -> Option<EditorCommand> {
+    if ch.is_control() || is_primary(modifiers) {
+        return None;
+    }
+    Some(EditorCommand::InsertChar(ch))
+}
+
+/// Returns what `command` runs as while a block selection is active:
+/// Alt+Shift+Left/Right, which otherwise shrink and expand the selection,
+/// move the block's corner like Alt+Shift+Up/Down do. Only those two and
+/// Ctrl+Shift+B start a block.
+pub fn block_mode_command(command: EditorCommand) -> EditorCommand {
+    match command {
+        EditorCommand::ShrinkSelection => EditorCommand::BlockSelectLeft,
+        EditorCommand::ExpandSelection => EditorCommand::BlockSelectRight,
+        command => command,
+    }
+}
+
+/// Maps a key pressed with `modifiers` held to the command it runs, if
+/// any. Modes that take keys themselves, like the search bar, see them
+/// before this mapping does.
+pub fn key_command(key: &Key, modifiers: ModifiersState) -> Option<EditorCommand> {
+    let primary = is_primary(modifiers);
+    let shift = modifiers.shift_key();
+    let alt = modifiers.alt_key();
+
+    match key {
+        Key::Named(NamedKey::Enter) if primary && shift => Some(EditorCommand::InsertLineAbove),
+        Key::Named(NamedKey::Enter) if primary => Some(EditorCommand::InsertLineBelow),
+        Key::Named(NamedKey::Enter) => Some(EditorCommand::InsertNewline),
+        Key::Named(NamedKey::Backspace) => Some(EditorCommand::DeleteBackward),
+        Key::Named(NamedKey::Delete) => Some(EditorCommand::DeleteForward),
+        Key::Named(NamedKey::ArrowLeft) => {
+            if primary && alt && shift {
+                Some(EditorCommand::SelectSubWordLeft)
+            } else if primary && alt {
+                Some(EditorCommand::MoveSubWordLeft)
+            } else if primary && shift {
+                Some(EditorCommand::SelectWordLeft)
+            } else if alt && shift {
+                // Moves a block's corner instead while one is active, see
+                // block_mode_command
+                Some(EditorCommand::ShrinkSelection)
+            } else if primary {
+                Some(EditorCommand::MoveWordLeft)
+            } else if shift {
+                Some(EditorCommand::SelectLeft)
+            } else if alt {
+                Some(EditorCommand::FocusLeftPane)
+            } else {
+                Some(EditorCommand::MoveLeft)
             }
-            Key::Named(NamedKey::Escape) => {
-                // Escape: Close search or collapse multiple cursors to one
-                Some(EditorCommand::CloseSearch)
+        }
+        Key::Named(NamedKey::ArrowRight) => {
+            if primary && alt && shift {
+                Some(EditorCommand::SelectSubWordRight)
+            } else if primary && alt {
+                Some(EditorCommand::MoveSubWordRight)
+            } else if primary && shift {
+                Some(EditorCommand::SelectWordRight)
+            } else if alt && shift {
+                // Moves a block's corner instead while one is active, see
+                // block_mode_command
+                Some(EditorCommand::ExpandSelection)
+            } else if primary {
+                Some(EditorCommand::MoveWordRight)
+            } else if shift {
+                Some(EditorCommand::SelectRight)
+            } else if alt {
+                Some(EditorCommand::FocusRightPane)
+            } else {
+                Some(EditorCommand::MoveRight)
             }
-            Key::Named(NamedKey::F3) => {
-                if shift {
-                    Some(EditorCommand::FindPrev)
-                } else {
-                    Some(EditorCommand::FindNext)
-                }
+        }
+        Key::Named(NamedKey::ArrowUp) => {
+            if primary && alt {
+                // Ctrl+Alt+Up: Add cursor above
+                Some(EditorCommand::AddCursorAbove)
+            } else if alt && shift {
+                Some(EditorCommand::BlockSelectUp)
+            } else if alt {
+                Some(EditorCommand::MoveLineUp)
+            } else if shift {
+                Some(EditorCommand::SelectUp)
+            } else {
+                Some(EditorCommand::MoveUp)
             }
-            Key::Named(NamedKey::F2) => Some(EditorCommand::RenameSymbol),
-            Key::Named(NamedKey::F12) => Some(EditorCommand::GotoDefinition),
-            Key::Named(NamedKey::Home) => {
-                if primary {
-                    if shift {
-                        Some(EditorCommand::SelectToBufferStart)
-                    } else {
-                        Some(EditorCommand::MoveToBufferStart)
-                    }
-                } else if shift {
-                    // Smart home with selection
-                    Some(EditorCommand::SelectToLineStartSmart)
-                } else {
-                    // Smart home: toggles between first non-whitespace and line start
-                    Some(EditorCommand::MoveToLineStartSmart)
-                }
+        }
+        Key::Named(NamedKey::ArrowDown) => {
+            if primary && alt {
+                // Ctrl+Alt+Down: Add cursor below
+                Some(EditorCommand::AddCursorBelow)
+            } else if alt && shift {
+                Some(EditorCommand::BlockSelectDown)
+            } else if alt {
+                Some(EditorCommand::MoveLineDown)
+            } else if shift {
+                Some(EditorCommand::SelectDown)
+            } else {
+                Some(EditorCommand::MoveDown)
             }
-            Key::Named(NamedKey::End) => {
-                if primary {
-                    if shift {
-                        Some(EditorCommand::SelectToBufferEnd)
-                    } else {
-                        Some(EditorCommand::MoveToBufferEnd)
-                    }
-                } else if shift {
-                    Some(EditorCommand::SelectToLineEnd)
-                } else {
-                    Some(EditorCommand::MoveToLineEnd)
-                }
+        }
+        Key::Named(NamedKey::Escape) => {
+            // Escape: Close search or collapse multiple cursors to one
+            Some(EditorCommand::CloseSearch)
+        }
+        Key::Named(NamedKey::F3) => {
+            if shift {
+                Some(EditorCommand::FindPrev)
+            } else {
+                Some(EditorCommand::FindNext)
             }
-            Key::Named(NamedKey::PageUp) => {
+        }
+        Key::Named(NamedKey::F2) => Some(EditorCommand::RenameSymbol),
+        Key::Named(NamedKey::F12) => Some(EditorCommand::GotoDefinition),
+        Key::Named(NamedKey::F7) => Some(EditorCommand::RunLinter),
+        Key::Named(NamedKey::F11) => Some(EditorCommand::ToggleZenMode),
+        Key::Named(NamedKey::Home) => {
+            if primary {
                 if shift {
-                    Some(EditorCommand::SelectPageUp)
+                    Some(EditorCommand::SelectToBufferStart)
                 } else {
-                    Some(EditorCommand::MovePageUp)
+                    Some(EditorCommand::MoveToBufferStart)
                 }
+            } else if shift {
+                // Smart home with selection
+                Some(EditorCommand::SelectToLineStartSmart)
+            } else {
+                // Smart home: toggles between first non-whitespace and line start
+                Some(EditorCommand::MoveToLineStartSmart)
             }
-            Key::Named(NamedKey::PageDown) => {
+        }
+        Key::Named(NamedKey::End) => {
+            if primary {
                 if shift {
-                    Some(EditorCommand::SelectPageDown)
+                    Some(EditorCommand::SelectToBufferEnd)
                 } else {
-                    Some(EditorCommand::MovePageDown)
+                    Some(EditorCommand::MoveToBufferEnd)
                 }
+            } else if shift {
+                Some(EditorCommand::SelectToLineEnd)
+            } else {
+                Some(EditorCommand::MoveToLineEnd)
             }
-            // Tab navigation (must come before generic Tab handling)
-            Key::Named(NamedKey::Tab) if primary && shift => Some(EditorCommand::PrevTab),
-            Key::Named(NamedKey::Tab) if primary => Some(EditorCommand::NextTab),
-            Key::Named(NamedKey::Tab) => Some(EditorCommand::InsertChar('\t')),
-            Key::Named(NamedKey::Space) if primary => Some(EditorCommand::TriggerCompletion),
-            Key::Named(NamedKey::Space) => Some(EditorCommand::InsertChar(' ')),
-
-            // Alt shortcuts
-            Key::Character(ch) if alt && !primary => match ch.as_str() {
-                "z" | "Z" => Some(EditorCommand::ToggleWordWrap),
-                _ => None,
-            },
-
-            // Character shortcuts
-            Key::Character(ch) if primary => match ch.as_str() {
-                "s" | "S" if shift => Some(EditorCommand::SaveAs),
-                "s" | "S" => Some(EditorCommand::Save),
-                "o" | "O" => Some(EditorCommand::OpenFile),
-                "n" | "N" => Some(EditorCommand::NewFile),
-                "w" | "W" => Some(EditorCommand::CloseTab),
-                "q" | "Q" => Some(EditorCommand::Quit),
-                "z" => Some(EditorCommand::Undo),
-                "Z" => Some(EditorCommand::Redo),
-                "y" | "Y" => Some(EditorCommand::Redo),
-                "a" | "A" => Some(EditorCommand::SelectAll),
-                "d" | "D" => Some(EditorCommand::DuplicateLine),
-                "b" | "B" if shift => Some(EditorCommand::ToggleBlockSelection),
-                "p" | "P" if shift => Some(EditorCommand::TogglePerfMetrics),
-                // Clipboard
-                "c" | "C" => Some(EditorCommand::Copy),
-                "x" | "X" => Some(EditorCommand::Cut),
-                "v" | "V" => Some(EditorCommand::Paste),
-                // Comment toggle
-                "/" => Some(EditorCommand::ToggleComment),
-                // Code folding
-                "[" if shift => Some(EditorCommand::FoldAll),
-                "]" if shift => Some(EditorCommand::UnfoldAll),
-                "[" => Some(EditorCommand::ToggleFold),
-                // Search & Navigation
-                "f" | "F" => Some(EditorCommand::OpenSearch),
-                "h" | "H" => Some(EditorCommand::OpenReplace),
-                "g" | "G" => Some(EditorCommand::GoToLine),
-                // Tab switching with Ctrl+1-9
-                "1" => Some(EditorCommand::SwitchToTab(0)),
-                "2" => Some(EditorCommand::SwitchToTab(1)),
-                "3" => Some(EditorCommand::SwitchToTab(2)),
-                "4" => Some(EditorCommand::SwitchToTab(3)),
-                "5" => Some(EditorCommand::SwitchToTab(4)),
-                "6" => Some(EditorCommand::SwitchToTab(5)),
-                "7" => Some(EditorCommand::SwitchToTab(6)),
-                "8" => Some(EditorCommand::SwitchToTab(7)),
-                "9" => Some(EditorCommand::SwitchToTab(8)),
-                _ => None,
-            },
-
-            _ => None,
         }
-    }
-
-    pub fn handle_scroll(&self, delta: MouseScrollDelta) -> Option<EditorCommand> {
-        match delta {
-            MouseScrollDelta::LineDelta(_, y) => {
-                if y > 0.0 {
-                    Some(EditorCommand::ScrollUp(y.abs()))
-                } else if y < 0.0 {
-                    Some(EditorCommand::ScrollDown(y.abs()))
-                } else {
-                    None
-                }
+        Key::Named(NamedKey::PageUp) => {
+            if shift {
+                Some(EditorCommand::SelectPageUp)
+            } else {
+                Some(EditorCommand::MovePageUp)
             }
-            MouseScrollDelta::PixelDelta(pos) => {
-                let lines = pos.y as f32 / 20.0;
-                if lines > 0.0 {
-                    Some(EditorCommand::ScrollUp(lines.abs()))
-                } else if lines < 0.0 {
-                    Some(EditorCommand::ScrollDown(lines.abs()))
-                } else {
-                    None
-                }
+        }
+        Key::Named(NamedKey::PageDown) => {
+            if shift {
+                Some(EditorCommand::SelectPageDown)
+            } else {
+                Some(EditorCommand::MovePageDown)
             }
         }
+        // Tab navigation (must come before generic Tab handling)
+        Key::Named(NamedKey::Tab) if primary && shift => Some(EditorCommand::PrevTab),
+        Key::Named(NamedKey::Tab) if primary => Some(EditorCommand::NextTab),
+        Key::Named(NamedKey::Tab) if shift => Some(EditorCommand::Dedent),
+        Key::Named(NamedKey::Tab) => Some(EditorCommand::InsertChar('\t')),
+        Key::Named(NamedKey::Space) if primary => Some(EditorCommand::TriggerCompletion),
+        Key::Named(NamedKey::Space) => Some(EditorCommand::InsertChar(' ')),
+
+        // Alt shortcuts
+        Key::Character(ch) if alt && !primary => match ch.as_str() {
+            "z" | "Z" => Some(EditorCommand::ToggleWordWrap),
+            "d" | "D" => Some(EditorCommand::CycleDiagnosticFilter),
+            "i" | "I" => Some(EditorCommand::ShowLineDiagnostics),
+            "l" | "L" => Some(EditorCommand::ToggleLintPanel),
+            "p" | "P" => Some(EditorCommand::ShowProblems),
+            "w" | "W" => Some(EditorCommand::ToggleDiffIgnoreWhitespace),
+            "f" | "F" => Some(EditorCommand::FilterSelection),
+            "e" | "E" => Some(EditorCommand::ConvertLineEndings),
+            "b" | "B" if shift => Some(EditorCommand::EscapeBidiControls),
+            "b" | "B" => Some(EditorCommand::StripBidiControls),
+            _ => None,
+        },
+
+        // Character shortcuts
+        Key::Character(ch) if primary => match ch.as_str() {
+            "s" | "S" if shift => Some(EditorCommand::SaveAs),
+            "s" | "S" => Some(EditorCommand::Save),
+            "o" | "O" => Some(EditorCommand::OpenFile),
+            "r" | "R" if alt => Some(EditorCommand::ReopenWithEncoding),
+            "r" | "R" => Some(EditorCommand::ReopenRecent),
+            "n" | "N" => Some(EditorCommand::NewFile),
+            "w" | "W" if alt && shift => Some(EditorCommand::CloseSavedTabs),
+            "w" | "W" if alt => Some(EditorCommand::CloseOtherTabs),
+            "w" | "W" if shift => Some(EditorCommand::CloseAllTabs),
+            "w" | "W" => Some(EditorCommand::CloseTab),
+            "q" | "Q" => Some(EditorCommand::Quit),
+            "z" => Some(EditorCommand::Undo),
+            "Z" => Some(EditorCommand::Redo),
+            "y" | "Y" => Some(EditorCommand::Redo),
+            "a" | "A" => Some(EditorCommand::SelectAll),
+            "d" | "D" if shift => Some(EditorCommand::DuplicateLine),
+            "k" | "K" if shift => Some(EditorCommand::DeleteLine),
+            "d" | "D" => Some(EditorCommand::SelectNextOccurrence),
+            "b" | "B" if shift => Some(EditorCommand::ToggleBlockSelection),
+            "l" | "L" if shift => Some(EditorCommand::SelectAllOccurrences),
+            "p" | "P" if shift => Some(EditorCommand::TogglePerfMetrics),
+            // Clipboard
+            "c" | "C" if alt && shift => Some(EditorCommand::CopyAsMarkdownCodeBlock),
+            "c" | "C" if alt => Some(EditorCommand::CopyWithLineNumbers),
+            "c" | "C" => Some(EditorCommand::Copy),
+            "x" | "X" => Some(EditorCommand::Cut),
+            "v" | "V" => Some(EditorCommand::Paste),
+            // Comment toggle
+            "/" => Some(EditorCommand::ToggleComment),
+            // Code actions
+            "." => Some(EditorCommand::ShowCodeActions),
+            // Split panes
+            "\\" => Some(EditorCommand::ToggleSplit),
+            // Code folding
+            "[" if shift => Some(EditorCommand::FoldAll),
+            "]" if shift => Some(EditorCommand::UnfoldAll),
+            "[" => Some(EditorCommand::ToggleFold),
+            "]" => Some(EditorCommand::Indent),
+            // Search & Navigation
+            "f" | "F" if shift => Some(EditorCommand::OpenProjectSearch),
+            "f" | "F" => Some(EditorCommand::OpenSearch),
+            "h" | "H" => Some(EditorCommand::OpenReplace),
+            "g" | "G" => Some(EditorCommand::GoToLine),
+            "m" | "M" if shift => Some(EditorCommand::SelectToMatchingBracket),
+            "m" | "M" => Some(EditorCommand::JumpToMatchingBracket),
+            // Zoom
+            "=" | "+" => Some(EditorCommand::ZoomIn),
+            "-" => Some(EditorCommand::ZoomOut),
+            "0" => Some(EditorCommand::ResetZoom),
+            // Tab switching with Ctrl+1-9
+            "1" => Some(EditorCommand::SwitchToTab(0)),
+            "2" => Some(EditorCommand::SwitchToTab(1)),
+            "3" => Some(EditorCommand::SwitchToTab(2)),
+            "4" => Some(EditorCommand::SwitchToTab(3)),
+            "5" => Some(EditorCommand::SwitchToTab(4)),
+            "6" => Some(EditorCommand::SwitchToTab(5)),
+            "7" => Some(EditorCommand::SwitchToTab(6)),
+            "8" => Some(EditorCommand::SwitchToTab(7)),
+            "9" => Some(EditorCommand::SwitchToTab(8)),
+            _ => None,
+        },
+
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::dpi::PhysicalPosition;
+
+    /// The platform's primary modifier: Cmd on macOS, Ctrl elsewhere.
+    const PRIMARY: ModifiersState = if cfg!(target_os = "macos") {
+        ModifiersState::SUPER
+    } else {
+        ModifiersState::CONTROL
+    };
+    const SHIFT: ModifiersState = ModifiersState::SHIFT;
+    const ALT: ModifiersState = ModifiersState::ALT;
+    const NONE: ModifiersState = ModifiersState::empty();
+
+    fn named(key: NamedKey) -> Key {
+        Key::Named(key)
+    }
+
+    fn character(text: &str) -> Key {
+        Key::Character(text.into())
+    }
+
+    #[test]
+    fn test_key_commands() {
+        use EditorCommand as C;
+        use NamedKey::*;
+
+        let chords = [
+            // Movement
+            (named(ArrowLeft), NONE, C::MoveLeft),
+            (named(ArrowRight), NONE, C::MoveRight),
+            (named(ArrowUp), NONE, C::MoveUp),
+            (named(ArrowDown), NONE, C::MoveDown),
+            (named(ArrowLeft), PRIMARY, C::MoveWordLeft),
+            (named(ArrowRight), PRIMARY, C::MoveWordRight),
+            (named(ArrowLeft), PRIMARY | ALT, C::MoveSubWordLeft),
+            (named(ArrowRight), PRIMARY | ALT, C::MoveSubWordRight),
+            (named(Home), NONE, C::MoveToLineStartSmart),
+            (named(End), NONE, C::MoveToLineEnd),
+            (named(Home), PRIMARY, C::MoveToBufferStart),
+            (named(End), PRIMARY, C::MoveToBufferEnd),
+            (named(PageUp), NONE, C::MovePageUp),
+            (named(PageDown), NONE, C::MovePageDown),
+            (named(ArrowUp), ALT, C::MoveLineUp),
+            (named(ArrowDown), ALT, C::MoveLineDown),
+            (named(ArrowLeft), ALT, C::FocusLeftPane),
+            (named(ArrowRight), ALT, C::FocusRightPane),
+            // Selection
+            (named(ArrowLeft), SHIFT, C::SelectLeft),
+            (named(ArrowRight), SHIFT, C::SelectRight),
+            (named(ArrowUp), SHIFT, C::SelectUp),
+            (named(ArrowDown), SHIFT, C::SelectDown),
+            (named(ArrowLeft), PRIMARY | SHIFT, C::SelectWordLeft),
+            (named(ArrowRight), PRIMARY | SHIFT, C::SelectWordRight),
+            (named(ArrowLeft), PRIMARY | ALT | SHIFT, C::SelectSubWordLeft),
+            (named(ArrowRight), PRIMARY | ALT | SHIFT, C::SelectSubWordRight),
+            (named(ArrowLeft), ALT | SHIFT, C::ShrinkSelection),
+            (named(ArrowRight), ALT | SHIFT, C::ExpandSelection),
+            (named(ArrowUp), ALT | SHIFT, C::BlockSelectUp),
+            (named(ArrowDown), ALT | SHIFT, C::BlockSelectDown),
+            (named(Home), SHIFT, C::SelectToLineStartSmart),
+            (named(End), SHIFT, C::SelectToLineEnd),
+            (named(Home), PRIMARY | SHIFT, C::SelectToBufferStart),
+            (named(End), PRIMARY | SHIFT, C::SelectToBufferEnd),
+            (named(PageUp), SHIFT, C::SelectPageUp),
+            (named(PageDown), SHIFT, C::SelectPageDown),
+            (character("a"), PRIMARY, C::SelectAll),
+            (character("d"), PRIMARY, C::SelectNextOccurrence),
+            (character("L"), PRIMARY | SHIFT, C::SelectAllOccurrences),
+            (named(ArrowUp), PRIMARY | ALT, C::AddCursorAbove),
+            (named(ArrowDown), PRIMARY | ALT, C::AddCursorBelow),
+            // Editing
+            (named(Enter), NONE, C::InsertNewline),
+            (named(Backspace), NONE, C::DeleteBackward),
+            (named(Delete), NONE, C::DeleteForward),
+            (named(Tab), NONE, C::InsertChar('\t')),
+            (named(Tab), SHIFT, C::Dedent),
+            (named(Space), NONE, C::InsertChar(' ')),
+            (character("z"), PRIMARY, C::Undo),
+            (character("Z"), PRIMARY | SHIFT, C::Redo),
+            (character("y"), PRIMARY, C::Redo),
+            (character("c"), PRIMARY, C::Copy),
+            (character("x"), PRIMARY, C::Cut),
+            (character("v"), PRIMARY, C::Paste),
+            (character("/"), PRIMARY, C::ToggleComment),
+            (character("]"), PRIMARY, C::Indent),
+            (character("D"), PRIMARY | SHIFT, C::DuplicateLine),
+            (character("K"), PRIMARY | SHIFT, C::DeleteLine),
+            (named(Enter), PRIMARY, C::InsertLineBelow),
+            (named(Enter), PRIMARY | SHIFT, C::InsertLineAbove),
+            // Files, tabs and search
+            (character("s"), PRIMARY, C::Save),
+            (character("S"), PRIMARY | SHIFT, C::SaveAs),
+            (character("w"), PRIMARY, C::CloseTab),
+            (named(Tab), PRIMARY, C::NextTab),
+            (named(Tab), PRIMARY | SHIFT, C::PrevTab),
+            (character("3"), PRIMARY, C::SwitchToTab(2)),
+            (character("f"), PRIMARY, C::OpenSearch),
+            (named(F3), NONE, C::FindNext),
+            (named(F3), SHIFT, C::FindPrev),
+            (named(Escape), NONE, C::CloseSearch),
+            (character("\\"), PRIMARY, C::ToggleSplit),
+            (character("z"), ALT, C::ToggleWordWrap),
+            (character("e"), ALT, C::ConvertLineEndings),
+            (character("p"), ALT, C::ShowProblems),
+            (character("."), PRIMARY, C::ShowCodeActions),
+        ];
+        for (key, modifiers, command) in chords {
+            assert_eq!(key_command(&key, modifiers), Some(command), "{:?} with {:?}", key, modifiers);
+        }
+
+        // Unbound chords and plain characters, which come as text input
+        assert_eq!(key_command(&character("k"), PRIMARY), None);
+        assert_eq!(key_command(&character("a"), NONE), None);
+        assert_eq!(key_command(&named(F1), NONE), None);
+    }
+
+    #[test]
+    fn test_char_commands() {
+        assert_eq!(char_command('a', NONE), Some(EditorCommand::InsertChar('a')));
+        assert_eq!(char_command('A', SHIFT), Some(EditorCommand::InsertChar('A')));
+        assert_eq!(char_command('é', ALT), Some(EditorCommand::InsertChar('é')));
+        // Control characters and primary chords are key presses
+        assert_eq!(char_command('\u{8}', NONE), None);
+        assert_eq!(char_command('s', PRIMARY), None);
+    }
+
+    #[test]
+    fn test_handler_uses_held_modifiers() {
+        let mut input = InputHandler::new();
+        input.update_modifiers_state(SHIFT);
+        let key = named(NamedKey::ArrowLeft);
+        assert_eq!(input.handle_key_event_new(&key, ElementState::Pressed), Some(EditorCommand::SelectLeft));
+        assert_eq!(input.handle_key_event_new(&key, ElementState::Released), None);
+    }
+
+    #[test]
+    fn test_scroll_lines_per_notch() {
+        let mut input = InputHandler::new();
+        assert_eq!(input.handle_scroll(MouseScrollDelta::LineDelta(0.0, 1.0)), Some(EditorCommand::ScrollUp(1.0)));
+        assert_eq!(input.handle_scroll(MouseScrollDelta::LineDelta(0.0, -2.0)), Some(EditorCommand::ScrollDown(2.0)));
+        assert_eq!(input.handle_scroll(MouseScrollDelta::LineDelta(0.0, 0.0)), None);
+        input.mouse.wheel_lines_per_notch = 3.0;
+        assert_eq!(input.handle_scroll(MouseScrollDelta::LineDelta(0.0, -1.0)), Some(EditorCommand::ScrollDown(3.0)));
+        // Trackpads scroll by pixels, not notches
+        let pixels = MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, 40.0));
+        assert_eq!(input.handle_scroll(pixels), Some(EditorCommand::ScrollUp(2.0)));
+    }
+
+    #[test]
+    fn test_scroll_horizontally() {
+        let mut input = InputHandler::new();
+        assert_eq!(input.handle_scroll(MouseScrollDelta::LineDelta(-2.0, 0.5)), Some(EditorCommand::ScrollRight(2.0)));
+        assert_eq!(input.handle_scroll(MouseScrollDelta::LineDelta(1.0, 0.0)), Some(EditorCommand::ScrollLeft(1.0)));
+        input.mouse.horizontal_scroll_multiplier = 4.0;
+        assert_eq!(input.handle_scroll(MouseScrollDelta::LineDelta(-1.0, 0.0)), Some(EditorCommand::ScrollRight(4.0)));
+        // Vertical scrolling isn't scaled
+        assert_eq!(input.handle_scroll(MouseScrollDelta::LineDelta(0.0, 1.0)), Some(EditorCommand::ScrollUp(1.0)));
+    }
+
+    #[test]
+    fn test_invert_scroll() {
+        let mut input = InputHandler::new();
+        input.mouse.invert_scroll = true;
+        assert_eq!(input.handle_scroll(MouseScrollDelta::LineDelta(0.0, 1.0)), Some(EditorCommand::ScrollDown(1.0)));
+        assert_eq!(input.handle_scroll(MouseScrollDelta::LineDelta(-1.0, 0.0)), Some(EditorCommand::ScrollLeft(1.0)));
+        let pixels = MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, -20.0));
+        assert_eq!(input.handle_scroll(pixels), Some(EditorCommand::ScrollUp(1.0)));
     }
 }
@@ -0,0 +1,223 @@
+//! Keeps keyboard and mouse input away from the editor while a modal is up.
+//!
+//! A file dialog or confirmation owns the input until it closes; whatever
+//! reaches the editor window meanwhile is dropped, except plain text,
+//! which is held in a [`ReplayQueue`]. Once the modal reports when it
+//! closed, text typed within [`REPLAY_WINDOW`] after that is replayed
+//! into the editor, so resumed typing doesn't lose its first characters
+//! while the app catches up; text typed before it was meant for the modal
+//! and is thrown away.
+//!
+//! A system dialog blocks the event loop, so input the editor window got
+//! while the app was busy with it only arrives once it returned. A
+//! [`DialogSpan`] keeps the gate closed until that input has arrived, so
+//! it is held, and replayed, rather than handled half-way.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long after a modal closes typed text is still replayed.
+pub const REPLAY_WINDOW: Duration = Duration::from_millis(200);
+
+/// Most keystrokes held for replay; older ones are dropped first.
+const REPLAY_CAPACITY: usize = 32;
+
+/// A surface that takes the input instead of the editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modal {
+    /// A system file dialog.
+    Dialog,
+    /// A confirmation waiting for an answer.
+    Confirmation,
+    /// A menu the editor draws itself, like the tab overflow menu.
+    Menu,
+}
+
+/// Kind of input event being routed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    /// A key producing text without Ctrl or Alt held.
+    Text,
+    /// Any other key.
+    Key,
+    /// A mouse button or wheel.
+    Mouse,
+}
+
+/// Where an input event goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputRoute {
+    /// To the editor, as usual.
+    Editor,
+    /// To the modal, which handles it.
+    Modal,
+    /// Into the replay queue.
+    Held,
+    /// Nowhere.
+    Dropped,
+}
+
+/// Decides where an input event goes while `modal` is up.
+pub fn route(modal: Option<Modal>, input: InputKind) -> InputRoute {
+    match (modal, input) {
+        (None, _) => InputRoute::Editor,
+        (Some(Modal::Menu), _) => InputRoute::Modal,
+        (Some(Modal::Dialog | Modal::Confirmation), InputKind::Text) => InputRoute::Held,
+        (Some(Modal::Dialog | Modal::Confirmation), _) => InputRoute::Dropped,
+    }
+}
+
+/// A blocking system dialog's time on screen.
+///
+/// The dialog keeps the input from when it opened until the event loop has
+/// delivered everything queued before it closed.
+#[derive(Debug, Default)]
+pub struct DialogSpan {
+    /// The dialog, while it is up or the input queued meanwhile is still
+    /// arriving.
+    current: Option<Modal>,
+    /// When the dialog closed, until its input has all arrived.
+    closed_at: Option<Instant>,
+}
+
+impl DialogSpan {
+    /// Creates a span with no dialog.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that a dialog taking input as `modal` opened.
+    pub fn open(&mut self, modal: Modal) {
+        self.current = Some(modal);
+        self.closed_at = None;
+    }
+
+    /// Records that the dialog closed at `now`. Input arriving until
+    /// [`DialogSpan::drained`] still belongs to it.
+    pub fn close(&mut self, now: Instant) {
+        if self.current.is_some() {
+            self.closed_at = Some(now);
+        }
+    }
+
+    /// Returns whether a dialog is on screen.
+    pub fn is_open(&self) -> bool {
+        self.current.is_some() && self.closed_at.is_none()
+    }
+
+    /// Returns the dialog that has the input, if any.
+    pub fn modal(&self) -> Option<Modal> {
+        self.current
+    }
+
+    /// Ends the span once the event loop has run out of queued input.
+    /// Returns when the dialog closed, if one closed since the last call.
+    pub fn drained(&mut self) -> Option<Instant> {
+        let closed_at = self.closed_at.take()?;
+        self.current = None;
+        Some(closed_at)
+    }
+}
+
+/// Text typed while a modal was up, with when it was typed.
+#[derive(Debug, Default)]
+pub struct ReplayQueue {
+    held: VecDeque<(Instant, String)>,
+}
+
+impl ReplayQueue {
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Holds `text` typed at `at`, dropping the oldest text when full.
+    pub fn hold(&mut self, text: &str, at: Instant) {
+        if self.held.len() == REPLAY_CAPACITY {
+            self.held.pop_front();
+        }
+        self.held.push_back((at, text.to_string()));
+    }
+
+    /// Returns whether any text is held.
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    /// Empties the queue once the modal closed at `closed_at`, returning
+    /// the text typed within [`REPLAY_WINDOW`] after that, oldest first.
+    pub fn take_after_close(&mut self, closed_at: Instant) -> Vec<String> {
+        let window_end = closed_at + REPLAY_WINDOW;
+        self.held
+            .drain(..)
+            .filter(|(at, _)| *at >= closed_at && *at <= window_end)
+            .map(|(_, text)| text)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        for input in [InputKind::Text, InputKind::Key, InputKind::Mouse] {
+            assert_eq!(route(None, input), InputRoute::Editor);
+            assert_eq!(route(Some(Modal::Menu), input), InputRoute::Modal);
+        }
+        for modal in [Modal::Dialog, Modal::Confirmation] {
+            assert_eq!(route(Some(modal), InputKind::Text), InputRoute::Held);
+            assert_eq!(route(Some(modal), InputKind::Key), InputRoute::Dropped);
+            assert_eq!(route(Some(modal), InputKind::Mouse), InputRoute::Dropped);
+        }
+    }
+
+    #[test]
+    fn test_dialog_span() {
+        let t0 = Instant::now();
+        let at = |millis: u64| t0 + Duration::from_millis(millis);
+        let mut span = DialogSpan::new();
+        assert_eq!(span.modal(), None);
+        assert_eq!(span.drained(), None);
+
+        span.open(Modal::Dialog);
+        assert!(span.is_open());
+        assert_eq!(span.drained(), None);
+        // Input queued meanwhile arrives after it closed, and the dialog
+        // keeps it until it has all arrived
+        span.close(at(500));
+        assert!(!span.is_open());
+        assert_eq!(span.modal(), Some(Modal::Dialog));
+        assert_eq!(span.drained(), Some(at(500)));
+        assert_eq!(span.modal(), None);
+        assert_eq!(span.drained(), None);
+
+        span.open(Modal::Confirmation);
+        assert_eq!(span.modal(), Some(Modal::Confirmation));
+    }
+
+    #[test]
+    fn test_replay_queue() {
+        let t0 = Instant::now();
+        let at = |millis: u64| t0 + Duration::from_millis(millis);
+        let mut queue = ReplayQueue::new();
+
+        // Typed for the dialog, just after it closed at 100ms, right at
+        // the end of the window, then too long after
+        queue.hold("x", at(50));
+        queue.hold("a", at(120));
+        queue.hold("b", at(300));
+        queue.hold("c", at(400));
+        assert_eq!(queue.take_after_close(at(100)), ["a", "b"]);
+        assert!(queue.is_empty());
+
+        // Bounded, keeping the latest
+        for i in 0..REPLAY_CAPACITY + 5 {
+            queue.hold(&i.to_string(), at(100));
+        }
+        let replayed = queue.take_after_close(at(0));
+        assert_eq!(replayed.len(), REPLAY_CAPACITY);
+        assert_eq!(replayed[0], "5");
+    }
+}
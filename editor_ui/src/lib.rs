@@ -3,18 +3,51 @@
 //! This crate provides GPU-accelerated text rendering using wgpu
 //! and input handling using winit.
 
+#[cfg(any(test, feature = "alloc-count"))]
+pub mod alloc_count;
+pub mod ansi;
+pub mod commands;
 pub mod app;
+pub mod code_actions;
+pub mod cursor_blink;
+pub mod diagnostics_popup;
+pub mod disk_banner;
 pub mod font;
+pub mod frame;
 pub mod gpu_renderer;
+pub mod gutter;
 pub mod input;
+pub mod input_gate;
 pub mod lsp;
 pub mod notifications;
+pub mod popup_anchor;
+pub mod problems;
+pub mod project_search;
+pub mod settings;
+pub mod tab_menu;
+pub mod tab_switcher;
+pub mod text_field;
+pub mod trust;
+pub mod window_geometry;
+pub mod zen;
 
 // Keep the old renderer module for reference, but it's deprecated
 #[deprecated(note = "Use gpu_renderer instead")]
 pub mod renderer;
 
-pub use app::{run, EditorApp};
-pub use gpu_renderer::GpuRenderer;
+pub use app::{run, ChromeMetrics, EditorApp};
+pub use code_actions::{CodeActionEntry, CodeActionMenu};
+pub use diagnostics_popup::DiagnosticsPopup;
+pub use gpu_renderer::{DrawList, GpuRenderer, TextAtlas};
 pub use lsp::{LspEvent, LspManager};
 pub use notifications::{Notification, NotificationManager, NotificationType};
+pub use popup_anchor::{HoverAnchor, PopupAnchor};
+pub use problems::{ProblemEntry, ProblemsPanel};
+pub use project_search::{ProjectSearch, ProjectSearchHit};
+pub use settings::{LspSettings, MouseSettings, NotificationSettings, SaveSettings, Settings};
+pub use tab_menu::{TabMenu, TabMenuEntry};
+pub use tab_switcher::TabSwitcher;
+pub use text_field::TextField;
+pub use trust::TrustStore;
+pub use window_geometry::WindowGeometry;
+pub use zen::ZenMode;
@@ -3,9 +3,16 @@
 //! This module provides LSP integration for the editor, managing LSP clients
 //! and polling for updates without blocking the UI.
 
-use cp_editor_core::{CompletionItem, CompletionKind, Diagnostic, DiagnosticSeverity, HoverInfo};
-use cp_editor_lsp::{LspClient, LspHandle, LspNotification, LspResponse, ServerConfig};
-use std::collections::HashMap;
+use crate::code_actions::CodeActionEntry;
+use cp_editor_core::syntax::path_matches;
+use cp_editor_core::template::{self, TemplateVars, UndefinedVariable};
+use cp_editor_core::{CompletionItem, CompletionKind, Diagnostic, DiagnosticSeverity, HoverInfo, SignatureHelp};
+use cp_editor_lsp::{
+    Capabilities, LspClient, LspHandle, LspNotification, LspResponse, Position, PositionEncoding, Range,
+    ServerConfig, WorkspaceEdit,
+};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
 /// Manages LSP clients and state for the editor.
@@ -18,18 +25,92 @@ pub struct LspManager {
     enabled: bool,
     /// Current workspace root.
     workspace_root: Option<PathBuf>,
+    /// What the server of each language supports, once it initialized.
+    capabilities: HashMap<String, Capabilities>,
+    /// Documents the servers were told are open, with their language.
+    open_documents: HashMap<PathBuf, String>,
+    /// Whether the user trusts the workspace root; servers only start in
+    /// a trusted one.
+    workspace_trusted: bool,
+    /// Whether a server was held back for want of trust since the last
+    /// [`LspManager::take_trust_request`].
+    trust_requested: bool,
+    /// Languages kept from the servers.
+    disabled_languages: Vec<String>,
+    /// Path patterns, relative to the workspace root, of files kept from
+    /// the servers.
+    exclude_globs: Vec<String>,
+    /// Excluded files let through for the rest of the session.
+    reenabled: HashSet<PathBuf>,
 }
 
 /// Types of pending requests.
 #[derive(Debug, Clone)]
 enum PendingRequest {
+    Initialize { language: String },
     Hover { path: PathBuf },
     Completion { path: PathBuf },
+    SignatureHelp { path: PathBuf },
     GotoDefinition { path: PathBuf },
-    References { path: PathBuf },
-    Rename { path: PathBuf },
+    References {
+        #[allow(dead_code)]
+        path: PathBuf,
+    },
+    Rename {
+        #[allow(dead_code)]
+        path: PathBuf,
+    },
+    CodeAction { path: PathBuf },
+    ExecuteCommand { command: String },
+    PullDiagnostics { path: PathBuf },
+}
+
+/// A request the editor sends only to servers that support it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LspFeature {
+    Hover,
+    Completion,
+    SignatureHelp,
+    GotoDefinition,
+    References,
+    Rename,
+    Formatting,
+    CodeAction,
+}
+
+impl LspFeature {
+    /// Returns the feature's display name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Hover => "Hover",
+            Self::Completion => "Completion",
+            Self::SignatureHelp => "Signature help",
+            Self::GotoDefinition => "Go to definition",
+            Self::References => "Find references",
+            Self::Rename => "Rename",
+            Self::Formatting => "Formatting",
+            Self::CodeAction => "Code actions",
+        }
+    }
+
+    /// Returns whether `capabilities` include the feature.
+    fn is_supported(&self, capabilities: &Capabilities) -> bool {
+        match self {
+            Self::Hover => capabilities.hover,
+            Self::Completion => capabilities.completion,
+            Self::SignatureHelp => capabilities.signature_help,
+            Self::GotoDefinition => capabilities.definition,
+            Self::References => capabilities.references,
+            Self::Rename => capabilities.rename,
+            Self::Formatting => capabilities.formatting,
+            Self::CodeAction => capabilities.code_action,
+        }
+    }
 }
 
+/// Edits for one file as (start_line, start_col, end_line, end_col, new_text).
+pub type FileEdits = (PathBuf, Vec<(usize, usize, usize, usize, String)>);
+
 /// LSP event to be handled by the UI.
 #[derive(Debug, Clone)]
 pub enum LspEvent {
@@ -48,6 +129,11 @@ pub enum LspEvent {
         path: PathBuf,
         items: Vec<CompletionItem>,
     },
+    /// Signature help received; `None` if the cursor isn't in a call.
+    SignatureHelp {
+        path: PathBuf,
+        help: Option<SignatureHelp>,
+    },
     /// Go to definition result.
     GotoDefinition {
         path: PathBuf,
@@ -55,7 +141,18 @@ pub enum LspEvent {
     },
     /// Rename result with workspace edits.
     Rename {
-        edits: Vec<(PathBuf, Vec<(usize, usize, usize, usize, String)>)>,
+        edits: Vec<FileEdits>,
+    },
+    /// Code actions offered for a range; empty if there are none or the
+    /// request failed.
+    CodeActions {
+        path: PathBuf,
+        actions: Vec<CodeActionEntry>,
+    },
+    /// Server-initiated workspace edit (`workspace/applyEdit`).
+    ApplyEdit {
+        label: Option<String>,
+        edits: Vec<FileEdits>,
     },
     /// Server initialized.
     ServerReady { language: String },
@@ -77,6 +174,13 @@ impl LspManager {
             pending_requests: HashMap::new(),
             enabled: true,
             workspace_root: None,
+            capabilities: HashMap::new(),
+            open_documents: HashMap::new(),
+            workspace_trusted: false,
+            trust_requested: false,
+            disabled_languages: Vec::new(),
+            exclude_globs: Vec::new(),
+            reenabled: HashSet::new(),
         }
     }
 
@@ -90,6 +194,54 @@ impl LspManager {
         self.workspace_root.as_deref()
     }
 
+    /// Sets whether the workspace root is trusted to run language servers.
+    pub fn set_workspace_trusted(&mut self, trusted: bool) {
+        self.workspace_trusted = trusted;
+    }
+
+    /// Returns true if the workspace root is trusted to run language
+    /// servers.
+    pub fn is_workspace_trusted(&self) -> bool {
+        self.workspace_trusted
+    }
+
+    /// Returns true, once, if a server was held back because the
+    /// workspace isn't trusted, so the user can be asked about it.
+    pub fn take_trust_request(&mut self) -> bool {
+        std::mem::take(&mut self.trust_requested)
+    }
+
+    /// Sets the languages and the path patterns, relative to the workspace
+    /// root, whose files are kept from the servers.
+    pub fn set_exclusions(&mut self, disabled_languages: Vec<String>, exclude_globs: Vec<String>) {
+        self.disabled_languages = disabled_languages;
+        self.exclude_globs = exclude_globs;
+    }
+
+    /// Returns true if the file at `path` is kept from the servers: its
+    /// `language` is disabled or it matches an exclude pattern, and it
+    /// wasn't [re-enabled](Self::reenable). Such files are treated like
+    /// files no server knows.
+    pub fn is_excluded(&self, path: &Path, language: &str) -> bool {
+        if self.reenabled.contains(path) {
+            return false;
+        }
+        if self.disabled_languages.iter().any(|disabled| disabled == language) {
+            return true;
+        }
+        let relative = self
+            .workspace_root
+            .as_deref()
+            .and_then(|root| path.strip_prefix(root).ok())
+            .unwrap_or(path);
+        self.exclude_globs.iter().any(|pattern| path_matches(pattern, relative))
+    }
+
+    /// Lets an excluded file reach the servers for the rest of the session.
+    pub fn reenable(&mut self, path: &Path) {
+        self.reenabled.insert(path.to_path_buf());
+    }
+
     /// Returns true if LSP is enabled.
     pub fn is_enabled(&self) -> bool {
         self.enabled
@@ -103,12 +255,25 @@ impl LspManager {
         }
     }
 
+    /// Returns true if a language server is running for `language`.
+    pub fn has_client(&self, language: &str) -> bool {
+        self.clients.contains_key(language)
+    }
+
+    /// Registers a client for `language`, e.g. a detached one in tests.
+    #[cfg(test)]
+    pub(crate) fn insert_client(&mut self, language: &str, client: LspClient) {
+        self.clients.insert(language.to_string(), client);
+    }
+
     /// Returns an LSP handle for the given language.
     fn get_handle(&self, language: &str) -> Option<LspHandle> {
         self.clients.get(language).map(|c| c.handle())
     }
 
     /// Starts an LSP client for the given language if not already running.
+    /// Servers can run code from the project, so none is started until the
+    /// workspace is trusted.
     pub fn start_client(&mut self, language: &str) -> bool {
         if !self.enabled {
             return false;
@@ -130,6 +295,21 @@ impl LspManager {
         };
 
         if let Some(config) = config {
+            if !self.workspace_trusted {
+                self.trust_requested = true;
+                return false;
+            }
+            let vars = TemplateVars {
+                workspace_root: self.workspace_root.clone(),
+                ..TemplateVars::default()
+            };
+            let config = match expand_server_config(&config, &vars) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::warn!("Invalid LSP config for {}: {}", language, e);
+                    return false;
+                }
+            };
             match LspClient::start(config) {
                 Ok(client) => {
                     log::info!("Started LSP client for {}", language);
@@ -138,7 +318,13 @@ impl LspManager {
                     // Initialize the server if we have a workspace root
                     if let Some(ref root) = self.workspace_root {
                         if let Some(handle) = self.get_handle(language) {
-                            handle.initialize(root.clone());
+                            let id = handle.initialize(root.clone());
+                            self.pending_requests.insert(
+                                id,
+                                PendingRequest::Initialize {
+                                    language: language.to_string(),
+                                },
+                            );
                         }
                     }
                     return true;
@@ -152,9 +338,10 @@ impl LspManager {
         false
     }
 
-    /// Notifies LSP that a document was opened.
+    /// Notifies LSP that a document was opened. Does nothing if it is
+    /// already open.
     pub fn did_open(&mut self, path: &Path, language: &str, text: &str) {
-        if !self.enabled {
+        if !self.enabled || self.open_documents.contains_key(path) || self.is_excluded(path, language) {
             return;
         }
 
@@ -163,34 +350,127 @@ impl LspManager {
 
         if let Some(handle) = self.get_handle(language) {
             handle.did_open(path.to_path_buf(), language, text.to_string());
+            self.open_documents.insert(path.to_path_buf(), language.to_string());
         }
+        self.pull_diagnostics(path, language);
     }
 
     /// Notifies LSP that a document changed.
     pub fn did_change(&mut self, path: &Path, language: &str, version: i32, text: &str) {
-        if !self.enabled {
+        if !self.enabled || self.is_excluded(path, language) {
             return;
         }
 
         if let Some(handle) = self.get_handle(language) {
             handle.did_change(path.to_path_buf(), version, text.to_string());
         }
+        self.pull_diagnostics(path, language);
     }
 
     /// Notifies LSP that a document was saved.
     pub fn did_save(&mut self, path: &Path, language: &str) {
-        if !self.enabled {
+        if !self.enabled || self.is_excluded(path, language) {
             return;
         }
 
         if let Some(handle) = self.get_handle(language) {
             handle.did_save(path.to_path_buf());
         }
+        self.pull_diagnostics(path, language);
+    }
+
+    /// Returns what the server for `language` supports, once it initialized.
+    pub fn capabilities(&self, language: &str) -> Option<&Capabilities> {
+        self.capabilities.get(language)
+    }
+
+    /// Sets what the server for `language` supports, e.g. in tests.
+    pub fn set_capabilities(&mut self, language: &str, capabilities: Capabilities) {
+        self.capabilities.insert(language.to_string(), capabilities);
+    }
+
+    /// Returns true if a server runs for `language` and supports `feature`.
+    /// Until the server said what it supports, every feature is assumed to
+    /// be.
+    pub fn supports(&self, language: &str, feature: LspFeature) -> bool {
+        self.enabled
+            && self.has_client(language)
+            && self.capabilities(language).is_none_or(|caps| feature.is_supported(caps))
+    }
+
+    /// Returns true if the server for `language` supports renaming.
+    pub fn supports_rename(&self, language: &str) -> bool {
+        self.supports(language, LspFeature::Rename)
+    }
+
+    /// Returns true if the server for `language` supports formatting.
+    pub fn supports_formatting(&self, language: &str) -> bool {
+        self.supports(language, LspFeature::Formatting)
+    }
+
+    /// Returns true if the server for `language` supports pull diagnostics.
+    pub fn supports_pull_diagnostics(&self, language: &str) -> bool {
+        self.capabilities(language).is_some_and(|caps| caps.pull_diagnostics)
+    }
+
+    /// Returns the characters that request completion when typed in a
+    /// `language` document.
+    pub fn completion_trigger_characters(&self, language: &str) -> &[String] {
+        self.capabilities(language)
+            .map_or(&[], |caps| caps.completion_trigger_characters.as_slice())
+    }
+
+    /// Returns true if typing `ch` in a `language` document requests
+    /// completion.
+    pub fn is_completion_trigger(&self, language: &str, ch: char) -> bool {
+        self.supports(language, LspFeature::Completion)
+            && self.capabilities(language).is_some_and(|caps| caps.is_completion_trigger(ch))
+    }
+
+    /// Returns true if typing `ch` in a `language` document requests
+    /// signature help: the server's trigger characters, or `(` and `,`
+    /// until it said which they are.
+    pub fn is_signature_help_trigger(&self, language: &str, ch: char) -> bool {
+        if !self.supports(language, LspFeature::SignatureHelp) {
+            return false;
+        }
+        match self.capabilities(language) {
+            Some(caps) => caps.is_signature_help_trigger(ch),
+            None => matches!(ch, '(' | ','),
+        }
+    }
+
+    /// Returns the unit the server for `language` counts columns in.
+    pub fn position_encoding(&self, language: &str) -> PositionEncoding {
+        self.capabilities(language)
+            .map(|caps| caps.position_encoding)
+            .unwrap_or_default()
+    }
+
+    /// Returns character column `col` of `line`, whose text is `line_text`,
+    /// as a position of the server for `language`.
+    pub fn position(&self, language: &str, line_text: &str, line: usize, col: usize) -> Position {
+        Position::new(line as u32, self.position_encoding(language).encode_col(line_text, col))
+    }
+
+    /// Requests diagnostics for a document if its server supports pulling
+    /// them; otherwise the server pushes them on its own.
+    fn pull_diagnostics(&mut self, path: &Path, language: &str) {
+        if !self.supports_pull_diagnostics(language) || self.is_excluded(path, language) {
+            return;
+        }
+
+        if let Some(handle) = self.get_handle(language) {
+            let id = handle.pull_diagnostics(path.to_path_buf());
+            self.pending_requests
+                .insert(id, PendingRequest::PullDiagnostics { path: path.to_path_buf() });
+        }
     }
 
-    /// Notifies LSP that a document was closed.
+    /// Notifies LSP that a document was closed. Does nothing if it isn't
+    /// open.
     pub fn did_close(&mut self, path: &Path, language: &str) {
-        if !self.enabled {
+        if !self.enabled || self.open_documents.remove(path).is_none() {
             return;
         }
 
@@ -199,16 +479,21 @@ impl LspManager {
         }
     }
 
+    /// Returns true if the servers were told `path` is open.
+    pub fn is_open(&self, path: &Path) -> bool {
+        self.open_documents.contains_key(path)
+    }
+
     /// Requests hover information.
-    pub fn hover(&mut self, path: &Path, language: &str, line: usize, col: usize) {
-        if !self.enabled {
+    pub fn hover(&mut self, path: &Path, language: &str, position: Position) {
+        if !self.enabled || self.is_excluded(path, language) {
             return;
         }
 
         if let Some(handle) = self.get_handle(language) {
             let id = handle.hover(
                 path.to_path_buf(),
-                cp_editor_lsp::Position::new(line as u32, col as u32),
+                position,
             );
             self.pending_requests
                 .insert(id, PendingRequest::Hover { path: path.to_path_buf() });
@@ -216,31 +501,47 @@ impl LspManager {
     }
 
     /// Requests completions.
-    pub fn completion(&mut self, path: &Path, language: &str, line: usize, col: usize) {
-        if !self.enabled {
+    pub fn completion(&mut self, path: &Path, language: &str, position: Position) {
+        if !self.enabled || self.is_excluded(path, language) {
             return;
         }
 
         if let Some(handle) = self.get_handle(language) {
             let id = handle.completion(
                 path.to_path_buf(),
-                cp_editor_lsp::Position::new(line as u32, col as u32),
+                position,
             );
             self.pending_requests
                 .insert(id, PendingRequest::Completion { path: path.to_path_buf() });
         }
     }
 
+    /// Requests signature help.
+    pub fn signature_help(&mut self, path: &Path, language: &str, position: Position) {
+        if !self.enabled || self.is_excluded(path, language) {
+            return;
+        }
+
+        if let Some(handle) = self.get_handle(language) {
+            let id = handle.signature_help(
+                path.to_path_buf(),
+                position,
+            );
+            self.pending_requests
+                .insert(id, PendingRequest::SignatureHelp { path: path.to_path_buf() });
+        }
+    }
+
     /// Requests go to definition.
-    pub fn goto_definition(&mut self, path: &Path, language: &str, line: usize, col: usize) {
-        if !self.enabled {
+    pub fn goto_definition(&mut self, path: &Path, language: &str, position: Position) {
+        if !self.enabled || self.is_excluded(path, language) {
             return;
         }
 
         if let Some(handle) = self.get_handle(language) {
             let id = handle.goto_definition(
                 path.to_path_buf(),
-                cp_editor_lsp::Position::new(line as u32, col as u32),
+                position,
             );
             self.pending_requests
                 .insert(id, PendingRequest::GotoDefinition { path: path.to_path_buf() });
@@ -248,15 +549,15 @@ impl LspManager {
     }
 
     /// Requests find references.
-    pub fn find_references(&mut self, path: &Path, language: &str, line: usize, col: usize) {
-        if !self.enabled {
+    pub fn find_references(&mut self, path: &Path, language: &str, position: Position) {
+        if !self.enabled || self.is_excluded(path, language) {
             return;
         }
 
         if let Some(handle) = self.get_handle(language) {
             let id = handle.find_references(
                 path.to_path_buf(),
-                cp_editor_lsp::Position::new(line as u32, col as u32),
+                position,
                 true, // include declaration
             );
             self.pending_requests
@@ -265,15 +566,15 @@ impl LspManager {
     }
 
     /// Requests rename symbol.
-    pub fn rename(&mut self, path: &Path, language: &str, line: usize, col: usize, new_name: &str) {
-        if !self.enabled {
+    pub fn rename(&mut self, path: &Path, language: &str, position: Position, new_name: &str) {
+        if !self.enabled || self.is_excluded(path, language) {
             return;
         }
 
         if let Some(handle) = self.get_handle(language) {
             let id = handle.rename(
                 path.to_path_buf(),
-                cp_editor_lsp::Position::new(line as u32, col as u32),
+                position,
                 new_name.to_string(),
             );
             self.pending_requests
@@ -281,6 +582,38 @@ impl LspManager {
         }
     }
 
+    /// Requests the code actions for `range`, with the diagnostics that
+    /// overlap it.
+    pub fn code_action(&mut self, path: &Path, language: &str, range: Range, diagnostics: Vec<cp_editor_lsp::Diagnostic>) {
+        if !self.enabled || self.is_excluded(path, language) {
+            return;
+        }
+
+        if let Some(handle) = self.get_handle(language) {
+            let id = handle.code_action(path.to_path_buf(), range, diagnostics);
+            self.pending_requests
+                .insert(id, PendingRequest::CodeAction { path: path.to_path_buf() });
+        }
+    }
+
+    /// Asks the language server to execute a command, typically one attached
+    /// to a code action. Any resulting edits arrive as `LspEvent::ApplyEdit`.
+    pub fn execute_command(&mut self, language: &str, command: &str, arguments: Vec<Value>) {
+        if !self.enabled {
+            return;
+        }
+
+        if let Some(handle) = self.get_handle(language) {
+            let id = handle.execute_command(command.to_string(), arguments);
+            self.pending_requests.insert(
+                id,
+                PendingRequest::ExecuteCommand {
+                    command: command.to_string(),
+                },
+            );
+        }
+    }
+
     /// Polls for LSP events. Call this from the event loop.
     /// Returns a list of events to be processed by the UI.
     pub fn poll(&mut self) -> Vec<LspEvent> {
@@ -321,8 +654,14 @@ impl LspManager {
     /// Handles a response from the LSP server.
     fn handle_response(&mut self, response: LspResponse) -> Option<LspEvent> {
         match response {
-            LspResponse::Initialized { id, capabilities_summary } => {
-                log::info!("LSP server initialized (id: {}): {}", id, capabilities_summary);
+            LspResponse::Initialized {
+                id,
+                capabilities,
+            } => {
+                log::info!("LSP server initialized (id: {}): {}", id, capabilities.summary());
+                if let Some(PendingRequest::Initialize { language }) = self.pending_requests.remove(&id) {
+                    self.capabilities.insert(language, capabilities);
+                }
                 None
             }
             LspResponse::InitializeFailed { id, error } => {
@@ -349,6 +688,7 @@ impl LspManager {
                             kind: item.kind.map(convert_completion_kind),
                             detail: item.detail,
                             insert_text: item.insert_text,
+                            is_snippet: item.is_snippet,
                         })
                         .collect();
                     Some(LspEvent::Completion {
@@ -359,6 +699,16 @@ impl LspManager {
                     None
                 }
             }
+            LspResponse::SignatureHelp { id, help } => {
+                let Some(PendingRequest::SignatureHelp { path }) = self.pending_requests.remove(&id) else {
+                    return None;
+                };
+                let help = help.as_ref().and_then(|help| help.active()).map(|(signature, parameter)| SignatureHelp {
+                    label: signature.label.clone(),
+                    active_parameter: parameter,
+                });
+                Some(LspEvent::SignatureHelp { path, help })
+            }
             LspResponse::GotoDefinition { id, locations } => {
                 if let Some(PendingRequest::GotoDefinition { path }) = self.pending_requests.remove(&id) {
                     let locs: Vec<(PathBuf, usize, usize)> = locations
@@ -380,39 +730,60 @@ impl LspManager {
             }
             LspResponse::Rename { id, edit } => {
                 self.pending_requests.remove(&id);
-                if let Some(workspace_edit) = edit {
-                    // Convert to UI-friendly format: (path, [(start_line, start_col, end_line, end_col, new_text)])
-                    let edits: Vec<(PathBuf, Vec<(usize, usize, usize, usize, String)>)> = workspace_edit
-                        .changes
-                        .into_iter()
-                        .map(|(path, text_edits)| {
-                            let edits = text_edits
-                                .into_iter()
-                                .map(|e| {
-                                    (
-                                        e.range.start.line as usize,
-                                        e.range.start.character as usize,
-                                        e.range.end.line as usize,
-                                        e.range.end.character as usize,
-                                        e.new_text,
-                                    )
-                                })
-                                .collect();
-                            (path, edits)
-                        })
-                        .collect();
-                    Some(LspEvent::Rename { edits })
-                } else {
-                    None
-                }
+                edit.map(|workspace_edit| LspEvent::Rename {
+                    edits: convert_workspace_edit(workspace_edit),
+                })
+            }
+            LspResponse::CodeActions { id, actions } => {
+                let Some(PendingRequest::CodeAction { path }) = self.pending_requests.remove(&id) else {
+                    return None;
+                };
+                let actions = actions
+                    .into_iter()
+                    .map(|action| CodeActionEntry {
+                        title: action.title,
+                        edits: action.edit.map(convert_workspace_edit).unwrap_or_default(),
+                        command: action.command,
+                        arguments: action.arguments,
+                    })
+                    .collect();
+                Some(LspEvent::CodeActions { path, actions })
             }
             LspResponse::DocumentSymbols { id, symbols: _ } => {
                 self.pending_requests.remove(&id);
                 // TODO: Handle symbols
                 None
             }
+            LspResponse::PullDiagnostics { id, diagnostics } => {
+                let Some(PendingRequest::PullDiagnostics { path }) = self.pending_requests.remove(&id) else {
+                    return None;
+                };
+                // An unchanged report keeps the diagnostics already shown
+                diagnostics.map(|diagnostics| LspEvent::Diagnostics {
+                    path,
+                    diagnostics: convert_diagnostics(diagnostics),
+                })
+            }
+            LspResponse::ExecuteCommand { id, result } => {
+                if let Some(PendingRequest::ExecuteCommand { command }) = self.pending_requests.remove(&id) {
+                    log::debug!("LSP command {} finished: {:?}", command, result);
+                }
+                None
+            }
             LspResponse::Error { id, message } => {
-                self.pending_requests.remove(&id);
+                match self.pending_requests.remove(&id) {
+                    Some(PendingRequest::ExecuteCommand { command }) => {
+                        return Some(LspEvent::Error {
+                            message: format!("Command {} failed: {}", command, message),
+                        });
+                    }
+                    // The menu is waiting for an answer
+                    Some(PendingRequest::CodeAction { path }) => {
+                        log::warn!("LSP code actions failed: {}", message);
+                        return Some(LspEvent::CodeActions { path, actions: Vec::new() });
+                    }
+                    _ => {}
+                }
                 log::warn!("LSP request {} failed: {}", id, message);
                 None
             }
@@ -422,25 +793,10 @@ impl LspManager {
     /// Handles a notification from the LSP server.
     fn handle_notification(&self, notification: LspNotification) -> Option<LspEvent> {
         match notification {
-            LspNotification::Diagnostics { path, diagnostics } => {
-                let diags: Vec<Diagnostic> = diagnostics
-                    .into_iter()
-                    .map(|d| {
-                        let mut diag = Diagnostic::new(
-                            d.range.start.line as usize,
-                            d.range.start.character as usize,
-                            d.range.end.line as usize,
-                            d.range.end.character as usize,
-                            convert_severity(d.severity),
-                            d.message,
-                        );
-                        diag.code = d.code;
-                        diag.source = d.source;
-                        diag
-                    })
-                    .collect();
-                Some(LspEvent::Diagnostics { path, diagnostics: diags })
-            }
+            LspNotification::Diagnostics { path, diagnostics } => Some(LspEvent::Diagnostics {
+                path,
+                diagnostics: convert_diagnostics(diagnostics),
+            }),
             LspNotification::ServerReady => {
                 log::info!("LSP server is ready");
                 None
@@ -464,19 +820,88 @@ impl LspManager {
                 }
                 None
             }
+            LspNotification::ApplyEdit { label, edit } => Some(LspEvent::ApplyEdit {
+                label,
+                edits: convert_workspace_edit(edit),
+            }),
         }
     }
 
-    /// Shuts down all LSP clients.
+    /// Closes every open document, then shuts down all LSP clients.
     pub fn shutdown_all(&mut self) {
+        for (path, language) in self.open_documents.drain() {
+            if let Some(client) = self.clients.get(&language) {
+                client.handle().did_close(path);
+            }
+        }
         for (language, client) in self.clients.drain() {
             log::info!("Shutting down LSP client for {}", language);
             client.shutdown();
         }
         self.pending_requests.clear();
+        self.capabilities.clear();
     }
 }
 
+/// Converts LSP diagnostics (pushed or pulled) to editor diagnostics.
+fn convert_diagnostics(diagnostics: Vec<cp_editor_lsp::Diagnostic>) -> Vec<Diagnostic> {
+    diagnostics
+        .into_iter()
+        .map(|d| {
+            let mut diag = Diagnostic::new(
+                d.range.start.line as usize,
+                d.range.start.character as usize,
+                d.range.end.line as usize,
+                d.range.end.character as usize,
+                convert_severity(d.severity),
+                d.message,
+            );
+            diag.code = d.code;
+            diag.source = d.source;
+            diag
+        })
+        .collect()
+}
+
+/// Converts an editor diagnostic back to the server's form, with its range
+/// already in the server's position encoding.
+pub fn to_lsp_diagnostic(diagnostic: &Diagnostic, range: Range) -> cp_editor_lsp::Diagnostic {
+    cp_editor_lsp::Diagnostic {
+        range,
+        severity: match diagnostic.severity {
+            DiagnosticSeverity::Error => cp_editor_lsp::DiagnosticSeverity::Error,
+            DiagnosticSeverity::Warning => cp_editor_lsp::DiagnosticSeverity::Warning,
+            DiagnosticSeverity::Information => cp_editor_lsp::DiagnosticSeverity::Information,
+            DiagnosticSeverity::Hint => cp_editor_lsp::DiagnosticSeverity::Hint,
+        },
+        message: diagnostic.message.clone(),
+        code: diagnostic.code.clone(),
+        source: diagnostic.source.clone(),
+    }
+}
+
+/// Converts a workspace edit to the UI-friendly per-file edit format.
+fn convert_workspace_edit(edit: WorkspaceEdit) -> Vec<FileEdits> {
+    edit.changes
+        .into_iter()
+        .map(|(path, text_edits)| {
+            let edits = text_edits
+                .into_iter()
+                .map(|e| {
+                    (
+                        e.range.start.line as usize,
+                        e.range.start.character as usize,
+                        e.range.end.line as usize,
+                        e.range.end.character as usize,
+                        e.new_text,
+                    )
+                })
+                .collect();
+            (path, edits)
+        })
+        .collect()
+}
+
 /// Converts LSP severity to editor severity.
 fn convert_severity(severity: cp_editor_lsp::DiagnosticSeverity) -> DiagnosticSeverity {
     match severity {
@@ -511,6 +936,22 @@ fn convert_completion_kind(kind: cp_editor_lsp::CompletionKind) -> CompletionKin
     }
 }
 
+/// Expands the placeholders in a server's command, arguments and working
+/// directory. A server isn't started with a placeholder that has no value.
+fn expand_server_config(config: &ServerConfig, vars: &TemplateVars) -> Result<ServerConfig, String> {
+    let expand = |value: &str| template::expand(value, vars, UndefinedVariable::Error);
+    let args = config.args.iter().map(|arg| expand(arg)).collect::<Result<_, _>>()?;
+    let working_dir = match &config.working_dir {
+        Some(dir) => Some(PathBuf::from(expand(&dir.to_string_lossy())?)),
+        None => None,
+    };
+    Ok(ServerConfig {
+        command: expand(&config.command)?,
+        args,
+        working_dir,
+    })
+}
+
 /// Maps file extensions to LSP language IDs.
 pub fn language_id_from_path(path: &Path) -> Option<&'static str> {
     let ext = path.extension()?.to_str()?;
@@ -536,3 +977,28 @@ pub fn language_id_from_path(path: &Path) -> Option<&'static str> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exclusions() {
+        let mut lsp = LspManager::new();
+        lsp.set_workspace_root(Some(PathBuf::from("/work")));
+        lsp.set_exclusions(vec!["python".to_string()], vec!["src/gen/*.rs".to_string(), "*.pb.rs".to_string()]);
+
+        assert!(!lsp.is_excluded(Path::new("/work/src/main.rs"), "rust"));
+        assert!(lsp.is_excluded(Path::new("/work/src/gen/schema.rs"), "rust"));
+        // `*` doesn't cross directories
+        assert!(!lsp.is_excluded(Path::new("/work/src/gen/nested/schema.rs"), "rust"));
+        // Patterns without a `/` match the file name anywhere
+        assert!(lsp.is_excluded(Path::new("/work/deep/api.pb.rs"), "rust"));
+        assert!(lsp.is_excluded(Path::new("/work/tool.py"), "python"));
+
+        // Re-enabling lets one file through, whatever excluded it
+        lsp.reenable(Path::new("/work/tool.py"));
+        assert!(!lsp.is_excluded(Path::new("/work/tool.py"), "python"));
+        assert!(lsp.is_excluded(Path::new("/work/other.py"), "python"));
+    }
+}
@@ -1,7 +1,13 @@
 //! Notification/toast system for user feedback.
 //!
 //! Provides transient notifications for operations like save, replace, etc.
+//! Expiry and fading take the current time as a parameter, so tests can
+//! step through a notification's life without waiting.
+//!
+//! How long a notification stays depends on its type, so errors outlast
+//! confirmations; sticky ones stay until clicked away.
 
+use crate::settings::NotificationSettings;
 use std::time::{Duration, Instant};
 
 /// Type of notification.
@@ -48,16 +54,20 @@ pub struct Notification {
     pub created_at: Instant,
     /// How long the notification should be visible.
     pub duration: Duration,
+    /// Whether the notification stays until dismissed, whatever its
+    /// duration.
+    pub sticky: bool,
 }
 
 impl Notification {
-    /// Creates a new notification.
-    pub fn new(message: impl Into<String>, notification_type: NotificationType) -> Self {
+    /// Creates a new notification shown from `now`.
+    pub fn new(message: impl Into<String>, notification_type: NotificationType, now: Instant) -> Self {
         Self {
             message: message.into(),
             notification_type,
-            created_at: Instant::now(),
+            created_at: now,
             duration: Duration::from_secs(3),
+            sticky: false,
         }
     }
 
@@ -67,14 +77,25 @@ impl Notification {
         self
     }
 
-    /// Returns whether this notification has expired.
-    pub fn is_expired(&self) -> bool {
-        self.created_at.elapsed() >= self.duration
+    /// Makes the notification stay until dismissed.
+    pub fn sticky(mut self) -> Self {
+        self.sticky = true;
+        self
+    }
+
+    /// Returns whether this notification has expired at `now`. Sticky
+    /// notifications never do.
+    pub fn is_expired(&self, now: Instant) -> bool {
+        !self.sticky && now.saturating_duration_since(self.created_at) >= self.duration
     }
 
-    /// Returns the remaining visibility (0.0 to 1.0) for fade-out effect.
-    pub fn visibility(&self) -> f32 {
-        let elapsed = self.created_at.elapsed();
+    /// Returns the remaining visibility at `now` (0.0 to 1.0) for the
+    /// fade-out effect.
+    pub fn visibility(&self, now: Instant) -> f32 {
+        if self.sticky {
+            return 1.0;
+        }
+        let elapsed = now.saturating_duration_since(self.created_at);
         if elapsed >= self.duration {
             return 0.0;
         }
@@ -98,6 +119,8 @@ pub struct NotificationManager {
     notifications: Vec<Notification>,
     /// Maximum number of visible notifications.
     max_visible: usize,
+    /// How long each type of notification stays.
+    pub settings: NotificationSettings,
 }
 
 impl NotificationManager {
@@ -106,20 +129,54 @@ impl NotificationManager {
         Self {
             notifications: Vec::new(),
             max_visible: 5,
+            settings: NotificationSettings::default(),
         }
     }
 
     /// Adds a notification.
     pub fn notify(&mut self, message: impl Into<String>, notification_type: NotificationType) {
-        let notification = Notification::new(message, notification_type);
+        self.notify_at(message, notification_type, Instant::now());
+    }
+
+    /// Adds a notification shown from `now`, staying as long as the
+    /// settings give its type.
+    pub fn notify_at(&mut self, message: impl Into<String>, notification_type: NotificationType, now: Instant) {
+        let mut notification = Notification::new(message, notification_type, now)
+            .with_duration(self.settings.duration(notification_type));
+        notification.sticky = self.settings.is_sticky(notification_type);
+        self.push(notification);
+    }
+
+    /// Adds a notification that stays until dismissed.
+    pub fn notify_sticky(&mut self, message: impl Into<String>, notification_type: NotificationType) {
+        self.push(Notification::new(message, notification_type, Instant::now()).sticky());
+    }
+
+    /// Adds a notification as it is.
+    pub fn push(&mut self, notification: Notification) {
         self.notifications.push(notification);
 
-        // Limit total notifications
+        // Limit total notifications, dropping sticky ones last
         while self.notifications.len() > self.max_visible * 2 {
-            self.notifications.remove(0);
+            let oldest = self.notifications.iter().position(|n| !n.sticky).unwrap_or(0);
+            self.notifications.remove(oldest);
         }
     }
 
+    /// Shows `message` in place of a visible notification starting with
+    /// `prefix`, or as a new one, so progress updates don't pile up.
+    pub fn replace(&mut self, prefix: &str, message: impl Into<String>, notification_type: NotificationType) {
+        self.notifications.retain(|n| !n.message.starts_with(prefix));
+        self.notify(message, notification_type);
+    }
+
+    /// Like [`NotificationManager::replace`], with a notification that
+    /// stays until dismissed.
+    pub fn replace_sticky(&mut self, prefix: &str, message: impl Into<String>, notification_type: NotificationType) {
+        self.notifications.retain(|n| !n.message.starts_with(prefix));
+        self.notify_sticky(message, notification_type);
+    }
+
     /// Adds a success notification.
     pub fn success(&mut self, message: impl Into<String>) {
         self.notify(message, NotificationType::Success);
@@ -140,15 +197,43 @@ impl NotificationManager {
         self.notify(message, NotificationType::Error);
     }
 
-    /// Removes expired notifications and returns whether any are still visible.
-    pub fn update(&mut self) -> bool {
-        self.notifications.retain(|n| !n.is_expired());
+    /// Removes the notifications expired at `now` and returns whether any
+    /// are still visible.
+    pub fn update(&mut self, now: Instant) -> bool {
+        self.notifications.retain(|n| !n.is_expired(now));
         !self.notifications.is_empty()
     }
 
-    /// Returns the visible notifications (most recent first).
+    /// Returns the visible notifications (most recent first): the sticky
+    /// ones, and the most recent others in the slots they leave.
     pub fn visible(&self) -> impl Iterator<Item = &Notification> {
-        self.notifications.iter().rev().take(self.max_visible)
+        self.visible_indices().into_iter().map(|i| &self.notifications[i])
+    }
+
+    /// Removes the notification at `index` in [`NotificationManager::visible`]
+    /// order. Returns whether there was one.
+    pub fn dismiss(&mut self, index: usize) -> bool {
+        let Some(&i) = self.visible_indices().get(index) else {
+            return false;
+        };
+        self.notifications.remove(i);
+        true
+    }
+
+    /// Returns the indices of the visible notifications, most recent first.
+    fn visible_indices(&self) -> Vec<usize> {
+        let sticky = self.notifications.iter().filter(|n| n.sticky).count().min(self.max_visible);
+        let mut sticky_slots = sticky;
+        let mut other_slots = self.max_visible - sticky;
+        (0..self.notifications.len())
+            .rev()
+            .filter(|&i| {
+                let slots = if self.notifications[i].sticky { &mut sticky_slots } else { &mut other_slots };
+                let shown = *slots > 0;
+                *slots = slots.saturating_sub(1);
+                shown
+            })
+            .collect()
     }
 
     /// Returns whether there are any visible notifications.
@@ -166,12 +251,42 @@ impl NotificationManager {
 mod tests {
     use super::*;
 
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
     #[test]
     fn test_notification_creation() {
-        let notification = Notification::new("Test message", NotificationType::Success);
+        let t0 = Instant::now();
+        let notification = Notification::new("Test message", NotificationType::Success, t0);
         assert_eq!(notification.message, "Test message");
-        assert!(!notification.is_expired());
-        assert!(notification.visibility() > 0.9);
+        assert!(!notification.is_expired(t0));
+        assert!(notification.visibility(t0) > 0.9);
+    }
+
+    #[test]
+    fn test_notification_fades_and_expires() {
+        let t0 = Instant::now();
+        let notification = Notification::new("Saved", NotificationType::Success, t0);
+        assert_eq!(notification.visibility(t0 + ms(2500)), 1.0);
+        // Fades over the last half second
+        assert_eq!(notification.visibility(t0 + ms(2750)), 0.5);
+        assert_eq!(notification.visibility(t0 + ms(3000)), 0.0);
+        assert!(!notification.is_expired(t0 + ms(2999)));
+        assert!(notification.is_expired(t0 + ms(3000)));
+        // A clock read before creation counts as no time passed
+        assert_eq!(notification.visibility(t0 - ms(10)), 1.0);
+
+        let mut manager = NotificationManager::new();
+        manager.notify_at("Saved", NotificationType::Success, t0);
+        manager.notify_at("Later", NotificationType::Info, t0 + ms(1000));
+        assert!(manager.update(t0 + ms(2999)));
+        assert_eq!(manager.visible().count(), 2);
+        assert!(manager.update(t0 + ms(3000)));
+        let messages: Vec<_> = manager.visible().map(|n| n.message.as_str()).collect();
+        assert_eq!(messages, ["Later"]);
+        assert!(!manager.update(t0 + ms(4000)));
+        assert_eq!(manager.visible().count(), 0);
     }
 
     #[test]
@@ -183,4 +298,73 @@ mod tests {
         assert!(manager.has_notifications());
         assert_eq!(manager.visible().count(), 2);
     }
+
+    #[test]
+    fn test_durations_by_type() {
+        let t0 = Instant::now();
+        let mut manager = NotificationManager::new();
+        manager.notify_at("Saved", NotificationType::Success, t0);
+        manager.notify_at("Server restarted", NotificationType::Warning, t0);
+        manager.notify_at("Save failed", NotificationType::Error, t0);
+        manager.update(t0 + ms(3000));
+        let messages: Vec<_> = manager.visible().map(|n| n.message.as_str()).collect();
+        assert_eq!(messages, ["Save failed", "Server restarted"]);
+        manager.update(t0 + ms(6000));
+        let messages: Vec<_> = manager.visible().map(|n| n.message.as_str()).collect();
+        assert_eq!(messages, ["Save failed"]);
+        assert!(!manager.update(t0 + ms(10_000)));
+
+        manager.settings.info_ms = 1000;
+        manager.notify_at("Indexing", NotificationType::Info, t0);
+        assert!(!manager.update(t0 + ms(1000)));
+    }
+
+    #[test]
+    fn test_sticky_error_never_expires() {
+        let t0 = Instant::now();
+        let notification = Notification::new("Build failed", NotificationType::Error, t0).sticky();
+        let much_later = t0 + Duration::from_secs(24 * 60 * 60);
+        assert!(!notification.is_expired(much_later));
+        assert_eq!(notification.visibility(much_later), 1.0);
+
+        // Errors made sticky by the settings stay too
+        let mut manager = NotificationManager::new();
+        manager.settings.sticky_errors = true;
+        manager.notify_at("Build failed", NotificationType::Error, t0);
+        manager.notify_at("Saved", NotificationType::Success, t0);
+        assert!(manager.update(much_later));
+        let messages: Vec<_> = manager.visible().map(|n| n.message.as_str()).collect();
+        assert_eq!(messages, ["Build failed"]);
+
+        // Crowding doesn't push it out, kept or shown
+        for i in 0..20 {
+            manager.notify_at(format!("Saved {i}"), NotificationType::Success, much_later);
+        }
+        let messages: Vec<_> = manager.visible().map(|n| n.message.as_str()).collect();
+        assert_eq!(messages, ["Saved 19", "Saved 18", "Saved 17", "Saved 16", "Build failed"]);
+    }
+
+    #[test]
+    fn test_dismiss() {
+        let t0 = Instant::now();
+        let mut manager = NotificationManager::new();
+        manager.push(Notification::new("Build failed", NotificationType::Error, t0).sticky());
+        manager.notify_at("Saved", NotificationType::Success, t0);
+        // Indices count from the most recent, as shown
+        assert!(manager.dismiss(1));
+        let messages: Vec<_> = manager.visible().map(|n| n.message.as_str()).collect();
+        assert_eq!(messages, ["Saved"]);
+        assert!(!manager.dismiss(1));
+        assert!(manager.dismiss(0));
+        assert!(!manager.has_notifications());
+
+        // A sticky one shown below more recent ones is dismissed where shown
+        manager.push(Notification::new("Build failed", NotificationType::Error, t0).sticky());
+        for i in 0..10 {
+            manager.notify_at(format!("Saved {i}"), NotificationType::Success, t0);
+        }
+        assert!(manager.dismiss(4));
+        assert!(manager.visible().all(|n| !n.sticky));
+        assert!(!manager.dismiss(5));
+    }
 }
@@ -0,0 +1,153 @@
+//! Buffer anchors for popups.
+//!
+//! Hover and completion popups point at text, so they are anchored to a
+//! buffer position rather than a screen position: the screen position is
+//! derived from the anchor every frame, and the popup moves with its text
+//! while scrolling. A popup is dismissed once its anchor scrolls out of
+//! view or the text before it on its line changes under it, e.g. when a
+//! workspace edit is applied.
+
+use cp_editor_core::TextBuffer;
+
+/// A buffer position a popup points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PopupAnchor {
+    /// Line of the anchor.
+    pub line: usize,
+    /// Column of the anchor.
+    pub col: usize,
+    /// Text of the line before `col` when the anchor was set.
+    prefix: String,
+}
+
+impl PopupAnchor {
+    /// Anchors at `line` and `col` of `buffer`.
+    pub fn new(buffer: &TextBuffer, line: usize, col: usize) -> Self {
+        Self {
+            line,
+            col,
+            prefix: line_prefix(buffer, line, col).unwrap_or_default(),
+        }
+    }
+
+    /// Returns whether the anchor still points at the text it was set on.
+    ///
+    /// Only the text before the anchor on its line is compared, so typing
+    /// after it (narrowing a completion) keeps the anchor; deleting across
+    /// it or moving its line doesn't.
+    pub fn is_valid(&self, buffer: &TextBuffer) -> bool {
+        line_prefix(buffer, self.line, self.col).as_deref() == Some(self.prefix.as_str())
+    }
+
+    /// Returns whether the anchor line is among the `visible_lines` lines
+    /// from `scroll_offset` on.
+    pub fn is_in_view(&self, scroll_offset: usize, visible_lines: usize) -> bool {
+        (scroll_offset..scroll_offset + visible_lines).contains(&self.line)
+    }
+}
+
+/// Returns the first `col` characters of `line`, or `None` if the line is
+/// missing or shorter.
+fn line_prefix(buffer: &TextBuffer, line: usize, col: usize) -> Option<String> {
+    if line >= buffer.len_lines() || col > buffer.line_len_chars(line) {
+        return None;
+    }
+    let start = buffer.line_start(line);
+    Some((start..start + col).filter_map(|i| buffer.char_at(i)).collect())
+}
+
+/// What a hover popup is drawn at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HoverAnchor {
+    /// Requested by resting the mouse: drawn at the pointer, and dismissed
+    /// on any scroll since the pointer no longer is over the same text.
+    Mouse {
+        /// Pointer position in screen coordinates.
+        x: f32,
+        y: f32,
+        /// Vertical and horizontal scroll offsets when it was requested.
+        scroll: (usize, usize),
+    },
+    /// Requested from the keyboard: drawn at the text.
+    Text(PopupAnchor),
+}
+
+impl HoverAnchor {
+    /// Returns whether the popup should stay open, given the buffer, the
+    /// scroll offsets and the number of visible lines.
+    pub fn is_valid(&self, buffer: &TextBuffer, scroll: (usize, usize), visible_lines: usize) -> bool {
+        match self {
+            Self::Mouse { scroll: opened_at, .. } => *opened_at == scroll,
+            Self::Text(anchor) => anchor.is_valid(buffer) && anchor.is_in_view(scroll.0, visible_lines),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typing_after_anchor_keeps_it() {
+        let mut buffer = TextBuffer::from_str("fn main() {\n    foo.\n}");
+        let anchor = PopupAnchor::new(&buffer, 1, 8);
+        assert!(anchor.is_valid(&buffer));
+
+        // Narrowing the completion
+        buffer.insert(buffer.line_col_to_char(1, 8), "ba");
+        assert!(anchor.is_valid(&buffer));
+        // Editing other lines below
+        buffer.insert(buffer.len_chars(), "\n// end");
+        assert!(anchor.is_valid(&buffer));
+    }
+
+    #[test]
+    fn test_edits_before_anchor_invalidate_it() {
+        let text = "fn main() {\n    foo.\n}";
+        let anchor = PopupAnchor::new(&TextBuffer::from_str(text), 1, 8);
+
+        // Deleting the trigger character
+        let mut buffer = TextBuffer::from_str(text);
+        buffer.remove(buffer.line_col_to_char(1, 7), buffer.line_col_to_char(1, 8));
+        assert!(!anchor.is_valid(&buffer));
+
+        // A line inserted above moves the anchor's text away
+        let mut buffer = TextBuffer::from_str(text);
+        buffer.insert(0, "use std::io;\n");
+        assert!(!anchor.is_valid(&buffer));
+
+        // A rename earlier on the line
+        let mut buffer = TextBuffer::from_str(text);
+        buffer.remove(buffer.line_col_to_char(1, 4), buffer.line_col_to_char(1, 7));
+        buffer.insert(buffer.line_col_to_char(1, 4), "bar");
+        assert!(!anchor.is_valid(&buffer));
+
+        // The line is gone
+        let buffer = TextBuffer::from_str("fn main() {}");
+        assert!(!anchor.is_valid(&buffer));
+    }
+
+    #[test]
+    fn test_anchor_in_view() {
+        let buffer = TextBuffer::from_str("a\nb\nc");
+        let anchor = PopupAnchor::new(&buffer, 20, 0);
+        assert!(anchor.is_in_view(11, 10));
+        assert!(anchor.is_in_view(20, 1));
+        assert!(!anchor.is_in_view(21, 10));
+        assert!(!anchor.is_in_view(10, 10));
+    }
+
+    #[test]
+    fn test_mouse_hover_dismissed_on_any_scroll() {
+        let buffer = TextBuffer::from_str("a\nb\nc");
+        let hover = HoverAnchor::Mouse { x: 10.0, y: 20.0, scroll: (0, 0) };
+        assert!(hover.is_valid(&buffer, (0, 0), 10));
+        assert!(!hover.is_valid(&buffer, (1, 0), 10));
+        assert!(!hover.is_valid(&buffer, (0, 3), 10));
+
+        // A text hover follows its line while it is on screen
+        let hover = HoverAnchor::Text(PopupAnchor::new(&buffer, 2, 1));
+        assert!(hover.is_valid(&buffer, (1, 4), 10));
+        assert!(!hover.is_valid(&buffer, (3, 0), 10));
+    }
+}
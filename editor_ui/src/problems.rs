@@ -0,0 +1,463 @@
+//! The Problems panel: the diagnostics of every open buffer in one list.
+//!
+//! The panel lists a snapshot of the diagnostics, taken when it opens and
+//! when it is refreshed, so entries don't move while the user steps
+//! through them as language servers publish new ones. Filtering, ordering
+//! and the exports are pure functions of the snapshot.
+
+use std::path::PathBuf;
+
+use cp_editor_core::{BufferId, DiagnosticSeverity};
+use serde_json::json;
+
+/// A diagnostic in the snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProblemEntry {
+    /// Buffer the diagnostic was reported in.
+    pub buffer: BufferId,
+    /// The buffer's file, if it has one.
+    pub path: Option<PathBuf>,
+    /// The file as shown: relative to the project root when inside it, or
+    /// the tab name for a buffer without a file.
+    pub file: String,
+    /// Line (0-indexed).
+    pub line: usize,
+    /// Column in characters (0-indexed).
+    pub col: usize,
+    /// Severity level.
+    pub severity: DiagnosticSeverity,
+    /// Diagnostic message.
+    pub message: String,
+    /// What reported it, e.g. "rust-analyzer".
+    pub source: Option<String>,
+}
+
+/// Returns the name a severity is exported and shown with.
+pub fn severity_name(severity: DiagnosticSeverity) -> &'static str {
+    match severity {
+        DiagnosticSeverity::Error => "error",
+        DiagnosticSeverity::Warning => "warning",
+        DiagnosticSeverity::Information => "info",
+        DiagnosticSeverity::Hint => "hint",
+    }
+}
+
+/// How the panel orders its entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProblemGrouping {
+    /// Under a heading per file, in line order.
+    #[default]
+    ByFile,
+    /// One list, most severe first.
+    BySeverity,
+}
+
+impl ProblemGrouping {
+    /// Returns the other grouping.
+    pub fn toggled(&self) -> Self {
+        match self {
+            ProblemGrouping::ByFile => ProblemGrouping::BySeverity,
+            ProblemGrouping::BySeverity => ProblemGrouping::ByFile,
+        }
+    }
+
+    /// Returns the name shown in the panel.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProblemGrouping::ByFile => "by file",
+            ProblemGrouping::BySeverity => "by severity",
+        }
+    }
+}
+
+/// Which entries the panel shows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProblemFilter {
+    /// Show errors.
+    pub errors: bool,
+    /// Show warnings.
+    pub warnings: bool,
+    /// Show information and hints.
+    pub hints: bool,
+    /// Text the message, file or source must contain, ignoring case.
+    pub text: String,
+}
+
+impl Default for ProblemFilter {
+    fn default() -> Self {
+        Self {
+            errors: true,
+            warnings: true,
+            hints: true,
+            text: String::new(),
+        }
+    }
+}
+
+impl ProblemFilter {
+    /// Returns whether entries of `severity` are shown.
+    pub fn shows(&self, severity: DiagnosticSeverity) -> bool {
+        match severity {
+            DiagnosticSeverity::Error => self.errors,
+            DiagnosticSeverity::Warning => self.warnings,
+            DiagnosticSeverity::Information | DiagnosticSeverity::Hint => self.hints,
+        }
+    }
+
+    /// Returns true if the filter lets `entry` through.
+    pub fn matches(&self, entry: &ProblemEntry) -> bool {
+        if !self.shows(entry.severity) {
+            return false;
+        }
+        if self.text.is_empty() {
+            return true;
+        }
+        let text = self.text.to_lowercase();
+        [Some(&entry.message), Some(&entry.file), entry.source.as_ref()]
+            .into_iter()
+            .flatten()
+            .any(|field| field.to_lowercase().contains(&text))
+    }
+}
+
+/// Returns the entries `filter` lets through, in `grouping`'s order: by
+/// file then position, or by severity then file and position.
+pub fn arrange<'a>(entries: &'a [ProblemEntry], filter: &ProblemFilter, grouping: ProblemGrouping) -> Vec<&'a ProblemEntry> {
+    let mut shown: Vec<&ProblemEntry> = entries.iter().filter(|entry| filter.matches(entry)).collect();
+    match grouping {
+        ProblemGrouping::ByFile => shown.sort_by(|a, b| (&a.file, a.line, a.col).cmp(&(&b.file, b.line, b.col))),
+        ProblemGrouping::BySeverity => {
+            shown.sort_by(|a, b| (a.severity, &a.file, a.line, a.col).cmp(&(b.severity, &b.file, b.line, b.col)))
+        }
+    }
+    shown
+}
+
+/// A line of the panel's list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProblemRow {
+    /// Index of the entry in the arranged list; `None` for a file heading.
+    pub entry: Option<usize>,
+    /// The text shown.
+    pub label: String,
+}
+
+/// Returns the panel's lines for `arranged` entries. Grouped by file, each
+/// file gets a heading with its count and its entries are indented under
+/// it without the file name.
+pub fn rows(arranged: &[&ProblemEntry], grouping: ProblemGrouping) -> Vec<ProblemRow> {
+    let mut rows = Vec::new();
+    for (index, entry) in arranged.iter().enumerate() {
+        let position = format!("{}:{}", entry.line + 1, entry.col + 1);
+        let label = match grouping {
+            ProblemGrouping::ByFile => {
+                if index == 0 || arranged[index - 1].file != entry.file {
+                    let count = arranged[index..].iter().take_while(|e| e.file == entry.file).count();
+                    rows.push(ProblemRow { entry: None, label: format!("{} ({})", entry.file, count) });
+                }
+                format!("  {} {} {}", severity_name(entry.severity), position, entry.message)
+            }
+            ProblemGrouping::BySeverity => {
+                format!("{} {}:{} {}", severity_name(entry.severity), entry.file, position, entry.message)
+            }
+        };
+        let label = match &entry.source {
+            Some(source) => format!("{} [{}]", label, source),
+            None => label,
+        };
+        rows.push(ProblemRow { entry: Some(index), label });
+    }
+    rows
+}
+
+/// Returns the entries in the `path:line:col: severity: message` format
+/// compilers print, one per line, with 1-based lines and columns.
+pub fn to_text(entries: &[&ProblemEntry]) -> String {
+    entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "{}:{}:{}: {}: {}\n",
+                entry.file,
+                entry.line + 1,
+                entry.col + 1,
+                severity_name(entry.severity),
+                entry.message
+            )
+        })
+        .collect()
+}
+
+/// Returns the entries as a JSON array of objects with `file`, `line`,
+/// `column` (both 1-based), `severity`, `message` and `source`.
+pub fn to_json(entries: &[&ProblemEntry]) -> String {
+    let list: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "file": entry.file,
+                "line": entry.line + 1,
+                "column": entry.col + 1,
+                "severity": severity_name(entry.severity),
+                "message": entry.message,
+                "source": entry.source,
+            })
+        })
+        .collect();
+    let mut text = serde_json::to_string_pretty(&list).unwrap_or_default();
+    text.push('\n');
+    text
+}
+
+/// The Problems panel's state: its snapshot, filter, grouping and the
+/// selected entry.
+#[derive(Debug, Clone, Default)]
+pub struct ProblemsPanel {
+    /// Diagnostics as of the last refresh.
+    snapshot: Vec<ProblemEntry>,
+    filter: ProblemFilter,
+    grouping: ProblemGrouping,
+    /// Index of the selected entry among the shown ones.
+    selected: usize,
+}
+
+impl ProblemsPanel {
+    /// Creates an empty panel showing everything, grouped by file.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the snapshot, keeping the selected entry selected if it is
+    /// still there.
+    pub fn refresh(&mut self, entries: Vec<ProblemEntry>) {
+        let selected = self.selected().cloned();
+        self.snapshot = entries;
+        let shown = self.shown();
+        self.selected = selected
+            .and_then(|selected| shown.iter().position(|&entry| *entry == selected))
+            .unwrap_or(0);
+    }
+
+    /// Returns the diagnostics as of the last refresh.
+    pub fn snapshot(&self) -> &[ProblemEntry] {
+        &self.snapshot
+    }
+
+    /// Returns the filter.
+    pub fn filter(&self) -> &ProblemFilter {
+        &self.filter
+    }
+
+    /// Returns the grouping.
+    pub fn grouping(&self) -> ProblemGrouping {
+        self.grouping
+    }
+
+    /// Returns the entries shown, in order.
+    pub fn shown(&self) -> Vec<&ProblemEntry> {
+        arrange(&self.snapshot, &self.filter, self.grouping)
+    }
+
+    /// Returns the panel's lines.
+    pub fn rows(&self) -> Vec<ProblemRow> {
+        rows(&self.shown(), self.grouping)
+    }
+
+    /// Returns the selected entry, if any is shown.
+    pub fn selected(&self) -> Option<&ProblemEntry> {
+        self.shown().get(self.selected).copied()
+    }
+
+    /// Returns the index of the selected entry's line in [`Self::rows`].
+    pub fn selected_row(&self) -> usize {
+        self.rows()
+            .iter()
+            .position(|row| row.entry == Some(self.selected))
+            .unwrap_or(0)
+    }
+
+    /// Moves the selection by `delta` entries, wrapping around.
+    pub fn select(&mut self, delta: isize) {
+        let len = self.shown().len() as isize;
+        if len > 0 {
+            self.selected = (self.selected as isize + delta).rem_euclid(len) as usize;
+        }
+    }
+
+    /// Replaces the filter text and selects the first entry.
+    pub fn set_text(&mut self, text: &str) {
+        self.filter.text = text.to_string();
+        self.selected = 0;
+    }
+
+    /// Shows or hides entries of `severity`; information and hints go
+    /// together.
+    pub fn toggle_severity(&mut self, severity: DiagnosticSeverity) {
+        let shown = match severity {
+            DiagnosticSeverity::Error => &mut self.filter.errors,
+            DiagnosticSeverity::Warning => &mut self.filter.warnings,
+            DiagnosticSeverity::Information | DiagnosticSeverity::Hint => &mut self.filter.hints,
+        };
+        *shown = !*shown;
+        self.selected = 0;
+    }
+
+    /// Switches between grouping by file and sorting by severity, keeping
+    /// the selected entry selected.
+    pub fn toggle_grouping(&mut self) {
+        let selected = self.selected().cloned();
+        self.grouping = self.grouping.toggled();
+        let shown = self.shown();
+        self.selected = selected
+            .and_then(|selected| shown.iter().position(|&entry| *entry == selected))
+            .unwrap_or(0);
+    }
+
+    /// Returns the counts in the snapshot and the grouping, e.g.
+    /// "2 errors · 1 warning · hints hidden · by file".
+    pub fn status_text(&self) -> String {
+        let count = |severities: &[DiagnosticSeverity]| {
+            self.snapshot.iter().filter(|entry| severities.contains(&entry.severity)).count()
+        };
+        let part = |severity: DiagnosticSeverity, n: usize, one: &str, many: &str| {
+            let name = if n == 1 { one } else { many };
+            if self.filter.shows(severity) {
+                format!("{} {}", n, name)
+            } else {
+                format!("{} hidden", many)
+            }
+        };
+        let errors = count(&[DiagnosticSeverity::Error]);
+        let warnings = count(&[DiagnosticSeverity::Warning]);
+        let hints = count(&[DiagnosticSeverity::Information, DiagnosticSeverity::Hint]);
+        format!(
+            "{} \u{b7} {} \u{b7} {} \u{b7} {}",
+            part(DiagnosticSeverity::Error, errors, "error", "errors"),
+            part(DiagnosticSeverity::Warning, warnings, "warning", "warnings"),
+            part(DiagnosticSeverity::Hint, hints, "hint", "hints"),
+            self.grouping.label()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(file: &str, line: usize, severity: DiagnosticSeverity, message: &str) -> ProblemEntry {
+        ProblemEntry {
+            buffer: 0,
+            path: Some(PathBuf::from(file)),
+            file: file.to_string(),
+            line,
+            col: 4,
+            severity,
+            message: message.to_string(),
+            source: None,
+        }
+    }
+
+    fn sample() -> Vec<ProblemEntry> {
+        let mut unused = entry("src/main.rs", 9, DiagnosticSeverity::Warning, "unused variable `x`");
+        unused.source = Some("rustc".to_string());
+        vec![
+            unused,
+            entry("src/lib.rs", 2, DiagnosticSeverity::Hint, "consider `&str`"),
+            entry("src/main.rs", 1, DiagnosticSeverity::Error, "mismatched types"),
+            entry("src/lib.rs", 0, DiagnosticSeverity::Error, "unresolved import"),
+        ]
+    }
+
+    fn labels(entries: &[ProblemEntry], filter: &ProblemFilter, grouping: ProblemGrouping) -> Vec<String> {
+        rows(&arrange(entries, filter, grouping), grouping).into_iter().map(|row| row.label).collect()
+    }
+
+    #[test]
+    fn test_rows() {
+        let entries = sample();
+        let filter = ProblemFilter::default();
+        assert_eq!(
+            labels(&entries, &filter, ProblemGrouping::ByFile),
+            [
+                "src/lib.rs (2)",
+                "  error 1:5 unresolved import",
+                "  hint 3:5 consider `&str`",
+                "src/main.rs (2)",
+                "  error 2:5 mismatched types",
+                "  warning 10:5 unused variable `x` [rustc]",
+            ]
+        );
+        assert_eq!(
+            labels(&entries, &filter, ProblemGrouping::BySeverity),
+            [
+                "error src/lib.rs:1:5 unresolved import",
+                "error src/main.rs:2:5 mismatched types",
+                "warning src/main.rs:10:5 unused variable `x` [rustc]",
+                "hint src/lib.rs:3:5 consider `&str`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter() {
+        let entries = sample();
+        let filter = ProblemFilter { errors: false, hints: false, ..ProblemFilter::default() };
+        assert_eq!(labels(&entries, &filter, ProblemGrouping::ByFile), ["src/main.rs (1)", "  warning 10:5 unused variable `x` [rustc]"]);
+
+        // Text matches the message, file or source, ignoring case
+        let filter = |text: &str| ProblemFilter { text: text.to_string(), ..ProblemFilter::default() };
+        assert_eq!(arrange(&entries, &filter("TYPES"), ProblemGrouping::ByFile).len(), 1);
+        assert_eq!(arrange(&entries, &filter("lib.rs"), ProblemGrouping::ByFile).len(), 2);
+        assert_eq!(arrange(&entries, &filter("rustc"), ProblemGrouping::ByFile).len(), 1);
+        assert!(arrange(&entries, &filter("nothing"), ProblemGrouping::ByFile).is_empty());
+    }
+
+    #[test]
+    fn test_exports() {
+        let entries = sample();
+        let shown = arrange(&entries, &ProblemFilter::default(), ProblemGrouping::BySeverity);
+        assert_eq!(
+            to_text(&shown[..2]),
+            "src/lib.rs:1:5: error: unresolved import\nsrc/main.rs:2:5: error: mismatched types\n"
+        );
+        assert_eq!(
+            to_json(&shown[2..3]),
+            "[\n  {\n    \"column\": 5,\n    \"file\": \"src/main.rs\",\n    \"line\": 10,\n    \"message\": \"unused variable `x`\",\n    \"severity\": \"warning\",\n    \"source\": \"rustc\"\n  }\n]\n"
+        );
+        assert_eq!(to_json(&[]), "[]\n");
+    }
+
+    #[test]
+    fn test_panel_keeps_selection() {
+        let mut panel = ProblemsPanel::new();
+        panel.refresh(sample());
+        assert_eq!(panel.selected().unwrap().message, "unresolved import");
+        panel.select(3);
+        assert_eq!(panel.selected().unwrap().message, "unused variable `x`");
+        assert_eq!(panel.selected_row(), 5);
+        panel.select(1);
+        assert_eq!(panel.selected().unwrap().message, "unresolved import");
+        panel.select(-1);
+
+        // Live diagnostics change, the snapshot doesn't until a refresh
+        let mut live = sample();
+        live.remove(2);
+        assert_eq!(panel.snapshot().len(), 4);
+        panel.refresh(live);
+        assert_eq!(panel.selected().unwrap().message, "unused variable `x`");
+
+        panel.toggle_grouping();
+        assert_eq!(panel.grouping(), ProblemGrouping::BySeverity);
+        assert_eq!(panel.selected().unwrap().message, "unused variable `x`");
+
+        panel.toggle_severity(DiagnosticSeverity::Information);
+        assert!(!panel.filter().hints);
+        assert_eq!(panel.status_text(), "1 error \u{b7} 1 warning \u{b7} hints hidden \u{b7} by severity");
+        panel.set_text("import");
+        assert_eq!(panel.shown().len(), 1);
+        panel.set_text("imports");
+        assert!(panel.shown().is_empty());
+        panel.set_text("import");
+        assert_eq!(panel.shown().len(), 1);
+    }
+}
@@ -0,0 +1,506 @@
+//! Project-wide search.
+//!
+//! Files under a root directory are searched on a background thread and
+//! matching lines are streamed back over a channel. The UI polls the channel
+//! once per frame, the same way LSP events are picked up, so a large tree
+//! never blocks rendering.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::SystemTime;
+
+/// Files larger than this are skipped.
+pub const MAX_FILE_SIZE: u64 = 2 * 1024 * 1024;
+
+/// Search stops after this many hits.
+pub const MAX_RESULTS: usize = 5000;
+
+/// Number of leading bytes checked for NUL when deciding if a file is binary.
+const BINARY_SNIFF_LEN: usize = 8192;
+
+/// Previews longer than this are truncated.
+const MAX_PREVIEW_CHARS: usize = 200;
+
+/// Directories that are never searched.
+const SKIPPED_DIRS: &[&str] = &[".git", ".hg", ".svn", "target", "node_modules", "__pycache__", ".venv"];
+
+/// A matching line in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectSearchHit {
+    /// File containing the match.
+    pub path: PathBuf,
+    /// Line of the match (0-indexed).
+    pub line: usize,
+    /// Column of the match start in characters (0-indexed).
+    pub col: usize,
+    /// The matching line, trimmed and truncated for display.
+    pub preview: String,
+    /// Modification time of the file when it was searched.
+    pub modified: Option<SystemTime>,
+}
+
+impl ProjectSearchHit {
+    /// Returns the `path:line: preview` label, with the path relative to `root`.
+    pub fn label(&self, root: Option<&Path>) -> String {
+        let path = root
+            .and_then(|root| self.path.strip_prefix(root).ok())
+            .unwrap_or(&self.path);
+        format!("{}:{}: {}", path.display(), self.line + 1, self.preview)
+    }
+}
+
+/// The hits of one file, for replacing across files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHits {
+    /// The file.
+    pub path: PathBuf,
+    /// Matching lines (0-indexed), in order.
+    pub lines: Vec<usize>,
+    /// Modification time of the file when it was searched.
+    pub modified: Option<SystemTime>,
+}
+
+impl FileHits {
+    /// Returns true if the file was modified on disk after it was searched.
+    pub fn changed_on_disk(&self) -> bool {
+        let current = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        current != self.modified
+    }
+}
+
+/// Message sent from the search thread.
+#[derive(Debug)]
+enum SearchMessage {
+    /// A match was found.
+    Hit(ProjectSearchHit),
+    /// The walk finished (or was stopped) after searching this many files.
+    Done { files_searched: usize },
+}
+
+/// State of a project search and its results.
+#[derive(Debug, Default)]
+pub struct ProjectSearch {
+    /// Root directory of the last search.
+    root: Option<PathBuf>,
+    /// Query of the last search.
+    query: String,
+    /// Results received so far, in walk order.
+    results: Vec<ProjectSearchHit>,
+    /// Index of the selected result.
+    selected: usize,
+    /// Channel from the running search thread, if any.
+    receiver: Option<Receiver<SearchMessage>>,
+    /// Set to stop the running search thread.
+    cancel: Arc<AtomicBool>,
+    /// Number of files searched by the last finished search.
+    files_searched: usize,
+}
+
+impl ProjectSearch {
+    /// Creates an empty project search.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts searching `root` for `query` on a background thread,
+    /// stopping any search already running and clearing previous results.
+    pub fn start(&mut self, root: PathBuf, query: &str) {
+        self.cancel();
+        self.results.clear();
+        self.selected = 0;
+        self.files_searched = 0;
+        self.query = query.to_string();
+        self.root = Some(root.clone());
+        if query.is_empty() {
+            return;
+        }
+
+        let (sender, receiver) = channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.receiver = Some(receiver);
+        self.cancel = Arc::clone(&cancel);
+
+        let query = query.to_string();
+        thread::spawn(move || {
+            let mut walker = Walker {
+                query: &query,
+                sender: &sender,
+                cancel: &cancel,
+                files_searched: 0,
+                hits: 0,
+            };
+            walker.walk(&root);
+            let _ = sender.send(SearchMessage::Done {
+                files_searched: walker.files_searched,
+            });
+        });
+    }
+
+    /// Stops the running search, keeping the results received so far.
+    pub fn cancel(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.receiver = None;
+    }
+
+    /// Collects results from the search thread without blocking.
+    /// Returns true if anything changed.
+    pub fn poll(&mut self) -> bool {
+        let Some(receiver) = &self.receiver else {
+            return false;
+        };
+
+        let mut changed = false;
+        loop {
+            match receiver.try_recv() {
+                Ok(SearchMessage::Hit(hit)) => {
+                    self.results.push(hit);
+                    changed = true;
+                }
+                Ok(SearchMessage::Done { files_searched }) => {
+                    self.files_searched = files_searched;
+                    self.receiver = None;
+                    return true;
+                }
+                Err(TryRecvError::Empty) => return changed,
+                Err(TryRecvError::Disconnected) => {
+                    self.receiver = None;
+                    return true;
+                }
+            }
+        }
+    }
+
+    /// Returns true while the search thread is still running.
+    pub fn is_running(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    /// Returns the query of the last search.
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Returns the root directory of the last search.
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Returns the results received so far.
+    pub fn results(&self) -> &[ProjectSearchHit] {
+        &self.results
+    }
+
+    /// Returns the results grouped by file, in walk order.
+    pub fn hits_by_file(&self) -> Vec<FileHits> {
+        let mut files: Vec<FileHits> = Vec::new();
+        for hit in &self.results {
+            match files.last_mut() {
+                Some(file) if file.path == hit.path => file.lines.push(hit.line),
+                _ => files.push(FileHits {
+                    path: hit.path.clone(),
+                    lines: vec![hit.line],
+                    modified: hit.modified,
+                }),
+            }
+        }
+        files
+    }
+
+    /// Returns the number of files searched by the last finished search.
+    pub fn files_searched(&self) -> usize {
+        self.files_searched
+    }
+
+    /// Returns the index of the selected result.
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    /// Returns the selected result.
+    pub fn selected(&self) -> Option<&ProjectSearchHit> {
+        self.results.get(self.selected)
+    }
+
+    /// Selects the next result, wrapping around.
+    pub fn select_next(&mut self) {
+        if !self.results.is_empty() {
+            self.selected = (self.selected + 1) % self.results.len();
+        }
+    }
+
+    /// Selects the previous result, wrapping around.
+    pub fn select_prev(&mut self) {
+        if !self.results.is_empty() {
+            self.selected = (self.selected + self.results.len() - 1) % self.results.len();
+        }
+    }
+
+    /// Returns a short status like "12 results in 40 files".
+    pub fn status_text(&self) -> String {
+        let count = self.results.len();
+        let plural = if count == 1 { "" } else { "s" };
+        if self.is_running() {
+            format!("Searching... {} result{}", count, plural)
+        } else if self.query.is_empty() {
+            String::new()
+        } else if count >= MAX_RESULTS {
+            format!("{}+ results (stopped)", MAX_RESULTS)
+        } else {
+            format!("{} result{} in {} files", count, plural, self.files_searched)
+        }
+    }
+}
+
+/// Recursive directory walk run on the search thread.
+struct Walker<'a> {
+    query: &'a str,
+    sender: &'a Sender<SearchMessage>,
+    cancel: &'a AtomicBool,
+    files_searched: usize,
+    hits: usize,
+}
+
+impl Walker<'_> {
+    /// Returns true if the walk should stop.
+    fn stopped(&self) -> bool {
+        self.hits >= MAX_RESULTS || self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// Searches every file under `dir`, in name order.
+    fn walk(&mut self, dir: &Path) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            if self.stopped() {
+                return;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                let name = entry.file_name();
+                if !SKIPPED_DIRS.iter().any(|skip| name == *skip) {
+                    self.walk(&path);
+                }
+            } else if file_type.is_file() {
+                let Ok(hits) = search_file(&path, self.query) else {
+                    continue;
+                };
+                self.files_searched += 1;
+                for hit in hits {
+                    if self.stopped() || self.sender.send(SearchMessage::Hit(hit)).is_err() {
+                        return;
+                    }
+                    self.hits += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Returns true if `bytes` look like binary content.
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0)
+}
+
+/// Searches one file for `query`, returning one hit per matching line.
+///
+/// Files over [`MAX_FILE_SIZE`], binary files, and files that aren't valid
+/// UTF-8 produce no hits.
+pub fn search_file(path: &Path, query: &str) -> io::Result<Vec<ProjectSearchHit>> {
+    let metadata = fs::metadata(path)?;
+    if query.is_empty() || metadata.len() > MAX_FILE_SIZE {
+        return Ok(Vec::new());
+    }
+    let modified = metadata.modified().ok();
+    let bytes = fs::read(path)?;
+    if is_binary(&bytes) {
+        return Ok(Vec::new());
+    }
+    let Ok(text) = std::str::from_utf8(&bytes) else {
+        return Ok(Vec::new());
+    };
+
+    let hits = text
+        .lines()
+        .enumerate()
+        .filter_map(|(line, content)| {
+            let byte_col = content.find(query)?;
+            Some(ProjectSearchHit {
+                path: path.to_path_buf(),
+                line,
+                col: content[..byte_col].chars().count(),
+                preview: content.trim().chars().take(MAX_PREVIEW_CHARS).collect(),
+                modified,
+            })
+        })
+        .collect();
+    Ok(hits)
+}
+
+/// Replaces every occurrence of `query` on the given lines (0-indexed) of
+/// `text`, keeping line endings as they are.
+/// Returns the new text and the number of replacements.
+pub fn replace_in_lines(text: &str, lines: &[usize], query: &str, replacement: &str) -> (String, usize) {
+    if query.is_empty() {
+        return (text.to_string(), 0);
+    }
+    let mut result = String::with_capacity(text.len());
+    let mut count = 0;
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        if lines.contains(&index) {
+            count += line.matches(query).count();
+            result.push_str(&line.replace(query, replacement));
+        } else {
+            result.push_str(line);
+        }
+    }
+    (result, count)
+}
+
+/// Replaces the hits of a file that isn't open by rewriting it on disk.
+/// Returns the number of replacements.
+pub fn replace_in_file(file: &FileHits, query: &str, replacement: &str) -> io::Result<usize> {
+    let text = fs::read_to_string(&file.path)?;
+    let (new_text, count) = replace_in_lines(&text, &file.lines, query, replacement);
+    if count > 0 {
+        fs::write(&file.path, new_text)?;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Creates an empty scratch directory unique to the test.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cp_editor_project_search_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Polls until the search finishes or a timeout passes.
+    fn wait_for(search: &mut ProjectSearch) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while search.is_running() && Instant::now() < deadline {
+            search.poll();
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_search_file_hits() {
+        let dir = scratch_dir("file");
+        let path = dir.join("a.rs");
+        fs::write(&path, "fn main() {\n    let café = needle;\n}\nneedle\n").unwrap();
+
+        let hits = search_file(&path, "needle").unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!((hits[0].line, hits[0].col), (1, 15));
+        assert_eq!(hits[0].preview, "let café = needle;");
+        assert_eq!(hits[0].label(Some(&dir)), "a.rs:2: let café = needle;");
+        assert_eq!((hits[1].line, hits[1].col), (3, 0));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_search_file_skips_binary() {
+        let dir = scratch_dir("binary");
+        let path = dir.join("blob.bin");
+        fs::write(&path, b"needle\0\xff\xfe").unwrap();
+
+        assert!(search_file(&path, "needle").unwrap().is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_search_streams_results() {
+        let dir = scratch_dir("walk");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join("src/lib.rs"), "needle one\nother\n").unwrap();
+        fs::write(dir.join("b.txt"), "no match\n").unwrap();
+        fs::write(dir.join("a.txt"), "x needle\n").unwrap();
+        fs::write(dir.join(".git/config"), "needle\n").unwrap();
+
+        let mut search = ProjectSearch::new();
+        search.start(dir.clone(), "needle");
+        wait_for(&mut search);
+
+        assert!(!search.is_running());
+        let labels: Vec<_> = search.results().iter().map(|hit| hit.label(search.root())).collect();
+        let lib = Path::new("src").join("lib.rs");
+        assert_eq!(labels, vec!["a.txt:1: x needle".to_string(), format!("{}:1: needle one", lib.display())]);
+        assert_eq!(search.files_searched(), 3);
+        assert_eq!(search.status_text(), "2 results in 3 files");
+
+        search.select_next();
+        assert_eq!(search.selected().unwrap().line, 0);
+        assert_eq!(search.selected_index(), 1);
+        search.select_next();
+        assert_eq!(search.selected_index(), 0);
+        search.select_prev();
+        assert_eq!(search.selected_index(), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_replace_in_lines() {
+        let text = "foo = foo;\r\nkeep foo\nfoo\n";
+        let (replaced, count) = replace_in_lines(text, &[0, 2], "foo", "bar");
+        assert_eq!(replaced, "bar = bar;\r\nkeep foo\nbar\n");
+        assert_eq!(count, 3);
+        assert_eq!(replace_in_lines(text, &[5], "foo", "bar"), (text.to_string(), 0));
+    }
+
+    #[test]
+    fn test_replace_in_file_and_conflicts() {
+        let dir = scratch_dir("replace");
+        let path = dir.join("a.txt");
+        fs::write(&path, "old one\nother\nold two old\n").unwrap();
+
+        let mut search = ProjectSearch::new();
+        search.start(dir.clone(), "old");
+        wait_for(&mut search);
+        let files = search.hits_by_file();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].lines, vec![0, 2]);
+        assert!(!files[0].changed_on_disk());
+
+        assert_eq!(replace_in_file(&files[0], "old", "new").unwrap(), 3);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new one\nother\nnew two new\n");
+
+        // Rewriting the file makes the search results stale
+        let stale = FileHits {
+            modified: Some(SystemTime::UNIX_EPOCH),
+            ..files[0].clone()
+        };
+        assert!(stale.changed_on_disk());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_empty_query_does_not_search() {
+        let mut search = ProjectSearch::new();
+        search.start(std::env::temp_dir(), "");
+        assert!(!search.is_running());
+        assert!(search.results().is_empty());
+        assert!(!search.poll());
+    }
+}
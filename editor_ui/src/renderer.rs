@@ -94,10 +94,10 @@ impl Renderer {
             }
         } else if alpha > 0 {
             // Alpha blend
-            let src_r = ((color >> 16) & 0xFF) as u32;
-            let src_g = ((color >> 8) & 0xFF) as u32;
-            let src_b = (color & 0xFF) as u32;
-            let src_a = alpha as u32;
+            let src_r = (color >> 16) & 0xFF;
+            let src_g = (color >> 8) & 0xFF;
+            let src_b = color & 0xFF;
+            let src_a = alpha;
             let inv_a = 255 - src_a;
 
             for py in y0..y1 {
@@ -105,9 +105,9 @@ impl Renderer {
                 for px in x0..x1 {
                     let idx = row_start + px as usize;
                     let dst = self.buffer[idx];
-                    let dst_r = ((dst >> 16) & 0xFF) as u32;
-                    let dst_g = ((dst >> 8) & 0xFF) as u32;
-                    let dst_b = (dst & 0xFF) as u32;
+                    let dst_r = (dst >> 16) & 0xFF;
+                    let dst_g = (dst >> 8) & 0xFF;
+                    let dst_b = dst & 0xFF;
 
                     let r = (src_r * src_a + dst_r * inv_a) / 255;
                     let g = (src_g * src_a + dst_g * inv_a) / 255;
@@ -136,9 +136,9 @@ impl Renderer {
         let baseline_y = y + self.atlas.ascent;
         let gy = (baseline_y - glyph.offset_y - glyph.height as f32) as i32;
 
-        let src_r = ((color >> 16) & 0xFF) as u32;
-        let src_g = ((color >> 8) & 0xFF) as u32;
-        let src_b = (color & 0xFF) as u32;
+        let src_r = (color >> 16) & 0xFF;
+        let src_g = (color >> 8) & 0xFF;
+        let src_b = color & 0xFF;
 
         for py in 0..glyph.height {
             let screen_y = gy + py as i32;
@@ -168,9 +168,9 @@ impl Renderer {
                     self.buffer[idx] = color;
                 } else {
                     let dst = self.buffer[idx];
-                    let dst_r = ((dst >> 16) & 0xFF) as u32;
-                    let dst_g = ((dst >> 8) & 0xFF) as u32;
-                    let dst_b = (dst & 0xFF) as u32;
+                    let dst_r = (dst >> 16) & 0xFF;
+                    let dst_g = (dst >> 8) & 0xFF;
+                    let dst_b = dst & 0xFF;
                     let inv_a = 255 - alpha;
 
                     let r = (src_r * alpha + dst_r * inv_a) / 255;
@@ -0,0 +1,196 @@
+//! User settings read from `settings.json` in the config directory.
+//!
+//! Every field has a default, so the file only needs the ones that
+//! differ, e.g. `{ "mouse": { "invert_scroll": true } }` or
+//! `{ "save": { "trim_trailing_whitespace": true } }`. A missing or
+//! malformed file gives the defaults.
+
+use crate::notifications::NotificationType;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// All user settings, by section.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// How the mouse and trackpad behave.
+    pub mouse: MouseSettings,
+    /// What saving cleans up.
+    pub save: SaveSettings,
+    /// Which files are kept from the language servers.
+    pub lsp: LspSettings,
+    /// How long notifications stay.
+    pub notifications: NotificationSettings,
+}
+
+impl Settings {
+    /// Reads the settings from the JSON file at `path`.
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|json| Self::parse(&json))
+            .unwrap_or_default()
+    }
+
+    /// Parses settings from JSON, or `None` if it is malformed.
+    pub fn parse(json: &str) -> Option<Self> {
+        serde_json::from_str(json).ok()
+    }
+}
+
+/// How the mouse and trackpad behave.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MouseSettings {
+    /// Lines scrolled per notch of the wheel.
+    pub wheel_lines_per_notch: f32,
+    /// Factor applied to horizontal scrolling.
+    pub horizontal_scroll_multiplier: f32,
+    /// Scrolls the other way, e.g. for natural scrolling on a trackpad.
+    pub invert_scroll: bool,
+    /// Longest pause between the clicks of a double or triple click, in
+    /// milliseconds.
+    pub multi_click_interval_ms: u64,
+    /// How far the pointer moves with the button held, in pixels, before
+    /// the selection follows it, so a click that jitters doesn't select.
+    pub drag_threshold: f32,
+    /// Whether text selected with the mouse becomes the primary selection,
+    /// pasted with the middle button on Linux.
+    pub primary_selection: bool,
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        Self {
+            wheel_lines_per_notch: 1.0,
+            horizontal_scroll_multiplier: 1.0,
+            invert_scroll: false,
+            multi_click_interval_ms: 400,
+            drag_threshold: 4.0,
+            primary_selection: true,
+        }
+    }
+}
+
+impl MouseSettings {
+    /// Returns the longest pause between the clicks of a double click.
+    pub fn multi_click_interval(&self) -> Duration {
+        Duration::from_millis(self.multi_click_interval_ms)
+    }
+
+    /// Returns whether moving the pointer from `press` to `now` with the
+    /// button held starts a drag.
+    pub fn starts_drag(&self, press: (f32, f32), now: (f32, f32)) -> bool {
+        let (dx, dy) = (now.0 - press.0, now.1 - press.1);
+        dx.hypot(dy) > self.drag_threshold
+    }
+}
+
+/// What saving cleans up, for buffers opened from then on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SaveSettings {
+    /// Strips spaces and tabs from the ends of lines.
+    pub trim_trailing_whitespace: bool,
+    /// Adds a line break at the end of the file if there is none.
+    pub ensure_final_newline: bool,
+}
+
+/// Which files are kept from the language servers, e.g. huge generated
+/// sources. They are treated like files no server knows.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct LspSettings {
+    /// Language ids, e.g. `rust`, whose servers are never used.
+    pub disabled_languages: Vec<String>,
+    /// Path patterns relative to the workspace root, e.g. `src/gen/*.rs`:
+    /// without a `/` a pattern matches the file name, with one the end of
+    /// the path.
+    pub exclude_globs: Vec<String>,
+}
+
+/// How long notifications stay, by type, so errors aren't gone before
+/// they are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    /// How long success notifications stay, in milliseconds.
+    pub success_ms: u64,
+    /// How long info notifications stay, in milliseconds.
+    pub info_ms: u64,
+    /// How long warnings stay, in milliseconds.
+    pub warning_ms: u64,
+    /// How long errors stay, in milliseconds.
+    pub error_ms: u64,
+    /// Keeps errors until they are clicked away.
+    pub sticky_errors: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            success_ms: 3000,
+            info_ms: 3000,
+            warning_ms: 6000,
+            error_ms: 10_000,
+            sticky_errors: false,
+        }
+    }
+}
+
+impl NotificationSettings {
+    /// Returns how long notifications of `notification_type` stay.
+    pub fn duration(&self, notification_type: NotificationType) -> Duration {
+        Duration::from_millis(match notification_type {
+            NotificationType::Success => self.success_ms,
+            NotificationType::Info => self.info_ms,
+            NotificationType::Warning => self.warning_ms,
+            NotificationType::Error => self.error_ms,
+        })
+    }
+
+    /// Returns whether notifications of `notification_type` stay until
+    /// dismissed.
+    pub fn is_sticky(&self, notification_type: NotificationType) -> bool {
+        self.sticky_errors && notification_type == NotificationType::Error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_settings() {
+        // Missing fields keep their defaults
+        let settings = Settings::parse(r#"{ "mouse": { "invert_scroll": true, "multi_click_interval_ms": 250 } }"#).unwrap();
+        assert!(settings.mouse.invert_scroll);
+        assert_eq!(settings.mouse.multi_click_interval(), Duration::from_millis(250));
+        assert_eq!(settings.mouse.wheel_lines_per_notch, 1.0);
+        assert_eq!(settings.save, SaveSettings::default());
+        let settings = Settings::parse(r#"{ "save": { "ensure_final_newline": true } }"#).unwrap();
+        assert!(settings.save.ensure_final_newline && !settings.save.trim_trailing_whitespace);
+        let settings = Settings::parse(r#"{ "lsp": { "exclude_globs": ["gen/*.rs"] } }"#).unwrap();
+        assert_eq!(settings.lsp.exclude_globs, ["gen/*.rs"]);
+        assert!(settings.lsp.disabled_languages.is_empty());
+        let settings = Settings::parse(r#"{ "notifications": { "sticky_errors": true, "error_ms": 20000 } }"#).unwrap();
+        assert!(settings.notifications.is_sticky(NotificationType::Error));
+        assert!(!settings.notifications.is_sticky(NotificationType::Warning));
+        assert_eq!(settings.notifications.duration(NotificationType::Error), Duration::from_secs(20));
+        assert_eq!(settings.notifications.duration(NotificationType::Success), Duration::from_secs(3));
+        assert_eq!(Settings::parse("{}"), Some(Settings::default()));
+        assert_eq!(Settings::parse("{ mouse"), None);
+        assert_eq!(Settings::load(Path::new("/nonexistent/settings.json")), Settings::default());
+    }
+
+    #[test]
+    fn test_drag_threshold() {
+        let mut mouse = MouseSettings::default();
+        assert!(!mouse.starts_drag((10.0, 10.0), (12.0, 13.0)));
+        assert!(mouse.starts_drag((10.0, 10.0), (15.0, 10.0)));
+        mouse.drag_threshold = 0.0;
+        assert!(mouse.starts_drag((10.0, 10.0), (10.5, 10.0)));
+    }
+}
@@ -0,0 +1,206 @@
+//! Tab overflow menu.
+//!
+//! When the tabs don't fit in the tab bar, a chevron button at its right
+//! end opens a dropdown listing every open buffer in tab order. Hovering an
+//! entry shows its full path; clicking it switches to that buffer.
+
+use cp_editor_core::{BufferId, TabInfo};
+use std::path::PathBuf;
+
+/// One open buffer as listed in the menu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabMenuEntry {
+    /// The buffer to switch to.
+    pub id: BufferId,
+    /// Display name, as on its tab.
+    pub name: String,
+    /// Full file path, if any.
+    pub path: Option<PathBuf>,
+    /// Whether the buffer has unsaved changes.
+    pub is_modified: bool,
+    /// Whether it is the active buffer.
+    pub is_active: bool,
+}
+
+/// Where the menu is drawn, for rendering and hit-testing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TabMenuLayout {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// Height of one entry.
+    pub row_height: f32,
+    /// Space above the first entry.
+    pub padding: f32,
+}
+
+impl TabMenuLayout {
+    /// Returns whether the point is inside the menu.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+
+    /// Returns the index of the entry at the point, if any.
+    pub fn row_at(&self, x: f32, y: f32, entries: usize) -> Option<usize> {
+        if !self.contains(x, y) || y < self.y + self.padding {
+            return None;
+        }
+        let row = ((y - self.y - self.padding) / self.row_height) as usize;
+        (row < entries).then_some(row)
+    }
+
+    /// Returns the top of an entry's row.
+    pub fn row_y(&self, row: usize) -> f32 {
+        self.y + self.padding + row as f32 * self.row_height
+    }
+}
+
+/// State of the overflow dropdown.
+#[derive(Debug, Default)]
+pub struct TabMenu {
+    /// Snapshot of the tabs taken when the menu opened.
+    entries: Vec<TabMenuEntry>,
+    /// Index of the entry under the mouse.
+    hovered: Option<usize>,
+    /// Whether the menu is open.
+    open: bool,
+}
+
+impl TabMenu {
+    /// Space around the entries.
+    pub const PADDING: f32 = 4.0;
+    /// Narrowest the menu gets, in characters.
+    const MIN_CHARS: usize = 16;
+    /// Widest the menu gets, in characters.
+    const MAX_CHARS: usize = 48;
+
+    /// Creates a closed menu.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the menu listing `tabs`, marking `active` as the current one.
+    pub fn open(&mut self, tabs: &[TabInfo], active: Option<BufferId>) {
+        self.entries = tabs
+            .iter()
+            .map(|tab| TabMenuEntry {
+                id: tab.id,
+                name: tab.name.clone(),
+                path: tab.path.clone(),
+                is_modified: tab.is_modified,
+                is_active: Some(tab.id) == active,
+            })
+            .collect();
+        self.hovered = None;
+        self.open = true;
+    }
+
+    /// Closes the menu.
+    pub fn close(&mut self) {
+        self.open = false;
+        self.entries.clear();
+        self.hovered = None;
+    }
+
+    /// Returns whether the menu is open.
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Returns the listed buffers, in tab order.
+    pub fn entries(&self) -> &[TabMenuEntry] {
+        &self.entries
+    }
+
+    /// Returns the entry under the mouse.
+    pub fn hovered(&self) -> Option<&TabMenuEntry> {
+        self.entries.get(self.hovered?)
+    }
+
+    /// Returns the index of the entry under the mouse.
+    pub fn hovered_index(&self) -> Option<usize> {
+        self.hovered
+    }
+
+    /// Sets the entry under the mouse. Returns true if it changed.
+    pub fn set_hovered(&mut self, index: Option<usize>) -> bool {
+        let index = index.filter(|&i| i < self.entries.len());
+        let changed = self.hovered != index;
+        self.hovered = index;
+        changed
+    }
+
+    /// Returns where the menu goes: right-aligned under the tab bar, whose
+    /// bottom is at `top`.
+    pub fn layout(&self, char_width: f32, line_height: f32, viewport_width: f32, top: f32) -> TabMenuLayout {
+        // Each row is a modified marker, a space and the name
+        let longest = self.entries.iter().map(|entry| entry.name.chars().count()).max().unwrap_or(0);
+        let chars = (longest + 2).clamp(Self::MIN_CHARS, Self::MAX_CHARS);
+        let width = (chars as f32 * char_width + 2.0 * Self::PADDING).min(viewport_width);
+        let height = self.entries.len() as f32 * line_height + 2.0 * Self::PADDING;
+        TabMenuLayout {
+            x: (viewport_width - width - Self::PADDING).max(0.0),
+            y: top,
+            width,
+            height,
+            row_height: line_height,
+            padding: Self::PADDING,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tab(id: BufferId, name: &str, is_modified: bool) -> TabInfo {
+        TabInfo {
+            id,
+            name: name.to_string(),
+            path: Some(PathBuf::from(format!("/project/src/{}", name))),
+            is_modified,
+            icon: "rs".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_lists_every_buffer() {
+        let tabs = [tab(4, "main.rs", false), tab(1, "lib.rs", true), tab(7, "app.rs", true)];
+        let mut menu = TabMenu::new();
+        menu.open(&tabs, Some(1));
+        assert!(menu.is_open());
+
+        let listed: Vec<_> = menu.entries().iter().map(|e| (e.id, e.is_modified, e.is_active)).collect();
+        assert_eq!(listed, [(4, false, false), (1, true, true), (7, true, false)]);
+
+        menu.close();
+        assert!(!menu.is_open());
+        assert!(menu.entries().is_empty());
+    }
+
+    #[test]
+    fn test_hit_testing() {
+        let tabs = [tab(0, "a.rs", false), tab(1, "b.rs", false)];
+        let mut menu = TabMenu::new();
+        menu.open(&tabs, None);
+        let layout = menu.layout(10.0, 20.0, 800.0, 30.0);
+        assert_eq!(layout.width, 16.0 * 10.0 + 8.0);
+        assert_eq!(layout.x + layout.width, 800.0 - TabMenu::PADDING);
+
+        let x = layout.x + 5.0;
+        assert_eq!(layout.row_at(x, layout.row_y(0) + 1.0, 2), Some(0));
+        assert_eq!(layout.row_at(x, layout.row_y(1) + 19.0, 2), Some(1));
+        // The padding and the outside aren't entries
+        assert_eq!(layout.row_at(x, 31.0, 2), None);
+        assert_eq!(layout.row_at(x, layout.row_y(2) + 1.0, 2), None);
+        assert_eq!(layout.row_at(layout.x - 1.0, layout.row_y(0) + 1.0, 2), None);
+
+        // Hovering shows the full path
+        assert!(menu.set_hovered(Some(1)));
+        assert!(!menu.set_hovered(Some(1)));
+        assert_eq!(menu.hovered().and_then(|e| e.path.clone()), Some(PathBuf::from("/project/src/b.rs")));
+        menu.set_hovered(Some(5));
+        assert!(menu.hovered().is_none());
+    }
+}
@@ -0,0 +1,161 @@
+//! Most-recently-used tab switcher (Ctrl+Tab popup).
+//!
+//! While the primary modifier is held, each Tab press advances the selection
+//! through the open buffers in MRU order; releasing the modifier commits the
+//! switch to the selected buffer.
+
+use cp_editor_core::BufferId;
+
+/// State for the Ctrl+Tab switcher popup.
+#[derive(Debug, Default)]
+pub struct TabSwitcher {
+    /// Snapshot of the MRU order taken when the switcher opened.
+    entries: Vec<BufferId>,
+    /// Index of the selected entry.
+    selected: usize,
+    /// Whether the switcher is currently open.
+    active: bool,
+}
+
+impl TabSwitcher {
+    /// Creates a new, inactive switcher.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handles a Tab press while the modifier is held.
+    ///
+    /// Opens the switcher on the first press, selecting the previously used
+    /// buffer (or the least recently used one when going backward);
+    /// subsequent presses move the selection. Does nothing with fewer than
+    /// two buffers.
+    pub fn advance(&mut self, mru: &[BufferId], forward: bool) {
+        if self.active {
+            self.step(forward);
+            return;
+        }
+        if mru.len() < 2 {
+            return;
+        }
+        self.entries = mru.to_vec();
+        self.selected = if forward { 1 } else { self.entries.len() - 1 };
+        self.active = true;
+    }
+
+    /// Moves the selection one entry forward or backward, wrapping around.
+    fn step(&mut self, forward: bool) {
+        let len = self.entries.len();
+        if len == 0 {
+            return;
+        }
+        self.selected = if forward {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+    }
+
+    /// Closes the switcher and returns the buffer to switch to.
+    ///
+    /// Returns `None` if the switcher wasn't open.
+    pub fn commit(&mut self) -> Option<BufferId> {
+        if !self.active {
+            return None;
+        }
+        let id = self.entries.get(self.selected).copied();
+        self.cancel();
+        id
+    }
+
+    /// Closes the switcher without switching.
+    pub fn cancel(&mut self) {
+        self.active = false;
+        self.entries.clear();
+        self.selected = 0;
+    }
+
+    /// Returns whether the switcher is open.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Returns the buffers listed in the switcher, in MRU order.
+    pub fn entries(&self) -> &[BufferId] {
+        &self.entries
+    }
+
+    /// Returns the index of the selected entry.
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_press_selects_previous_buffer() {
+        let mut switcher = TabSwitcher::new();
+        switcher.advance(&[3, 1, 2], true);
+
+        assert!(switcher.is_active());
+        assert_eq!(switcher.entries(), &[3, 1, 2]);
+        assert_eq!(switcher.selected_index(), 1);
+        assert_eq!(switcher.commit(), Some(1));
+        assert!(!switcher.is_active());
+    }
+
+    #[test]
+    fn test_repeated_presses_wrap() {
+        let mut switcher = TabSwitcher::new();
+        switcher.advance(&[3, 1, 2], true);
+        switcher.advance(&[3, 1, 2], true);
+        assert_eq!(switcher.selected_index(), 2);
+        switcher.advance(&[3, 1, 2], true);
+        assert_eq!(switcher.selected_index(), 0);
+
+        // Shift+Tab goes back
+        switcher.advance(&[3, 1, 2], false);
+        assert_eq!(switcher.commit(), Some(2));
+    }
+
+    #[test]
+    fn test_backward_first_press_selects_oldest() {
+        let mut switcher = TabSwitcher::new();
+        switcher.advance(&[3, 1, 2], false);
+        assert_eq!(switcher.commit(), Some(2));
+    }
+
+    #[test]
+    fn test_commit_when_inactive() {
+        let mut switcher = TabSwitcher::new();
+        assert_eq!(switcher.commit(), None);
+
+        // A single buffer never opens the switcher
+        switcher.advance(&[0], true);
+        assert!(!switcher.is_active());
+        assert_eq!(switcher.commit(), None);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut switcher = TabSwitcher::new();
+        switcher.advance(&[0, 1], true);
+        switcher.cancel();
+
+        assert!(!switcher.is_active());
+        assert!(switcher.entries().is_empty());
+        assert_eq!(switcher.commit(), None);
+    }
+
+    #[test]
+    fn test_entries_are_snapshot() {
+        let mut switcher = TabSwitcher::new();
+        switcher.advance(&[0, 1, 2], true);
+        // Later presses step through the original snapshot
+        switcher.advance(&[1, 0, 2], true);
+        assert_eq!(switcher.entries(), &[0, 1, 2]);
+        assert_eq!(switcher.commit(), Some(2));
+    }
+}
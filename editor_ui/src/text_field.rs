@@ -0,0 +1,330 @@
+//! Single-line text input of the input bar.
+//!
+//! The search, replace, go to line and rename bars, and the others that
+//! take text, each edit a [`TextField`]: a line of text with a cursor and
+//! a selection, moved by character or word, with pasting and an optional
+//! filter on what may be entered. It knows nothing of keys or the
+//! clipboard; the app maps those onto its methods.
+
+use std::ops::Range;
+
+/// A line of editable text with a cursor and a selection.
+#[derive(Debug, Clone, Default)]
+pub struct TextField {
+    text: String,
+    /// Byte offset of the cursor.
+    cursor: usize,
+    /// Byte offset of the selection's other end, if there is a selection.
+    anchor: Option<usize>,
+    /// Which characters may be entered, if not all.
+    filter: Option<fn(char) -> bool>,
+}
+
+impl TextField {
+    /// Creates an empty field taking any text.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty field taking only the characters `filter` accepts.
+    pub fn with_filter(filter: fn(char) -> bool) -> Self {
+        Self {
+            filter: Some(filter),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the text.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns whether the field is empty.
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Replaces the text, as is, putting the cursor at its end.
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        self.text = text.into();
+        self.cursor = self.text.len();
+        self.anchor = None;
+    }
+
+    /// Empties the field.
+    pub fn clear(&mut self) {
+        self.set_text(String::new());
+    }
+
+    /// Returns the byte offset of the cursor.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Returns the selected byte range, if any text is selected.
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor.filter(|&anchor| anchor != self.cursor)?;
+        Some(anchor.min(self.cursor)..anchor.max(self.cursor))
+    }
+
+    /// Returns the selected text, if any.
+    pub fn selected_text(&self) -> Option<&str> {
+        self.selection().map(|range| &self.text[range])
+    }
+
+    /// Types `text` over the selection, or at the cursor. Line breaks and
+    /// other control characters, and characters the filter rejects, are
+    /// left out. Returns whether the text changed.
+    pub fn insert(&mut self, text: &str) -> bool {
+        let accepted: String = text
+            .chars()
+            .filter(|&ch| !ch.is_control() && self.filter.is_none_or(|filter| filter(ch)))
+            .collect();
+        if accepted.is_empty() {
+            return false;
+        }
+        self.delete_selection();
+        self.text.insert_str(self.cursor, &accepted);
+        self.cursor += accepted.len();
+        true
+    }
+
+    /// Pastes the first line of `text` over the selection, or at the
+    /// cursor. Returns whether the text changed.
+    pub fn paste(&mut self, text: &str) -> bool {
+        self.insert(text.lines().next().unwrap_or_default())
+    }
+
+    /// Deletes the selection, or the character before the cursor. Returns
+    /// whether the text changed.
+    pub fn backspace(&mut self) -> bool {
+        if self.delete_selection() {
+            return true;
+        }
+        let Some(ch) = self.text[..self.cursor].chars().next_back() else {
+            return false;
+        };
+        self.cursor -= ch.len_utf8();
+        self.text.remove(self.cursor);
+        true
+    }
+
+    /// Deletes the selection, or the character after the cursor. Returns
+    /// whether the text changed.
+    pub fn delete(&mut self) -> bool {
+        if self.delete_selection() {
+            return true;
+        }
+        if self.cursor == self.text.len() {
+            return false;
+        }
+        self.text.remove(self.cursor);
+        true
+    }
+
+    /// Moves the cursor a character left, extending the selection if
+    /// `select` is set. Without it a selection collapses to its start.
+    pub fn move_left(&mut self, select: bool) {
+        let target = match self.selection() {
+            Some(range) if !select => range.start,
+            _ => self.prev_char_boundary(),
+        };
+        self.move_to(target, select);
+    }
+
+    /// Moves the cursor a character right, extending the selection if
+    /// `select` is set. Without it a selection collapses to its end.
+    pub fn move_right(&mut self, select: bool) {
+        let target = match self.selection() {
+            Some(range) if !select => range.end,
+            _ => self.next_char_boundary(),
+        };
+        self.move_to(target, select);
+    }
+
+    /// Moves the cursor to the start of the word before it.
+    pub fn move_word_left(&mut self, select: bool) {
+        let before = &self.text[..self.cursor];
+        let end = before.trim_end_matches(|ch| !is_word_char(ch)).len();
+        let start = before[..end].trim_end_matches(is_word_char).len();
+        self.move_to(start, select);
+    }
+
+    /// Moves the cursor to the end of the word after it.
+    pub fn move_word_right(&mut self, select: bool) {
+        let after = &self.text[self.cursor..];
+        let rest = after.trim_start_matches(|ch| !is_word_char(ch));
+        let end = self.text.len() - rest.trim_start_matches(is_word_char).len();
+        self.move_to(end, select);
+    }
+
+    /// Moves the cursor to the start of the text.
+    pub fn move_home(&mut self, select: bool) {
+        self.move_to(0, select);
+    }
+
+    /// Moves the cursor to the end of the text.
+    pub fn move_end(&mut self, select: bool) {
+        self.move_to(self.text.len(), select);
+    }
+
+    /// Selects the whole text, the cursor at its end.
+    pub fn select_all(&mut self) {
+        self.anchor = Some(0);
+        self.cursor = self.text.len();
+    }
+
+    /// Returns the index of the first character shown when `columns`
+    /// characters fit, so that the cursor stays in view.
+    pub fn scroll(&self, columns: usize) -> usize {
+        let cursor_col = self.text[..self.cursor].chars().count();
+        cursor_col.saturating_sub(columns)
+    }
+
+    /// Moves the cursor to `pos`, keeping or starting the selection if
+    /// `select` is set and dropping it otherwise.
+    fn move_to(&mut self, pos: usize, select: bool) {
+        if select {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = pos;
+    }
+
+    /// Removes the selected text. Returns whether there was any.
+    fn delete_selection(&mut self) -> bool {
+        let Some(range) = self.selection() else {
+            self.anchor = None;
+            return false;
+        };
+        self.cursor = range.start;
+        self.text.replace_range(range, "");
+        self.anchor = None;
+        true
+    }
+
+    fn prev_char_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .chars()
+            .next_back()
+            .map_or(0, |ch| self.cursor - ch.len_utf8())
+    }
+
+    fn next_char_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .chars()
+            .next()
+            .map_or(self.cursor, |ch| self.cursor + ch.len_utf8())
+    }
+}
+
+/// Returns whether word movement treats `ch` as part of a word.
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field_with(text: &str) -> TextField {
+        let mut field = TextField::new();
+        field.set_text(text);
+        field
+    }
+
+    #[test]
+    fn test_insert_and_delete_at_cursor() {
+        let mut field = field_with("hello");
+        field.move_left(false);
+        field.move_left(false);
+        assert!(field.insert("X"));
+        assert_eq!((field.text(), field.cursor()), ("helXlo", 4));
+        assert!(field.backspace());
+        assert!(field.delete());
+        assert_eq!((field.text(), field.cursor()), ("helo", 3));
+
+        field.move_home(false);
+        assert!(!field.backspace());
+        field.move_end(false);
+        assert!(!field.delete());
+        assert!(field.insert("\u{e9}\u{e9}"));
+        field.move_left(false);
+        assert!(field.backspace());
+        assert_eq!(field.text(), "helo\u{e9}");
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut field = field_with("find me");
+        field.move_left(true);
+        field.move_left(true);
+        assert_eq!(field.selected_text(), Some("me"));
+        // Typing replaces the selection
+        assert!(field.insert("you"));
+        assert_eq!(field.text(), "find you");
+        assert_eq!(field.selection(), None);
+
+        field.select_all();
+        assert_eq!(field.selected_text(), Some("find you"));
+        // Moving without Shift collapses the selection to that side
+        field.move_left(false);
+        assert_eq!((field.cursor(), field.selection()), (0, None));
+        field.select_all();
+        assert!(field.backspace());
+        assert!(field.is_empty());
+
+        // Selecting back to where it started selects nothing
+        let mut field = field_with("ab");
+        field.move_left(true);
+        field.move_right(true);
+        assert_eq!(field.selection(), None);
+    }
+
+    #[test]
+    fn test_word_movement() {
+        let mut field = field_with("let my_var = 42;");
+        field.move_word_left(false);
+        assert_eq!(field.cursor(), 13);
+        field.move_word_left(true);
+        assert_eq!(field.selected_text(), Some("my_var = "));
+        field.move_home(false);
+        field.move_word_right(false);
+        assert_eq!(field.cursor(), 3);
+        field.move_word_right(true);
+        assert_eq!(field.selected_text(), Some(" my_var"));
+        field.move_end(false);
+        field.move_word_right(false);
+        assert_eq!(field.cursor(), 16);
+    }
+
+    #[test]
+    fn test_filter_and_paste() {
+        let mut field = TextField::with_filter(|ch| ch.is_ascii_digit() || ch == ':');
+        assert!(field.insert("12a:3"));
+        assert_eq!(field.text(), "12:3");
+        assert!(!field.insert("x"));
+        // A rejected paste leaves the selection in place
+        field.select_all();
+        assert!(!field.paste("abc"));
+        assert_eq!(field.selected_text(), Some("12:3"));
+        // Only the first line of a paste goes in
+        assert!(field.paste("40\n50"));
+        assert_eq!(field.text(), "40");
+
+        let mut field = TextField::new();
+        assert!(field.paste("fn main() {\n}"));
+        assert_eq!(field.text(), "fn main() {");
+        assert!(!field.insert("\t"));
+    }
+
+    #[test]
+    fn test_scroll_keeps_cursor_in_view() {
+        let mut field = field_with("0123456789");
+        assert_eq!(field.scroll(4), 6);
+        assert_eq!(field.scroll(20), 0);
+        field.move_home(false);
+        assert_eq!(field.scroll(4), 0);
+    }
+}
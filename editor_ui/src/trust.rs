@@ -0,0 +1,123 @@
+//! Folders trusted to run language servers in.
+//!
+//! A language server started on a project can run code from it: build
+//! scripts, project-local binaries and configuration. Until the user
+//! trusts a workspace root, its servers stay off. Trusting a folder trusts
+//! everything inside it, and the trusted folders are kept in
+//! `trusted.json` in the config directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The folders the user trusts.
+#[derive(Debug, Clone, Default)]
+pub struct TrustStore {
+    /// Trusted folders, canonical, none inside another.
+    roots: Vec<PathBuf>,
+    /// JSON file the folders are kept in, if any.
+    store: Option<PathBuf>,
+}
+
+impl TrustStore {
+    /// Loads the folders kept in the JSON file `store`. A missing or
+    /// unreadable file trusts nothing.
+    pub fn load(store: PathBuf) -> Self {
+        let roots = fs::read_to_string(&store)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            roots,
+            store: Some(store),
+        }
+    }
+
+    /// Returns the trusted folders.
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Returns true if `root` or a folder containing it is trusted.
+    pub fn is_trusted(&self, root: &Path) -> bool {
+        let root = canonical(root);
+        self.roots.iter().any(|trusted| root.starts_with(trusted))
+    }
+
+    /// Trusts `root` and everything inside it.
+    pub fn trust(&mut self, root: &Path) {
+        if self.is_trusted(root) {
+            return;
+        }
+        let root = canonical(root);
+        // Folders inside it are now trusted through it
+        self.roots.retain(|trusted| !trusted.starts_with(&root));
+        self.roots.push(root);
+        self.roots.sort();
+        self.save();
+    }
+
+    /// Writes the folders to their JSON file, if there is one.
+    fn save(&self) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if let Some(dir) = store.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        match serde_json::to_string_pretty(&self.roots) {
+            Ok(json) => {
+                if let Err(e) = fs::write(store, json) {
+                    log::warn!("Failed to save trusted folders: {}", e);
+                }
+            }
+            Err(e) => log::warn!("Failed to save trusted folders: {}", e),
+        }
+    }
+}
+
+/// Returns `path` with symlinks and `..` resolved, so a folder is trusted
+/// however it is reached, or as given if it doesn't exist.
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trusted_parents_imply_children() {
+        let mut trust = TrustStore::default();
+        assert!(!trust.is_trusted(Path::new("/work/project")));
+
+        trust.trust(Path::new("/work/project/crate"));
+        assert!(trust.is_trusted(Path::new("/work/project/crate")));
+        assert!(trust.is_trusted(Path::new("/work/project/crate/sub")));
+        assert!(!trust.is_trusted(Path::new("/work/project")));
+        // A sibling whose name starts the same isn't inside it
+        assert!(!trust.is_trusted(Path::new("/work/project/crate-other")));
+
+        // Trusting the parent replaces the child
+        trust.trust(Path::new("/work/project"));
+        assert_eq!(trust.roots(), [PathBuf::from("/work/project")]);
+        assert!(trust.is_trusted(Path::new("/work/project/other")));
+        trust.trust(Path::new("/work/project/crate"));
+        assert_eq!(trust.roots(), [PathBuf::from("/work/project")]);
+    }
+
+    #[test]
+    fn test_trust_is_kept() {
+        let dir = std::env::temp_dir().join(format!("cp_editor_trust_{}", std::process::id()));
+        let project = dir.join("project");
+        fs::create_dir_all(&project).unwrap();
+        let store = dir.join("config").join("trusted.json");
+
+        let mut trust = TrustStore::load(store.clone());
+        trust.trust(&project);
+        // The same folder reached another way is the same folder
+        let reloaded = TrustStore::load(store);
+        assert!(reloaded.is_trusted(&project.join("..").join("project")));
+        assert!(!reloaded.is_trusted(&dir));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,232 @@
+//! Where the window was and how big, kept across restarts.
+//!
+//! The window's outer position, inner size and maximized state are written
+//! to `window.json` in the config directory on quit and read back before
+//! the window is created, so it opens in place instead of jumping there.
+//! Monitors may have changed in between: [`place`] keeps a window that
+//! would open off every monitor from doing so.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use winit::monitor::MonitorHandle;
+
+/// Size the window opens with when there is nothing to restore, in
+/// physical pixels.
+pub const DEFAULT_SIZE: (u32, u32) = (1280, 720);
+
+/// Pixels of the window that must be on a monitor, across and down, for it
+/// to count as on that monitor: enough of the title bar to drag it by.
+const MIN_VISIBLE: (i64, i64) = (64, 32);
+
+/// The window's placement, in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    /// Left edge of the window's frame.
+    pub x: i32,
+    /// Top edge of the window's frame.
+    pub y: i32,
+    /// Width of the window's content.
+    pub width: u32,
+    /// Height of the window's content.
+    pub height: u32,
+    /// Whether the window was maximized. The position and size are then
+    /// those it returns to when unmaximized.
+    pub maximized: bool,
+    /// Scale factor of the monitor the window was on.
+    pub scale_factor: f64,
+}
+
+impl WindowGeometry {
+    /// Reads the placement written by [`WindowGeometry::save`]. A missing
+    /// or unreadable file gives `None`.
+    pub fn load(path: &Path) -> Option<Self> {
+        let json = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    /// Writes the placement to `path` as JSON.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+}
+
+/// A monitor's area, in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MonitorRect {
+    /// Left edge.
+    pub x: i32,
+    /// Top edge.
+    pub y: i32,
+    /// Width.
+    pub width: u32,
+    /// Height.
+    pub height: u32,
+    /// The monitor's scale factor.
+    pub scale_factor: f64,
+}
+
+impl MonitorRect {
+    /// Returns the area of a monitor winit reports.
+    pub fn of(monitor: &MonitorHandle) -> Self {
+        let (position, size) = (monitor.position(), monitor.size());
+        Self {
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+            scale_factor: monitor.scale_factor(),
+        }
+    }
+
+    /// Returns how many pixels of a `width` by `height` rectangle at `x`,
+    /// `y` are on the monitor, across and down.
+    fn overlap(&self, x: i64, y: i64, width: i64, height: i64) -> (i64, i64) {
+        let across = (x + width).min(self.x as i64 + self.width as i64) - x.max(self.x as i64);
+        let down = (y + height).min(self.y as i64 + self.height as i64) - y.max(self.y as i64);
+        (across.max(0), down.max(0))
+    }
+}
+
+/// Returns where to open a window saved as `saved` on `monitors`, or
+/// `None` if it would be off every monitor.
+///
+/// The window goes on the monitor holding most of it. Its size is scaled
+/// by how that monitor's scale factor differs from the one it was saved
+/// at, so it keeps its size on screen, then shrunk to fit the monitor,
+/// and the window is moved fully onto the monitor.
+pub fn place(saved: &WindowGeometry, monitors: &[MonitorRect]) -> Option<WindowGeometry> {
+    let (x, y) = (saved.x as i64, saved.y as i64);
+    let (width, height) = (saved.width as i64, saved.height as i64);
+    let monitor = monitors
+        .iter()
+        .map(|monitor| (monitor, monitor.overlap(x, y, width, height)))
+        .filter(|&(_, (across, down))| across >= MIN_VISIBLE.0.min(width) && down >= MIN_VISIBLE.1.min(height))
+        .max_by_key(|&(_, (across, down))| across * down)
+        .map(|(monitor, _)| monitor)?;
+
+    let scale = if saved.scale_factor > 0.0 { monitor.scale_factor / saved.scale_factor } else { 1.0 };
+    let width = ((saved.width as f64 * scale).round() as u32).clamp(1, monitor.width.max(1));
+    let height = ((saved.height as f64 * scale).round() as u32).clamp(1, monitor.height.max(1));
+    let right = monitor.x as i64 + monitor.width as i64 - width as i64;
+    let bottom = monitor.y as i64 + monitor.height as i64 - height as i64;
+    Some(WindowGeometry {
+        x: x.clamp(monitor.x as i64, right) as i32,
+        y: y.clamp(monitor.y as i64, bottom) as i32,
+        width,
+        height,
+        maximized: saved.maximized,
+        scale_factor: monitor.scale_factor,
+    })
+}
+
+/// Returns a window of [`DEFAULT_SIZE`], shrunk to fit, centered on
+/// `monitor`.
+pub fn centered(monitor: &MonitorRect) -> WindowGeometry {
+    let width = DEFAULT_SIZE.0.min(monitor.width);
+    let height = DEFAULT_SIZE.1.min(monitor.height);
+    WindowGeometry {
+        x: monitor.x + ((monitor.width - width) / 2) as i32,
+        y: monitor.y + ((monitor.height - height) / 2) as i32,
+        width,
+        height,
+        maximized: false,
+        scale_factor: monitor.scale_factor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(x: i32, y: i32, width: u32, height: u32, scale_factor: f64) -> MonitorRect {
+        MonitorRect { x, y, width, height, scale_factor }
+    }
+
+    fn window(x: i32, y: i32, width: u32, height: u32) -> WindowGeometry {
+        WindowGeometry { x, y, width, height, maximized: false, scale_factor: 1.0 }
+    }
+
+    #[test]
+    fn test_place_on_screen() {
+        let monitors = [monitor(0, 0, 1920, 1080, 1.0), monitor(1920, 0, 2560, 1440, 1.0)];
+        let saved = window(2000, 100, 1280, 720);
+        assert_eq!(place(&saved, &monitors), Some(saved));
+
+        let maximized = WindowGeometry { maximized: true, ..saved };
+        assert_eq!(place(&maximized, &monitors), Some(maximized));
+    }
+
+    #[test]
+    fn test_place_off_screen() {
+        // Last used on an external display that is gone
+        let monitors = [monitor(0, 0, 1920, 1080, 1.0)];
+        assert_eq!(place(&window(2000, 100, 1280, 720), &monitors), None);
+        assert_eq!(place(&window(-3000, -2000, 800, 600), &monitors), None);
+        // Only a sliver left on screen isn't enough to grab
+        assert_eq!(place(&window(1900, 100, 1280, 720), &monitors), None);
+        assert_eq!(place(&window(100, 100, 800, 600), &[]), None);
+    }
+
+    #[test]
+    fn test_place_partially_visible() {
+        let monitors = [monitor(0, 0, 1920, 1080, 1.0)];
+        // Hanging off the right and bottom edges, it moves fully on screen
+        let placed = place(&window(1500, 800, 1280, 720), &monitors).unwrap();
+        assert_eq!((placed.x, placed.y, placed.width, placed.height), (640, 360, 1280, 720));
+        // Above the top edge
+        let placed = place(&window(100, -200, 800, 600), &monitors).unwrap();
+        assert_eq!((placed.x, placed.y), (100, 0));
+        // Larger than the monitor, it shrinks to fit
+        let placed = place(&window(-10, 0, 2560, 1440), &monitors).unwrap();
+        assert_eq!((placed.x, placed.y, placed.width, placed.height), (0, 0, 1920, 1080));
+
+        // Spanning two monitors it goes where most of it is
+        let monitors = [monitor(0, 0, 1920, 1080, 1.0), monitor(1920, 0, 1920, 1080, 1.0)];
+        let placed = place(&window(1700, 100, 1000, 600), &monitors).unwrap();
+        assert_eq!((placed.x, placed.y), (1920, 100));
+    }
+
+    #[test]
+    fn test_place_with_different_scale() {
+        // Saved on a standard display that is now high-DPI: twice the pixels
+        let monitors = [monitor(0, 0, 3840, 2160, 2.0)];
+        let placed = place(&window(100, 100, 1280, 720), &monitors).unwrap();
+        assert_eq!((placed.width, placed.height, placed.scale_factor), (2560, 1440, 2.0));
+        assert_eq!((placed.x, placed.y), (100, 100));
+
+        // And back: half the pixels
+        let saved = WindowGeometry { scale_factor: 2.0, ..window(200, 200, 2560, 1440) };
+        let placed = place(&saved, &[monitor(0, 0, 1920, 1080, 1.0)]).unwrap();
+        assert_eq!((placed.x, placed.y, placed.width, placed.height), (200, 200, 1280, 720));
+
+        // A mixed layout: the scaled window still has to fit
+        let saved = WindowGeometry { scale_factor: 1.0, ..window(0, 0, 1800, 1000) };
+        let placed = place(&saved, &[monitor(0, 0, 2560, 1440, 1.5)]).unwrap();
+        assert_eq!((placed.width, placed.height), (2560, 1440));
+    }
+
+    #[test]
+    fn test_centered() {
+        let placed = centered(&monitor(1920, 0, 2560, 1440, 1.0));
+        assert_eq!((placed.x, placed.y, placed.width, placed.height), (1920 + 640, 360, 1280, 720));
+        let placed = centered(&monitor(0, 0, 1024, 600, 1.0));
+        assert_eq!((placed.x, placed.y, placed.width, placed.height), (0, 0, 1024, 600));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = std::env::temp_dir().join(format!("cp_editor_window_{}", std::process::id())).join("window.json");
+        let saved = WindowGeometry { maximized: true, scale_factor: 1.25, ..window(-50, 20, 900, 700) };
+        saved.save(&path).unwrap();
+        assert_eq!(WindowGeometry::load(&path), Some(saved));
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        assert_eq!(WindowGeometry::load(&path), None);
+    }
+}
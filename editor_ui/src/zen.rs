@@ -0,0 +1,101 @@
+//! Distraction-free (zen) mode.
+//!
+//! Zen mode hides the tab bar, the status bar and the gutter, and draws the
+//! text in a column of at most [`ZenMode::max_columns`] characters centered
+//! in the window. The cursor can optionally stop blinking. Nothing about
+//! the normal layout is changed while it is on, so turning it off brings
+//! that layout back as it was.
+
+/// Settings and state of zen mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZenMode {
+    /// Widest the text column gets, in characters.
+    pub max_columns: usize,
+    /// Whether the cursor stays solid instead of blinking.
+    pub hide_cursor_blink: bool,
+    /// Whether zen mode is on.
+    active: bool,
+    /// Whether the cursor blinked before zen mode was turned on.
+    blink_before: bool,
+}
+
+impl Default for ZenMode {
+    fn default() -> Self {
+        Self {
+            max_columns: 100,
+            hide_cursor_blink: true,
+            active: false,
+            blink_before: true,
+        }
+    }
+}
+
+impl ZenMode {
+    /// Creates the settings with zen mode off.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether zen mode is on.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Turns zen mode on, remembering whether the cursor blinked so
+    /// [`ZenMode::exit`] can restore it. Returns whether the cursor should
+    /// blink while it is on.
+    pub fn enter(&mut self, cursor_blinks: bool) -> bool {
+        if !self.active {
+            self.active = true;
+            self.blink_before = cursor_blinks;
+        }
+        cursor_blinks && !self.hide_cursor_blink
+    }
+
+    /// Turns zen mode off. Returns whether the cursor should blink again.
+    pub fn exit(&mut self) -> bool {
+        self.active = false;
+        self.blink_before
+    }
+
+    /// Returns the left edge and the width of the text column in a window
+    /// `viewport_width` wide: `max_columns` characters centered, or the
+    /// whole window if it is narrower than that.
+    pub fn text_column(&self, char_width: f32, viewport_width: f32) -> (f32, f32) {
+        let width = (self.max_columns as f32 * char_width).min(viewport_width).max(0.0);
+        ((viewport_width - width) / 2.0, width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_column_is_centered() {
+        let zen = ZenMode { max_columns: 80, ..ZenMode::default() };
+        // 80 columns of 10 pixels in a 1200 pixel window
+        assert_eq!(zen.text_column(10.0, 1200.0), (200.0, 800.0));
+        // A narrower window gets the whole width
+        assert_eq!(zen.text_column(10.0, 600.0), (0.0, 600.0));
+        assert_eq!(zen.text_column(10.0, 800.0), (0.0, 800.0));
+    }
+
+    #[test]
+    fn test_exit_restores_cursor_blink() {
+        let mut zen = ZenMode::new();
+        assert!(!zen.enter(true));
+        assert!(zen.is_active());
+        assert!(zen.exit());
+        assert!(!zen.is_active());
+
+        // Blinking that was off stays off
+        assert!(!zen.enter(false));
+        assert!(!zen.exit());
+
+        // Keeping the blink
+        zen.hide_cursor_blink = false;
+        assert!(zen.enter(true));
+        assert!(zen.exit());
+    }
+}